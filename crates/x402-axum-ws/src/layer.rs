@@ -0,0 +1,173 @@
+//! The [`X402WsMiddleware`] layer and the [`PaidWebSocketUpgrade`] extractor.
+
+use axum::extract::FromRequestParts;
+use axum::extract::ws::{WebSocket, WebSocketUpgrade};
+use axum::http::StatusCode;
+use axum::http::request::Parts;
+use axum::response::{IntoResponse, Response};
+use std::future::Future;
+use std::task::{Context, Poll};
+use tower::{Layer, Service};
+use x402_rs::duration::Seconds;
+use x402_rs::facilitator_remote::FacilitatorWsClient;
+use x402_rs::types::PaymentRequirements;
+
+use crate::stream::{self, PaidStream};
+
+/// What to do with the content sent by the application while the prepaid window has lapsed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LapsePolicy {
+    /// Keeps up to `max_chunks` chunks, delivered once the next slice is paid; drops the others.
+    Buffer { max_chunks: usize },
+    /// Drops the content.
+    Drop,
+}
+
+impl Default for LapsePolicy {
+    fn default() -> Self {
+        LapsePolicy::Buffer { max_chunks: 64 }
+    }
+}
+
+/// Axum layer selling a WebSocket stream in paid slices, see the [crate docs](crate).
+///
+/// The slice accounting is kept by the facilitator, over its WebSocket endpoint: `stream.init`,
+/// `stream.require` and `stream.pay` are forwarded to it.
+#[derive(Clone, Debug)]
+pub struct X402WsMiddleware {
+    pub(crate) facilitator: FacilitatorWsClient,
+    pub(crate) requirements: PaymentRequirements,
+    pub(crate) unit_seconds: Seconds,
+    pub(crate) price_per_unit: Option<String>,
+    pub(crate) lapse: LapsePolicy,
+}
+
+impl X402WsMiddleware {
+    /// Sells slices of `unit_seconds`, each paid against `requirements`, whose
+    /// `maxAmountRequired` is the price per unit.
+    pub fn new(
+        facilitator: FacilitatorWsClient,
+        requirements: PaymentRequirements,
+        unit_seconds: Seconds,
+    ) -> Self {
+        Self {
+            facilitator,
+            requirements,
+            unit_seconds,
+            price_per_unit: None,
+            lapse: LapsePolicy::default(),
+        }
+    }
+
+    /// Price per unit advertised in `stream.accept`, e.g. `0.05`.
+    pub fn with_price_per_unit(&self, price_per_unit: impl Into<String>) -> Self {
+        let mut this = self.clone();
+        this.price_per_unit = Some(price_per_unit.into());
+        this
+    }
+
+    /// What to do with content while the prepaid window has lapsed; buffers 64 chunks by default.
+    pub fn with_lapse_policy(&self, lapse: LapsePolicy) -> Self {
+        let mut this = self.clone();
+        this.lapse = lapse;
+        this
+    }
+}
+
+impl<S> Layer<S> for X402WsMiddleware {
+    type Service = X402WsMiddlewareService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        X402WsMiddlewareService {
+            middleware: self.clone(),
+            inner,
+        }
+    }
+}
+
+/// Service of [`X402WsMiddleware`], making it available to [`PaidWebSocketUpgrade`].
+#[derive(Clone, Debug)]
+pub struct X402WsMiddlewareService<S> {
+    middleware: X402WsMiddleware,
+    inner: S,
+}
+
+impl<S, B> Service<axum::http::Request<B>> for X402WsMiddlewareService<S>
+where
+    S: Service<axum::http::Request<B>>,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = S::Future;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut req: axum::http::Request<B>) -> Self::Future {
+        req.extensions_mut().insert(self.middleware.clone());
+        self.inner.call(req)
+    }
+}
+
+/// Extractor upgrading a request to a paid stream, in a route layered with [`X402WsMiddleware`].
+pub struct PaidWebSocketUpgrade {
+    upgrade: WebSocketUpgrade,
+    middleware: X402WsMiddleware,
+}
+
+impl PaidWebSocketUpgrade {
+    /// Finalizes the upgrade: once the Buyer opened the stream with `stream.init`, `callback`
+    /// gets the [`PaidStream`] to send its content to. Connections closed before are dropped.
+    pub fn on_paid_stream<C, Fut>(self, callback: C) -> Response
+    where
+        C: FnOnce(PaidStream) -> Fut + Send + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        let middleware = self.middleware;
+        self.upgrade
+            .on_upgrade(move |socket: WebSocket| async move {
+                if let Some(paid) = stream::open(socket, middleware).await {
+                    callback(paid).await;
+                }
+            })
+            .into_response()
+    }
+}
+
+/// Rejection of [`PaidWebSocketUpgrade`].
+#[derive(Debug, thiserror::Error)]
+pub enum PaidWebSocketRejection {
+    #[error("Route is not layered with X402WsMiddleware")]
+    MissingMiddleware,
+    #[error(transparent)]
+    Upgrade(#[from] axum::extract::ws::rejection::WebSocketUpgradeRejection),
+}
+
+impl IntoResponse for PaidWebSocketRejection {
+    fn into_response(self) -> Response {
+        match self {
+            PaidWebSocketRejection::MissingMiddleware => {
+                (StatusCode::INTERNAL_SERVER_ERROR, self.to_string()).into_response()
+            }
+            PaidWebSocketRejection::Upgrade(rejection) => rejection.into_response(),
+        }
+    }
+}
+
+impl<S: Send + Sync> FromRequestParts<S> for PaidWebSocketUpgrade {
+    type Rejection = PaidWebSocketRejection;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let middleware = parts
+            .extensions
+            .get::<X402WsMiddleware>()
+            .cloned()
+            .ok_or(PaidWebSocketRejection::MissingMiddleware)?;
+        let upgrade = WebSocketUpgrade::from_request_parts(parts, state).await?;
+        Ok(Self {
+            upgrade,
+            middleware,
+        })
+    }
+}
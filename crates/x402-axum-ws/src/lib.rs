@@ -0,0 +1,57 @@
+//! Axum layer and extractor for WebSocket streams sold in paid slices, per `x402-ws-stream.md`.
+//!
+//! [`X402WsMiddleware`] wraps the route of a Seller's WebSocket handler, and the
+//! [`PaidWebSocketUpgrade`] extractor hands the handler a [`PaidStream`] once the Buyer opened a
+//! stream. The crate enforces the payments in between:
+//! - it runs the handshake: `stream.init` is answered with `stream.accept`, and the first slice
+//!   required with `stream.require`,
+//! - it accepts the payments of the Buyer (`stream.pay`), and requires the next slice as soon as
+//!   one is paid,
+//! - it gates delivery on the prepaid window: content sent to the [`PaidStream`] goes out as
+//!   `stream.data` frames while the window is open, and is buffered until the next payment or
+//!   dropped once it lapsed, see [`LapsePolicy`],
+//! - it closes the stream once the application drops the [`PaidStream`], or the Buyer asks to with
+//!   `stream.closing`: the content held during a lapse is required as a final slice, and the final
+//!   bill sent as `stream.closed`, so both parties agree on what was paid and delivered.
+//!
+//! The slice accounting is kept by the facilitator, over its WebSocket endpoint (see
+//! [`FacilitatorWsClient`]): payments are verified against the requirements it issued, and the
+//! stream is finalized with it once closed. End-to-end encryption is not supported: offers of it
+//! are rejected.
+//!
+//! ```rust,no_run
+//! use axum::{Router, routing::get};
+//! use std::time::Duration;
+//! use x402_axum_ws::{PaidWebSocketUpgrade, X402WsMiddleware};
+//! use x402_rs::duration::Seconds;
+//! use x402_rs::facilitator_remote::FacilitatorWsClient;
+//! use x402_rs::types::PaymentRequirements;
+//!
+//! # fn requirements() -> PaymentRequirements { unimplemented!() }
+//! let facilitator = FacilitatorWsClient::try_from("wss://facilitator.example/ws").unwrap();
+//! // Requirements of one slice: `maxAmountRequired` is the price per unit
+//! let x402 = X402WsMiddleware::new(facilitator, requirements(), Seconds::new(60))
+//!     .with_price_per_unit("0.05");
+//!
+//! let app: Router = Router::new().route(
+//!     "/ws",
+//!     get(|upgrade: PaidWebSocketUpgrade| async move {
+//!         upgrade.on_paid_stream(|mut stream| async move {
+//!             while stream.paid().await.is_ok() {
+//!                 if stream.send("tick").await.is_err() {
+//!                     break;
+//!                 }
+//!                 tokio::time::sleep(Duration::from_secs(1)).await;
+//!             }
+//!         })
+//!     })
+//!     .layer(x402),
+//! );
+//! ```
+
+pub mod layer;
+pub mod stream;
+
+pub use layer::{LapsePolicy, PaidWebSocketRejection, PaidWebSocketUpgrade, X402WsMiddleware};
+pub use stream::{PaidStream, PaidStreamError, PaidWindow};
+pub use x402_rs::facilitator_remote::FacilitatorWsClient;
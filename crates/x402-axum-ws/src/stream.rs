@@ -0,0 +1,504 @@
+//! The [`PaidStream`] handle, and the task driving the paid stream of a connection.
+//!
+//! The task owns the WebSocket. It answers `stream.init` and `stream.pay` with `stream.accept`,
+//! issues `stream.require` for the next slice as soon as one is paid, and delivers the content
+//! sent to the [`PaidStream`] as `stream.data` frames while the prepaid window is open, applying
+//! the [`LapsePolicy`] to the content sent after it closed.
+//!
+//! Streams ended by the application, or by the Buyer with `stream.closing`, are closed
+//! cooperatively: the task answers with its own `stream.closing`, requiring the content held
+//! while the window had lapsed as a final slice, if any, waits for its `stream.pay`, and sends
+//! the final bill as `stream.closed`.
+
+use axum::extract::ws::{Message, WebSocket};
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as b64;
+use serde_json::json;
+use sha2::{Digest, Sha256};
+use std::collections::VecDeque;
+use std::time::Duration;
+use tokio::sync::{mpsc, watch};
+use x402_rs::facilitator_remote::WsClientError;
+use x402_rs::timestamp::UnixTimestampMs;
+use x402_rs::types::TokenAmount;
+use x402_rs::types::stream::{
+    StreamAccept, StreamClosed, StreamClosing, StreamData, StreamEnvelope, StreamMessage,
+    StreamPay, StreamRequire,
+};
+use x402_rs::ws_error::{WsError, WsErrorCode};
+
+use crate::layer::{LapsePolicy, X402WsMiddleware};
+
+/// Chunks the application may send ahead of the task delivering them.
+const CHANNEL_CAPACITY: usize = 64;
+/// Time the Buyer has to pay the remainder of a closing stream.
+const CLOSE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Error of a [`PaidStream`].
+#[derive(Debug, thiserror::Error)]
+pub enum PaidStreamError {
+    #[error("Stream closed")]
+    Closed,
+}
+
+/// Payment state of a stream.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PaidWindow {
+    /// End of the prepaid window, `None` until the first slice is paid.
+    pub prepaid_until: Option<UnixTimestampMs>,
+    /// Number of slices paid.
+    pub slices: u64,
+    /// Whether the stream is closed, by either party.
+    pub closed: bool,
+}
+
+impl PaidWindow {
+    /// Whether content sent now is delivered.
+    pub fn is_paid(&self) -> bool {
+        !self.closed
+            && self
+                .prepaid_until
+                .is_some_and(|until| UnixTimestampMs::now() < until)
+    }
+}
+
+/// Handle of a paid stream, given to the application to send content to the Buyer.
+///
+/// Content is delivered only while the prepaid window is open; the application does not need to
+/// care about payments, but may wait for one with [`PaidStream::paid`]. Clones share the stream,
+/// which is ended once the last clone is dropped.
+#[derive(Debug, Clone)]
+pub struct PaidStream {
+    stream_id: String,
+    content: mpsc::Sender<Vec<u8>>,
+    window: watch::Receiver<PaidWindow>,
+}
+
+impl PaidStream {
+    pub fn stream_id(&self) -> &str {
+        &self.stream_id
+    }
+
+    /// Current payment state of the stream.
+    pub fn window(&self) -> PaidWindow {
+        self.window.borrow().clone()
+    }
+
+    pub fn is_paid(&self) -> bool {
+        self.window.borrow().is_paid()
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.window.borrow().closed
+    }
+
+    /// Sends `content` as a chunk of the stream: delivered at once within the prepaid window,
+    /// buffered or dropped otherwise, see [`LapsePolicy`].
+    pub async fn send(&self, content: impl Into<Vec<u8>>) -> Result<(), PaidStreamError> {
+        self.content
+            .send(content.into())
+            .await
+            .map_err(|_| PaidStreamError::Closed)
+    }
+
+    /// Waits until the prepaid window is open: returns at once within it, and waits for the next
+    /// payment otherwise.
+    pub async fn paid(&mut self) -> Result<(), PaidStreamError> {
+        loop {
+            {
+                let window = self.window.borrow_and_update();
+                if window.closed {
+                    return Err(PaidStreamError::Closed);
+                }
+                if window.is_paid() {
+                    return Ok(());
+                }
+            }
+            self.window
+                .changed()
+                .await
+                .map_err(|_| PaidStreamError::Closed)?;
+        }
+    }
+}
+
+/// Runs the `stream.init` handshake on `socket`, then drives the stream in a task.
+///
+/// `None` if the connection closed before the Buyer opened a stream.
+pub(crate) async fn open(
+    mut socket: WebSocket,
+    middleware: X402WsMiddleware,
+) -> Option<PaidStream> {
+    let (stream_id, require) = loop {
+        let text = match socket.recv().await? {
+            Ok(Message::Text(text)) => text,
+            Ok(Message::Close(_)) | Err(_) => return None,
+            Ok(_) => continue,
+        };
+        let Ok(envelope) = serde_json::from_str::<StreamEnvelope>(&text) else {
+            continue;
+        };
+        let id = envelope.id().clone();
+        let StreamMessage::Init(init) = envelope.into_message() else {
+            let error = WsError::invalid_params("stream not opened, send stream.init first");
+            reply_error(&mut socket, &id, error).await;
+            continue;
+        };
+        if init.encryption.is_some() {
+            // Streaming in the clear would betray the offer of the Buyer
+            let error = WsError::invalid_params("end-to-end encryption is not supported");
+            reply_error(&mut socket, &id, error).await;
+            continue;
+        }
+        let session = middleware
+            .facilitator
+            .call::<_, serde_json::Value>(
+                "stream.init",
+                &json!({
+                    "requirements": middleware.requirements,
+                    "unitSeconds": middleware.unit_seconds,
+                }),
+            )
+            .await;
+        let stream_id =
+            match session.map(|session| session["streamId"].as_str().map(str::to_string)) {
+                Ok(Some(stream_id)) => stream_id,
+                Ok(None) => {
+                    let error = WsError::payment_failed("Facilitator returned no stream id", None);
+                    reply_error(&mut socket, &id, error).await;
+                    continue;
+                }
+                Err(e) => {
+                    reply_error(&mut socket, &id, facilitator_error(e)).await;
+                    continue;
+                }
+            };
+        let accept = StreamAccept {
+            price_per_unit: middleware.price_per_unit.clone(),
+            unit_seconds: Some(middleware.unit_seconds),
+            pay_to: Some(middleware.requirements.pay_to.clone()),
+            asset: Some(middleware.requirements.asset.clone()),
+            network: Some(middleware.requirements.network),
+            ..StreamAccept::new(&stream_id)
+        };
+        if !reply(&mut socket, &id, StreamMessage::Accept(accept)).await {
+            return None;
+        }
+        let require = require_slice(&middleware, &stream_id).await;
+        break (stream_id, require);
+    };
+
+    let (content, content_rx) = mpsc::channel(CHANNEL_CAPACITY);
+    let (window, window_rx) = watch::channel(PaidWindow::default());
+    let mut driver = Driver {
+        socket,
+        middleware,
+        stream_id: stream_id.clone(),
+        window,
+        slice_index: 0,
+        seq: 0,
+        lapsed: VecDeque::new(),
+        pending: require.clone(),
+        paid: TokenAmount::from(0u64),
+    };
+    tokio::spawn(async move {
+        if let Some(require) = require
+            && !driver.send(StreamMessage::Require(require)).await
+        {
+            driver.close(End::Gone).await;
+            return;
+        }
+        driver.run(content_rx).await;
+    });
+    Some(PaidStream {
+        stream_id,
+        content,
+        window: window_rx,
+    })
+}
+
+/// Requirements of the next slice of `stream_id`, issued by the facilitator.
+async fn require_slice(middleware: &X402WsMiddleware, stream_id: &str) -> Option<StreamRequire> {
+    middleware
+        .facilitator
+        .call("stream.require", &json!({ "streamId": stream_id }))
+        .await
+        .ok()
+}
+
+async fn reply(socket: &mut WebSocket, id: &serde_json::Value, result: StreamMessage) -> bool {
+    let envelope = json!({ "id": id, "result": result });
+    socket
+        .send(Message::Text(envelope.to_string().into()))
+        .await
+        .is_ok()
+}
+
+async fn reply_error(socket: &mut WebSocket, id: &serde_json::Value, error: WsError) {
+    let envelope = json!({ "id": id, "error": error });
+    let _ = socket
+        .send(Message::Text(envelope.to_string().into()))
+        .await;
+}
+
+/// Error returned to the Buyer for a request the facilitator failed: its error envelope as is,
+/// e.g. [`WsErrorCode::REQUIRE_EXPIRED`], and a payment failure if it could not be reached.
+fn facilitator_error(error: WsClientError) -> WsError {
+    match error {
+        WsClientError::Rpc {
+            code,
+            message,
+            data,
+        } => WsError {
+            code: WsErrorCode(code as i32),
+            message,
+            data,
+        },
+        e => WsError::payment_failed(e.to_string(), None),
+    }
+}
+
+/// `SHA256(0x00 ‖ content)`, 0x-prefixed hex, the `hash` of a `stream.data` frame.
+fn chunk_hash(content: &[u8]) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update([0x00]);
+    hasher.update(content);
+    let hash = hasher.finalize();
+    let hex: String = hash.iter().map(|byte| format!("{byte:02x}")).collect();
+    format!("0x{hex}")
+}
+
+/// Task driving a stream opened by `stream.init`.
+struct Driver {
+    socket: WebSocket,
+    middleware: X402WsMiddleware,
+    stream_id: String,
+    window: watch::Sender<PaidWindow>,
+    /// Index of the last slice paid, the slice content is delivered for.
+    slice_index: u64,
+    seq: u64,
+    /// Content sent while the prepaid window had lapsed.
+    lapsed: VecDeque<Vec<u8>>,
+    /// Last requirements issued, not paid yet.
+    pending: Option<StreamRequire>,
+    /// Total paid for the slices accepted.
+    paid: TokenAmount,
+}
+
+/// How a stream ends.
+enum End {
+    /// Ended by the application, or by the Buyer with `stream.closing`: closed cooperatively.
+    Closing(Option<String>),
+    /// Ended by the Buyer with `stream.end`, or the connection is gone.
+    Gone,
+}
+
+impl Driver {
+    async fn run(mut self, mut content: mpsc::Receiver<Vec<u8>>) {
+        let end = loop {
+            tokio::select! {
+                message = self.socket.recv() => {
+                    let text = match message {
+                        Some(Ok(Message::Text(text))) => text,
+                        Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break End::Gone,
+                        Some(Ok(_)) => continue,
+                    };
+                    let Ok(envelope) = serde_json::from_str::<StreamEnvelope>(&text) else {
+                        continue;
+                    };
+                    let id = envelope.id().clone();
+                    let open = match envelope.into_message() {
+                        StreamMessage::Pay(pay) => self.pay(&id, pay).await,
+                        StreamMessage::Closing(closing) => break End::Closing(closing.reason),
+                        StreamMessage::Close(_) => false,
+                        _ => true,
+                    };
+                    if !open {
+                        break End::Gone;
+                    }
+                }
+                chunk = content.recv() => {
+                    let Some(chunk) = chunk else {
+                        break End::Closing(Some("completed".to_string()));
+                    };
+                    if !self.deliver(chunk).await {
+                        break End::Gone;
+                    }
+                }
+            }
+        };
+        self.close(end).await;
+    }
+
+    /// Accepts `stream.pay`, then requires the next slice.
+    ///
+    /// `false` if the connection is gone.
+    async fn pay(&mut self, id: &serde_json::Value, pay: StreamPay) -> bool {
+        match self.accept_pay(id, pay).await {
+            None => return false,
+            Some(false) => return true,
+            Some(true) => {}
+        }
+        while let Some(chunk) = self.lapsed.pop_front() {
+            if !self.deliver(chunk).await {
+                return false;
+            }
+        }
+        self.pending = require_slice(&self.middleware, &self.stream_id).await;
+        match self.pending.clone() {
+            Some(require) => self.send(StreamMessage::Require(require)).await,
+            None => true,
+        }
+    }
+
+    /// Forwards `stream.pay` to the facilitator, and accepts the payment if it went through.
+    ///
+    /// `None` if the connection is gone, whether the payment was accepted otherwise.
+    async fn accept_pay(&mut self, id: &serde_json::Value, pay: StreamPay) -> Option<bool> {
+        let pay = StreamPay {
+            stream_id: self.stream_id.clone(),
+            ..pay
+        };
+        let accept = match self
+            .middleware
+            .facilitator
+            .call::<_, StreamAccept>("stream.pay", &pay)
+            .await
+        {
+            Ok(accept) => accept,
+            Err(e) => {
+                reply_error(&mut self.socket, id, facilitator_error(e)).await;
+                return Some(false);
+            }
+        };
+        self.slice_index = accept.slice_index.unwrap_or(pay.slice_index);
+        if let Some(require) = self.pending.take() {
+            self.paid = self.paid + require.requirements.max_amount_required;
+        }
+        let prepaid_until = accept.prepaid_until_ms;
+        if !reply(&mut self.socket, id, StreamMessage::Accept(accept)).await {
+            return None;
+        }
+        self.window.send_modify(|window| {
+            window.prepaid_until = prepaid_until;
+            window.slices += 1;
+        });
+        Some(true)
+    }
+
+    /// Delivers `chunk` within the prepaid window, applies the lapse policy otherwise.
+    ///
+    /// `false` if the connection is gone.
+    async fn deliver(&mut self, chunk: Vec<u8>) -> bool {
+        if !self.window.borrow().is_paid() {
+            if let LapsePolicy::Buffer { max_chunks } = self.middleware.lapse
+                && self.lapsed.len() < max_chunks
+            {
+                self.lapsed.push_back(chunk);
+            }
+            return true;
+        }
+        self.send_data(chunk).await
+    }
+
+    /// Sends `chunk` as a `stream.data` frame of the last slice paid.
+    ///
+    /// `false` if the connection is gone.
+    async fn send_data(&mut self, chunk: Vec<u8>) -> bool {
+        let data = StreamData {
+            stream_id: self.stream_id.clone(),
+            slice_index: self.slice_index,
+            seq: self.seq,
+            hash: chunk_hash(&chunk),
+            data: Some(b64.encode(&chunk)),
+            ciphertext: None,
+        };
+        self.seq += 1;
+        self.send(StreamMessage::Data(data)).await
+    }
+
+    async fn send(&mut self, message: StreamMessage) -> bool {
+        let envelope = StreamEnvelope::new(uuid::Uuid::new_v4().to_string(), message);
+        let Ok(text) = serde_json::to_string(&envelope) else {
+            return true;
+        };
+        self.socket.send(Message::Text(text.into())).await.is_ok()
+    }
+
+    /// Ends the stream, closing it cooperatively unless the connection is gone, and finalizes
+    /// the session of the facilitator.
+    async fn close(mut self, end: End) {
+        self.window.send_modify(|window| window.closed = true);
+        if let End::Closing(reason) = end {
+            self.close_cooperatively(reason).await;
+        }
+        let _ = self
+            .middleware
+            .facilitator
+            .call::<_, serde_json::Value>("stream.finalize", &json!({ "streamId": self.stream_id }))
+            .await;
+    }
+
+    /// Sends `stream.closing`, requiring the content held while the window had lapsed as a final
+    /// slice, if any, waits up to [`CLOSE_TIMEOUT`] for its payment, and sends the final bill as
+    /// `stream.closed`. Content not paid for is dropped.
+    async fn close_cooperatively(&mut self, reason: Option<String>) {
+        // Requirements issued before are void: the Buyer pays the remainder only
+        self.pending = if self.lapsed.is_empty() {
+            None
+        } else {
+            require_slice(&self.middleware, &self.stream_id).await
+        };
+        let closing = StreamClosing {
+            require: self.pending.clone(),
+            ..StreamClosing::new(&self.stream_id, reason.clone())
+        };
+        if !self.send(StreamMessage::Closing(closing)).await {
+            return;
+        }
+        if self.pending.is_some() {
+            let deadline = tokio::time::sleep(CLOSE_TIMEOUT);
+            tokio::pin!(deadline);
+            loop {
+                let text = tokio::select! {
+                    _ = &mut deadline => break,
+                    message = self.socket.recv() => match message {
+                        Some(Ok(Message::Text(text))) => text,
+                        Some(Ok(Message::Close(_))) | Some(Err(_)) | None => return,
+                        Some(Ok(_)) => continue,
+                    },
+                };
+                let Ok(envelope) = serde_json::from_str::<StreamEnvelope>(&text) else {
+                    continue;
+                };
+                let id = envelope.id().clone();
+                match envelope.into_message() {
+                    StreamMessage::Pay(pay) => match self.accept_pay(&id, pay).await {
+                        None => return,
+                        // A rejected payment may be retried until the deadline
+                        Some(false) => {}
+                        Some(true) => {
+                            while let Some(chunk) = self.lapsed.pop_front() {
+                                if !self.send_data(chunk).await {
+                                    return;
+                                }
+                            }
+                            break;
+                        }
+                    },
+                    StreamMessage::Close(_) => return,
+                    _ => {}
+                }
+            }
+        }
+        let closed = StreamClosed {
+            stream_id: self.stream_id.clone(),
+            reason,
+            slices: self.window.borrow().slices,
+            amount: self.paid,
+            chunks: self.seq,
+        };
+        let _ = self.send(StreamMessage::Closed(closed)).await;
+        let _ = self.socket.send(Message::Close(None)).await;
+    }
+}
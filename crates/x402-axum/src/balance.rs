@@ -0,0 +1,145 @@
+//! Prepaid balances, for repeat buyers of chatty APIs.
+//!
+//! With [`X402Middleware::with_prepaid_balance`](crate::X402Middleware::with_prepaid_balance),
+//! buyers pay a deposit worth a number of requests once, instead of signing a payment for every
+//! request, see [`x402_rs::balance`]:
+//! - a request without payment is answered with `402 Payment Required`, asking for the deposit:
+//!   the price of the route times the number of requests, advertised as `prepaidRequests` in the
+//!   `extra` field of the payment requirements,
+//! - a request paying the deposit in the `X-Payment` header gets the deposit settled and credited
+//!   to a balance, whose token is returned in the `X-Payment-Balance` header, and is charged
+//!   against it,
+//! - a request carrying the token in the `X-Payment-Balance` header is charged the price of the
+//!   route against the balance. The balance left is returned in the `X-Payment-Balance-Remaining`
+//!   header. A balance too low is re-challenged with `402 Payment Required`, asking for a new
+//!   deposit, which tops the balance up when paid together with the token.
+//!
+//! Deposits are settled before the request is handled, unlike single payments.
+//!
+//! ```rust,no_run
+//! use axum::{Router, routing::get};
+//! use axum::http::HeaderMap;
+//! use x402_axum::{FacilitatorClient, X402Middleware};
+//! use x402_axum::balance::PrepaidBalance;
+//! use x402_rs::address_evm;
+//! use x402_rs::balance::{BalanceLedger, PrepaidBalances};
+//! use x402_rs::network::Network;
+//!
+//! let facilitator = FacilitatorClient::try_from("https://facilitator.example/").unwrap();
+//! // Balances kept in process; a `FacilitatorWsClient` keeps them with the facilitator
+//! let store = PrepaidBalances::new(BalanceLedger::new(), facilitator.clone());
+//! let prepaid = PrepaidBalance::new(store, 100);
+//!
+//! let x402 = X402Middleware::new(facilitator).with_usdc_price_on(
+//!     &[Network::BaseSepolia],
+//!     "0.001",
+//!     address_evm!("0xBAc675C310721717Cd4A37F6cbeA1F081b1C2a07"),
+//! );
+//! let balance = prepaid.clone();
+//! let app: Router = Router::new()
+//!     .route(
+//!         "/search",
+//!         get(|| async { "results" }).layer(x402.with_prepaid_balance(prepaid)),
+//!     )
+//!     .route(
+//!         "/balance",
+//!         get(move |headers: HeaderMap| async move { balance.balance(&headers).await }),
+//!     );
+//! ```
+
+use axum_core::body::Body;
+use axum_core::response::Response;
+use http::{HeaderMap, StatusCode};
+use serde_json::json;
+use std::fmt::Debug;
+use std::sync::Arc;
+use x402_rs::balance::{BALANCE_HEADER, BalanceError, BalanceStore, Charge};
+use x402_rs::types::PaymentRequirements;
+
+/// Prepaid balances charged by a middleware, with the number of requests a deposit pays for.
+#[derive(Clone)]
+pub struct PrepaidBalance {
+    store: Arc<dyn BalanceStore>,
+    requests: u64,
+}
+
+impl Debug for PrepaidBalance {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("PrepaidBalance")
+            .field("requests", &self.requests)
+            .finish_non_exhaustive()
+    }
+}
+
+impl PrepaidBalance {
+    /// Keeps balances in `store`, with deposits worth `requests` requests.
+    pub fn new<S: BalanceStore + 'static>(store: S, requests: u64) -> Self {
+        Self {
+            store: Arc::new(store),
+            requests: requests.max(1),
+        }
+    }
+
+    pub fn store(&self) -> &Arc<dyn BalanceStore> {
+        &self.store
+    }
+
+    /// Requirements of a deposit: `requirements` worth `requests` requests each.
+    pub fn deposit_requirements(
+        &self,
+        requirements: &[PaymentRequirements],
+    ) -> Vec<PaymentRequirements> {
+        requirements
+            .iter()
+            .map(|requirements| {
+                let mut deposit = requirements.clone();
+                deposit.max_amount_required = requirements.max_amount_required * self.requests;
+                let mut extra = match deposit.extra.take() {
+                    Some(serde_json::Value::Object(extra)) => extra,
+                    _ => serde_json::Map::new(),
+                };
+                extra.insert("prepaidRequests".to_string(), json!(self.requests));
+                deposit.extra = Some(serde_json::Value::Object(extra));
+                deposit
+            })
+            .collect()
+    }
+
+    /// Prices of a request, one per requirement.
+    pub fn charges(requirements: &[PaymentRequirements]) -> Vec<Charge> {
+        requirements.iter().map(Charge::from_requirements).collect()
+    }
+
+    /// The balance whose token is the `X-Payment-Balance` header of `headers`, as JSON, e.g. for
+    /// a `GET /balance` route.
+    pub async fn balance(&self, headers: &HeaderMap) -> Response {
+        let Some(token) = headers
+            .get(BALANCE_HEADER)
+            .and_then(|token| token.to_str().ok())
+        else {
+            return json_response(
+                StatusCode::BAD_REQUEST,
+                &json!({ "error": format!("Missing {BALANCE_HEADER} header") }),
+            );
+        };
+        match self.store.balance(token).await {
+            Ok(account) => json_response(StatusCode::OK, &account),
+            Err(error) => {
+                let status = match error {
+                    BalanceError::UnknownBalance => StatusCode::NOT_FOUND,
+                    _ => StatusCode::SERVICE_UNAVAILABLE,
+                };
+                json_response(status, &json!({ "error": error.to_string() }))
+            }
+        }
+    }
+}
+
+fn json_response<T: serde::Serialize>(status: StatusCode, body: &T) -> Response {
+    let body = serde_json::to_vec(body).expect("serialization failed");
+    Response::builder()
+        .status(status)
+        .header("Content-Type", "application/json")
+        .body(Body::from(body))
+        .expect("Fail to construct response")
+}
@@ -0,0 +1,86 @@
+//! Prices computed from the request itself, e.g. per token for an LLM proxy, or per kilobyte of upload.
+//!
+//! With a [`DynamicPrice`], the price of a route is not fixed: it is computed on every request, from its
+//! path, query string or headers, and listed in the `402 Payment Required` response. A client paying
+//! sends the same request again with its `X-Payment` header, which is priced the same way and verified
+//! against that price.
+//!
+//! The price is computed before the request reaches the handler, and its body is not read: price
+//! by the size of the body with its `Content-Length` header.
+//!
+//! ```rust,no_run
+//! use axum::extract::Request;
+//! use x402_axum::X402Middleware;
+//! use x402_axum::dynamic_price::DynamicPriceError;
+//! use x402_axum::price::IntoPriceTag;
+//! use x402_rs::address_evm;
+//! use x402_rs::network::{Network, USDCDeployment};
+//!
+//! let x402 = X402Middleware::try_from("https://facilitator.example/").unwrap();
+//! // 0.00002 USDC per completion token, as in `/completions?max_tokens=512`
+//! let paygate = x402.with_dynamic_price(|request: &Request| {
+//!     let max_tokens = request
+//!         .uri()
+//!         .query()
+//!         .and_then(|query| query.split('&').find_map(|pair| pair.strip_prefix("max_tokens=")))
+//!         .and_then(|max_tokens| max_tokens.parse::<u32>().ok())
+//!         .unwrap_or(256);
+//!     async move {
+//!         let price_tag = USDCDeployment::by_network(Network::BaseSepolia)
+//!             .pay_to(address_evm!("0xBAc675C310721717Cd4A37F6cbeA1F081b1C2a07"))
+//!             .amount(0.00002 * max_tokens as f64)
+//!             .build()
+//!             .map_err(|e| DynamicPriceError(e.to_string()))?;
+//!         Ok(vec![price_tag])
+//!     }
+//! });
+//! ```
+
+use axum_core::extract::Request;
+use std::fmt::Debug;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+
+use crate::price::PriceTag;
+
+/// Error returned by a [`DynamicPrice`] that can not price a request.
+#[derive(Debug, thiserror::Error)]
+#[error("Dynamic price error: {0}")]
+pub struct DynamicPriceError(pub String);
+
+/// Source of the price tags of a request.
+///
+/// Implemented for functions and closures taking `&Request` and returning a future of
+/// `Result<Vec<PriceTag>, DynamicPriceError>`. The future can not borrow the request: read what
+/// the price depends on first, then move it into an `async move` block.
+pub trait DynamicPrice: Send + Sync {
+    fn price_tags(
+        &self,
+        request: &Request,
+    ) -> Pin<Box<dyn Future<Output = Result<Vec<PriceTag>, DynamicPriceError>> + Send + 'static>>;
+}
+
+impl<F, Fut> DynamicPrice for F
+where
+    F: Fn(&Request) -> Fut + Send + Sync,
+    Fut: Future<Output = Result<Vec<PriceTag>, DynamicPriceError>> + Send + 'static,
+{
+    fn price_tags(
+        &self,
+        request: &Request,
+    ) -> Pin<Box<dyn Future<Output = Result<Vec<PriceTag>, DynamicPriceError>> + Send + 'static>>
+    {
+        Box::pin(self(request))
+    }
+}
+
+/// A [`DynamicPrice`] shared by the clones of a middleware.
+#[derive(Clone)]
+pub(crate) struct DynamicPricing(pub Arc<dyn DynamicPrice>);
+
+impl Debug for DynamicPricing {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("DynamicPricing").finish_non_exhaustive()
+    }
+}
@@ -18,6 +18,9 @@
 //!
 //! - Uses `reqwest` for async HTTP requests
 //! - Supports optional timeout and headers
+//! - Retries requests turned away by an overloaded facilitator (`429` or `503`) after its
+//!   `retry-after` delay or a jittered backoff, see [`RetryPolicy`], and stops sending to it
+//!   for a while when it keeps turning requests away, see [`CircuitBreaker`]
 //! - Integrates with `tracing` if the `telemetry` feature is enabled
 //!
 //! ## Error Handling
@@ -27,13 +30,16 @@
 //! - HTTP transport failures
 //! - JSON deserialization errors
 //! - Unexpected HTTP status responses
+//! - Overload of the facilitator, once retries run out or while the circuit breaker is open
 
+use http::header::RETRY_AFTER;
 use http::{HeaderMap, StatusCode};
-use reqwest::Client;
+use reqwest::{Client, RequestBuilder};
 use std::fmt::Display;
 use std::time::Duration;
 use url::Url;
 use x402_rs::facilitator::Facilitator;
+use x402_rs::retry::{CircuitBreaker, Overload, RetryPolicy};
 use x402_rs::types::{
     SettleRequest, SettleResponse, SupportedPaymentKindsResponse, VerifyRequest, VerifyResponse,
 };
@@ -61,6 +67,10 @@ pub struct FacilitatorClient {
     headers: HeaderMap,
     /// Optional request timeout
     timeout: Option<Duration>,
+    /// Retries of requests turned away by the facilitator
+    retry: RetryPolicy,
+    /// Shared by the clones of the client
+    breaker: CircuitBreaker,
 }
 
 impl Facilitator for FacilitatorClient {
@@ -152,6 +162,30 @@ pub enum FacilitatorClientError {
         #[source]
         source: reqwest::Error,
     },
+    #[error("Facilitator overloaded ({status}): {context}: {body}")]
+    Overloaded {
+        context: &'static str,
+        status: StatusCode,
+        retry_after: Option<Duration>,
+        body: String,
+    },
+    #[error("Facilitator overloaded, not sending requests for {retry_after:?}: {context}")]
+    CircuitOpen {
+        context: &'static str,
+        retry_after: Duration,
+    },
+}
+
+impl FacilitatorClientError {
+    /// Overload of the facilitator, if it turned the request away.
+    pub fn overload(&self) -> Option<Overload> {
+        match self {
+            FacilitatorClientError::Overloaded { retry_after, .. } => Some(Overload {
+                retry_after: *retry_after,
+            }),
+            _ => None,
+        }
+    }
 }
 
 impl FacilitatorClient {
@@ -219,6 +253,8 @@ impl FacilitatorClient {
             supported_url,
             headers: HeaderMap::new(),
             timeout: None,
+            retry: RetryPolicy::default(),
+            breaker: CircuitBreaker::default(),
         })
     }
 
@@ -238,6 +274,22 @@ impl FacilitatorClient {
         this
     }
 
+    /// Sets how requests turned away by an overloaded facilitator are retried, see [`RetryPolicy`].
+    #[allow(dead_code)] // Public for consumption by downstream crates.
+    pub fn with_retry(&self, retry: RetryPolicy) -> Self {
+        let mut this = self.clone();
+        this.retry = retry;
+        this
+    }
+
+    /// Stops sending requests to the facilitator per `breaker`, see [`CircuitBreaker`].
+    #[allow(dead_code)] // Public for consumption by downstream crates.
+    pub fn with_circuit_breaker(&self, breaker: CircuitBreaker) -> Self {
+        let mut this = self.clone();
+        this.breaker = breaker;
+        this
+    }
+
     /// Sends a `POST /verify` request to the facilitator.
     pub async fn verify(
         &self,
@@ -261,7 +313,7 @@ impl FacilitatorClient {
     }
 
     /// Generic POST helper that handles JSON serialization, error mapping,
-    /// timeout application, and telemetry integration.
+    /// timeout application, retries, and telemetry integration.
     ///
     /// `context` is a human-readable identifier used in tracing and error messages (e.g. `"POST /verify"`).
     async fn post_json<T, R>(
@@ -274,43 +326,12 @@ impl FacilitatorClient {
         T: serde::Serialize + ?Sized,
         R: serde::de::DeserializeOwned,
     {
-        let mut req = self.client.post(url.clone()).json(payload);
-        for (key, value) in self.headers.iter() {
-            req = req.header(key, value);
-        }
-        if let Some(timeout) = self.timeout {
-            req = req.timeout(timeout);
-        }
-        let http_response = req
-            .send()
+        self.send_json(context, || self.client.post(url.clone()).json(payload))
             .await
-            .map_err(|e| FacilitatorClientError::Http { context, source: e })?;
-
-        let result = if http_response.status() == StatusCode::OK {
-            http_response
-                .json::<R>()
-                .await
-                .map_err(|e| FacilitatorClientError::JsonDeserialization { context, source: e })
-        } else {
-            let status = http_response.status();
-            let body = http_response
-                .text()
-                .await
-                .map_err(|e| FacilitatorClientError::ResponseBodyRead { context, source: e })?;
-            Err(FacilitatorClientError::HttpStatus {
-                context,
-                status,
-                body,
-            })
-        };
-
-        record_result_on_span(&result);
-
-        result
     }
 
     /// Generic GET helper that handles JSON serialization, error mapping,
-    /// timeout application, and telemetry integration.
+    /// timeout application, retries, and telemetry integration.
     ///
     /// `context` is a human-readable identifier used in tracing and error messages (e.g. `"POST /verify"`).
     async fn get_json<R>(
@@ -321,7 +342,46 @@ impl FacilitatorClient {
     where
         R: serde::de::DeserializeOwned,
     {
-        let mut req = self.client.get(url.clone());
+        self.send_json(context, || self.client.get(url.clone()))
+            .await
+    }
+
+    /// Sends the request made by `request`, again if the facilitator turns it away, per the
+    /// [`RetryPolicy`] and the [`CircuitBreaker`] of the client.
+    async fn send_json<R>(
+        &self,
+        context: &'static str,
+        request: impl Fn() -> RequestBuilder,
+    ) -> Result<R, FacilitatorClientError>
+    where
+        R: serde::de::DeserializeOwned,
+    {
+        let result = self
+            .retry
+            .run(
+                &self.breaker,
+                FacilitatorClientError::overload,
+                |retry_after| FacilitatorClientError::CircuitOpen {
+                    context,
+                    retry_after,
+                },
+                || self.send_json_once(context, request()),
+            )
+            .await;
+
+        record_result_on_span(&result);
+
+        result
+    }
+
+    async fn send_json_once<R>(
+        &self,
+        context: &'static str,
+        mut req: RequestBuilder,
+    ) -> Result<R, FacilitatorClientError>
+    where
+        R: serde::de::DeserializeOwned,
+    {
         for (key, value) in self.headers.iter() {
             req = req.header(key, value);
         }
@@ -333,27 +393,35 @@ impl FacilitatorClient {
             .await
             .map_err(|e| FacilitatorClientError::Http { context, source: e })?;
 
-        let result = if http_response.status() == StatusCode::OK {
-            http_response
+        if http_response.status() == StatusCode::OK {
+            return http_response
                 .json::<R>()
                 .await
-                .map_err(|e| FacilitatorClientError::JsonDeserialization { context, source: e })
-        } else {
-            let status = http_response.status();
-            let body = http_response
-                .text()
-                .await
-                .map_err(|e| FacilitatorClientError::ResponseBodyRead { context, source: e })?;
-            Err(FacilitatorClientError::HttpStatus {
+                .map_err(|e| FacilitatorClientError::JsonDeserialization { context, source: e });
+        }
+        let status = http_response.status();
+        let retry_after = http_response
+            .headers()
+            .get(RETRY_AFTER)
+            .and_then(|value| value.to_str().ok())
+            .map(str::to_string);
+        let body = http_response
+            .text()
+            .await
+            .map_err(|e| FacilitatorClientError::ResponseBodyRead { context, source: e })?;
+        match Overload::from_http(status.as_u16(), retry_after.as_deref()) {
+            Some(overload) => Err(FacilitatorClientError::Overloaded {
                 context,
                 status,
+                retry_after: overload.retry_after,
                 body,
-            })
-        };
-
-        record_result_on_span(&result);
-
-        result
+            }),
+            None => Err(FacilitatorClientError::HttpStatus {
+                context,
+                status,
+                body,
+            }),
+        }
     }
 }
 
@@ -12,13 +12,14 @@
 //! use axum::response::IntoResponse;
 //! use http::StatusCode;
 //! use serde_json::json;
+//! use x402_rs::address_evm;
 //! use x402_rs::network::{Network, USDCDeployment};
 //! use x402_axum::layer::X402Middleware;
 //! use x402_axum::price::IntoPriceTag;
 //!
 //! let x402 = X402Middleware::try_from("https://facilitator.ukstv.me/").unwrap();
 //! let usdc = USDCDeployment::by_network(Network::BaseSepolia)
-//!     .pay_to("0xADDRESS");
+//!     .pay_to(address_evm!("0xBAc675C310721717Cd4A37F6cbeA1F081b1C2a07"));
 //!
 //! let app: Router = Router::new().route(
 //!     "/protected",
@@ -35,13 +36,33 @@
 //!
 //! ## Configuration Notes
 //!
-//! - **[`X402Middleware::with_price_tag`]** sets the assets and amounts accepted for payment.
+//! - **[`X402Middleware::with_price_tag`]** sets the assets and amounts accepted for payment. Several price tags
+//!   are alternatives, e.g. USDC on Base or on Polygon, or EURC on Base, all listed in the `accepts` of the
+//!   `402 Payment Required` response for the payer to choose from: add them with
+//!   **[`X402Middleware::or_price_tag`]** or **[`X402Middleware::or_token_price_on`]**.
 //! - **[`X402Middleware::with_description`]** and **[`X402Middleware::with_mime_type`]** are optional but help the payer understand what is being paid for.
 //! - **[`X402Middleware::with_resource`]** explicitly sets the full URI of the protected resource.
 //!   This avoids recomputing [`PaymentRequirements`] on every request and should be preferred when possible.
 //! - If `with_resource` is **not** used, the middleware will compute the resource URI dynamically from the request
 //!   and a base URL set via **[`X402Middleware::with_base_url`]**.
 //! - If no base URL is provided, the default is `http://localhost/` (⚠️ avoid this in production).
+//! - To verify and settle in-process, without a facilitator service, create the middleware from a
+//!   [`FacilitatorLocal`], or with [`X402Middleware::local_from_env`].
+//! - With several facilitators, use [`X402Middleware::try_from`] on a slice of URLs: requests are routed to the
+//!   best performing one, see [`FacilitatorPool`].
+//! - **[`X402Middleware::with_dynamic_price`]** prices every request from its path, query string or headers,
+//!   e.g. per token for an LLM proxy, instead of fixed price tags, see [`crate::dynamic_price`].
+//! - **[`X402Middleware::with_settle_on_success`]** settles payments only once the handler succeeds, with a `2xx`
+//!   status, so that buyers are not charged for errors. **[`X402Middleware::with_settle_failure`]** chooses
+//!   whether a response whose settlement fails is still served ([`SettleFailure::FailOpen`]) or replaced by a
+//!   `402` ([`SettleFailure::FailClosed`], the default).
+//! - **[`X402Middleware::with_prepaid_balance`]** lets buyers pay a deposit once, charged per request,
+//!   see [`crate::balance`].
+//! - **[`X402Middleware::with_metadata`]** attaches opaque data (e.g. a product id) to the verify and settle requests,
+//!   echoed back in the settlement. Per-request data, such as an order id, can be attached by an outer layer
+//!   as a [`PaymentMetadata`] request extension.
+//! - Handlers get the payer, amount and network of the payment of their request as a [`PaymentDetails`]
+//!   extension, also an extractor, see [`crate::payment`].
 //!
 //! ## Best Practices (Production)
 //!
@@ -60,6 +81,7 @@ use serde_json::json;
 use std::collections::HashSet;
 use std::fmt::{Debug, Display};
 use std::sync::Arc;
+use std::time::Duration;
 use std::{
     convert::Infallible,
     future::Future,
@@ -69,19 +91,28 @@ use std::{
 use tower::util::BoxCloneSyncService;
 use tower::{Layer, Service};
 use url::Url;
+use x402_rs::balance::{BALANCE_HEADER, BALANCE_REMAINING_HEADER, BalanceAccount, Charge};
+use x402_rs::duration::Seconds;
 use x402_rs::facilitator::Facilitator;
+use x402_rs::facilitator_local::FacilitatorLocal;
+use x402_rs::facilitator_pool::FacilitatorPool;
 use x402_rs::network::Network;
+use x402_rs::provider_cache::ProviderCache;
 use x402_rs::types::{
-    Base64Bytes, FacilitatorErrorReason, MixedAddress, PaymentPayload, PaymentRequiredResponse,
-    PaymentRequirements, Scheme, SettleRequest, SettleResponse, TokenAmount, VerifyRequest,
-    VerifyResponse, X402Version,
+    Base64Bytes, FacilitatorErrorReason, MixedAddress, MoneyAmount, PaymentPayload,
+    PaymentRequiredResponse, PaymentRequirements, Scheme, SettleRequest, SettleResponse,
+    TokenAmount, VerifyRequest, VerifyResponse, X402Version,
 };
 
 #[cfg(feature = "telemetry")]
 use tracing::{Instrument, Level, instrument};
 
+use crate::balance::PrepaidBalance;
+use crate::dynamic_price::{DynamicPrice, DynamicPricing};
 use crate::facilitator_client::{FacilitatorClient, FacilitatorClientError};
-use crate::price::PriceTag;
+use crate::payment::PaymentDetails;
+use crate::price::{PriceTag, TokenRegistry, token_price_tags, usdc_price_tags};
+use crate::quote::{PriceOracle, QUOTE_HEADER, Quote, QuoteBook, QuotedPricing};
 
 /// Middleware layer that enforces x402 payment verification and settlement.
 ///
@@ -102,8 +133,20 @@ pub struct X402Middleware<F> {
     base_url: Option<Url>,
     /// List of price tags accepted for this endpoint.
     price_tag: Vec<PriceTag>,
-    /// Timeout in seconds for payment settlement.
-    max_timeout_seconds: u64,
+    /// Optional source of moving prices, used in place of `price_tag`, see [`X402Middleware::with_price_oracle`].
+    price_oracle: Option<QuotedPricing>,
+    /// Optional price computed from every request, used in place of `price_tag`, see [`X402Middleware::with_dynamic_price`].
+    dynamic_price: Option<DynamicPricing>,
+    /// Optional prepaid balances charged in place of single payments, see [`X402Middleware::with_prepaid_balance`].
+    prepaid: Option<PrepaidBalance>,
+    /// Timeout for payment settlement.
+    max_timeout_seconds: Seconds,
+    /// Opaque data attached to the verify and settle requests.
+    metadata: Option<serde_json::Value>,
+    /// Responses after which payments are settled, see [`X402Middleware::with_settle_on_success`].
+    settle_policy: SettlePolicy,
+    /// Response served when a settlement fails, see [`X402Middleware::with_settle_failure`].
+    settle_failure: SettleFailure,
     /// Cached set of payment offers for this middleware instance.
     ///
     /// This field holds either:
@@ -130,6 +173,52 @@ impl TryFrom<String> for X402Middleware<FacilitatorClient> {
     }
 }
 
+impl TryFrom<&[&str]> for X402Middleware<FacilitatorPool<FacilitatorClient>> {
+    type Error = FacilitatorClientError;
+
+    /// Creates a middleware routing to the best performing of several facilitators,
+    /// see [`FacilitatorPool`]. Facilitators are named by their URLs.
+    fn try_from(urls: &[&str]) -> Result<Self, Self::Error> {
+        let facilitators = urls
+            .iter()
+            .map(|url| FacilitatorClient::try_from(*url).map(|client| (url.to_string(), client)))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(X402Middleware::new(FacilitatorPool::new(facilitators)))
+    }
+}
+
+impl From<FacilitatorLocal> for X402Middleware<FacilitatorLocal> {
+    /// Creates a middleware verifying and settling payments in-process with `facilitator`,
+    /// without a facilitator service.
+    fn from(facilitator: FacilitatorLocal) -> Self {
+        X402Middleware::new(facilitator)
+    }
+}
+
+impl X402Middleware<FacilitatorLocal> {
+    /// Creates a middleware verifying and settling payments in-process, on the networks
+    /// configured in the environment like for the facilitator service: `RPC_URL_<NETWORK>`
+    /// endpoints, and `EVM_PRIVATE_KEY` or `SOLANA_PRIVATE_KEY` paying the gas of settlements.
+    ///
+    /// ```rust,no_run
+    /// # async fn run() -> Result<(), Box<dyn std::error::Error>> {
+    /// use x402_axum::X402Middleware;
+    ///
+    /// let x402 = X402Middleware::local_from_env().await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// Fails if a provider can not be built, see [`ProviderCache::from_env`]. Build a
+    /// [`FacilitatorLocal`] to configure it further, e.g. with a persistent nonce store, and
+    /// convert it with [`From`].
+    #[allow(dead_code)] // Public for consumption by downstream crates.
+    pub async fn local_from_env() -> Result<Self, Box<dyn std::error::Error>> {
+        let provider_cache = ProviderCache::from_env().await?;
+        Ok(X402Middleware::from(FacilitatorLocal::new(provider_cache)))
+    }
+}
+
 impl<F> X402Middleware<F>
 where
     F: Clone,
@@ -141,8 +230,14 @@ where
             mime_type: None,
             resource: None,
             base_url: None,
-            max_timeout_seconds: 300,
+            max_timeout_seconds: Seconds::new(300),
+            metadata: None,
+            settle_policy: SettlePolicy::default(),
+            settle_failure: SettleFailure::default(),
             price_tag: Vec::new(),
+            price_oracle: None,
+            dynamic_price: None,
+            prepaid: None,
             payment_offers: Arc::new(PaymentOffers::Ready(Arc::new(Vec::new()))),
         }
     }
@@ -197,10 +292,43 @@ where
     #[allow(dead_code)] // Public for consumption by downstream crates.
     pub fn with_max_timeout_seconds(&self, seconds: u64) -> Self {
         let mut this = self.clone();
-        this.max_timeout_seconds = seconds;
+        this.max_timeout_seconds = Seconds::new(seconds);
         this.recompute_offers()
     }
 
+    /// Attaches opaque data, e.g. `{"product": "weather"}`, to the verify and settle requests
+    /// sent to the facilitator. It is echoed in the settlement, in the `X-Payment-Response` header.
+    ///
+    /// A [`PaymentMetadata`] request extension takes precedence, for per-request data.
+    #[allow(dead_code)] // Public for consumption by downstream crates.
+    pub fn with_metadata(&self, metadata: serde_json::Value) -> Self {
+        let mut this = self.clone();
+        this.metadata = Some(metadata);
+        this
+    }
+
+    /// Settles payments only after responses with a `2xx` status: a payment verified for a
+    /// request whose handler fails is not settled, so the buyer is not charged for the error.
+    /// The authorization it carries is never submitted, and expires unused.
+    ///
+    /// Requests charged against a prepaid balance are charged before the handler, whatever its
+    /// response.
+    #[allow(dead_code)] // Public for consumption by downstream crates.
+    pub fn with_settle_on_success(&self) -> Self {
+        let mut this = self.clone();
+        this.settle_policy = SettlePolicy::OnSuccess;
+        this
+    }
+
+    /// Sets the response served when the settlement of a payment fails once the handler ran:
+    /// a `402` in place of the response of the handler by default, see [`SettleFailure`].
+    #[allow(dead_code)] // Public for consumption by downstream crates.
+    pub fn with_settle_failure(&self, settle_failure: SettleFailure) -> Self {
+        let mut this = self.clone();
+        this.settle_failure = settle_failure;
+        this
+    }
+
     /// Replaces all price tags with the provided value(s).
     #[allow(dead_code)] // Public for consumption by downstream crates.
     pub fn with_price_tag<T: Into<Vec<PriceTag>>>(&self, price_tag: T) -> Self {
@@ -222,17 +350,155 @@ where
         this.recompute_offers()
     }
 
+    /// Replaces all price tags with equivalent USDC prices on each of `networks`,
+    /// see [`usdc_price_tags`].
+    ///
+    /// ```rust,no_run
+    /// use x402_axum::X402Middleware;
+    /// use x402_rs::address_evm;
+    /// use x402_rs::network::Network;
+    ///
+    /// let x402 = X402Middleware::try_from("https://facilitator.example/").unwrap();
+    /// let paygate = x402.with_usdc_price_on(
+    ///     &[Network::Base, Network::PolygonAmoy],
+    ///     "0.01",
+    ///     address_evm!("0xBAc675C310721717Cd4A37F6cbeA1F081b1C2a07"),
+    /// );
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// Panics if a price tag can not be built, e.g. on an invalid amount, or if `pay_to`
+    /// is not an address of a network's family. Use [`usdc_price_tags`] with
+    /// [`with_price_tag`](Self::with_price_tag) to handle the error instead.
+    #[allow(dead_code)] // Public for consumption by downstream crates.
+    pub fn with_usdc_price_on<A, P>(&self, networks: &[Network], amount: A, pay_to: P) -> Self
+    where
+        A: TryInto<MoneyAmount> + Clone,
+        P: Into<MixedAddress>,
+    {
+        let price_tags = usdc_price_tags(networks, amount, pay_to)
+            .unwrap_or_else(|e| panic!("Invalid USDC price: {e}"));
+        self.with_price_tag(price_tags)
+    }
+
+    /// Adds equivalent prices in the token `symbol` of `registry` on each of `networks` to the
+    /// existing price tags, see [`token_price_tags`]. The payer chooses among all of them.
+    ///
+    /// ```rust,no_run
+    /// use x402_axum::X402Middleware;
+    /// use x402_axum::price::TokenRegistry;
+    /// use x402_rs::address_evm;
+    /// use x402_rs::network::Network;
+    ///
+    /// let pay_to = address_evm!("0xBAc675C310721717Cd4A37F6cbeA1F081b1C2a07");
+    /// let x402 = X402Middleware::try_from("https://facilitator.example/").unwrap();
+    /// // USDC on Base or on Polygon, or EURC on Base
+    /// let paygate = x402
+    ///     .with_usdc_price_on(&[Network::Base, Network::Polygon], "0.01", pay_to.clone())
+    ///     .or_token_price_on(&TokenRegistry::known(), "EURC", &[Network::Base], "0.01", pay_to);
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// Panics if a price tag can not be built, e.g. on a token not registered on a network, or
+    /// if `pay_to` is not an address of a network's family. Use [`token_price_tags`] with
+    /// [`or_price_tag`](Self::or_price_tag) to handle the error instead.
+    #[allow(dead_code)] // Public for consumption by downstream crates.
+    pub fn or_token_price_on<A, P>(
+        &self,
+        registry: &TokenRegistry,
+        symbol: &str,
+        networks: &[Network],
+        amount: A,
+        pay_to: P,
+    ) -> Self
+    where
+        A: TryInto<MoneyAmount> + Clone,
+        P: Into<MixedAddress>,
+    {
+        let price_tags = token_price_tags(registry, symbol, networks, amount, pay_to)
+            .unwrap_or_else(|e| panic!("Invalid {symbol} price: {e}"));
+        self.or_price_tag(price_tags)
+    }
+
+    /// Prices the endpoint with `oracle` on every request, instead of fixed price tags, e.g. to
+    /// peg the price to a fiat amount. Quoted prices are honored for `quote_ttl`, see [`crate::quote`].
+    ///
+    /// Replaces a price set with [`with_dynamic_price`](Self::with_dynamic_price).
+    #[allow(dead_code)] // Public for consumption by downstream crates.
+    pub fn with_price_oracle<O: PriceOracle + 'static>(
+        &self,
+        oracle: O,
+        quote_ttl: Duration,
+    ) -> Self {
+        let mut this = self.clone();
+        this.price_oracle = Some(QuotedPricing {
+            oracle: Arc::new(oracle),
+            book: Arc::new(QuoteBook::new(quote_ttl)),
+        });
+        this.dynamic_price = None;
+        this
+    }
+
+    /// Prices every request with `price`, from its path, query string or headers, instead of
+    /// fixed price tags, e.g. per token for an LLM proxy, see [`crate::dynamic_price`].
+    ///
+    /// Replaces a price oracle set with [`with_price_oracle`](Self::with_price_oracle).
+    #[allow(dead_code)] // Public for consumption by downstream crates.
+    pub fn with_dynamic_price<P: DynamicPrice + 'static>(&self, price: P) -> Self {
+        let mut this = self.clone();
+        this.dynamic_price = Some(DynamicPricing(Arc::new(price)));
+        this.price_oracle = None;
+        this
+    }
+
+    /// Charges requests against prepaid balances of buyers, who pay a deposit worth a number of
+    /// requests once, see [`crate::balance`].
+    #[allow(dead_code)] // Public for consumption by downstream crates.
+    pub fn with_prepaid_balance(&self, prepaid: PrepaidBalance) -> Self {
+        let mut this = self.clone();
+        this.prepaid = Some(prepaid);
+        this
+    }
+
+    fn offer_template(&self) -> OfferTemplate {
+        OfferTemplate {
+            resource: self.resource.clone(),
+            base_url: self.base_url(),
+            description: self.description.clone().unwrap_or_default(),
+            mime_type: self
+                .mime_type
+                .clone()
+                .unwrap_or("application/json".to_string()),
+            max_timeout_seconds: self.max_timeout_seconds,
+        }
+    }
+
     fn recompute_offers(mut self) -> Self {
-        let base_url = self.base_url();
-        let description = self.description.clone().unwrap_or_default();
-        let mime_type = self
-            .mime_type
-            .clone()
-            .unwrap_or("application/json".to_string());
+        self.payment_offers = Arc::new(self.offer_template().offers(&self.price_tag));
+        self
+    }
+}
+
+/// Settings shared by all payment requirements of an endpoint, whatever its price.
+#[derive(Clone, Debug)]
+struct OfferTemplate {
+    resource: Option<Url>,
+    base_url: Url,
+    description: String,
+    mime_type: String,
+    max_timeout_seconds: Seconds,
+}
+
+impl OfferTemplate {
+    fn offers(&self, price_tags: &[PriceTag]) -> PaymentOffers {
+        let base_url = self.base_url.clone();
+        let description = self.description.clone();
+        let mime_type = self.mime_type.clone();
         let max_timeout_seconds = self.max_timeout_seconds;
-        let payment_offers = if let Some(resource) = self.resource.clone() {
-            let payment_requirements = self
-                .price_tag
+        if let Some(resource) = self.resource.clone() {
+            let payment_requirements = price_tags
                 .iter()
                 .map(|price_tag| {
                     let extra = if let Some(eip712) = price_tag.token.eip712.clone() {
@@ -255,13 +521,14 @@ where
                         asset: price_tag.token.address(),
                         extra,
                         output_schema: None,
+                        consumed_amount: None,
+                        settled_amount: None,
                     }
                 })
                 .collect::<Vec<_>>();
             PaymentOffers::Ready(Arc::new(payment_requirements))
         } else {
-            let no_resource = self
-                .price_tag
+            let no_resource = price_tags
                 .iter()
                 .map(|price_tag| {
                     let extra = if let Some(eip712) = price_tag.token.eip712.clone() {
@@ -290,12 +557,96 @@ where
                 partial: no_resource,
                 base_url,
             }
+        }
+    }
+}
+
+/// Payment requirements priced by a [`PriceOracle`] on every request.
+#[derive(Debug)]
+struct QuotedOffers {
+    pricing: QuotedPricing,
+    template: OfferTemplate,
+}
+
+impl QuotedOffers {
+    /// Returns the requirements of the quote named in the `X-Payment-Quote` header if it is still
+    /// valid, or else of the current price. The current price is locked under a new quote when
+    /// the request is not paid yet, for the `402` response.
+    async fn payment_requirements(
+        &self,
+        headers: &HeaderMap,
+        req_uri: &Uri,
+    ) -> Result<Arc<Vec<PaymentRequirements>>, X402Error> {
+        let locked = headers
+            .get(QUOTE_HEADER)
+            .and_then(|id| id.to_str().ok())
+            .and_then(|id| self.pricing.book.get(id));
+        let quote = match locked {
+            Some(quote) => quote,
+            None => {
+                let price_tags = self
+                    .pricing
+                    .oracle
+                    .price_tags()
+                    .await
+                    .map_err(X402Error::price_unavailable)?;
+                if headers.contains_key("X-Payment") {
+                    let offers = self.template.offers(&price_tags);
+                    return Ok(gather_payment_requirements(&offers, req_uri));
+                }
+                self.pricing.book.lock(price_tags)
+            }
         };
-        self.payment_offers = Arc::new(payment_offers);
-        self
+        let offers = self.template.offers(&quote.price_tags);
+        let payment_requirements = gather_payment_requirements(&offers, req_uri)
+            .iter()
+            .map(|requirements| quoted(requirements.clone(), &quote))
+            .collect();
+        Ok(Arc::new(payment_requirements))
+    }
+}
+
+/// Payment requirements priced by a [`DynamicPrice`] from every request.
+#[derive(Debug)]
+struct DynamicOffers {
+    pricing: DynamicPricing,
+    template: OfferTemplate,
+}
+
+impl DynamicOffers {
+    /// Returns the requirements of the price of `req`.
+    ///
+    /// The request is read before the returned future runs, so that it is not held across the
+    /// pricing: its body is not `Sync`.
+    fn payment_requirements(
+        self: Arc<Self>,
+        req: &Request,
+    ) -> impl Future<Output = Result<Arc<Vec<PaymentRequirements>>, X402Error>> + Send + use<> {
+        let price_tags = self.pricing.0.price_tags(req);
+        let req_uri = req.uri().clone();
+        async move {
+            let price_tags = price_tags.await.map_err(X402Error::price_unavailable)?;
+            let offers = self.template.offers(&price_tags);
+            Ok(gather_payment_requirements(&offers, &req_uri))
+        }
     }
 }
 
+/// Advertises `quote` in the `extra` field of `requirements`.
+fn quoted(mut requirements: PaymentRequirements, quote: &Quote) -> PaymentRequirements {
+    let mut extra = match requirements.extra.take() {
+        Some(serde_json::Value::Object(extra)) => extra,
+        _ => serde_json::Map::new(),
+    };
+    extra.insert("quoteId".to_string(), json!(quote.id));
+    extra.insert(
+        "quoteExpiresAt".to_string(),
+        json!(quote.expires_at_timestamp().seconds_since_epoch()),
+    );
+    requirements.extra = Some(serde_json::Value::Object(extra));
+    requirements
+}
+
 /// Wraps a cloned inner Axum service and augments it with payment enforcement logic.
 #[derive(Clone, Debug)]
 pub struct X402MiddlewareService<F> {
@@ -303,6 +654,18 @@ pub struct X402MiddlewareService<F> {
     facilitator: Arc<F>,
     /// Payment requirements either with static or dynamic resource URLs
     payment_offers: Arc<PaymentOffers>,
+    /// Payment requirements priced per request, in place of `payment_offers`
+    quoted_offers: Option<Arc<QuotedOffers>>,
+    /// Payment requirements priced from every request, in place of `payment_offers`
+    dynamic_offers: Option<Arc<DynamicOffers>>,
+    /// Opaque data attached to the verify and settle requests
+    metadata: Option<serde_json::Value>,
+    /// Responses after which payments are settled
+    settle_policy: SettlePolicy,
+    /// Response served when a settlement fails
+    settle_failure: SettleFailure,
+    /// Prepaid balances charged in place of single payments
+    prepaid: Option<PrepaidBalance>,
     /// The inner Axum service being wrapped
     inner: BoxCloneSyncService<Request, Response, Infallible>,
 }
@@ -325,6 +688,22 @@ where
         X402MiddlewareService {
             facilitator: self.facilitator.clone(),
             payment_offers: self.payment_offers.clone(),
+            quoted_offers: self.price_oracle.clone().map(|pricing| {
+                Arc::new(QuotedOffers {
+                    pricing,
+                    template: self.offer_template(),
+                })
+            }),
+            dynamic_offers: self.dynamic_price.clone().map(|pricing| {
+                Arc::new(DynamicOffers {
+                    pricing,
+                    template: self.offer_template(),
+                })
+            }),
+            metadata: self.metadata.clone(),
+            settle_policy: self.settle_policy,
+            settle_failure: self.settle_failure,
+            prepaid: self.prepaid.clone(),
             inner: BoxCloneSyncService::new(inner),
         }
     }
@@ -345,17 +724,83 @@ where
 
     /// Intercepts the request, injects payment enforcement logic, and forwards to the wrapped service.
     fn call(&mut self, req: Request) -> Self::Future {
-        let payment_requirements =
-            gather_payment_requirements(self.payment_offers.as_ref(), req.uri());
-        let gate = X402Paygate {
-            facilitator: self.facilitator.clone(),
-            payment_requirements,
-        };
+        let payment_offers = self.payment_offers.clone();
+        let quoted_offers = self.quoted_offers.clone();
+        let dynamic_offers = self.dynamic_offers.clone();
+        let metadata = req
+            .extensions()
+            .get::<PaymentMetadata>()
+            .map(|metadata| metadata.0.clone())
+            .or_else(|| self.metadata.clone());
+        let facilitator = self.facilitator.clone();
+        let settle_policy = self.settle_policy;
+        let settle_failure = self.settle_failure;
+        let prepaid = self.prepaid.clone();
         let inner = self.inner.clone();
-        Box::pin(gate.call(inner, req))
+        Box::pin(async move {
+            let payment_requirements = match (quoted_offers, dynamic_offers) {
+                (Some(quoted_offers), _) => {
+                    match quoted_offers
+                        .payment_requirements(req.headers(), req.uri())
+                        .await
+                    {
+                        Ok(payment_requirements) => payment_requirements,
+                        Err(err) => return Ok(err.into_response()),
+                    }
+                }
+                (None, Some(dynamic_offers)) => {
+                    match dynamic_offers.payment_requirements(&req).await {
+                        Ok(payment_requirements) => payment_requirements,
+                        Err(err) => return Ok(err.into_response()),
+                    }
+                }
+                (None, None) => gather_payment_requirements(payment_offers.as_ref(), req.uri()),
+            };
+            let gate = X402Paygate {
+                facilitator,
+                payment_requirements,
+                metadata,
+                settle_policy,
+                settle_failure,
+            };
+            match prepaid {
+                Some(prepaid) => Ok(gate.handle_prepaid_request(&prepaid, inner, req).await),
+                None => gate.call(inner, req).await,
+            }
+        })
     }
 }
 
+/// Responses after which the middleware settles the payment of a request.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SettlePolicy {
+    /// Settles after every response of the handler, whatever its status.
+    #[default]
+    Always,
+    /// Settles only after a response with a `2xx` status, see [`X402Middleware::with_settle_on_success`].
+    OnSuccess,
+}
+
+/// Response served when the settlement of a payment fails once the handler ran.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SettleFailure {
+    /// Replaces the response of the handler with a `402 Payment Required`: the resource is not
+    /// served unpaid.
+    #[default]
+    FailClosed,
+    /// Serves the response of the handler anyway, without a `X-Payment-Response` header: the
+    /// resource may be served unpaid, but a settlement failing after the work is done does not
+    /// waste it.
+    FailOpen,
+}
+
+/// Request extension attaching per-request opaque data (e.g. an order id) to the payment,
+/// in place of [`X402Middleware::with_metadata`].
+///
+/// Insert it from a layer running before the [`X402Middleware`].
+#[derive(Clone, Debug)]
+pub struct PaymentMetadata(pub serde_json::Value);
+
 #[derive(Debug)]
 /// Wrapper for producing a `402 Payment Required` response with context.
 pub struct X402Error(PaymentRequiredResponse);
@@ -417,6 +862,29 @@ impl X402Error {
         Self(payment_required_response)
     }
 
+    /// A request the prepaid balance it carries can not pay, re-challenged with the requirements
+    /// of a deposit.
+    pub fn prepaid_balance_required<E2: Display>(
+        error: E2,
+        payment_requirements: Vec<PaymentRequirements>,
+    ) -> Self {
+        let payment_required_response = PaymentRequiredResponse {
+            error: error.to_string(),
+            accepts: payment_requirements,
+            x402_version: X402Version::V1,
+        };
+        Self(payment_required_response)
+    }
+
+    pub fn price_unavailable<E2: Display>(error: E2) -> Self {
+        let payment_required_response = PaymentRequiredResponse {
+            error: format!("Unable to price the resource: {error}"),
+            accepts: vec![],
+            x402_version: X402Version::V1,
+        };
+        Self(payment_required_response)
+    }
+
     pub fn settlement_failed<E2: Display>(
         error: E2,
         payment_requirements: Vec<PaymentRequirements>,
@@ -448,6 +916,12 @@ impl IntoResponse for X402Error {
 pub struct X402Paygate<F> {
     pub facilitator: Arc<F>,
     pub payment_requirements: Arc<Vec<PaymentRequirements>>,
+    /// Opaque data attached to the verify and settle requests.
+    pub metadata: Option<serde_json::Value>,
+    /// Responses after which the payment is settled.
+    pub settle_policy: SettlePolicy,
+    /// Response served when the settlement fails.
+    pub settle_failure: SettleFailure,
 }
 
 impl<F> X402Paygate<F>
@@ -479,13 +953,28 @@ where
                         let extra = supported
                             .kinds
                             .iter()
-                            .find(|s| s.network == network)
+                            .find(|s| s.network == network && s.scheme == r.scheme)
                             .cloned()
                             .and_then(|s| s.extra);
                         if let Some(extra) = extra {
-                            r.extra = Some(json!({
+                            let mut fee_payer_extra = json!({
                                 "feePayer": extra.fee_payer
-                            }));
+                            });
+                            // Quoted requirements keep their quote, see `crate::quote`, deposits
+                            // their number of requests, see `crate::balance`, and `permit`
+                            // requirements their EIP-712 domain
+                            for key in [
+                                "quoteId",
+                                "quoteExpiresAt",
+                                "prepaidRequests",
+                                "name",
+                                "version",
+                            ] {
+                                if let Some(value) = r.extra.as_ref().and_then(|e| e.get(key)) {
+                                    fee_payer_extra[key] = value.clone();
+                                }
+                            }
+                            r.extra = Some(fee_payer_extra);
                             r
                         } else {
                             r
@@ -522,14 +1011,25 @@ where
     }
 
     /// Verifies the provided payment using the facilitator and known requirements. Returns a [`VerifyRequest`] if the payment is valid.
+    pub async fn verify_payment(
+        &self,
+        payment_payload: PaymentPayload,
+    ) -> Result<VerifyRequest, X402Error> {
+        self.verify_payment_details(payment_payload)
+            .await
+            .map(|(verify_request, _)| verify_request)
+    }
+
+    /// Verifies the provided payment like [`verify_payment`](Self::verify_payment), and returns
+    /// the [`PaymentDetails`] of the request along with the [`VerifyRequest`].
     #[cfg_attr(
         feature = "telemetry",
         instrument(name = "x402.verify_payment", skip_all, err)
     )]
-    pub async fn verify_payment(
+    pub async fn verify_payment_details(
         &self,
         payment_payload: PaymentPayload,
-    ) -> Result<VerifyRequest, X402Error> {
+    ) -> Result<(VerifyRequest, PaymentDetails), X402Error> {
         let selected = self
             .find_matching_payment_requirements(&payment_payload)
             .ok_or(X402Error::no_payment_matching(
@@ -539,6 +1039,8 @@ where
             x402_version: payment_payload.x402_version,
             payment_payload,
             payment_requirements: selected,
+            metadata: self.metadata.clone(),
+            idempotency_key: None,
         };
         let verify_response = self
             .facilitator
@@ -548,7 +1050,26 @@ where
                 X402Error::verification_failed(e, self.payment_requirements.as_ref().clone())
             })?;
         match verify_response {
-            VerifyResponse::Valid { .. } => Ok(verify_request),
+            VerifyResponse::Valid { payer, warnings } => {
+                #[cfg(feature = "telemetry")]
+                for warning in &warnings {
+                    tracing::warn!(code = ?warning.code, message = %warning.message, "Payment verified with warning");
+                }
+                #[cfg(not(feature = "telemetry"))]
+                let _ = warnings;
+                let requirements = &verify_request.payment_requirements;
+                let payment_details = PaymentDetails {
+                    payer,
+                    scheme: requirements.scheme.clone(),
+                    network: requirements.network,
+                    asset: requirements.asset.clone(),
+                    pay_to: requirements.pay_to.clone(),
+                    amount: requirements.max_amount_required,
+                    transaction: None,
+                    prepaid_balance: None,
+                };
+                Ok((verify_request, payment_details))
+            }
             VerifyResponse::Invalid { reason, .. } => Err(X402Error::verification_failed(
                 reason,
                 self.payment_requirements.as_ref().clone(),
@@ -583,7 +1104,8 @@ where
 
     /// Processes an incoming request through the middleware:
     /// determines payment requirements, verifies the payment,
-    /// and invokes the inner Axum handler if the payment is valid.
+    /// and invokes the inner Axum handler if the payment is valid, with the [`PaymentDetails`]
+    /// of the request as an extension.
     /// Adds a `X-Payment-Response` header to the response on success.
     pub async fn call<
         ReqBody,
@@ -613,7 +1135,7 @@ where
     >(
         self,
         mut inner: S,
-        req: http::Request<ReqBody>,
+        mut req: http::Request<ReqBody>,
     ) -> Response
     where
         S::Response: IntoResponse,
@@ -627,10 +1149,12 @@ where
                 return err.into_response();
             }
         };
-        let verify_request = match self.verify_payment(payment_payload).await {
-            Ok(verify_request) => verify_request,
-            Err(err) => return err.into_response(),
-        };
+        let (verify_request, payment_details) =
+            match self.verify_payment_details(payment_payload).await {
+                Ok(verified) => verified,
+                Err(err) => return err.into_response(),
+            };
+        req.extensions_mut().insert(payment_details);
         let inner_fut = {
             #[cfg(feature = "telemetry")]
             {
@@ -645,34 +1169,187 @@ where
             Ok(response) => response,
             Err(err) => return err.into_response(),
         };
+        if self.settle_policy == SettlePolicy::OnSuccess && !response.status().is_success() {
+            #[cfg(feature = "telemetry")]
+            tracing::event!(Level::INFO, status = %response.status(), "Handler failed, payment not settled");
+            return response.into_response();
+        }
         let settlement = match self.settle_payment(&verify_request).await {
             Ok(settlement) => settlement,
-            Err(err) => return err.into_response(),
-        };
-        let payment_header: Base64Bytes = match settlement.try_into() {
-            Ok(payment_header) => payment_header,
-            Err(err) => {
-                return X402Error::settlement_failed(
-                    err,
-                    self.payment_requirements.as_ref().clone(),
-                )
-                .into_response();
-            }
+            Err(err) => return self.settlement_failed(err, response),
         };
-        let header_value = match HeaderValue::from_bytes(payment_header.as_ref()) {
+        let header_value = match self.settlement_header(settlement) {
             Ok(header_value) => header_value,
-            Err(err) => {
-                return X402Error::settlement_failed(
-                    err,
-                    self.payment_requirements.as_ref().clone(),
-                )
-                .into_response();
-            }
+            Err(err) => return self.settlement_failed(err, response),
         };
         let mut res = response;
         res.headers_mut().insert("X-Payment-Response", header_value);
         res.into_response()
     }
+
+    /// Answers a request whose settlement failed with `err`, or with the `response` of the
+    /// handler, as set by [`SettleFailure`].
+    fn settlement_failed<R: IntoResponse>(&self, err: X402Error, response: R) -> Response {
+        match self.settle_failure {
+            SettleFailure::FailClosed => err.into_response(),
+            SettleFailure::FailOpen => {
+                #[cfg(feature = "telemetry")]
+                tracing::warn!(error = %err, "Settlement failed, response served unpaid");
+                response.into_response()
+            }
+        }
+    }
+
+    /// Encodes `settlement` as the value of the `X-Payment-Response` header.
+    fn settlement_header(&self, settlement: SettleResponse) -> Result<HeaderValue, X402Error> {
+        let payment_header: Base64Bytes = settlement.try_into().map_err(|err| {
+            X402Error::settlement_failed(err, self.payment_requirements.as_ref().clone())
+        })?;
+        HeaderValue::from_bytes(payment_header.as_ref()).map_err(|err| {
+            X402Error::settlement_failed(err, self.payment_requirements.as_ref().clone())
+        })
+    }
+
+    /// Serves a request of a route charged against prepaid balances, see [`crate::balance`]:
+    /// settles the deposit paid in the `X-Payment` header, if any, then charges the balance of
+    /// the deposit, or of the `X-Payment-Balance` header, before invoking the inner handler.
+    /// Requests the balance can not pay are re-challenged with the requirements of a deposit.
+    /// The inner handler gets the [`PaymentDetails`] of the charge as an extension.
+    #[cfg_attr(
+        feature = "telemetry",
+        instrument(name = "x402.handle_prepaid_request", skip_all)
+    )]
+    pub async fn handle_prepaid_request<
+        ReqBody,
+        ResBody,
+        S: Service<http::Request<ReqBody>, Response = http::Response<ResBody>>,
+    >(
+        self,
+        prepaid: &PrepaidBalance,
+        mut inner: S,
+        mut req: http::Request<ReqBody>,
+    ) -> Response
+    where
+        S::Response: IntoResponse,
+        S::Error: IntoResponse,
+    {
+        let charges = PrepaidBalance::charges(&self.payment_requirements);
+        let deposit_gate = X402Paygate {
+            facilitator: self.facilitator.clone(),
+            payment_requirements: Arc::new(
+                prepaid.deposit_requirements(&self.payment_requirements),
+            ),
+            metadata: self.metadata.clone(),
+            settle_policy: self.settle_policy,
+            settle_failure: self.settle_failure,
+        };
+        let deposit_requirements = || deposit_gate.payment_requirements.as_ref().clone();
+        let token = req
+            .headers()
+            .get(BALANCE_HEADER)
+            .and_then(|token| token.to_str().ok())
+            .map(str::to_string);
+        let mut settlement = None;
+        let token = if req.headers().contains_key("X-Payment") || token.is_none() {
+            let payment_payload = match deposit_gate.extract_payment_payload(req.headers()).await {
+                Ok(payment_payload) => payment_payload,
+                Err(err) => return err.into_response(),
+            };
+            let Some(payment_requirements) =
+                deposit_gate.find_matching_payment_requirements(&payment_payload)
+            else {
+                return X402Error::no_payment_matching(deposit_requirements()).into_response();
+            };
+            let deposit_request = SettleRequest {
+                x402_version: payment_payload.x402_version,
+                payment_payload,
+                payment_requirements,
+                metadata: self.metadata.clone(),
+                idempotency_key: None,
+            };
+            let deposit = match prepaid
+                .store()
+                .deposit(&deposit_request, token.as_deref())
+                .await
+            {
+                Ok(deposit) => deposit,
+                Err(err) => {
+                    return X402Error::verification_failed(err, deposit_requirements())
+                        .into_response();
+                }
+            };
+            #[cfg(feature = "telemetry")]
+            tracing::info!(balance = %deposit.account.balance, "Prepaid balance credited");
+            settlement = Some(deposit.settlement);
+            deposit.account.token
+        } else {
+            token.unwrap_or_default()
+        };
+        let account = match prepaid.store().charge(&token, &charges).await {
+            Ok(account) => account,
+            Err(err) => {
+                #[cfg(feature = "telemetry")]
+                tracing::event!(Level::INFO, status = "failed", error = %err, "Prepaid balance not charged");
+                return X402Error::prepaid_balance_required(err, deposit_requirements())
+                    .into_response();
+            }
+        };
+        if let Some(payment_details) =
+            self.prepaid_payment_details(&account, &charges, settlement.as_ref())
+        {
+            req.extensions_mut().insert(payment_details);
+        }
+        let response = match inner.call(req).await {
+            Ok(response) => response,
+            Err(err) => return err.into_response(),
+        };
+        let mut res = response.into_response();
+        if let Some(settlement) = settlement {
+            match deposit_gate.settlement_header(settlement) {
+                Ok(header_value) => {
+                    res.headers_mut().insert("X-Payment-Response", header_value);
+                }
+                Err(err) => return err.into_response(),
+            }
+            if let Ok(token) = HeaderValue::from_str(&token) {
+                res.headers_mut().insert(BALANCE_HEADER, token);
+            }
+        }
+        if let Ok(remaining) = HeaderValue::from_str(&account.balance.to_string()) {
+            res.headers_mut()
+                .insert(BALANCE_REMAINING_HEADER, remaining);
+        }
+        res
+    }
+
+    /// The [`PaymentDetails`] of a request charged against `account`, with the `settlement` of
+    /// the deposit it came with, if any.
+    fn prepaid_payment_details(
+        &self,
+        account: &BalanceAccount,
+        charges: &[Charge],
+        settlement: Option<&SettleResponse>,
+    ) -> Option<PaymentDetails> {
+        let charge = charges.iter().find(|charge| {
+            charge.network == account.network
+                && charge.asset == account.asset
+                && charge.pay_to == account.pay_to
+        })?;
+        let requirements = self
+            .payment_requirements
+            .iter()
+            .find(|requirements| Charge::from_requirements(requirements) == *charge)?;
+        Some(PaymentDetails {
+            payer: account.payer.clone(),
+            scheme: requirements.scheme.clone(),
+            network: charge.network,
+            asset: charge.asset.clone(),
+            pay_to: charge.pay_to.clone(),
+            amount: charge.amount,
+            transaction: settlement.and_then(|settlement| settlement.transaction.clone()),
+            prepaid_balance: Some(account.balance),
+        })
+    }
 }
 
 /// A variant of [`PaymentRequirements`] without the `resource` field.
@@ -686,7 +1363,7 @@ pub struct PaymentRequirementsNoResource {
     pub description: String,
     pub mime_type: String,
     pub pay_to: MixedAddress,
-    pub max_timeout_seconds: u64,
+    pub max_timeout_seconds: Seconds,
     pub asset: MixedAddress,
     pub extra: Option<serde_json::Value>,
     pub output_schema: Option<serde_json::Value>,
@@ -697,7 +1374,7 @@ impl PaymentRequirementsNoResource {
     /// using the provided resource URL.
     pub fn to_payment_requirements(&self, resource: Url) -> PaymentRequirements {
         PaymentRequirements {
-            scheme: self.scheme,
+            scheme: self.scheme.clone(),
             network: self.network,
             max_amount_required: self.max_amount_required,
             resource,
@@ -708,6 +1385,8 @@ impl PaymentRequirementsNoResource {
             asset: self.asset.clone(),
             extra: self.extra.clone(),
             output_schema: self.output_schema.clone(),
+            consumed_amount: None,
+            settled_amount: None,
         }
     }
 }
@@ -11,11 +11,12 @@
 //! use http::StatusCode;
 //! use serde_json::json;
 //! use x402_axum::{X402Middleware, IntoPriceTag};
+//! use x402_rs::address_evm;
 //! use x402_rs::network::{Network, USDCDeployment};
 //!
 //! let x402 = X402Middleware::try_from("https://facilitator.example.com/").unwrap();
 //! // You can construct `TokenAsset` manually. Here we use known USDC on Base Sepolia
-//! let usdc = USDCDeployment::by_network(Network::BaseSepolia).pay_to("0xADDRESS");
+//! let usdc = USDCDeployment::by_network(Network::BaseSepolia).pay_to(address_evm!("0xBAc675C310721717Cd4A37F6cbeA1F081b1C2a07"));
 //!
 //! let app: Router = Router::new().route(
 //!     "/paywall",
@@ -32,15 +33,45 @@
 //! See [`X402Middleware`] for full configuration options.
 //! For low-level interaction with the facilitator, see [`facilitator_client::FacilitatorClient`].
 //!
+//! ## Custom Facilitators
+//!
+//! The middleware is generic over the [`Facilitator`] trait: [`X402Middleware::new`] takes any
+//! cloneable implementation, e.g. a [`FacilitatorLocal`](x402_rs::facilitator_local::FacilitatorLocal)
+//! settling in-process, a caching decorator around a [`FacilitatorClient`], or a mock in tests.
+//!
+//! Single-binary sellers verify and settle in-process, without a facilitator service, with a
+//! middleware created from a [`FacilitatorLocal`](x402_rs::facilitator_local::FacilitatorLocal),
+//! or read from the environment with [`X402Middleware::local_from_env`].
+//!
 //! ## Defining Prices
 //!
 //! To define price tags for your protected routes, see the [`price`] module.
 //! It provides builder-style helpers like [`IntoPriceTag`] and types like [`PriceTag`]
 //! for working with tokens, networks, and payment amounts.
+//! Prices that move, e.g. pegged to a fiat amount, can be looked up per request with
+//! [`X402Middleware::with_price_oracle`], see the [`quote`] module. Prices that depend on the request,
+//! e.g. on its query string, can be computed from it with [`X402Middleware::with_dynamic_price`],
+//! see the [`dynamic_price`] module.
+//!
+//! ## Payment Details
+//!
+//! Handlers behind the middleware can extract the [`PaymentDetails`](payment::PaymentDetails) of
+//! their request, e.g. its payer, to apply per-payer quotas, see the [`payment`] module.
+//!
+//! ## Prepaid Balances
+//!
+//! Buyers calling a route many times can pay a deposit once, charged per request without signing,
+//! with [`X402Middleware::with_prepaid_balance`], see the [`balance`] module.
 
+pub mod balance;
+pub mod dynamic_price;
 pub mod facilitator_client;
 pub mod layer;
+pub mod payment;
 pub mod price;
+pub mod quote;
 
+pub use facilitator_client::FacilitatorClient;
 pub use layer::X402Middleware;
 pub use price::*;
+pub use x402_rs::facilitator::Facilitator;
@@ -0,0 +1,93 @@
+//! Details of the payment of a request, for the handlers behind an [`X402Middleware`](crate::X402Middleware).
+//!
+//! Once the payment of a request is verified, or charged against a prepaid balance, the middleware
+//! inserts its [`PaymentDetails`] as a request extension, before invoking the handler. Handlers
+//! extract it to log who paid, apply per-payer quotas, or personalize the response:
+//!
+//! ```rust,no_run
+//! use axum::{Json, Router, routing::get};
+//! use serde_json::{Value, json};
+//! use x402_axum::X402Middleware;
+//! use x402_axum::payment::PaymentDetails;
+//!
+//! async fn my_handler(payment: PaymentDetails) -> Json<Value> {
+//!     Json(json!({ "hello": payment.payer.to_string() }))
+//! }
+//!
+//! let x402 = X402Middleware::try_from("https://facilitator.example/").unwrap();
+//! let app: Router = Router::new().route("/paywall", get(my_handler).layer(x402));
+//! ```
+//!
+//! Single payments are settled after the handler returns, so their `transaction` is not known yet
+//! to the handler: it is only set for deposits to a prepaid balance, settled before. Extract an
+//! `Option<PaymentDetails>` on handlers also served without payment.
+
+use axum_core::extract::{FromRequestParts, OptionalFromRequestParts};
+use axum_core::response::{IntoResponse, Response};
+use http::StatusCode;
+use http::request::Parts;
+use serde::Serialize;
+use std::convert::Infallible;
+use x402_rs::network::Network;
+use x402_rs::types::{MixedAddress, Scheme, TokenAmount, TransactionHash};
+
+/// Payment of a request, inserted as a request extension by the middleware.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PaymentDetails {
+    /// Address the payment is made from.
+    pub payer: MixedAddress,
+    pub scheme: Scheme,
+    pub network: Network,
+    pub asset: MixedAddress,
+    pub pay_to: MixedAddress,
+    /// Price of the request, in token base units.
+    pub amount: TokenAmount,
+    /// Settlement transaction, if settled before the handler runs.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub transaction: Option<TransactionHash>,
+    /// Balance left after the request is charged, for requests charged against a prepaid balance.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub prepaid_balance: Option<TokenAmount>,
+}
+
+/// Rejection of a [`PaymentDetails`] extractor on a request not paid through the middleware,
+/// e.g. of a route the middleware is not layered on.
+#[derive(Debug, thiserror::Error)]
+#[error("Missing payment details: is the route behind the X402Middleware?")]
+pub struct MissingPaymentDetails;
+
+impl IntoResponse for MissingPaymentDetails {
+    fn into_response(self) -> Response {
+        (StatusCode::INTERNAL_SERVER_ERROR, self.to_string()).into_response()
+    }
+}
+
+impl<S> FromRequestParts<S> for PaymentDetails
+where
+    S: Send + Sync,
+{
+    type Rejection = MissingPaymentDetails;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        parts
+            .extensions
+            .get::<PaymentDetails>()
+            .cloned()
+            .ok_or(MissingPaymentDetails)
+    }
+}
+
+impl<S> OptionalFromRequestParts<S> for PaymentDetails
+where
+    S: Send + Sync,
+{
+    type Rejection = Infallible;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        _state: &S,
+    ) -> Result<Option<Self>, Self::Rejection> {
+        Ok(parts.extensions.get::<PaymentDetails>().cloned())
+    }
+}
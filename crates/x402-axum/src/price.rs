@@ -1,7 +1,9 @@
+use once_cell::sync::Lazy;
 use std::fmt::Debug;
-use x402_rs::network::USDCDeployment;
+use x402_rs::network::{Network, NetworkFamily, USDCDeployment};
+use x402_rs::tokens::KnownToken;
 use x402_rs::types::{EvmAddress, MixedAddress, TokenDeployment};
-use x402_rs::types::{MoneyAmount, TokenAmount};
+use x402_rs::types::{MoneyAmount, MoneyAmountParseError, TokenAmount};
 
 /// A complete x402-compatible price tag, describing a required payment.
 ///
@@ -45,6 +47,120 @@ pub struct PriceTagBuilder<A, P> {
     token: TokenDeployment,
     amount: Option<A>,
     pay_to: Option<P>,
+    registry: Option<TokenRegistry>,
+}
+
+/// Known token deployments that price tags are validated against when built.
+///
+/// A [`PriceTagBuilder`] checks that a token known on the selected network is declared with its
+/// decimals and EIP-712 metadata. This surfaces a misconfigured asset at startup, rather than as
+/// unverifiable payment requirements at request time.
+///
+/// By default, [`TokenRegistry::known`] is used, which contains USDC on all supported networks,
+/// and EURC, PYUSD and DAI where deployed (see [`x402_rs::tokens`]). Tokens it does not know are
+/// not validated, and only logged as a warning (`telemetry` feature). Validation against a
+/// registry is opted in with [`PriceTagBuilder::with_registry`], or by starting the price tag from
+/// the registry (see [`PriceInToken`]): tokens unknown to it are then rejected.
+///
+/// ```rust
+/// use x402_axum::price::{IntoPriceTag, TokenRegistry};
+/// use x402_rs::address_evm;
+/// use x402_rs::network::Network;
+/// use x402_rs::types::{TokenAsset, TokenDeployment, TokenDeploymentEip712};
+///
+/// let my_token = TokenDeployment {
+///     asset: TokenAsset {
+///         address: address_evm!("0x036CbD53842c5426634e7929541eC2318f3dCF7e"),
+///         network: Network::BaseSepolia,
+///     },
+///     decimals: 6,
+///     eip712: Some(TokenDeploymentEip712 {
+///         name: "MyToken".into(),
+///         version: "1".into(),
+///     }),
+/// };
+/// let registry = TokenRegistry::known().with(my_token.clone());
+///
+/// let price_tag = my_token
+///     .amount("0.25")
+///     .pay_to(address_evm!("0xBAc675C310721717Cd4A37F6cbeA1F081b1C2a07"))
+///     .with_registry(&registry)
+///     .build()
+///     .unwrap();
+/// ```
+pub use x402_rs::tokens::TokenRegistry;
+
+static KNOWN_TOKENS: Lazy<TokenRegistry> = Lazy::new(TokenRegistry::known);
+
+/// Trait for initiating a [`PriceTagBuilder`] in a token of a [`TokenRegistry`], by symbol.
+///
+/// Price tags built this way are validated against the registry they were started from:
+///
+/// ```rust
+/// use x402_axum::price::{PriceInToken, TokenRegistry};
+/// use x402_rs::address_evm;
+/// use x402_rs::network::Network;
+/// use x402_rs::types::{TokenAsset, TokenDeployment};
+///
+/// let registry = TokenRegistry::known().with_token(
+///     "MYT",
+///     TokenDeployment {
+///         asset: TokenAsset {
+///             address: address_evm!("0x1111111111111111111111111111111111111111"),
+///             network: Network::Base,
+///         },
+///         decimals: 18,
+///         eip712: None,
+///     },
+/// );
+///
+/// let eurc = registry
+///     .price_in("EURC", Network::Base)
+///     .unwrap()
+///     .amount("0.10")
+///     .pay_to(address_evm!("0xBAc675C310721717Cd4A37F6cbeA1F081b1C2a07"))
+///     .build()
+///     .unwrap();
+/// assert_eq!(eurc.amount.to_string(), "100000");
+///
+/// let myt = registry
+///     .price_in("myt", Network::Base)
+///     .unwrap()
+///     .amount("0.10")
+///     .pay_to(address_evm!("0xBAc675C310721717Cd4A37F6cbeA1F081b1C2a07"))
+///     .build()
+///     .unwrap();
+/// assert_eq!(myt.amount.to_string(), "100000000000000000");
+/// ```
+pub trait PriceInToken {
+    fn price_in(
+        &self,
+        symbol: &str,
+        network: Network,
+    ) -> Result<PriceTagBuilder<(), ()>, PriceTagBuilderError>;
+}
+
+impl PriceInToken for TokenRegistry {
+    /// Starts a price tag in the token `symbol` (case-insensitive) on `network`, validated
+    /// against this registry.
+    fn price_in(
+        &self,
+        symbol: &str,
+        network: Network,
+    ) -> Result<PriceTagBuilder<(), ()>, PriceTagBuilderError> {
+        let token = self.by_symbol(symbol, network).cloned().ok_or_else(|| {
+            PriceTagBuilderError::UnknownToken {
+                symbol: symbol.to_string(),
+                network,
+            }
+        })?;
+        Ok(PriceTagBuilder {
+            token,
+            amount: None,
+            pay_to: None,
+            registry: Some(self.clone()),
+        })
+    }
 }
 
 /// Wrapper type used to distinguish [`PriceTagBuilder::amount`] created with human-friendly money values.
@@ -90,11 +206,12 @@ where
 ///
 /// ```rust
 /// use x402_axum::price::IntoPriceTag;
+/// use x402_rs::address_evm;
 /// use x402_rs::network::{Network, USDCDeployment};
 ///
 /// let price_tag = USDCDeployment::by_network(Network::Base)
 ///     .amount("1.50")
-///     .pay_to("0x036CbD53842c5426634e7929541eC2318f3dCF7e")
+///     .pay_to(address_evm!("0x036CbD53842c5426634e7929541eC2318f3dCF7e"))
 ///     .build()
 ///     .unwrap();
 /// ```
@@ -121,6 +238,122 @@ pub enum PriceTagBuilderError {
     NoPayTo,
     #[error("Invalid pay_to address")]
     InvalidPayTo,
+    #[error("Token {symbol} is not registered on {network}")]
+    UnknownToken { symbol: String, network: Network },
+    #[error("Asset {asset} is not a known token deployment on {network}")]
+    UnknownAsset {
+        asset: MixedAddress,
+        network: Network,
+    },
+    #[error("Asset {asset} has {expected} decimals, but the price tag declares {actual}")]
+    DecimalsMismatch {
+        asset: MixedAddress,
+        expected: u8,
+        actual: u8,
+    },
+    #[error("Asset {asset} EIP-712 name/version does not match the known deployment")]
+    Eip712Mismatch { asset: MixedAddress },
+    #[error("Amount has {amount} decimal places, but the token only supports {token}")]
+    AmountPrecision { amount: u32, token: u32 },
+    #[error("Address {pay_to} can not receive payments on {network}")]
+    PayToNetworkMismatch {
+        pay_to: MixedAddress,
+        network: Network,
+    },
+}
+
+/// Builds equivalent price tags for the USDC deployment of each of `networks`: the same
+/// human-readable `amount` (e.g. `"0.01"`), payable to `pay_to`.
+///
+/// Fails if the amount is invalid for any deployment, or if `pay_to` is not an address of
+/// the network's family (e.g. an EVM address on Solana).
+///
+/// ```rust
+/// use x402_axum::price::usdc_price_tags;
+/// use x402_rs::address_evm;
+/// use x402_rs::network::Network;
+///
+/// let price_tags = usdc_price_tags(
+///     &[Network::Base, Network::PolygonAmoy],
+///     "0.01",
+///     address_evm!("0xBAc675C310721717Cd4A37F6cbeA1F081b1C2a07"),
+/// )
+/// .unwrap();
+/// assert_eq!(price_tags.len(), 2);
+/// ```
+pub fn usdc_price_tags<A, P>(
+    networks: &[Network],
+    amount: A,
+    pay_to: P,
+) -> Result<Vec<PriceTag>, PriceTagBuilderError>
+where
+    A: TryInto<MoneyAmount> + Clone,
+    P: Into<MixedAddress>,
+{
+    token_price_tags(
+        &KNOWN_TOKENS,
+        KnownToken::Usdc.symbol(),
+        networks,
+        amount,
+        pay_to,
+    )
+}
+
+/// Builds equivalent price tags for the deployment of the token `symbol` of `registry` on each
+/// of `networks`, as [`usdc_price_tags`] does for USDC.
+///
+/// Fails with [`PriceTagBuilderError::UnknownToken`] if the token is not registered on one of
+/// the networks.
+///
+/// ```rust
+/// use x402_axum::price::{TokenRegistry, token_price_tags};
+/// use x402_rs::address_evm;
+/// use x402_rs::network::Network;
+///
+/// let price_tags = token_price_tags(
+///     &TokenRegistry::known(),
+///     "EURC",
+///     &[Network::Base, Network::Avalanche],
+///     "0.01",
+///     address_evm!("0xBAc675C310721717Cd4A37F6cbeA1F081b1C2a07"),
+/// )
+/// .unwrap();
+/// assert_eq!(price_tags.len(), 2);
+/// ```
+pub fn token_price_tags<A, P>(
+    registry: &TokenRegistry,
+    symbol: &str,
+    networks: &[Network],
+    amount: A,
+    pay_to: P,
+) -> Result<Vec<PriceTag>, PriceTagBuilderError>
+where
+    A: TryInto<MoneyAmount> + Clone,
+    P: Into<MixedAddress>,
+{
+    let pay_to: MixedAddress = pay_to.into();
+    networks
+        .iter()
+        .map(|network| {
+            let family_matches = matches!(
+                (NetworkFamily::from(*network), &pay_to),
+                (NetworkFamily::Evm, MixedAddress::Evm(_))
+                    | (NetworkFamily::Solana, MixedAddress::Solana(_))
+                    | (_, MixedAddress::Offchain(_))
+            );
+            if !family_matches {
+                return Err(PriceTagBuilderError::PayToNetworkMismatch {
+                    pay_to: pay_to.clone(),
+                    network: *network,
+                });
+            }
+            registry
+                .price_in(symbol, *network)?
+                .pay_to(pay_to.clone())
+                .amount(amount.clone())
+                .build()
+        })
+        .collect()
 }
 
 impl<A, P> PriceTagBuilder<PriceTagTokenAmount<A>, P>
@@ -130,10 +363,12 @@ where
 {
     /// Builds a [`PriceTag`] using a token-denominated amount.
     ///
-    /// Returns an error if the amount or payee are missing or invalid.
+    /// Returns an error if the amount or payee are missing or invalid,
+    /// or if the token does not match its known deployment (see [`TokenRegistry`]).
     #[allow(dead_code)] // Public for consumption by downstream crates.
     pub fn build(self) -> Result<PriceTag, PriceTagBuilderError> {
         let token = self.token;
+        validate_token(&token, self.registry.as_ref())?;
         let amount = self.amount.ok_or(PriceTagBuilderError::NoAmount)?;
         let amount = amount
             .try_into()
@@ -163,9 +398,11 @@ where
 {
     /// Builds a [`PriceTag`] from a human-readable money amount (e.g., `"1.50"`).
     ///
-    /// Converts the money amount to a [`TokenAmount`] using the asset's decimal precision.
+    /// Converts the money amount to a [`TokenAmount`] using the asset's decimal precision,
+    /// failing if the amount has more decimal places than the token supports.
     pub fn build(self) -> Result<PriceTag, PriceTagBuilderError> {
         let token = self.token;
+        validate_token(&token, self.registry.as_ref())?;
         let amount = self.amount.ok_or(PriceTagBuilderError::NoAmount)?;
         let money_amount: MoneyAmount = amount
             .try_into()
@@ -173,8 +410,15 @@ where
             .ok_or(PriceTagBuilderError::InvalidAmount)?;
         let amount = money_amount
             .as_token_amount(token.decimals as u32)
-            .ok()
-            .ok_or(PriceTagBuilderError::InvalidAmount)?;
+            .map_err(|e| match e {
+                MoneyAmountParseError::WrongPrecision { money, token } => {
+                    PriceTagBuilderError::AmountPrecision {
+                        amount: money,
+                        token,
+                    }
+                }
+                _ => PriceTagBuilderError::InvalidAmount,
+            })?;
         let pay_to = self.pay_to.ok_or(PriceTagBuilderError::NoPayTo)?;
         let pay_to = pay_to.into();
         let price_tag = PriceTag {
@@ -191,6 +435,55 @@ where
     }
 }
 
+impl<A, P> PriceTagBuilder<A, P> {
+    /// Validates the token against `registry` instead of [`TokenRegistry::known`], rejecting it
+    /// if not registered.
+    pub fn with_registry(self, registry: &TokenRegistry) -> Self {
+        Self {
+            registry: Some(registry.clone()),
+            ..self
+        }
+    }
+}
+
+/// Checks that `token` matches a deployment of `registry`, or of the known deployments if none
+/// was configured: same address on the same network, same decimals, and same EIP-712 domain name
+/// and version. Tokens unknown to the known deployments pass; only a configured registry rejects
+/// them.
+fn validate_token(
+    token: &TokenDeployment,
+    registry: Option<&TokenRegistry>,
+) -> Result<(), PriceTagBuilderError> {
+    let Some(known) = registry.unwrap_or(&KNOWN_TOKENS).get(token) else {
+        if registry.is_some() {
+            return Err(PriceTagBuilderError::UnknownAsset {
+                asset: token.address(),
+                network: token.network(),
+            });
+        }
+        #[cfg(feature = "telemetry")]
+        tracing::warn!(
+            asset = %token.address(),
+            network = %token.network(),
+            "Price tag in a token unknown to the registry, not validated"
+        );
+        return Ok(());
+    };
+    if known.decimals != token.decimals {
+        return Err(PriceTagBuilderError::DecimalsMismatch {
+            asset: token.address(),
+            expected: known.decimals,
+            actual: token.decimals,
+        });
+    }
+    if known.eip712 != token.eip712 {
+        return Err(PriceTagBuilderError::Eip712Mismatch {
+            asset: token.address(),
+        });
+    }
+    Ok(())
+}
+
 impl<A, P> PriceTagBuilder<A, P>
 where
     A: Clone,
@@ -202,6 +495,7 @@ where
             token: self.token.clone(),
             amount: self.amount.clone(),
             pay_to: Some(address),
+            registry: self.registry.clone(),
         }
     }
 }
@@ -219,6 +513,7 @@ where
             token: self.token.clone(),
             amount: Some(PriceTagMoneyAmount(amount)),
             pay_to: self.pay_to.clone(),
+            registry: self.registry.clone(),
         }
     }
 
@@ -232,6 +527,7 @@ where
             token: self.token.clone(),
             amount: Some(PriceTagTokenAmount(token_amount)),
             pay_to: self.pay_to.clone(),
+            registry: self.registry.clone(),
         }
     }
 }
@@ -246,6 +542,7 @@ impl IntoPriceTag for TokenDeployment {
             token,
             amount: Some(PriceTagTokenAmount(token_amount)),
             pay_to: None,
+            registry: None,
         }
     }
 
@@ -258,6 +555,7 @@ impl IntoPriceTag for TokenDeployment {
             token,
             amount: Some(PriceTagMoneyAmount(amount)),
             pay_to: None,
+            registry: None,
         }
     }
 
@@ -267,6 +565,7 @@ impl IntoPriceTag for TokenDeployment {
             token,
             amount: None,
             pay_to: Some(address),
+            registry: None,
         }
     }
 }
@@ -0,0 +1,153 @@
+//! Time-boxed price quotes for prices that move, e.g. fiat-pegged prices converted at a live exchange rate.
+//!
+//! With a [`PriceOracle`], the price of a route is looked up when a client asks for it, and may
+//! have changed by the time the client pays. To avoid that race, every `402 Payment Required`
+//! response locks the quoted price tags for a while under a quote id, advertised in the `extra`
+//! field of the payment requirements:
+//!
+//! ```json
+//! { "extra": { "name": "USDC", "version": "2", "quoteId": "8f0c…", "quoteExpiresAt": 1730000123 } }
+//! ```
+//!
+//! A client paying before `quoteExpiresAt` sends the quote id back in the `X-Payment-Quote` header,
+//! next to `X-Payment`, and its payment is verified against the locked price, even if the oracle
+//! price moved in between. Payments without a valid quote id are verified against the current price.
+//!
+//! ```rust,no_run
+//! use std::time::Duration;
+//! use x402_axum::X402Middleware;
+//! use x402_axum::price::{IntoPriceTag, PriceTag};
+//! use x402_axum::quote::PriceOracleError;
+//! use x402_rs::address_evm;
+//! use x402_rs::network::{Network, USDCDeployment};
+//!
+//! async fn eur_price() -> Result<Vec<PriceTag>, PriceOracleError> {
+//!     let usd_per_eur = 1.08; // Fetched from an exchange rate feed
+//!     let price_tag = USDCDeployment::by_network(Network::BaseSepolia)
+//!         .pay_to(address_evm!("0xBAc675C310721717Cd4A37F6cbeA1F081b1C2a07"))
+//!         .amount(0.10 * usd_per_eur)
+//!         .build()
+//!         .map_err(|e| PriceOracleError(e.to_string()))?;
+//!     Ok(vec![price_tag])
+//! }
+//!
+//! let x402 = X402Middleware::try_from("https://facilitator.example/").unwrap();
+//! let paygate = x402.with_price_oracle(eur_price, Duration::from_secs(60));
+//! ```
+
+use rand::Rng;
+use std::fmt::Debug;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+use x402_rs::lru_cache::LruCache;
+use x402_rs::timestamp::UnixTimestamp;
+
+use crate::price::PriceTag;
+
+/// Header carrying the id of the quote a payment is made against.
+pub const QUOTE_HEADER: &str = "X-Payment-Quote";
+
+/// Quotes remembered by a [`QuoteBook`], least recently used forgotten first.
+const QUOTE_BOOK_CAPACITY: usize = 10_000;
+
+/// Error returned by a [`PriceOracle`] that can not price a route.
+#[derive(Debug, thiserror::Error)]
+#[error("Price oracle error: {0}")]
+pub struct PriceOracleError(pub String);
+
+/// Source of the current price tags of a route.
+///
+/// Implemented for async functions and closures returning `Result<Vec<PriceTag>, PriceOracleError>`.
+pub trait PriceOracle: Send + Sync {
+    fn price_tags(
+        &self,
+    ) -> Pin<Box<dyn Future<Output = Result<Vec<PriceTag>, PriceOracleError>> + Send + '_>>;
+}
+
+impl<F, Fut> PriceOracle for F
+where
+    F: Fn() -> Fut + Send + Sync,
+    Fut: Future<Output = Result<Vec<PriceTag>, PriceOracleError>> + Send + 'static,
+{
+    fn price_tags(
+        &self,
+    ) -> Pin<Box<dyn Future<Output = Result<Vec<PriceTag>, PriceOracleError>> + Send + '_>> {
+        Box::pin(self())
+    }
+}
+
+/// Price tags locked under a quote id until `expires_at`.
+#[derive(Clone, Debug)]
+pub struct Quote {
+    pub id: String,
+    pub price_tags: Vec<PriceTag>,
+    pub expires_at: SystemTime,
+}
+
+impl Quote {
+    /// Expiry, in seconds since the Unix epoch.
+    pub fn expires_at_timestamp(&self) -> UnixTimestamp {
+        let seconds = self
+            .expires_at
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        UnixTimestamp(seconds)
+    }
+}
+
+/// Quotes issued by a middleware, honored until they expire.
+///
+/// At most 10,000 quotes are kept: under a flood of `402` responses, the least recently used
+/// quotes are dropped early, and payments made against them are verified against the current price.
+#[derive(Debug)]
+pub struct QuoteBook {
+    ttl: Duration,
+    quotes: LruCache<String, Quote>,
+}
+
+impl QuoteBook {
+    /// Creates a book of quotes valid for `ttl` after they are issued.
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            ttl,
+            quotes: LruCache::new("price_quotes", QUOTE_BOOK_CAPACITY).with_ttl(ttl),
+        }
+    }
+
+    /// Locks `price_tags` under a new quote id.
+    pub fn lock(&self, price_tags: Vec<PriceTag>) -> Quote {
+        let now = SystemTime::now();
+        let quote = Quote {
+            id: format!("{:032x}", rand::rng().random::<u128>()),
+            price_tags,
+            expires_at: now + self.ttl,
+        };
+        self.quotes.insert(quote.id.clone(), quote.clone());
+        quote
+    }
+
+    /// Returns the quote `id`, unless unknown or expired.
+    pub fn get(&self, id: &str) -> Option<Quote> {
+        self.quotes
+            .get(&id.to_string())
+            .filter(|quote| quote.expires_at > SystemTime::now())
+    }
+}
+
+/// A [`PriceOracle`] with the quotes it issued, shared by the clones of a middleware.
+#[derive(Clone)]
+pub(crate) struct QuotedPricing {
+    pub oracle: Arc<dyn PriceOracle>,
+    pub book: Arc<QuoteBook>,
+}
+
+impl Debug for QuotedPricing {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("QuotedPricing")
+            .field("book", &self.book)
+            .finish_non_exhaustive()
+    }
+}
@@ -0,0 +1,190 @@
+//! Spend budgets shared across [`X402Payments`](crate::X402Payments) instances.
+//!
+//! A fleet of agent workers typically runs many independent clients, each with its own
+//! per-payment cap (see [`X402Payments::max`](crate::X402Payments::max)). A budget caps what the
+//! fleet spends *collectively*: before signing a payment, the client atomically reserves the
+//! amount from a [`BudgetBackend`]. If the reservation fails, no payment is signed.
+//!
+//! The backend is pluggable:
+//! - [`TokenBucketBudget`] – in-process token bucket, shared by cloning it into every client of
+//!   the process
+//! - [`RedisBudget`](redis::RedisBudget) – token bucket in Redis, shared by the clients of every
+//!   process connected to the same server (`redis` feature)
+//!
+//! Other stores (e.g. a local budget daemon) are supported by implementing [`BudgetBackend`] on
+//! top of them; the implementation must make [`BudgetBackend::reserve`] atomic.
+//!
+//! ```rust
+//! use std::time::Duration;
+//! use alloy::signers::local::PrivateKeySigner;
+//! use x402_reqwest::X402Payments;
+//! use x402_reqwest::budget::TokenBucketBudget;
+//! use x402_rs::network::{Network, USDCDeployment};
+//!
+//! let usdc = USDCDeployment::by_network(Network::Base);
+//! // 10 USDC, refilled by 1 USDC per minute, shared by all workers
+//! let budget = TokenBucketBudget::new()
+//!     .with_bucket(usdc.asset.clone(), 10_000_000u64, 1_000_000u64, Duration::from_secs(60));
+//!
+//! let workers: Vec<X402Payments> = (0..4)
+//!     .map(|_| X402Payments::with_wallet(PrivateKeySigner::random()).budget(budget.clone()))
+//!     .collect();
+//! ```
+
+use alloy::primitives::U256;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use x402_rs::types::{TokenAmount, TokenAsset};
+
+#[cfg(feature = "redis")]
+pub mod redis;
+
+/// Errors returned by a [`BudgetBackend`].
+#[derive(Debug, thiserror::Error)]
+pub enum BudgetError {
+    /// Reserving the amount would exceed the remaining budget.
+    #[error("Budget exceeded for token {asset}: requested {requested}, available {available}")]
+    Exceeded {
+        asset: TokenAsset,
+        requested: TokenAmount,
+        available: TokenAmount,
+    },
+    /// The coordination backend could not be reached or failed.
+    #[error("Budget backend error: {0}")]
+    Backend(String),
+}
+
+/// Amount reserved from a budget, returned by [`BudgetBackend::reserve`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BudgetReservation {
+    pub asset: TokenAsset,
+    pub amount: TokenAmount,
+}
+
+/// A spend budget shared by multiple clients.
+#[async_trait::async_trait]
+pub trait BudgetBackend: Send + Sync {
+    /// Atomically reserves `amount` of `asset`, failing with [`BudgetError::Exceeded`] if the
+    /// remaining budget is insufficient. Assets without a configured budget are not limited.
+    async fn reserve(
+        &self,
+        asset: &TokenAsset,
+        amount: TokenAmount,
+    ) -> Result<BudgetReservation, BudgetError>;
+
+    /// Returns an unused reservation to the budget, e.g. when signing the payment failed.
+    async fn release(&self, reservation: BudgetReservation) -> Result<(), BudgetError>;
+}
+
+#[derive(Debug, Clone)]
+struct Bucket {
+    capacity: U256,
+    available: U256,
+    refill_amount: U256,
+    refill_interval: Duration,
+    last_refill: Instant,
+}
+
+impl Bucket {
+    fn refill(&mut self, now: Instant) {
+        if self.refill_interval.is_zero() || self.refill_amount.is_zero() {
+            return;
+        }
+        let elapsed = now.duration_since(self.last_refill);
+        let intervals = (elapsed.as_nanos() / self.refill_interval.as_nanos()) as u64;
+        if intervals == 0 {
+            return;
+        }
+        let refilled = self
+            .refill_amount
+            .saturating_mul(U256::from(intervals))
+            .saturating_add(self.available);
+        self.available = refilled.min(self.capacity);
+        let remainder = elapsed.as_nanos() % self.refill_interval.as_nanos();
+        self.last_refill = now - Duration::from_nanos(remainder as u64);
+    }
+}
+
+/// In-process token-bucket budget.
+///
+/// Cloning is cheap and clones share the same buckets, so one instance can be passed to every
+/// [`X402Payments`](crate::X402Payments) of the process.
+#[derive(Clone, Default)]
+pub struct TokenBucketBudget {
+    buckets: Arc<Mutex<HashMap<TokenAsset, Bucket>>>,
+}
+
+impl TokenBucketBudget {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Configures a bucket for `asset` holding at most `capacity` token units,
+    /// refilled by `refill_amount` every `refill_interval`. The bucket starts full.
+    ///
+    /// Use a zero `refill_amount` for a fixed, non-replenishing budget.
+    pub fn with_bucket<C: Into<TokenAmount>, R: Into<TokenAmount>>(
+        self,
+        asset: TokenAsset,
+        capacity: C,
+        refill_amount: R,
+        refill_interval: Duration,
+    ) -> Self {
+        let capacity = capacity.into().0;
+        let bucket = Bucket {
+            capacity,
+            available: capacity,
+            refill_amount: refill_amount.into().0,
+            refill_interval,
+            last_refill: Instant::now(),
+        };
+        self.buckets.lock().unwrap().insert(asset, bucket);
+        self
+    }
+
+    /// Amount of `asset` currently available, or `None` if the asset has no budget.
+    pub fn available(&self, asset: &TokenAsset) -> Option<TokenAmount> {
+        let mut buckets = self.buckets.lock().unwrap();
+        let bucket = buckets.get_mut(asset)?;
+        bucket.refill(Instant::now());
+        Some(TokenAmount(bucket.available))
+    }
+}
+
+#[async_trait::async_trait]
+impl BudgetBackend for TokenBucketBudget {
+    async fn reserve(
+        &self,
+        asset: &TokenAsset,
+        amount: TokenAmount,
+    ) -> Result<BudgetReservation, BudgetError> {
+        let mut buckets = self.buckets.lock().unwrap();
+        if let Some(bucket) = buckets.get_mut(asset) {
+            bucket.refill(Instant::now());
+            if bucket.available < amount.0 {
+                return Err(BudgetError::Exceeded {
+                    asset: asset.clone(),
+                    requested: amount,
+                    available: TokenAmount(bucket.available),
+                });
+            }
+            bucket.available -= amount.0;
+        }
+        Ok(BudgetReservation {
+            asset: asset.clone(),
+            amount,
+        })
+    }
+
+    async fn release(&self, reservation: BudgetReservation) -> Result<(), BudgetError> {
+        let mut buckets = self.buckets.lock().unwrap();
+        if let Some(bucket) = buckets.get_mut(&reservation.asset) {
+            bucket.available = bucket
+                .available
+                .saturating_add(reservation.amount.0)
+                .min(bucket.capacity);
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,269 @@
+//! [`BudgetBackend`] shared across processes and hosts through Redis.
+
+use alloy::primitives::U256;
+use redis::Script;
+use redis::aio::ConnectionManager;
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use x402_rs::types::{TokenAmount, TokenAsset};
+
+use super::{BudgetBackend, BudgetError, BudgetReservation};
+
+/// Prefix of the keys of the buckets, by default.
+pub const DEFAULT_KEY_PREFIX: &str = "x402:budget";
+
+/// Refills and updates a bucket atomically, on the clock of the Redis server.
+///
+/// `KEYS[1]` is the hash of the bucket, with its `available` amount and the time it was last
+/// `refilledAt`, in milliseconds. `ARGV` are the capacity, the refill amount, the refill interval
+/// in milliseconds, the amount to reserve or release, and `reserve` or `release`. Returns whether
+/// the amount was reserved, and the amount available after.
+///
+/// Amounts are decimal strings of any size: token amounts overflow the numbers of Lua, so they are
+/// added and compared as arrays of base 10^6 digits, least significant first.
+const BUCKET_SCRIPT: &str = r#"
+local BASE = 1000000
+local function parse(s)
+  local n = {}
+  for i = #s, 1, -6 do
+    n[#n + 1] = tonumber(string.sub(s, math.max(1, i - 5), i))
+  end
+  return n
+end
+local function trim(n)
+  while #n > 1 and n[#n] == 0 do
+    n[#n] = nil
+  end
+  return n
+end
+local function format(n)
+  n = trim(n)
+  local s = tostring(n[#n])
+  for i = #n - 1, 1, -1 do
+    s = s .. string.format('%06d', n[i])
+  end
+  return s
+end
+local function cmp(a, b)
+  a, b = trim(a), trim(b)
+  if #a ~= #b then
+    return #a < #b and -1 or 1
+  end
+  for i = #a, 1, -1 do
+    if a[i] ~= b[i] then
+      return a[i] < b[i] and -1 or 1
+    end
+  end
+  return 0
+end
+local function add(a, b)
+  local n, carry = {}, 0
+  for i = 1, math.max(#a, #b) do
+    local d = (a[i] or 0) + (b[i] or 0) + carry
+    n[i] = d % BASE
+    carry = math.floor(d / BASE)
+  end
+  if carry > 0 then
+    n[#n + 1] = carry
+  end
+  return n
+end
+local function sub(a, b)
+  local n, borrow = {}, 0
+  for i = 1, #a do
+    local d = a[i] - (b[i] or 0) - borrow
+    if d < 0 then
+      d, borrow = d + BASE, 1
+    else
+      borrow = 0
+    end
+    n[i] = d
+  end
+  return trim(n)
+end
+local function mul(a, m)
+  local n, carry = {}, 0
+  for i = 1, #a do
+    local d = a[i] * m + carry
+    n[i] = d % BASE
+    carry = math.floor(d / BASE)
+  end
+  while carry > 0 do
+    n[#n + 1] = carry % BASE
+    carry = math.floor(carry / BASE)
+  end
+  return n
+end
+
+if redis.replicate_commands then
+  redis.replicate_commands()
+end
+local time = redis.call('TIME')
+local now = tonumber(time[1]) * 1000 + math.floor(tonumber(time[2]) / 1000)
+local capacity, refill, interval = parse(ARGV[1]), parse(ARGV[2]), tonumber(ARGV[3])
+local amount = parse(ARGV[4])
+local state = redis.call('HMGET', KEYS[1], 'available', 'refilledAt')
+local available = state[1] and parse(state[1]) or capacity
+local refilled_at = tonumber(state[2]) or now
+if interval > 0 then
+  local intervals = math.floor((now - refilled_at) / interval)
+  if intervals > 0 then
+    local high, low = math.floor(intervals / BASE), intervals % BASE
+    available = add(available, add(mul(mul(refill, high), BASE), mul(refill, low)))
+    refilled_at = refilled_at + intervals * interval
+  end
+else
+  refilled_at = now
+end
+if cmp(available, capacity) > 0 then
+  available = capacity
+end
+local reserved = 1
+if ARGV[5] == 'reserve' then
+  if cmp(available, amount) < 0 then
+    reserved = 0
+  else
+    available = sub(available, amount)
+  end
+else
+  available = add(available, amount)
+  if cmp(available, capacity) > 0 then
+    available = capacity
+  end
+end
+redis.call('HSET', KEYS[1], 'available', format(available), 'refilledAt', refilled_at)
+return {reserved, format(available)}
+"#;
+
+#[derive(Debug, Clone)]
+struct RedisBucket {
+    capacity: TokenAmount,
+    refill_amount: TokenAmount,
+    refill_interval: Duration,
+}
+
+/// Token-bucket budget kept in Redis, shared by the clients of every process connected to the
+/// same server.
+///
+/// Buckets are refilled and drawn from by a Lua script, atomically, on the clock of the server.
+/// The configuration of a bucket is not stored: every process configures the same buckets, under
+/// the same [prefix](Self::with_prefix). A bucket starts full the first time it is drawn from.
+///
+/// Cloning is cheap and clones share the same connection and buckets.
+#[derive(Clone)]
+pub struct RedisBudget {
+    connection: ConnectionManager,
+    script: Script,
+    prefix: String,
+    buckets: Arc<Mutex<HashMap<TokenAsset, RedisBucket>>>,
+}
+
+impl RedisBudget {
+    /// Connects to the Redis server at `url`, e.g. `redis://localhost:6379`, or `rediss://` over
+    /// TLS. The connection is re-established if lost.
+    pub async fn connect(url: &str) -> Result<Self, BudgetError> {
+        let client = redis::Client::open(url).map_err(backend_error)?;
+        let connection = ConnectionManager::new(client)
+            .await
+            .map_err(backend_error)?;
+        Ok(Self {
+            connection,
+            script: Script::new(BUCKET_SCRIPT),
+            prefix: DEFAULT_KEY_PREFIX.to_string(),
+            buckets: Arc::default(),
+        })
+    }
+
+    /// Keeps the buckets under keys starting with `prefix`, e.g. to run several fleets with
+    /// budgets of their own on one server.
+    pub fn with_prefix<S: Into<String>>(mut self, prefix: S) -> Self {
+        self.prefix = prefix.into();
+        self
+    }
+
+    /// Configures a bucket for `asset` holding at most `capacity` token units,
+    /// refilled by `refill_amount` every `refill_interval`.
+    ///
+    /// Use a zero `refill_amount` for a fixed, non-replenishing budget.
+    pub fn with_bucket<C: Into<TokenAmount>, R: Into<TokenAmount>>(
+        self,
+        asset: TokenAsset,
+        capacity: C,
+        refill_amount: R,
+        refill_interval: Duration,
+    ) -> Self {
+        let bucket = RedisBucket {
+            capacity: capacity.into(),
+            refill_amount: refill_amount.into(),
+            refill_interval,
+        };
+        self.buckets.lock().unwrap().insert(asset, bucket);
+        self
+    }
+
+    /// Key of the bucket of `asset`.
+    fn key(&self, asset: &TokenAsset) -> String {
+        format!("{}:{}:{}", self.prefix, asset.network, asset.address)
+    }
+
+    /// Runs the bucket script on the bucket of `asset`, if configured. Returns whether `amount`
+    /// was reserved, and the amount available after.
+    async fn update(
+        &self,
+        asset: &TokenAsset,
+        amount: TokenAmount,
+        operation: &str,
+    ) -> Result<Option<(bool, U256)>, BudgetError> {
+        let Some(bucket) = self.buckets.lock().unwrap().get(asset).cloned() else {
+            return Ok(None);
+        };
+        let mut connection = self.connection.clone();
+        let (reserved, available): (i64, String) = self
+            .script
+            .key(self.key(asset))
+            .arg(bucket.capacity.to_string())
+            .arg(bucket.refill_amount.to_string())
+            .arg(bucket.refill_interval.as_millis() as u64)
+            .arg(amount.to_string())
+            .arg(operation)
+            .invoke_async(&mut connection)
+            .await
+            .map_err(backend_error)?;
+        let available = U256::from_str(&available)
+            .map_err(|e| BudgetError::Backend(format!("Invalid available amount: {e}")))?;
+        Ok(Some((reserved == 1, available)))
+    }
+}
+
+#[async_trait::async_trait]
+impl BudgetBackend for RedisBudget {
+    async fn reserve(
+        &self,
+        asset: &TokenAsset,
+        amount: TokenAmount,
+    ) -> Result<BudgetReservation, BudgetError> {
+        if let Some((false, available)) = self.update(asset, amount, "reserve").await? {
+            return Err(BudgetError::Exceeded {
+                asset: asset.clone(),
+                requested: amount,
+                available: TokenAmount(available),
+            });
+        }
+        Ok(BudgetReservation {
+            asset: asset.clone(),
+            amount,
+        })
+    }
+
+    async fn release(&self, reservation: BudgetReservation) -> Result<(), BudgetError> {
+        self.update(&reservation.asset, reservation.amount, "release")
+            .await?;
+        Ok(())
+    }
+}
+
+fn backend_error(error: redis::RedisError) -> BudgetError {
+    BudgetError::Backend(error.to_string())
+}
@@ -7,21 +7,27 @@
 //! use reqwest::Client;
 //! use x402_reqwest::{ReqwestWithPayments, ReqwestWithPaymentsBuild};
 //! use alloy::signers::local::PrivateKeySigner;
+//! # use x402_reqwest::MaxTokenAmountFromAmount;
+//! # use x402_rs::network::{Network, USDCDeployment};
 //!
 //! let signer: PrivateKeySigner = "...".parse().unwrap();
 //!
 //! let client: reqwest_middleware::ClientWithMiddleware = Client::new()
 //!     .with_payments(signer)
-//!     .prefer(...)
-//!     .max(...)
+//!     .prefer(USDCDeployment::by_network(Network::Base))
+//!     .max(USDCDeployment::by_network(Network::Base).amount("1.00").unwrap())
 //!     .build();
 //! ```
 
 use reqwest::{Client, ClientBuilder};
 use reqwest_middleware as rqm;
 use reqwest_middleware::ClientWithMiddleware;
+use x402_rs::network::Network;
+use x402_rs::retry::{CircuitBreaker, RetryPolicy};
+use x402_rs::scheme::SchemeHandler;
 use x402_rs::types::TokenAsset;
 
+use crate::budget::BudgetBackend;
 use crate::chains::IntoSenderWallet;
 use crate::{MaxTokenAmount, X402Payments};
 
@@ -52,6 +58,60 @@ impl<A> ReqwestWithPaymentsBuilder<A> {
         }
     }
 
+    /// Draw payments from a spend budget shared with other clients.
+    /// Mimics [`X402Payments::budget`].
+    pub fn budget<B: BudgetBackend + 'static>(self, budget: B) -> Self {
+        Self {
+            inner: self.inner,
+            x402: self.x402.budget(budget),
+        }
+    }
+
+    /// Retry requests turned away by an overloaded seller.
+    /// Mimics [`X402Payments::retry`].
+    pub fn retry(self, retry: RetryPolicy) -> Self {
+        Self {
+            inner: self.inner,
+            x402: self.x402.retry(retry),
+        }
+    }
+
+    /// Stop sending requests to a host that turned away too many in a row.
+    /// Mimics [`X402Payments::circuit_breaker`].
+    pub fn circuit_breaker(self, breaker: CircuitBreaker) -> Self {
+        Self {
+            inner: self.inner,
+            x402: self.x402.circuit_breaker(breaker),
+        }
+    }
+
+    /// Pay prepaid balances with sellers offering them a deposit once, instead of every request.
+    /// Mimics [`X402Payments::prepaid_balances`].
+    pub fn prepaid_balances(self) -> Self {
+        Self {
+            inner: self.inner,
+            x402: self.x402.prepaid_balances(),
+        }
+    }
+
+    /// Send later requests to a resource paid before with a payment right away.
+    /// Mimics [`X402Payments::payment_cache`].
+    pub fn payment_cache(self) -> Self {
+        Self {
+            inner: self.inner,
+            x402: self.x402.payment_cache(),
+        }
+    }
+
+    /// Pay with a custom payment scheme.
+    /// Mimics [`X402Payments::scheme`].
+    pub fn scheme<H: SchemeHandler + 'static>(self, handler: H) -> Self {
+        Self {
+            inner: self.inner,
+            x402: self.x402.scheme(handler),
+        }
+    }
+
     /// Extend the list of preferred tokens to use for payment,
     /// prioritized during requirement selection.
     /// Mimics [`X402Payments::prefer`].
@@ -61,6 +121,24 @@ impl<A> ReqwestWithPaymentsBuilder<A> {
             x402: self.x402.prefer(prefer),
         }
     }
+
+    /// Extend the list of preferred networks to pay on.
+    /// Mimics [`X402Payments::prefer_network`].
+    pub fn prefer_network(self, network: Network) -> Self {
+        Self {
+            inner: self.inner,
+            x402: self.x402.prefer_network(network),
+        }
+    }
+
+    /// Pay the most preferred requirement that the wallet balances cover.
+    /// Mimics [`X402Payments::check_balances`].
+    pub fn check_balances(self) -> Self {
+        Self {
+            inner: self.inner,
+            x402: self.x402.check_balances(),
+        }
+    }
 }
 
 /// A trait implemented for both builder variants to finalize the HTTP client.
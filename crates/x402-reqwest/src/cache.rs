@@ -0,0 +1,176 @@
+//! Payments cached per resource, reused or renewed on later requests without a `402` round trip.
+//!
+//! Without a cache, every request to a paid resource is sent twice: once unpaid, answered with
+//! `402 Payment Required`, then again with a payment. With
+//! [`X402Payments::payment_cache`](crate::X402Payments::payment_cache), the requirements paid for a
+//! resource are remembered, by origin, path and query string, for their validity window: the
+//! `maxTimeoutSeconds` of the requirements paid, or until the `quoteExpiresAt` of their quote if
+//! sooner. Later requests to the resource are sent paid right away:
+//! - with the same payment, if the seller did not settle it, e.g. a seller settling only the
+//!   successful responses answered with an error,
+//! - or else with a new payment of the same requirements: an `exact` payment authorizes a single
+//!   transfer, which can not be settled twice.
+//!
+//! The query string is part of the resource: sellers pricing requests dynamically, e.g. by a
+//! `max_tokens` parameter, ask different prices for different query strings. Payments are cached
+//! under the resource and the [hash](requirements_hash) of the requirements paid, so that a
+//! payment is only ever sent again for the exact requirements it paid.
+//!
+//! A paid request still answered with `402`, e.g. because the price changed, is paid again for
+//! the requirements of the new response, which replace the cached ones. Requirements of deposits
+//! to a prepaid balance are not cached.
+//!
+//! ```rust
+//! use alloy::signers::local::PrivateKeySigner;
+//! use x402_reqwest::X402Payments;
+//!
+//! let payments = X402Payments::with_wallet(PrivateKeySigner::random()).payment_cache();
+//! ```
+
+use alloy::primitives::B256;
+use http::HeaderValue;
+use reqwest::Url;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime};
+use x402_rs::canonical_json;
+use x402_rs::types::PaymentRequirements;
+
+/// Resource of a cached payment: origin, path and query string of its URL.
+type ResourceKey = (String, String, Option<String>);
+
+/// Requirements paid for a resource, and its payment while not settled.
+#[derive(Debug, Clone)]
+pub struct CachedPayment {
+    /// Requirements offered by the seller, in its last `402` response.
+    pub accepts: Vec<PaymentRequirements>,
+    /// Requirements paid, among `accepts`.
+    pub selected: PaymentRequirements,
+    /// Hash of `selected`, see [`requirements_hash`].
+    pub requirements_hash: B256,
+    /// `X-Payment` header of the last payment, until it is settled.
+    pub payment_header: Option<HeaderValue>,
+    pub expires_at: Instant,
+}
+
+impl CachedPayment {
+    /// Caches the payment `payment_header` of `selected`, among `accepts`, for the validity
+    /// window of `selected`.
+    pub fn new(
+        accepts: Vec<PaymentRequirements>,
+        selected: &PaymentRequirements,
+        payment_header: HeaderValue,
+    ) -> Self {
+        let mut validity = Duration::from_secs(selected.max_timeout_seconds.as_secs());
+        let quote_expires_at = selected
+            .extra
+            .as_ref()
+            .and_then(|extra| extra.get("quoteExpiresAt")?.as_u64());
+        if let Some(quote_expires_at) = quote_expires_at {
+            let quote_validity = (SystemTime::UNIX_EPOCH + Duration::from_secs(quote_expires_at))
+                .duration_since(SystemTime::now())
+                .unwrap_or_default();
+            validity = validity.min(quote_validity);
+        }
+        Self {
+            accepts,
+            selected: selected.clone(),
+            requirements_hash: requirements_hash(selected),
+            payment_header: Some(payment_header),
+            expires_at: Instant::now() + validity,
+        }
+    }
+}
+
+/// Keccak-256 hash of the canonical (RFC 8785) JSON encoding of `requirements`, telling apart
+/// requirements of different prices, recipients or quotes.
+pub fn requirements_hash(requirements: &PaymentRequirements) -> B256 {
+    canonical_json::keccak256(requirements).unwrap_or_default()
+}
+
+#[derive(Debug, Default)]
+struct Entries {
+    /// Hash of the requirements last paid for each resource.
+    current: HashMap<ResourceKey, B256>,
+    /// Payments, by resource and hash of the requirements paid.
+    payments: HashMap<(ResourceKey, B256), CachedPayment>,
+}
+
+/// Payments cached by origin, path and query string of the resource paid, and hash of the
+/// requirements paid.
+///
+/// Clones share the same payments.
+#[derive(Debug, Clone, Default)]
+pub struct PaymentCache {
+    entries: Arc<Mutex<Entries>>,
+}
+
+impl PaymentCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn key(url: &Url) -> ResourceKey {
+        (
+            url.origin().ascii_serialization(),
+            url.path().to_string(),
+            url.query().map(ToString::to_string),
+        )
+    }
+
+    /// The payment of the requirements last paid for the resource of `url`, unless expired.
+    pub fn get(&self, url: &Url) -> Option<CachedPayment> {
+        let mut entries = self.entries.lock().unwrap();
+        let key = Self::key(url);
+        let hash = *entries.current.get(&key)?;
+        let payment_key = (key, hash);
+        match entries.payments.get(&payment_key) {
+            Some(cached) if cached.expires_at > Instant::now() => Some(cached.clone()),
+            _ => {
+                entries.payments.remove(&payment_key);
+                entries.current.remove(&payment_key.0);
+                None
+            }
+        }
+    }
+
+    /// Caches `payment` for the resource of `url`, as the requirements last paid for it, in
+    /// place of a previous payment of the same requirements. Expired payments are dropped.
+    pub fn insert(&self, url: &Url, payment: CachedPayment) {
+        let mut entries = self.entries.lock().unwrap();
+        let now = Instant::now();
+        entries.payments.retain(|_, cached| cached.expires_at > now);
+        let key = Self::key(url);
+        entries
+            .current
+            .insert(key.clone(), payment.requirements_hash);
+        entries
+            .payments
+            .insert((key, payment.requirements_hash), payment);
+        let Entries { current, payments } = &mut *entries;
+        current.retain(|key, hash| payments.contains_key(&(key.clone(), *hash)));
+    }
+
+    /// Forgets the payment of the requirements hashed as `requirements_hash` for the resource
+    /// of `url` once settled, keeping the requirements to pay the next request.
+    pub fn settled(&self, url: &Url, requirements_hash: B256) {
+        let mut entries = self.entries.lock().unwrap();
+        if let Some(cached) = entries
+            .payments
+            .get_mut(&(Self::key(url), requirements_hash))
+        {
+            cached.payment_header = None;
+        }
+    }
+
+    /// Forgets the requirements hashed as `requirements_hash`, and their payment, for the
+    /// resource of `url`.
+    pub fn remove(&self, url: &Url, requirements_hash: B256) {
+        let mut entries = self.entries.lock().unwrap();
+        let key = Self::key(url);
+        if entries.current.get(&key) == Some(&requirements_hash) {
+            entries.current.remove(&key);
+        }
+        entries.payments.remove(&(key, requirements_hash));
+    }
+}
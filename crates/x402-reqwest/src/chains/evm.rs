@@ -1,31 +1,179 @@
 use crate::X402PaymentsError;
 use crate::chains::{IntoSenderWallet, SenderWallet};
-use alloy::primitives::FixedBytes;
+use alloy::dyn_abi::TypedData;
+use alloy::primitives::{Address, FixedBytes};
+use alloy::providers::{Provider, RootProvider};
 use alloy::signers::Signer;
 use alloy::signers::local::PrivateKeySigner;
-use alloy::sol_types::{SolStruct, eip712_domain};
+use alloy::sol;
+use alloy::sol_types::{Eip712Domain, eip712_domain};
 use async_trait::async_trait;
 use rand::{Rng, rng};
+use reqwest::Url;
 use std::sync::Arc;
 use x402_rs::chain::evm::EvmChain;
 use x402_rs::network::NetworkFamily;
 use x402_rs::timestamp::UnixTimestamp;
 use x402_rs::types::{
-    EvmSignature, ExactEvmPayload, ExactEvmPayloadAuthorization, ExactPaymentPayload,
-    HexEncodedNonce, PaymentPayload, PaymentRequirements, Scheme, TransferWithAuthorization,
+    EvmAddress, EvmSignature, ExactEvmPayload, ExactEvmPayloadAuthorization, ExactPaymentPayload,
+    HexEncodedNonce, PaymentPayload, PaymentRequirements, Permit, PermitEvmPayload,
+    PermitEvmPayloadAuthorization, PermitIntent, Scheme, TokenAmount, TransferWithAuthorization,
 };
 
+sol! {
+    #[sol(rpc)]
+    interface IERC20Permit {
+        function nonces(address owner) external view returns (uint256);
+    }
+
+    #[sol(rpc)]
+    interface IERC20 {
+        function balanceOf(address account) external view returns (uint256);
+    }
+}
+
+/// Pays EVM requirements, signing with any alloy [`Signer`]: a [`PrivateKeySigner`], a key
+/// decrypted from an encrypted JSON keystore (`keystore` feature, see
+/// [`from_keystore`](Self::from_keystore)), or a Ledger (`ledger` feature, see
+/// [`ledger`](Self::ledger)).
+///
+/// Payments are signed as EIP-712 typed data, which hardware wallets display and sign, rather
+/// than as raw hashes, which they refuse to sign. Trezor signers of alloy sign neither, and can
+/// not pay.
 #[derive(Clone)]
 pub struct EvmSenderWallet {
     signer: Arc<dyn Signer + Send + Sync>,
+    /// Node to read permit nonces and token balances from, required to pay `permit` and `upto`
+    /// requirements.
+    provider: Option<RootProvider>,
 }
 
 impl EvmSenderWallet {
     pub fn new(signer: impl Signer + Send + Sync + 'static) -> Self {
         Self {
             signer: Arc::new(signer),
+            provider: None,
         }
     }
+
+    /// Decrypts the private key of the encrypted JSON keystore (Web3 Secret Storage) at `path`
+    /// with `password`.
+    #[cfg(feature = "keystore")]
+    pub fn from_keystore<P, S>(path: P, password: S) -> Result<Self, X402PaymentsError>
+    where
+        P: AsRef<std::path::Path>,
+        S: AsRef<[u8]>,
+    {
+        let signer = PrivateKeySigner::decrypt_keystore(path, password)
+            .map_err(|e| X402PaymentsError::WalletError(e.to_string()))?;
+        Ok(Self::new(signer))
+    }
+
+    /// Signs with the account at `hd_path` of a Ledger connected over USB, running the Ethereum
+    /// app. Every payment is confirmed on the device.
+    #[cfg(feature = "ledger")]
+    pub async fn ledger(
+        hd_path: alloy::signers::ledger::HDPath,
+    ) -> Result<Self, X402PaymentsError> {
+        let signer = alloy::signers::ledger::LedgerSigner::new(hd_path, None)
+            .await
+            .map_err(|e| X402PaymentsError::WalletError(e.to_string()))?;
+        Ok(Self::new(signer))
+    }
+
+    /// Address of the account paying.
+    pub fn address(&self) -> Address {
+        self.signer.address()
+    }
+
+    /// Reads the EIP-2612 nonces of the signer from the node at `rpc_url`, which enables paying
+    /// requirements of the `permit` scheme, for tokens without ERC-3009, and of the metered
+    /// `upto` scheme. Token balances are read from the node as well, to choose among the
+    /// requirements offered (see [`X402Payments::check_balances`](crate::X402Payments::check_balances)).
+    pub fn with_rpc_url(&self, rpc_url: Url) -> Self {
+        let mut this = self.clone();
+        this.provider = Some(RootProvider::new_http(rpc_url));
+        this
+    }
+
+    /// Signs an EIP-2612 permit allowing the facilitator, named as `feePayer` in the
+    /// requirements, to transfer the required amount until the requirements time out. For `upto`
+    /// requirements, the required amount is a maximum, of which only the amount consumed is
+    /// transferred. The [`PermitIntent`] signed along binds the permit to the `payTo` of the
+    /// requirements.
+    async fn permit_payload(
+        &self,
+        selected: &PaymentRequirements,
+        domain: &Eip712Domain,
+    ) -> Result<ExactPaymentPayload, X402PaymentsError> {
+        let provider = self
+            .provider
+            .as_ref()
+            .ok_or(X402PaymentsError::SigningError(
+                "permit payments need an RPC URL".to_string(),
+            ))?;
+        let spender = selected
+            .extra
+            .as_ref()
+            .and_then(|extra| extra.get("feePayer")?.as_str()?.parse::<Address>().ok())
+            .ok_or(X402PaymentsError::SigningError(
+                "permit requirements name no feePayer".to_string(),
+            ))?;
+        let token: EvmAddress = selected
+            .asset
+            .clone()
+            .try_into()
+            .map_err(X402PaymentsError::InvalidEVMAddress)?;
+        let pay_to: EvmAddress = selected
+            .pay_to
+            .clone()
+            .try_into()
+            .map_err(X402PaymentsError::InvalidEVMAddress)?;
+        let owner = self.signer.address();
+        let nonce = IERC20Permit::new(token.0, provider)
+            .nonces(owner)
+            .call()
+            .await
+            .map_err(|e| X402PaymentsError::SigningError(format!("{e:?}")))?;
+        let now = UnixTimestamp::try_now().map_err(X402PaymentsError::ClockError)?;
+        let authorization = PermitEvmPayloadAuthorization {
+            owner: owner.into(),
+            spender: spender.into(),
+            value: selected.max_amount_required,
+            nonce: nonce.into(),
+            deadline: now + selected.max_timeout_seconds,
+        };
+        #[cfg(feature = "telemetry")]
+        tracing::debug!(?authorization, "Constructed permit payload");
+        let permit = Permit {
+            owner,
+            spender,
+            value: authorization.value.into(),
+            nonce,
+            deadline: authorization.deadline.into(),
+        };
+        let signature = self
+            .signer
+            .sign_dynamic_typed_data(&TypedData::from_struct(&permit, Some(domain.clone())))
+            .await
+            .map_err(|e| X402PaymentsError::SigningError(format!("{e:?}")))?;
+        let intent = PermitIntent {
+            owner,
+            payTo: pay_to.0,
+            value: authorization.value.into(),
+            nonce,
+        };
+        let intent_signature = self
+            .signer
+            .sign_dynamic_typed_data(&TypedData::from_struct(&intent, Some(domain.clone())))
+            .await
+            .map_err(|e| X402PaymentsError::SigningError(format!("{e:?}")))?;
+        Ok(ExactPaymentPayload::Permit(PermitEvmPayload {
+            signature: EvmSignature::from(signature.as_bytes()),
+            permit: authorization,
+            intent_signature: EvmSignature::from(intent_signature.as_bytes()),
+        }))
+    }
 }
 
 impl<S> From<S> for EvmSenderWallet
@@ -43,6 +191,13 @@ impl IntoSenderWallet for PrivateKeySigner {
     }
 }
 
+#[cfg(feature = "ledger")]
+impl IntoSenderWallet for alloy::signers::ledger::LedgerSigner {
+    fn into_sender_wallet(self) -> Arc<dyn SenderWallet> {
+        Arc::new(EvmSenderWallet::new(self))
+    }
+}
+
 impl IntoSenderWallet for EvmSenderWallet {
     fn into_sender_wallet(self) -> Arc<dyn SenderWallet> {
         Arc::new(self)
@@ -52,6 +207,11 @@ impl IntoSenderWallet for EvmSenderWallet {
 #[async_trait]
 impl SenderWallet for EvmSenderWallet {
     fn can_handle(&self, requirements: &PaymentRequirements) -> bool {
+        match requirements.scheme {
+            Scheme::Exact => {}
+            Scheme::Permit | Scheme::Upto if self.provider.is_some() => {}
+            _ => return false,
+        }
         let network = requirements.network;
         let network_family: NetworkFamily = network.into();
         match network_family {
@@ -64,7 +224,7 @@ impl SenderWallet for EvmSenderWallet {
         &self,
         selected: PaymentRequirements,
     ) -> Result<PaymentPayload, X402PaymentsError> {
-        let (name, version) = match selected.extra {
+        let (name, version) = match &selected.extra {
             None => (None, None),
             Some(extra) => {
                 let name = extra
@@ -87,8 +247,16 @@ impl SenderWallet for EvmSenderWallet {
             name: name.unwrap_or("".to_string()),
             version: version.unwrap_or("".to_string()),
             chain_id: chain_id,
-            verifying_contract: selected.asset.try_into().map_err(X402PaymentsError::InvalidEVMAddress)?,
+            verifying_contract: selected.asset.clone().try_into().map_err(X402PaymentsError::InvalidEVMAddress)?,
         };
+        if matches!(selected.scheme, Scheme::Permit | Scheme::Upto) {
+            return Ok(PaymentPayload {
+                x402_version: x402_rs::types::X402Version::V1,
+                scheme: selected.scheme.clone(),
+                network,
+                payload: self.permit_payload(&selected, &domain).await?,
+            });
+        }
         let now = UnixTimestamp::try_now().map_err(X402PaymentsError::ClockError)?;
         let valid_after = UnixTimestamp(now.seconds_since_epoch() - 10 * 60); // 10 mins before
         let valid_before = now + selected.max_timeout_seconds;
@@ -114,10 +282,10 @@ impl SenderWallet for EvmSenderWallet {
             validBefore: authorization.valid_before.into(),
             nonce: FixedBytes(nonce),
         };
-        let eip712_hash = transfer_with_authorization.eip712_signing_hash(&domain);
+        let typed_data = TypedData::from_struct(&transfer_with_authorization, Some(domain));
         let signature = self
             .signer
-            .sign_hash(&eip712_hash)
+            .sign_dynamic_typed_data(&typed_data)
             .await
             .map_err(|e| X402PaymentsError::SigningError(format!("{e:?}")))?;
         #[cfg(feature = "telemetry")]
@@ -133,4 +301,21 @@ impl SenderWallet for EvmSenderWallet {
         };
         Ok(payment_payload)
     }
+
+    /// Reads the balance from the node set with [`with_rpc_url`](Self::with_rpc_url), if it is a
+    /// node of the network of `requirements`.
+    async fn balance(&self, requirements: &PaymentRequirements) -> Option<TokenAmount> {
+        let provider = self.provider.as_ref()?;
+        let evm_chain: EvmChain = requirements.network.try_into().ok()?;
+        if provider.get_chain_id().await.ok()? != evm_chain.chain_id {
+            return None;
+        }
+        let token: EvmAddress = requirements.asset.clone().try_into().ok()?;
+        let balance = IERC20::new(token.0, provider)
+            .balanceOf(self.signer.address())
+            .call()
+            .await
+            .ok()?;
+        Some(balance.into())
+    }
 }
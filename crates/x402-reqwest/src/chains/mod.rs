@@ -1,9 +1,10 @@
 use std::sync::Arc;
-use x402_rs::types::{PaymentPayload, PaymentRequirements};
+use x402_rs::types::{PaymentPayload, PaymentRequirements, TokenAmount};
 
 use crate::X402PaymentsError;
 
 pub mod evm;
+pub mod scheme;
 pub mod solana;
 
 #[async_trait::async_trait]
@@ -13,6 +14,12 @@ pub trait SenderWallet: Send + Sync {
         &self,
         selected: PaymentRequirements,
     ) -> Result<PaymentPayload, X402PaymentsError>;
+
+    /// Balance of the payer in the token of `requirements`, or `None` if the wallet can not
+    /// read it.
+    async fn balance(&self, _requirements: &PaymentRequirements) -> Option<TokenAmount> {
+        None
+    }
 }
 
 pub trait IntoSenderWallet {
@@ -0,0 +1,43 @@
+use async_trait::async_trait;
+use std::sync::Arc;
+use x402_rs::scheme::SchemeHandler;
+use x402_rs::types::{PaymentPayload, PaymentRequirements};
+
+use crate::X402PaymentsError;
+use crate::chains::SenderWallet;
+
+/// [`SenderWallet`] that constructs payloads of a custom payment scheme through its [`SchemeHandler`].
+#[derive(Clone)]
+pub struct SchemeSenderWallet {
+    handler: Arc<dyn SchemeHandler>,
+}
+
+impl SchemeSenderWallet {
+    pub fn new(handler: impl SchemeHandler + 'static) -> Self {
+        Self {
+            handler: Arc::new(handler),
+        }
+    }
+}
+
+#[async_trait]
+impl SenderWallet for SchemeSenderWallet {
+    fn can_handle(&self, requirements: &PaymentRequirements) -> bool {
+        requirements.scheme == self.handler.scheme()
+            && self
+                .handler
+                .kinds()
+                .iter()
+                .any(|kind| kind.network == requirements.network)
+    }
+
+    async fn payment_payload(
+        &self,
+        selected: PaymentRequirements,
+    ) -> Result<PaymentPayload, X402PaymentsError> {
+        self.handler
+            .payment_payload(&selected)
+            .await
+            .map_err(X402PaymentsError::SchemeError)
+    }
+}
@@ -15,7 +15,8 @@ use std::sync::Arc;
 use x402_rs::chain::solana::{SolanaAddress, TransactionInt};
 use x402_rs::network::NetworkFamily;
 use x402_rs::types::{
-    ExactPaymentPayload, ExactSolanaPayload, PaymentPayload, PaymentRequirements, X402Version,
+    ExactPaymentPayload, ExactSolanaPayload, PaymentPayload, PaymentRequirements, Scheme,
+    TokenAmount, X402Version,
 };
 
 use crate::X402PaymentsError;
@@ -77,6 +78,9 @@ impl IntoSenderWallet for SolanaSenderWallet {
 #[async_trait]
 impl SenderWallet for SolanaSenderWallet {
     fn can_handle(&self, requirements: &PaymentRequirements) -> bool {
+        if requirements.scheme != Scheme::Exact {
+            return false;
+        }
         let network = requirements.network;
         let network_family: NetworkFamily = network.into();
         match network_family {
@@ -249,6 +253,32 @@ impl SenderWallet for SolanaSenderWallet {
         };
         Ok(payment_payload)
     }
+
+    /// Reads the balance of the associated token account of the payer, zero if it does not exist.
+    async fn balance(&self, requirements: &PaymentRequirements) -> Option<TokenAmount> {
+        let asset: SolanaAddress = requirements.asset.clone().try_into().ok()?;
+        let mint = self.fetch_mint(&asset).ok()?;
+        let program_id = Pubkey::from_str("ATokenGPvbdGVxr1b2hvZbsiqW5xWH25efTNsLJA8knL").ok()?;
+        let asset_address: Pubkey = asset.into();
+        let (ata, _) = Pubkey::find_program_address(
+            &[
+                self.keypair.pubkey().as_ref(),
+                mint.token_program().as_ref(),
+                asset_address.as_ref(),
+            ],
+            &program_id,
+        );
+        let ata_account = self
+            .rpc_client
+            .get_account_with_commitment(&ata, self.rpc_client.commitment())
+            .ok()?
+            .value;
+        if ata_account.is_none() {
+            return Some(TokenAmount::from(0u64));
+        }
+        let balance = self.rpc_client.get_token_account_balance(&ata).ok()?;
+        balance.amount.parse::<u64>().ok().map(TokenAmount::from)
+    }
 }
 
 #[derive(Debug)]
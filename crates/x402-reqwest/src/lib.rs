@@ -9,14 +9,30 @@
 //! ## Features
 //! - Seamless integration with [`reqwest`] and [`reqwest_middleware`]
 //! - Transparent handling of `402 Payment Required` responses
-//! - EIP-712 signing using any [`alloy::Signer`]
+//! - EIP-712 signing using any [`alloy::Signer`], including encrypted keystores (`keystore`
+//!   feature) and Ledger hardware wallets (`ledger` feature), see [`chains::evm::EvmSenderWallet`]
 //! - Fluent builder pattern for ergonomic usage
 //! - Token-specific payment caps and preference lists
+//! - Spend budgets shared across clients, of one process or, through Redis, of several (`redis`
+//!   feature, see [`budget`])
+//! - Spend policies with per-request, per-stream, hourly and total caps, and allowlists of
+//!   recipients, networks and tokens (see [`policy`])
+//! - Pay-to-continue for resources that require payment mid-stream (see [`resume`])
+//! - Retries with jittered backoff of requests turned away by an overloaded seller, honoring
+//!   `Retry-After`, and a circuit breaker per host (see [`X402Payments::retry`])
+//! - Payments cached per resource, sent with later requests without a `402` round trip (see
+//!   [`cache`])
+//! - Paid WebSocket streams, each slice paid on request of the seller (`stream` feature, see
+//!   `stream`)
 //!
 //! ## Token Preferences and Spending Limits
 //! You can control how the client selects a payment method when multiple options are offered
 //! by the server. Using `.prefer(...)`, you can specify a priority list of accepted tokens.
 //!
+//! Among requirements of equally preferred tokens, `.prefer_network(...)` sets the networks to pay
+//! on first. With `.check_balances()`, the balance of the wallet is read before paying, and
+//! requirements it can not cover are passed over for the next preferred ones.
+//!
 //! You can also enforce safety limits with `.max(...)` to ensure that the client never
 //! spends more than a configured amount per token. This helps prevent overpayment or abuse.
 //!
@@ -25,11 +41,14 @@
 //! use x402_reqwest::{MaxTokenAmountFromAmount, X402Payments};
 //! use x402_rs::network::{Network, USDCDeployment};
 //!
+//! # fn main() -> Result<(), Box<dyn std::error::Error>> {
 //! let signer: PrivateKeySigner = "0x...".parse()?;
-//! X402Payments::with_signer(signer)
+//! let payments = X402Payments::with_wallet(signer)
 //!     // Example: prefer USDC on Base, and limit payments to 1.00 USDC
 //!     .prefer(USDCDeployment::by_network(Network::Base))
-//!     .max(USDCDeployment::by_network(Network::Base).amount("1.00")?)
+//!     .max(USDCDeployment::by_network(Network::Base).amount("1.00")?);
+//! # Ok(())
+//! # }
 //! ```
 //!
 //! ## Examples
@@ -97,7 +116,7 @@
 //!     let signer: PrivateKeySigner = "0x...".parse()?;
 //!     let client = rqm::ClientBuilder::new(Client::new())
 //!         .with(
-//!             X402Payments::with_signer(signer)
+//!             X402Payments::with_wallet(signer)
 //!                 .prefer(USDCDeployment::by_network(Network::BaseSepolia))
 //!                 .max(USDCDeployment::by_network(Network::BaseSepolia).amount(0.1)?),
 //!         )
@@ -125,6 +144,10 @@
 //!
 //! ## Crate Layout
 //! - [`middleware`] – The core [`X402Payments`] middleware and logic
+//! - [`policy`] – Spend policies: caps and allowlists of payments
+//! - [`cache`] – Payments cached per resource, reused or renewed on later requests
+//! - [`resume`] – Resuming partially downloaded resources with payment
+//! - `stream` – Paid WebSocket streams, consumed as a stream of bytes (`stream` feature)
 //! - [`builder`] – Builder traits for attaching `X402Payments` to [`reqwest::Client`] or [`reqwest::ClientBuilder`]
 //!
 //! ## Related Crates
@@ -138,7 +161,14 @@
 mod builder;
 mod middleware;
 
+pub mod budget;
+pub mod cache;
 pub mod chains;
+pub mod policy;
+pub mod resume;
+#[cfg(feature = "stream")]
+pub mod stream;
 
 pub use builder::*;
 pub use middleware::*;
+pub use x402_rs::retry::{CircuitBreaker, RetryPolicy};
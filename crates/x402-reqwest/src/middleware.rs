@@ -4,30 +4,50 @@
 //! allowing automatic retries of requests with valid `X-Payment` headers constructed via a signer.
 //!
 //! It includes:
-//! - Selection of preferred payment methods
+//! - Selection of preferred payment methods, by token and network, among those the wallet
+//!   balances can pay (see [`X402Payments::check_balances`])
 //! - Max token enforcement
+//! - Shared spend budgets (see [`crate::budget`])
+//! - Spend policies capping and allowlisting payments (see [`crate::policy`])
 //! - EIP-712-based payload construction and signing
+//! - Custom payment schemes via [`SchemeHandler`]
 //! - Base64 encoding into a payment header
+//! - Retries of requests turned away by an overloaded seller, per [`RetryPolicy`], with a
+//!   [`CircuitBreaker`] per host
+//! - Prepaid balances with sellers, charged per request without signing (see
+//!   [`X402Payments::prepaid_balances`])
+//! - Payments cached per resource, sent with later requests without a `402` round trip (see
+//!   [`crate::cache`])
 
+use alloy::primitives::B256;
+use http::header::RETRY_AFTER;
 use http::{Extensions, HeaderValue, StatusCode};
 use reqwest::{Request, Response};
 use reqwest_middleware as rqm;
 use std::collections::HashMap;
-use std::sync::Arc;
-use std::time::SystemTimeError;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTimeError};
 use tracing::instrument;
+use x402_rs::balance::BALANCE_HEADER;
+use x402_rs::chain::FacilitatorLocalError;
 use x402_rs::network::{Network, USDCDeployment};
+use x402_rs::retry::{CircuitBreaker, Overload, RetryPolicy};
+use x402_rs::scheme::SchemeHandler;
 use x402_rs::types::{
     Base64Bytes, MixedAddressError, MoneyAmount, MoneyAmountParseError, PaymentPayload,
     PaymentRequiredResponse, PaymentRequirements, TokenAmount, TokenAsset, TokenDeployment,
 };
 
+use crate::budget::{BudgetBackend, BudgetError};
+use crate::cache::{CachedPayment, PaymentCache};
+use crate::chains::scheme::SchemeSenderWallet;
 use crate::chains::{IntoSenderWallet, SenderWallet};
+use crate::policy::{SpendPolicy, SpendPolicyError};
 
 /// Represents the maximum allowed amount for a specific token asset.
 pub struct MaxTokenAmount {
-    asset: TokenAsset,
-    amount: TokenAmount,
+    pub(crate) asset: TokenAsset,
+    pub(crate) amount: TokenAmount,
 }
 
 /// Trait for converting from a token amount directly into a MaxTokenAmount bound.
@@ -91,6 +111,10 @@ pub enum X402PaymentsError {
         accepts: Vec<PaymentRequirements>,
         prefer: Vec<TokenAsset>,
     },
+    /// Raised when the wallet balances can not pay any of the server's accepted payment methods
+    /// the client could otherwise pay, see [`X402Payments::check_balances`].
+    #[error("Insufficient balance for any payment method. Accepted: {accepts:?}")]
+    InsufficientBalance { accepts: Vec<PaymentRequirements> },
     /// Raised when an EVM address (e.g., `to`, `from`, or `verifying_contract`) is invalid or cannot be parsed.
     #[error("Invalid EVM address")]
     InvalidEVMAddress(#[source] MixedAddressError),
@@ -98,6 +122,10 @@ pub enum X402PaymentsError {
     /// Should be an extremely rare occurrence.
     #[error("Failed to get system clock")]
     ClockError(#[source] SystemTimeError),
+    /// Raised when a wallet can not be loaded, e.g. a keystore with a wrong password, or a
+    /// hardware wallet not connected.
+    #[error("Failed to load wallet: {0}")]
+    WalletError(String),
     /// Indicates that signing the EIP-712 payment payload failed using the provided signer.
     #[error("Failed to sign payment payload: {0}")]
     SigningError(String),
@@ -109,6 +137,22 @@ pub enum X402PaymentsError {
     /// Typically caused by invalid characters or excessive length.
     #[error("Failed to encode payment payload to HTTP header")]
     HeaderValueEncodeError(#[source] http::header::InvalidHeaderValue),
+    /// Raised when the [`SchemeHandler`] of a custom payment scheme failed to construct the payload.
+    #[error("Failed to construct payment payload for custom scheme: {0}")]
+    SchemeError(#[source] FacilitatorLocalError),
+    /// Raised when a response passed to [`X402Payments::resume_with_payment`] is not a `402 Payment Required`.
+    #[error("Expected 402 Payment Required, got {0}")]
+    NotPaymentRequired(StatusCode),
+    /// Raised when the shared spend budget could not cover the payment, or its backend failed.
+    #[error(transparent)]
+    Budget(#[from] BudgetError),
+    /// Raised when the payment is outside the [spend policy](X402Payments::spend_policy).
+    #[error(transparent)]
+    Policy(#[from] SpendPolicyError),
+    /// Raised without sending the request when the seller at `host` turned away too many
+    /// requests in a row, see [`X402Payments::circuit_breaker`].
+    #[error("Too many requests turned away by {host}, retry after {retry_after:?}")]
+    CircuitOpen { host: String, retry_after: Duration },
 }
 
 impl From<X402PaymentsError> for rqm::Error {
@@ -141,6 +185,20 @@ pub struct X402Payments {
     wallets: Vec<Arc<dyn SenderWallet>>,
     max_token_amount: HashMap<TokenAsset, TokenAmount>,
     prefer: Vec<TokenAsset>,
+    prefer_networks: Vec<Network>,
+    /// Whether requirements the wallet balances can not pay are passed over.
+    check_balances: bool,
+    budget: Option<Arc<dyn BudgetBackend>>,
+    policy: Option<SpendPolicy>,
+    retry: RetryPolicy,
+    /// Template of the breakers of [`Self::breakers`].
+    breaker: CircuitBreaker,
+    /// Breaker of every host requested, shared by the clones of the middleware.
+    breakers: Arc<Mutex<HashMap<String, CircuitBreaker>>>,
+    /// Token of the prepaid balance with every host, if balances are used.
+    balances: Option<Arc<Mutex<HashMap<String, String>>>>,
+    /// Payments of every resource paid, if cached.
+    payment_cache: Option<PaymentCache>,
 }
 
 impl X402Payments {
@@ -149,6 +207,15 @@ impl X402Payments {
             wallets: vec![wallet.into_sender_wallet()],
             max_token_amount: HashMap::new(),
             prefer: vec![],
+            prefer_networks: vec![],
+            check_balances: false,
+            budget: None,
+            policy: None,
+            retry: RetryPolicy::default(),
+            breaker: CircuitBreaker::default(),
+            breakers: Arc::new(Mutex::new(HashMap::new())),
+            balances: None,
+            payment_cache: None,
         }
     }
 
@@ -159,6 +226,15 @@ impl X402Payments {
             wallets,
             max_token_amount: self.max_token_amount,
             prefer: self.prefer,
+            prefer_networks: self.prefer_networks,
+            check_balances: self.check_balances,
+            budget: self.budget,
+            policy: self.policy,
+            retry: self.retry,
+            breaker: self.breaker,
+            breakers: self.breakers,
+            balances: self.balances,
+            payment_cache: self.payment_cache,
         }
     }
 
@@ -176,23 +252,265 @@ impl X402Payments {
         this
     }
 
-    /// Selects the most preferred payment requirement based on the client's `prefer` list
-    /// and network priority (Base preferred).
-    pub fn select_payment_requirements(
+    /// Extend the preferred network list, prioritizing where the client wants to pay among the
+    /// requirements of equally preferred tokens.
+    pub fn prefer_network(&self, network: Network) -> Self {
+        let mut this = self.clone();
+        this.prefer_networks.push(network);
+        this
+    }
+
+    /// Read the balance of the wallet in the token of every requirement offered, most preferred
+    /// first, and pay the first one it covers. Requirements whose balance can not be read (e.g.
+    /// of an EVM wallet without [RPC URL](crate::chains::evm::EvmSenderWallet::with_rpc_url))
+    /// are not passed over.
+    ///
+    /// Fails with [`X402PaymentsError::InsufficientBalance`] if no balance covers a requirement.
+    pub fn check_balances(&self) -> Self {
+        let mut this = self.clone();
+        this.check_balances = true;
+        this
+    }
+
+    /// Draw payments from a spend budget shared with other clients.
+    ///
+    /// The amount of every payment is reserved from the budget before signing it.
+    /// See [`crate::budget`].
+    pub fn budget<B: BudgetBackend + 'static>(&self, budget: B) -> Self {
+        let mut this = self.clone();
+        this.budget = Some(Arc::new(budget));
+        this
+    }
+
+    /// Pay within `policy`: payments outside its allowlists or over its caps are never signed.
+    /// See [`crate::policy`].
+    pub fn spend_policy(&self, policy: SpendPolicy) -> Self {
+        let mut this = self.clone();
+        this.policy = Some(policy);
+        this
+    }
+
+    /// Checks paying the next slice of a stream after `stream_spent` was paid for the stream,
+    /// against the per-stream cap of the [spend policy](Self::spend_policy).
+    pub fn check_stream_spend(
+        &self,
+        requirements: &PaymentRequirements,
+        stream_spent: TokenAmount,
+    ) -> Result<(), X402PaymentsError> {
+        if let Some(policy) = &self.policy {
+            policy.check_stream(requirements, stream_spent)?;
+        }
+        Ok(())
+    }
+
+    /// Pay with a custom payment scheme, constructing payloads through its [`SchemeHandler`].
+    ///
+    /// Requirements of the scheme are only selected on the networks the handler advertises.
+    pub fn scheme<H: SchemeHandler + 'static>(&self, handler: H) -> Self {
+        let mut this = self.clone();
+        this.wallets
+            .push(Arc::new(SchemeSenderWallet::new(handler)));
+        this
+    }
+
+    /// Retry requests turned away by an overloaded seller (`429` or `503`) per `retry`,
+    /// honoring their `Retry-After` header. Retries up to 3 times by default.
+    ///
+    /// A paid request turned away was not processed, so it is sent again with the same payment.
+    /// Once retries run out, the last response is returned as is.
+    pub fn retry(&self, retry: RetryPolicy) -> Self {
+        let mut this = self.clone();
+        this.retry = retry;
+        this
+    }
+
+    /// Stop sending requests to a host that turned away too many in a row, failing them with
+    /// [`X402PaymentsError::CircuitOpen`] instead. `breaker` is the template of the breaker of
+    /// every host.
+    pub fn circuit_breaker(&self, breaker: CircuitBreaker) -> Self {
+        let mut this = self.clone();
+        this.breaker = breaker;
+        this.breakers = Arc::new(Mutex::new(HashMap::new()));
+        this
+    }
+
+    /// Pay sellers offering prepaid balances a deposit once, and send the token of the balance
+    /// with later requests to the same host, in the `X-Payment-Balance` header, instead of
+    /// signing a payment for every request. A balance running low is topped up with a new deposit
+    /// when the seller asks for it. See `x402_rs::balance`.
+    ///
+    /// A deposit is worth several requests: [`Self::max`] must allow for it.
+    pub fn prepaid_balances(&self) -> Self {
+        let mut this = self.clone();
+        this.balances = Some(Arc::new(Mutex::new(HashMap::new())));
+        this
+    }
+
+    /// Remember the requirements paid for every resource, by origin, path and query string, for their validity
+    /// window, and send later requests to the resource paid right away, without waiting for a
+    /// `402` response: with the same payment while the seller did not settle it, or else with a
+    /// new payment of the same requirements. See [`crate::cache`].
+    pub fn payment_cache(&self) -> Self {
+        let mut this = self.clone();
+        this.payment_cache = Some(PaymentCache::new());
+        this
+    }
+
+    /// The payment cached for the resource of `request`, unless it already carries a payment or
+    /// the token of a prepaid balance.
+    fn cached_payment(&self, request: &Request) -> Option<CachedPayment> {
+        let headers = request.headers();
+        if headers.contains_key("X-Payment") || headers.contains_key(BALANCE_HEADER) {
+            return None;
+        }
+        self.payment_cache.as_ref()?.get(request.url())
+    }
+
+    /// Attaches the payment cached for the resource of `request`, or a new payment of the same
+    /// requirements once settled, cached in turn. Returns the request, and the hash of the
+    /// requirements paid.
+    async fn cached_paid_request(
+        &self,
+        mut request: Request,
+        cached: CachedPayment,
+    ) -> Result<(Request, B256), X402PaymentsError> {
+        let payment_header = match cached.payment_header {
+            Some(payment_header) => payment_header,
+            None => {
+                let (selected, payment_header) = self
+                    .sign_payment(std::slice::from_ref(&cached.selected))
+                    .await?;
+                if let Some(cache) = &self.payment_cache {
+                    let renewed = CachedPayment::new(
+                        cached.accepts.clone(),
+                        &selected,
+                        payment_header.clone(),
+                    );
+                    cache.insert(request.url(), renewed);
+                }
+                payment_header
+            }
+        };
+        Self::attach_payment(&mut request, &cached.accepts, payment_header);
+        Ok((request, cached.requirements_hash))
+    }
+
+    /// Caches the payment `payment_header` of `selected` among `accepts`, sent with the request
+    /// of `response`, unless it is the deposit of a prepaid balance.
+    fn cache_payment(
+        &self,
+        response: &Response,
+        accepts: Vec<PaymentRequirements>,
+        selected: &PaymentRequirements,
+        payment_header: HeaderValue,
+    ) {
+        let Some(cache) = &self.payment_cache else {
+            return;
+        };
+        let deposit = selected
+            .extra
+            .as_ref()
+            .is_some_and(|extra| extra.get("prepaidRequests").is_some());
+        if deposit {
+            return;
+        }
+        let payment = CachedPayment::new(accepts, selected, payment_header);
+        let requirements_hash = payment.requirements_hash;
+        cache.insert(response.url(), payment);
+        self.record_settlement(response, requirements_hash);
+    }
+
+    /// Forgets the cached payment of the requirements hashed as `requirements_hash` for the
+    /// resource of `response` once the seller settled it.
+    fn record_settlement(&self, response: &Response, requirements_hash: B256) {
+        if let Some(cache) = &self.payment_cache
+            && response.headers().contains_key("X-Payment-Response")
+        {
+            cache.settled(response.url(), requirements_hash);
+        }
+    }
+
+    /// Token of the prepaid balance with the host of `url`, if any.
+    pub fn balance_token(&self, url: &reqwest::Url) -> Option<String> {
+        let host = url.host_str()?;
+        self.balances.as_ref()?.lock().unwrap().get(host).cloned()
+    }
+
+    /// Sends the token of the prepaid balance with the host of `request`, if any.
+    fn with_balance_token(&self, request: &mut Request) {
+        let token = self
+            .balance_token(request.url())
+            .and_then(|token| HeaderValue::from_str(&token).ok());
+        if let Some(token) = token {
+            request.headers_mut().insert(BALANCE_HEADER, token);
+        }
+    }
+
+    /// Remembers the token of the prepaid balance a deposit opened with the host of `response`.
+    fn record_balance_token(&self, response: &Response) {
+        let (Some(balances), Some(host)) = (&self.balances, response.url().host_str()) else {
+            return;
+        };
+        let token = response
+            .headers()
+            .get(BALANCE_HEADER)
+            .and_then(|token| token.to_str().ok());
+        if let Some(token) = token {
+            balances
+                .lock()
+                .unwrap()
+                .insert(host.to_string(), token.to_string());
+        }
+    }
+
+    /// Breaker of the host of `request`.
+    fn breaker_for(&self, request: &Request) -> (String, CircuitBreaker) {
+        let host = request.url().host_str().unwrap_or_default().to_string();
+        let breaker = self
+            .breakers
+            .lock()
+            .unwrap()
+            .entry(host.clone())
+            .or_insert_with(|| self.breaker.detached())
+            .clone();
+        (host, breaker)
+    }
+
+    /// Ranks the payment requirements the client has a wallet for and its
+    /// [spend policy](Self::spend_policy) allows, most preferred first: by the client's `prefer`
+    /// list of tokens, then its [network preferences](Self::prefer_network), then USDC first,
+    /// then Base first.
+    pub fn rank_payment_requirements(
         &self,
         payment_requirements: &[PaymentRequirements],
-    ) -> Result<PaymentRequirements, X402PaymentsError> {
-        let mut sorted: Vec<PaymentRequirements> = payment_requirements.to_vec();
+    ) -> Vec<PaymentRequirements> {
+        // Only consider requirements that one of the wallets (or scheme handlers) can pay
+        let mut sorted: Vec<PaymentRequirements> = payment_requirements
+            .iter()
+            .filter(|req| self.wallets.iter().any(|w| w.can_handle(req)))
+            .filter(|req| {
+                self.policy
+                    .as_ref()
+                    .is_none_or(|policy| policy.check_allowed(req).is_ok())
+            })
+            .cloned()
+            .collect();
         // Assign priority score: lower is better
-        // Prefer what is in self.prefer and ultimately Base
         sorted.sort_by_key(|req| {
             let pref_index = self
                 .prefer
                 .iter()
                 .position(|a| a == &req.token_asset())
                 .unwrap_or(usize::MAX);
+            let network_index = self
+                .prefer_networks
+                .iter()
+                .position(|network| network == &req.network)
+                .unwrap_or(usize::MAX);
+            let usdc = USDCDeployment::by_network(req.network);
+            let usdc_priority = if req.asset == usdc.address() { 0 } else { 1 };
             let base_priority = if req.network == Network::Base { 0 } else { 1 };
-            (pref_index, base_priority)
+            (pref_index, network_index, usdc_priority, base_priority)
         });
 
         #[cfg(feature = "telemetry")]
@@ -202,22 +520,57 @@ impl X402Payments {
             }
         }
 
-        // Try to find a USDC requirement
-        let usdc_requirement = sorted.iter().find(|req| {
-            let usdc = USDCDeployment::by_network(req.network);
-            req.asset == usdc.address()
-        });
-
-        let selected = usdc_requirement
-            .cloned() // Prioritize USDC requirements if available
-            .or_else(|| sorted.into_iter().next()); // If no USDC requirements are found, return the first accepted requirement.
+        sorted
+    }
 
+    /// Selects the most preferred payment requirement, see
+    /// [`rank_payment_requirements`](Self::rank_payment_requirements).
+    pub fn select_payment_requirements(
+        &self,
+        payment_requirements: &[PaymentRequirements],
+    ) -> Result<PaymentRequirements, X402PaymentsError> {
+        let selected = self
+            .rank_payment_requirements(payment_requirements)
+            .into_iter()
+            .next();
         selected.ok_or(X402PaymentsError::NoSuitablePaymentMethod {
             accepts: payment_requirements.to_vec(),
             prefer: self.prefer.clone(),
         })
     }
 
+    /// Selects the most preferred payment requirement that the wallet balances cover, if
+    /// [balances are checked](Self::check_balances), or else the most preferred one.
+    pub async fn select_affordable_requirements(
+        &self,
+        payment_requirements: &[PaymentRequirements],
+    ) -> Result<PaymentRequirements, X402PaymentsError> {
+        if !self.check_balances {
+            return self.select_payment_requirements(payment_requirements);
+        }
+        let ranked = self.rank_payment_requirements(payment_requirements);
+        if ranked.is_empty() {
+            return Err(X402PaymentsError::NoSuitablePaymentMethod {
+                accepts: payment_requirements.to_vec(),
+                prefer: self.prefer.clone(),
+            });
+        }
+        for req in ranked {
+            let Some(wallet) = self.wallets.iter().find(|w| w.can_handle(&req)) else {
+                continue;
+            };
+            let balance = wallet.balance(&req).await;
+            #[cfg(feature = "telemetry")]
+            tracing::debug!(asset = ?req.asset, network = ?req.network, ?balance, "Read wallet balance");
+            if balance.is_none_or(|balance| balance >= req.max_amount_required) {
+                return Ok(req);
+            }
+        }
+        Err(X402PaymentsError::InsufficientBalance {
+            accepts: payment_requirements.to_vec(),
+        })
+    }
+
     /// Ensures that the selected requirement does not exceed the max configured amount.
     pub fn assert_max_amount(
         &self,
@@ -238,6 +591,9 @@ impl X402Payments {
 
     /// Constructs a [`PaymentPayload`] for a given requirement by generating
     /// a nonce and signing an EIP-712 [`TransferWithAuthorization`] struct.
+    ///
+    /// The payment is checked against the [spend policy](Self::spend_policy), if any, and if a
+    /// [budget](Self::budget) is configured, the amount is reserved from it first.
     #[instrument(name = "x402.make_payment_payload", skip_all, fields(
         network = ?selected.network,
         token = ?selected.asset,
@@ -248,12 +604,40 @@ impl X402Payments {
         selected: PaymentRequirements,
     ) -> Result<PaymentPayload, X402PaymentsError> {
         let wallet = self.wallets.iter().find(|w| w.can_handle(&selected));
-        match wallet {
-            None => Err(X402PaymentsError::SigningError(
-                "No suitable wallet found".to_string(),
-            )),
-            Some(wallet) => wallet.payment_payload(selected).await,
+        let wallet = wallet.ok_or(X402PaymentsError::SigningError(
+            "No suitable wallet found".to_string(),
+        ))?;
+        let policy_reservation = match &self.policy {
+            Some(policy) => Some((policy, policy.reserve(&selected)?)),
+            None => None,
+        };
+        let payment_payload = self.reserve_and_sign(wallet, selected).await;
+        if payment_payload.is_err()
+            && let Some((policy, reservation)) = policy_reservation
+        {
+            policy.release(reservation);
+        }
+        payment_payload
+    }
+
+    /// Signs a payment of `selected` with `wallet`, reserving its amount from the budget first.
+    async fn reserve_and_sign(
+        &self,
+        wallet: &Arc<dyn SenderWallet>,
+        selected: PaymentRequirements,
+    ) -> Result<PaymentPayload, X402PaymentsError> {
+        let Some(budget) = &self.budget else {
+            return wallet.payment_payload(selected).await;
+        };
+        // Reserve before signing, so that concurrent clients can not collectively overspend
+        let reservation = budget
+            .reserve(&selected.token_asset(), selected.max_amount_required)
+            .await?;
+        let payment_payload = wallet.payment_payload(selected).await;
+        if payment_payload.is_err() {
+            budget.release(reservation).await?;
         }
+        payment_payload
     }
 
     /// Encodes the `PaymentPayload` into a base64 string suitable for an `X-Payment` header.
@@ -272,12 +656,59 @@ impl X402Payments {
         &self,
         accepts: &[PaymentRequirements],
     ) -> Result<HeaderValue, X402PaymentsError> {
-        let selected = self.select_payment_requirements(accepts)?;
+        let (_, payment_header) = self.sign_payment(accepts).await?;
+        Ok(payment_header)
+    }
+
+    /// Builds the payment header like [`Self::build_payment_header`], and returns it with the
+    /// requirement selected.
+    async fn sign_payment(
+        &self,
+        accepts: &[PaymentRequirements],
+    ) -> Result<(PaymentRequirements, HeaderValue), X402PaymentsError> {
+        let selected = self.select_affordable_requirements(accepts).await?;
         #[cfg(feature = "telemetry")]
         tracing::debug!(?selected, "Selected payment requirement");
         self.assert_max_amount(&selected)?;
-        let payment_payload = self.make_payment_payload(selected).await?;
-        Self::encode_payment_header(&payment_payload)
+        let payment_payload = self.make_payment_payload(selected.clone()).await?;
+        let payment_header = Self::encode_payment_header(&payment_payload)?;
+        Ok((selected, payment_header))
+    }
+
+    /// Attaches a payment for one of `accepts` to `request`, as the `X-Payment` header.
+    ///
+    /// If the requirements carry a `quoteId` in their `extra` field, it is sent back in the
+    /// `X-Payment-Quote` header, so that the price quoted by the seller is honored.
+    pub async fn paid_request(
+        &self,
+        mut request: Request,
+        accepts: &[PaymentRequirements],
+    ) -> Result<Request, X402PaymentsError> {
+        let payment_header = self.build_payment_header(accepts).await?;
+        Self::attach_payment(&mut request, accepts, payment_header);
+        Ok(request)
+    }
+
+    /// Attaches `payment_header`, a payment for one of `accepts`, to `request`, with the quote
+    /// of `accepts` if any.
+    fn attach_payment(
+        request: &mut Request,
+        accepts: &[PaymentRequirements],
+        payment_header: HeaderValue,
+    ) {
+        let quote_id = accepts
+            .iter()
+            .find_map(|requirements| requirements.extra.as_ref()?.get("quoteId")?.as_str())
+            .and_then(|quote_id| HeaderValue::from_str(quote_id).ok());
+        let headers = request.headers_mut();
+        headers.insert("X-Payment", payment_header);
+        if let Some(quote_id) = quote_id {
+            headers.insert("X-Payment-Quote", quote_id);
+        }
+        headers.insert(
+            "Access-Control-Expose-Headers",
+            HeaderValue::from_static("X-Payment-Response"),
+        );
     }
 }
 
@@ -287,41 +718,94 @@ impl rqm::Middleware for X402Payments {
     #[instrument(name = "x402.handle", skip(self, req, extensions, next), fields(method = %req.method(), url = %req.url()))]
     async fn handle(
         &self,
-        req: Request,
+        mut req: Request,
         extensions: &mut Extensions,
         next: rqm::Next<'_>,
     ) -> rqm::Result<Response> {
+        self.with_balance_token(&mut req);
         let retry_req = req.try_clone(); // For retrying with payment later
 
-        let res = next.clone().run(req, extensions).await?;
+        // Pay right away for a resource paid before, see `crate::cache`
+        let cached = self.cached_payment(&req);
+        let (req, cached_hash) = match cached {
+            Some(cached) => {
+                let (req, requirements_hash) = self.cached_paid_request(req, cached).await?;
+                (req, Some(requirements_hash))
+            }
+            None => (req, None),
+        };
+
+        let res = self.send(req, extensions, next.clone()).await?;
 
         #[cfg(feature = "telemetry")]
         tracing::debug!("Received response: {}", res.status());
 
         if res.status() != StatusCode::PAYMENT_REQUIRED {
+            if let Some(requirements_hash) = cached_hash {
+                self.record_settlement(&res, requirements_hash);
+            }
             return Ok(res); // No 402 needed: passthrough
         }
 
         #[cfg(feature = "telemetry")]
-        tracing::debug!("Received 402 Payment Required");
+        tracing::debug!(
+            from_cache = cached_hash.is_some(),
+            "Received 402 Payment Required"
+        );
+
+        if let (Some(requirements_hash), Some(cache)) = (cached_hash, &self.payment_cache) {
+            cache.remove(res.url(), requirements_hash);
+        }
 
         let payment_required_response = res.json::<PaymentRequiredResponse>().await?;
+        let accepts = payment_required_response.accepts;
+
+        let mut retry_req = retry_req.ok_or(X402PaymentsError::RequestNotCloneable)?;
+        let (selected, payment_header) = self.sign_payment(&accepts).await?;
+        Self::attach_payment(&mut retry_req, &accepts, payment_header.clone());
+        let res = self.send(retry_req, extensions, next).await?;
+        self.record_balance_token(&res);
+        if res.status() != StatusCode::PAYMENT_REQUIRED {
+            self.cache_payment(&res, accepts, &selected, payment_header);
+        }
+        Ok(res)
+    }
+}
 
-        let retry_req = async {
-            let payment_header = self
-                .build_payment_header(&payment_required_response.accepts)
-                .await?;
-            let mut req = retry_req.ok_or(X402PaymentsError::RequestNotCloneable)?;
-            let headers = req.headers_mut();
-            headers.insert("X-Payment", payment_header);
-            headers.insert(
-                "Access-Control-Expose-Headers",
-                HeaderValue::from_static("X-Payment-Response"),
-            );
-            Ok::<Request, X402PaymentsError>(req)
+impl X402Payments {
+    /// Sends `request` to the next middleware, again per [`Self::retry`] while it is turned away
+    /// by an overloaded seller. Requests that can not be cloned are sent once.
+    async fn send(
+        &self,
+        mut request: Request,
+        extensions: &mut Extensions,
+        next: rqm::Next<'_>,
+    ) -> rqm::Result<Response> {
+        let (host, breaker) = self.breaker_for(&request);
+        let mut retry = 0;
+        loop {
+            if let Err(retry_after) = breaker.check() {
+                return Err(X402PaymentsError::CircuitOpen { host, retry_after }.into());
+            }
+            let again = request.try_clone();
+            let res = next.clone().run(request, extensions).await?;
+            let retry_after = res
+                .headers()
+                .get(RETRY_AFTER)
+                .and_then(|value| value.to_str().ok());
+            let Some(overload) = Overload::from_http(res.status().as_u16(), retry_after) else {
+                breaker.record_success();
+                return Ok(res);
+            };
+            breaker.record_overload(overload);
+            retry += 1;
+            let (Some(delay), Some(again)) = (self.retry.delay(retry, overload), again) else {
+                return Ok(res);
+            };
+            #[cfg(feature = "telemetry")]
+            tracing::debug!(retry, ?delay, status = %res.status(), "Request turned away, retrying");
+            tokio::time::sleep(delay).await;
+            request = again;
         }
-        .await
-        .map_err(Into::<rqm::Error>::into)?;
-        next.run(retry_req, extensions).await
     }
 }
@@ -0,0 +1,320 @@
+//! Spend policies: guardrails on what a client pays, and to whom.
+//!
+//! An agent paying for resources on its own can loop: retry a request forever, follow links to
+//! ever more paid resources, or be steered to a seller of its attacker's choosing. A
+//! [`SpendPolicy`] set with [`X402Payments::spend_policy`](crate::X402Payments::spend_policy)
+//! bounds the damage. Before any payment payload is signed, the policy checks:
+//! - that the recipient, the network and the token of the payment are allowlisted, if allowlists
+//!   are set,
+//! - the amount of the payment, against the per-request cap of its token,
+//! - what a stream was paid so far, against the per-stream cap (see `stream`),
+//! - what was paid over the last hour and in total, against the hourly and total caps.
+//!
+//! A payment outside the policy is never signed, and fails with a [`SpendPolicyError`].
+//! Requirements outside the allowlists are not selected among those offered by a seller.
+//!
+//! Caps are in token base units (e.g. `1000000` for 1 USDC), per token: a token without a cap is
+//! not limited. Payments count toward the hourly and total caps when signed, whether or not the
+//! seller settles them. Clones of a policy share what was paid, so one policy can be passed to
+//! every client of an agent. Unlike a [budget](crate::budget), a policy is kept in memory, and
+//! what was paid starts over with the process.
+//!
+//! ```rust
+//! use alloy::signers::local::PrivateKeySigner;
+//! use x402_reqwest::{MaxTokenAmountFromAmount, X402Payments};
+//! use x402_reqwest::policy::SpendPolicy;
+//! use x402_rs::network::{Network, USDCDeployment};
+//!
+//! # fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! let usdc = USDCDeployment::by_network(Network::Base);
+//! let policy = SpendPolicy::new()
+//!     .allow_network(Network::Base)
+//!     .allow_asset(usdc.asset.clone())
+//!     .with_per_request(usdc.amount("0.10")?)
+//!     .with_per_hour(usdc.amount("5.00")?)
+//!     .with_total(usdc.amount("50.00")?);
+//!
+//! let payments = X402Payments::with_wallet(PrivateKeySigner::random()).spend_policy(policy);
+//! # Ok(())
+//! # }
+//! ```
+
+use alloy::primitives::U256;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use x402_rs::network::Network;
+use x402_rs::types::{MixedAddress, PaymentRequirements, TokenAmount, TokenAsset};
+
+use crate::middleware::MaxTokenAmount;
+
+/// Time over which the hourly caps are counted, rolling.
+const HOUR: Duration = Duration::from_secs(3600);
+
+/// Caps of a [`SpendPolicy`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SpendCap {
+    PerRequest,
+    PerStream,
+    PerHour,
+    Total,
+}
+
+impl std::fmt::Display for SpendCap {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let cap = match self {
+            SpendCap::PerRequest => "per-request",
+            SpendCap::PerStream => "per-stream",
+            SpendCap::PerHour => "hourly",
+            SpendCap::Total => "total",
+        };
+        f.write_str(cap)
+    }
+}
+
+/// A payment outside a [`SpendPolicy`].
+#[derive(Debug, thiserror::Error)]
+pub enum SpendPolicyError {
+    #[error("Recipient {0} is not allowlisted")]
+    RecipientNotAllowed(MixedAddress),
+    #[error("Network {0} is not allowlisted")]
+    NetworkNotAllowed(Network),
+    #[error("Token {} on {} is not allowlisted", .0.address, .0.network)]
+    AssetNotAllowed(TokenAsset),
+    /// Paying `requested` would exceed the `cap` of the token, `allowed` being what is left of it.
+    #[error("Payment of {requested} exceeds the {cap} cap for token {asset}: {allowed} left")]
+    CapExceeded {
+        cap: SpendCap,
+        asset: TokenAsset,
+        requested: TokenAmount,
+        allowed: TokenAmount,
+    },
+}
+
+/// What was paid in a token under a [`SpendPolicy`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PolicySpend {
+    /// Paid over the last hour.
+    pub last_hour: TokenAmount,
+    /// Paid since the policy was created.
+    pub total: TokenAmount,
+}
+
+/// Payments counted toward the caps of a [`SpendPolicy`], given back with
+/// [`SpendPolicy::release`] if signing fails.
+#[derive(Debug)]
+pub struct PolicyReservation {
+    asset: TokenAsset,
+    amount: U256,
+    at: Instant,
+}
+
+/// Payments of a token.
+#[derive(Debug, Default)]
+struct Spent {
+    /// Payments of the last hour, oldest first.
+    recent: VecDeque<(Instant, U256)>,
+    total: U256,
+}
+
+impl Spent {
+    /// Forgets the payments older than an hour, and returns what was paid since.
+    fn last_hour(&mut self, now: Instant) -> U256 {
+        while let Some((at, _)) = self.recent.front()
+            && now.duration_since(*at) >= HOUR
+        {
+            self.recent.pop_front();
+        }
+        self.recent
+            .iter()
+            .fold(U256::ZERO, |sum, (_, amount)| sum.saturating_add(*amount))
+    }
+}
+
+/// Caps and allowlists of the payments of a client, see the [module documentation](self).
+/// Allows any payment by default.
+///
+/// Cloning is cheap, and clones share what was paid.
+#[derive(Debug, Clone, Default)]
+pub struct SpendPolicy {
+    per_request: HashMap<TokenAsset, TokenAmount>,
+    per_stream: HashMap<TokenAsset, TokenAmount>,
+    per_hour: HashMap<TokenAsset, TokenAmount>,
+    total: HashMap<TokenAsset, TokenAmount>,
+    recipients: HashSet<MixedAddress>,
+    networks: HashSet<Network>,
+    assets: HashSet<TokenAsset>,
+    spent: Arc<Mutex<HashMap<TokenAsset, Spent>>>,
+}
+
+impl SpendPolicy {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Caps the amount of a single payment, each slice of a stream included.
+    pub fn with_per_request(mut self, max: MaxTokenAmount) -> Self {
+        self.per_request.insert(max.asset, max.amount);
+        self
+    }
+
+    /// Caps the amount paid for a single stream.
+    pub fn with_per_stream(mut self, max: MaxTokenAmount) -> Self {
+        self.per_stream.insert(max.asset, max.amount);
+        self
+    }
+
+    /// Caps the amount paid over any rolling hour.
+    pub fn with_per_hour(mut self, max: MaxTokenAmount) -> Self {
+        self.per_hour.insert(max.asset, max.amount);
+        self
+    }
+
+    /// Caps the amount paid over the lifetime of the policy.
+    pub fn with_total(mut self, max: MaxTokenAmount) -> Self {
+        self.total.insert(max.asset, max.amount);
+        self
+    }
+
+    /// Allows paying `recipient`. Once a recipient is allowlisted, others are refused.
+    pub fn allow_recipient<A: Into<MixedAddress>>(mut self, recipient: A) -> Self {
+        self.recipients.insert(recipient.into());
+        self
+    }
+
+    /// Allows paying on `network`. Once a network is allowlisted, others are refused.
+    pub fn allow_network(mut self, network: Network) -> Self {
+        self.networks.insert(network);
+        self
+    }
+
+    /// Allows paying with `asset`. Once a token is allowlisted, others are refused.
+    pub fn allow_asset(mut self, asset: TokenAsset) -> Self {
+        self.assets.insert(asset);
+        self
+    }
+
+    /// What was paid in `asset` under the policy.
+    pub fn spent(&self, asset: &TokenAsset) -> PolicySpend {
+        let mut spent = self.spent.lock().unwrap();
+        let (last_hour, total) = match spent.get_mut(asset) {
+            Some(spent) => (spent.last_hour(Instant::now()), spent.total),
+            None => (U256::ZERO, U256::ZERO),
+        };
+        PolicySpend {
+            last_hour: TokenAmount(last_hour),
+            total: TokenAmount(total),
+        }
+    }
+
+    /// Checks the recipient, the network and the token of `requirements` against the allowlists.
+    pub fn check_allowed(
+        &self,
+        requirements: &PaymentRequirements,
+    ) -> Result<(), SpendPolicyError> {
+        if !self.recipients.is_empty() && !self.recipients.contains(&requirements.pay_to) {
+            return Err(SpendPolicyError::RecipientNotAllowed(
+                requirements.pay_to.clone(),
+            ));
+        }
+        if !self.networks.is_empty() && !self.networks.contains(&requirements.network) {
+            return Err(SpendPolicyError::NetworkNotAllowed(requirements.network));
+        }
+        let asset = requirements.token_asset();
+        if !self.assets.is_empty() && !self.assets.contains(&asset) {
+            return Err(SpendPolicyError::AssetNotAllowed(asset));
+        }
+        Ok(())
+    }
+
+    /// Checks paying the next slice of a stream, of `requirements`, after `stream_spent` was paid
+    /// for the stream, against the per-stream cap.
+    pub fn check_stream(
+        &self,
+        requirements: &PaymentRequirements,
+        stream_spent: TokenAmount,
+    ) -> Result<(), SpendPolicyError> {
+        let asset = requirements.token_asset();
+        let Some(cap) = self.per_stream.get(&asset) else {
+            return Ok(());
+        };
+        check_cap(
+            SpendCap::PerStream,
+            &asset,
+            requirements.max_amount_required.0,
+            stream_spent.0,
+            cap.0,
+        )
+    }
+
+    /// Checks paying `requirements` against the allowlists and the caps, and counts its amount
+    /// toward the hourly and total caps.
+    pub fn reserve(
+        &self,
+        requirements: &PaymentRequirements,
+    ) -> Result<PolicyReservation, SpendPolicyError> {
+        self.check_allowed(requirements)?;
+        let asset = requirements.token_asset();
+        let amount = requirements.max_amount_required.0;
+        if let Some(cap) = self.per_request.get(&asset) {
+            check_cap(SpendCap::PerRequest, &asset, amount, U256::ZERO, cap.0)?;
+        }
+        let now = Instant::now();
+        let mut spent = self.spent.lock().unwrap();
+        let spent = spent.entry(asset.clone()).or_default();
+        if let Some(cap) = self.per_hour.get(&asset) {
+            check_cap(
+                SpendCap::PerHour,
+                &asset,
+                amount,
+                spent.last_hour(now),
+                cap.0,
+            )?;
+        }
+        if let Some(cap) = self.total.get(&asset) {
+            check_cap(SpendCap::Total, &asset, amount, spent.total, cap.0)?;
+        }
+        spent.recent.push_back((now, amount));
+        spent.total = spent.total.saturating_add(amount);
+        Ok(PolicyReservation {
+            asset,
+            amount,
+            at: now,
+        })
+    }
+
+    /// Gives back a payment that was not signed.
+    pub fn release(&self, reservation: PolicyReservation) {
+        let mut spent = self.spent.lock().unwrap();
+        let Some(spent) = spent.get_mut(&reservation.asset) else {
+            return;
+        };
+        spent.total = spent.total.saturating_sub(reservation.amount);
+        if let Some(position) = spent
+            .recent
+            .iter()
+            .position(|entry| *entry == (reservation.at, reservation.amount))
+        {
+            spent.recent.remove(position);
+        }
+    }
+}
+
+/// Checks paying `amount` after `spent`, against `cap`.
+fn check_cap(
+    cap_kind: SpendCap,
+    asset: &TokenAsset,
+    amount: U256,
+    spent: U256,
+    cap: U256,
+) -> Result<(), SpendPolicyError> {
+    if spent.saturating_add(amount) > cap {
+        return Err(SpendPolicyError::CapExceeded {
+            cap: cap_kind,
+            asset: asset.clone(),
+            requested: TokenAmount(amount),
+            allowed: TokenAmount(cap.saturating_sub(spent)),
+        });
+    }
+    Ok(())
+}
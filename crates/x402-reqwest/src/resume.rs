@@ -0,0 +1,108 @@
+//! Pay-to-continue for resources that require payment mid-stream.
+//!
+//! A server may serve part of a resource for free, e.g. a preview of a download, and only
+//! require payment for the rest. The status line of the first response has already been sent
+//! by then, so the server ends the body early and answers the follow-up request for the
+//! remaining bytes with `402 Payment Required`.
+//!
+//! [`X402Payments::resume`] implements the client side of this flow: it re-issues the request
+//! with a `Range: bytes=<offset>-` header and, if the server answers with `402`, pays for it and
+//! retries. If the `402` was already received, e.g. by a client driving the requests itself,
+//! [`X402Payments::resume_with_payment`] pays for it directly.
+//!
+//! ```rust,no_run
+//! use alloy::signers::local::PrivateKeySigner;
+//! use reqwest::Client;
+//! use x402_reqwest::X402Payments;
+//!
+//! #[tokio::main]
+//! async fn main() -> Result<(), Box<dyn std::error::Error>> {
+//!     let signer: PrivateKeySigner = "0x...".parse()?;
+//!     let payments = X402Payments::with_wallet(signer);
+//!     let client = Client::new();
+//!     let url = "https://example.com/video.mp4";
+//!
+//!     // Free preview: read until the server cuts the body off.
+//!     let mut downloaded = Vec::new();
+//!     let mut preview = client.get(url).send().await?;
+//!     while let Ok(Some(chunk)) = preview.chunk().await {
+//!         downloaded.extend_from_slice(&chunk);
+//!     }
+//!
+//!     // Pay for the rest and continue where the preview stopped.
+//!     let request = client.get(url).build()?;
+//!     let rest = payments
+//!         .resume(&client, request, Some(downloaded.len() as u64))
+//!         .await?;
+//!     downloaded.extend_from_slice(&rest.bytes().await?);
+//!     Ok(())
+//! }
+//! ```
+
+use http::header::RANGE;
+use http::{HeaderValue, StatusCode};
+use reqwest::{Client, Request, Response};
+use reqwest_middleware as rqm;
+use tracing::instrument;
+use x402_rs::types::PaymentRequiredResponse;
+
+use crate::middleware::{X402Payments, X402PaymentsError};
+
+impl X402Payments {
+    /// Re-issues `request`, starting at byte `offset` if given, and pays for it if the server
+    /// responds with `402 Payment Required`.
+    ///
+    /// `client` should be a plain [`Client`]: the payment is handled here, not by a middleware.
+    #[instrument(name = "x402.resume", skip(self, client, request), fields(method = %request.method(), url = %request.url()))]
+    pub async fn resume(
+        &self,
+        client: &Client,
+        request: Request,
+        offset: Option<u64>,
+    ) -> rqm::Result<Response> {
+        let request = with_range(request, offset);
+        let retry_req = request.try_clone();
+        let res = client.execute(request).await?;
+        if res.status() != StatusCode::PAYMENT_REQUIRED {
+            return Ok(res);
+        }
+        let retry_req = retry_req.ok_or(X402PaymentsError::RequestNotCloneable)?;
+        self.resume_with_payment(client, retry_req, res, offset).await
+    }
+
+    /// Pays for an already received `402 Payment Required` response and re-issues `request`,
+    /// starting at byte `offset` if given.
+    ///
+    /// Fails with [`X402PaymentsError::NotPaymentRequired`] if `payment_required` is not a `402`.
+    #[instrument(name = "x402.resume_with_payment", skip(self, client, request, payment_required), fields(method = %request.method(), url = %request.url()))]
+    pub async fn resume_with_payment(
+        &self,
+        client: &Client,
+        request: Request,
+        payment_required: Response,
+        offset: Option<u64>,
+    ) -> rqm::Result<Response> {
+        let status = payment_required.status();
+        if status != StatusCode::PAYMENT_REQUIRED {
+            return Err(X402PaymentsError::NotPaymentRequired(status).into());
+        }
+        let payment_required_response = payment_required
+            .json::<PaymentRequiredResponse>()
+            .await?;
+        let request = with_range(request, offset);
+        let request = self
+            .paid_request(request, &payment_required_response.accepts)
+            .await?;
+        Ok(client.execute(request).await?)
+    }
+}
+
+/// Sets `Range: bytes=<offset>-` on `request`, unless `offset` is `None`.
+fn with_range(mut request: Request, offset: Option<u64>) -> Request {
+    if let Some(offset) = offset {
+        let range = HeaderValue::from_str(&format!("bytes={offset}-"))
+            .expect("Range header is always valid ASCII");
+        request.headers_mut().insert(RANGE, range);
+    }
+    request
+}
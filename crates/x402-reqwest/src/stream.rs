@@ -0,0 +1,578 @@
+//! Paid WebSocket streams, consumed as a [`Stream`] of bytes (`stream` feature).
+//!
+//! A seller of a paid stream asks for the payment of every slice with `stream.require`, see
+//! `x402-ws-stream.md`. [`X402StreamClient`] opens the stream with `stream.init`, answers every
+//! `stream.require` with a `stream.pay` signed by an [`X402Payments`], within the spend limits of
+//! the stream, and hands the content of the `stream.data` frames to the application as a
+//! [`PaidByteStream`]:
+//!
+//! ```rust,no_run
+//! use alloy::signers::local::PrivateKeySigner;
+//! use futures_util::StreamExt;
+//! use x402_reqwest::X402Payments;
+//! use x402_reqwest::stream::X402StreamClient;
+//! use x402_rs::types::stream::StreamInit;
+//!
+//! #[tokio::main]
+//! async fn main() -> Result<(), Box<dyn std::error::Error>> {
+//!     let signer: PrivateKeySigner = "0x...".parse()?;
+//!     let client = X402StreamClient::new(X402Payments::with_wallet(signer))
+//!         // Pay at most 1 USDC for the whole stream
+//!         .max_total(1_000_000u64);
+//!
+//!     let mut stream = client
+//!         .connect("wss://example.com/ws", StreamInit::new("wss://example.com/feed"))
+//!         .await?;
+//!     while let Some(chunk) = stream.next().await {
+//!         println!("{} bytes", chunk?.len());
+//!     }
+//!     Ok(())
+//! }
+//! ```
+//!
+//! The hash of every chunk is checked against its content. A `stream.require` cancelled by the
+//! seller with `stream.require.cancel` is never paid. End-to-end encrypted streams are not
+//! supported.
+//!
+//! [`PaidByteStream::close`], or dropping the [`PaidByteStream`], closes the stream cooperatively
+//! with `stream.closing`: the remainder the seller requires in its own `stream.closing` is paid,
+//! within the limits of the stream, and the final bill of the seller, `stream.closed`, is checked
+//! against the payments it accepted and the chunks received, see [`PaidByteStream::bill`]. A
+//! seller not sending its bill within 10 seconds gets the stream ended with `stream.end`.
+
+use alloy::primitives::U256;
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as b64;
+use bytes::Bytes;
+use futures_util::{SinkExt, Stream, StreamExt};
+use serde_json::{Value, json};
+use sha2::{Digest, Sha256};
+use std::collections::{HashMap, HashSet};
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use std::time::Duration;
+use tokio::net::TcpStream;
+use tokio::sync::mpsc;
+use tokio::time::Instant;
+use tokio_tungstenite::tungstenite::Message;
+use tokio_tungstenite::{MaybeTlsStream, WebSocketStream, connect_async, tungstenite};
+use uuid::Uuid;
+use x402_rs::types::TokenAmount;
+use x402_rs::types::stream::{
+    StreamAccept, StreamClose, StreamClosed, StreamClosing, StreamEnvelope, StreamInit,
+    StreamMessage, StreamPay, StreamRequire,
+};
+use x402_rs::ws_error::WsError;
+
+use crate::middleware::{X402Payments, X402PaymentsError};
+
+/// Chunks received ahead of the application reading them.
+const CHANNEL_CAPACITY: usize = 64;
+/// Time the seller has to send its final bill once the stream is closing.
+const CLOSE_TIMEOUT: Duration = Duration::from_secs(10);
+
+type Socket = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+/// Errors of an [`X402StreamClient`] stream.
+#[derive(Debug, thiserror::Error)]
+pub enum X402StreamError {
+    #[error("WebSocket error: {0}")]
+    WebSocket(Box<tungstenite::Error>),
+    #[error("Invalid message from the seller: {0}")]
+    Protocol(String),
+    /// The seller answered with an error envelope, e.g. rejecting a payment.
+    #[error("Seller rejected the request: {0}")]
+    Rejected(WsError),
+    /// Paying the next slice would exceed a limit of the stream: the stream is ended.
+    #[error("Stream spend limit reached: {0}")]
+    SpendLimit(String),
+    #[error("Payment failed: {0}")]
+    Payment(Box<X402PaymentsError>),
+    /// The content of chunk `seq` does not match its hash.
+    #[error("Content of chunk {seq} does not match its hash")]
+    Integrity { seq: u64 },
+    #[error("End-to-end encrypted streams are not supported")]
+    EncryptionUnsupported,
+    /// The final bill of the seller differs from the payments it accepted, or the chunks received.
+    #[error("Final bill of the seller differs from the stream: {0}")]
+    BillMismatch(String),
+}
+
+impl From<tungstenite::Error> for X402StreamError {
+    fn from(e: tungstenite::Error) -> Self {
+        X402StreamError::WebSocket(Box::new(e))
+    }
+}
+
+impl From<X402PaymentsError> for X402StreamError {
+    fn from(e: X402PaymentsError) -> Self {
+        X402StreamError::Payment(Box::new(e))
+    }
+}
+
+impl From<serde_json::Error> for X402StreamError {
+    fn from(e: serde_json::Error) -> Self {
+        X402StreamError::Protocol(e.to_string())
+    }
+}
+
+/// What a stream was paid so far.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StreamSpend {
+    /// Number of slices paid.
+    pub slices: u64,
+    /// Total paid, in the asset of the slices.
+    pub amount: TokenAmount,
+}
+
+impl Default for StreamSpend {
+    fn default() -> Self {
+        Self {
+            slices: 0,
+            amount: TokenAmount(U256::ZERO),
+        }
+    }
+}
+
+/// Client of paid WebSocket streams, see the [module documentation](self).
+///
+/// Every stream is paid by the [`X402Payments`] of the client, with its wallets, limits and
+/// budget; the limits set here apply to each stream on its own. Unlimited by default.
+#[derive(Clone)]
+pub struct X402StreamClient {
+    payments: X402Payments,
+    max_per_slice: Option<TokenAmount>,
+    max_total: Option<TokenAmount>,
+    max_slices: Option<u64>,
+}
+
+impl X402StreamClient {
+    pub fn new(payments: X402Payments) -> Self {
+        Self {
+            payments,
+            max_per_slice: None,
+            max_total: None,
+            max_slices: None,
+        }
+    }
+
+    /// Pay at most `amount` for a slice.
+    pub fn max_per_slice<A: Into<TokenAmount>>(&self, amount: A) -> Self {
+        let mut this = self.clone();
+        this.max_per_slice = Some(amount.into());
+        this
+    }
+
+    /// Pay at most `amount` for all slices of a stream, in the asset of its slices.
+    pub fn max_total<A: Into<TokenAmount>>(&self, amount: A) -> Self {
+        let mut this = self.clone();
+        this.max_total = Some(amount.into());
+        this
+    }
+
+    /// Pay at most `slices` slices of a stream.
+    pub fn max_slices(&self, slices: u64) -> Self {
+        let mut this = self.clone();
+        this.max_slices = Some(slices);
+        this
+    }
+
+    /// Opens a stream at the seller WebSocket endpoint `url` with `init`, and returns it once the
+    /// seller accepted it. Must be called from within a Tokio runtime.
+    pub async fn connect(
+        &self,
+        url: &str,
+        init: StreamInit,
+    ) -> Result<PaidByteStream, X402StreamError> {
+        if init.encryption.is_some() {
+            return Err(X402StreamError::EncryptionUnsupported);
+        }
+        let (mut socket, _) = connect_async(url).await?;
+        let init_id = json!(Uuid::new_v4().to_string());
+        let init = StreamEnvelope::new(init_id.clone(), StreamMessage::Init(init));
+        socket
+            .send(Message::Text(serde_json::to_string(&init)?.into()))
+            .await?;
+        let accept = loop {
+            let Some(message) = socket.next().await else {
+                return Err(X402StreamError::Protocol(
+                    "Connection closed before stream.accept".to_string(),
+                ));
+            };
+            let text = match message? {
+                Message::Text(text) => text,
+                Message::Close(_) => {
+                    return Err(X402StreamError::Protocol(
+                        "Connection closed before stream.accept".to_string(),
+                    ));
+                }
+                _ => continue,
+            };
+            let envelope: Value = serde_json::from_str(&text)?;
+            if envelope.get("id") != Some(&init_id) {
+                continue;
+            }
+            if let Some(error) = envelope.get("error") {
+                return Err(X402StreamError::Rejected(serde_json::from_value(
+                    error.clone(),
+                )?));
+            }
+            match envelope
+                .get("result")
+                .map(|result| serde_json::from_value(result.clone()))
+            {
+                Some(Ok(StreamMessage::Accept(accept))) => break accept,
+                _ => {
+                    return Err(X402StreamError::Protocol(
+                        "stream.init not answered with stream.accept".to_string(),
+                    ));
+                }
+            }
+        };
+
+        let (chunks, chunks_rx) = mpsc::channel(CHANNEL_CAPACITY);
+        let (close, close_rx) = mpsc::channel(1);
+        let spend = Arc::new(Mutex::new(StreamSpend::default()));
+        let bill = Arc::new(Mutex::new(None));
+        let driver = Driver {
+            client: self.clone(),
+            socket,
+            stream_id: accept.stream_id.clone(),
+            chunks,
+            close: close_rx,
+            spend: spend.clone(),
+            bill: bill.clone(),
+            cancelled: HashSet::new(),
+            closing: None,
+            payments: HashMap::new(),
+            accepted: StreamSpend::default(),
+            received: 0,
+        };
+        tokio::spawn(driver.run());
+        Ok(PaidByteStream {
+            accept,
+            chunks: chunks_rx,
+            close,
+            spend,
+            bill,
+        })
+    }
+
+    /// Checks paying `require` after `spend`.
+    fn check(&self, require: &StreamRequire, spend: StreamSpend) -> Result<(), X402StreamError> {
+        let amount = require.requirements.max_amount_required;
+        if let Some(max) = self.max_per_slice
+            && amount > max
+        {
+            return Err(X402StreamError::SpendLimit(format!(
+                "slice price {amount} above {max}"
+            )));
+        }
+        if let Some(max) = self.max_slices
+            && spend.slices >= max
+        {
+            return Err(X402StreamError::SpendLimit(format!("{max} slices paid")));
+        }
+        let total = spend.amount.0.saturating_add(amount.0);
+        if let Some(max) = self.max_total
+            && total > max.0
+        {
+            return Err(X402StreamError::SpendLimit(format!(
+                "total paid {total} above {max}"
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// Content of a paid stream, opened by [`X402StreamClient::connect`].
+///
+/// Yields the content of every `stream.data` frame, and the error envelopes of the seller, e.g. a
+/// rejected payment, as [`X402StreamError::Rejected`], after which the stream goes on. Other
+/// errors end the stream. The stream is over once the seller ended it.
+pub struct PaidByteStream {
+    accept: StreamAccept,
+    chunks: mpsc::Receiver<Result<Bytes, X402StreamError>>,
+    close: mpsc::Sender<()>,
+    spend: Arc<Mutex<StreamSpend>>,
+    bill: Arc<Mutex<Option<StreamClosed>>>,
+}
+
+impl PaidByteStream {
+    pub fn stream_id(&self) -> &str {
+        &self.accept.stream_id
+    }
+
+    /// The `stream.accept` of the seller, with the price per unit and the asset of the slices.
+    pub fn accept(&self) -> &StreamAccept {
+        &self.accept
+    }
+
+    /// What the stream was paid so far, counted when the payments are signed: a slice rejected
+    /// by the seller may still be settled.
+    pub fn spent(&self) -> StreamSpend {
+        *self.spend.lock().unwrap()
+    }
+
+    /// Asks the seller to close the stream with `stream.closing`. The stream yields the content
+    /// still delivered, and is over once the seller sent its final bill.
+    pub fn close(&self) {
+        let _ = self.close.try_send(());
+    }
+
+    /// The final bill of the seller, `stream.closed`, once it closed the stream cooperatively.
+    /// Yielded as [`X402StreamError::BillMismatch`] before the end of the stream if it differs
+    /// from the payments the seller accepted, or from the chunks received.
+    pub fn bill(&self) -> Option<StreamClosed> {
+        self.bill.lock().unwrap().clone()
+    }
+}
+
+impl Stream for PaidByteStream {
+    type Item = Result<Bytes, X402StreamError>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.chunks.poll_recv(cx)
+    }
+}
+
+/// `0x`-prefixed hex `SHA256(0x00 ‖ content)`, the `hash` of a `stream.data` frame.
+fn chunk_hash(content: &[u8]) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update([0x00]);
+    hasher.update(content);
+    let hex: String = hasher
+        .finalize()
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect();
+    format!("0x{hex}")
+}
+
+/// Task paying and reading a stream opened by [`X402StreamClient::connect`].
+struct Driver {
+    client: X402StreamClient,
+    socket: Socket,
+    stream_id: String,
+    chunks: mpsc::Sender<Result<Bytes, X402StreamError>>,
+    /// Requests of the application to close the stream.
+    close: mpsc::Receiver<()>,
+    spend: Arc<Mutex<StreamSpend>>,
+    bill: Arc<Mutex<Option<StreamClosed>>>,
+    /// Requirements cancelled by the seller, never to be paid.
+    cancelled: HashSet<String>,
+    /// Deadline of the final bill, once the stream is closing.
+    closing: Option<Instant>,
+    /// Amounts of the payments not answered yet, by envelope id.
+    payments: HashMap<String, TokenAmount>,
+    /// Payments accepted by the seller.
+    accepted: StreamSpend,
+    /// Number of chunks received.
+    received: u64,
+}
+
+impl Driver {
+    async fn run(mut self) {
+        loop {
+            let closing = self.closing;
+            let message = tokio::select! {
+                _ = self.chunks.closed(), if closing.is_none() => {
+                    if !self.start_closing().await {
+                        return;
+                    }
+                    continue;
+                }
+                Some(()) = self.close.recv(), if closing.is_none() => {
+                    if !self.start_closing().await {
+                        return;
+                    }
+                    continue;
+                }
+                _ = tokio::time::sleep_until(closing.unwrap_or_else(Instant::now)), if closing.is_some() => {
+                    self.end().await;
+                    let error = X402StreamError::Protocol("No stream.closed from the seller".to_string());
+                    let _ = self.chunks.send(Err(error)).await;
+                    return;
+                }
+                message = self.socket.next() => message,
+            };
+            let result = match message {
+                Some(Ok(Message::Text(text))) => self.handle(&text).await,
+                Some(Ok(Message::Close(_))) | None => return,
+                Some(Ok(_)) => continue,
+                Some(Err(e)) => Err(e.into()),
+            };
+            match result {
+                Ok(true) => {}
+                Ok(false) => return,
+                Err(e) => {
+                    if let X402StreamError::SpendLimit(_) | X402StreamError::Payment(_) = e {
+                        self.end().await;
+                    }
+                    let _ = self.chunks.send(Err(e)).await;
+                    return;
+                }
+            }
+        }
+    }
+
+    /// Handles an envelope of the seller; `false` once the stream is over.
+    async fn handle(&mut self, text: &str) -> Result<bool, X402StreamError> {
+        let envelope: Value = serde_json::from_str(text)?;
+        let payment = envelope
+            .get("id")
+            .and_then(|id| self.payments.remove(&id.to_string()));
+        if let Some(error) = envelope.get("error") {
+            let error = X402StreamError::Rejected(serde_json::from_value(error.clone())?);
+            return Ok(self.chunks.send(Err(error)).await.is_ok() || self.closing.is_some());
+        }
+        let Some(method) = envelope.get("method").and_then(Value::as_str) else {
+            // stream.accept of a payment
+            if let Some(amount) = payment {
+                self.accepted.slices += 1;
+                self.accepted.amount = TokenAmount(self.accepted.amount.0.saturating_add(amount.0));
+            }
+            return Ok(true);
+        };
+        if !StreamMessage::METHODS.contains(&method) {
+            return Ok(true);
+        }
+        let envelope: StreamEnvelope = serde_json::from_value(envelope)?;
+        let id = envelope.id().clone();
+        match envelope.into_message() {
+            StreamMessage::RequireCancel(cancel) => {
+                self.cancelled.insert(cancel.require_id);
+                Ok(true)
+            }
+            StreamMessage::Require(require) => {
+                // Requirements are void once closing, but for the remainder of stream.closing
+                if self.cancelled.contains(&require.require_id) || self.closing.is_some() {
+                    return Ok(true);
+                }
+                self.pay(id, require).await?;
+                Ok(true)
+            }
+            StreamMessage::Closing(closing) => {
+                self.closing
+                    .get_or_insert_with(|| Instant::now() + CLOSE_TIMEOUT);
+                if let Some(require) = closing.require
+                    && !self.cancelled.contains(&require.require_id)
+                {
+                    self.pay(Value::Null, require).await?;
+                }
+                Ok(true)
+            }
+            StreamMessage::Closed(closed) => {
+                self.closed(closed).await;
+                Ok(false)
+            }
+            StreamMessage::Data(data) => {
+                let Some(content) = data.data else {
+                    return Err(X402StreamError::EncryptionUnsupported);
+                };
+                let content = b64
+                    .decode(content)
+                    .map_err(|e| X402StreamError::Protocol(format!("Invalid stream.data: {e}")))?;
+                if !chunk_hash(&content).eq_ignore_ascii_case(&data.hash) {
+                    return Err(X402StreamError::Integrity { seq: data.seq });
+                }
+                self.received += 1;
+                let sent = self.chunks.send(Ok(Bytes::from(content))).await.is_ok();
+                Ok(sent || self.closing.is_some())
+            }
+            StreamMessage::Close(_) => Ok(false),
+            _ => Ok(true),
+        }
+    }
+
+    /// Answers `require` with a `stream.pay`, within the limits of the stream.
+    async fn pay(&mut self, id: Value, require: StreamRequire) -> Result<(), X402StreamError> {
+        let spend = *self.spend.lock().unwrap();
+        self.client.check(&require, spend)?;
+        let requirements = require.requirements.clone();
+        self.client.payments.assert_max_amount(&requirements)?;
+        self.client
+            .payments
+            .check_stream_spend(&requirements, spend.amount)?;
+        let amount = requirements.max_amount_required;
+        let payload = self
+            .client
+            .payments
+            .make_payment_payload(requirements)
+            .await?;
+        let id = if id.is_null() {
+            json!(Uuid::new_v4().to_string())
+        } else {
+            id
+        };
+        self.payments.insert(id.to_string(), amount);
+        let pay = StreamEnvelope::new(id, StreamMessage::Pay(StreamPay::new(&require, payload)));
+        self.socket
+            .send(Message::Text(serde_json::to_string(&pay)?.into()))
+            .await?;
+        let mut spend = self.spend.lock().unwrap();
+        spend.slices += 1;
+        spend.amount = TokenAmount(spend.amount.0.saturating_add(amount.0));
+        Ok(())
+    }
+
+    /// Asks the seller to close the stream with `stream.closing`; `false` if the connection is
+    /// gone.
+    async fn start_closing(&mut self) -> bool {
+        self.closing = Some(Instant::now() + CLOSE_TIMEOUT);
+        let closing = StreamEnvelope::new(
+            Uuid::new_v4().to_string(),
+            StreamMessage::Closing(StreamClosing::new(&self.stream_id, None)),
+        );
+        let Ok(closing) = serde_json::to_string(&closing) else {
+            return true;
+        };
+        self.socket
+            .send(Message::Text(closing.into()))
+            .await
+            .is_ok()
+    }
+
+    /// Checks the final bill of the seller against the payments it accepted and the chunks
+    /// received, keeps it, and closes the connection.
+    async fn closed(&mut self, closed: StreamClosed) {
+        let mismatch =
+            if closed.slices != self.accepted.slices || closed.amount != self.accepted.amount {
+                Some(format!(
+                    "billed {} slices for {}, {} slices for {} accepted",
+                    closed.slices, closed.amount, self.accepted.slices, self.accepted.amount
+                ))
+            } else if closed.chunks != self.received {
+                Some(format!(
+                    "{} chunks billed, {} received",
+                    closed.chunks, self.received
+                ))
+            } else {
+                None
+            };
+        *self.bill.lock().unwrap() = Some(closed);
+        let _ = self.socket.close(None).await;
+        if let Some(mismatch) = mismatch {
+            let _ = self
+                .chunks
+                .send(Err(X402StreamError::BillMismatch(mismatch)))
+                .await;
+        }
+    }
+
+    /// Ends the stream with `stream.end`, and closes the connection.
+    async fn end(&mut self) {
+        let end = StreamEnvelope::new(
+            Uuid::new_v4().to_string(),
+            StreamMessage::Close(StreamClose {
+                stream_id: self.stream_id.clone(),
+                reason: None,
+            }),
+        );
+        if let Ok(end) = serde_json::to_string(&end) {
+            let _ = self.socket.send(Message::Text(end.into())).await;
+        }
+        let _ = self.socket.close(None).await;
+    }
+}
@@ -0,0 +1,48 @@
+//! WebSocket client for [x402](https://www.x402.org) facilitators.
+//!
+//! [`FacilitatorWsClient`] talks to the `/ws` endpoint of a facilitator over a single long-lived
+//! connection, per `x402-ws-stream.md`, and implements the [`Facilitator`] trait: it is a drop-in
+//! replacement for the HTTP [`FacilitatorClient`](https://docs.rs/x402-axum) or an in-process
+//! facilitator, e.g. in the `x402-axum` middleware.
+//!
+//! ```rust,no_run
+//! use x402_rs::facilitator::Facilitator;
+//! use x402_ws_client::{FacilitatorWsClient, WsClientError};
+//!
+//! # async fn run() -> Result<(), WsClientError> {
+//! let facilitator = FacilitatorWsClient::try_from("ws://localhost:8080/ws")?;
+//! let supported = facilitator.supported().await?;
+//! // Methods without a typed wrapper are reachable with `call`
+//! let hello: serde_json::Value = facilitator.call("x402.hello", &()).await?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! The client takes care of the envelope plumbing:
+//! - requests get a unique id, and replies are matched to their request by id, so any number of
+//!   requests can be in flight on the connection;
+//! - every request fails with [`WsClientError::Timeout`] if not answered in time;
+//! - the connection is opened on the first request. When it drops, the client reconnects with
+//!   exponential backoff while requests are pending, and sends the unanswered ones again with
+//!   the same envelope ids, see [`ReconnectPolicy`];
+//! - server-pushed notifications (e.g. `x402.settlement`) are broadcast to
+//!   [`FacilitatorWsClient::notifications`] receivers, and connection-level problems
+//!   (`x402.error`) to [`FacilitatorWsClient::connection_errors`] receivers, as typed
+//!   [`ConnectionError`]s;
+//! - requests turned away by an overloaded facilitator (`-32029`) are retried after the delay it
+//!   asks for, or a jittered backoff, see [`RetryPolicy`], and not sent at all while a
+//!   [`CircuitBreaker`] is open.
+//!
+//! Envelopes are JSON by default, or CBOR with [`WsCodec::Cbor`](x402_rs::ws_codec::WsCodec::Cbor),
+//! see [`FacilitatorWsClient::with_codec`], and compressed once large enough if the facilitator
+//! accepts it, see [`FacilitatorWsClient::with_compression`].
+//!
+//! The client lives in [`x402_rs::facilitator_remote`], next to its HTTP counterpart; this crate
+//! re-exports it for consumers that only need the WebSocket transport.
+
+pub use x402_rs::facilitator::Facilitator;
+pub use x402_rs::facilitator_remote::{
+    FacilitatorWsClient, ReconnectPolicy, WsClientError, WsNotification,
+};
+pub use x402_rs::retry::{CircuitBreaker, RetryPolicy};
+pub use x402_rs::types::{ConnectionError, ConnectionErrorKind};
@@ -8,8 +8,8 @@ use std::env;
 use tower_http::trace::TraceLayer;
 use tracing::instrument;
 use tracing_opentelemetry::OpenTelemetrySpanExt;
-use x402_axum::{IntoPriceTag, X402Middleware};
-use x402_rs::network::{Network, USDCDeployment};
+use x402_axum::X402Middleware;
+use x402_rs::network::Network;
 use x402_rs::telemetry::Telemetry;
 use x402_rs::types::EvmAddress;
 
@@ -41,20 +41,18 @@ async fn main() {
     let amount_polygon_amoy =
         env::var("PRICE_USDC_POLYGON_AMOY").unwrap_or_else(|_| "0.0025".to_string());
 
-    // Build price tag for Polygon Amoy
-    let price_polygon_amoy = USDCDeployment::by_network(Network::PolygonAmoy)
-        .pay_to(pay_to_polygon_amoy)
-        .amount(amount_polygon_amoy.as_str())
-        .build()
-        .unwrap();
-
     let app = Router::new()
         .route(
             "/protected-route",
             get(my_handler).layer(
                 x402.with_description("Premium API")
                     .with_mime_type("application/json")
-                    .with_price_tag(price_polygon_amoy),
+                    // Add more networks to accept the same USDC price on each of them
+                    .with_usdc_price_on(
+                        &[Network::PolygonAmoy],
+                        amount_polygon_amoy.as_str(),
+                        pay_to_polygon_amoy,
+                    ),
             ),
         )
         .layer(
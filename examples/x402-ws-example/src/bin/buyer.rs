@@ -1,15 +1,21 @@
-use alloy::signers::local::PrivateKeySigner;
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as b64;
 use dotenvy::dotenv;
 use futures_util::{SinkExt, StreamExt};
 use serde_json::json;
+use std::collections::HashSet;
 use std::env;
 use tokio_tungstenite::connect_async;
 use tracing_subscriber::EnvFilter;
 use uuid::Uuid;
 
-use x402_reqwest::chains::evm::EvmSenderWallet;
 use x402_reqwest::X402Payments;
-use x402_rs::types::PaymentRequirements;
+use x402_reqwest::stream::{X402StreamClient, X402StreamError};
+use x402_rs::network::Network;
+use x402_rs::types::stream::{StreamEnvelope, StreamInit, StreamMessage, StreamPay};
+use x402_ws_example::e2e::{EncryptionParams, KeyExchange, StreamCipher};
+use x402_ws_example::integrity::DeliveryLog;
+use x402_ws_example::wallet::evm_wallet_from_env;
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
@@ -22,24 +28,81 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         .init();
 
     let seller_ws = env::var("SELLER_WS_URL").unwrap_or_else(|_| "ws://localhost:8081/ws".into());
-    let (mut ws, _) = connect_async(seller_ws.as_str()).await?;
 
-    let evm_pk: PrivateKeySigner = env::var("EVM_PRIVATE_KEY")?.parse()?;
-    let buyer_addr = evm_pk.address();
-    let payments = X402Payments::with_wallet(EvmSenderWallet::new(evm_pk));
+    let wallet = evm_wallet_from_env().await?;
+    let buyer_addr = wallet.address();
+    let payments = X402Payments::with_wallet(wallet);
     tracing::info!(buyer_address = %buyer_addr, "Buyer ready");
 
+    // Optionally offer end-to-end encryption of stream.data frames
+    let encrypt = env::var("STREAM_ENCRYPT")
+        .map(|v| v == "true" || v == "1")
+        .unwrap_or(false);
+    if encrypt {
+        return consume_encrypted(&seller_ws, payments).await;
+    }
+
+    // X402StreamClient pays every stream.require, and checks the hash of every chunk
+    let mut client = X402StreamClient::new(payments);
+    if let Ok(max_slices) = env::var("STREAM_MAX_SLICES") {
+        client = client.max_slices(max_slices.parse()?);
+    }
+    let mut init = StreamInit::new("wss://example/stream");
+    init.network = Some(Network::PolygonAmoy);
+    let mut stream = client.connect(&seller_ws, init).await?;
+    tracing::info!(stream_id = %stream.stream_id(), "Stream accepted");
+    // Ctrl-C closes the stream cooperatively: the seller sends its final bill before the socket closes
+    let ctrl_c = tokio::signal::ctrl_c();
+    tokio::pin!(ctrl_c);
+    let mut closing = false;
+    loop {
+        let chunk = tokio::select! {
+            _ = &mut ctrl_c, if !closing => {
+                tracing::info!("Closing the stream");
+                stream.close();
+                closing = true;
+                continue;
+            }
+            chunk = stream.next() => chunk,
+        };
+        let Some(chunk) = chunk else {
+            break;
+        };
+        match chunk {
+            Ok(content) => tracing::info!(content = %String::from_utf8_lossy(&content), "Received stream.data"),
+            Err(X402StreamError::Rejected(error)) => tracing::warn!(%error, "WS error envelope from seller"),
+            Err(e) => return Err(e.into()),
+        }
+    }
+    let spent = stream.spent();
+    tracing::info!(slices = spent.slices, amount = %spent.amount, "Stream ended");
+    if let Some(bill) = stream.bill() {
+        tracing::info!(slices = bill.slices, amount = %bill.amount, chunks = bill.chunks, "Final bill of the seller matches the stream");
+    }
+    Ok(())
+}
+
+/// Consumes an end-to-end encrypted stream, which `X402StreamClient` does not support.
+async fn consume_encrypted(seller_ws: &str, payments: X402Payments) -> Result<(), Box<dyn std::error::Error>> {
+    let (mut ws, _) = connect_async(seller_ws).await?;
+    let mut key_exchange = Some(KeyExchange::new());
+    let mut cipher: Option<StreamCipher> = None;
+    // Hashes of the received chunks, checked against the seller's stream.summary
+    let mut delivered = DeliveryLog::new();
+    // Requirements cancelled by the seller, e.g. repriced, never to be paid
+    let mut cancelled = HashSet::new();
+
     // Send stream.init
-    let init = json!({
-        "id": Uuid::new_v4().to_string(),
-        "method": "stream.init",
-        "params": { "resource": "wss://example/stream", "network": "polygon-amoy" }
-    });
+    let mut init = StreamInit::new("wss://example/stream");
+    init.network = Some(Network::PolygonAmoy);
+    if let Some(key_exchange) = &key_exchange {
+        init.encryption = Some(json!(key_exchange.params()));
+    }
+    let init = StreamEnvelope::new(Uuid::new_v4().to_string(), StreamMessage::Init(init));
+    let init = serde_json::to_string(&init)?;
     tracing::info!(env = %init, "Sending stream.init");
     ws
-        .send(tokio_tungstenite::tungstenite::Message::Text(
-            init.to_string().into(),
-        ))
+        .send(tokio_tungstenite::tungstenite::Message::Text(init.into()))
         .await?;
 
     while let Some(msg) = ws.next().await {
@@ -50,48 +113,69 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
             if let Some(err) = val.get("error") {
                 tracing::warn!(error = %err, "WS error envelope from seller");
             }
-            if let Some(method) = val.get("method").and_then(|m| m.as_str()) {
-                match method {
-                    "stream.require" => {
-                        let params = val.get("params").cloned().unwrap_or_default();
-                        let stream_id = params.get("streamId").and_then(|v| v.as_str()).unwrap_or("");
-                        let slice_index = params.get("sliceIndex").and_then(|v| v.as_u64()).unwrap_or(0);
-                        let requirements_json = params.get("requirements").cloned().unwrap();
-                        let requirements: PaymentRequirements = serde_json::from_value(requirements_json.clone())?;
-
+            if val.get("method").is_some() {
+                let envelope = match serde_json::from_value::<StreamEnvelope>(val) {
+                    Ok(envelope) => envelope,
+                    Err(e) => {
+                        tracing::debug!(error = %e, "Unhandled stream message");
+                        continue;
+                    }
+                };
+                let id = envelope.id().clone();
+                match envelope.into_message() {
+                    StreamMessage::RequireCancel(cancel) => {
+                        tracing::info!(require_id = %cancel.require_id, "Seller cancelled stream.require");
+                        cancelled.insert(cancel.require_id);
+                    }
+                    StreamMessage::Require(require) => {
+                        if cancelled.contains(&require.require_id) {
+                            tracing::info!(require_id = %require.require_id, "Skipping cancelled stream.require");
+                            continue;
+                        }
                         // Build PaymentPayload using reqwest's signer logic
-                        let payload = payments.make_payment_payload(requirements).await?;
-                        tracing::info!(%stream_id, slice_index, "Sending stream.pay");
-                        let env = json!({
-                            "id": val.get("id").cloned().unwrap_or_else(|| json!(Uuid::new_v4().to_string())),
-                            "method": "stream.pay",
-                            "params": {
-                                "streamId": stream_id,
-                                "sliceIndex": slice_index,
-                                "paymentPayload": payload,
-                                "requirements": requirements_json,
-                                "verifyOnly": false,
-                            }
-                        });
+                        let payload = payments.make_payment_payload(require.requirements.clone()).await?;
+                        tracing::info!(stream_id = %require.stream_id, slice_index = require.slice_index, "Sending stream.pay");
+                        let id = if id.is_null() { json!(Uuid::new_v4().to_string()) } else { id };
+                        let env = StreamEnvelope::new(id, StreamMessage::Pay(StreamPay::new(&require, payload)));
                         ws
                             .send(tokio_tungstenite::tungstenite::Message::Text(
-                                env.to_string().into(),
+                                serde_json::to_string(&env)?.into(),
                             ))
                             .await?;
                     }
-                    _ => {}
+                    StreamMessage::Data(data) => {
+                        let content = match (&mut cipher, &data.ciphertext) {
+                            (Some(cipher), Some(ciphertext)) => cipher.open(data.seq, ciphertext)?,
+                            (Some(_), None) => {
+                                tracing::warn!(seq = data.seq, "Dropping unencrypted stream.data on an encrypted stream");
+                                continue;
+                            }
+                            (None, _) => b64.decode(data.data.as_deref().unwrap_or_default())?,
+                        };
+                        delivered.record(data.seq, &content, &data.hash)?;
+                        tracing::info!(seq = data.seq, encrypted = cipher.is_some(), content = %String::from_utf8_lossy(&content), "Received stream.data");
+                    }
+                    StreamMessage::Summary(summary) => {
+                        let merkle_root = summary.merkle_root.as_deref().unwrap_or_default();
+                        delivered.verify_summary(summary.chunks, merkle_root)?;
+                        tracing::info!(chunks = summary.chunks, %merkle_root, "Verified stream.summary");
+                    }
+                    message => tracing::debug!(method = message.method(), "Unhandled stream message"),
                 }
             } else if let Some(result) = val.get("result") {
                 // Handle "stream.accept" envelope shape from seller
-                if result.get("method").and_then(|m| m.as_str()) == Some("stream.accept") {
-                    let prepaid_until = result
-                        .get("params")
-                        .and_then(|p| p.get("prepaidUntilMs"))
-                        .and_then(|v| v.as_i64())
-                        .unwrap_or(0);
-                    let verify = result.get("params").and_then(|p| p.get("verify"));
-                    let settle = result.get("params").and_then(|p| p.get("settle"));
-                    tracing::info!(prepaid_until, verify = %verify.unwrap_or(&serde_json::Value::Null), settle = %settle.unwrap_or(&serde_json::Value::Null), "Accepted slice");
+                if let Ok(StreamMessage::Accept(accept)) = serde_json::from_value(result.clone()) {
+                    // The stream.init acceptance completes the key agreement
+                    if let Some(key_exchange) = key_exchange.take() {
+                        let Some(encryption) = accept.encryption.clone() else {
+                            return Err("Seller does not support end-to-end encryption".into());
+                        };
+                        let encryption: EncryptionParams = serde_json::from_value(encryption)?;
+                        cipher = Some(key_exchange.agree(&encryption, &accept.stream_id)?);
+                        tracing::info!(stream_id = %accept.stream_id, alg = %encryption.alg, "End-to-end encryption established");
+                    }
+                    let prepaid_until = accept.prepaid_until_ms.map(|ms| ms.0).unwrap_or(0);
+                    tracing::info!(prepaid_until, verify = ?accept.verify, settle = ?accept.settle, "Accepted slice");
                 }
             } else {
                 tracing::debug!(env = %val, "Unhandled envelope");
@@ -0,0 +1,98 @@
+//! Buyer consuming paid streams from several sellers at once, within one budget.
+
+use dotenvy::dotenv;
+use std::env;
+use std::str::FromStr;
+use std::time::Duration;
+use tracing_subscriber::EnvFilter;
+
+use x402_reqwest::X402Payments;
+use x402_reqwest::budget::TokenBucketBudget;
+use x402_rs::network::{Network, USDCDeployment};
+use x402_rs::types::MoneyAmount;
+use x402_ws_example::streams::{StreamEventKind, StreamPolicy, StreamSet, StreamTarget};
+use x402_ws_example::wallet::evm_wallet_from_env;
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let _ = dotenvy::from_filename(".env.buyer");
+    let _ = dotenvy::from_filename("examples/x402-ws-example/.env.buyer");
+    dotenv().ok();
+    tracing_subscriber::fmt()
+        .with_env_filter(EnvFilter::from_default_env())
+        .init();
+
+    let sellers = env::var("SELLER_WS_URLS").unwrap_or_else(|_| "ws://localhost:4000/ws".into());
+    let network = env::var("STREAM_NETWORK")
+        .ok()
+        .and_then(|s| serde_json::from_str::<Network>(&format!("\"{}\"", s)).ok())
+        .unwrap_or(Network::PolygonAmoy);
+    let usdc = USDCDeployment::by_network(network);
+    // Budget of all streams together, never refilled
+    let budget_usdc = env::var("STREAM_BUDGET_USDC").unwrap_or_else(|_| "1".into());
+    let budget = MoneyAmount::from_str(&budget_usdc)?.as_token_amount(usdc.decimals as u32)?;
+    let max_slices: Option<u64> = env::var("STREAM_MAX_SLICES")
+        .ok()
+        .map(|s| s.parse())
+        .transpose()?;
+
+    let wallet = evm_wallet_from_env().await?;
+    tracing::info!(buyer_address = %wallet.address(), %budget, "Buyer ready");
+    let payments = X402Payments::with_wallet(wallet).budget(TokenBucketBudget::new().with_bucket(
+        usdc.asset.clone(),
+        budget,
+        0u64,
+        Duration::ZERO,
+    ));
+
+    let mut policy = StreamPolicy::new();
+    if let Some(max_slices) = max_slices {
+        policy = policy.max_slices(max_slices);
+    }
+    let mut streams = StreamSet::new(payments);
+    for url in sellers
+        .split(',')
+        .map(str::trim)
+        .filter(|url| !url.is_empty())
+    {
+        let key = streams.open(
+            StreamTarget::new(url, "wss://example/stream", network),
+            policy.clone(),
+        );
+        tracing::info!(key = key.0, %url, "Opened stream");
+    }
+
+    while let Some(event) = streams.next_event().await {
+        let key = event.key.0;
+        match event.kind {
+            StreamEventKind::Accepted { stream_id } => {
+                tracing::info!(key, %stream_id, "Stream accepted")
+            }
+            StreamEventKind::Paid {
+                slice_index,
+                amount,
+                ..
+            } => tracing::info!(key, slice_index, %amount, "Paid slice"),
+            StreamEventKind::RequireCancelled { require_id } => {
+                tracing::info!(key, %require_id, "Seller cancelled stream.require")
+            }
+            StreamEventKind::Rejected { error } => {
+                tracing::warn!(key, %error, "Seller error")
+            }
+            StreamEventKind::Data { seq, content } => {
+                tracing::info!(key, seq, content = %String::from_utf8_lossy(&content), "Received stream.data")
+            }
+            StreamEventKind::Summary { chunks } => {
+                tracing::info!(key, chunks, "Verified stream.summary")
+            }
+            StreamEventKind::Closed { error } => match error {
+                Some(error) => tracing::warn!(key, %error, "Stream closed"),
+                None => tracing::info!(key, "Stream ended"),
+            },
+        }
+    }
+    for (asset, amount) in streams.spent() {
+        tracing::info!(asset = %asset.address, %amount, "Spent");
+    }
+    Ok(())
+}
@@ -0,0 +1,513 @@
+use axum::extract::ws::{Message, WebSocket};
+use axum::extract::{Path, Query, WebSocketUpgrade};
+use axum::http::{StatusCode, header};
+use axum::response::IntoResponse;
+use axum::routing::get;
+use axum::{Json, Router, Extension};
+use dotenvy::dotenv;
+use futures_util::StreamExt;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::env;
+use std::net::SocketAddr;
+use tracing::instrument;
+use tracing_subscriber::EnvFilter;
+use url::Url;
+use uuid::Uuid;
+
+use x402_rs::duration::{self, Seconds};
+use x402_rs::network::{Network, USDCDeployment};
+use x402_rs::stream::UNIT_RANGE;
+use x402_rs::types::stream::{StreamAccept, StreamClosed, StreamClosing, StreamData, StreamEnvelope, StreamMessage, StreamPay, StreamRequire, StreamSummary};
+use x402_rs::types::{PaymentRequirements, Scheme, TokenAmount};
+use x402_rs::ws_codec::{WsCodec, WsDeflate};
+use x402_rs::ws_error::WsError;
+use x402_ws_client::FacilitatorWsClient;
+use x402_ws_example::e2e::{EncryptionParams, KeyExchange, StreamCipher};
+use x402_ws_example::integrity::{MerkleAccumulator, chunk_hash, encode_hash};
+
+mod session;
+
+use session::{StreamSession, StreamSessions, StreamState, StreamTrigger};
+
+#[derive(Clone)]
+struct AppConfig {
+    /// Shared connection to the facilitator WS endpoint
+    facilitator: FacilitatorWsClient,
+    network: Network,
+    unit_seconds: Seconds,
+    price_usdc: String,
+    pay_to: String,
+}
+
+#[tokio::main]
+async fn main() {
+    // Load .env.seller (project root) and also example-local path, then fallback to .env
+    let _ = dotenvy::from_filename(".env.seller");
+    let _ = dotenvy::from_filename("examples/x402-ws-example/.env.seller");
+    dotenv().ok();
+    tracing_subscriber::fmt()
+        .with_env_filter(EnvFilter::from_default_env())
+        .init();
+
+    let host = env::var("WS_SELLER_HOST")
+        .unwrap_or_else(|_| "0.0.0.0".into());
+    let port: u16 = env::var("WS_SELLER_PORT")
+        .ok()
+        .and_then(|s| s.parse().ok())
+        .unwrap_or(8081);
+
+    let facilitator_ws = env::var("FACILITATOR_WS_URL")
+        .unwrap_or_else(|_| "ws://localhost:8080/ws".into());
+    let facilitator_ws = Url::parse(&facilitator_ws).expect("FACILITATOR_WS_URL invalid");
+    // FACILITATOR_WS_CBOR=true talks CBOR to the facilitator, for smaller frames
+    let facilitator_codec = match env::var("FACILITATOR_WS_CBOR").as_deref() {
+        Ok("true") | Ok("1") => WsCodec::Cbor,
+        _ => WsCodec::Json,
+    };
+    // FACILITATOR_WS_COMPRESSION=true offers to compress the frames exchanged with the facilitator
+    let facilitator_compression = matches!(
+        env::var("FACILITATOR_WS_COMPRESSION").as_deref(),
+        Ok("true") | Ok("1")
+    );
+
+    let network = env::var("STREAM_NETWORK")
+        .ok()
+        .and_then(|s| serde_json::from_str::<Network>(&format!("\"{}\"", s)).ok())
+        .unwrap_or(Network::PolygonAmoy);
+
+    // STREAM_UNIT_SECONDS takes seconds, or a duration such as `2m`
+    let unit_seconds = duration::from_env("STREAM_UNIT_SECONDS", duration::SECOND, UNIT_RANGE)
+        .expect("STREAM_UNIT_SECONDS invalid")
+        .map(|unit| Seconds::try_from(unit).expect("STREAM_UNIT_SECONDS invalid"))
+        .unwrap_or(Seconds::new(60));
+
+    let price_usdc = env::var("STREAM_PRICE_USDC").unwrap_or_else(|_| "0.05".into());
+    let pay_to = env::var("STREAM_PAY_TO")
+        .unwrap_or_else(|_| "0xBAc675C310721717Cd4A37F6cbeA1F081b1C2a07".into());
+
+    let mut facilitator = FacilitatorWsClient::new(facilitator_ws).with_codec(facilitator_codec);
+    if facilitator_compression {
+        facilitator = facilitator.with_compression(WsDeflate::new());
+    }
+
+    let config = AppConfig {
+        facilitator,
+        network,
+        unit_seconds,
+        price_usdc,
+        pay_to,
+    };
+
+    let sessions_capacity: usize = env::var("STREAM_DASHBOARD_CAPACITY")
+        .ok()
+        .and_then(|s| s.parse().ok())
+        .unwrap_or(1024);
+    let sessions = StreamSessions::new(sessions_capacity);
+
+    let app = Router::new()
+        .route("/ws", get(ws_handler))
+        .route("/dashboard/streams", get(list_streams))
+        .route("/dashboard/streams/{stream_id}", get(get_stream))
+        .layer(Extension(config))
+        .layer(Extension(sessions));
+
+    let ip: std::net::IpAddr = match host.parse() {
+        Ok(ip) => ip,
+        Err(_) => match host.as_str() {
+            "*" => std::net::IpAddr::from([0, 0, 0, 0]),
+            "localhost" => std::net::IpAddr::from([127, 0, 0, 1]),
+            _ => std::net::IpAddr::from([0, 0, 0, 0]),
+        },
+    };
+    let addr = SocketAddr::from((ip, port));
+    tracing::info!(%addr, host=%host, "WS Seller listening");
+    let listener = tokio::net::TcpListener::bind(addr).await.unwrap();
+    axum::serve(listener, app).await.unwrap();
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct EnvelopeReq {
+    id: serde_json::Value,
+    method: String,
+}
+
+#[instrument(skip_all)]
+async fn ws_handler(
+    Extension(config): Extension<AppConfig>,
+    Extension(sessions): Extension<StreamSessions>,
+    ws: WebSocketUpgrade,
+) -> axum::response::Response {
+    ws.on_upgrade(move |socket| ws_serve(socket, config, sessions))
+}
+
+/// Seller dashboard: lists known streams with their current state.
+async fn list_streams(Extension(sessions): Extension<StreamSessions>) -> impl IntoResponse {
+    let streams: Vec<_> = sessions
+        .list()
+        .await
+        .into_iter()
+        .map(|(stream_id, state, slice_index)| {
+            json!({ "streamId": stream_id, "state": state, "sliceIndex": slice_index })
+        })
+        .collect();
+    Json(json!({ "streams": streams }))
+}
+
+#[derive(Deserialize)]
+struct StreamExportQuery {
+    #[serde(default)]
+    format: Option<String>,
+}
+
+/// Seller dashboard: exports the state transitions of a stream.
+///
+/// `?format=dot` returns a Graphviz digraph, anything else a JSON timeline.
+async fn get_stream(
+    Extension(sessions): Extension<StreamSessions>,
+    Path(stream_id): Path<String>,
+    Query(query): Query<StreamExportQuery>,
+) -> axum::response::Response {
+    let Some(session) = sessions.get(&stream_id).await else {
+        return (
+            StatusCode::NOT_FOUND,
+            Json(json!({ "error": "Unknown stream" })),
+        )
+            .into_response();
+    };
+    match query.format.as_deref() {
+        Some("dot") => (
+            [(header::CONTENT_TYPE, "text/vnd.graphviz")],
+            session.to_dot(),
+        )
+            .into_response(),
+        _ => Json(session.timeline()).into_response(),
+    }
+}
+
+async fn ws_serve(mut socket: WebSocket, config: AppConfig, sessions: StreamSessions) {
+    let mut session: Option<StreamSession> = None;
+    // Set when the buyer negotiated end-to-end encryption of stream.data frames
+    let mut cipher: Option<StreamCipher> = None;
+    let mut data_seq: u64 = 0;
+    // Rolling Merkle root over the delivered chunks, reported in stream.summary
+    let mut delivered = MerkleAccumulator::new();
+    // Final bill of the stream, sent as stream.closed: slices paid, and their total
+    let mut paid_slices: u64 = 0;
+    let mut paid_amount = TokenAmount::from(0u64);
+    let mut pending_amount: Option<TokenAmount> = None;
+    // Wait for stream.init from buyer
+    while let Some(Ok(msg)) = socket.next().await {
+        match msg {
+            Message::Text(text) => {
+                tracing::debug!(raw = %text, "Buyer WS message");
+                let envelope = match serde_json::from_str::<StreamEnvelope>(&text) {
+                    Ok(envelope) => envelope,
+                    Err(e) => {
+                        // Reject malformed stream messages, ignore the other methods
+                        if let Ok(req) = serde_json::from_str::<EnvelopeReq>(&text)
+                            && StreamMessage::METHODS.contains(&req.method.as_str())
+                        {
+                            let env = json!({
+                                "id": req.id,
+                                "error": WsError::invalid_params(&e)
+                            });
+                            let _ = socket.send(Message::Text(env.to_string().into())).await;
+                        }
+                        continue;
+                    }
+                };
+                let id = envelope.id().clone();
+                match envelope.into_message() {
+                    StreamMessage::Init(init) => {
+                        // Choose USDC on configured network
+                        let usdc = USDCDeployment::by_network(config.network);
+                        // The facilitator keeps the slice accounting of the stream
+                        let requirements = slice_requirements(&config, usdc);
+                        let facilitator_init = json!({
+                            "requirements": requirements,
+                            "unitSeconds": config.unit_seconds,
+                        });
+                        let stream_id = match facilitator_call(&config, "stream.init", facilitator_init).await {
+                            Ok(session) => session.get("streamId").and_then(|v| v.as_str()).unwrap_or_default().to_string(),
+                            Err(e) => {
+                                let env = json!({
+                                    "id": id,
+                                    "error": WsError::payment_failed(e.to_string(), None)
+                                });
+                                tracing::warn!(error = %e, "Facilitator stream.init failed");
+                                let _ = socket.send(Message::Text(env.to_string().into())).await;
+                                continue;
+                            }
+                        };
+                        cipher = None;
+                        data_seq = 0;
+                        delivered = MerkleAccumulator::new();
+                        paid_slices = 0;
+                        paid_amount = TokenAmount::from(0u64);
+                        // Optional end-to-end encryption offered by the buyer
+                        let mut encryption = None;
+                        if let Some(offer) = init.encryption {
+                            let agreed = serde_json::from_value::<EncryptionParams>(offer)
+                                .map_err(|e| e.to_string())
+                                .and_then(|offer| {
+                                    let key_exchange = KeyExchange::new();
+                                    let params = key_exchange.params();
+                                    key_exchange
+                                        .agree(&offer, &stream_id)
+                                        .map(|c| (params, c))
+                                        .map_err(|e| e.to_string())
+                                });
+                            match agreed {
+                                Ok((params, c)) => {
+                                    encryption = Some(params);
+                                    cipher = Some(c);
+                                }
+                                Err(e) => {
+                                    let env = json!({
+                                        "id": id,
+                                        "error": WsError::invalid_params(format!("invalid encryption: {}", e))
+                                    });
+                                    tracing::warn!(error = %e, "Rejected stream.init encryption offer");
+                                    let _ = socket.send(Message::Text(env.to_string().into())).await;
+                                    continue;
+                                }
+                            }
+                        }
+                        let mut stream = StreamSession::new(stream_id.clone());
+                        stream.transition(
+                            StreamState::Negotiating,
+                            StreamTrigger::new("stream.init").with_envelope_id(&id),
+                        );
+                        let accept = StreamAccept {
+                            price_per_unit: Some(config.price_usdc.clone()),
+                            unit_seconds: Some(config.unit_seconds),
+                            pay_to: Some(requirements.pay_to),
+                            asset: Some(requirements.asset),
+                            network: Some(config.network),
+                            encryption: encryption.map(|encryption| json!(encryption)),
+                            ..StreamAccept::new(&stream_id)
+                        };
+                        let response = json!({
+                            "id": id,
+                            "result": StreamMessage::Accept(accept),
+                        });
+                        tracing::info!(%stream_id, unit_seconds = %config.unit_seconds, price = %config.price_usdc, asset = %usdc.address(), network = %config.network, encrypted = cipher.is_some(), "Accepted stream");
+                        let _ = socket.send(Message::Text(response.to_string().into())).await;
+
+                        // Immediately request first slice
+                        let slice_index = stream.slice_index();
+                        let require = match facilitator_call(&config, "stream.require", json!({ "streamId": stream_id })).await.and_then(|require| Ok(serde_json::from_value::<StreamRequire>(require)?)) {
+                            Ok(require) => require,
+                            Err(e) => {
+                                tracing::warn!(error = %e, "Facilitator stream.require failed");
+                                continue;
+                            }
+                        };
+                        pending_amount = Some(require.requirements.max_amount_required);
+                        let require_id = json!(Uuid::new_v4().to_string());
+                        let env = StreamEnvelope::new(require_id.clone(), StreamMessage::Require(require));
+                        tracing::info!(slice_index, "Requesting first slice");
+                        let _ = socket.send(Message::Text(json!(env).to_string().into())).await;
+                        stream.transition(
+                            StreamState::AwaitingPayment,
+                            StreamTrigger::new("stream.require").with_envelope_id(&require_id),
+                        );
+                        sessions.upsert(&stream).await;
+                        session = Some(stream);
+                    }
+                    StreamMessage::Pay(pay) => {
+                        // Forward to facilitator WS for verify (+ optional settle)
+                        let verify_only = pay.verify_only;
+
+                        let Some(stream) = session.as_mut() else {
+                            let env = json!({
+                                "id": id,
+                                "error": WsError::invalid_params("stream.pay before stream.init")
+                            });
+                            let _ = socket.send(Message::Text(env.to_string().into())).await;
+                            continue;
+                        };
+                        let slice_index = stream.slice_index();
+                        stream.transition(
+                            StreamState::Verifying,
+                            StreamTrigger::new("stream.pay").with_envelope_id(&id),
+                        );
+                        sessions.upsert(stream).await;
+
+                        tracing::info!(slice_index, verify_only, "Received stream.pay; forwarding to facilitator");
+                        let pay = StreamPay {
+                            stream_id: stream.stream_id().to_string(),
+                            ..pay
+                        };
+                        match facilitator_call(&config, "stream.pay", json!(pay)).await.and_then(|result| Ok(serde_json::from_value::<StreamAccept>(result)?)) {
+                            Ok(result) => {
+                                let paid_slice = slice_index;
+                                paid_slices += 1;
+                                if let Some(amount) = pending_amount.take() {
+                                    paid_amount = paid_amount + amount;
+                                }
+                                // The facilitator extended the prepaid window by one unit
+                                let slice_index = stream.next_slice();
+                                stream.transition(
+                                    StreamState::Streaming,
+                                    StreamTrigger::new("stream.accept").with_envelope_id(&id),
+                                );
+                                let prepaid_until_ms = result.prepaid_until_ms.map(|ms| ms.0).unwrap_or_default();
+                                let env = json!({
+                                    "id": id,
+                                    "result": StreamMessage::Accept(result),
+                                });
+                                tracing::info!(prepaid_until_ms, "Accepted payment slice");
+                                let _ = socket.send(Message::Text(env.to_string().into())).await;
+
+                                // Deliver the content of the paid slice
+                                let content = format!("Content of slice {} of stream {}", paid_slice, stream.stream_id());
+                                let data = build_data(stream.stream_id(), paid_slice, cipher.as_mut(), &mut data_seq, content.as_bytes());
+                                delivered.push(chunk_hash(content.as_bytes()));
+                                let env_data = StreamEnvelope::new(Uuid::new_v4().to_string(), StreamMessage::Data(data));
+                                let _ = socket.send(Message::Text(json!(env_data).to_string().into())).await;
+
+                                // Commit to everything delivered so far
+                                let summary = StreamSummary {
+                                    stream_id: stream.stream_id().to_string(),
+                                    slice_index: paid_slice,
+                                    chunks: delivered.len(),
+                                    merkle_root: delivered.root().map(|root| encode_hash(&root)),
+                                };
+                                let summary = StreamEnvelope::new(Uuid::new_v4().to_string(), StreamMessage::Summary(summary));
+                                let _ = socket.send(Message::Text(json!(summary).to_string().into())).await;
+
+                                // Issue next require a bit before end
+                                let next_require = match facilitator_call(&config, "stream.require", json!({ "streamId": stream.stream_id() })).await.and_then(|require| Ok(serde_json::from_value::<StreamRequire>(require)?)) {
+                                    Ok(require) => require,
+                                    Err(e) => {
+                                        tracing::warn!(error = %e, "Facilitator stream.require failed");
+                                        continue;
+                                    }
+                                };
+                                pending_amount = Some(next_require.requirements.max_amount_required);
+                                let require_id = json!(Uuid::new_v4().to_string());
+                                let env2 = StreamEnvelope::new(require_id.clone(), StreamMessage::Require(next_require));
+                                tracing::info!(slice_index, "Requesting next slice");
+                                let _ = socket.send(Message::Text(json!(env2).to_string().into())).await;
+                                stream.transition(
+                                    StreamState::AwaitingPayment,
+                                    StreamTrigger::new("stream.require").with_envelope_id(&require_id),
+                                );
+                                sessions.upsert(stream).await;
+                            }
+                            Err(e) => {
+                                let env = json!({
+                                    "id": id,
+                                    "error": WsError::payment_failed(e.to_string(), None)
+                                });
+                                tracing::warn!(error = %e, "Facilitator verify/settle failed");
+                                let _ = socket.send(Message::Text(env.to_string().into())).await;
+                                stream.transition(
+                                    StreamState::Failed,
+                                    StreamTrigger::new("facilitator.error")
+                                        .with_envelope_id(&id)
+                                        .with_detail(e.to_string()),
+                                );
+                                sessions.upsert(stream).await;
+                            }
+                        }
+                    }
+                    StreamMessage::Closing(closing) => {
+                        let Some(stream) = session.as_mut() else {
+                            break;
+                        };
+                        // Every slice is delivered once paid: nothing is left to pay, and the
+                        // pending stream.require is void
+                        let stream_id = stream.stream_id().to_string();
+                        let reply = StreamEnvelope::new(Uuid::new_v4().to_string(), StreamMessage::Closing(StreamClosing::new(&stream_id, closing.reason.clone())));
+                        let _ = socket.send(Message::Text(json!(reply).to_string().into())).await;
+                        let closed = StreamClosed {
+                            stream_id: stream_id.clone(),
+                            reason: closing.reason,
+                            slices: paid_slices,
+                            amount: paid_amount,
+                            chunks: delivered.len(),
+                        };
+                        tracing::info!(%stream_id, slices = closed.slices, amount = %closed.amount, chunks = closed.chunks, "Closing stream with its final bill");
+                        let closed = StreamEnvelope::new(Uuid::new_v4().to_string(), StreamMessage::Closed(closed));
+                        let _ = socket.send(Message::Text(json!(closed).to_string().into())).await;
+                        let _ = facilitator_call(&config, "stream.finalize", json!({ "streamId": stream_id })).await;
+                        let _ = socket.send(Message::Close(None)).await;
+                        stream.transition(StreamState::Closed, StreamTrigger::new("stream.closing").with_envelope_id(&id));
+                        sessions.upsert(stream).await;
+                        return;
+                    }
+                    _ => {}
+                }
+            }
+            Message::Close(_) => break,
+            _ => {}
+        }
+    }
+    if let Some(stream) = session.as_mut() {
+        stream.transition(StreamState::Closed, StreamTrigger::new("socket.close"));
+        sessions.upsert(stream).await;
+    }
+}
+
+/// Requirements of one slice of a stream, priced per unit.
+fn slice_requirements(config: &AppConfig, usdc: &USDCDeployment) -> PaymentRequirements {
+    PaymentRequirements {
+        scheme: Scheme::Exact,
+        network: config.network,
+        max_amount_required: {
+            use std::str::FromStr;
+            use x402_rs::types::MoneyAmount;
+            MoneyAmount::from_str(config.price_usdc.as_str())
+                .and_then(|m| m.as_token_amount(usdc.decimals as u32))
+                .expect("valid amount")
+        },
+        resource: Url::parse("wss://example/stream").unwrap(),
+        description: "Stream slice".into(),
+        mime_type: "application/octet-stream".into(),
+        output_schema: None,
+        pay_to: serde_json::from_str::<x402_rs::types::MixedAddress>(&format!("\"{}\"", config.pay_to))
+            .expect("valid pay_to"),
+        max_timeout_seconds: Seconds::new(config.unit_seconds.as_secs() + 30),
+        asset: usdc.address(),
+        extra: usdc.eip712.as_ref().map(|meta| json!({ "name": meta.name, "version": meta.version })),
+        consumed_amount: None,
+        settled_amount: None,
+    }
+}
+
+/// Params of a `stream.data` frame: `ciphertext` if the stream is end-to-end encrypted,
+/// base64 `data` otherwise, and the `hash` of the content.
+fn build_data(
+    stream_id: &str,
+    slice_index: u64,
+    cipher: Option<&mut StreamCipher>,
+    data_seq: &mut u64,
+    content: &[u8],
+) -> StreamData {
+    use base64::Engine;
+    let hash = encode_hash(&chunk_hash(content));
+    match cipher {
+        Some(cipher) => {
+            let (seq, ciphertext) = cipher.seal(content);
+            StreamData { stream_id: stream_id.to_string(), slice_index, seq, hash, data: None, ciphertext: Some(ciphertext) }
+        }
+        None => {
+            let seq = *data_seq;
+            *data_seq += 1;
+            let data = base64::engine::general_purpose::STANDARD.encode(content);
+            StreamData { stream_id: stream_id.to_string(), slice_index, seq, hash, data: Some(data), ciphertext: None }
+        }
+    }
+}
+
+/// Calls `method` on the facilitator WS endpoint, returning its result.
+async fn facilitator_call(
+    config: &AppConfig,
+    method: &str,
+    params: serde_json::Value,
+) -> anyhow::Result<serde_json::Value> {
+    Ok(config.facilitator.call(method, &params).await?)
+}
@@ -0,0 +1,257 @@
+//! Per-stream negotiation state kept by the Seller.
+//!
+//! Every [`StreamSession`] records the transitions it goes through together with the
+//! triggering message and a timestamp. The transition log can be exported as a JSON
+//! timeline or as a Graphviz DOT graph through the seller dashboard API, which makes it
+//! possible to diagnose negotiations that got stuck in production.
+
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::sync::Arc;
+use tokio::sync::RwLock;
+
+/// Negotiation state of a single stream.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum StreamState {
+    /// Buyer connected, `stream.init` not yet received.
+    Connected,
+    /// `stream.init` accepted, no slice requested yet.
+    Negotiating,
+    /// `stream.require` sent, waiting for `stream.pay`.
+    AwaitingPayment,
+    /// `stream.pay` received, waiting for the Facilitator to verify (and settle).
+    Verifying,
+    /// Slice paid, content is flowing until the prepaid window ends.
+    Streaming,
+    /// Facilitator rejected the payment or could not be reached.
+    Failed,
+    /// Connection closed by either side.
+    Closed,
+}
+
+impl StreamState {
+    fn as_str(&self) -> &'static str {
+        match self {
+            StreamState::Connected => "connected",
+            StreamState::Negotiating => "negotiating",
+            StreamState::AwaitingPayment => "awaitingPayment",
+            StreamState::Verifying => "verifying",
+            StreamState::Streaming => "streaming",
+            StreamState::Failed => "failed",
+            StreamState::Closed => "closed",
+        }
+    }
+}
+
+/// Message (or local event) that caused a state transition.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StreamTrigger {
+    /// WS method name, e.g. `stream.pay`, or a local event name, e.g. `socket.close`.
+    pub method: String,
+    /// Envelope id of the triggering message, if any.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub envelope_id: Option<serde_json::Value>,
+    /// Free-form detail, e.g. the Facilitator error message.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub detail: Option<String>,
+}
+
+impl StreamTrigger {
+    pub fn new(method: impl Into<String>) -> Self {
+        Self {
+            method: method.into(),
+            envelope_id: None,
+            detail: None,
+        }
+    }
+
+    pub fn with_envelope_id(mut self, id: &serde_json::Value) -> Self {
+        self.envelope_id = Some(id.clone());
+        self
+    }
+
+    pub fn with_detail(mut self, detail: impl Into<String>) -> Self {
+        self.detail = Some(detail.into());
+        self
+    }
+}
+
+/// A single recorded state transition.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StreamTransition {
+    pub at_ms: i64,
+    pub from: StreamState,
+    pub to: StreamState,
+    pub slice_index: u64,
+    pub trigger: StreamTrigger,
+}
+
+/// JSON timeline export of a [`StreamSession`].
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StreamTimeline {
+    pub stream_id: String,
+    pub state: StreamState,
+    pub slice_index: u64,
+    pub created_at_ms: i64,
+    pub transitions: Vec<StreamTransition>,
+}
+
+/// Server-side state of one buyer stream.
+#[derive(Debug, Clone)]
+pub struct StreamSession {
+    stream_id: String,
+    state: StreamState,
+    slice_index: u64,
+    created_at_ms: i64,
+    transitions: Vec<StreamTransition>,
+}
+
+impl StreamSession {
+    pub fn new(stream_id: impl Into<String>) -> Self {
+        Self {
+            stream_id: stream_id.into(),
+            state: StreamState::Connected,
+            slice_index: 0,
+            created_at_ms: chrono::Utc::now().timestamp_millis(),
+            transitions: Vec::new(),
+        }
+    }
+
+    pub fn stream_id(&self) -> &str {
+        &self.stream_id
+    }
+
+    pub fn state(&self) -> StreamState {
+        self.state
+    }
+
+    pub fn slice_index(&self) -> u64 {
+        self.slice_index
+    }
+
+    /// Advances to the next slice. Called once a slice has been paid for.
+    pub fn next_slice(&mut self) -> u64 {
+        self.slice_index += 1;
+        self.slice_index
+    }
+
+    /// Moves the session to `to`, recording the transition and what caused it.
+    pub fn transition(&mut self, to: StreamState, trigger: StreamTrigger) {
+        let from = self.state;
+        tracing::debug!(stream_id = %self.stream_id, from = from.as_str(), to = to.as_str(), method = %trigger.method, "Stream state transition");
+        self.transitions.push(StreamTransition {
+            at_ms: chrono::Utc::now().timestamp_millis(),
+            from,
+            to,
+            slice_index: self.slice_index,
+            trigger,
+        });
+        self.state = to;
+    }
+
+    /// Exports the recorded transitions as a JSON timeline.
+    pub fn timeline(&self) -> StreamTimeline {
+        StreamTimeline {
+            stream_id: self.stream_id.clone(),
+            state: self.state,
+            slice_index: self.slice_index,
+            created_at_ms: self.created_at_ms,
+            transitions: self.transitions.clone(),
+        }
+    }
+
+    /// Exports the recorded transitions as a Graphviz DOT digraph.
+    ///
+    /// Edges are labelled with their sequence number, triggering method and the time
+    /// elapsed since the session was created, so the path through the state machine can be
+    /// read top to bottom. The current state is highlighted.
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::new();
+        let _ = writeln!(dot, "digraph \"{}\" {{", escape_dot(&self.stream_id));
+        let _ = writeln!(dot, "  rankdir=LR;");
+        let _ = writeln!(
+            dot,
+            "  \"{}\" [style=filled, fillcolor=lightgrey];",
+            self.state.as_str()
+        );
+        for (seq, transition) in self.transitions.iter().enumerate() {
+            let mut label = format!(
+                "#{} {} +{}ms",
+                seq + 1,
+                transition.trigger.method,
+                transition.at_ms - self.created_at_ms
+            );
+            if let Some(detail) = &transition.trigger.detail {
+                label.push_str("\\n");
+                label.push_str(detail);
+            }
+            let _ = writeln!(
+                dot,
+                "  \"{}\" -> \"{}\" [label=\"{}\"];",
+                transition.from.as_str(),
+                transition.to.as_str(),
+                escape_dot(&label)
+            );
+        }
+        dot.push_str("}\n");
+        dot
+    }
+}
+
+/// Escapes `value` for a quoted DOT string: backslashes first, so that the backslashes escaping
+/// quotes are not escaped again.
+fn escape_dot(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+/// Sessions known to the Seller, keyed by stream id.
+///
+/// Closed and failed sessions are kept so their history stays inspectable; the oldest ones
+/// are evicted once `capacity` is reached.
+#[derive(Clone)]
+pub struct StreamSessions {
+    inner: Arc<RwLock<HashMap<String, StreamSession>>>,
+    capacity: usize,
+}
+
+impl StreamSessions {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            inner: Arc::new(RwLock::new(HashMap::new())),
+            capacity,
+        }
+    }
+
+    /// Stores the current snapshot of `session`.
+    pub async fn upsert(&self, session: &StreamSession) {
+        let mut sessions = self.inner.write().await;
+        if !sessions.contains_key(session.stream_id()) && sessions.len() >= self.capacity {
+            let oldest = sessions
+                .values()
+                .min_by_key(|s| s.created_at_ms)
+                .map(|s| s.stream_id.clone());
+            if let Some(oldest) = oldest {
+                sessions.remove(&oldest);
+            }
+        }
+        sessions.insert(session.stream_id.clone(), session.clone());
+    }
+
+    pub async fn get(&self, stream_id: &str) -> Option<StreamSession> {
+        self.inner.read().await.get(stream_id).cloned()
+    }
+
+    pub async fn list(&self) -> Vec<(String, StreamState, u64)> {
+        self.inner
+            .read()
+            .await
+            .values()
+            .map(|s| (s.stream_id.clone(), s.state(), s.slice_index))
+            .collect()
+    }
+}
@@ -0,0 +1,115 @@
+//! Buyer of the market data feed of `sse-seller`: pays per unit over WebSocket with
+//! `X402StreamClient`, and reads the feed as Server-Sent Events.
+//!
+//! The `meter` events of the Seller are checked against what the Buyer received. Ctrl-C closes
+//! the stream cooperatively, with the final bill of the Seller.
+
+use dotenvy::dotenv;
+use futures_util::StreamExt;
+use std::env;
+use tracing_subscriber::EnvFilter;
+
+use x402_reqwest::X402Payments;
+use x402_reqwest::stream::{X402StreamClient, X402StreamError};
+use x402_rs::network::Network;
+use x402_rs::types::stream::StreamInit;
+use x402_ws_example::sse::{FeedMeter, METER_EVENT, MeterReport, QUOTE_EVENT, Quote, SseParser};
+use x402_ws_example::wallet::evm_wallet_from_env;
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    // Load .env.buyer (project root) and also example-local path, then fallback to .env
+    let _ = dotenvy::from_filename(".env.buyer");
+    let _ = dotenvy::from_filename("examples/x402-ws-example/.env.buyer");
+    dotenv().ok();
+    tracing_subscriber::fmt()
+        .with_env_filter(EnvFilter::from_default_env())
+        .init();
+
+    let seller_ws = env::var("SELLER_WS_URL").unwrap_or_else(|_| "ws://localhost:8082/ws".into());
+    let seller_feed = env::var("SELLER_FEED_URL").unwrap_or_else(|_| "http://localhost:8082/feed".into());
+
+    let wallet = evm_wallet_from_env().await?;
+    tracing::info!(buyer_address = %wallet.address(), "Buyer ready");
+    let mut client = X402StreamClient::new(X402Payments::with_wallet(wallet));
+    if let Ok(max_slices) = env::var("STREAM_MAX_SLICES") {
+        client = client.max_slices(max_slices.parse()?);
+    }
+
+    // The WS only carries the payments: X402StreamClient pays every stream.require on its own
+    let mut init = StreamInit::new("https://example/feed");
+    init.network = Some(Network::PolygonAmoy);
+    let mut paid = client.connect(&seller_ws, init).await?;
+    let accept = paid.accept();
+    tracing::info!(stream_id = %paid.stream_id(), price_per_unit = ?accept.price_per_unit, unit_seconds = ?accept.unit_seconds, "Stream accepted");
+
+    let response = reqwest::get(format!("{}/{}", seller_feed.trim_end_matches('/'), paid.stream_id()))
+        .await?
+        .error_for_status()?;
+    let mut feed = response.bytes_stream();
+    let mut parser = SseParser::new();
+    let mut meter = FeedMeter::new();
+    let ctrl_c = tokio::signal::ctrl_c();
+    tokio::pin!(ctrl_c);
+    let mut closing = false;
+    let mut feed_ended = false;
+    loop {
+        tokio::select! {
+            _ = &mut ctrl_c, if !closing => {
+                tracing::info!("Closing the stream");
+                paid.close();
+                closing = true;
+            }
+            chunk = paid.next() => match chunk {
+                // Content is delivered over SSE, not as stream.data
+                Some(Ok(_)) => {}
+                Some(Err(X402StreamError::Rejected(error))) => tracing::warn!(%error, "WS error envelope from seller"),
+                Some(Err(e)) => return Err(e.into()),
+                None => {
+                    tracing::info!("Paid stream ended");
+                    break;
+                }
+            },
+            chunk = feed.next(), if !feed_ended => {
+                // The paid stream ends after the feed, once the seller sent its final bill
+                let Some(chunk) = chunk else {
+                    tracing::info!("Feed ended");
+                    feed_ended = true;
+                    continue;
+                };
+                for event in parser.push(&chunk?) {
+                    match event.event.as_deref() {
+                        Some(QUOTE_EVENT) => {
+                            meter.record(&event.data);
+                            let quote: Quote = serde_json::from_str(&event.data)?;
+                            tracing::info!(symbol = %quote.symbol, price = quote.price, "Quote");
+                        }
+                        Some(METER_EVENT) => {
+                            let report: MeterReport = serde_json::from_str(&event.data)?;
+                            if meter.matches(&report) {
+                                tracing::info!(slices = report.slices, events = report.events, bytes = report.bytes, prepaid_until_ms = ?report.prepaid_until_ms, "Slice paid, feed metered");
+                            } else {
+                                tracing::warn!(reported_events = report.events, reported_bytes = report.bytes, received_events = meter.events(), received_bytes = meter.bytes(), "Feed metered by the seller differs from the feed received");
+                            }
+                        }
+                        _ => tracing::debug!(event = ?event.event, "Unhandled SSE event"),
+                    }
+                }
+            }
+        }
+    }
+    let spent = paid.spent();
+    tracing::info!(
+        slices = spent.slices,
+        amount = %spent.amount,
+        events = meter.events(),
+        bytes = meter.bytes(),
+        elapsed = ?meter.elapsed(),
+        bytes_per_second = meter.bytes_per_second(),
+        "Stream ended"
+    );
+    if let Some(bill) = paid.bill() {
+        tracing::info!(slices = bill.slices, amount = %bill.amount, "Final bill of the seller matches the stream");
+    }
+    Ok(())
+}
@@ -0,0 +1,233 @@
+//! Seller of a market data feed over Server-Sent Events, paid per minute over WebSocket.
+//!
+//! The Buyer opens a paid stream on `/ws` with `stream.init`, and pays its slices there, see
+//! `x402-axum-ws`. The feed of the stream is served at `GET /feed/{stream_id}` as
+//! `text/event-stream`: `quote` events while the prepaid window is open, paused while it is
+//! lapsed, and a `meter` event with what was delivered every time a slice is paid. The feed ends
+//! with the paid stream.
+
+use axum::extract::Path;
+use axum::http::StatusCode;
+use axum::response::sse::{Event, KeepAlive, Sse};
+use axum::response::{IntoResponse, Response};
+use axum::routing::get;
+use axum::{Extension, Json, Router};
+use dotenvy::dotenv;
+use rand::Rng;
+use serde_json::json;
+use std::collections::HashMap;
+use std::env;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tracing_subscriber::EnvFilter;
+use url::Url;
+
+use x402_axum_ws::{FacilitatorWsClient, LapsePolicy, PaidStream, PaidWebSocketUpgrade, X402WsMiddleware};
+use x402_rs::duration::{self, Seconds};
+use x402_rs::network::{Network, USDCDeployment};
+use x402_rs::stream::UNIT_RANGE;
+use x402_rs::types::{MixedAddress, MoneyAmount, PaymentRequirements, Scheme};
+use x402_ws_example::sse::{FeedMeter, METER_EVENT, MeterReport, QUOTE_EVENT, Quote};
+
+/// Paid streams opened on `/ws`, whose feeds are served at `/feed/{stream_id}`.
+#[derive(Clone)]
+struct Feeds {
+    streams: Arc<Mutex<HashMap<String, PaidStream>>>,
+    /// Interval between two quotes.
+    tick: Duration,
+}
+
+impl Feeds {
+    fn new(tick: Duration) -> Self {
+        Self {
+            streams: Arc::new(Mutex::new(HashMap::new())),
+            tick,
+        }
+    }
+
+    fn insert(&self, stream: PaidStream) {
+        let mut streams = self.streams.lock().unwrap();
+        streams.retain(|_, stream| !stream.is_closed());
+        streams.insert(stream.stream_id().to_string(), stream);
+    }
+
+    fn get(&self, stream_id: &str) -> Option<PaidStream> {
+        self.streams
+            .lock()
+            .unwrap()
+            .get(stream_id)
+            .filter(|stream| !stream.is_closed())
+            .cloned()
+    }
+}
+
+#[tokio::main]
+async fn main() {
+    // Load .env.seller (project root) and also example-local path, then fallback to .env
+    let _ = dotenvy::from_filename(".env.seller");
+    let _ = dotenvy::from_filename("examples/x402-ws-example/.env.seller");
+    dotenv().ok();
+    tracing_subscriber::fmt()
+        .with_env_filter(EnvFilter::from_default_env())
+        .init();
+
+    let host = env::var("SSE_SELLER_HOST").unwrap_or_else(|_| "0.0.0.0".into());
+    let port: u16 = env::var("SSE_SELLER_PORT")
+        .ok()
+        .and_then(|s| s.parse().ok())
+        .unwrap_or(8082);
+
+    let facilitator_ws = env::var("FACILITATOR_WS_URL")
+        .unwrap_or_else(|_| "ws://localhost:8080/ws".into());
+    let facilitator = FacilitatorWsClient::try_from(facilitator_ws.as_str())
+        .expect("FACILITATOR_WS_URL invalid");
+
+    let network = env::var("STREAM_NETWORK")
+        .ok()
+        .and_then(|s| serde_json::from_str::<Network>(&format!("\"{}\"", s)).ok())
+        .unwrap_or(Network::PolygonAmoy);
+    // STREAM_UNIT_SECONDS takes seconds, or a duration such as `2m`
+    let unit_seconds = duration::from_env("STREAM_UNIT_SECONDS", duration::SECOND, UNIT_RANGE)
+        .expect("STREAM_UNIT_SECONDS invalid")
+        .map(|unit| Seconds::try_from(unit).expect("STREAM_UNIT_SECONDS invalid"))
+        .unwrap_or(Seconds::new(60));
+    let price_usdc = env::var("STREAM_PRICE_USDC").unwrap_or_else(|_| "0.05".into());
+    let pay_to = env::var("STREAM_PAY_TO")
+        .unwrap_or_else(|_| "0xBAc675C310721717Cd4A37F6cbeA1F081b1C2a07".into());
+    // SSE_TICK_MS sets the interval between two quotes
+    let tick = env::var("SSE_TICK_MS")
+        .ok()
+        .and_then(|s| s.parse().ok())
+        .map(Duration::from_millis)
+        .unwrap_or(Duration::from_secs(1));
+
+    let requirements = slice_requirements(network, unit_seconds, &price_usdc, &pay_to);
+    // Quotes are worthless once stale: drop those of lapsed windows rather than replaying them
+    let x402 = X402WsMiddleware::new(facilitator, requirements, unit_seconds)
+        .with_price_per_unit(price_usdc.clone())
+        .with_lapse_policy(LapsePolicy::Drop);
+
+    let feeds = Feeds::new(tick);
+    let app = Router::new()
+        .route(
+            "/ws",
+            get(|Extension(feeds): Extension<Feeds>, upgrade: PaidWebSocketUpgrade| async move {
+                // The payments are carried by the WS, the content by the SSE feed
+                upgrade.on_paid_stream(move |stream| async move {
+                    tracing::info!(stream_id = %stream.stream_id(), "Opened paid stream");
+                    feeds.insert(stream);
+                })
+            })
+            .layer(x402),
+        )
+        .route("/feed/{stream_id}", get(feed))
+        .layer(Extension(feeds));
+
+    let ip: std::net::IpAddr = host.parse().unwrap_or(std::net::IpAddr::from([0, 0, 0, 0]));
+    let addr = SocketAddr::from((ip, port));
+    tracing::info!(%addr, unit_seconds = %unit_seconds, price = %price_usdc, %network, "SSE Seller listening");
+    let listener = tokio::net::TcpListener::bind(addr).await.unwrap();
+    axum::serve(listener, app).await.unwrap();
+}
+
+/// State of the feed of one paid stream.
+struct Feed {
+    stream: PaidStream,
+    meter: FeedMeter,
+    /// Slices paid when the last `meter` event was sent.
+    slices: u64,
+    price: f64,
+    interval: tokio::time::Interval,
+}
+
+/// `GET /feed/{stream_id}`: the market data feed of a paid stream, as Server-Sent Events.
+async fn feed(
+    Extension(feeds): Extension<Feeds>,
+    Path(stream_id): Path<String>,
+) -> Response {
+    let Some(stream) = feeds.get(&stream_id) else {
+        return (
+            StatusCode::NOT_FOUND,
+            Json(json!({ "error": "Unknown stream, open it on /ws first" })),
+        )
+            .into_response();
+    };
+    tracing::info!(%stream_id, "Feed opened");
+    let feed = Feed {
+        stream,
+        meter: FeedMeter::new(),
+        slices: 0,
+        price: 2500.0,
+        interval: tokio::time::interval(feeds.tick),
+    };
+    let events = futures_util::stream::unfold(feed, |mut feed| async move {
+        // Paused until the next payment while the prepaid window is lapsed
+        if feed.stream.paid().await.is_err() {
+            tracing::info!(
+                stream_id = %feed.stream.stream_id(),
+                events = feed.meter.events(),
+                bytes = feed.meter.bytes(),
+                elapsed = ?feed.meter.elapsed(),
+                bytes_per_second = feed.meter.bytes_per_second(),
+                "Feed ended with the paid stream"
+            );
+            return None;
+        }
+        let window = feed.stream.window();
+        if window.slices != feed.slices {
+            feed.slices = window.slices;
+            let report = MeterReport {
+                slices: window.slices,
+                events: feed.meter.events(),
+                bytes: feed.meter.bytes(),
+                prepaid_until_ms: window.prepaid_until.map(|until| until.0),
+            };
+            tracing::info!(stream_id = %feed.stream.stream_id(), slices = report.slices, events = report.events, bytes = report.bytes, "Slice paid");
+            let event = Event::default().event(METER_EVENT).json_data(&report);
+            return Some((event, feed));
+        }
+        feed.interval.tick().await;
+        feed.price *= 1.0 + rand::thread_rng().gen_range(-0.001..0.001);
+        let quote = Quote {
+            symbol: "ETH-USD".into(),
+            price: (feed.price * 100.0).round() / 100.0,
+            at_ms: chrono::Utc::now().timestamp_millis(),
+        };
+        let data = serde_json::to_string(&quote).expect("quotes serialize");
+        feed.meter.record(&data);
+        Some((Ok(Event::default().event(QUOTE_EVENT).data(data)), feed))
+    });
+    Sse::new(events)
+        .keep_alive(KeepAlive::default())
+        .into_response()
+}
+
+/// Requirements of one slice of the feed, priced per unit.
+fn slice_requirements(
+    network: Network,
+    unit_seconds: Seconds,
+    price_usdc: &str,
+    pay_to: &str,
+) -> PaymentRequirements {
+    use std::str::FromStr;
+    let usdc = USDCDeployment::by_network(network);
+    PaymentRequirements {
+        scheme: Scheme::Exact,
+        network,
+        max_amount_required: MoneyAmount::from_str(price_usdc)
+            .and_then(|m| m.as_token_amount(usdc.decimals as u32))
+            .expect("STREAM_PRICE_USDC invalid"),
+        resource: Url::parse("https://example/feed").unwrap(),
+        description: "Market data feed, per unit".into(),
+        mime_type: "text/event-stream".into(),
+        output_schema: None,
+        pay_to: serde_json::from_str::<MixedAddress>(&format!("\"{}\"", pay_to))
+            .expect("STREAM_PAY_TO invalid"),
+        max_timeout_seconds: Seconds::new(unit_seconds.as_secs() + 30),
+        asset: usdc.address(),
+        extra: usdc.eip712.as_ref().map(|meta| json!({ "name": meta.name, "version": meta.version })),
+        consumed_amount: None,
+        settled_amount: None,
+    }
+}
@@ -0,0 +1,205 @@
+//! Helper commands for running the WS example end-to-end.
+//!
+//! ```text
+//! x402 keygen [--network <network>] [--out-dir <dir>] [--faucet] [--force]
+//! ```
+//!
+//! `keygen` creates a buyer and a seller keypair, and writes `.env.buyer` and `.env.seller`
+//! ready for the `ws-buyer` and `ws-seller` binaries: the buyer signs payments with its key, and
+//! the seller is paid to its address. Existing files are kept unless `--force` is given.
+//!
+//! With `--faucet`, the buyer address is funded on testnets through the faucet API configured
+//! for the network:
+//! - `FAUCET_URL_<NETWORK>` – e.g. `FAUCET_URL_BASE_SEPOLIA`; receives a JSON POST
+//!   `{ "address": "0x…", "network": "base-sepolia" }`
+//! - `FAUCET_API_KEY` – sent as `Authorization: Bearer <key>`, if set
+
+use alloy::signers::local::PrivateKeySigner;
+use anyhow::{Context, anyhow, bail};
+use dotenvy::dotenv;
+use serde_json::json;
+use std::env;
+use std::path::{Path, PathBuf};
+
+use x402_rs::network::{Network, NetworkFamily};
+
+const ENV_FAUCET_API_KEY: &str = "FAUCET_API_KEY";
+
+const USAGE: &str =
+    "Usage: x402 keygen [--network <network>] [--out-dir <dir>] [--faucet] [--force]
+
+Creates buyer and seller keypairs, and writes .env.buyer and .env.seller.
+
+Options:
+  --network <network>  Network the stream is paid on (default: base-sepolia)
+  --out-dir <dir>      Directory to write the files to (default: current directory)
+  --faucet             Requests testnet funds for the buyer from FAUCET_URL_<NETWORK>
+  --force              Overwrites existing files";
+
+struct KeygenArgs {
+    network: Network,
+    out_dir: PathBuf,
+    faucet: bool,
+    force: bool,
+}
+
+impl KeygenArgs {
+    fn parse(mut args: impl Iterator<Item = String>) -> anyhow::Result<Self> {
+        let mut parsed = KeygenArgs {
+            network: Network::BaseSepolia,
+            out_dir: PathBuf::from("."),
+            faucet: false,
+            force: false,
+        };
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--network" => {
+                    let name = args.next().context("--network requires a value")?;
+                    parsed.network = serde_json::from_value(json!(name))
+                        .map_err(|_| anyhow!("Unknown network: {name}"))?;
+                }
+                "--out-dir" => {
+                    parsed.out_dir = args.next().context("--out-dir requires a value")?.into();
+                }
+                "--faucet" => parsed.faucet = true,
+                "--force" => parsed.force = true,
+                other => bail!("Unknown option: {other}\n\n{USAGE}"),
+            }
+        }
+        if !matches!(NetworkFamily::from(parsed.network), NetworkFamily::Evm) {
+            bail!(
+                "The WS example pays on EVM networks only, not {}",
+                parsed.network
+            );
+        }
+        Ok(parsed)
+    }
+}
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    dotenv().ok();
+    let mut args = env::args().skip(1);
+    match args.next().as_deref() {
+        Some("keygen") => keygen(KeygenArgs::parse(args)?).await,
+        Some("--help") | Some("-h") | None => {
+            println!("{USAGE}");
+            Ok(())
+        }
+        Some(other) => bail!("Unknown command: {other}\n\n{USAGE}"),
+    }
+}
+
+async fn keygen(args: KeygenArgs) -> anyhow::Result<()> {
+    let buyer_path = args.out_dir.join(".env.buyer");
+    let seller_path = args.out_dir.join(".env.seller");
+    if !args.force {
+        for path in [&buyer_path, &seller_path] {
+            if path.exists() {
+                bail!(
+                    "{} already exists, pass --force to overwrite it",
+                    path.display()
+                );
+            }
+        }
+    }
+    let faucet_url = args
+        .faucet
+        .then(|| faucet_url(args.network))
+        .transpose()?;
+
+    let buyer = PrivateKeySigner::random();
+    let seller = PrivateKeySigner::random();
+    let buyer_env = format!(
+        "SELLER_WS_URL=ws://localhost:8081/ws\n\
+         # Buyer address: {buyer_address}\n\
+         EVM_PRIVATE_KEY=0x{buyer_key}\n\
+         STREAM_ENCRYPT=false\n",
+        buyer_address = buyer.address(),
+        buyer_key = alloy::hex::encode(buyer.to_bytes()),
+    );
+    let seller_env = format!(
+        "WS_SELLER_HOST=0.0.0.0\n\
+         WS_SELLER_PORT=8081\n\
+         FACILITATOR_WS_URL=ws://localhost:8080/ws\n\
+         STREAM_NETWORK={network}\n\
+         STREAM_UNIT_SECONDS=60\n\
+         STREAM_PRICE_USDC=0.05\n\
+         STREAM_PAY_TO={seller_address}\n\
+         # Private key of STREAM_PAY_TO, to spend what the seller earns: 0x{seller_key}\n\
+         STREAM_DASHBOARD_CAPACITY=1024\n",
+        network = args.network,
+        seller_address = seller.address(),
+        seller_key = alloy::hex::encode(seller.to_bytes()),
+    );
+    write_env(&buyer_path, &buyer_env)?;
+    write_env(&seller_path, &seller_env)?;
+    println!("Buyer:  {} ({})", buyer.address(), buyer_path.display());
+    println!("Seller: {} ({})", seller.address(), seller_path.display());
+
+    if let Some(faucet_url) = faucet_url {
+        request_funds(&faucet_url, args.network, &buyer.address().to_string()).await?;
+    } else {
+        println!(
+            "Fund the buyer with USDC on {} before running the example, e.g. with --faucet",
+            args.network
+        );
+    }
+    Ok(())
+}
+
+/// Writes an env file readable by its owner only: it holds a private key.
+fn write_env(path: &Path, contents: &str) -> anyhow::Result<()> {
+    std::fs::write(path, contents).with_context(|| format!("Can not write {}", path.display()))?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o600))?;
+    }
+    Ok(())
+}
+
+fn is_testnet(network: Network) -> bool {
+    matches!(
+        network,
+        Network::BaseSepolia
+            | Network::AvalancheFuji
+            | Network::SolanaDevnet
+            | Network::PolygonAmoy
+            | Network::SeiTestnet
+    )
+}
+
+/// URL of the faucet API configured for `network`, from `FAUCET_URL_<NETWORK>`.
+fn faucet_url(network: Network) -> anyhow::Result<String> {
+    if !is_testnet(network) {
+        bail!("No faucet on {network}: faucets only fund testnet addresses");
+    }
+    let env_faucet_url = format!(
+        "FAUCET_URL_{}",
+        network.to_string().to_uppercase().replace('-', "_")
+    );
+    env::var(&env_faucet_url)
+        .with_context(|| format!("Set {env_faucet_url} to request funds on {network}"))
+}
+
+/// Asks the faucet at `url` to fund `address` on `network`.
+async fn request_funds(url: &str, network: Network, address: &str) -> anyhow::Result<()> {
+    let mut request = reqwest::Client::new()
+        .post(url)
+        .json(&json!({ "address": address, "network": network }));
+    if let Ok(api_key) = env::var(ENV_FAUCET_API_KEY) {
+        request = request.bearer_auth(api_key);
+    }
+    let response = request
+        .send()
+        .await
+        .with_context(|| format!("Can not reach the faucet at {url}"))?;
+    let status = response.status();
+    let body = response.text().await.unwrap_or_default();
+    if !status.is_success() {
+        bail!("Faucet at {url} answered {status}: {body}");
+    }
+    println!("Faucet funded {address} on {network}: {body}");
+    Ok(())
+}
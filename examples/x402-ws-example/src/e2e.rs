@@ -0,0 +1,182 @@
+//! Optional end-to-end encryption of `stream.data` frames.
+//!
+//! Buyer and Seller are often connected through relays (WS gateways, load balancers, CDNs)
+//! that terminate TLS and can therefore read the paid content. To keep it confidential, the
+//! Buyer offers an ephemeral X25519 public key in `stream.init`, and the Seller answers with its
+//! own ephemeral key in `stream.accept`:
+//!
+//! ```json
+//! { "encryption": { "alg": "x25519-chacha20poly1305", "publicKey": "<base64>" } }
+//! ```
+//!
+//! Both sides derive a per-stream key from the shared secret with HKDF-SHA256, salted with the
+//! `streamId`. Every `stream.data` frame is then sealed with ChaCha20-Poly1305, using its
+//! sequence number as nonce and `streamId` plus sequence number as associated data. A relay can
+//! neither read, alter, replay nor reorder frames.
+//!
+//! Keys are generated per stream and never persisted. The key exchange itself is not
+//! authenticated: it protects against relays that observe the traffic, not against relays that
+//! rewrite `stream.init`/`stream.accept`.
+
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as b64;
+use chacha20poly1305::aead::{Aead, KeyInit, Payload};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use hkdf::Hkdf;
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use x25519_dalek::{EphemeralSecret, PublicKey};
+
+/// Identifier of the only supported algorithm suite, sent as `encryption.alg`.
+pub const ALGORITHM: &str = "x25519-chacha20poly1305";
+
+/// HKDF `info` binding the derived key to its purpose.
+const KEY_INFO: &[u8] = b"x402-ws-stream stream.data";
+
+/// `encryption` parameter of `stream.init` and `stream.accept`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EncryptionParams {
+    pub alg: String,
+    /// Base64-encoded X25519 public key of the sender.
+    pub public_key: String,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum E2eError {
+    #[error("Unsupported encryption algorithm: {0}")]
+    UnsupportedAlgorithm(String),
+    #[error("Invalid X25519 public key")]
+    InvalidPublicKey,
+    #[error("Invalid base64 ciphertext")]
+    InvalidEncoding,
+    #[error("Frame {0} is out of order, expected at least {1}")]
+    OutOfOrder(u64, u64),
+    #[error("Frame {0} failed authentication")]
+    Authentication(u64),
+}
+
+/// One side of the key agreement: an ephemeral X25519 key pair.
+pub struct KeyExchange {
+    secret: EphemeralSecret,
+    public: PublicKey,
+}
+
+impl KeyExchange {
+    /// Generates a fresh ephemeral key pair.
+    pub fn new() -> Self {
+        let secret = EphemeralSecret::random_from_rng(rand::rngs::OsRng);
+        let public = PublicKey::from(&secret);
+        Self { secret, public }
+    }
+
+    /// The `encryption` parameter announcing this side's public key.
+    pub fn params(&self) -> EncryptionParams {
+        EncryptionParams {
+            alg: ALGORITHM.to_string(),
+            public_key: b64.encode(self.public.as_bytes()),
+        }
+    }
+
+    /// Completes the key agreement with the peer's `encryption` parameter, and derives the
+    /// cipher for the `stream.data` frames of `stream_id`.
+    pub fn agree(self, peer: &EncryptionParams, stream_id: &str) -> Result<StreamCipher, E2eError> {
+        if peer.alg != ALGORITHM {
+            return Err(E2eError::UnsupportedAlgorithm(peer.alg.clone()));
+        }
+        let peer_public: [u8; 32] = b64
+            .decode(&peer.public_key)
+            .ok()
+            .and_then(|bytes| bytes.try_into().ok())
+            .ok_or(E2eError::InvalidPublicKey)?;
+        let shared = self.secret.diffie_hellman(&PublicKey::from(peer_public));
+        // Low-order points yield a predictable shared secret.
+        if !shared.was_contributory() {
+            return Err(E2eError::InvalidPublicKey);
+        }
+        let mut key = [0u8; 32];
+        Hkdf::<Sha256>::new(Some(stream_id.as_bytes()), shared.as_bytes())
+            .expand(KEY_INFO, &mut key)
+            .expect("32 bytes is a valid HKDF-SHA256 output length");
+        Ok(StreamCipher {
+            cipher: ChaCha20Poly1305::new(Key::from_slice(&key)),
+            stream_id: stream_id.to_string(),
+            next_seq: 0,
+        })
+    }
+}
+
+impl Default for KeyExchange {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Seals (Seller) or opens (Buyer) the `stream.data` frames of a single stream.
+///
+/// Content only flows from Seller to Buyer, so a single key is used, and sequence numbers are
+/// never reused for sealing.
+pub struct StreamCipher {
+    cipher: ChaCha20Poly1305,
+    stream_id: String,
+    /// Next sequence number to seal, or lowest sequence number accepted when opening.
+    next_seq: u64,
+}
+
+impl StreamCipher {
+    /// Encrypts the content of the next frame. Returns its sequence number and the
+    /// base64-encoded ciphertext.
+    pub fn seal(&mut self, plaintext: &[u8]) -> (u64, String) {
+        let seq = self.next_seq;
+        self.next_seq += 1;
+        let aad = self.associated_data(seq);
+        let ciphertext = self
+            .cipher
+            .encrypt(
+                &nonce(seq),
+                Payload {
+                    msg: plaintext,
+                    aad: &aad,
+                },
+            )
+            .expect("ChaCha20-Poly1305 encryption is infallible for in-memory buffers");
+        (seq, b64.encode(ciphertext))
+    }
+
+    /// Decrypts and authenticates frame `seq`. Frames must arrive in increasing order;
+    /// gaps are tolerated, replays are not.
+    pub fn open(&mut self, seq: u64, ciphertext: &str) -> Result<Vec<u8>, E2eError> {
+        if seq < self.next_seq {
+            return Err(E2eError::OutOfOrder(seq, self.next_seq));
+        }
+        let ciphertext = b64
+            .decode(ciphertext)
+            .map_err(|_| E2eError::InvalidEncoding)?;
+        let aad = self.associated_data(seq);
+        let plaintext = self
+            .cipher
+            .decrypt(
+                &nonce(seq),
+                Payload {
+                    msg: &ciphertext,
+                    aad: &aad,
+                },
+            )
+            .map_err(|_| E2eError::Authentication(seq))?;
+        self.next_seq = seq + 1;
+        Ok(plaintext)
+    }
+
+    fn associated_data(&self, seq: u64) -> Vec<u8> {
+        let mut aad = self.stream_id.as_bytes().to_vec();
+        aad.extend_from_slice(&seq.to_be_bytes());
+        aad
+    }
+}
+
+/// 96-bit nonce: four zero bytes followed by the big-endian sequence number.
+fn nonce(seq: u64) -> Nonce {
+    let mut nonce = [0u8; 12];
+    nonce[4..].copy_from_slice(&seq.to_be_bytes());
+    Nonce::from(nonce)
+}
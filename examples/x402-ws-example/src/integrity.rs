@@ -0,0 +1,232 @@
+//! Content integrity of delivered `stream.data` chunks.
+//!
+//! Every `stream.data` frame carries the `hash` of its chunk, and after every paid slice the
+//! Seller sends a `stream.summary` with the number of chunks delivered so far and the Merkle
+//! root over their hashes. The Buyer recomputes both with a [`DeliveryLog`]: a matching root
+//! commits the Seller to exactly the content received, and [`DeliveryLog::inclusion_proof`] lets
+//! the Buyer prove that a single chunk was part of it without disclosing the others.
+//!
+//! Hashing follows RFC 6962 (Certificate Transparency), with SHA-256 and domain separation
+//! between leaves and nodes:
+//! - chunk hash: `SHA256(0x00 ‖ content)`
+//! - node hash: `SHA256(0x01 ‖ left ‖ right)`
+//!
+//! Hashes are encoded as 0x-prefixed hex strings. For end-to-end encrypted streams the hash is
+//! computed over the plaintext, so it only proves anything to whoever can decrypt the chunk.
+
+use alloy::hex;
+use sha2::{Digest, Sha256};
+
+/// SHA-256 digest.
+pub type Hash = [u8; 32];
+
+/// Hash of a delivered chunk, i.e. a Merkle leaf.
+pub fn chunk_hash(content: &[u8]) -> Hash {
+    let mut hasher = Sha256::new();
+    hasher.update([0x00]);
+    hasher.update(content);
+    hasher.finalize().into()
+}
+
+fn node_hash(left: &Hash, right: &Hash) -> Hash {
+    let mut hasher = Sha256::new();
+    hasher.update([0x01]);
+    hasher.update(left);
+    hasher.update(right);
+    hasher.finalize().into()
+}
+
+/// Encodes a hash as used in `stream.data` and `stream.summary`.
+pub fn encode_hash(hash: &Hash) -> String {
+    hex::encode_prefixed(hash)
+}
+
+/// Decodes a hash from `stream.data` or `stream.summary`.
+pub fn decode_hash(hash: &str) -> Result<Hash, IntegrityError> {
+    hex::decode(hash.trim_start_matches("0x"))
+        .ok()
+        .and_then(|bytes| bytes.try_into().ok())
+        .ok_or_else(|| IntegrityError::InvalidHash(hash.to_string()))
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum IntegrityError {
+    #[error("Invalid hash: {0}")]
+    InvalidHash(String),
+    #[error("Chunk {0} does not match its hash")]
+    ChunkMismatch(u64),
+    #[error("Chunk {0} is out of order, expected {1}")]
+    OutOfOrder(u64, u64),
+    #[error("Summary covers {0} chunks, {1} were received")]
+    ChunkCountMismatch(u64, u64),
+    #[error("Summary Merkle root does not match the received chunks")]
+    RootMismatch,
+}
+
+/// Rolling Merkle root over an append-only sequence of chunk hashes.
+///
+/// Only keeps the roots of the perfect subtrees covering the sequence, so the Seller
+/// can maintain it for arbitrarily long streams in `O(log n)` memory.
+#[derive(Debug, Clone, Default)]
+pub struct MerkleAccumulator {
+    /// Roots of perfect subtrees, largest first; sizes are the set bits of `len`.
+    peaks: Vec<Hash>,
+    len: u64,
+}
+
+impl MerkleAccumulator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Appends a chunk hash.
+    pub fn push(&mut self, leaf: Hash) {
+        let mut node = leaf;
+        // Merge subtrees of equal size, one per trailing set bit of the current length.
+        let mut len = self.len;
+        while len & 1 == 1 {
+            let left = self.peaks.pop().expect("one peak per set bit");
+            node = node_hash(&left, &node);
+            len >>= 1;
+        }
+        self.peaks.push(node);
+        self.len += 1;
+    }
+
+    /// Number of chunk hashes appended.
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Merkle root of all chunk hashes appended so far, or `None` if there are none.
+    pub fn root(&self) -> Option<Hash> {
+        let mut peaks = self.peaks.iter().rev();
+        let smallest = *peaks.next()?;
+        Some(peaks.fold(smallest, |acc, peak| node_hash(peak, &acc)))
+    }
+}
+
+/// Buyer-side record of the received chunks, to check them against the Seller's claims.
+#[derive(Debug, Clone, Default)]
+pub struct DeliveryLog {
+    leaves: Vec<Hash>,
+    accumulator: MerkleAccumulator,
+}
+
+impl DeliveryLog {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records chunk `seq` of a `stream.data` frame, checking that it matches the `hash`
+    /// announced by the Seller. Chunks must be recorded in sequence.
+    pub fn record(&mut self, seq: u64, content: &[u8], hash: &str) -> Result<Hash, IntegrityError> {
+        let expected = self.accumulator.len();
+        if seq != expected {
+            return Err(IntegrityError::OutOfOrder(seq, expected));
+        }
+        let leaf = chunk_hash(content);
+        if decode_hash(hash)? != leaf {
+            return Err(IntegrityError::ChunkMismatch(seq));
+        }
+        self.leaves.push(leaf);
+        self.accumulator.push(leaf);
+        Ok(leaf)
+    }
+
+    /// Number of chunks recorded.
+    pub fn len(&self) -> u64 {
+        self.accumulator.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.accumulator.is_empty()
+    }
+
+    /// Merkle root over the recorded chunks.
+    pub fn root(&self) -> Option<Hash> {
+        self.accumulator.root()
+    }
+
+    /// Checks a `stream.summary` against the recorded chunks.
+    pub fn verify_summary(&self, chunks: u64, merkle_root: &str) -> Result<(), IntegrityError> {
+        if chunks != self.len() {
+            return Err(IntegrityError::ChunkCountMismatch(chunks, self.len()));
+        }
+        let claimed = decode_hash(merkle_root)?;
+        match self.root() {
+            Some(root) if root == claimed => Ok(()),
+            _ => Err(IntegrityError::RootMismatch),
+        }
+    }
+
+    /// Audit path proving that chunk `seq` is included in [`root`](Self::root),
+    /// to be checked with [`verify_inclusion`]. `None` if `seq` was not recorded.
+    pub fn inclusion_proof(&self, seq: u64) -> Option<Vec<Hash>> {
+        let index = usize::try_from(seq).ok()?;
+        (index < self.leaves.len()).then(|| audit_path(index, &self.leaves))
+    }
+}
+
+/// RFC 6962 Merkle tree hash of `leaves`.
+fn tree_hash(leaves: &[Hash]) -> Hash {
+    if leaves.len() == 1 {
+        return leaves[0];
+    }
+    let split = split_point(leaves.len());
+    node_hash(&tree_hash(&leaves[..split]), &tree_hash(&leaves[split..]))
+}
+
+/// RFC 6962 audit path of leaf `index`, from the leaf up to the root.
+fn audit_path(index: usize, leaves: &[Hash]) -> Vec<Hash> {
+    if leaves.len() <= 1 {
+        return Vec::new();
+    }
+    let split = split_point(leaves.len());
+    if index < split {
+        let mut path = audit_path(index, &leaves[..split]);
+        path.push(tree_hash(&leaves[split..]));
+        path
+    } else {
+        let mut path = audit_path(index - split, &leaves[split..]);
+        path.push(tree_hash(&leaves[..split]));
+        path
+    }
+}
+
+/// Largest power of two smaller than `len`, for `len >= 2`.
+fn split_point(len: usize) -> usize {
+    1 << (usize::BITS - 1 - (len - 1).leading_zeros())
+}
+
+/// Checks that the chunk hashed to `leaf` is chunk `seq` of a stream of `chunks` chunks
+/// committed to by `root`, given its [`inclusion_proof`](DeliveryLog::inclusion_proof).
+pub fn verify_inclusion(leaf: &Hash, seq: u64, chunks: u64, proof: &[Hash], root: &Hash) -> bool {
+    if seq >= chunks {
+        return false;
+    }
+    // RFC 9162, section 2.1.3.2
+    let (mut index, mut last) = (seq, chunks - 1);
+    let mut hash = *leaf;
+    for sibling in proof {
+        if last == 0 {
+            return false;
+        }
+        if index & 1 == 1 || index == last {
+            hash = node_hash(sibling, &hash);
+            while index & 1 == 0 && index != 0 {
+                index >>= 1;
+                last >>= 1;
+            }
+        } else {
+            hash = node_hash(&hash, sibling);
+        }
+        index >>= 1;
+        last >>= 1;
+    }
+    last == 0 && hash == *root
+}
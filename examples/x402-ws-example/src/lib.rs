@@ -0,0 +1,13 @@
+//! Shared building blocks of the WS streaming Buyer and Seller examples.
+//!
+//! - [`e2e`] – optional end-to-end encryption of `stream.data` frames
+//! - [`integrity`] – chunk hashes and Merkle roots proving what content was delivered
+//! - [`sse`] – a market data feed over Server-Sent Events, paid over a WS payment channel
+//! - [`streams`] – paid streams from several sellers, consumed at once within a shared budget
+//! - [`wallet`] – buyer wallets from an encrypted keystore, a Ledger or a private key
+
+pub mod e2e;
+pub mod integrity;
+pub mod sse;
+pub mod streams;
+pub mod wallet;
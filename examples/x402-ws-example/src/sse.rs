@@ -0,0 +1,159 @@
+//! Market data feed over Server-Sent Events, paid per unit over a WS payment channel.
+//!
+//! The `sse-seller` example sells a feed of [`Quote`]s: the Buyer opens a paid stream on the
+//! WebSocket endpoint of the Seller, which only carries the payments, then reads the feed of the
+//! stream as Server-Sent Events. The feed pauses while the prepaid window is lapsed, and ends with
+//! the paid stream.
+//!
+//! Both sides meter the feed with a [`FeedMeter`]: every paid slice, the Seller sends a `meter`
+//! event with a [`MeterReport`] of what it delivered, which the Buyer checks against what it
+//! received.
+
+use serde::{Deserialize, Serialize};
+use std::time::{Duration, Instant};
+
+/// SSE event carrying a [`Quote`].
+pub const QUOTE_EVENT: &str = "quote";
+/// SSE event carrying a [`MeterReport`].
+pub const METER_EVENT: &str = "meter";
+
+/// Price of a symbol at a point in time.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Quote {
+    pub symbol: String,
+    pub price: f64,
+    pub at_ms: i64,
+}
+
+/// What the Seller delivered on a feed, sent as a `meter` event once a slice is paid.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MeterReport {
+    /// Slices paid so far.
+    pub slices: u64,
+    /// `quote` events delivered so far.
+    pub events: u64,
+    /// Bytes of the `data` of the `quote` events delivered so far.
+    pub bytes: u64,
+    /// End of the prepaid window, in milliseconds since the Unix epoch.
+    pub prepaid_until_ms: Option<u64>,
+}
+
+/// Bytes and events delivered on a feed, and for how long it has been open.
+#[derive(Debug, Clone)]
+pub struct FeedMeter {
+    events: u64,
+    bytes: u64,
+    opened_at: Instant,
+}
+
+impl Default for FeedMeter {
+    fn default() -> Self {
+        Self {
+            events: 0,
+            bytes: 0,
+            opened_at: Instant::now(),
+        }
+    }
+}
+
+impl FeedMeter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Counts an event of `data`.
+    pub fn record(&mut self, data: &str) {
+        self.events += 1;
+        self.bytes += data.len() as u64;
+    }
+
+    pub fn events(&self) -> u64 {
+        self.events
+    }
+
+    pub fn bytes(&self) -> u64 {
+        self.bytes
+    }
+
+    /// Time since the feed was opened.
+    pub fn elapsed(&self) -> Duration {
+        self.opened_at.elapsed()
+    }
+
+    /// Bytes delivered per second since the feed was opened.
+    pub fn bytes_per_second(&self) -> f64 {
+        self.bytes as f64 / self.elapsed().as_secs_f64().max(f64::EPSILON)
+    }
+
+    /// Whether `report` matches what this meter counted, for the Buyer to check the Seller.
+    pub fn matches(&self, report: &MeterReport) -> bool {
+        self.events == report.events && self.bytes == report.bytes
+    }
+}
+
+/// An event of a `text/event-stream` body.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SseEvent {
+    /// `event` field, `message` if absent.
+    pub event: Option<String>,
+    /// `data` fields, joined with line feeds.
+    pub data: String,
+    pub id: Option<String>,
+}
+
+/// Incremental parser of a `text/event-stream` body, fed with chunks as they arrive.
+///
+/// Comments (lines starting with `:`, e.g. keep-alives) and the `retry` field are ignored.
+#[derive(Debug, Default)]
+pub struct SseParser {
+    buffer: String,
+    event: SseEvent,
+    has_data: bool,
+}
+
+impl SseParser {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Feeds `chunk`, and returns the events it completes.
+    pub fn push(&mut self, chunk: &[u8]) -> Vec<SseEvent> {
+        self.buffer.push_str(&String::from_utf8_lossy(chunk));
+        let mut events = Vec::new();
+        while let Some(end) = self.buffer.find('\n') {
+            let line: String = self.buffer.drain(..=end).collect();
+            let line = line.trim_end_matches(['\n', '\r']);
+            if line.is_empty() {
+                if self.has_data {
+                    events.push(std::mem::take(&mut self.event));
+                } else {
+                    self.event = SseEvent::default();
+                }
+                self.has_data = false;
+                continue;
+            }
+            if line.starts_with(':') {
+                continue;
+            }
+            let (field, value) = match line.split_once(':') {
+                Some((field, value)) => (field, value.strip_prefix(' ').unwrap_or(value)),
+                None => (line, ""),
+            };
+            match field {
+                "event" => self.event.event = Some(value.to_string()),
+                "data" => {
+                    if self.has_data {
+                        self.event.data.push('\n');
+                    }
+                    self.event.data.push_str(value);
+                    self.has_data = true;
+                }
+                "id" => self.event.id = Some(value.to_string()),
+                _ => {}
+            }
+        }
+        events
+    }
+}
@@ -0,0 +1,473 @@
+//! Paid streams from several sellers at once, consumed by one buyer.
+//!
+//! Agents typically consume many paid feeds side by side. A [`StreamSet`] opens a WS stream per
+//! seller, pays every `stream.require` of them with one [`X402Payments`], and merges what they
+//! deliver into a single queue of [`StreamEvent`]s:
+//! - the wallet is shared: every stream pays from the wallets of the [`X402Payments`],
+//! - the budget is shared: give the [`X402Payments`] a [budget](x402_reqwest::budget), and the
+//!   amount of every slice of every stream is reserved from it before signing,
+//! - policies are per stream: a [`StreamPolicy`] caps the price of a slice, the number of slices
+//!   and the total paid on its stream, which is closed once a `stream.require` would exceed it.
+//!
+//! A `stream.require` cancelled by its seller with `stream.require.cancel`, e.g. to reprice it,
+//! is never paid; payments echo the `requireId` they pay, so that a seller rejects them if
+//! cancelled in the meantime.
+//!
+//! ```no_run
+//! use alloy::signers::local::PrivateKeySigner;
+//! use std::time::Duration;
+//! use x402_reqwest::X402Payments;
+//! use x402_reqwest::budget::TokenBucketBudget;
+//! use x402_rs::network::{Network, USDCDeployment};
+//! use x402_ws_example::streams::{StreamPolicy, StreamSet, StreamTarget};
+//!
+//! # async fn run() {
+//! let usdc = USDCDeployment::by_network(Network::BaseSepolia);
+//! // 1 USDC across all streams, never refilled
+//! let budget = TokenBucketBudget::new().with_bucket(
+//!     usdc.asset.clone(),
+//!     1_000_000u64,
+//!     0u64,
+//!     Duration::ZERO,
+//! );
+//! let payments = X402Payments::with_wallet(PrivateKeySigner::random()).budget(budget);
+//! let mut streams = StreamSet::new(payments);
+//! for url in ["ws://seller-a/ws", "ws://seller-b/ws"] {
+//!     let target = StreamTarget::new(url, "wss://example/stream", Network::BaseSepolia);
+//!     streams.open(target, StreamPolicy::new().max_slices(10));
+//! }
+//! while let Some(event) = streams.next_event().await {
+//!     println!("{:?}: {:?}", event.key, event.kind);
+//! }
+//! # }
+//! ```
+//!
+//! Amounts paid are counted when the payment is signed: a slice rejected by its seller may
+//! still be settled with it.
+
+use alloy::primitives::U256;
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as b64;
+use futures_util::{SinkExt, StreamExt};
+use serde_json::{Value, json};
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+use tokio::sync::{mpsc, oneshot};
+use tokio_tungstenite::connect_async;
+use tokio_tungstenite::tungstenite::Message;
+use uuid::Uuid;
+use x402_reqwest::{X402Payments, X402PaymentsError};
+use x402_rs::network::Network;
+use x402_rs::types::stream::{StreamClose, StreamEnvelope, StreamInit, StreamMessage, StreamPay};
+use x402_rs::types::{PaymentRequirements, TokenAmount, TokenAsset};
+
+use crate::integrity::{DeliveryLog, IntegrityError};
+
+/// Where to open a stream: the WS endpoint of the seller and the `stream.init` parameters.
+#[derive(Debug, Clone)]
+pub struct StreamTarget {
+    pub url: String,
+    pub resource: String,
+    pub network: Network,
+}
+
+impl StreamTarget {
+    pub fn new<U: Into<String>, R: Into<String>>(url: U, resource: R, network: Network) -> Self {
+        Self {
+            url: url.into(),
+            resource: resource.into(),
+            network,
+        }
+    }
+}
+
+/// Limits of what one stream may be paid, beyond the limits of the [`X402Payments`] paying it.
+/// Unlimited by default.
+#[derive(Debug, Clone, Default)]
+pub struct StreamPolicy {
+    max_per_slice: Option<TokenAmount>,
+    max_total: Option<TokenAmount>,
+    max_slices: Option<u64>,
+}
+
+impl StreamPolicy {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Pay at most `amount` for a slice.
+    pub fn max_per_slice<A: Into<TokenAmount>>(&self, amount: A) -> Self {
+        let mut this = self.clone();
+        this.max_per_slice = Some(amount.into());
+        this
+    }
+
+    /// Pay at most `amount` for all slices of the stream, in the asset of its slices.
+    pub fn max_total<A: Into<TokenAmount>>(&self, amount: A) -> Self {
+        let mut this = self.clone();
+        this.max_total = Some(amount.into());
+        this
+    }
+
+    /// Pay at most `slices` slices.
+    pub fn max_slices(&self, slices: u64) -> Self {
+        let mut this = self.clone();
+        this.max_slices = Some(slices);
+        this
+    }
+
+    /// Checks paying `requirements` for the next slice, after `slices` slices paid `paid` in total.
+    fn check(
+        &self,
+        requirements: &PaymentRequirements,
+        slices: u64,
+        paid: U256,
+    ) -> Result<(), StreamError> {
+        let amount = requirements.max_amount_required;
+        if let Some(max) = self.max_per_slice
+            && amount > max
+        {
+            return Err(StreamError::Policy(format!(
+                "slice price {amount} above {max}"
+            )));
+        }
+        if let Some(max) = self.max_slices
+            && slices >= max
+        {
+            return Err(StreamError::Policy(format!("{max} slices paid")));
+        }
+        if let Some(max) = self.max_total
+            && paid.saturating_add(amount.0) > max.0
+        {
+            return Err(StreamError::Policy(format!(
+                "total paid {} above {max}",
+                paid.saturating_add(amount.0)
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// Why a stream of a [`StreamSet`] closed before its seller ended it.
+#[derive(Debug, thiserror::Error)]
+pub enum StreamError {
+    #[error("WebSocket error: {0}")]
+    WebSocket(Box<tungstenite::Error>),
+    #[error("Invalid message from the seller: {0}")]
+    Protocol(String),
+    #[error("Stream policy exceeded: {0}")]
+    Policy(String),
+    #[error("Payment failed: {0}")]
+    Payment(Box<X402PaymentsError>),
+    #[error("Integrity check failed: {0}")]
+    Integrity(#[from] IntegrityError),
+}
+
+impl From<tungstenite::Error> for StreamError {
+    fn from(e: tungstenite::Error) -> Self {
+        StreamError::WebSocket(Box::new(e))
+    }
+}
+
+impl From<X402PaymentsError> for StreamError {
+    fn from(e: X402PaymentsError) -> Self {
+        StreamError::Payment(Box::new(e))
+    }
+}
+
+impl From<serde_json::Error> for StreamError {
+    fn from(e: serde_json::Error) -> Self {
+        StreamError::Protocol(e.to_string())
+    }
+}
+
+/// Identifier of a stream within its [`StreamSet`], known before the seller assigns a `streamId`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct StreamKey(pub u64);
+
+/// What happened on a stream of a [`StreamSet`].
+#[derive(Debug)]
+pub struct StreamEvent {
+    pub key: StreamKey,
+    pub kind: StreamEventKind,
+}
+
+#[derive(Debug)]
+pub enum StreamEventKind {
+    /// The seller accepted `stream.init`.
+    Accepted { stream_id: String },
+    /// A slice was paid with `stream.pay`.
+    Paid {
+        slice_index: u64,
+        asset: TokenAsset,
+        amount: TokenAmount,
+    },
+    /// The seller cancelled the `stream.require` `require_id`, which is not paid.
+    RequireCancelled { require_id: String },
+    /// The seller answered with an error envelope, e.g. rejecting a payment.
+    Rejected { error: Value },
+    /// Content delivered by `stream.data`.
+    Data { seq: u64, content: Vec<u8> },
+    /// The seller's `stream.summary` matched the content delivered.
+    Summary { chunks: u64 },
+    /// The stream is over: ended by the seller or closed by the buyer if `error` is `None`.
+    /// Always the last event of a stream.
+    Closed { error: Option<StreamError> },
+}
+
+/// Paid streams from several sellers, consumed at once, see the [module documentation](self).
+pub struct StreamSet {
+    payments: X402Payments,
+    /// Amounts paid across all streams, by asset.
+    spent: Arc<Mutex<HashMap<TokenAsset, TokenAmount>>>,
+    /// Closes the streams still open, by key.
+    cancels: HashMap<StreamKey, oneshot::Sender<()>>,
+    /// Number of streams that have not reported [`StreamEventKind::Closed`] yet.
+    open: usize,
+    next_key: u64,
+    events_tx: mpsc::UnboundedSender<StreamEvent>,
+    events_rx: mpsc::UnboundedReceiver<StreamEvent>,
+}
+
+impl StreamSet {
+    /// Streams paid by `payments`, with its wallets, limits and budget.
+    pub fn new(payments: X402Payments) -> Self {
+        let (events_tx, events_rx) = mpsc::unbounded_channel();
+        Self {
+            payments,
+            spent: Arc::new(Mutex::new(HashMap::new())),
+            cancels: HashMap::new(),
+            open: 0,
+            next_key: 0,
+            events_tx,
+            events_rx,
+        }
+    }
+
+    /// Opens a stream to `target`, paid within `policy`. Must be called from within a Tokio
+    /// runtime.
+    pub fn open(&mut self, target: StreamTarget, policy: StreamPolicy) -> StreamKey {
+        let key = StreamKey(self.next_key);
+        self.next_key += 1;
+        let (cancel_tx, cancel_rx) = oneshot::channel();
+        self.cancels.insert(key, cancel_tx);
+        self.open += 1;
+        let consumer = StreamConsumer {
+            key,
+            policy,
+            payments: self.payments.clone(),
+            spent: self.spent.clone(),
+            events: self.events_tx.clone(),
+        };
+        tokio::spawn(async move {
+            let error = consumer.run(target, cancel_rx).await.err();
+            consumer.emit(StreamEventKind::Closed { error });
+        });
+        key
+    }
+
+    /// Closes stream `key`, sending `stream.end` to its seller. Returns `false` if the stream is
+    /// already over.
+    pub fn close(&mut self, key: StreamKey) -> bool {
+        self.cancels
+            .remove(&key)
+            .is_some_and(|cancel| cancel.send(()).is_ok())
+    }
+
+    /// Closes all streams.
+    pub fn close_all(&mut self) {
+        for (_, cancel) in self.cancels.drain() {
+            let _ = cancel.send(());
+        }
+    }
+
+    /// Next event of any stream, or `None` once all streams are closed.
+    pub async fn next_event(&mut self) -> Option<StreamEvent> {
+        if self.open == 0 {
+            return self.events_rx.try_recv().ok();
+        }
+        let event = self.events_rx.recv().await?;
+        if let StreamEventKind::Closed { .. } = event.kind {
+            self.open -= 1;
+            self.cancels.remove(&event.key);
+        }
+        Some(event)
+    }
+
+    /// Number of streams not closed yet.
+    pub fn len(&self) -> usize {
+        self.open
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.open == 0
+    }
+
+    /// Amounts paid across all streams, by asset.
+    pub fn spent(&self) -> HashMap<TokenAsset, TokenAmount> {
+        self.spent.lock().unwrap().clone()
+    }
+}
+
+impl Drop for StreamSet {
+    fn drop(&mut self) {
+        self.close_all();
+    }
+}
+
+/// Task consuming one stream of a [`StreamSet`].
+struct StreamConsumer {
+    key: StreamKey,
+    policy: StreamPolicy,
+    payments: X402Payments,
+    spent: Arc<Mutex<HashMap<TokenAsset, TokenAmount>>>,
+    events: mpsc::UnboundedSender<StreamEvent>,
+}
+
+impl StreamConsumer {
+    fn emit(&self, kind: StreamEventKind) {
+        let _ = self.events.send(StreamEvent {
+            key: self.key,
+            kind,
+        });
+    }
+
+    async fn run(
+        &self,
+        target: StreamTarget,
+        mut cancel: oneshot::Receiver<()>,
+    ) -> Result<(), StreamError> {
+        let (mut ws, _) = connect_async(target.url.as_str()).await?;
+        let mut init = StreamInit::new(target.resource);
+        init.network = Some(target.network);
+        let init = StreamEnvelope::new(Uuid::new_v4().to_string(), StreamMessage::Init(init));
+        ws.send(Message::Text(serde_json::to_string(&init)?.into()))
+            .await?;
+
+        let mut stream_id: Option<String> = None;
+        let mut slices = 0u64;
+        let mut paid = U256::ZERO;
+        let mut delivered = DeliveryLog::new();
+        // Requirements cancelled by the seller, never to be paid
+        let mut cancelled = HashSet::new();
+        loop {
+            let msg = tokio::select! {
+                _ = &mut cancel => {
+                    if let Some(stream_id) = &stream_id {
+                        let end = StreamEnvelope::new(
+                            Uuid::new_v4().to_string(),
+                            StreamMessage::Close(StreamClose {
+                                stream_id: stream_id.clone(),
+                                reason: None,
+                            }),
+                        );
+                        ws.send(Message::Text(serde_json::to_string(&end)?.into())).await?;
+                    }
+                    ws.close(None).await?;
+                    return Ok(());
+                }
+                msg = ws.next() => msg,
+            };
+            let Some(msg) = msg else {
+                return Ok(());
+            };
+            let text = match msg? {
+                Message::Text(text) => text,
+                Message::Close(_) => return Ok(()),
+                _ => continue,
+            };
+            let envelope: Value = serde_json::from_str(&text)?;
+            if let Some(error) = envelope.get("error") {
+                self.emit(StreamEventKind::Rejected {
+                    error: error.clone(),
+                });
+                continue;
+            }
+            let Some(method) = envelope.get("method").and_then(Value::as_str) else {
+                // The first stream.accept answers stream.init, later ones answer stream.pay
+                let accept = envelope
+                    .get("result")
+                    .and_then(|result| serde_json::from_value(result.clone()).ok());
+                if stream_id.is_none()
+                    && let Some(StreamMessage::Accept(accept)) = accept
+                {
+                    stream_id = Some(accept.stream_id.clone());
+                    self.emit(StreamEventKind::Accepted {
+                        stream_id: accept.stream_id,
+                    });
+                }
+                continue;
+            };
+            if !StreamMessage::METHODS.contains(&method) {
+                continue;
+            }
+            let envelope: StreamEnvelope = serde_json::from_value(envelope)
+                .map_err(|e| StreamError::Protocol(format!("Invalid stream message: {e}")))?;
+            let id = envelope.id().clone();
+            match envelope.into_message() {
+                StreamMessage::RequireCancel(cancel) => {
+                    cancelled.insert(cancel.require_id.clone());
+                    self.emit(StreamEventKind::RequireCancelled {
+                        require_id: cancel.require_id,
+                    });
+                }
+                StreamMessage::Require(require) => {
+                    if cancelled.contains(&require.require_id) {
+                        continue;
+                    }
+                    let requirements = require.requirements.clone();
+                    let slice_index = require.slice_index;
+                    self.policy.check(&requirements, slices, paid)?;
+                    self.payments.assert_max_amount(&requirements)?;
+                    let asset = requirements.token_asset();
+                    let amount = requirements.max_amount_required;
+                    let payload = self.payments.make_payment_payload(requirements).await?;
+                    let id = if id.is_null() {
+                        json!(Uuid::new_v4().to_string())
+                    } else {
+                        id
+                    };
+                    let pay = StreamEnvelope::new(
+                        id,
+                        StreamMessage::Pay(StreamPay::new(&require, payload)),
+                    );
+                    ws.send(Message::Text(serde_json::to_string(&pay)?.into()))
+                        .await?;
+                    slices += 1;
+                    paid = paid.saturating_add(amount.0);
+                    {
+                        let mut spent = self.spent.lock().unwrap();
+                        let total = spent
+                            .entry(asset.clone())
+                            .or_insert(TokenAmount(U256::ZERO));
+                        total.0 = total.0.saturating_add(amount.0);
+                    }
+                    self.emit(StreamEventKind::Paid {
+                        slice_index,
+                        asset,
+                        amount,
+                    });
+                }
+                StreamMessage::Data(data) => {
+                    let content = b64
+                        .decode(data.data.as_deref().unwrap_or_default())
+                        .map_err(|e| StreamError::Protocol(format!("Invalid stream.data: {e}")))?;
+                    delivered.record(data.seq, &content, &data.hash)?;
+                    self.emit(StreamEventKind::Data {
+                        seq: data.seq,
+                        content,
+                    });
+                }
+                StreamMessage::Summary(summary) => {
+                    let merkle_root = summary.merkle_root.as_deref().unwrap_or_default();
+                    delivered.verify_summary(summary.chunks, merkle_root)?;
+                    self.emit(StreamEventKind::Summary {
+                        chunks: summary.chunks,
+                    });
+                }
+                StreamMessage::Close(_) => return Ok(()),
+                _ => {}
+            }
+        }
+    }
+}
@@ -0,0 +1,55 @@
+//! Buyer wallets loaded from the environment, without a raw private key where possible.
+//!
+//! In order of precedence:
+//! - `EVM_KEYSTORE`: path of an encrypted JSON keystore, decrypted with `EVM_KEYSTORE_PASSWORD`,
+//! - `EVM_LEDGER_ACCOUNT`: index of a Ledger Live account of a Ledger connected over USB
+//!   (`ledger` feature of the example),
+//! - `EVM_PRIVATE_KEY`: hex private key.
+
+use std::env;
+use x402_reqwest::chains::evm::EvmSenderWallet;
+
+#[derive(Debug, thiserror::Error)]
+pub enum WalletError {
+    #[error("Set EVM_KEYSTORE, EVM_LEDGER_ACCOUNT or EVM_PRIVATE_KEY")]
+    Missing,
+    #[error("EVM_KEYSTORE_PASSWORD is required with EVM_KEYSTORE")]
+    MissingPassword,
+    #[error("EVM_LEDGER_ACCOUNT needs the ledger feature")]
+    LedgerUnsupported,
+    #[error("Invalid {0}")]
+    Invalid(&'static str),
+    #[error(transparent)]
+    Payments(#[from] x402_reqwest::X402PaymentsError),
+}
+
+/// The buyer wallet configured in the environment.
+pub async fn evm_wallet_from_env() -> Result<EvmSenderWallet, WalletError> {
+    if let Ok(path) = env::var("EVM_KEYSTORE") {
+        let password =
+            env::var("EVM_KEYSTORE_PASSWORD").map_err(|_| WalletError::MissingPassword)?;
+        return Ok(EvmSenderWallet::from_keystore(path, password)?);
+    }
+    if let Ok(account) = env::var("EVM_LEDGER_ACCOUNT") {
+        let account: usize = account
+            .parse()
+            .map_err(|_| WalletError::Invalid("EVM_LEDGER_ACCOUNT"))?;
+        return ledger(account).await;
+    }
+    let private_key = env::var("EVM_PRIVATE_KEY").map_err(|_| WalletError::Missing)?;
+    let signer: alloy::signers::local::PrivateKeySigner = private_key
+        .parse()
+        .map_err(|_| WalletError::Invalid("EVM_PRIVATE_KEY"))?;
+    Ok(EvmSenderWallet::new(signer))
+}
+
+#[cfg(feature = "ledger")]
+async fn ledger(account: usize) -> Result<EvmSenderWallet, WalletError> {
+    let hd_path = alloy::signers::ledger::HDPath::LedgerLive(account);
+    Ok(EvmSenderWallet::ledger(hd_path).await?)
+}
+
+#[cfg(not(feature = "ledger"))]
+async fn ledger(_account: usize) -> Result<EvmSenderWallet, WalletError> {
+    Err(WalletError::LedgerUnsupported)
+}
@@ -0,0 +1,413 @@
+//! Prepaid balances of repeat buyers.
+//!
+//! Chatty API consumers pay for every request with a signed payment, and every payment is
+//! settled on-chain. With a prepaid balance, a buyer deposits an amount once, with a single x402
+//! payment, and later requests are charged against the balance, kept by the seller or its
+//! facilitator, without signing anything:
+//! - the deposit is verified and settled like any payment, and credited to a balance identified
+//!   by a bearer token, returned in the `X-Payment-Balance` header,
+//! - later requests send the token back in the `X-Payment-Balance` header, and the price of the
+//!   request is charged against the balance. The balance left is returned in the
+//!   `X-Payment-Balance-Remaining` header,
+//! - a request the balance can not pay is answered with `402 Payment Required`, asking for a new
+//!   deposit, which tops the balance up if paid with the token.
+//!
+//! A balance belongs to a payer, and can only be charged in the network and asset it was
+//! deposited in, for the recipient it was deposited to. The token is a secret: anyone holding it
+//! can spend the balance with its recipient.
+//!
+//! Balances are kept by a [`BalanceStore`]: a [`PrepaidBalances`] in the seller process, or the
+//! facilitator, which serves its [`BalanceLedger`] with the `balance.deposit`, `balance.charge`
+//! and `balance.get` WebSocket methods and `GET /balance` (see `x402-ws-stream.md`), through a
+//! [`FacilitatorWsClient`](crate::facilitator_remote::FacilitatorWsClient) (`remote` feature).
+//! Balances are kept in memory, and lost on restart.
+
+use futures_util::future::BoxFuture;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use crate::facilitator::Facilitator;
+use crate::network::Network;
+use crate::timestamp::UnixTimestamp;
+use crate::types::{
+    MixedAddress, PaymentRequirements, Scheme, SettleRequest, SettleResponse, TokenAmount,
+    VerifyResponse,
+};
+
+/// Header carrying the token of a prepaid balance, in requests and in the response to a deposit.
+pub const BALANCE_HEADER: &str = "X-Payment-Balance";
+/// Header carrying the balance left after a request, in token base units.
+#[allow(dead_code)] // Public for consumption by downstream crates.
+pub const BALANCE_REMAINING_HEADER: &str = "X-Payment-Balance-Remaining";
+
+#[derive(Debug, thiserror::Error)]
+pub enum BalanceError {
+    #[error("Unknown prepaid balance")]
+    UnknownBalance,
+    #[error("Insufficient prepaid balance: {balance} left, {amount} required")]
+    Insufficient {
+        balance: TokenAmount,
+        amount: TokenAmount,
+    },
+    #[error("The prepaid balance can not pay this recipient, network or asset")]
+    NoMatchingCharge,
+    #[error("Deposits must be payments of the exact scheme")]
+    UnsupportedScheme,
+    #[error("Deposit rejected: {0}")]
+    DepositRejected(String),
+    #[error("Balance store unavailable: {0}")]
+    #[allow(dead_code)] // Constructed by the balance stores of the `remote` feature.
+    Unavailable(String),
+}
+
+impl BalanceError {
+    /// The `data` of the error envelope of a `balance.*` method failing with this error, its
+    /// `reason` and details.
+    pub fn data(&self) -> serde_json::Value {
+        match self {
+            BalanceError::UnknownBalance => serde_json::json!({ "reason": "unknown_balance" }),
+            BalanceError::Insufficient { balance, amount } => serde_json::json!({
+                "reason": "insufficient_balance",
+                "balance": balance,
+                "amount": amount,
+            }),
+            BalanceError::NoMatchingCharge => serde_json::json!({ "reason": "no_matching_charge" }),
+            BalanceError::UnsupportedScheme => {
+                serde_json::json!({ "reason": "unsupported_scheme" })
+            }
+            BalanceError::DepositRejected(_) => serde_json::json!({ "reason": "deposit_rejected" }),
+            BalanceError::Unavailable(_) => serde_json::json!({ "reason": "unavailable" }),
+        }
+    }
+}
+
+/// Price of a request, charged against a balance of the same network, asset and recipient.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Charge {
+    pub network: Network,
+    pub asset: MixedAddress,
+    pub pay_to: MixedAddress,
+    pub amount: TokenAmount,
+}
+
+impl Charge {
+    /// The charge of `requirements`, of their `maxAmountRequired`.
+    pub fn from_requirements(requirements: &PaymentRequirements) -> Self {
+        Self {
+            network: requirements.network,
+            asset: requirements.asset.clone(),
+            pay_to: requirements.pay_to.clone(),
+            amount: requirements.max_amount_required,
+        }
+    }
+}
+
+/// A prepaid balance.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BalanceAccount {
+    /// Bearer token identifying the balance.
+    pub token: String,
+    pub payer: MixedAddress,
+    pub network: Network,
+    pub asset: MixedAddress,
+    pub pay_to: MixedAddress,
+    /// Amount left, in token base units.
+    pub balance: TokenAmount,
+    /// Total deposited.
+    pub deposited: TokenAmount,
+    /// Total charged.
+    pub spent: TokenAmount,
+    pub updated_at: UnixTimestamp,
+}
+
+impl BalanceAccount {
+    fn can_pay(&self, charge: &Charge) -> bool {
+        self.network == charge.network && self.asset == charge.asset && self.pay_to == charge.pay_to
+    }
+}
+
+/// A settled deposit, and the balance it was credited to.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Deposit {
+    pub account: BalanceAccount,
+    pub settlement: SettleResponse,
+}
+
+/// Prepaid balances, keyed by token.
+#[derive(Debug, Clone, Default)]
+pub struct BalanceLedger {
+    accounts: Arc<Mutex<HashMap<String, BalanceAccount>>>,
+}
+
+impl BalanceLedger {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Verifies and settles the deposit `request` with `facilitator`, and credits the amount
+    /// settled to the balance `token`, or to a new balance if `token` is unknown, or of another
+    /// payer, network, asset or recipient.
+    ///
+    /// The amount settled is the one decoded from the logs of the settlement transaction, or else
+    /// the `maxAmountRequired` of the requirements, at most what an `exact` payment moved.
+    ///
+    /// # Errors
+    /// Returns [`BalanceError::UnsupportedScheme`] for deposits of other schemes than `exact`,
+    /// and [`BalanceError::DepositRejected`] if the payment is invalid or not settled.
+    pub async fn deposit<F: Facilitator>(
+        &self,
+        facilitator: &F,
+        request: &SettleRequest,
+        token: Option<&str>,
+    ) -> Result<Deposit, BalanceError> {
+        if request.payment_requirements.scheme != Scheme::Exact {
+            return Err(BalanceError::UnsupportedScheme);
+        }
+        let verify = facilitator
+            .verify(request)
+            .await
+            .map_err(|e| BalanceError::DepositRejected(e.to_string()))?;
+        if let VerifyResponse::Invalid { reason, .. } = verify {
+            return Err(BalanceError::DepositRejected(reason.to_string()));
+        }
+        let settlement = facilitator
+            .settle(request)
+            .await
+            .map_err(|e| BalanceError::DepositRejected(e.to_string()))?;
+        if !settlement.success {
+            let reason = settlement
+                .error_reason
+                .map(|reason| reason.to_string())
+                .unwrap_or_else(|| "settlement failed".to_string());
+            return Err(BalanceError::DepositRejected(reason));
+        }
+        let mut deposit = Charge::from_requirements(&request.payment_requirements);
+        if let Some(details) = &settlement.settlement {
+            deposit.amount = details.amount;
+        }
+        let account = self.credit(token, settlement.payer.clone(), &deposit);
+        Ok(Deposit {
+            account,
+            settlement,
+        })
+    }
+
+    /// Credits `deposit` to the balance `token` of `payer`, or to a new balance. The deposit must
+    /// have been settled.
+    pub fn credit(
+        &self,
+        token: Option<&str>,
+        payer: MixedAddress,
+        deposit: &Charge,
+    ) -> BalanceAccount {
+        let now = UnixTimestamp::try_now().unwrap_or(UnixTimestamp(0));
+        let mut accounts = self.accounts.lock().unwrap();
+        let existing = token
+            .and_then(|token| accounts.get_mut(token))
+            .filter(|account| account.payer == payer && account.can_pay(deposit));
+        if let Some(account) = existing {
+            account.balance = account.balance + deposit.amount;
+            account.deposited = account.deposited + deposit.amount;
+            account.updated_at = now;
+            return account.clone();
+        }
+        let account = BalanceAccount {
+            token: uuid::Uuid::new_v4().simple().to_string(),
+            payer,
+            network: deposit.network,
+            asset: deposit.asset.clone(),
+            pay_to: deposit.pay_to.clone(),
+            balance: deposit.amount,
+            deposited: deposit.amount,
+            spent: TokenAmount::from(0u64),
+            updated_at: now,
+        };
+        accounts.insert(account.token.clone(), account.clone());
+        account
+    }
+
+    /// Charges the balance `token` with the first of `charges` of its network, asset and
+    /// recipient, e.g. the prices of a request in every asset it accepts.
+    ///
+    /// # Errors
+    /// Returns [`BalanceError::UnknownBalance`] for an unknown token,
+    /// [`BalanceError::NoMatchingCharge`] if none of `charges` can be paid from the balance, and
+    /// [`BalanceError::Insufficient`] if the balance is below the charge.
+    pub fn charge(&self, token: &str, charges: &[Charge]) -> Result<BalanceAccount, BalanceError> {
+        let mut accounts = self.accounts.lock().unwrap();
+        let account = accounts
+            .get_mut(token)
+            .ok_or(BalanceError::UnknownBalance)?;
+        let charge = charges
+            .iter()
+            .find(|charge| account.can_pay(charge))
+            .ok_or(BalanceError::NoMatchingCharge)?;
+        if account.balance < charge.amount {
+            return Err(BalanceError::Insufficient {
+                balance: account.balance,
+                amount: charge.amount,
+            });
+        }
+        account.balance = account.balance - charge.amount;
+        account.spent = account.spent + charge.amount;
+        account.updated_at = UnixTimestamp::try_now().unwrap_or(UnixTimestamp(0));
+        Ok(account.clone())
+    }
+
+    /// Returns the balance `token`, if known.
+    pub fn get(&self, token: &str) -> Option<BalanceAccount> {
+        self.accounts.lock().unwrap().get(token).cloned()
+    }
+}
+
+/// Keeps prepaid balances for a seller, see the [module documentation](self).
+#[allow(dead_code)] // Public for consumption by downstream crates.
+pub trait BalanceStore: Send + Sync {
+    /// Verifies and settles the deposit `request`, and credits it to the balance `token`, or to a
+    /// new balance.
+    fn deposit<'a>(
+        &'a self,
+        request: &'a SettleRequest,
+        token: Option<&'a str>,
+    ) -> BoxFuture<'a, Result<Deposit, BalanceError>>;
+
+    /// Charges the balance `token` with the first of `charges` it can pay.
+    fn charge<'a>(
+        &'a self,
+        token: &'a str,
+        charges: &'a [Charge],
+    ) -> BoxFuture<'a, Result<BalanceAccount, BalanceError>>;
+
+    /// Returns the balance `token`.
+    fn balance<'a>(&'a self, token: &'a str)
+    -> BoxFuture<'a, Result<BalanceAccount, BalanceError>>;
+}
+
+/// Balances kept in the seller process, with deposits settled by `facilitator`.
+#[derive(Debug, Clone)]
+#[allow(dead_code)] // Public for consumption by downstream crates.
+pub struct PrepaidBalances<F> {
+    ledger: BalanceLedger,
+    facilitator: F,
+}
+
+impl<F> PrepaidBalances<F> {
+    #[allow(dead_code)] // Public for consumption by downstream crates.
+    pub fn new(ledger: BalanceLedger, facilitator: F) -> Self {
+        Self {
+            ledger,
+            facilitator,
+        }
+    }
+
+    #[allow(dead_code)] // Public for consumption by downstream crates.
+    pub fn ledger(&self) -> &BalanceLedger {
+        &self.ledger
+    }
+}
+
+impl<F: Facilitator + Send + Sync> BalanceStore for PrepaidBalances<F> {
+    fn deposit<'a>(
+        &'a self,
+        request: &'a SettleRequest,
+        token: Option<&'a str>,
+    ) -> BoxFuture<'a, Result<Deposit, BalanceError>> {
+        Box::pin(self.ledger.deposit(&self.facilitator, request, token))
+    }
+
+    fn charge<'a>(
+        &'a self,
+        token: &'a str,
+        charges: &'a [Charge],
+    ) -> BoxFuture<'a, Result<BalanceAccount, BalanceError>> {
+        Box::pin(async move { self.ledger.charge(token, charges) })
+    }
+
+    fn balance<'a>(
+        &'a self,
+        token: &'a str,
+    ) -> BoxFuture<'a, Result<BalanceAccount, BalanceError>> {
+        Box::pin(async move { self.ledger.get(token).ok_or(BalanceError::UnknownBalance) })
+    }
+}
+
+/// Balances kept by the facilitator at the other end of the connection.
+#[cfg(feature = "remote")]
+impl BalanceStore for crate::facilitator_remote::FacilitatorWsClient {
+    fn deposit<'a>(
+        &'a self,
+        request: &'a SettleRequest,
+        token: Option<&'a str>,
+    ) -> BoxFuture<'a, Result<Deposit, BalanceError>> {
+        let params = serde_json::json!({
+            "paymentPayload": request.payment_payload,
+            "paymentRequirements": request.payment_requirements,
+            "token": token,
+        });
+        Box::pin(async move {
+            self.call("balance.deposit", &params)
+                .await
+                .map_err(remote_error)
+        })
+    }
+
+    fn charge<'a>(
+        &'a self,
+        token: &'a str,
+        charges: &'a [Charge],
+    ) -> BoxFuture<'a, Result<BalanceAccount, BalanceError>> {
+        let params = serde_json::json!({ "token": token, "charges": charges });
+        Box::pin(async move {
+            self.call("balance.charge", &params)
+                .await
+                .map_err(remote_error)
+        })
+    }
+
+    fn balance<'a>(
+        &'a self,
+        token: &'a str,
+    ) -> BoxFuture<'a, Result<BalanceAccount, BalanceError>> {
+        let params = serde_json::json!({ "token": token });
+        Box::pin(async move {
+            self.call("balance.get", &params)
+                .await
+                .map_err(remote_error)
+        })
+    }
+}
+
+/// Errors of the `balance.*` methods carry the [`BalanceError`] in their `data`, see
+/// [`BalanceError::data`]; other errors are the facilitator being unavailable.
+#[cfg(feature = "remote")]
+fn remote_error(error: crate::facilitator_remote::WsClientError) -> BalanceError {
+    use crate::facilitator_remote::WsClientError;
+    let WsClientError::Rpc {
+        message,
+        data: Some(data),
+        ..
+    } = error
+    else {
+        return BalanceError::Unavailable(error.to_string());
+    };
+    let amount = |field: &str| {
+        data.get(field)
+            .and_then(|amount| serde_json::from_value(amount.clone()).ok())
+            .unwrap_or(TokenAmount::from(0u64))
+    };
+    match data.get("reason").and_then(|reason| reason.as_str()) {
+        Some("unknown_balance") => BalanceError::UnknownBalance,
+        Some("insufficient_balance") => BalanceError::Insufficient {
+            balance: amount("balance"),
+            amount: amount("amount"),
+        },
+        Some("no_matching_charge") => BalanceError::NoMatchingCharge,
+        Some("unsupported_scheme") => BalanceError::UnsupportedScheme,
+        Some("deposit_rejected") => BalanceError::DepositRejected(message),
+        _ => BalanceError::Unavailable(message),
+    }
+}
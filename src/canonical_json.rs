@@ -0,0 +1,180 @@
+//! Canonical JSON, per the JSON Canonicalization Scheme ([RFC 8785](https://www.rfc-editor.org/rfc/rfc8785)).
+//!
+//! The same value serialized by two implementations rarely yields the same bytes: keys come in
+//! different orders, numbers are written differently (`1.0`, `1`, `1e0`), and so are escapes.
+//! Hashes and signatures computed over JSON must agree across implementations, TypeScript and Go
+//! ones included, so everything hashed or signed is serialized canonically:
+//! - no whitespace,
+//! - object keys sorted by their UTF-16 code units, as JavaScript sorts strings,
+//! - strings escaped minimally: `"`, `\` and control characters only, with the short escapes
+//!   (`\n`, `\t`, ...) where they exist, and `\u00xx` in lowercase otherwise,
+//! - numbers written as JavaScript writes them (`Number.prototype.toString`): integers without
+//!   a fraction, the shortest digits that round-trip, and an exponent below `1e-6` and from
+//!   `1e21` on. Integers beyond 2^53 lose precision, as in JavaScript: amounts are strings in
+//!   x402 for that reason.
+//!
+//! Numbers are parsed exactly (the `float_roundtrip` feature of `serde_json`), so that a parsed
+//! document hashes as its sender hashed it.
+//!
+//! Test vectors, from RFC 8785 and shared with other implementations:
+//!
+//! ```
+//! use x402_rs::canonical_json;
+//!
+//! let vectors = [
+//!     (
+//!         r#"{"numbers":[333333333.33333329,1E30,4.50,2e-3,0.000000000000000000000000001],"string":"\u20ac$\u000F\u000aA'\u0042\u0022\u005c\\\"\/","literals":[null,true,false]}"#,
+//!         r#"{"literals":[null,true,false],"numbers":[333333333.3333333,1e+30,4.5,0.002,1e-27],"string":"€$\u000f\nA'B\"\\\\\"/"}"#,
+//!     ),
+//!     (
+//!         r#"{"\u20ac":"Euro Sign","\r":"Carriage Return","\ufb33":"Hebrew Letter Dalet With Dagesh","1":"One","\ud83d\ude00":"Emoji: Grinning Face","\u0080":"Control","\u00f6":"Latin Small Letter O With Diaeresis"}"#,
+//!         "{\"\\r\":\"Carriage Return\",\"1\":\"One\",\"\u{80}\":\"Control\",\"\u{f6}\":\"Latin Small Letter O With Diaeresis\",\"\u{20ac}\":\"Euro Sign\",\"\u{1f600}\":\"Emoji: Grinning Face\",\"\u{fb33}\":\"Hebrew Letter Dalet With Dagesh\"}",
+//!     ),
+//!     (
+//!         "[0, -0.0, 1e21, 1e20, 1e-7, 0.000001, 5e-324, 1.7976931348623157e308, 9007199254740993, -12.5e3]",
+//!         "[0,0,1e+21,100000000000000000000,1e-7,0.000001,5e-324,1.7976931348623157e+308,9007199254740992,-12500]",
+//!     ),
+//! ];
+//! for (input, canonical) in vectors {
+//!     let value: serde_json::Value = serde_json::from_str(input).unwrap();
+//!     assert_eq!(canonical_json::to_string(&value).unwrap(), canonical);
+//! }
+//! ```
+
+use alloy::primitives::B256;
+use serde::Serialize;
+use serde_json::{Number, Value};
+use std::fmt::Write;
+
+/// Largest integer JavaScript represents exactly.
+const MAX_SAFE_INTEGER: u64 = (1 << 53) - 1;
+
+/// Canonical JSON text of `value`.
+///
+/// # Errors
+/// Fails if `value` can not be serialized to JSON, e.g. a map with non-string keys.
+pub fn to_string<T: Serialize + ?Sized>(value: &T) -> Result<String, serde_json::Error> {
+    let value = serde_json::to_value(value)?;
+    let mut out = String::new();
+    write_value(&mut out, &value);
+    Ok(out)
+}
+
+/// Canonical JSON bytes of `value`, see [`to_string`].
+pub fn to_vec<T: Serialize + ?Sized>(value: &T) -> Result<Vec<u8>, serde_json::Error> {
+    to_string(value).map(String::into_bytes)
+}
+
+/// Keccak-256 hash of the canonical JSON bytes of `value`, see [`to_string`].
+pub fn keccak256<T: Serialize + ?Sized>(value: &T) -> Result<B256, serde_json::Error> {
+    to_vec(value).map(alloy::primitives::keccak256)
+}
+
+fn write_value(out: &mut String, value: &Value) {
+    match value {
+        Value::Null => out.push_str("null"),
+        Value::Bool(value) => out.push_str(if *value { "true" } else { "false" }),
+        Value::Number(number) => write_number(out, number),
+        Value::String(string) => write_string(out, string),
+        Value::Array(values) => {
+            out.push('[');
+            for (i, value) in values.iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                write_value(out, value);
+            }
+            out.push(']');
+        }
+        Value::Object(object) => {
+            let mut entries: Vec<_> = object.iter().collect();
+            entries.sort_by(|(a, _), (b, _)| a.encode_utf16().cmp(b.encode_utf16()));
+            out.push('{');
+            for (i, (key, value)) in entries.into_iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                write_string(out, key);
+                out.push(':');
+                write_value(out, value);
+            }
+            out.push('}');
+        }
+    }
+}
+
+fn write_string(out: &mut String, string: &str) {
+    out.push('"');
+    for c in string.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\u{08}' => out.push_str("\\b"),
+            '\u{0C}' => out.push_str("\\f"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if c < ' ' => {
+                let _ = write!(out, "\\u{:04x}", c as u32);
+            }
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+fn write_number(out: &mut String, number: &Number) {
+    if let Some(value) = number.as_u64()
+        && value <= MAX_SAFE_INTEGER
+    {
+        let _ = write!(out, "{value}");
+    } else if let Some(value) = number.as_i64()
+        && value.unsigned_abs() <= MAX_SAFE_INTEGER
+    {
+        let _ = write!(out, "{value}");
+    } else {
+        // Numbers of `serde_json` are always finite
+        write_f64(out, number.as_f64().unwrap_or_default());
+    }
+}
+
+/// Writes `value` as JavaScript's `Number.prototype.toString` does.
+fn write_f64(out: &mut String, value: f64) {
+    if value == 0.0 {
+        // Negative zero too
+        out.push('0');
+        return;
+    }
+    if value < 0.0 {
+        out.push('-');
+    }
+    // Shortest digits that round-trip, as `d.ddde<exponent>`
+    let scientific = format!("{:e}", value.abs());
+    let (mantissa, exponent) = scientific
+        .split_once('e')
+        .expect("Scientific notation has an exponent");
+    let digits = mantissa.replace('.', "");
+    let exponent: i32 = exponent.parse().expect("Exponent is an integer");
+    let k = digits.len() as i32;
+    // Position of the decimal point, relative to the first digit
+    let n = exponent + 1;
+    if k <= n && n <= 21 {
+        out.push_str(&digits);
+        out.extend(std::iter::repeat_n('0', (n - k) as usize));
+    } else if 0 < n && n <= 21 {
+        let (integer, fraction) = digits.split_at(n as usize);
+        let _ = write!(out, "{integer}.{fraction}");
+    } else if -6 < n && n <= 0 {
+        out.push_str("0.");
+        out.extend(std::iter::repeat_n('0', (-n) as usize));
+        out.push_str(&digits);
+    } else {
+        let (first, rest) = digits.split_at(1);
+        out.push_str(first);
+        if !rest.is_empty() {
+            let _ = write!(out, ".{rest}");
+        }
+        let sign = if n > 0 { '+' } else { '-' };
+        let _ = write!(out, "e{sign}{}", (n - 1).abs());
+    }
+}
@@ -0,0 +1,376 @@
+//! Transaction nonces of a settlement account, kept in sync with the chain.
+//!
+//! Every settlement transaction of an [`EvmProvider`](crate::chain::evm::EvmProvider), batched
+//! or not, takes its nonce from the [`AccountNonces`] of the account sending it, one of its
+//! [`SignerPool`](crate::chain::signer_pool::SignerPool): the next nonce is tracked locally, so that
+//! concurrent settlements do not race for the same one. The local view diverges from the chain
+//! when something else uses the account (a transaction sent by an operator, another process
+//! sharing the key) or when a transaction is dropped from the mempool:
+//! - a send rejected for its nonce (too low, too high, already used) resynchronizes the nonce
+//!   from the chain, and is retried once,
+//! - a send failing before broadcast gives its nonce back, instead of leaving a gap,
+//! - a periodic check compares the next nonce with the pending transaction count of the account,
+//!   and resynchronizes it if they differ on two consecutive checks (a single difference is
+//!   usually a transaction being broadcast).
+//!
+//! Transactions are priced per the [`GasPolicy`] of the network: a send rejected as underpriced
+//! is retried with raised fees at the same nonce, and a transaction not mined in time is replaced,
+//! see [`gas`](crate::chain::gas).
+//!
+//! On every resynchronization, settlements waiting for a transaction the node no longer knows
+//! of fail with [`FacilitatorLocalError::ContractCall`]: the settlement queue retries them with a
+//! fresh nonce, see [`settlement_queue`](crate::settlement_queue). Operators can force a
+//! resynchronization with `POST /admin/nonce-resync`.
+//!
+//! Environment:
+//! - `NONCE_SYNC_INTERVAL_SECS` – Interval of the periodic check, in seconds or with a unit (e.g.
+//!   `1m`), up to 1 day (default `30`, `0` disables it)
+
+use alloy::network::{Ethereum, TransactionBuilder};
+use alloy::primitives::{Address, B256};
+use alloy::providers::{
+    PendingTransactionBuilder, PendingTransactionError, Provider, WatchTxError,
+};
+use alloy::rpc::types::{TransactionReceipt, TransactionRequest};
+use serde::Serialize;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{Mutex, watch};
+
+use crate::chain::FacilitatorLocalError;
+use crate::chain::evm::InnerProvider;
+use crate::chain::gas::{GasPolicy, is_underpriced_error};
+use crate::duration::{self, DurationRange};
+use crate::network::Network;
+
+const ENV_NONCE_SYNC_INTERVAL_SECS: &str = "NONCE_SYNC_INTERVAL_SECS";
+
+const DEFAULT_SYNC_INTERVAL: Duration = Duration::from_secs(30);
+const SYNC_INTERVAL_RANGE: DurationRange =
+    DurationRange::new(Duration::ZERO, Duration::from_secs(86_400));
+
+/// Interval of the periodic nonce check, from `NONCE_SYNC_INTERVAL_SECS`.
+/// Returns `None` if the check is disabled. Invalid values are logged, and ignored.
+pub fn sync_interval_from_env() -> Option<Duration> {
+    match duration::from_env(
+        ENV_NONCE_SYNC_INTERVAL_SECS,
+        duration::SECOND,
+        SYNC_INTERVAL_RANGE,
+    ) {
+        Ok(Some(Duration::ZERO)) => None,
+        Ok(Some(interval)) => Some(interval),
+        Ok(None) => Some(DEFAULT_SYNC_INTERVAL),
+        Err(e) => {
+            tracing::warn!(error = %e, "Ignoring {ENV_NONCE_SYNC_INTERVAL_SECS}");
+            Some(DEFAULT_SYNC_INTERVAL)
+        }
+    }
+}
+
+/// A correction of the next nonce of the settlement account.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NonceResync {
+    pub network: Network,
+    pub address: Address,
+    /// Next nonce before the resynchronization, `None` if no transaction was sent yet.
+    pub previous: Option<u64>,
+    /// Next nonce from now on: the pending transaction count of the account.
+    pub next: u64,
+}
+
+/// Locally tracked nonce of the settlement account.
+#[derive(Debug, Default)]
+struct AccountNonce {
+    /// Next nonce to use, `None` until fetched from the chain.
+    next: Option<u64>,
+    /// Pending transaction count seen by the last check, if it differed from `next`.
+    divergence: Option<u64>,
+}
+
+/// Next transaction nonce of a settlement account of a network, see the
+/// [module documentation](self).
+///
+/// Clones share the same state.
+#[derive(Clone, Debug)]
+pub struct AccountNonces {
+    network: Network,
+    /// The settlement account, signing with the wallet of the provider.
+    address: Address,
+    /// Provider sending the transactions of the account, in place of the one given, for accounts
+    /// whose key is not in the wallet of the latter.
+    sender: Option<InnerProvider>,
+    account: Arc<Mutex<AccountNonce>>,
+    resyncs: Arc<watch::Sender<Option<NonceResync>>>,
+}
+
+impl AccountNonces {
+    pub fn new(network: Network, address: Address) -> Self {
+        Self {
+            network,
+            address,
+            sender: None,
+            account: Arc::new(Mutex::new(AccountNonce::default())),
+            resyncs: Arc::new(watch::Sender::new(None)),
+        }
+    }
+
+    /// Sends the transactions of the account with `sender`, whose wallet holds its key.
+    pub fn with_sender(&self, sender: InnerProvider) -> Self {
+        let mut this = self.clone();
+        this.sender = Some(sender);
+        this
+    }
+
+    /// The settlement account.
+    pub fn address(&self) -> Address {
+        self.address
+    }
+
+    /// Reserves the next nonce, fetched from the chain the first time.
+    async fn reserve(&self, provider: &InnerProvider) -> Result<u64, FacilitatorLocalError> {
+        let mut account = self.account.lock().await;
+        let nonce = match account.next {
+            Some(next) => next,
+            None => pending_count(provider, self.address).await?,
+        };
+        account.next = Some(nonce + 1);
+        Ok(nonce)
+    }
+
+    /// Gives back `nonce` if no later one was reserved since, so that it does not leave a gap.
+    async fn release(&self, nonce: u64) {
+        let mut account = self.account.lock().await;
+        if account.next == Some(nonce + 1) {
+            account.next = Some(nonce);
+        }
+    }
+
+    /// Compares the next nonce with the pending transaction count of the account, and
+    /// resynchronizes it if they differed on the previous check too.
+    pub async fn check(
+        &self,
+        provider: &InnerProvider,
+    ) -> Result<Option<NonceResync>, FacilitatorLocalError> {
+        let mut account = self.account.lock().await;
+        let Some(next) = account.next else {
+            // Fetched from the chain on the first transaction anyway
+            return Ok(None);
+        };
+        let pending = pending_count(provider, self.address).await?;
+        if pending == next {
+            account.divergence = None;
+            return Ok(None);
+        }
+        if account.divergence != Some(pending) {
+            account.divergence = Some(pending);
+            return Ok(None);
+        }
+        Ok(Some(self.apply(&mut account, pending)))
+    }
+
+    /// Resynchronizes the next nonce with the pending transaction count of the account.
+    pub async fn resync(
+        &self,
+        provider: &InnerProvider,
+    ) -> Result<NonceResync, FacilitatorLocalError> {
+        let mut account = self.account.lock().await;
+        let pending = pending_count(provider, self.address).await?;
+        Ok(self.apply(&mut account, pending))
+    }
+
+    fn apply(&self, account: &mut AccountNonce, pending: u64) -> NonceResync {
+        let resync = NonceResync {
+            network: self.network,
+            address: self.address,
+            previous: account.next,
+            next: pending,
+        };
+        if resync.previous != Some(resync.next) {
+            tracing::warn!(
+                network = %self.network,
+                address = %self.address,
+                previous = ?resync.previous,
+                next = resync.next,
+                "Resynchronized the settlement account nonce"
+            );
+        }
+        account.next = Some(pending);
+        account.divergence = None;
+        self.resyncs.send_replace(Some(resync.clone()));
+        resync
+    }
+
+    /// Runs [`check`](Self::check) every `interval`, in a background task.
+    /// Must be called from within a Tokio runtime.
+    pub fn start(&self, provider: InnerProvider, interval: Duration) {
+        let this = self.clone();
+        tokio::spawn(async move {
+            let mut ticks = tokio::time::interval(interval);
+            ticks.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+            loop {
+                ticks.tick().await;
+                if let Err(error) = this.check(&provider).await {
+                    tracing::warn!(network = %this.network, address = %this.address, %error, "Failed to check the settlement account nonce");
+                }
+            }
+        });
+    }
+
+    /// Sends `tx` from the account, priced per `gas`, with the next nonce, and waits for its
+    /// receipt, through `provider` unless the account has a sender of its own.
+    ///
+    /// A send rejected for its nonce is retried once after a resynchronization, and a send
+    /// rejected as underpriced is retried with raised fees. The wait is abandoned if a
+    /// resynchronization happens while the node no longer knows the transaction.
+    pub async fn send_transaction(
+        &self,
+        provider: &InnerProvider,
+        tx: TransactionRequest,
+        gas: &GasPolicy,
+    ) -> Result<TransactionReceipt, FacilitatorLocalError> {
+        let provider = self.sender.as_ref().unwrap_or(provider);
+        let mut tx = tx.with_from(self.address);
+        let mut retried = false;
+        let mut escalations = 0;
+        let max_escalations = gas.escalation.map_or(0, |e| e.max_escalations);
+        loop {
+            let mut resyncs = self.resyncs.subscribe();
+            let nonce = self.reserve(provider).await?;
+            let pending = match provider
+                .send_transaction(tx.clone().with_nonce(nonce))
+                .await
+            {
+                Ok(pending) => pending,
+                Err(error) if !retried && is_nonce_error(&error.to_string()) => {
+                    tracing::warn!(network = %self.network, nonce, %error, "Settlement transaction rejected for its nonce");
+                    self.resync(provider).await?;
+                    retried = true;
+                    continue;
+                }
+                Err(error)
+                    if escalations < max_escalations
+                        && is_underpriced_error(&error.to_string()) =>
+                {
+                    self.release(nonce).await;
+                    let Some(raised) = gas.escalate(&tx) else {
+                        return Err(FacilitatorLocalError::ContractCall(format!("{error:?}")));
+                    };
+                    tracing::warn!(network = %self.network, nonce, %error, "Settlement transaction underpriced, raising its fees");
+                    tx = raised;
+                    escalations += 1;
+                    continue;
+                }
+                Err(error) => {
+                    self.release(nonce).await;
+                    return Err(FacilitatorLocalError::ContractCall(format!("{error:?}")));
+                }
+            };
+            let tx = tx.with_nonce(nonce);
+            return self
+                .confirm(provider, &mut resyncs, pending, tx, gas, escalations)
+                .await;
+        }
+    }
+
+    /// Waits for the receipt of `pending`, sent as `tx`, replacing it with raised fees per
+    /// `gas` while it is not mined, after `escalations` raises already.
+    async fn confirm(
+        &self,
+        provider: &InnerProvider,
+        resyncs: &mut watch::Receiver<Option<NonceResync>>,
+        mut pending: PendingTransactionBuilder<Ethereum>,
+        mut tx: TransactionRequest,
+        gas: &GasPolicy,
+        mut escalations: u32,
+    ) -> Result<TransactionReceipt, FacilitatorLocalError> {
+        let nonce = tx.nonce.unwrap_or_default();
+        // Every transaction sent with the nonce: any of them may be the one mined
+        let mut hashes = vec![*pending.tx_hash()];
+        loop {
+            let hash = *pending.tx_hash();
+            let timeout = gas.escalation.map(|escalation| escalation.after);
+            let receipt = tokio::select! {
+                receipt = pending.with_timeout(timeout).get_receipt() => receipt,
+                _ = dropped(provider, resyncs, hash) => {
+                    return Err(FacilitatorLocalError::ContractCall(format!(
+                        "Transaction {hash} with nonce {nonce} was dropped"
+                    )));
+                }
+            };
+            match receipt {
+                Ok(receipt) => return Ok(receipt),
+                Err(PendingTransactionError::TxWatcher(WatchTxError::Timeout)) => {}
+                Err(e) => return Err(FacilitatorLocalError::ContractCall(format!("{e:?}"))),
+            }
+            for hash in &hashes {
+                if let Ok(Some(receipt)) = provider.get_transaction_receipt(*hash).await {
+                    return Ok(receipt);
+                }
+            }
+            let max_escalations = gas.escalation.map_or(0, |e| e.max_escalations);
+            let raised = gas.escalate(&tx).filter(|_| escalations < max_escalations);
+            let Some(raised) = raised else {
+                pending = PendingTransactionBuilder::new(provider.root().clone(), hash);
+                continue;
+            };
+            escalations += 1;
+            match provider.send_transaction(raised.clone()).await {
+                Ok(replacement) => {
+                    tracing::warn!(network = %self.network, nonce, replaced = %hash, tx = %replacement.tx_hash(), "Settlement transaction not mined in time, replaced with raised fees");
+                    hashes.push(*replacement.tx_hash());
+                    tx = raised;
+                    pending = replacement;
+                }
+                Err(error) => {
+                    // Mined meanwhile, or not raised enough to replace it
+                    tracing::warn!(network = %self.network, nonce, %error, "Failed to replace the settlement transaction");
+                    if is_underpriced_error(&error.to_string()) {
+                        tx = raised;
+                    }
+                    pending = PendingTransactionBuilder::new(provider.root().clone(), hash);
+                }
+            }
+        }
+    }
+}
+
+/// Pending transaction count of settlement account `address`: the next nonce it can use.
+async fn pending_count(
+    provider: &InnerProvider,
+    address: Address,
+) -> Result<u64, FacilitatorLocalError> {
+    provider
+        .get_transaction_count(address)
+        .pending()
+        .await
+        .map_err(|e| FacilitatorLocalError::ContractCall(format!("{e:?}")))
+}
+
+/// Resolves once a resynchronization happens while the node does not know transaction `hash`.
+async fn dropped(
+    provider: &InnerProvider,
+    resyncs: &mut watch::Receiver<Option<NonceResync>>,
+    hash: B256,
+) {
+    while resyncs.changed().await.is_ok() {
+        if let Ok(None) = provider.get_transaction_by_hash(hash).await {
+            return;
+        }
+    }
+    std::future::pending().await
+}
+
+/// Whether a node rejected a transaction for its nonce. Nodes word it differently.
+fn is_nonce_error(message: &str) -> bool {
+    let message = message.to_lowercase();
+    [
+        "nonce too low",
+        "nonce too high",
+        "invalid nonce",
+        "already known",
+        "replacement transaction underpriced",
+    ]
+    .iter()
+    .any(|needle| message.contains(needle))
+}
@@ -6,8 +6,29 @@
 //! - **Settle**: if the signer wallet is not yet deployed, we deploy it (via the 6492
 //!   factory+calldata) and then call ERC-3009 `transferWithAuthorization` in a real tx.
 //!
+//! Every settlement transaction is simulated with `eth_call` before being broadcast: a payment
+//! bound to revert (authorization already used, bad signature, balance spent since verification)
+//! is refused without spending gas, with the revert reason of the token decoded into a precise
+//! [`FacilitatorErrorReason`], see [`revert`](crate::chain::revert). Refused settlements are
+//! counted in the `x402_settlements_reverted` metric.
+//!
+//! Payments of the `permit` scheme, for ERC-20 tokens without ERC-3009, carry an EIP-2612
+//! `permit` allowing the facilitator to move the tokens of the payer instead:
+//! - **Verify**: recover the signers of the permit and of its [`PermitIntent`], binding it to
+//!   the receiver, check the permit nonce against `nonces(owner)` and the balance of the payer,
+//!   and simulate `permit` in an `eth_call`.
+//! - **Settle**: submit `permit`, then `transferFrom` the payer to the receiver the
+//!   `maxAmountRequired`, in two transactions. A permit consumed already is refused, whatever
+//!   the allowance left: it can not be replayed.
+//!
+//! Payments of the `upto` scheme carry the same `permit`, of a maximum amount, and are settled
+//! the same way, transferring only the `consumedAmount` of the requirements. Later checkpoints
+//! of the same authorization, with a `settledAmount`, transfer from the allowance granted by
+//! the first settlement, without submitting the permit again.
+//!
 //! Assumptions:
-//! - Target tokens implement ERC-3009 and support ERC-1271 for contract signers.
+//! - Target tokens implement ERC-3009 and support ERC-1271 for contract signers, or implement
+//!   EIP-2612 for `permit` payments, signed by EOAs.
 //! - The validator contract exists at [`VALIDATOR_ADDRESS`] on supported chains.
 //!
 //! Invariants:
@@ -17,30 +38,43 @@
 use alloy::contract::SolCallBuilder;
 use alloy::dyn_abi::SolType;
 use alloy::network::{EthereumWallet, TransactionBuilder};
-use alloy::primitives::{Bytes, FixedBytes, U256, address};
+use alloy::primitives::{B256, Bytes, FixedBytes, U256, address};
 use alloy::providers::bindings::IMulticall3;
 use alloy::providers::fillers::{
     BlobGasFiller, ChainIdFiller, FillProvider, GasFiller, JoinFill, NonceFiller, WalletFiller,
 };
 use alloy::providers::{
-    Identity, MULTICALL3_ADDRESS, MulticallItem, Provider, RootProvider, WalletProvider,
+    Identity, MULTICALL3_ADDRESS, MulticallItem, Provider, ProviderBuilder, RootProvider,
+    WalletProvider,
 };
-use alloy::rpc::types::{TransactionReceipt, TransactionRequest};
-use alloy::sol_types::{Eip712Domain, SolCall, SolStruct, eip712_domain};
+use alloy::rpc::types::{BlockNumberOrTag, Filter, TransactionReceipt, TransactionRequest};
+use alloy::sol_types::{Eip712Domain, SolCall, SolEvent, SolStruct, eip712_domain};
 use alloy::{hex, sol};
+use serde::Serialize;
+use std::sync::Arc;
+use std::time::Duration;
 use tracing::{Instrument, instrument};
 use tracing_core::Level;
 
-use crate::chain::{FacilitatorLocalError, NetworkProviderOps};
+use crate::chain::account_nonce::{AccountNonces, NonceResync};
+use crate::chain::gas::GasPolicy;
+use crate::chain::multicall::{MulticallBatcher, SettlementBatching};
+use crate::chain::revert::RevertReason;
+use crate::chain::signer_pool::{SignerPool, SignerRotation};
+use crate::chain::{FacilitatorLocalError, NetworkProviderOps, verify_warnings};
 use crate::facilitator::Facilitator;
+use crate::lru_cache::LruCache;
 use crate::network::{Network, USDCDeployment};
 use crate::timestamp::UnixTimestamp;
+use crate::tokens::KnownToken;
 use crate::types::{
     EvmAddress, EvmSignature, ExactPaymentPayload, FacilitatorErrorReason, HexEncodedNonce,
-    MixedAddress, PaymentPayload, PaymentRequirements, Scheme, SettleRequest, SettleResponse,
-    SupportedPaymentKind, SupportedPaymentKindsResponse, TokenAmount, TransactionHash,
-    TransferWithAuthorization, VerifyRequest, VerifyResponse, X402Version,
+    MixedAddress, PaymentPayload, PaymentRequirements, Permit, PermitEvmPayload, PermitIntent,
+    Scheme, SettleRequest, SettleResponse, SettlementDetails, SupportedPaymentKind,
+    SupportedPaymentKindExtra, SupportedPaymentKindsResponse, TokenAmount, TokenAsset,
+    TransactionHash, TransferWithAuthorization, VerifyRequest, VerifyResponse, X402Version,
 };
+use crate::verify_pool;
 
 sol!(
     #[allow(missing_docs)]
@@ -65,6 +99,11 @@ sol! {
 const VALIDATOR_ADDRESS: alloy::primitives::Address =
     address!("0xdAcD51A54883eb67D95FAEb2BBfdC4a9a6BD2a3B");
 
+/// Token contracts whose EIP-712 domain version is remembered, per network.
+const EIP712_VERSION_CACHE_CAPACITY: usize = 1024;
+/// Time after which the EIP-712 domain version of a token is fetched again.
+const EIP712_VERSION_CACHE_TTL: Duration = Duration::from_secs(3600);
+
 /// The fully composed Ethereum provider type used in this project.
 ///
 /// Combines multiple filler layers for gas, nonce, chain ID, blob gas, and wallet signing,
@@ -118,6 +157,7 @@ impl TryFrom<Network> for EvmChain {
             Network::Polygon => Ok(EvmChain::new(value, 137)),
             Network::Sei => Ok(EvmChain::new(value, 1329)),
             Network::SeiTestnet => Ok(EvmChain::new(value, 1328)),
+            Network::Test => Ok(EvmChain::new(value, 1337)),
         }
     }
 }
@@ -143,15 +183,51 @@ pub struct ExactEvmPayment {
     pub signature: EvmSignature,
 }
 
+/// A fully specified EIP-2612 permit payload for EVM settlement.
+pub struct PermitEvmPayment {
+    /// Owner of the tokens, who signed the permit.
+    pub owner: EvmAddress,
+    /// Account allowed to move the tokens: the facilitator.
+    pub spender: EvmAddress,
+    /// Recipient of the transfer, from the requirements.
+    pub to: EvmAddress,
+    /// Permitted amount (token units).
+    pub value: TokenAmount,
+    /// Maximum amount required, signed in the [`PermitIntent`] along `to`.
+    pub max_amount: TokenAmount,
+    /// Amount transferred: the maximum required for `permit` payments, the amount consumed for
+    /// `upto` payments.
+    pub amount: TokenAmount,
+    /// Whether an amount of the same `upto` authorization is settled already, the permit having
+    /// been submitted by the first settlement.
+    pub checkpoint: bool,
+    /// Permit nonce of `owner` on the token contract.
+    pub nonce: U256,
+    /// Not valid at/after this timestamp.
+    pub deadline: UnixTimestamp,
+    /// Raw 65-byte ECDSA signature of the permit.
+    pub signature: EvmSignature,
+    /// Raw 65-byte ECDSA signature of the [`PermitIntent`].
+    pub intent_signature: EvmSignature,
+}
+
 /// EVM implementation of the x402 facilitator.
 ///
 /// Holds a composed Alloy ethereum provider [`InnerProvider`],
-/// an `eip1559` toggle for gas pricing strategy, and the `EvmChain` context.
+/// an `eip1559` toggle and a [`GasPolicy`] for gas pricing strategy, and the `EvmChain` context.
 #[derive(Clone, Debug)]
 pub struct EvmProvider {
     inner: InnerProvider,
     eip1559: bool,
+    /// Fees of settlement transactions.
+    gas: Arc<GasPolicy>,
     chain: EvmChain,
+    /// EIP-712 domain versions fetched from token contracts, by token address.
+    eip712_versions: LruCache<alloy::primitives::Address, String>,
+    /// Queue of settlements submitted in Multicall3 batches, if batching is enabled.
+    settlement_batcher: Option<MulticallBatcher>,
+    /// Settlement accounts, with their transaction nonces.
+    signers: SignerPool,
 }
 
 impl EvmProvider {
@@ -165,13 +241,76 @@ impl EvmProvider {
         network: Network,
     ) -> Result<Self, FacilitatorLocalError> {
         let chain = EvmChain::try_from(network)?;
+        let signers = SignerPool::from_wallet(network, inner.wallet());
         Ok(Self {
             inner,
             eip1559,
+            gas: Arc::new(GasPolicy::default()),
             chain,
+            eip712_versions: LruCache::new("eip712_versions", EIP712_VERSION_CACHE_CAPACITY)
+                .with_ttl(EIP712_VERSION_CACHE_TTL),
+            settlement_batcher: None,
+            signers,
         })
     }
 
+    /// Settles payments in Multicall3 batches, see [`multicall`](crate::chain::multicall).
+    /// Must be called from within a Tokio runtime.
+    pub fn with_settlement_batching(&self, batching: SettlementBatching) -> Self {
+        let mut this = self.clone();
+        this.settlement_batcher = Some(MulticallBatcher::start(
+            self.inner.clone(),
+            self.signers.clone(),
+            self.eip1559,
+            *self.gas,
+            batching,
+        ));
+        this
+    }
+
+    /// Prices settlement transactions per `gas`, see [`gas`](crate::chain::gas). Call it before
+    /// [`with_settlement_batching`](Self::with_settlement_batching), for batches to follow it too.
+    pub fn with_gas_policy(&self, gas: GasPolicy) -> Self {
+        let mut this = self.clone();
+        this.gas = Arc::new(gas);
+        this
+    }
+
+    /// Checks the nonces of the settlement accounts against the chain every `interval`, see
+    /// [`account_nonce`](crate::chain::account_nonce). Must be called from within a Tokio runtime.
+    pub fn start_nonce_sync(&self, interval: Duration) {
+        self.signers.start(self.inner.clone(), interval);
+    }
+
+    /// Resynchronizes the nonces of the settlement accounts with the chain.
+    ///
+    /// # Errors
+    /// Return [`FacilitatorLocalError::ContractCall`] if an RPC call fails.
+    pub async fn resync_nonces(&self) -> Result<Vec<NonceResync>, FacilitatorLocalError> {
+        self.signers.resync(&self.inner).await
+    }
+
+    /// Settlement accounts of the network, see [`signer_pool`](crate::chain::signer_pool).
+    pub fn signers(&self) -> &SignerPool {
+        &self.signers
+    }
+
+    /// Replaces the settlement accounts with the signers of `wallet`, its default signer
+    /// becoming the primary account, see [`SignerPool::rotate`]. Every clone of the provider
+    /// follows.
+    pub fn rotate_signers(&self, wallet: EthereumWallet) -> SignerRotation {
+        let previous = self.signers.addresses();
+        let sender = ProviderBuilder::new()
+            .wallet(wallet)
+            .connect_provider(self.inner.root().clone());
+        self.signers.rotate(sender);
+        SignerRotation {
+            network: self.chain.network,
+            previous,
+            current: self.signers.addresses(),
+        }
+    }
+
     /// Runs all preconditions needed for a successful payment:
     /// - Valid scheme, network, and receiver.
     /// - Valid time window (validAfter/validBefore).
@@ -196,6 +335,11 @@ impl EvmProvider {
             ExactPaymentPayload::Solana(_) => {
                 return Err(FacilitatorLocalError::UnsupportedNetwork(None));
             }
+            ExactPaymentPayload::Permit(_) | ExactPaymentPayload::Custom(_) => {
+                return Err(FacilitatorLocalError::UnsupportedScheme(
+                    payload.scheme.clone(),
+                ));
+            }
         };
         let payer = payment_payload.authorization.from;
         if payload.network != self.network() {
@@ -215,8 +359,13 @@ impl EvmProvider {
         if payload.scheme != requirements.scheme {
             return Err(FacilitatorLocalError::SchemeMismatch(
                 Some(payer.into()),
-                requirements.scheme,
-                payload.scheme,
+                requirements.scheme.clone(),
+                payload.scheme.clone(),
+            ));
+        }
+        if payload.scheme != Scheme::Exact {
+            return Err(FacilitatorLocalError::UnsupportedScheme(
+                payload.scheme.clone(),
             ));
         }
         let payload_to: EvmAddress = payment_payload.authorization.to;
@@ -270,11 +419,339 @@ impl EvmProvider {
         Ok((contract, payment, domain))
     }
 
+    /// Runs all preconditions needed for a successful `permit` or `upto` payment:
+    /// - Valid scheme and network, and the facilitator as spender.
+    /// - Valid deadline.
+    /// - Correct EIP-712 domain construction.
+    /// - Sufficient value in payload, and for `upto` payments, a consumed amount within it.
+    /// - Sufficient on-chain balance for the amount transferred.
+    #[instrument(skip_all, err)]
+    async fn assert_valid_permit(
+        &self,
+        payload: &PaymentPayload,
+        permit: &PermitEvmPayload,
+        requirements: &PaymentRequirements,
+    ) -> Result<
+        (
+            USDC::USDCInstance<&InnerProvider>,
+            PermitEvmPayment,
+            Eip712Domain,
+        ),
+        FacilitatorLocalError,
+    > {
+        let payer = permit.permit.owner;
+        if payload.network != self.network() {
+            return Err(FacilitatorLocalError::NetworkMismatch(
+                Some(payer.into()),
+                self.network(),
+                payload.network,
+            ));
+        }
+        if requirements.network != self.network() {
+            return Err(FacilitatorLocalError::NetworkMismatch(
+                Some(payer.into()),
+                self.network(),
+                requirements.network,
+            ));
+        }
+        if payload.scheme != requirements.scheme {
+            return Err(FacilitatorLocalError::SchemeMismatch(
+                Some(payer.into()),
+                requirements.scheme.clone(),
+                payload.scheme.clone(),
+            ));
+        }
+        if !matches!(payload.scheme, Scheme::Permit | Scheme::Upto) {
+            return Err(FacilitatorLocalError::UnsupportedScheme(
+                payload.scheme.clone(),
+            ));
+        }
+        // Any settlement account can move the tokens, the primary one being advertised
+        if self.signers.by_address(permit.permit.spender.0).is_none() {
+            let facilitator: EvmAddress = self.signers.primary().address().into();
+            return Err(FacilitatorLocalError::ReceiverMismatch(
+                payer.into(),
+                permit.permit.spender.to_string(),
+                facilitator.to_string(),
+            ));
+        }
+        assert_time(payer.into(), UnixTimestamp(0), permit.permit.deadline)?;
+        let to: EvmAddress = requirements
+            .pay_to
+            .clone()
+            .try_into()
+            .map_err(|e| FacilitatorLocalError::InvalidAddress(format!("{e:?}")))?;
+        let asset_address = requirements
+            .asset
+            .clone()
+            .try_into()
+            .map_err(|e| FacilitatorLocalError::InvalidAddress(format!("{e:?}")))?;
+        let contract = USDC::new(asset_address, &self.inner);
+
+        let domain = self
+            .assert_domain(&contract, payload, &asset_address, requirements)
+            .await?;
+
+        let amount_required = requirements.max_amount_required.0;
+        let value: U256 = permit.permit.value.into();
+        assert_enough_value(&payer, &value, &amount_required)?;
+        // The maximum required is transferred, or the amount consumed of it, on top of the amount
+        // of the same authorization settled already by channel checkpoints
+        let settled = requirements.settled_amount.map(|s| s.0).unwrap_or_default();
+        let amount = match payload.scheme {
+            Scheme::Upto => {
+                let consumed = requirements
+                    .consumed_amount
+                    .unwrap_or(requirements.max_amount_required);
+                if consumed
+                    .0
+                    .checked_add(settled)
+                    .is_none_or(|total| total > amount_required)
+                {
+                    return Err(FacilitatorLocalError::InsufficientValue(payer.into()));
+                }
+                consumed
+            }
+            _ => requirements.max_amount_required,
+        };
+        assert_enough_balance(&contract, &payer, amount.into()).await?;
+
+        let payment = PermitEvmPayment {
+            owner: payer,
+            spender: permit.permit.spender,
+            to,
+            value: permit.permit.value,
+            max_amount: requirements.max_amount_required,
+            amount,
+            checkpoint: payload.scheme == Scheme::Upto && !settled.is_zero(),
+            nonce: permit.permit.nonce.into(),
+            deadline: permit.permit.deadline,
+            signature: permit.signature.clone(),
+            intent_signature: permit.intent_signature.clone(),
+        };
+
+        Ok((contract, payment, domain))
+    }
+
+    /// Checks the permit nonce of `payment` against `nonces(owner)` on the token contract, and
+    /// returns whether `permit` must be submitted before the transfer.
+    ///
+    /// A first settlement must carry the permit of the current nonce of the owner, always
+    /// submitted: a permit consumed already is not replayed against the allowance left over.
+    /// Later checkpoints of an `upto` authorization transfer from the allowance granted by the
+    /// first settlement, whose permit must be consumed, and the allowance must cover the amount.
+    ///
+    /// # Errors
+    /// Return [`FacilitatorLocalError::NonceReused`] if the permit is consumed already,
+    /// [`FacilitatorLocalError::InvalidSignature`] if the nonce is not the one expected,
+    /// [`FacilitatorLocalError::InsufficientValue`] if the allowance of a checkpoint does not
+    /// cover the amount, or [`FacilitatorLocalError::ContractCall`] if a query fails.
+    async fn needs_permit(
+        &self,
+        contract: &USDC::USDCInstance<&InnerProvider>,
+        payment: &PermitEvmPayment,
+    ) -> Result<bool, FacilitatorLocalError> {
+        let nonce = contract
+            .nonces(payment.owner.0)
+            .call()
+            .into_future()
+            .instrument(tracing::info_span!(
+                "fetch_permit_nonce",
+                owner = %payment.owner,
+                token_contract = %contract.address(),
+                otel.kind = "client"
+            ))
+            .await
+            .map_err(|e| FacilitatorLocalError::ContractCall(format!("{e:?}")))?;
+        if !payment.checkpoint {
+            return match nonce.cmp(&payment.nonce) {
+                std::cmp::Ordering::Equal => Ok(true),
+                std::cmp::Ordering::Greater => Err(FacilitatorLocalError::NonceReused(Some(
+                    payment.owner.into(),
+                ))),
+                std::cmp::Ordering::Less => Err(FacilitatorLocalError::InvalidSignature(
+                    payment.owner.into(),
+                    format!(
+                        "Permit nonce {} is ahead of the owner nonce {nonce}",
+                        payment.nonce
+                    ),
+                )),
+            };
+        }
+        if nonce <= payment.nonce {
+            return Err(FacilitatorLocalError::InvalidSignature(
+                payment.owner.into(),
+                format!(
+                    "Permit nonce {} of a settled authorization is not consumed",
+                    payment.nonce
+                ),
+            ));
+        }
+        let allowance = contract
+            .allowance(payment.owner.0, payment.spender.0)
+            .call()
+            .into_future()
+            .instrument(tracing::info_span!(
+                "fetch_allowance",
+                owner = %payment.owner,
+                spender = %payment.spender,
+                token_contract = %contract.address(),
+                otel.kind = "client"
+            ))
+            .await
+            .map_err(|e| FacilitatorLocalError::ContractCall(format!("{e:?}")))?;
+        if allowance < payment.amount.into() {
+            return Err(FacilitatorLocalError::InsufficientValue(
+                payment.owner.into(),
+            ));
+        }
+        Ok(false)
+    }
+
+    /// Constructs the `permit` transaction of a verified permit payment, signed by `signature`.
+    fn permit_request(
+        contract: &USDC::USDCInstance<&InnerProvider>,
+        payment: &PermitEvmPayment,
+        signature: &alloy::primitives::Signature,
+    ) -> TransactionRequest {
+        contract
+            .permit_1(
+                payment.owner.0,
+                payment.spender.0,
+                payment.value.into(),
+                payment.deadline.into(),
+                27 + signature.v() as u8,
+                signature.r().into(),
+                signature.s().into(),
+            )
+            .into_transaction_request()
+    }
+
+    /// Verifies a `permit` or `upto` payment: recovers the signers of the permit and of its
+    /// intent, checks the permit nonce, and simulates `permit` unless the payment is a checkpoint
+    /// of an `upto` authorization settled already.
+    async fn verify_permit(
+        &self,
+        payload: &PaymentPayload,
+        permit: &PermitEvmPayload,
+        requirements: &PaymentRequirements,
+    ) -> Result<VerifyResponse, FacilitatorLocalError> {
+        let (contract, payment, domain) = self
+            .assert_valid_permit(payload, permit, requirements)
+            .await?;
+        let signature = recover_permit_signature(&payment, &domain).await?;
+        if self.needs_permit(&contract, &payment).await? {
+            let tx = Self::permit_request(&contract, &payment, &signature);
+            self.inner
+                .call(tx)
+                .into_future()
+                .instrument(tracing::info_span!("call_permit",
+                    owner = %payment.owner,
+                    spender = %payment.spender,
+                    value = %payment.value,
+                    deadline = %payment.deadline,
+                    token_contract = %contract.address(),
+                    otel.kind = "client",
+                ))
+                .await
+                .map_err(|e| {
+                    let revert_data = e.as_error_resp().and_then(|e| e.as_revert_data());
+                    simulation_error(payment.owner.0, revert_data, e)
+                })?;
+        }
+        let warnings = verify_warnings(requirements, Some(payment.value), Some(payment.deadline));
+        Ok(VerifyResponse::valid(payment.owner.into()).with_warnings(warnings))
+    }
+
+    /// Settles a `permit` or `upto` payment: submits `permit` unless the payment is a checkpoint
+    /// of an `upto` authorization settled already, then transfers the maximum required, or the
+    /// amount consumed, to the receiver with `transferFrom`. Not batched, even with [settlement batching](Self::with_settlement_batching):
+    /// `transferFrom` must be sent by the facilitator itself.
+    async fn settle_permit(
+        &self,
+        payload: &PaymentPayload,
+        permit: &PermitEvmPayload,
+        requirements: &PaymentRequirements,
+    ) -> Result<SettleResponse, FacilitatorLocalError> {
+        let (contract, payment, domain) = self
+            .assert_valid_permit(payload, permit, requirements)
+            .await?;
+        let signature = recover_permit_signature(&payment, &domain).await?;
+        let failed = |receipt: &TransactionReceipt, step: &str| {
+            tracing::event!(
+                Level::WARN,
+                status = "failed",
+                tx = %receipt.transaction_hash,
+                "{step} failed"
+            );
+            SettleResponse {
+                success: false,
+                error_reason: Some(FacilitatorErrorReason::InvalidScheme),
+                payer: payment.owner.into(),
+                transaction: Some(TransactionHash::Evm(receipt.transaction_hash.0)),
+                network: payload.network,
+                settlement: None,
+                confirmations: None,
+                metadata: None,
+                receipt: None,
+            }
+        };
+        // Sent by the spender, checked to be one of the settlement accounts
+        let spender = self
+            .signers
+            .by_address(payment.spender.0)
+            .unwrap_or_else(|| self.signers.primary());
+        if self.needs_permit(&contract, &payment).await? {
+            let tx = Self::permit_request(&contract, &payment, &signature);
+            self.simulate_from(spender.address(), payment.owner.0, &tx)
+                .await?;
+            let receipt = self.send_transaction_from(&spender, tx).await?;
+            if !receipt.status() {
+                return Ok(failed(&receipt, "permit"));
+            }
+        }
+        let tx = contract
+            .transferFrom(payment.owner.0, payment.to.0, payment.amount.into())
+            .into_transaction_request();
+        self.simulate_from(spender.address(), payment.owner.0, &tx)
+            .await?;
+        let receipt = self.send_transaction_from(&spender, tx).await?;
+        if !receipt.status() {
+            return Ok(failed(&receipt, "transferFrom"));
+        }
+        let settlement = settlement_details(
+            &receipt,
+            payment.owner,
+            payment.to,
+            payment.amount,
+            None,
+            *contract.address(),
+        );
+        tracing::event!(Level::INFO,
+            status = "ok",
+            tx = %receipt.transaction_hash,
+            amount = settlement.as_ref().map(|s| s.amount.to_string()),
+            log_index = settlement.as_ref().and_then(|s| s.log_index),
+            "transferFrom succeeded"
+        );
+        Ok(SettleResponse {
+            success: true,
+            error_reason: None,
+            payer: payment.owner.into(),
+            transaction: Some(TransactionHash::Evm(receipt.transaction_hash.0)),
+            network: payload.network,
+            settlement,
+            confirmations: receipt.block_number.map(|_| 1),
+            metadata: None,
+            receipt: None,
+        })
+    }
+
     /// Constructs a full `transferWithAuthorization` call for a verified payment payload.
     ///
-    /// This function prepares the transaction builder with gas pricing adapted to the network's
-    /// capabilities (EIP-1559 or legacy) and packages it together with signature metadata
-    /// into a [`TransferWithAuthorization0Call`] structure.
+    /// This function prepares the transaction builder and packages it together with signature
+    /// metadata into a [`TransferWithAuthorization0Call`] structure. Fees are set when the
+    /// transaction is sent, per the [`GasPolicy`].
     ///
     /// This function does not perform any validation — it assumes inputs are already checked.
     #[allow(non_snake_case)]
@@ -299,17 +776,6 @@ impl EvmProvider {
             nonce,
             signature.clone(),
         );
-        let tx = if self.eip1559 {
-            tx
-        } else {
-            let provider = contract.provider();
-            let gas: u128 = provider
-                .get_gas_price()
-                .instrument(tracing::info_span!("get_gas_price"))
-                .await
-                .map_err(|e| FacilitatorLocalError::ContractCall(format!("{e:?}")))?;
-            tx.gas_price(gas)
-        };
         Ok(TransferWithAuthorization0Call {
             tx,
             from,
@@ -326,8 +792,10 @@ impl EvmProvider {
     /// Constructs the correct EIP-712 domain for signature verification.
     ///
     /// Resolves the `name` and `version` based on:
-    /// - Static metadata from [`USDCDeployment`] (if available),
-    /// - Or by calling `version()` on the token contract if not matched statically.
+    /// - Static metadata of the asset from the known tokens (see [`KnownToken`]), or of the USDC
+    ///   deployment of the network for the name,
+    /// - Or by calling `version()` on the token contract if not matched statically, cached
+    ///   per token for [`EIP712_VERSION_CACHE_TTL`].
     #[instrument(skip_all, err, fields(
         network = %payload.network,
         asset = %asset_address
@@ -340,10 +808,16 @@ impl EvmProvider {
         requirements: &PaymentRequirements,
     ) -> Result<Eip712Domain, FacilitatorLocalError> {
         let usdc = USDCDeployment::by_network(payload.network);
+        let known = KnownToken::by_asset(&TokenAsset {
+            address: (*asset_address).into(),
+            network: payload.network,
+        })
+        .and_then(|token| token.eip712.clone());
         let name = requirements
             .extra
             .as_ref()
             .and_then(|e| e.get("name")?.as_str().map(str::to_string))
+            .or_else(|| known.clone().map(|e| e.name))
             .or_else(|| usdc.eip712.clone().map(|e| e.name))
             .ok_or(FacilitatorLocalError::UnsupportedNetwork(None))?;
         let chain_id = self.chain.chain_id;
@@ -352,17 +826,13 @@ impl EvmProvider {
             .as_ref()
             .and_then(|extra| extra.get("version"))
             .and_then(|version| version.as_str().map(|s| s.to_string()));
-        let version = if let Some(extra_version) = version {
-            Some(extra_version)
-        } else if usdc.address() == (*asset_address).into() {
-            usdc.eip712.clone().map(|e| e.version)
-        } else {
-            None
-        };
+        let version = version.or_else(|| known.map(|e| e.version));
         let version = if let Some(version) = version {
             version
+        } else if let Some(version) = self.eip712_versions.get(asset_address) {
+            version
         } else {
-            token_contract
+            let version = token_contract
                 .version()
                 .call()
                 .into_future()
@@ -371,7 +841,9 @@ impl EvmProvider {
                     otel.kind = "client",
                 ))
                 .await
-                .map_err(|e| FacilitatorLocalError::ContractCall(format!("{e:?}")))?
+                .map_err(|e| FacilitatorLocalError::ContractCall(format!("{e:?}")))?;
+            self.eip712_versions.insert(*asset_address, version.clone());
+            version
         };
         let domain = eip712_domain! {
             name: name,
@@ -407,11 +879,86 @@ impl EvmProvider {
         Ok(!bytes.is_empty())
     }
 
-    /// Send a prepared transaction and wait for its receipt.
+    /// Simulates `tx` sent by settlement account `from`, so that a payment of `payer` bound to
+    /// revert is refused before any gas is spent.
+    ///
+    /// # Errors
+    /// Return [`FacilitatorLocalError::Reverted`] if the token reverts with a known reason, and
+    /// [`FacilitatorLocalError::ContractCall`] if the call fails otherwise.
+    async fn simulate_from(
+        &self,
+        from: alloy::primitives::Address,
+        payer: alloy::primitives::Address,
+        tx: &TransactionRequest,
+    ) -> Result<(), FacilitatorLocalError> {
+        self.inner
+            .call(tx.clone().with_from(from))
+            .into_future()
+            .instrument(tracing::info_span!("simulate_settlement",
+                from = %from,
+                otel.kind = "client",
+            ))
+            .await
+            .map(|_| ())
+            .map_err(|e| {
+                let revert_data = e.as_error_resp().and_then(|e| e.as_revert_data());
+                simulation_error(payer, revert_data, e)
+            })
+            .inspect_err(warn_reverted)
+    }
+
+    /// Simulates `transferWithAuthorization` of a payment of `payer`, preceded by the deployment
+    /// of the wallet of the payer if needed, see [`simulate_from`](Self::simulate_from).
+    async fn simulate_transfer(
+        &self,
+        payer: alloy::primitives::Address,
+        deployment_call: Option<&IMulticall3::Call3>,
+        transfer_call: IMulticall3::Call3,
+    ) -> Result<(), FacilitatorLocalError> {
+        let from = self.signers.primary().address();
+        let Some(deployment_call) = deployment_call else {
+            let tx = TransactionRequest::default()
+                .with_to(transfer_call.target)
+                .with_input(transfer_call.callData);
+            return self.simulate_from(from, payer, &tx).await;
+        };
+        // Both calls in the same simulation, for the transfer to see the deployed wallet
+        let aggregate_call = IMulticall3::aggregate3Call {
+            calls: vec![deployment_call.clone(), transfer_call],
+        };
+        let tx = TransactionRequest::default()
+            .with_from(from)
+            .with_to(MULTICALL3_ADDRESS)
+            .with_input(aggregate_call.abi_encode());
+        let output = self
+            .inner
+            .call(tx)
+            .into_future()
+            .instrument(tracing::info_span!("simulate_settlement",
+                from = %from,
+                otel.kind = "client",
+            ))
+            .await
+            .map_err(|e| FacilitatorLocalError::ContractCall(format!("{e:?}")))?;
+        let results = IMulticall3::aggregate3Call::abi_decode_returns(&output)
+            .map_err(|e| FacilitatorLocalError::ContractCall(format!("{e:?}")))?;
+        match results.last() {
+            Some(result) if !result.success => Err(simulation_error(
+                payer,
+                Some(result.returnData.clone()),
+                "transferWithAuthorization reverted",
+            ))
+            .inspect_err(warn_reverted),
+            _ => Ok(()),
+        }
+    }
+
+    /// Send a prepared transaction from the next settlement account and wait for its receipt.
     ///
     /// Convenience wrapper that:
-    /// 1) calls `send_transaction` on the inner provider, and
-    /// 2) awaits the receipt.
+    /// 1) sets the fees of the transaction per the [`GasPolicy`],
+    /// 2) sends it with the next nonce of the next settlement account of the [`SignerPool`], and
+    /// 3) awaits the receipt, replacing the transaction if it is not mined in time.
     ///
     /// # Errors
     /// Return [`FacilitatorLocalError::ContractCall`] if tx sending or receipt retrieval fails.
@@ -419,21 +966,180 @@ impl EvmProvider {
         &self,
         tx: TransactionRequest,
     ) -> Result<TransactionReceipt, FacilitatorLocalError> {
-        let tx = self
+        self.send_transaction_from(&self.signers.next(), tx).await
+    }
+
+    /// Send a prepared transaction from settlement account `account`, see
+    /// [`send_transaction`](Self::send_transaction).
+    async fn send_transaction_from(
+        &self,
+        account: &AccountNonces,
+        tx: TransactionRequest,
+    ) -> Result<TransactionReceipt, FacilitatorLocalError> {
+        let tx = self.gas.apply(&self.inner, self.eip1559, tx).await?;
+        account.send_transaction(&self.inner, tx, &self.gas).await
+    }
+
+    /// Number of the latest block.
+    ///
+    /// # Errors
+    /// Return [`FacilitatorLocalError::ContractCall`] if the RPC call fails.
+    pub async fn block_number(&self) -> Result<u64, FacilitatorLocalError> {
+        self.inner
+            .get_block_number()
+            .await
+            .map_err(|e| FacilitatorLocalError::ContractCall(format!("{e:?}")))
+    }
+
+    /// Balance of `account` in the native token of the chain, in wei.
+    ///
+    /// # Errors
+    /// Return [`FacilitatorLocalError::ContractCall`] if the RPC call fails.
+    pub async fn native_balance(
+        &self,
+        account: alloy::primitives::Address,
+    ) -> Result<U256, FacilitatorLocalError> {
+        self.inner
+            .get_balance(account)
+            .await
+            .map_err(|e| FacilitatorLocalError::ContractCall(format!("{e:?}")))
+    }
+
+    /// Number of the head block `tag`, e.g. [`BlockNumberOrTag::Safe`] or
+    /// [`BlockNumberOrTag::Finalized`], or `None` if the node does not know it yet.
+    ///
+    /// On rollups, the safe head is derived from data posted to L1, and the finalized head from
+    /// finalized L1 blocks.
+    ///
+    /// # Errors
+    /// Return [`FacilitatorLocalError::ContractCall`] if the RPC call fails, e.g. on nodes not
+    /// supporting the tag.
+    pub async fn tagged_block_number(
+        &self,
+        tag: BlockNumberOrTag,
+    ) -> Result<Option<u64>, FacilitatorLocalError> {
+        let block = self
             .inner
-            .send_transaction(tx)
+            .get_block_by_number(tag)
             .await
             .map_err(|e| FacilitatorLocalError::ContractCall(format!("{e:?}")))?;
-        tx.get_receipt()
+        Ok(block.map(|block| block.header.number))
+    }
+
+    /// Number of the block including transaction `hash`, or `None` if the transaction
+    /// is not (or no longer) included in the canonical chain.
+    ///
+    /// # Errors
+    /// Return [`FacilitatorLocalError::ContractCall`] if the RPC call fails.
+    pub async fn transaction_block_number(
+        &self,
+        hash: B256,
+    ) -> Result<Option<u64>, FacilitatorLocalError> {
+        let receipt = self
+            .inner
+            .get_transaction_receipt(hash)
             .await
-            .map_err(|e| FacilitatorLocalError::ContractCall(format!("{e:?}")))
+            .map_err(|e| FacilitatorLocalError::ContractCall(format!("{e:?}")))?;
+        Ok(receipt.and_then(|receipt| receipt.block_number))
+    }
+
+    /// Broadcasts transaction `hash` again, e.g. after a reorganization returned it to the
+    /// mempool of the node. Returns `false` if the node does not know the transaction anymore.
+    ///
+    /// # Errors
+    /// Return [`FacilitatorLocalError::ContractCall`] if an RPC call fails.
+    pub async fn rebroadcast(&self, hash: B256) -> Result<bool, FacilitatorLocalError> {
+        let Some(raw) = self
+            .inner
+            .get_raw_transaction_by_hash(hash)
+            .await
+            .map_err(|e| FacilitatorLocalError::ContractCall(format!("{e:?}")))?
+        else {
+            return Ok(false);
+        };
+        match self.inner.send_raw_transaction(&raw).await {
+            Ok(_) => Ok(true),
+            // Still in the mempool of the node
+            Err(e) if e.to_string().contains("already known") => Ok(true),
+            Err(e) => Err(FacilitatorLocalError::ContractCall(format!("{e:?}"))),
+        }
+    }
+
+    /// ERC-20 `Transfer`s of `token` to any of `recipients`, in blocks `from_block..=to_block`.
+    ///
+    /// # Errors
+    /// Return [`FacilitatorLocalError::ContractCall`] if the `eth_getLogs` call fails.
+    #[allow(dead_code)] // Public for consumption by downstream crates.
+    pub async fn transfers_to(
+        &self,
+        token: EvmAddress,
+        recipients: &[EvmAddress],
+        from_block: u64,
+        to_block: u64,
+    ) -> Result<Vec<TokenTransfer>, FacilitatorLocalError> {
+        let recipients: Vec<B256> = recipients
+            .iter()
+            .map(|recipient| recipient.0.into_word())
+            .collect();
+        let filter = Filter::new()
+            .address(token.0)
+            .event_signature(USDC::Transfer::SIGNATURE_HASH)
+            .topic2(recipients)
+            .from_block(from_block)
+            .to_block(to_block);
+        let logs = self
+            .inner
+            .get_logs(&filter)
+            .instrument(tracing::info_span!("get_logs",
+                token = %token,
+                from_block,
+                to_block,
+                otel.kind = "client",
+            ))
+            .await
+            .map_err(|e| FacilitatorLocalError::ContractCall(format!("{e:?}")))?;
+        let transfers = logs
+            .iter()
+            .filter_map(|log| {
+                let transfer = log.log_decode::<USDC::Transfer>().ok()?;
+                Some(TokenTransfer {
+                    network: self.network(),
+                    token,
+                    from: transfer.inner.from.into(),
+                    to: transfer.inner.to.into(),
+                    value: transfer.inner.value.into(),
+                    transaction: TransactionHash::Evm(log.transaction_hash?.0),
+                    block_number: log.block_number?,
+                    log_index: log.log_index?,
+                    metadata: None,
+                })
+            })
+            .collect();
+        Ok(transfers)
     }
 }
 
+/// An ERC-20 transfer observed on-chain, see [`EvmProvider::transfers_to`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TokenTransfer {
+    pub network: Network,
+    pub token: EvmAddress,
+    pub from: EvmAddress,
+    pub to: EvmAddress,
+    pub value: TokenAmount,
+    pub transaction: TransactionHash,
+    pub block_number: u64,
+    pub log_index: u64,
+    /// Metadata of the settle request, if the transfer settled a payment on this facilitator.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub metadata: Option<serde_json::Value>,
+}
+
 impl NetworkProviderOps for EvmProvider {
     /// Address of the default signer used by this provider (for tx sending).
     fn signer_address(&self) -> MixedAddress {
-        self.inner.default_signer_address().into()
+        self.signers.primary().address().into()
     }
 
     /// x402 network handled by this provider.
@@ -446,6 +1152,7 @@ impl Facilitator for EvmProvider {
     type Error = FacilitatorLocalError;
 
     /// Verify x402 payment intent by simulating signature validity and ERC-3009 transfer.
+    /// Payments of the `permit` and `upto` schemes are verified by simulating `permit` instead.
     ///
     /// For EIP-6492 signatures, perform a multicall: first the validator’s
     /// `isValidSigWithSideEffects` (which *may* deploy the counterfactual wallet in sim),
@@ -456,14 +1163,18 @@ impl Facilitator for EvmProvider {
     /// - [`FacilitatorLocalError::NetworkMismatch`], [`FacilitatorLocalError::SchemeMismatch`], [`FacilitatorLocalError::ReceiverMismatch`] if inputs are inconsistent.
     /// - [`FacilitatorLocalError::InvalidTiming`] if outside `validAfter/validBefore`.
     /// - [`FacilitatorLocalError::InsufficientFunds`] / `FacilitatorLocalError::InsufficientValue` on balance/value checks.
-    /// - [`FacilitatorLocalError::ContractCall`] if on-chain calls revert.
+    /// - [`FacilitatorLocalError::Reverted`] if the simulated transfer reverts with a known reason.
+    /// - [`FacilitatorLocalError::ContractCall`] if on-chain calls revert otherwise.
     async fn verify(&self, request: &VerifyRequest) -> Result<VerifyResponse, Self::Error> {
         let payload = &request.payment_payload;
         let requirements = &request.payment_requirements;
+        if let ExactPaymentPayload::Permit(permit) = &payload.payload {
+            return self.verify_permit(payload, permit, requirements).await;
+        }
         let (contract, payment, eip712_domain) =
             self.assert_valid_payment(payload, requirements).await?;
 
-        let signed_message = SignedMessage::extract(&payment, &eip712_domain)?;
+        let signed_message = SignedMessage::extract(&payment, &eip712_domain).await?;
         let payer = signed_message.address;
         let hash = signed_message.hash;
         match signed_message.signature {
@@ -509,7 +1220,8 @@ impl Facilitator for EvmProvider {
                         "Incorrect signature".to_string(),
                     ));
                 }
-                transfer_result.map_err(|e| FacilitatorLocalError::ContractCall(format!("{e}")))?;
+                transfer_result
+                    .map_err(|e| simulation_error(payer, Some(e.return_data.clone()), e))?;
             }
             StructuredSignature::EIP1271(signature) => {
                 // It is EOA or EIP-1271 signature, which we can pass to the transfer simulation
@@ -532,11 +1244,16 @@ impl Facilitator for EvmProvider {
                             otel.kind = "client",
                     ))
                     .await
-                    .map_err(|e| FacilitatorLocalError::ContractCall(format!("{e:?}")))?;
+                    .map_err(|e| simulation_error(payer, e.as_revert_data(), e))?;
             }
         }
 
-        Ok(VerifyResponse::valid(payer.into()))
+        let warnings = verify_warnings(
+            requirements,
+            Some(payment.value),
+            Some(payment.valid_before),
+        );
+        Ok(VerifyResponse::valid(payer.into()).with_warnings(warnings))
     }
 
     /// Settle a verified payment on-chain.
@@ -551,21 +1268,31 @@ impl Facilitator for EvmProvider {
     /// If the wallet is already deployed (or the signature is plain EIP-1271/EOA),
     /// we submit a single `transferWithAuthorization` transaction.
     ///
+    /// Payments of the `permit` and `upto` schemes are settled with `permit` and `transferFrom`
+    /// instead.
+    ///
+    /// With [settlement batching](Self::with_settlement_batching), the same calls are queued
+    /// instead, and submitted in a Multicall3 transaction together with other payments.
+    ///
     /// # Returns
     /// A [`SettleResponse`] containing success flag and transaction hash.
     ///
     /// # Errors
     /// Propagates [`FacilitatorLocalError::ContractCall`] on deployment or transfer failures
-    /// and all prior validation errors.
+    /// and all prior validation errors. Returns [`FacilitatorLocalError::Reverted`], without
+    /// broadcasting anything, if the simulation of the settlement reverts with a known reason.
     async fn settle(&self, request: &SettleRequest) -> Result<SettleResponse, Self::Error> {
         let payload = &request.payment_payload;
         let requirements = &request.payment_requirements;
+        if let ExactPaymentPayload::Permit(permit) = &payload.payload {
+            return self.settle_permit(payload, permit, requirements).await;
+        }
         let (contract, payment, eip712_domain) =
             self.assert_valid_payment(payload, requirements).await?;
 
-        let signed_message = SignedMessage::extract(&payment, &eip712_domain)?;
+        let signed_message = SignedMessage::extract(&payment, &eip712_domain).await?;
         let payer = signed_message.address;
-        let transaction_receipt_fut = match signed_message.signature {
+        let (transfer_call, deployment_call) = match signed_message.signature {
             StructuredSignature::EIP6492 {
                 factory,
                 factory_calldata,
@@ -576,46 +1303,81 @@ impl Facilitator for EvmProvider {
                 let transfer_call = self
                     .transferWithAuthorization_0(&contract, &payment, inner)
                     .await?;
-                if is_contract_deployed {
-                    // transferWithAuthorization with inner signature
-                    let transaction_request = transfer_call.tx.into_transaction_request();
-                    self.send_transaction(transaction_request)
-                } else {
-                    // deploy the smart wallet, and transferWithAuthorization with inner signature
-                    let deployment_call = IMulticall3::Call3 {
-                        allowFailure: true,
-                        target: factory,
-                        callData: factory_calldata,
-                    };
-                    let transfer_with_authorization_call = IMulticall3::Call3 {
-                        allowFailure: false,
-                        target: transfer_call.tx.target(),
-                        callData: transfer_call.tx.calldata().clone(),
-                    };
-                    let aggregate_call = IMulticall3::aggregate3Call {
-                        calls: vec![deployment_call, transfer_with_authorization_call],
-                    };
-                    let aggregate_tx = TransactionRequest::default()
-                        .with_to(MULTICALL3_ADDRESS)
-                        .with_input(aggregate_call.abi_encode());
-                    self.send_transaction(aggregate_tx)
-                }
+                // deploy the smart wallet first, if needed
+                let deployment_call = (!is_contract_deployed).then_some(IMulticall3::Call3 {
+                    allowFailure: true,
+                    target: factory,
+                    callData: factory_calldata,
+                });
+                (transfer_call, deployment_call)
             }
             StructuredSignature::EIP1271(eip1271_signature) => {
                 let transfer_call = self
                     .transferWithAuthorization_0(&contract, &payment, eip1271_signature)
                     .await?;
-                // transferWithAuthorization with eip1271 signature
-                let transaction_request = transfer_call.tx.into_transaction_request();
-                self.send_transaction(transaction_request)
+                (transfer_call, None)
             }
         };
-        let receipt = transaction_receipt_fut.await?;
-        let success = receipt.status();
+        // Refuse a payment bound to revert before spending gas on it
+        let transfer = IMulticall3::Call3 {
+            allowFailure: true,
+            target: transfer_call.tx.target(),
+            callData: transfer_call.tx.calldata().clone(),
+        };
+        self.simulate_transfer(payer, deployment_call.as_ref(), transfer)
+            .await?;
+        let receipt = if let Some(batcher) = &self.settlement_batcher {
+            // transferWithAuthorization may fail without failing the other payments of the batch
+            let transfer_with_authorization_call = IMulticall3::Call3 {
+                allowFailure: true,
+                target: transfer_call.tx.target(),
+                callData: transfer_call.tx.calldata().clone(),
+            };
+            let calls = deployment_call
+                .into_iter()
+                .chain([transfer_with_authorization_call])
+                .collect();
+            batcher.submit(calls).await?
+        } else if let Some(deployment_call) = deployment_call {
+            // deploy the smart wallet, and transferWithAuthorization with inner signature
+            let transfer_with_authorization_call = IMulticall3::Call3 {
+                allowFailure: false,
+                target: transfer_call.tx.target(),
+                callData: transfer_call.tx.calldata().clone(),
+            };
+            let aggregate_call = IMulticall3::aggregate3Call {
+                calls: vec![deployment_call, transfer_with_authorization_call],
+            };
+            let aggregate_tx = TransactionRequest::default()
+                .with_to(MULTICALL3_ADDRESS)
+                .with_input(aggregate_call.abi_encode());
+            self.send_transaction(aggregate_tx).await?
+        } else {
+            // transferWithAuthorization with eip1271 or inner signature
+            let transaction_request = transfer_call.tx.into_transaction_request();
+            self.send_transaction(transaction_request).await?
+        };
+        let mut settlement = settlement_details(
+            &receipt,
+            payment.from,
+            payment.to,
+            payment.value,
+            Some(payment.nonce),
+            *contract.address(),
+        );
+        if self.settlement_batcher.is_some() {
+            // The batch transaction succeeds even if this payment failed: only the authorization
+            // being used tells
+            settlement = settlement.filter(|s| s.authorization_log_index.is_some());
+        }
+        let success =
+            receipt.status() && (self.settlement_batcher.is_none() || settlement.is_some());
         if success {
             tracing::event!(Level::INFO,
                 status = "ok",
                 tx = %receipt.transaction_hash,
+                amount = settlement.as_ref().map(|s| s.amount.to_string()),
+                log_index = settlement.as_ref().and_then(|s| s.log_index),
                 "transferWithAuthorization_0 succeeded"
             );
             Ok(SettleResponse {
@@ -624,6 +1386,10 @@ impl Facilitator for EvmProvider {
                 payer: payment.from.into(),
                 transaction: Some(TransactionHash::Evm(receipt.transaction_hash.0)),
                 network: payload.network,
+                settlement,
+                confirmations: receipt.block_number.map(|_| 1),
+                metadata: None,
+                receipt: None,
             })
         } else {
             tracing::event!(
@@ -638,20 +1404,99 @@ impl Facilitator for EvmProvider {
                 payer: payment.from.into(),
                 transaction: Some(TransactionHash::Evm(receipt.transaction_hash.0)),
                 network: payload.network,
+                settlement: None,
+                confirmations: None,
+                metadata: None,
+                receipt: None,
             })
         }
     }
 
     /// Report payment kinds supported by this provider on its current network.
+    ///
+    /// The `permit` and `upto` kinds name the facilitator as `feePayer`: the spender payers must
+    /// permit.
     async fn supported(&self) -> Result<SupportedPaymentKindsResponse, Self::Error> {
-        let kinds = vec![SupportedPaymentKind {
+        let permit = |scheme| SupportedPaymentKind {
             network: self.network(),
             x402_version: X402Version::V1,
-            scheme: Scheme::Exact,
-            extra: None,
-        }];
-        Ok(SupportedPaymentKindsResponse { kinds })
+            scheme,
+            extra: Some(SupportedPaymentKindExtra {
+                fee_payer: self.signer_address(),
+            }),
+        };
+        let kinds = vec![
+            SupportedPaymentKind {
+                network: self.network(),
+                x402_version: X402Version::V1,
+                scheme: Scheme::Exact,
+                extra: None,
+            },
+            permit(Scheme::Permit),
+            permit(Scheme::Upto),
+        ];
+        Ok(SupportedPaymentKindsResponse {
+            kinds,
+            extensions: Vec::new(),
+            gas: Vec::new(),
+        })
+    }
+}
+
+/// Decode the token movement of a settlement of `value` paid by `from` to `to` from its receipt
+/// logs.
+///
+/// Picks the `AuthorizationUsed` log matching the ERC-3009 `nonce`, and the `Transfer` log of
+/// `value` from `from` to `to` emitted by the token contract right after it, or the first one if
+/// the token does not log authorizations, or without a nonce (`permit` payments). Logs from other
+/// contracts (e.g. a smart wallet deployed in the same transaction) are ignored, as are the
+/// transfers of other payments of a batch, and other transfers of the payer.
+///
+/// Returns `None` if no matching `Transfer` log is found.
+fn settlement_details(
+    receipt: &TransactionReceipt,
+    from: EvmAddress,
+    to: EvmAddress,
+    value: TokenAmount,
+    nonce: Option<HexEncodedNonce>,
+    token: alloy::primitives::Address,
+) -> Option<SettlementDetails> {
+    let logs = receipt
+        .inner
+        .logs()
+        .iter()
+        .filter(|log| log.address() == token);
+    let mut first_transfer = None;
+    let mut authorized_transfer = None;
+    let mut authorization_log_index = None;
+    for log in logs {
+        if let Ok(decoded) = log.log_decode::<USDC::Transfer>() {
+            let event = decoded.inner.data;
+            if event.from != from.0 || event.to != to.0 || event.value != value.0 {
+                continue;
+            }
+            if authorization_log_index.is_some() && authorized_transfer.is_none() {
+                authorized_transfer = Some((event.clone(), log.log_index));
+            }
+            if first_transfer.is_none() {
+                first_transfer = Some((event, log.log_index));
+            }
+        } else if let Ok(decoded) = log.log_decode::<USDC::AuthorizationUsed>() {
+            let event = decoded.inner.data;
+            if event.authorizer == from.0 && nonce.is_some_and(|nonce| event.nonce.0 == nonce.0) {
+                authorization_log_index = log.log_index;
+            }
+        }
     }
+    let (event, log_index) = authorized_transfer.or(first_transfer)?;
+    Some(SettlementDetails {
+        from: EvmAddress(event.from).into(),
+        to: EvmAddress(event.to).into(),
+        amount: event.value.into(),
+        log_index,
+        authorization_log_index,
+        block_number: receipt.block_number,
+    })
 }
 
 /// A prepared call to `transferWithAuthorization` (ERC-3009) including all derived fields.
@@ -681,6 +1526,31 @@ pub struct TransferWithAuthorization0Call<P> {
     pub contract_address: alloy::primitives::Address,
 }
 
+/// Error of the failed simulation of a payment of `payer`: [`FacilitatorLocalError::Reverted`] if
+/// the token reverted with a known reason, [`FacilitatorLocalError::ContractCall`] otherwise.
+fn simulation_error(
+    payer: alloy::primitives::Address,
+    revert_data: Option<Bytes>,
+    error: impl std::fmt::Debug,
+) -> FacilitatorLocalError {
+    match revert_data.and_then(|data| RevertReason::decode(&data)) {
+        Some(reason) => FacilitatorLocalError::Reverted(payer.into(), reason),
+        None => FacilitatorLocalError::ContractCall(format!("{error:?}")),
+    }
+}
+
+/// Logs a settlement refused because its simulation reverted.
+fn warn_reverted(error: &FacilitatorLocalError) {
+    if let FacilitatorLocalError::Reverted(payer, reason) = error {
+        tracing::warn!(
+            monotonic_counter.x402_settlements_reverted = 1,
+            %payer,
+            %reason,
+            "Refused to broadcast a settlement bound to revert"
+        );
+    }
+}
+
 /// Validates that the current time is within the `validAfter` and `validBefore` bounds.
 ///
 /// Adds a 6-second grace buffer when checking expiration to account for latency.
@@ -825,11 +1695,13 @@ impl SignedMessage {
     ///    - EIP-6492 (counterfactual signature wrapper).
     /// 4. Assemble all parts into a [`SignedMessage`] and return it.
     ///
+    /// Steps 2 and 3 run on the [verification pool](crate::verify_pool), off the async runtime.
+    ///
     /// # Errors
     ///
     /// Returns [`FacilitatorLocalError`] if:
     /// - The raw signature cannot be decoded as either EIP-1271 or EIP-6492.
-    pub fn extract(
+    pub async fn extract(
         payment: &ExactEvmPayment,
         domain: &Eip712Domain,
     ) -> Result<Self, FacilitatorLocalError> {
@@ -841,18 +1713,80 @@ impl SignedMessage {
             validBefore: payment.valid_before.into(),
             nonce: FixedBytes(payment.nonce.0),
         };
-        let eip712_hash = transfer_with_authorization.eip712_signing_hash(domain);
+        let domain = domain.clone();
         let expected_address = payment.from;
-        let structured_signature: StructuredSignature = payment.signature.clone().try_into()?;
-        let signed_message = Self {
-            address: expected_address.into(),
-            hash: eip712_hash,
-            signature: structured_signature,
-        };
-        Ok(signed_message)
+        let signature = payment.signature.clone();
+        verify_pool::spawn(move || {
+            let eip712_hash = transfer_with_authorization.eip712_signing_hash(&domain);
+            let structured_signature: StructuredSignature = signature.try_into()?;
+            Ok(Self {
+                address: expected_address.into(),
+                hash: eip712_hash,
+                signature: structured_signature,
+            })
+        })
+        .await
     }
 }
 
+/// Recovers the signers of the permit of `payment` and of its [`PermitIntent`] under `domain`,
+/// on the [verification pool](crate::verify_pool), and checks both are the owner.
+///
+/// Only plain 65-byte ECDSA signatures are accepted: EIP-2612 tokens check permits with
+/// `ecrecover`, so contract wallets can not sign them.
+///
+/// # Errors
+/// Returns [`FacilitatorLocalError::InvalidSignature`] if a signature is malformed or not
+/// signed by the owner, in particular an intent for another receiver or amount.
+async fn recover_permit_signature(
+    payment: &PermitEvmPayment,
+    domain: &Eip712Domain,
+) -> Result<alloy::primitives::Signature, FacilitatorLocalError> {
+    let permit = Permit {
+        owner: payment.owner.0,
+        spender: payment.spender.0,
+        value: payment.value.into(),
+        nonce: payment.nonce,
+        deadline: payment.deadline.into(),
+    };
+    let intent = PermitIntent {
+        owner: payment.owner.0,
+        payTo: payment.to.0,
+        value: payment.max_amount.into(),
+        nonce: payment.nonce,
+    };
+    let domain = domain.clone();
+    let owner = payment.owner;
+    let signature = payment.signature.clone();
+    let intent_signature = payment.intent_signature.clone();
+    verify_pool::spawn(move || {
+        let invalid =
+            |reason: String| FacilitatorLocalError::InvalidSignature(owner.into(), reason);
+        let signature = alloy::primitives::Signature::from_raw(&signature.0)
+            .map_err(|e| invalid(format!("Malformed permit signature: {e}")))?;
+        let hash = permit.eip712_signing_hash(&domain);
+        let signer = signature
+            .recover_address_from_prehash(&hash)
+            .map_err(|e| invalid(format!("Can not recover permit signer: {e}")))?;
+        if signer != owner.0 {
+            return Err(invalid(format!(
+                "Permit signed by {signer}, not by the owner {owner}"
+            )));
+        }
+        let intent_signer = alloy::primitives::Signature::from_raw(&intent_signature.0)
+            .map_err(|e| invalid(format!("Malformed intent signature: {e}")))?
+            .recover_address_from_prehash(&intent.eip712_signing_hash(&domain))
+            .map_err(|e| invalid(format!("Can not recover intent signer: {e}")))?;
+        if intent_signer != owner.0 {
+            return Err(invalid(format!(
+                "Permit intent signed by {intent_signer}, not by the owner {owner}"
+            )));
+        }
+        Ok(signature)
+    })
+    .await
+}
+
 /// The fixed 32-byte magic suffix defined by [EIP-6492](https://eips.ethereum.org/EIPS/eip-6492).
 ///
 /// Any signature ending with this constant is treated as a 6492-wrapped
@@ -0,0 +1,302 @@
+//! Fees of EVM settlement transactions.
+//!
+//! Without a [`GasPolicy`], settlement transactions are priced by the node: EIP-1559 fees are
+//! estimated by the provider, and legacy networks pay the current gas price. A policy bounds and
+//! steers that pricing:
+//! - `max_fee_per_gas` caps the fee paid per gas (the gas price on legacy networks), so that a
+//!   fee spike does not make settlements cost more than they are worth; settlements are rejected
+//!   by the node rather than overpaid,
+//! - `priority_fee_per_gas` replaces the estimated tip to the block producer,
+//! - [`FeeEscalation`] replaces a transaction not mined in time with the same transaction, at
+//!   the same nonce, with fees raised by a percentage, up to the cap.
+//!
+//! A send rejected as underpriced (below the base fee or the minimum fee of the node) is also
+//! retried with raised fees, when escalation is enabled.
+//!
+//! Environment (see [`GasPolicies::from_env`]), every variable also read with the network as a
+//! suffix to override it for that network, e.g. `GAS_MAX_FEE_PER_GAS_BASE_SEPOLIA`:
+//! - `GAS_MAX_FEE_PER_GAS` – Cap on the fee per gas, in wei
+//! - `GAS_PRIORITY_FEE_PER_GAS` – Priority fee per gas, in wei, on EIP-1559 networks (default:
+//!   estimated by the node)
+//! - `GAS_ESCALATE_AFTER_SECS` – Enables escalation, replacing a transaction not mined after
+//!   this many seconds, or a duration with a unit (e.g. `1m`), up to 10 minutes
+//! - `GAS_ESCALATION_PERCENT` – Fee raise of every replacement, at least `10` as nodes require
+//!   (default `20`)
+//! - `GAS_MAX_ESCALATIONS` – Replacements of a transaction (default `3`)
+
+use alloy::network::TransactionBuilder;
+use alloy::providers::Provider;
+use alloy::rpc::types::TransactionRequest;
+use std::collections::HashMap;
+use std::env;
+use std::time::Duration;
+use tracing::Instrument;
+
+use crate::chain::FacilitatorLocalError;
+use crate::chain::evm::InnerProvider;
+use crate::duration::{self, DurationError, DurationRange};
+use crate::network::{Network, NetworkFamily};
+
+const ENV_GAS_MAX_FEE_PER_GAS: &str = "GAS_MAX_FEE_PER_GAS";
+const ENV_GAS_PRIORITY_FEE_PER_GAS: &str = "GAS_PRIORITY_FEE_PER_GAS";
+const ENV_GAS_ESCALATE_AFTER_SECS: &str = "GAS_ESCALATE_AFTER_SECS";
+const ENV_GAS_ESCALATION_PERCENT: &str = "GAS_ESCALATION_PERCENT";
+const ENV_GAS_MAX_ESCALATIONS: &str = "GAS_MAX_ESCALATIONS";
+
+/// Delays accepted from `GAS_ESCALATE_AFTER_SECS`.
+const ESCALATE_AFTER_RANGE: DurationRange =
+    DurationRange::new(Duration::from_secs(1), Duration::from_secs(600));
+/// Smallest fee raise of a replacement accepted by nodes.
+const MIN_ESCALATION_PERCENT: u32 = 10;
+
+#[derive(Debug, thiserror::Error)]
+pub enum GasPolicyError {
+    #[error("Invalid {0}: expected an amount in wei")]
+    Fee(String),
+    #[error("Invalid {0}: expected an integer of at least {MIN_ESCALATION_PERCENT}")]
+    EscalationPercent(String),
+    #[error("Invalid {0}: expected an integer")]
+    EscalationCount(String),
+    #[error(transparent)]
+    Duration(#[from] DurationError),
+}
+
+/// Replacement of settlement transactions not mined in time, see the [module documentation](self).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FeeEscalation {
+    /// Time a transaction is given to be mined before it is replaced.
+    pub after: Duration,
+    /// Fee raise of every replacement, in percent.
+    pub percent: u32,
+    /// Replacements of a transaction.
+    pub max_escalations: u32,
+}
+
+impl Default for FeeEscalation {
+    fn default() -> Self {
+        Self {
+            after: Duration::from_secs(30),
+            percent: 20,
+            max_escalations: 3,
+        }
+    }
+}
+
+/// Fees of the settlement transactions of a network, see the [module documentation](self).
+///
+/// The default policy leaves fees to the node.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct GasPolicy {
+    /// Cap on the fee per gas, or on the gas price of legacy networks, in wei.
+    pub max_fee_per_gas: Option<u128>,
+    /// Priority fee per gas, in wei, replacing the estimate of the node.
+    pub priority_fee_per_gas: Option<u128>,
+    pub escalation: Option<FeeEscalation>,
+}
+
+impl GasPolicy {
+    /// Caps the fee per gas, or the gas price of legacy networks.
+    #[allow(dead_code)] // Public for consumption by downstream crates.
+    pub fn with_max_fee_per_gas(&self, max_fee_per_gas: u128) -> Self {
+        Self {
+            max_fee_per_gas: Some(max_fee_per_gas),
+            ..*self
+        }
+    }
+
+    /// Pays `priority_fee_per_gas` to the block producer, instead of the estimate of the node.
+    #[allow(dead_code)] // Public for consumption by downstream crates.
+    pub fn with_priority_fee_per_gas(&self, priority_fee_per_gas: u128) -> Self {
+        Self {
+            priority_fee_per_gas: Some(priority_fee_per_gas),
+            ..*self
+        }
+    }
+
+    /// Replaces transactions not mined in time, see [`FeeEscalation`].
+    #[allow(dead_code)] // Public for consumption by downstream crates.
+    pub fn with_escalation(&self, escalation: FeeEscalation) -> Self {
+        Self {
+            escalation: Some(escalation),
+            ..*self
+        }
+    }
+
+    /// Sets the fees of `tx`, sent on a network with EIP-1559 fees if `eip1559`.
+    ///
+    /// On EIP-1559 networks, fees are left to the provider unless the policy says otherwise.
+    ///
+    /// # Errors
+    /// Return [`FacilitatorLocalError::ContractCall`] if the fee estimation fails.
+    pub async fn apply(
+        &self,
+        provider: &InnerProvider,
+        eip1559: bool,
+        tx: TransactionRequest,
+    ) -> Result<TransactionRequest, FacilitatorLocalError> {
+        if !eip1559 {
+            let gas_price: u128 = provider
+                .get_gas_price()
+                .instrument(tracing::info_span!("get_gas_price"))
+                .await
+                .map_err(|e| FacilitatorLocalError::ContractCall(format!("{e:?}")))?;
+            return Ok(tx.with_gas_price(self.capped(gas_price)));
+        }
+        if *self == Self::default() {
+            return Ok(tx);
+        }
+        let estimate = provider
+            .estimate_eip1559_fees()
+            .instrument(tracing::info_span!("estimate_eip1559_fees"))
+            .await
+            .map_err(|e| FacilitatorLocalError::ContractCall(format!("{e:?}")))?;
+        let priority_fee = self
+            .priority_fee_per_gas
+            .unwrap_or(estimate.max_priority_fee_per_gas);
+        // The estimate leaves room for the base fee to rise, on top of the estimated tip
+        let max_fee = estimate
+            .max_fee_per_gas
+            .saturating_sub(estimate.max_priority_fee_per_gas)
+            .saturating_add(priority_fee);
+        let max_fee = self.capped(max_fee);
+        Ok(tx
+            .with_max_fee_per_gas(max_fee)
+            .with_max_priority_fee_per_gas(priority_fee.min(max_fee)))
+    }
+
+    /// `tx` with its fees raised by the escalation percentage, up to the cap, or `None` if
+    /// escalation is disabled or the fees can not be raised anymore.
+    pub fn escalate(&self, tx: &TransactionRequest) -> Option<TransactionRequest> {
+        let escalation = self.escalation?;
+        let raise = |fee: u128| {
+            let raised =
+                fee.saturating_add(fee.saturating_mul(escalation.percent as u128).div_ceil(100));
+            self.capped(raised)
+        };
+        let mut tx = tx.clone();
+        if let Some(max_fee) = tx.max_fee_per_gas {
+            let raised = raise(max_fee);
+            if raised <= max_fee {
+                return None;
+            }
+            let priority_fee = tx.max_priority_fee_per_gas.unwrap_or_default();
+            tx.max_fee_per_gas = Some(raised);
+            tx.max_priority_fee_per_gas = Some(raise(priority_fee).min(raised));
+        } else {
+            let gas_price = tx.gas_price?;
+            let raised = raise(gas_price);
+            if raised <= gas_price {
+                return None;
+            }
+            tx.gas_price = Some(raised);
+        }
+        Some(tx)
+    }
+
+    fn capped(&self, fee: u128) -> u128 {
+        self.max_fee_per_gas.map_or(fee, |cap| fee.min(cap))
+    }
+}
+
+/// Gas policies of every EVM network: a default one, and overrides per network.
+#[derive(Debug, Clone, Default)]
+pub struct GasPolicies {
+    default: GasPolicy,
+    networks: HashMap<Network, GasPolicy>,
+}
+
+impl GasPolicies {
+    /// Reads the policies from the `GAS_*` variables, see the [module documentation](self).
+    /// Unset variables leave the matching setting to the node, or to its default.
+    pub fn from_env() -> Result<Self, GasPolicyError> {
+        let default = policy_from_env("", GasPolicy::default())?;
+        let mut networks = HashMap::new();
+        for network in Network::variants() {
+            if let NetworkFamily::Evm = NetworkFamily::from(*network) {
+                let suffix = format!("_{}", network.to_string().to_uppercase().replace('-', "_"));
+                let policy = policy_from_env(&suffix, default)?;
+                if policy != default {
+                    networks.insert(*network, policy);
+                }
+            }
+        }
+        Ok(Self { default, networks })
+    }
+
+    /// Policy of the networks without their own.
+    #[allow(dead_code)] // Public for consumption by downstream crates.
+    pub fn with_default(&self, policy: GasPolicy) -> Self {
+        Self {
+            default: policy,
+            ..self.clone()
+        }
+    }
+
+    /// Policy of `network`, instead of the default one.
+    #[allow(dead_code)] // Public for consumption by downstream crates.
+    pub fn with_network(&self, network: Network, policy: GasPolicy) -> Self {
+        let mut this = self.clone();
+        this.networks.insert(network, policy);
+        this
+    }
+
+    /// Policy of `network`.
+    pub fn for_network(&self, network: Network) -> GasPolicy {
+        self.networks.get(&network).copied().unwrap_or(self.default)
+    }
+}
+
+/// Policy of the `GAS_*` variables ending with `suffix`, on top of `base`.
+fn policy_from_env(suffix: &str, base: GasPolicy) -> Result<GasPolicy, GasPolicyError> {
+    let read = |name: &str| {
+        let name = format!("{name}{suffix}");
+        match env::var(&name) {
+            Ok(value) if !value.trim().is_empty() => Some((name, value.trim().to_string())),
+            _ => None,
+        }
+    };
+    let mut policy = base;
+    if let Some((name, value)) = read(ENV_GAS_MAX_FEE_PER_GAS) {
+        policy.max_fee_per_gas = Some(value.parse().map_err(|_| GasPolicyError::Fee(name))?);
+    }
+    if let Some((name, value)) = read(ENV_GAS_PRIORITY_FEE_PER_GAS) {
+        policy.priority_fee_per_gas = Some(value.parse().map_err(|_| GasPolicyError::Fee(name))?);
+    }
+    let name = format!("{ENV_GAS_ESCALATE_AFTER_SECS}{suffix}");
+    if let Some(after) = duration::from_env(&name, duration::SECOND, ESCALATE_AFTER_RANGE)? {
+        policy.escalation = Some(FeeEscalation {
+            after,
+            ..policy.escalation.unwrap_or_default()
+        });
+    }
+    if let Some(mut escalation) = policy.escalation {
+        if let Some((name, value)) = read(ENV_GAS_ESCALATION_PERCENT) {
+            escalation.percent = value
+                .parse()
+                .ok()
+                .filter(|percent| *percent >= MIN_ESCALATION_PERCENT)
+                .ok_or(GasPolicyError::EscalationPercent(name))?;
+        }
+        if let Some((name, value)) = read(ENV_GAS_MAX_ESCALATIONS) {
+            escalation.max_escalations = value
+                .parse()
+                .map_err(|_| GasPolicyError::EscalationCount(name))?;
+        }
+        policy.escalation = Some(escalation);
+    }
+    Ok(policy)
+}
+
+/// Whether a node rejected a transaction for its fees. Nodes word it differently.
+pub(crate) fn is_underpriced_error(message: &str) -> bool {
+    let message = message.to_lowercase();
+    [
+        "underpriced",
+        "fee too low",
+        "less than block base fee",
+        "max fee per gas less than",
+        "gas price too low",
+    ]
+    .iter()
+    .any(|needle| message.contains(needle))
+}
@@ -0,0 +1,587 @@
+//! Settlement signers keeping their keys in a KMS or a remote signer.
+//!
+//! Keys are secp256k1 keys that never leave their backend: the facilitator reads their public
+//! key once, to derive the address of the settlement account, and asks the backend to sign the
+//! hash of every settlement transaction. See [`SettlementSigner`].
+//!
+//! - [`AwsKmsSigner`]: an `ECC_SECG_P256K1` key of AWS KMS, signing with `ECDSA_SHA_256`,
+//!   authenticated with AWS Signature Version 4.
+//! - [`GcpKmsSigner`]: an `EC_SIGN_SECP256K1_SHA256` key version of Google Cloud KMS,
+//!   authenticated with an OAuth access token.
+//! - [`RemoteSigner`]: the accounts of a JSON-RPC 2.0 remote signer, listed by `eth_accounts`.
+//!   Transaction hashes are signed by `x402_signHash`, with params `[address, hash]` and the
+//!   65-byte signature as result, e.g. by a thin adapter in front of web3signer or an HSM.
+//!
+//! KMS signatures are DER-encoded and carry no recovery id: it is found by recovering the
+//! address of the account from the hash.
+//!
+//! Environment (see [`SignerType`](crate::provider_cache::SignerType)):
+//! - `SIGNER_TYPE` – `aws-kms`, `gcp-kms` or `remote`
+//! - `AWS_KMS_KEY_ID` – Comma-separated key ids or ARNs, one per settlement account
+//! - `AWS_REGION` – Region of the keys; `AWS_ACCESS_KEY_ID`, `AWS_SECRET_ACCESS_KEY` and
+//!   `AWS_SESSION_TOKEN` are the credentials
+//! - `AWS_KMS_ENDPOINT` – Endpoint of AWS KMS (default `https://kms.{region}.amazonaws.com`)
+//! - `GCP_KMS_KEY` – Comma-separated key version names, `projects/…/cryptoKeyVersions/{n}`
+//! - `GCP_KMS_ENDPOINT` – Endpoint of Google Cloud KMS (default `https://cloudkms.googleapis.com/v1`)
+//! - `GCP_ACCESS_TOKEN` – OAuth access token (default: the token of the service account of the
+//!   instance, from the metadata server)
+//! - `REMOTE_SIGNER_URL` – Endpoint of the remote signer, and `REMOTE_SIGNER_TOKEN` its bearer token
+
+use alloy::hex;
+use alloy::primitives::{Address, B256, Signature, U256, keccak256};
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as b64;
+use futures_util::future::BoxFuture;
+use hmac::{Hmac, Mac};
+use serde_json::{Value, json};
+use sha2::{Digest, Sha256};
+use std::env;
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tokio::sync::Mutex;
+use url::Url;
+
+use crate::chain::settlement_signer::{SettlementSigner, SettlementSignerError};
+
+const ENV_AWS_KMS_KEY_ID: &str = "AWS_KMS_KEY_ID";
+const ENV_AWS_REGION: &str = "AWS_REGION";
+const ENV_AWS_DEFAULT_REGION: &str = "AWS_DEFAULT_REGION";
+const ENV_AWS_ACCESS_KEY_ID: &str = "AWS_ACCESS_KEY_ID";
+const ENV_AWS_SECRET_ACCESS_KEY: &str = "AWS_SECRET_ACCESS_KEY";
+const ENV_AWS_SESSION_TOKEN: &str = "AWS_SESSION_TOKEN";
+const ENV_AWS_KMS_ENDPOINT: &str = "AWS_KMS_ENDPOINT";
+const ENV_GCP_KMS_KEY: &str = "GCP_KMS_KEY";
+const ENV_GCP_KMS_ENDPOINT: &str = "GCP_KMS_ENDPOINT";
+const ENV_GCP_ACCESS_TOKEN: &str = "GCP_ACCESS_TOKEN";
+const ENV_REMOTE_SIGNER_URL: &str = "REMOTE_SIGNER_URL";
+const ENV_REMOTE_SIGNER_TOKEN: &str = "REMOTE_SIGNER_TOKEN";
+
+/// Timeout of every request to a signing backend.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+/// Google Cloud KMS API.
+const GCP_KMS_ENDPOINT: &str = "https://cloudkms.googleapis.com/v1";
+/// Access token of the service account of a Google Cloud instance.
+const GCP_METADATA_TOKEN_URL: &str =
+    "http://metadata.google.internal/computeMetadata/v1/instance/service-accounts/default/token";
+
+fn client() -> Result<reqwest::Client, SettlementSignerError> {
+    reqwest::Client::builder()
+        .timeout(REQUEST_TIMEOUT)
+        .build()
+        .map_err(|e| SettlementSignerError::Config(e.to_string()))
+}
+
+fn env_var(name: &str) -> Result<String, SettlementSignerError> {
+    env::var(name)
+        .ok()
+        .filter(|value| !value.trim().is_empty())
+        .ok_or_else(|| SettlementSignerError::Config(format!("env {name} not set")))
+}
+
+/// Comma-separated values of `name`.
+fn env_list(name: &str) -> Result<Vec<String>, SettlementSignerError> {
+    Ok(env_var(name)?
+        .split(',')
+        .map(str::trim)
+        .filter(|value| !value.is_empty())
+        .map(str::to_string)
+        .collect())
+}
+
+/// A settlement account of AWS KMS.
+#[derive(Debug)]
+pub struct AwsKmsSigner {
+    key_id: String,
+    address: Address,
+    client: Arc<AwsKmsClient>,
+}
+
+impl AwsKmsSigner {
+    /// Signers of the keys of `AWS_KMS_KEY_ID`, in order.
+    ///
+    /// # Errors
+    /// Fails if the environment is incomplete, or if the public key of a key can not be read.
+    pub async fn from_env() -> Result<Vec<Self>, SettlementSignerError> {
+        let region = env_var(ENV_AWS_REGION).or_else(|_| env_var(ENV_AWS_DEFAULT_REGION))?;
+        let endpoint = env::var(ENV_AWS_KMS_ENDPOINT)
+            .unwrap_or_else(|_| format!("https://kms.{region}.amazonaws.com"));
+        let client = Arc::new(AwsKmsClient {
+            client: client()?,
+            endpoint: Url::parse(&endpoint).map_err(|e| {
+                SettlementSignerError::Config(format!("Invalid {ENV_AWS_KMS_ENDPOINT}: {e}"))
+            })?,
+            region,
+            access_key_id: env_var(ENV_AWS_ACCESS_KEY_ID)?,
+            secret_access_key: env_var(ENV_AWS_SECRET_ACCESS_KEY)?,
+            session_token: env::var(ENV_AWS_SESSION_TOKEN).ok(),
+        });
+        let mut signers = Vec::new();
+        for key_id in env_list(ENV_AWS_KMS_KEY_ID)? {
+            let response = client
+                .call("GetPublicKey", json!({ "KeyId": key_id }))
+                .await?;
+            let public_key = base64_field(&response, "PublicKey")?;
+            signers.push(Self {
+                address: public_key_address(&public_key)?,
+                key_id,
+                client: client.clone(),
+            });
+        }
+        Ok(signers)
+    }
+}
+
+impl SettlementSigner for AwsKmsSigner {
+    fn address(&self) -> Address {
+        self.address
+    }
+
+    fn sign_hash<'a>(
+        &'a self,
+        hash: &'a B256,
+    ) -> BoxFuture<'a, Result<Signature, SettlementSignerError>> {
+        Box::pin(async move {
+            let request = json!({
+                "KeyId": self.key_id,
+                "Message": b64.encode(hash),
+                "MessageType": "DIGEST",
+                "SigningAlgorithm": "ECDSA_SHA_256",
+            });
+            let response = self.client.call("Sign", request).await?;
+            let der = base64_field(&response, "Signature")?;
+            recoverable_signature(&der, hash, self.address)
+        })
+    }
+}
+
+/// Requests to AWS KMS, signed with AWS Signature Version 4.
+struct AwsKmsClient {
+    client: reqwest::Client,
+    endpoint: Url,
+    region: String,
+    access_key_id: String,
+    secret_access_key: String,
+    session_token: Option<String>,
+}
+
+impl std::fmt::Debug for AwsKmsClient {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("AwsKmsClient")
+            .field("endpoint", &self.endpoint.as_str())
+            .field("region", &self.region)
+            .finish_non_exhaustive()
+    }
+}
+
+impl AwsKmsClient {
+    async fn call(&self, action: &str, body: Value) -> Result<Value, SettlementSignerError> {
+        let body = body.to_string();
+        let target = format!("TrentService.{action}");
+        let host = match self.endpoint.port() {
+            Some(port) => format!("{}:{port}", self.endpoint.host_str().unwrap_or_default()),
+            None => self.endpoint.host_str().unwrap_or_default().to_string(),
+        };
+        let (date, time) = utc_now();
+        let amz_date = format!("{date}T{time}Z");
+        let mut headers = vec![
+            ("content-type", "application/x-amz-json-1.1".to_string()),
+            ("host", host),
+            ("x-amz-date", amz_date.clone()),
+        ];
+        if let Some(session_token) = &self.session_token {
+            headers.push(("x-amz-security-token", session_token.clone()));
+        }
+        headers.push(("x-amz-target", target));
+        let signed_headers = headers
+            .iter()
+            .map(|(name, _)| *name)
+            .collect::<Vec<_>>()
+            .join(";");
+        let canonical_headers: String = headers
+            .iter()
+            .map(|(name, value)| format!("{name}:{}\n", value.trim()))
+            .collect();
+        let canonical_request = format!(
+            "POST\n{}\n\n{canonical_headers}\n{signed_headers}\n{}",
+            self.endpoint.path(),
+            hex::encode(Sha256::digest(body.as_bytes()))
+        );
+        let scope = format!("{date}/{}/kms/aws4_request", self.region);
+        let string_to_sign = format!(
+            "AWS4-HMAC-SHA256\n{amz_date}\n{scope}\n{}",
+            hex::encode(Sha256::digest(canonical_request.as_bytes()))
+        );
+        let mut key = hmac_sha256(
+            format!("AWS4{}", self.secret_access_key).as_bytes(),
+            date.as_bytes(),
+        );
+        for part in [self.region.as_str(), "kms", "aws4_request"] {
+            key = hmac_sha256(&key, part.as_bytes());
+        }
+        let signature = hex::encode(hmac_sha256(&key, string_to_sign.as_bytes()));
+        let authorization = format!(
+            "AWS4-HMAC-SHA256 Credential={}/{scope}, SignedHeaders={signed_headers}, Signature={signature}",
+            self.access_key_id
+        );
+
+        let mut request = self
+            .client
+            .post(self.endpoint.clone())
+            .header("Authorization", authorization)
+            .body(body);
+        for (name, value) in headers {
+            if name != "host" {
+                request = request.header(name, value);
+            }
+        }
+        json_response(request.send().await).await
+    }
+}
+
+fn hmac_sha256(key: &[u8], data: &[u8]) -> [u8; 32] {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC takes keys of any length");
+    mac.update(data);
+    mac.finalize().into_bytes().into()
+}
+
+/// Current UTC date as `YYYYMMDD` and time as `HHMMSS`.
+fn utc_now() -> (String, String) {
+    let secs = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    let (days, secs_of_day) = ((secs / 86_400) as i64, secs % 86_400);
+    // Civil date of a day count, per Howard Hinnant's `civil_from_days`
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    (
+        format!("{year:04}{month:02}{day:02}"),
+        format!(
+            "{:02}{:02}{:02}",
+            secs_of_day / 3_600,
+            secs_of_day / 60 % 60,
+            secs_of_day % 60
+        ),
+    )
+}
+
+/// A settlement account of Google Cloud KMS.
+#[derive(Debug)]
+pub struct GcpKmsSigner {
+    key_version: String,
+    address: Address,
+    client: Arc<GcpKmsClient>,
+}
+
+impl GcpKmsSigner {
+    /// Signers of the key versions of `GCP_KMS_KEY`, in order.
+    ///
+    /// # Errors
+    /// Fails if the environment is incomplete, or if the public key of a key can not be read.
+    pub async fn from_env() -> Result<Vec<Self>, SettlementSignerError> {
+        let client = Arc::new(GcpKmsClient {
+            client: client()?,
+            endpoint: env::var(ENV_GCP_KMS_ENDPOINT)
+                .unwrap_or_else(|_| GCP_KMS_ENDPOINT.to_string()),
+            static_token: env::var(ENV_GCP_ACCESS_TOKEN).ok(),
+            token: Mutex::new(None),
+        });
+        let mut signers = Vec::new();
+        for key_version in env_list(ENV_GCP_KMS_KEY)? {
+            let url = format!("{}/{key_version}/publicKey", client.endpoint);
+            let response = client.call(client.client.get(url)).await?;
+            let pem = response
+                .get("pem")
+                .and_then(Value::as_str)
+                .ok_or_else(|| SettlementSignerError::Response("missing pem".into()))?;
+            let der: String = pem
+                .lines()
+                .filter(|line| !line.starts_with("-----"))
+                .collect();
+            let der = b64
+                .decode(der.trim())
+                .map_err(|e| SettlementSignerError::Response(format!("invalid pem: {e}")))?;
+            signers.push(Self {
+                address: public_key_address(&der)?,
+                key_version,
+                client: client.clone(),
+            });
+        }
+        Ok(signers)
+    }
+}
+
+impl SettlementSigner for GcpKmsSigner {
+    fn address(&self) -> Address {
+        self.address
+    }
+
+    fn sign_hash<'a>(
+        &'a self,
+        hash: &'a B256,
+    ) -> BoxFuture<'a, Result<Signature, SettlementSignerError>> {
+        Box::pin(async move {
+            let url = format!(
+                "{}/{}:asymmetricSign",
+                self.client.endpoint, self.key_version
+            );
+            let request = self
+                .client
+                .client
+                .post(url)
+                .json(&json!({ "digest": { "sha256": b64.encode(hash) } }));
+            let response = self.client.call(request).await?;
+            let der = base64_field(&response, "signature")?;
+            recoverable_signature(&der, hash, self.address)
+        })
+    }
+}
+
+/// Requests to Google Cloud KMS, with an access token.
+struct GcpKmsClient {
+    client: reqwest::Client,
+    endpoint: String,
+    /// Token of `GCP_ACCESS_TOKEN`, if set.
+    static_token: Option<String>,
+    /// Token of the metadata server, until it expires.
+    token: Mutex<Option<(String, Instant)>>,
+}
+
+impl std::fmt::Debug for GcpKmsClient {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("GcpKmsClient")
+            .field("endpoint", &self.endpoint)
+            .finish_non_exhaustive()
+    }
+}
+
+impl GcpKmsClient {
+    async fn call(&self, request: reqwest::RequestBuilder) -> Result<Value, SettlementSignerError> {
+        let token = self.access_token().await?;
+        json_response(request.bearer_auth(token).send().await).await
+    }
+
+    async fn access_token(&self) -> Result<String, SettlementSignerError> {
+        if let Some(token) = &self.static_token {
+            return Ok(token.clone());
+        }
+        let mut cached = self.token.lock().await;
+        if let Some((token, expires_at)) = cached.as_ref()
+            && Instant::now() < *expires_at
+        {
+            return Ok(token.clone());
+        }
+        let request = self
+            .client
+            .get(GCP_METADATA_TOKEN_URL)
+            .header("Metadata-Flavor", "Google");
+        let response = json_response(request.send().await).await?;
+        let token = response
+            .get("access_token")
+            .and_then(Value::as_str)
+            .ok_or_else(|| SettlementSignerError::Response("missing access_token".into()))?
+            .to_string();
+        let expires_in = response
+            .get("expires_in")
+            .and_then(Value::as_u64)
+            .unwrap_or(0);
+        // Renewed a minute before it expires
+        let expires_at = Instant::now() + Duration::from_secs(expires_in.saturating_sub(60));
+        *cached = Some((token.clone(), expires_at));
+        Ok(token)
+    }
+}
+
+/// A settlement account of a JSON-RPC remote signer.
+#[derive(Debug)]
+pub struct RemoteSigner {
+    address: Address,
+    client: Arc<RemoteSignerClient>,
+}
+
+impl RemoteSigner {
+    /// Signers of the accounts of the remote signer at `REMOTE_SIGNER_URL`, in the order of
+    /// `eth_accounts`.
+    ///
+    /// # Errors
+    /// Fails if the environment is incomplete, or if the accounts can not be listed.
+    pub async fn from_env() -> Result<Vec<Self>, SettlementSignerError> {
+        let url = env_var(ENV_REMOTE_SIGNER_URL)?;
+        let client = Arc::new(RemoteSignerClient {
+            client: client()?,
+            url: Url::parse(&url).map_err(|e| {
+                SettlementSignerError::Config(format!("Invalid {ENV_REMOTE_SIGNER_URL}: {e}"))
+            })?,
+            token: env::var(ENV_REMOTE_SIGNER_TOKEN).ok(),
+        });
+        let accounts: Vec<Address> =
+            serde_json::from_value(client.call("eth_accounts", json!([])).await?)
+                .map_err(|e| SettlementSignerError::Response(format!("eth_accounts: {e}")))?;
+        Ok(accounts
+            .into_iter()
+            .map(|address| Self {
+                address,
+                client: client.clone(),
+            })
+            .collect())
+    }
+}
+
+impl SettlementSigner for RemoteSigner {
+    fn address(&self) -> Address {
+        self.address
+    }
+
+    fn sign_hash<'a>(
+        &'a self,
+        hash: &'a B256,
+    ) -> BoxFuture<'a, Result<Signature, SettlementSignerError>> {
+        Box::pin(async move {
+            let result = self
+                .client
+                .call("x402_signHash", json!([self.address, hash]))
+                .await?;
+            let bytes = result
+                .as_str()
+                .and_then(|signature| hex::decode(signature).ok())
+                .ok_or_else(|| SettlementSignerError::Response("invalid signature".into()))?;
+            let signature = Signature::from_raw(&bytes)
+                .map_err(|e| SettlementSignerError::Response(e.to_string()))?;
+            match signature.recover_address_from_prehash(hash) {
+                Ok(address) if address == self.address => Ok(signature),
+                _ => Err(SettlementSignerError::Signing(format!(
+                    "signature does not recover to {}",
+                    self.address
+                ))),
+            }
+        })
+    }
+}
+
+/// JSON-RPC 2.0 requests to a remote signer.
+struct RemoteSignerClient {
+    client: reqwest::Client,
+    url: Url,
+    token: Option<String>,
+}
+
+impl std::fmt::Debug for RemoteSignerClient {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RemoteSignerClient")
+            .field("url", &self.url.as_str())
+            .finish_non_exhaustive()
+    }
+}
+
+impl RemoteSignerClient {
+    async fn call(&self, method: &str, params: Value) -> Result<Value, SettlementSignerError> {
+        let mut request = self.client.post(self.url.clone()).json(&json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "method": method,
+            "params": params,
+        }));
+        if let Some(token) = &self.token {
+            request = request.bearer_auth(token);
+        }
+        let mut response = json_response(request.send().await).await?;
+        if let Some(error) = response.get("error") {
+            return Err(SettlementSignerError::Request(format!("{method}: {error}")));
+        }
+        response
+            .get_mut("result")
+            .map(Value::take)
+            .ok_or_else(|| SettlementSignerError::Response(format!("{method}: missing result")))
+    }
+}
+
+/// JSON body of a successful `response`.
+async fn json_response(
+    response: Result<reqwest::Response, reqwest::Error>,
+) -> Result<Value, SettlementSignerError> {
+    let response = response.map_err(|e| SettlementSignerError::Request(e.to_string()))?;
+    let status = response.status();
+    let body = response
+        .text()
+        .await
+        .map_err(|e| SettlementSignerError::Request(e.to_string()))?;
+    if !status.is_success() {
+        return Err(SettlementSignerError::Request(format!("{status}: {body}")));
+    }
+    serde_json::from_str(&body).map_err(|e| SettlementSignerError::Response(e.to_string()))
+}
+
+fn base64_field(response: &Value, field: &str) -> Result<Vec<u8>, SettlementSignerError> {
+    response
+        .get(field)
+        .and_then(Value::as_str)
+        .and_then(|value| b64.decode(value).ok())
+        .ok_or_else(|| SettlementSignerError::Response(format!("missing or invalid {field}")))
+}
+
+/// Address of a DER-encoded `SubjectPublicKeyInfo` of a secp256k1 key, which ends with the
+/// uncompressed point `04 ‖ x ‖ y`.
+fn public_key_address(spki: &[u8]) -> Result<Address, SettlementSignerError> {
+    let point = spki
+        .len()
+        .checked_sub(65)
+        .map(|start| &spki[start..])
+        .filter(|point| point[0] == 0x04)
+        .ok_or_else(|| {
+            SettlementSignerError::Response("not an uncompressed secp256k1 public key".into())
+        })?;
+    Ok(Address::from_slice(&keccak256(&point[1..])[12..]))
+}
+
+/// Signature of `hash` by `address` from a DER-encoded ECDSA signature: `s` normalized to its
+/// lower value, with the recovery id that recovers `address`.
+fn recoverable_signature(
+    der: &[u8],
+    hash: &B256,
+    address: Address,
+) -> Result<Signature, SettlementSignerError> {
+    let (r, s) = parse_der_signature(der)
+        .ok_or_else(|| SettlementSignerError::Response("invalid DER signature".into()))?;
+    let signature = Signature::new(r, s, false);
+    let signature = signature.normalize_s().unwrap_or(signature);
+    let (r, s) = (signature.r(), signature.s());
+    [false, true]
+        .into_iter()
+        .map(|parity| Signature::new(r, s, parity))
+        .find(|signature| signature.recover_address_from_prehash(hash).ok() == Some(address))
+        .ok_or_else(|| {
+            SettlementSignerError::Signing(format!("signature does not recover to {address}"))
+        })
+}
+
+/// `r` and `s` of a DER-encoded `SEQUENCE { INTEGER r, INTEGER s }`.
+fn parse_der_signature(der: &[u8]) -> Option<(U256, U256)> {
+    let (&tag, rest) = der.split_first()?;
+    let (&len, rest) = rest.split_first()?;
+    if tag != 0x30 || usize::from(len) != rest.len() {
+        return None;
+    }
+    let (r, rest) = parse_der_integer(rest)?;
+    let (s, rest) = parse_der_integer(rest)?;
+    rest.is_empty().then_some((r, s))
+}
+
+fn parse_der_integer(der: &[u8]) -> Option<(U256, &[u8])> {
+    let (&tag, rest) = der.split_first()?;
+    let (&len, rest) = rest.split_first()?;
+    let len = usize::from(len);
+    if tag != 0x02 || len > rest.len() {
+        return None;
+    }
+    let (value, rest) = rest.split_at(len);
+    // Leading zero keeping the integer positive
+    let value = value.strip_prefix(&[0]).unwrap_or(value);
+    (value.len() <= 32).then(|| (U256::from_be_slice(value), rest))
+}
@@ -1,21 +1,76 @@
+use futures_util::future::BoxFuture;
+use std::sync::Arc;
 use std::time::SystemTimeError;
 
 use crate::chain::evm::EvmProvider;
+use crate::chain::revert::RevertReason;
 use crate::chain::solana::SolanaProvider;
 use crate::facilitator::Facilitator;
 use crate::network::Network;
+use crate::timestamp::UnixTimestamp;
+use crate::tokens::KnownToken;
 use crate::types::{
-    MixedAddress, Scheme, SettleRequest, SettleResponse, SupportedPaymentKindsResponse,
-    VerifyRequest, VerifyResponse,
+    MixedAddress, PaymentRequirements, Scheme, SettleRequest, SettleResponse, SupportedPaymentKind,
+    SupportedPaymentKindsResponse, TokenAmount, TokenAsset, VerifyRequest, VerifyResponse,
+    VerifyWarning, VerifyWarningCode, X402Version,
 };
 
+pub mod account_nonce;
 pub mod evm;
+pub mod gas;
+#[cfg(feature = "kms")]
+pub mod kms;
+pub mod multicall;
+pub mod revert;
+pub mod rpc_failover;
+pub mod sandbox;
+pub mod settlement_signer;
+pub mod signer_pool;
 pub mod solana;
 
 #[derive(Clone)]
 pub enum NetworkProvider {
     Evm(EvmProvider),
     Solana(SolanaProvider),
+    /// A chain backend registered by a downstream crate, see [`ChainProvider`].
+    Custom(Arc<dyn ChainProvider>),
+}
+
+/// Verification and settlement of `exact` payments on a chain not natively supported by this
+/// crate, such as an L2 or an appchain.
+///
+/// Register an implementation with
+/// [`FacilitatorLocal::with_chain_provider`](crate::facilitator_local::FacilitatorLocal::with_chain_provider):
+/// payments on its [`ChainProvider::network`] are then verified and settled by it, in place of
+/// any provider configured from the environment for that network.
+pub trait ChainProvider: Send + Sync {
+    /// Network this provider verifies and settles payments on.
+    fn network(&self) -> Network;
+
+    /// Address of the facilitator on that network.
+    fn signer_address(&self) -> MixedAddress;
+
+    /// Payment kinds supported on the network, advertised via `/supported`.
+    fn kinds(&self) -> Vec<SupportedPaymentKind> {
+        vec![SupportedPaymentKind {
+            x402_version: X402Version::V1,
+            scheme: Scheme::Exact,
+            network: self.network(),
+            extra: None,
+        }]
+    }
+
+    /// Verifies a payment on the network.
+    fn verify<'a>(
+        &'a self,
+        request: &'a VerifyRequest,
+    ) -> BoxFuture<'a, Result<VerifyResponse, FacilitatorLocalError>>;
+
+    /// Settles a payment on the network.
+    fn settle<'a>(
+        &'a self,
+        request: &'a SettleRequest,
+    ) -> BoxFuture<'a, Result<SettleResponse, FacilitatorLocalError>>;
 }
 
 pub trait NetworkProviderOps {
@@ -28,6 +83,7 @@ impl NetworkProviderOps for NetworkProvider {
         match self {
             NetworkProvider::Evm(provider) => provider.signer_address(),
             NetworkProvider::Solana(provider) => provider.signer_address(),
+            NetworkProvider::Custom(provider) => provider.signer_address(),
         }
     }
 
@@ -35,6 +91,7 @@ impl NetworkProviderOps for NetworkProvider {
         match self {
             NetworkProvider::Evm(provider) => provider.network(),
             NetworkProvider::Solana(provider) => provider.network(),
+            NetworkProvider::Custom(provider) => provider.network(),
         }
     }
 }
@@ -46,6 +103,7 @@ impl Facilitator for NetworkProvider {
         match self {
             NetworkProvider::Evm(provider) => provider.verify(request).await,
             NetworkProvider::Solana(provider) => provider.verify(request).await,
+            NetworkProvider::Custom(provider) => provider.verify(request).await,
         }
     }
 
@@ -53,6 +111,7 @@ impl Facilitator for NetworkProvider {
         match self {
             NetworkProvider::Evm(provider) => provider.settle(request).await,
             NetworkProvider::Solana(provider) => provider.settle(request).await,
+            NetworkProvider::Custom(provider) => provider.settle(request).await,
         }
     }
 
@@ -60,10 +119,75 @@ impl Facilitator for NetworkProvider {
         match self {
             NetworkProvider::Evm(provider) => provider.supported().await,
             NetworkProvider::Solana(provider) => provider.supported().await,
+            NetworkProvider::Custom(provider) => Ok(SupportedPaymentKindsResponse {
+                kinds: provider.kinds(),
+                extensions: Vec::new(),
+                gas: Vec::new(),
+            }),
         }
     }
 }
 
+/// Authorizations expiring within this many seconds are reported
+/// with [`VerifyWarningCode::AuthorizationExpiresSoon`].
+const EXPIRY_WARNING_SECONDS: u64 = 30;
+
+/// Authorized amounts of at least this many times the requirement are reported
+/// with [`VerifyWarningCode::AmountExceedsRequirement`].
+const AMOUNT_WARNING_FACTOR: u64 = 10;
+
+/// Collects the non-fatal findings of an otherwise valid payment.
+///
+/// `value` and `valid_before` are checked only if the payload of the network exposes them.
+pub fn verify_warnings(
+    requirements: &PaymentRequirements,
+    value: Option<TokenAmount>,
+    valid_before: Option<UnixTimestamp>,
+) -> Vec<VerifyWarning> {
+    let mut warnings = Vec::new();
+    if let Some(valid_before) = valid_before
+        && let Ok(now) = UnixTimestamp::try_now()
+        && valid_before < now + EXPIRY_WARNING_SECONDS
+    {
+        warnings.push(VerifyWarning::new(
+            VerifyWarningCode::AuthorizationExpiresSoon,
+            format!(
+                "Authorization expires in {}s",
+                valid_before
+                    .seconds_since_epoch()
+                    .saturating_sub(now.seconds_since_epoch())
+            ),
+        ));
+    }
+    let required = requirements.max_amount_required;
+    if let Some(value) = value
+        && !required.0.is_zero()
+        && value.0
+            >= required
+                .0
+                .saturating_mul(alloy::primitives::U256::from(AMOUNT_WARNING_FACTOR))
+    {
+        warnings.push(VerifyWarning::new(
+            VerifyWarningCode::AmountExceedsRequirement,
+            format!("Authorized amount {value} exceeds required amount {required} by {AMOUNT_WARNING_FACTOR}x or more"),
+        ));
+    }
+    let asset = TokenAsset {
+        address: requirements.asset.clone(),
+        network: requirements.network,
+    };
+    if KnownToken::by_asset(&asset).is_none() {
+        warnings.push(VerifyWarning::new(
+            VerifyWarningCode::TokenNotAllowlisted,
+            format!(
+                "Token {} is not on the settlement allowlist for {}",
+                requirements.asset, requirements.network
+            ),
+        ));
+    }
+    warnings
+}
+
 #[derive(Debug, thiserror::Error)]
 pub enum FacilitatorLocalError {
     /// The network is not supported by this facilitator.
@@ -75,6 +199,9 @@ pub enum FacilitatorLocalError {
     /// Scheme mismatch.
     #[error("Scheme mismatch: expected {1}, actual {2}")]
     SchemeMismatch(Option<MixedAddress>, Scheme, Scheme),
+    /// No handler is available for the payment scheme.
+    #[error("Unsupported scheme: {0}")]
+    UnsupportedScheme(Scheme),
     /// Invalid address.
     #[error("Invalid address: {0}")]
     InvalidAddress(String),
@@ -102,4 +229,32 @@ pub enum FacilitatorLocalError {
     /// The payload decoding failed.
     #[error("Decoding error: {0}")]
     DecodingError(String),
+    /// The payment authorization was already settled, see [`PaymentNonceStore`](crate::nonce_store::PaymentNonceStore).
+    #[error("Payment authorization already used")]
+    NonceReused(Option<MixedAddress>),
+    /// The nonce store could not be queried.
+    #[error("Nonce store error: {0}")]
+    NonceStore(String),
+    /// The payment exceeds a cap on settled amounts, see [`SettlementCaps`](crate::settlement_caps::SettlementCaps).
+    #[error("Amount cap exceeded: {1}")]
+    AmountCapExceeded(Option<MixedAddress>, String),
+    /// The payment was not verified recently enough to be settled, see [`StrictSettle`](crate::strict_settle::StrictSettle).
+    #[error("Payment not verified before settlement")]
+    NotVerified(Option<MixedAddress>),
+    /// The idempotency key of the settlement was used for another payment, see [`SettleIdempotency`](crate::settle_idempotency::SettleIdempotency).
+    #[error("Idempotency key used for another payment")]
+    IdempotencyKeyReused(Option<MixedAddress>),
+    /// Settlements on the network are paused by an operator, see [`SettlementPause`](crate::settlement_pause::SettlementPause).
+    #[error("Settlement paused on {0}")]
+    SettlementPaused(Network),
+    /// A settlement account of the facilitator on the network is below its gas floor, see [`GasMonitor`](crate::gas_monitor::GasMonitor).
+    #[error("Insufficient gas on {0}")]
+    InsufficientGas(Network),
+    /// The token contract reverted, or would revert, the payment, see [`revert`].
+    #[error("Payment reverted: {1}")]
+    Reverted(MixedAddress, RevertReason),
+    /// A remote facilitator, see [`facilitator_remote`](crate::facilitator_remote), failed or
+    /// rejected the payment, with the reason if any.
+    #[error("Upstream facilitator error: {0}")]
+    Upstream(String, Option<Box<VerifyResponse>>),
 }
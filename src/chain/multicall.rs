@@ -0,0 +1,212 @@
+//! Batching of EVM settlements into Multicall3 transactions.
+//!
+//! Without batching, every settlement is its own transaction. With [`SettlementBatching`],
+//! an [`EvmProvider`](crate::chain::evm::EvmProvider) queues the `transferWithAuthorization`
+//! calls of concurrent settlements for a short window, and submits them together as a single
+//! Multicall3 `aggregate3` transaction, paying the base transaction cost once per batch. Streaming
+//! sellers settling many small slices save most of their gas this way.
+//!
+//! Every call of a batch is allowed to fail on its own: a bad payment does not revert the others.
+//! The outcome of each payment is read from the receipt, by its ERC-3009 `AuthorizationUsed` log.
+//! All the payments of a batch share the transaction hash.
+//!
+//! A batch is sent once the window elapses or [`max_payments`](SettlementBatching::max_payments)
+//! are queued, and batches are sent one at a time: settlements arriving while a batch is being
+//! mined are queued for the next one.
+//!
+//! Environment:
+//! - `MULTICALL_WINDOW_MS` – Enables batching, queueing settlements for up to this many
+//!   milliseconds, or a duration with a unit (e.g. `1s`), up to 10 seconds
+//! - `MULTICALL_MAX_PAYMENTS` – Maximum number of payments in a batch (default `50`)
+
+use alloy::network::TransactionBuilder;
+use alloy::providers::MULTICALL3_ADDRESS;
+use alloy::providers::bindings::IMulticall3;
+use alloy::rpc::types::{TransactionReceipt, TransactionRequest};
+use alloy::sol_types::SolCall;
+use std::env;
+use std::time::Duration;
+use tokio::sync::{mpsc, oneshot};
+use tokio::time::Instant;
+use tracing::Instrument;
+
+use crate::chain::FacilitatorLocalError;
+use crate::chain::evm::InnerProvider;
+use crate::chain::gas::GasPolicy;
+use crate::chain::signer_pool::SignerPool;
+use crate::duration::{self, DurationRange};
+
+const ENV_MULTICALL_WINDOW_MS: &str = "MULTICALL_WINDOW_MS";
+const ENV_MULTICALL_MAX_PAYMENTS: &str = "MULTICALL_MAX_PAYMENTS";
+
+/// Windows accepted from `MULTICALL_WINDOW_MS`.
+const WINDOW_RANGE: DurationRange = DurationRange::new(Duration::ZERO, Duration::from_secs(10));
+
+/// Configuration of settlement batching, see the [module documentation](self).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SettlementBatching {
+    /// Time a settlement waits for others to join its batch.
+    pub window: Duration,
+    /// Maximum number of payments in a batch.
+    pub max_payments: usize,
+}
+
+impl Default for SettlementBatching {
+    fn default() -> Self {
+        Self {
+            window: Duration::from_millis(250),
+            max_payments: 50,
+        }
+    }
+}
+
+impl SettlementBatching {
+    /// Reads the configuration from `MULTICALL_WINDOW_MS` and `MULTICALL_MAX_PAYMENTS`.
+    /// Returns `None`, settling every payment in its own transaction, if the window is not set
+    /// or invalid; invalid windows are logged.
+    pub fn from_env() -> Option<Self> {
+        let window =
+            duration::from_env(ENV_MULTICALL_WINDOW_MS, duration::MILLISECOND, WINDOW_RANGE)
+                .inspect_err(|e| tracing::warn!(error = %e, "Settlement batching disabled"))
+                .ok()??;
+        let max_payments = env::var(ENV_MULTICALL_MAX_PAYMENTS)
+            .ok()
+            .and_then(|s| s.parse().ok())
+            .filter(|value| *value > 0)
+            .unwrap_or(Self::default().max_payments);
+        Some(Self {
+            window,
+            max_payments,
+        })
+    }
+
+    /// Sets the time a settlement waits for others to join its batch.
+    #[allow(dead_code)] // Public for consumption by downstream crates.
+    pub fn with_window(&self, window: Duration) -> Self {
+        Self { window, ..*self }
+    }
+
+    /// Sets the maximum number of payments in a batch (at least one).
+    #[allow(dead_code)] // Public for consumption by downstream crates.
+    pub fn with_max_payments(&self, max_payments: usize) -> Self {
+        Self {
+            max_payments: max_payments.max(1),
+            ..*self
+        }
+    }
+}
+
+/// Calls of a single payment, waiting for their batch.
+struct QueuedSettlement {
+    calls: Vec<IMulticall3::Call3>,
+    reply: oneshot::Sender<Result<TransactionReceipt, String>>,
+}
+
+/// Queue of the settlements of a network, submitted in Multicall3 batches by a background task.
+///
+/// Clones share the same queue.
+#[derive(Clone, Debug)]
+pub struct MulticallBatcher {
+    sender: mpsc::UnboundedSender<QueuedSettlement>,
+}
+
+impl MulticallBatcher {
+    /// Starts the background task submitting batches through `provider`, from the accounts of
+    /// `signers` in turn, priced per `gas`. Must be called from within a Tokio runtime.
+    pub fn start(
+        provider: InnerProvider,
+        signers: SignerPool,
+        eip1559: bool,
+        gas: GasPolicy,
+        batching: SettlementBatching,
+    ) -> Self {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        tokio::spawn(run(provider, signers, eip1559, gas, batching, receiver));
+        Self { sender }
+    }
+
+    /// Queues the calls of a payment, and waits for the receipt of the batch including them.
+    /// Calls are executed in order, and each of them may fail without failing the batch.
+    pub async fn submit(
+        &self,
+        calls: Vec<IMulticall3::Call3>,
+    ) -> Result<TransactionReceipt, FacilitatorLocalError> {
+        let (reply, receiver) = oneshot::channel();
+        self.sender
+            .send(QueuedSettlement { calls, reply })
+            .map_err(|_| {
+                FacilitatorLocalError::ContractCall("Settlement batcher stopped".into())
+            })?;
+        receiver
+            .await
+            .map_err(|_| FacilitatorLocalError::ContractCall("Settlement batch dropped".into()))?
+            .map_err(FacilitatorLocalError::ContractCall)
+    }
+}
+
+async fn run(
+    provider: InnerProvider,
+    signers: SignerPool,
+    eip1559: bool,
+    gas: GasPolicy,
+    batching: SettlementBatching,
+    mut receiver: mpsc::UnboundedReceiver<QueuedSettlement>,
+) {
+    while let Some(first) = receiver.recv().await {
+        let mut batch = vec![first];
+        let deadline = Instant::now() + batching.window;
+        while batch.len() < batching.max_payments {
+            match tokio::time::timeout_at(deadline, receiver.recv()).await {
+                Ok(Some(queued)) => batch.push(queued),
+                // Window elapsed, or every batcher dropped
+                Ok(None) | Err(_) => break,
+            }
+        }
+        let calls = batch
+            .iter()
+            .flat_map(|queued| queued.calls.iter().cloned())
+            .collect::<Vec<_>>();
+        let span = tracing::info_span!(
+            "send_multicall_batch",
+            payments = batch.len(),
+            calls = calls.len(),
+            otel.kind = "client",
+        );
+        let result = send_batch(&provider, &signers, eip1559, &gas, calls)
+            .instrument(span)
+            .await;
+        match &result {
+            Ok(receipt) => tracing::info!(
+                tx = %receipt.transaction_hash,
+                payments = batch.len(),
+                status = receipt.status(),
+                "Settlement batch mined"
+            ),
+            Err(error) => tracing::warn!(%error, payments = batch.len(), "Settlement batch failed"),
+        }
+        for queued in batch {
+            let _ = queued.reply.send(result.clone());
+        }
+    }
+}
+
+async fn send_batch(
+    provider: &InnerProvider,
+    signers: &SignerPool,
+    eip1559: bool,
+    gas: &GasPolicy,
+    calls: Vec<IMulticall3::Call3>,
+) -> Result<TransactionReceipt, String> {
+    let aggregate_call = IMulticall3::aggregate3Call { calls };
+    let tx = TransactionRequest::default()
+        .with_to(MULTICALL3_ADDRESS)
+        .with_input(aggregate_call.abi_encode());
+    let send = async {
+        let tx = gas.apply(provider, eip1559, tx).await?;
+        signers.next().send_transaction(provider, tx, gas).await
+    };
+    send.await.map_err(|e| match e {
+        FacilitatorLocalError::ContractCall(message) => message,
+        e => e.to_string(),
+    })
+}
@@ -0,0 +1,158 @@
+//! Decoding of the reverts of token contracts.
+//!
+//! EVM settlements are simulated with `eth_call` before being broadcast, see
+//! [`EvmProvider`](crate::chain::evm::EvmProvider): a payment bound to revert is refused before
+//! any gas is spent, with the reason given by the token rather than a generic contract call
+//! failure. Reverts are decoded from:
+//! - the custom errors of ERC-3009 and ERC-2612 implementations, of OpenZeppelin ERC-20 tokens
+//!   (ERC-6093) and of Solady ERC-20 tokens,
+//! - the `Error(string)` messages of USDC (`FiatTokenV2`) and of tokens using the same wording.
+//!
+//! Reverts without data, or with data that is not an error, are not decoded.
+
+use alloy::sol;
+use alloy::sol_types::{Revert, SolError, SolInterface};
+
+use crate::types::FacilitatorErrorReason;
+
+sol! {
+    /// Custom errors of token contracts, telling why a transfer reverted.
+    #[derive(Debug)]
+    interface TokenErrors {
+        // ERC-3009
+        error AuthorizationUsed(address authorizer, bytes32 nonce);
+        error AuthorizationAlreadyUsed(address authorizer, bytes32 nonce);
+        error AuthorizationExpired();
+        error AuthorizationNotYetValid();
+        error InvalidSignature();
+        // OpenZeppelin ERC-20 (ERC-6093), ERC-2612, ECDSA, Nonces and Pausable
+        error ERC20InsufficientBalance(address sender, uint256 balance, uint256 needed);
+        error ERC20InsufficientAllowance(address spender, uint256 allowance, uint256 needed);
+        error ERC2612ExpiredSignature(uint256 deadline);
+        error ERC2612InvalidSigner(address signer, address owner);
+        error ECDSAInvalidSignature();
+        error ECDSAInvalidSignatureLength(uint256 length);
+        error ECDSAInvalidSignatureS(bytes32 s);
+        error InvalidAccountNonce(address account, uint256 currentNonce);
+        error EnforcedPause();
+        // Solady ERC-20
+        error InsufficientBalance();
+        error InsufficientAllowance();
+        error PermitExpired();
+        error InvalidPermit();
+    }
+}
+
+/// Why a token contract reverted a transfer.
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum RevertReason {
+    /// The authorization, or the permit nonce, was already used or canceled.
+    #[error("authorization is used or canceled")]
+    AuthorizationUsed,
+    /// The signature of the authorization, or of the permit, does not match the payer.
+    #[error("invalid signature")]
+    InvalidSignature,
+    /// The authorization, or the permit, expired.
+    #[error("authorization is expired")]
+    AuthorizationExpired,
+    /// The authorization is not valid yet.
+    #[error("authorization is not yet valid")]
+    AuthorizationNotYetValid,
+    /// The balance of the payer does not cover the transfer.
+    #[error("transfer amount exceeds balance")]
+    InsufficientBalance,
+    /// The allowance given to the facilitator does not cover the transfer.
+    #[error("transfer amount exceeds allowance")]
+    InsufficientAllowance,
+    /// The payer, or the recipient, is blacklisted by the token.
+    #[error("account is blacklisted")]
+    Blacklisted,
+    /// Transfers of the token are paused.
+    #[error("token is paused")]
+    Paused,
+    /// Any other `Error(string)` message.
+    #[error("{0}")]
+    Other(String),
+}
+
+impl RevertReason {
+    /// Decodes the reason of a revert from its data, `None` if it is not a known error.
+    pub fn decode(data: &[u8]) -> Option<Self> {
+        if let Ok(error) = TokenErrors::TokenErrorsErrors::abi_decode(data) {
+            return Some(Self::from_error(error));
+        }
+        let Revert { reason } = Revert::abi_decode(data).ok()?;
+        Some(Self::from_message(reason))
+    }
+
+    fn from_error(error: TokenErrors::TokenErrorsErrors) -> Self {
+        use TokenErrors::TokenErrorsErrors as E;
+        match error {
+            E::AuthorizationUsed(_)
+            | E::AuthorizationAlreadyUsed(_)
+            | E::InvalidAccountNonce(_) => Self::AuthorizationUsed,
+            E::AuthorizationExpired(_) | E::ERC2612ExpiredSignature(_) | E::PermitExpired(_) => {
+                Self::AuthorizationExpired
+            }
+            E::AuthorizationNotYetValid(_) => Self::AuthorizationNotYetValid,
+            E::InvalidSignature(_)
+            | E::ERC2612InvalidSigner(_)
+            | E::ECDSAInvalidSignature(_)
+            | E::ECDSAInvalidSignatureLength(_)
+            | E::ECDSAInvalidSignatureS(_)
+            | E::InvalidPermit(_) => Self::InvalidSignature,
+            E::ERC20InsufficientBalance(_) | E::InsufficientBalance(_) => Self::InsufficientBalance,
+            E::ERC20InsufficientAllowance(_) | E::InsufficientAllowance(_) => {
+                Self::InsufficientAllowance
+            }
+            E::EnforcedPause(_) => Self::Paused,
+        }
+    }
+
+    /// Classifies an `Error(string)` message, e.g. `FiatTokenV2: authorization is used or canceled`.
+    fn from_message(message: String) -> Self {
+        let lowercase = message.to_lowercase();
+        let matches = |patterns: &[&str]| patterns.iter().any(|p| lowercase.contains(p));
+        if matches(&["authorization is used", "invalid nonce"]) {
+            Self::AuthorizationUsed
+        } else if matches(&["invalid signature", "invalid signer"]) {
+            Self::InvalidSignature
+        } else if matches(&[
+            "authorization is expired",
+            "permit is expired",
+            "expired deadline",
+        ]) {
+            Self::AuthorizationExpired
+        } else if matches(&["authorization is not yet valid"]) {
+            Self::AuthorizationNotYetValid
+        } else if matches(&["exceeds balance"]) {
+            Self::InsufficientBalance
+        } else if matches(&["exceeds allowance", "insufficient allowance"]) {
+            Self::InsufficientAllowance
+        } else if matches(&["blacklisted"]) {
+            Self::Blacklisted
+        } else if matches(&["paused"]) {
+            Self::Paused
+        } else {
+            Self::Other(message)
+        }
+    }
+
+    /// Reason reported to clients for a payment reverting with this reason.
+    pub fn error_reason(&self) -> FacilitatorErrorReason {
+        match self {
+            RevertReason::AuthorizationUsed => FacilitatorErrorReason::AuthorizationUsed,
+            RevertReason::InvalidSignature => FacilitatorErrorReason::InvalidSignature,
+            RevertReason::AuthorizationExpired => FacilitatorErrorReason::AuthorizationExpired,
+            RevertReason::AuthorizationNotYetValid => {
+                FacilitatorErrorReason::AuthorizationNotYetValid
+            }
+            RevertReason::InsufficientBalance => FacilitatorErrorReason::InsufficientFunds,
+            RevertReason::InsufficientAllowance => FacilitatorErrorReason::InsufficientAllowance,
+            RevertReason::Blacklisted | RevertReason::Paused => {
+                FacilitatorErrorReason::TransferBlocked
+            }
+            RevertReason::Other(_) => FacilitatorErrorReason::InvalidScheme,
+        }
+    }
+}
@@ -0,0 +1,549 @@
+//! Failover between the RPC endpoints of a network.
+//!
+//! A network may be served by several RPC endpoints, comma-separated in its `RPC_URL_*` variable,
+//! the primary one first. Calls go to the selected endpoint; a call the endpoint fails to answer
+//! (connection refused, timeout, HTTP error) is sent again to the other endpoints, in turn,
+//! before failing. JSON-RPC errors, e.g. a reverted call, are answers: they are not retried.
+//!
+//! An endpoint failing a call is skipped for a cooldown, or until it passes a health check, and
+//! the selection moves to the next healthy endpoint. Health checks call every endpoint
+//! (`eth_blockNumber`, or `getHealth` on Solana) at an interval, and measure their latency: the
+//! selected endpoint is replaced with a healthy one at least 20% faster, so that calls do not
+//! flap between endpoints of similar latency. Until measured, endpoints are preferred in their
+//! configured order. If every endpoint failed, calls still try them all.
+//!
+//! Metrics, with endpoints named by the host of their URL, so that keys in URLs are not exported:
+//! - `x402_rpc_errors` – Calls and health checks failed, by `network` and `endpoint`
+//! - `x402_rpc_switchovers` – Changes of the selected endpoint, by `network`, `from` and `to`
+//!
+//! Environment (see [`RpcFailover::from_env`]):
+//! - `RPC_HEALTH_CHECK_INTERVAL_SECS` – Interval of the health checks, in seconds or with a unit
+//!   (e.g. `1m`), up to 1 hour (default `15`, `0` disables them)
+//! - `RPC_FAILOVER_COOLDOWN_SECS` – How long an endpoint failing a call is skipped, unless it
+//!   passes a health check, up to 1 hour (default `30`)
+
+use alloy::rpc::client::BuiltInConnectionString;
+use alloy::rpc::json_rpc::{Id, Request, RequestPacket, ResponsePacket, ResponsePayload};
+use alloy::transports::{
+    BoxTransport, TransportConnect, TransportError, TransportErrorKind, TransportFut,
+};
+use async_trait::async_trait;
+use once_cell::sync::Lazy;
+use opentelemetry::KeyValue;
+use opentelemetry::metrics::Counter;
+use solana_client::client_error::{ClientError, ClientErrorKind, Result as ClientResult};
+use solana_client::http_sender::HttpSender;
+use solana_client::rpc_request::RpcRequest;
+use solana_client::rpc_sender::{RpcSender, RpcTransportStats};
+use std::fmt::Display;
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+use tokio::time::MissedTickBehavior;
+use tower::Service;
+
+use crate::duration::{self, DurationError, DurationRange};
+use crate::network::Network;
+
+const ENV_RPC_HEALTH_CHECK_INTERVAL_SECS: &str = "RPC_HEALTH_CHECK_INTERVAL_SECS";
+const ENV_RPC_FAILOVER_COOLDOWN_SECS: &str = "RPC_FAILOVER_COOLDOWN_SECS";
+
+const DEFAULT_HEALTH_CHECK_INTERVAL: Duration = Duration::from_secs(15);
+const DEFAULT_COOLDOWN: Duration = Duration::from_secs(30);
+const HEALTH_CHECK_INTERVAL_RANGE: DurationRange =
+    DurationRange::new(Duration::ZERO, Duration::from_secs(3600));
+const COOLDOWN_RANGE: DurationRange =
+    DurationRange::new(Duration::from_secs(1), Duration::from_secs(3600));
+
+/// Longest wait for the answer to a health check.
+const HEALTH_CHECK_TIMEOUT: Duration = Duration::from_secs(5);
+/// Latency of another endpoint, relative to the selected one, under which it gets selected.
+const SWITCH_LATENCY_RATIO: f64 = 0.8;
+/// Weight of the last health check in the latency of an endpoint.
+const LATENCY_SMOOTHING: f64 = 0.3;
+
+static RPC_ERRORS: Lazy<Counter<u64>> = Lazy::new(|| {
+    opentelemetry::global::meter("x402-rs")
+        .u64_counter("x402_rpc_errors")
+        .build()
+});
+
+static RPC_SWITCHOVERS: Lazy<Counter<u64>> = Lazy::new(|| {
+    opentelemetry::global::meter("x402-rs")
+        .u64_counter("x402_rpc_switchovers")
+        .build()
+});
+
+/// Health checks and cooldown of the endpoints of networks served by several of them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RpcFailover {
+    /// Interval of the health checks, `None` if disabled.
+    pub health_check_interval: Option<Duration>,
+    /// How long an endpoint failing a call is skipped, unless it passes a health check.
+    pub cooldown: Duration,
+}
+
+impl Default for RpcFailover {
+    fn default() -> Self {
+        Self {
+            health_check_interval: Some(DEFAULT_HEALTH_CHECK_INTERVAL),
+            cooldown: DEFAULT_COOLDOWN,
+        }
+    }
+}
+
+impl RpcFailover {
+    /// Reads `RPC_HEALTH_CHECK_INTERVAL_SECS` and `RPC_FAILOVER_COOLDOWN_SECS`, defaulting
+    /// unset ones.
+    pub fn from_env() -> Result<Self, DurationError> {
+        let health_check_interval = match duration::from_env(
+            ENV_RPC_HEALTH_CHECK_INTERVAL_SECS,
+            duration::SECOND,
+            HEALTH_CHECK_INTERVAL_RANGE,
+        )? {
+            Some(Duration::ZERO) => None,
+            Some(interval) => Some(interval),
+            None => Some(DEFAULT_HEALTH_CHECK_INTERVAL),
+        };
+        let cooldown = duration::from_env(
+            ENV_RPC_FAILOVER_COOLDOWN_SECS,
+            duration::SECOND,
+            COOLDOWN_RANGE,
+        )?
+        .unwrap_or(DEFAULT_COOLDOWN);
+        Ok(Self {
+            health_check_interval,
+            cooldown,
+        })
+    }
+}
+
+/// Splits comma-separated RPC URLs, dropping blank ones.
+pub fn parse_urls(urls: &str) -> Vec<String> {
+    urls.split(',')
+        .map(str::trim)
+        .filter(|url| !url.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+/// Name of the endpoint at `url` in logs and metrics: its host, as its path or query may hold
+/// an API key.
+fn endpoint_name(url: &str) -> String {
+    url::Url::parse(url)
+        .ok()
+        .and_then(|url| url.host_str().map(str::to_string))
+        .unwrap_or_else(|| "invalid".to_string())
+}
+
+#[derive(Debug, Default)]
+struct Health {
+    /// Smoothed latency of the health checks, `None` until the first one passes.
+    latency: Option<Duration>,
+    /// End of the cooldown of an endpoint that failed.
+    down_until: Option<Instant>,
+}
+
+impl Health {
+    fn is_up(&self, now: Instant) -> bool {
+        self.down_until.is_none_or(|until| until <= now)
+    }
+}
+
+struct Endpoint<C> {
+    name: String,
+    client: C,
+    health: Mutex<Health>,
+}
+
+/// Endpoints of a network, and the one selected, shared by the clones of a transport.
+struct Endpoints<C> {
+    network: Network,
+    endpoints: Vec<Endpoint<C>>,
+    selected: Mutex<usize>,
+    cooldown: Duration,
+}
+
+impl<C: Clone + Send + Sync + 'static> Endpoints<C> {
+    /// Endpoints of `clients`, named after their URL. Expects at least one.
+    fn new(network: Network, clients: Vec<(&str, C)>, cooldown: Duration) -> Self {
+        let endpoints = clients
+            .into_iter()
+            .map(|(url, client)| Endpoint {
+                name: endpoint_name(url),
+                client,
+                health: Mutex::new(Health::default()),
+            })
+            .collect();
+        Self {
+            network,
+            endpoints,
+            selected: Mutex::new(0),
+            cooldown,
+        }
+    }
+
+    /// Endpoints in the order a call tries them: the selected one, the other healthy ones,
+    /// fastest first, then the failed ones, the soonest out of cooldown first.
+    fn candidates(&self) -> Vec<usize> {
+        let now = Instant::now();
+        let selected = *self.selected.lock().unwrap();
+        let mut up = Vec::new();
+        let mut down = Vec::new();
+        for (index, endpoint) in self.endpoints.iter().enumerate() {
+            let health = endpoint.health.lock().unwrap();
+            if health.is_up(now) {
+                up.push((
+                    index != selected,
+                    health.latency.is_none(),
+                    health.latency,
+                    index,
+                ));
+            } else {
+                down.push((health.down_until, index));
+            }
+        }
+        up.sort();
+        down.sort();
+        up.into_iter()
+            .map(|(.., index)| index)
+            .chain(down.into_iter().map(|(_, index)| index))
+            .collect()
+    }
+
+    /// Sends a call to the endpoints in turn, until one answers. Errors for which `is_failure`
+    /// is `false` are answers.
+    async fn call<T, E, F, Fut>(&self, call: F, is_failure: fn(&E) -> bool) -> Result<T, E>
+    where
+        E: Display,
+        F: Fn(C) -> Fut,
+        Fut: Future<Output = Result<T, E>>,
+    {
+        let mut result = None;
+        for index in self.candidates() {
+            match call(self.endpoints[index].client.clone()).await {
+                Err(e) if is_failure(&e) => {
+                    self.failed(index, &e);
+                    self.reselect();
+                    result = Some(Err(e));
+                }
+                answer => {
+                    if result.is_some() {
+                        self.recovered(index, None);
+                        self.reselect();
+                    }
+                    return answer;
+                }
+            }
+        }
+        result.expect("A network has at least one RPC endpoint")
+    }
+
+    /// Skips the endpoint at `index` for the cooldown.
+    fn failed(&self, index: usize, error: &dyn Display) {
+        let endpoint = &self.endpoints[index];
+        RPC_ERRORS.add(
+            1,
+            &[
+                KeyValue::new("network", self.network.to_string()),
+                KeyValue::new("endpoint", endpoint.name.clone()),
+            ],
+        );
+        let mut health = endpoint.health.lock().unwrap();
+        if health.is_up(Instant::now()) {
+            tracing::warn!(
+                network = %self.network,
+                endpoint = %endpoint.name,
+                error = %error,
+                "RPC endpoint failed, skipped for {:?}",
+                self.cooldown
+            );
+        }
+        health.down_until = Some(Instant::now() + self.cooldown);
+    }
+
+    /// Ends the cooldown of the endpoint at `index`, and records its `latency` if measured.
+    fn recovered(&self, index: usize, latency: Option<Duration>) {
+        let endpoint = &self.endpoints[index];
+        let mut health = endpoint.health.lock().unwrap();
+        if health.down_until.take().is_some() {
+            tracing::info!(network = %self.network, endpoint = %endpoint.name, "RPC endpoint recovered");
+        }
+        if let Some(latency) = latency {
+            health.latency = Some(match health.latency {
+                Some(smoothed) => {
+                    smoothed.mul_f64(1.0 - LATENCY_SMOOTHING) + latency.mul_f64(LATENCY_SMOOTHING)
+                }
+                None => latency,
+            });
+        }
+    }
+
+    /// Selects the fastest healthy endpoint in place of a failed one, or of one slower by more
+    /// than [`SWITCH_LATENCY_RATIO`]. Keeps the selection if every endpoint failed.
+    fn reselect(&self) {
+        let now = Instant::now();
+        let mut selected = self.selected.lock().unwrap();
+        let (current_up, current_latency) = {
+            let health = self.endpoints[*selected].health.lock().unwrap();
+            (health.is_up(now), health.latency)
+        };
+        let best = self
+            .endpoints
+            .iter()
+            .enumerate()
+            .filter_map(|(index, endpoint)| {
+                let health = endpoint.health.lock().unwrap();
+                health.is_up(now).then_some((health.latency, index))
+            })
+            .min_by_key(|(latency, index)| (latency.is_none(), *latency, *index));
+        let Some((best_latency, best)) = best else {
+            return;
+        };
+        let switch = match (current_up, best_latency, current_latency) {
+            (false, ..) => true,
+            (true, Some(best_latency), Some(current_latency)) => {
+                best_latency.as_secs_f64() < current_latency.as_secs_f64() * SWITCH_LATENCY_RATIO
+            }
+            (true, ..) => false,
+        };
+        if !switch || best == *selected {
+            return;
+        }
+        let from = &self.endpoints[*selected].name;
+        let to = &self.endpoints[best].name;
+        RPC_SWITCHOVERS.add(
+            1,
+            &[
+                KeyValue::new("network", self.network.to_string()),
+                KeyValue::new("from", from.clone()),
+                KeyValue::new("to", to.clone()),
+            ],
+        );
+        tracing::warn!(network = %self.network, from = %from, to = %to, "Switched RPC endpoint");
+        *selected = best;
+    }
+
+    /// Checks the health of every endpoint with `probe`, and updates the selection.
+    async fn check<F, Fut>(&self, probe: &F)
+    where
+        F: Fn(C) -> Fut,
+        Fut: Future<Output = Result<(), String>>,
+    {
+        let probes = self.endpoints.iter().map(|endpoint| async {
+            let started = Instant::now();
+            let result = tokio::time::timeout(HEALTH_CHECK_TIMEOUT, probe(endpoint.client.clone()))
+                .await
+                .unwrap_or_else(|_| Err("health check timed out".to_string()));
+            (result, started.elapsed())
+        });
+        let results = futures_util::future::join_all(probes).await;
+        for (index, (result, latency)) in results.into_iter().enumerate() {
+            match result {
+                Ok(()) => self.recovered(index, Some(latency)),
+                Err(e) => self.failed(index, &e),
+            }
+        }
+        self.reselect();
+    }
+
+    /// Checks the health of every endpoint every `interval`, as long as a clone of the
+    /// transport is alive. Must be called from within a Tokio runtime.
+    fn start_health_checks<F, Fut>(self: &Arc<Self>, interval: Duration, probe: F)
+    where
+        F: Fn(C) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<(), String>> + Send + 'static,
+    {
+        let endpoints = Arc::downgrade(self);
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+            loop {
+                ticker.tick().await;
+                let Some(endpoints) = endpoints.upgrade() else {
+                    break;
+                };
+                endpoints.check(&probe).await;
+            }
+        });
+    }
+}
+
+/// JSON-RPC transport of an EVM network served by several endpoints, see the
+/// [module documentation](self).
+///
+/// Wrap it into an alloy client with [`ClientBuilder::transport`](alloy::rpc::client::ClientBuilder::transport).
+#[derive(Clone)]
+pub struct FailoverTransport {
+    endpoints: Arc<Endpoints<BoxTransport>>,
+    is_local: bool,
+}
+
+impl FailoverTransport {
+    /// Connects to the endpoints at `urls`, HTTP or WebSocket. Endpoints failing to connect are
+    /// skipped; fails if none connects. Must be called from within a Tokio runtime.
+    pub async fn connect(
+        network: Network,
+        urls: &[String],
+        failover: RpcFailover,
+    ) -> Result<Self, TransportError> {
+        let mut clients = Vec::new();
+        let mut is_local = true;
+        let mut error = None;
+        for url in urls {
+            match connect(url).await {
+                Ok((transport, local)) => {
+                    is_local &= local;
+                    clients.push((url.as_str(), transport));
+                }
+                Err(e) => {
+                    tracing::warn!(
+                        network = %network,
+                        endpoint = %endpoint_name(url),
+                        error = %e,
+                        "Can not connect to RPC endpoint, skipped"
+                    );
+                    error = Some(e);
+                }
+            }
+        }
+        if clients.is_empty() {
+            return Err(error.unwrap_or_else(|| TransportErrorKind::custom_str("No RPC endpoint")));
+        }
+        let endpoints = Arc::new(Endpoints::new(network, clients, failover.cooldown));
+        if let Some(interval) = failover.health_check_interval {
+            endpoints.start_health_checks(interval, |mut transport: BoxTransport| async move {
+                let request = Request::new("eth_blockNumber", Id::Number(0), ())
+                    .serialize()
+                    .map_err(|e| e.to_string())?;
+                let response = transport
+                    .call(RequestPacket::Single(request))
+                    .await
+                    .map_err(|e| e.to_string())?;
+                match response {
+                    ResponsePacket::Single(response) => match response.payload {
+                        ResponsePayload::Success(_) => Ok(()),
+                        ResponsePayload::Failure(e) => Err(e.to_string()),
+                    },
+                    ResponsePacket::Batch(_) => Err("unexpected batch response".to_string()),
+                }
+            });
+        }
+        Ok(Self {
+            endpoints,
+            is_local,
+        })
+    }
+
+    /// Whether every endpoint is on the local machine, for alloy's polling interval.
+    pub fn is_local(&self) -> bool {
+        self.is_local
+    }
+}
+
+async fn connect(url: &str) -> Result<(BoxTransport, bool), TransportError> {
+    let connection: BuiltInConnectionString = url.parse()?;
+    let transport = connection.get_transport().await?;
+    Ok((transport, connection.is_local()))
+}
+
+impl Service<RequestPacket> for FailoverTransport {
+    type Response = ResponsePacket;
+    type Error = TransportError;
+    type Future = TransportFut<'static>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, request: RequestPacket) -> Self::Future {
+        let endpoints = self.endpoints.clone();
+        Box::pin(async move {
+            // Error responses of the node are in the packet: every error is the endpoint's
+            endpoints
+                .call(
+                    |mut transport: BoxTransport| transport.call(request.clone()),
+                    |_| true,
+                )
+                .await
+        })
+    }
+}
+
+/// RPC sender of a Solana network served by several endpoints, see the
+/// [module documentation](self).
+///
+/// Wrap it into a client with [`RpcClient::new_sender`](solana_client::nonblocking::rpc_client::RpcClient::new_sender).
+#[derive(Clone)]
+pub struct FailoverSender {
+    endpoints: Arc<Endpoints<Arc<HttpSender>>>,
+}
+
+impl FailoverSender {
+    /// Sends calls to the endpoints at `urls`. Expects at least one. Must be called from within
+    /// a Tokio runtime.
+    pub fn new(network: Network, urls: &[String], failover: RpcFailover) -> Self {
+        let clients = urls
+            .iter()
+            .map(|url| (url.as_str(), Arc::new(HttpSender::new(url.clone()))))
+            .collect();
+        let endpoints = Arc::new(Endpoints::new(network, clients, failover.cooldown));
+        if let Some(interval) = failover.health_check_interval {
+            endpoints.start_health_checks(interval, |sender: Arc<HttpSender>| async move {
+                sender
+                    .send(RpcRequest::GetHealth, serde_json::Value::Null)
+                    .await
+                    .map(|_| ())
+                    .map_err(|e| e.to_string())
+            });
+        }
+        Self { endpoints }
+    }
+}
+
+/// Whether the endpoint failed to answer, rather than answered with an error.
+fn is_sender_failure(error: &ClientError) -> bool {
+    matches!(
+        error.kind(),
+        ClientErrorKind::Io(_) | ClientErrorKind::Reqwest(_) | ClientErrorKind::Middleware(_)
+    )
+}
+
+#[async_trait]
+impl RpcSender for FailoverSender {
+    async fn send(
+        &self,
+        request: RpcRequest,
+        params: serde_json::Value,
+    ) -> ClientResult<serde_json::Value> {
+        self.endpoints
+            .call(
+                |sender: Arc<HttpSender>| {
+                    let params = params.clone();
+                    async move { sender.send(request, params).await }
+                },
+                is_sender_failure,
+            )
+            .await
+    }
+
+    fn get_transport_stats(&self) -> RpcTransportStats {
+        self.endpoints
+            .endpoints
+            .iter()
+            .map(|endpoint| endpoint.client.get_transport_stats())
+            .fold(RpcTransportStats::default(), |total, stats| {
+                RpcTransportStats {
+                    request_count: total.request_count + stats.request_count,
+                    elapsed_time: total.elapsed_time + stats.elapsed_time,
+                    rate_limited_time: total.rate_limited_time + stats.rate_limited_time,
+                }
+            })
+    }
+
+    fn url(&self) -> String {
+        let selected = *self.endpoints.selected.lock().unwrap();
+        self.endpoints.endpoints[selected].client.url()
+    }
+}
@@ -0,0 +1,186 @@
+//! Fake settlement on the sandboxed `test` network.
+//!
+//! [`Network::Test`] is no chain: [`SandboxProvider`] verifies payments structurally, and settles
+//! them without sending anything anywhere, returning a synthetic transaction hash. Integrators
+//! develop buyer, seller and stream flows end to end against it, without testnet funds.
+//!
+//! A payment is valid if it is an `exact` EIP-3009 authorization to the `payTo` of its
+//! requirements, of at least `maxAmountRequired`, within its validity window. Signatures and
+//! balances are not checked: nothing moves. Replay protection, settlement caps and events apply
+//! as on any network, being enforced by the facilitator.
+//!
+//! The network is only served when explicitly enabled, see
+//! [`ProviderCache::from_env`](crate::provider_cache::ProviderCache::from_env):
+//! - `TEST_NETWORK` – `true` to serve the `test` network (default `false`)
+
+use alloy::primitives::{Address, U256, address, keccak256};
+use futures_util::future::BoxFuture;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use crate::chain::{ChainProvider, FacilitatorLocalError, verify_warnings};
+use crate::network::Network;
+use crate::timestamp::UnixTimestamp;
+use crate::types::{
+    EvmAddress, ExactEvmPayloadAuthorization, ExactPaymentPayload, MixedAddress, PaymentPayload,
+    PaymentRequirements, Scheme, SettleRequest, SettleResponse, SettlementDetails, TransactionHash,
+    VerifyRequest, VerifyResponse,
+};
+
+/// Address of the facilitator on the `test` network, advertised as `feePayer`.
+pub const SANDBOX_FACILITATOR: Address = address!("0x0000000000000000000000000000000000004020");
+
+/// Verifies and fakes the settlement of payments on [`Network::Test`], see the
+/// [module documentation](self).
+///
+/// Clones share the same block height.
+#[derive(Clone, Debug, Default)]
+pub struct SandboxProvider {
+    /// Number of the last synthetic block.
+    block_number: Arc<AtomicU64>,
+}
+
+impl SandboxProvider {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Checks `payload` against `requirements`, returning the authorization.
+    fn assert_valid_payment<'a>(
+        &self,
+        payload: &'a PaymentPayload,
+        requirements: &PaymentRequirements,
+    ) -> Result<&'a ExactEvmPayloadAuthorization, FacilitatorLocalError> {
+        let ExactPaymentPayload::Evm(evm) = &payload.payload else {
+            return Err(FacilitatorLocalError::UnsupportedScheme(
+                payload.scheme.clone(),
+            ));
+        };
+        let authorization = &evm.authorization;
+        let payer: MixedAddress = authorization.from.into();
+        for network in [payload.network, requirements.network] {
+            if network != Network::Test {
+                return Err(FacilitatorLocalError::NetworkMismatch(
+                    Some(payer),
+                    Network::Test,
+                    network,
+                ));
+            }
+        }
+        if payload.scheme != Scheme::Exact || requirements.scheme != Scheme::Exact {
+            return Err(FacilitatorLocalError::SchemeMismatch(
+                Some(payer),
+                Scheme::Exact,
+                payload.scheme.clone(),
+            ));
+        }
+        let pay_to: EvmAddress = requirements
+            .pay_to
+            .clone()
+            .try_into()
+            .map_err(|e| FacilitatorLocalError::InvalidAddress(format!("{e:?}")))?;
+        if authorization.to != pay_to {
+            return Err(FacilitatorLocalError::ReceiverMismatch(
+                payer,
+                authorization.to.to_string(),
+                pay_to.to_string(),
+            ));
+        }
+        let now = UnixTimestamp::try_now().map_err(FacilitatorLocalError::ClockError)?;
+        if authorization.valid_before < now + 6 {
+            return Err(FacilitatorLocalError::InvalidTiming(
+                payer,
+                format!(
+                    "Expired: now {} > valid_before {}",
+                    now + 6,
+                    authorization.valid_before
+                ),
+            ));
+        }
+        if authorization.valid_after > now {
+            return Err(FacilitatorLocalError::InvalidTiming(
+                payer,
+                format!(
+                    "Not active yet: valid_after {} > now {now}",
+                    authorization.valid_after
+                ),
+            ));
+        }
+        let value: U256 = authorization.value.into();
+        if value < requirements.max_amount_required.0 {
+            return Err(FacilitatorLocalError::InsufficientValue(payer));
+        }
+        if evm.signature.0.is_empty() {
+            return Err(FacilitatorLocalError::InvalidSignature(
+                payer,
+                "Empty signature".to_string(),
+            ));
+        }
+        Ok(authorization)
+    }
+}
+
+impl ChainProvider for SandboxProvider {
+    fn network(&self) -> Network {
+        Network::Test
+    }
+
+    fn signer_address(&self) -> MixedAddress {
+        SANDBOX_FACILITATOR.into()
+    }
+
+    fn verify<'a>(
+        &'a self,
+        request: &'a VerifyRequest,
+    ) -> BoxFuture<'a, Result<VerifyResponse, FacilitatorLocalError>> {
+        Box::pin(async move {
+            let requirements = &request.payment_requirements;
+            let authorization =
+                self.assert_valid_payment(&request.payment_payload, requirements)?;
+            let warnings = verify_warnings(
+                requirements,
+                Some(authorization.value),
+                Some(authorization.valid_before),
+            );
+            Ok(VerifyResponse::valid(authorization.from.into()).with_warnings(warnings))
+        })
+    }
+
+    fn settle<'a>(
+        &'a self,
+        request: &'a SettleRequest,
+    ) -> BoxFuture<'a, Result<SettleResponse, FacilitatorLocalError>> {
+        Box::pin(async move {
+            let authorization =
+                self.assert_valid_payment(&request.payment_payload, &request.payment_requirements)?;
+            // Same payment, same transaction
+            let hash = keccak256(
+                [
+                    authorization.from.0.as_slice(),
+                    authorization.nonce.0.as_slice(),
+                ]
+                .concat(),
+            );
+            let block_number = self.block_number.fetch_add(1, Ordering::Relaxed) + 1;
+            tracing::info!(tx = %hash, block_number, "Faked settlement on the test network");
+            Ok(SettleResponse {
+                success: true,
+                error_reason: None,
+                payer: authorization.from.into(),
+                transaction: Some(TransactionHash::Evm(hash.0)),
+                network: Network::Test,
+                settlement: Some(SettlementDetails {
+                    from: authorization.from.into(),
+                    to: authorization.to.into(),
+                    amount: authorization.value,
+                    log_index: Some(0),
+                    authorization_log_index: Some(1),
+                    block_number: Some(block_number),
+                }),
+                confirmations: Some(1),
+                metadata: None,
+                receipt: None,
+            })
+        })
+    }
+}
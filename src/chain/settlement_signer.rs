@@ -0,0 +1,80 @@
+//! Signers of EVM settlement transactions.
+//!
+//! Settlement transactions are signed by a [`SettlementSigner`]: a local private key, or a key
+//! kept in a KMS or a remote signer, which never leaves it (`kms` feature, see
+//! [`kms`](crate::chain::kms)). Operators of such backends never handle plaintext keys.
+//!
+//! A signer signs transaction hashes. [`SettlementTxSigner`] turns it into a transaction signer
+//! of an alloy [`EthereumWallet`](alloy::network::EthereumWallet), so that every settlement path
+//! signs through it.
+
+use alloy::consensus::SignableTransaction;
+use alloy::network::TxSigner;
+use alloy::primitives::{Address, B256, Signature};
+use alloy::signers::SignerSync;
+use alloy::signers::local::PrivateKeySigner;
+use futures_util::future::BoxFuture;
+use std::fmt::Debug;
+use std::sync::Arc;
+
+#[derive(Debug, thiserror::Error)]
+#[allow(dead_code)] // Public for consumption by downstream crates.
+pub enum SettlementSignerError {
+    #[error("Invalid signer configuration: {0}")]
+    Config(String),
+    #[error("Signer request failed: {0}")]
+    Request(String),
+    #[error("Invalid signer response: {0}")]
+    Response(String),
+    #[error("Signing failed: {0}")]
+    Signing(String),
+}
+
+/// Signs the transactions of one settlement account.
+pub trait SettlementSigner: Debug + Send + Sync {
+    /// Address of the settlement account.
+    fn address(&self) -> Address;
+
+    /// Signs `hash`, the signature hash of a transaction.
+    fn sign_hash<'a>(
+        &'a self,
+        hash: &'a B256,
+    ) -> BoxFuture<'a, Result<Signature, SettlementSignerError>>;
+}
+
+impl SettlementSigner for PrivateKeySigner {
+    fn address(&self) -> Address {
+        PrivateKeySigner::address(self)
+    }
+
+    fn sign_hash<'a>(
+        &'a self,
+        hash: &'a B256,
+    ) -> BoxFuture<'a, Result<Signature, SettlementSignerError>> {
+        Box::pin(async move {
+            self.sign_hash_sync(hash)
+                .map_err(|e| SettlementSignerError::Signing(e.to_string()))
+        })
+    }
+}
+
+/// A [`SettlementSigner`] as the transaction signer of an alloy wallet.
+#[derive(Debug, Clone)]
+pub struct SettlementTxSigner(pub Arc<dyn SettlementSigner>);
+
+#[async_trait::async_trait]
+impl TxSigner<Signature> for SettlementTxSigner {
+    fn address(&self) -> Address {
+        self.0.address()
+    }
+
+    async fn sign_transaction(
+        &self,
+        tx: &mut dyn SignableTransaction<Signature>,
+    ) -> alloy::signers::Result<Signature> {
+        self.0
+            .sign_hash(&tx.signature_hash())
+            .await
+            .map_err(alloy::signers::Error::other)
+    }
+}
@@ -0,0 +1,336 @@
+//! Settlement accounts of an EVM network, used in turn.
+//!
+//! A single account serializes settlements behind its nonce sequence: every transaction waits
+//! for its nonce, and a stuck transaction holds up all later ones. [`SignerPool`] spreads
+//! settlement transactions over several accounts, round-robin, each with the nonces of its own
+//! [`AccountNonces`], so that concurrent settlements do not contend for the same sequence.
+//!
+//! The first account is the primary one: it is the `feePayer` advertised to clients, hence the
+//! spender of their `permit` and `upto` payments. Those are settled by the account they name as
+//! spender, any account of the pool. Every account pays gas for its transactions: fund them all.
+//!
+//! The accounts can be rotated at runtime, through the admin API (`POST /admin/signers/rotate`),
+//! see [`SignerPool::rotate`]: the accounts rotated out stop sending new transactions, but still
+//! settle the payments naming them as spender.
+//!
+//! Accounts come from comma-separated private keys in `EVM_PRIVATE_KEY`, or are derived from a
+//! BIP-39 mnemonic along the standard Ethereum path `m/44'/60'/0'/0/{index}`, see
+//! [`mnemonic_signers`].
+//!
+//! Environment (see [`SignerType`](crate::provider_cache::SignerType)):
+//! - `SIGNER_TYPE` – `private-key` (default) or `mnemonic`
+//! - `EVM_PRIVATE_KEY` – Private key of the settlement account, or comma-separated keys of several
+//! - `EVM_MNEMONIC` – BIP-39 mnemonic the accounts are derived from, with `SIGNER_TYPE=mnemonic`
+//! - `EVM_SIGNER_COUNT` – Number of accounts derived from the mnemonic (default `1`)
+
+use alloy::network::{Ethereum, EthereumWallet, NetworkWallet};
+use alloy::primitives::{Address, B256};
+use alloy::signers::k256::elliptic_curve::sec1::ToEncodedPoint;
+use alloy::signers::k256::{NonZeroScalar, PublicKey, Scalar, SecretKey};
+use alloy::signers::local::PrivateKeySigner;
+use hmac::{Hmac, Mac};
+use serde::Serialize;
+use sha2::Sha512;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+use crate::chain::FacilitatorLocalError;
+use crate::chain::account_nonce::{AccountNonces, NonceResync};
+use crate::chain::evm::InnerProvider;
+use crate::network::Network;
+
+/// Rounds of PBKDF2 turning a mnemonic into a seed, per BIP-39.
+const SEED_ROUNDS: u32 = 2048;
+/// First index of hardened derivation, per BIP-32.
+const HARDENED: u32 = 1 << 31;
+/// Derivation path of Ethereum accounts, up to the account index.
+const ETHEREUM_PATH: [u32; 4] = [44 | HARDENED, 60 | HARDENED, HARDENED, 0];
+
+#[derive(Debug, thiserror::Error)]
+pub enum SignerPoolError {
+    #[error("Invalid private key at position {0}")]
+    PrivateKey(usize),
+    #[error("Invalid mnemonic: expected 12 to 24 words")]
+    Mnemonic,
+    #[error("Invalid signer count: {0}, expected 1 to 1000")]
+    Count(String),
+    #[error("Failed to derive signer {0} from the mnemonic")]
+    Derivation(u32),
+}
+
+/// Signers of comma-separated private keys, e.g. the value of `EVM_PRIVATE_KEY`.
+///
+/// # Errors
+/// Fails if any key is invalid, naming its position only: keys never end up in logs.
+pub fn private_key_signers(keys: &str) -> Result<Vec<PrivateKeySigner>, SignerPoolError> {
+    keys.split(',')
+        .map(str::trim)
+        .filter(|key| !key.is_empty())
+        .enumerate()
+        .map(|(i, key)| key.parse().map_err(|_| SignerPoolError::PrivateKey(i)))
+        .collect()
+}
+
+/// The first `count` signers derived from BIP-39 `mnemonic`, along `m/44'/60'/0'/0/{index}`:
+/// the accounts of wallets such as MetaMask or Anvil for the same mnemonic.
+///
+/// The words are not checked against the BIP-39 word list, as the seed does not depend on it.
+///
+/// ```
+/// use x402_rs::chain::signer_pool::mnemonic_signers;
+///
+/// let signers =
+///     mnemonic_signers("test test test test test test test test test test test junk", 2).unwrap();
+/// assert_eq!(
+///     signers[0].address().to_string(),
+///     "0xf39Fd6e51aad88F6F4ce6aB8827279cffFb92266"
+/// );
+/// assert_eq!(
+///     signers[1].address().to_string(),
+///     "0x70997970C51812dc3A010C7d01b50e0d17dc79C8"
+/// );
+/// ```
+///
+/// # Errors
+/// Fails if the mnemonic is not 12 to 24 words, or in the astronomically unlikely case of a
+/// derived key being invalid.
+pub fn mnemonic_signers(
+    mnemonic: &str,
+    count: u32,
+) -> Result<Vec<PrivateKeySigner>, SignerPoolError> {
+    let words = mnemonic.split_whitespace().collect::<Vec<_>>();
+    if !(12..=24).contains(&words.len()) {
+        return Err(SignerPoolError::Mnemonic);
+    }
+    let mut seed = [0u8; 64];
+    pbkdf2::pbkdf2::<Hmac<Sha512>>(
+        words.join(" ").as_bytes(),
+        b"mnemonic",
+        SEED_ROUNDS,
+        &mut seed,
+    );
+    let (mut key, mut chain_code) = split(hmac_sha512(b"Bitcoin seed", &[&seed]));
+    for index in ETHEREUM_PATH {
+        (key, chain_code) =
+            derive_child(&key, &chain_code, index).ok_or(SignerPoolError::Derivation(index))?;
+    }
+    (0..count)
+        .map(|index| {
+            let (key, _) =
+                derive_child(&key, &chain_code, index).ok_or(SignerPoolError::Derivation(index))?;
+            PrivateKeySigner::from_bytes(&B256::from(key))
+                .map_err(|_| SignerPoolError::Derivation(index))
+        })
+        .collect()
+}
+
+fn hmac_sha512(key: &[u8], data: &[&[u8]]) -> [u8; 64] {
+    let mut mac = Hmac::<Sha512>::new_from_slice(key).expect("HMAC takes keys of any length");
+    for data in data {
+        mac.update(data);
+    }
+    mac.finalize().into_bytes().into()
+}
+
+fn split(bytes: [u8; 64]) -> ([u8; 32], [u8; 32]) {
+    let mut left = [0u8; 32];
+    let mut right = [0u8; 32];
+    left.copy_from_slice(&bytes[..32]);
+    right.copy_from_slice(&bytes[32..]);
+    (left, right)
+}
+
+/// Private key and chain code of child `index` of extended private key `(key, chain_code)`,
+/// per BIP-32, or `None` for the rare invalid children.
+fn derive_child(key: &[u8; 32], chain_code: &[u8; 32], index: u32) -> Option<([u8; 32], [u8; 32])> {
+    let secret = SecretKey::from_slice(key).ok()?;
+    let index_bytes = index.to_be_bytes();
+    let mac = if index >= HARDENED {
+        hmac_sha512(chain_code, &[&[0], key, &index_bytes])
+    } else {
+        let public: PublicKey = secret.public_key();
+        let point = public.to_encoded_point(true);
+        hmac_sha512(chain_code, &[point.as_bytes(), &index_bytes])
+    };
+    let (tweak, child_chain_code) = split(mac);
+    let tweak = Option::<NonZeroScalar>::from(NonZeroScalar::from_repr(tweak.into()))?;
+    let child: Scalar = *tweak + *secret.to_nonzero_scalar();
+    let child = Option::<NonZeroScalar>::from(NonZeroScalar::new(child))?;
+    Some((child.to_bytes().into(), child_chain_code))
+}
+
+/// Settlement accounts of a network before and after a rotation, see [`SignerPool::rotate`].
+#[derive(Debug, Clone, Serialize)]
+pub struct SignerRotation {
+    pub network: Network,
+    /// Accounts before the rotation, the primary one first.
+    pub previous: Vec<Address>,
+    /// Accounts after the rotation, the primary one first.
+    pub current: Vec<Address>,
+}
+
+/// Settlement accounts of a network, with their nonces, see the [module documentation](self).
+///
+/// Clones share the same state.
+#[derive(Clone, Debug)]
+pub struct SignerPool {
+    network: Network,
+    accounts: Arc<RwLock<PoolAccounts>>,
+    /// Index of the account sending the next transaction, modulo the number of accounts.
+    next: Arc<AtomicUsize>,
+}
+
+#[derive(Debug)]
+struct PoolAccounts {
+    /// Accounts sending transactions, the primary one first.
+    active: Vec<AccountNonces>,
+    /// Accounts rotated out, still settling the payments naming them as spender.
+    retired: Vec<AccountNonces>,
+    /// Provider and interval of the nonce checks, once started, see [`SignerPool::start`].
+    sync: Option<(InnerProvider, Duration)>,
+}
+
+impl SignerPool {
+    /// Pool of the signers of `wallet`: its default signer as the primary account, then the
+    /// others in address order.
+    pub fn from_wallet(network: Network, wallet: &EthereumWallet) -> Self {
+        let active = wallet_addresses(wallet)
+            .map(|address| AccountNonces::new(network, address))
+            .collect();
+        Self {
+            network,
+            accounts: Arc::new(RwLock::new(PoolAccounts {
+                active,
+                retired: Vec::new(),
+                sync: None,
+            })),
+            next: Arc::new(AtomicUsize::new(0)),
+        }
+    }
+
+    /// The primary account, advertised to clients as the `feePayer`.
+    pub fn primary(&self) -> AccountNonces {
+        self.accounts.read().unwrap().active[0].clone()
+    }
+
+    /// The account sending the next transaction, in turn.
+    pub fn next(&self) -> AccountNonces {
+        let index = self.next.fetch_add(1, Ordering::Relaxed);
+        let accounts = self.accounts.read().unwrap();
+        accounts.active[index % accounts.active.len()].clone()
+    }
+
+    /// The account of `address`, if part of the pool or rotated out of it.
+    pub fn by_address(&self, address: Address) -> Option<AccountNonces> {
+        let accounts = self.accounts.read().unwrap();
+        accounts
+            .active
+            .iter()
+            .chain(&accounts.retired)
+            .find(|account| account.address() == address)
+            .cloned()
+    }
+
+    /// Addresses of the accounts, the primary one first.
+    pub fn addresses(&self) -> Vec<Address> {
+        let accounts = self.accounts.read().unwrap();
+        accounts.active.iter().map(AccountNonces::address).collect()
+    }
+
+    /// Addresses of the accounts rotated out of the pool.
+    pub fn retired_addresses(&self) -> Vec<Address> {
+        let accounts = self.accounts.read().unwrap();
+        accounts
+            .retired
+            .iter()
+            .map(AccountNonces::address)
+            .collect()
+    }
+
+    /// Replaces the accounts of the pool with the signers of the wallet of `sender`, which sends
+    /// their transactions: its default signer as the primary account, then the others in
+    /// address order.
+    ///
+    /// Accounts rotated out are kept to settle the payments naming them as spender, e.g. `permit`
+    /// payments signed for the previous primary account. Accounts kept keep their nonces.
+    pub fn rotate(&self, sender: InnerProvider) {
+        let addresses = wallet_addresses(sender.wallet()).collect::<Vec<_>>();
+        let mut accounts = self.accounts.write().unwrap();
+        let PoolAccounts {
+            active,
+            retired,
+            sync,
+        } = &mut *accounts;
+        let mut previous = std::mem::take(active);
+        previous.append(retired);
+        for address in addresses {
+            let account = match previous
+                .iter()
+                .position(|account| account.address() == address)
+            {
+                Some(index) => previous.swap_remove(index),
+                None => {
+                    let account = AccountNonces::new(self.network, address);
+                    if let Some((provider, interval)) = sync {
+                        account.start(provider.clone(), *interval);
+                    }
+                    account
+                }
+            };
+            active.push(account.with_sender(sender.clone()));
+        }
+        *retired = previous;
+        tracing::warn!(
+            network = %self.network,
+            active = ?active.iter().map(AccountNonces::address).collect::<Vec<_>>(),
+            retired = ?retired.iter().map(AccountNonces::address).collect::<Vec<_>>(),
+            "Rotated the settlement accounts"
+        );
+    }
+
+    /// Checks the nonce of every account against the chain every `interval`, see
+    /// [`AccountNonces::start`]. Must be called from within a Tokio runtime.
+    pub fn start(&self, provider: InnerProvider, interval: Duration) {
+        let mut accounts = self.accounts.write().unwrap();
+        for account in accounts.active.iter().chain(&accounts.retired) {
+            account.start(provider.clone(), interval);
+        }
+        accounts.sync = Some((provider, interval));
+    }
+
+    /// Resynchronizes the nonce of every account with the chain.
+    ///
+    /// # Errors
+    /// Return [`FacilitatorLocalError::ContractCall`] if an RPC call fails.
+    pub async fn resync(
+        &self,
+        provider: &InnerProvider,
+    ) -> Result<Vec<NonceResync>, FacilitatorLocalError> {
+        let accounts = {
+            let accounts = self.accounts.read().unwrap();
+            accounts
+                .active
+                .iter()
+                .chain(&accounts.retired)
+                .cloned()
+                .collect::<Vec<_>>()
+        };
+        let mut resyncs = Vec::with_capacity(accounts.len());
+        for account in accounts {
+            resyncs.push(account.resync(provider).await?);
+        }
+        Ok(resyncs)
+    }
+}
+
+/// Addresses of the signers of `wallet`: its default signer first, then the others in address
+/// order.
+fn wallet_addresses(wallet: &EthereumWallet) -> impl Iterator<Item = Address> {
+    let primary = NetworkWallet::<Ethereum>::default_signer_address(wallet);
+    let mut others = NetworkWallet::<Ethereum>::signer_addresses(wallet)
+        .filter(|address| *address != primary)
+        .collect::<Vec<_>>();
+    others.sort();
+    std::iter::once(primary).chain(others)
+}
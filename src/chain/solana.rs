@@ -1,4 +1,4 @@
-use crate::chain::{FacilitatorLocalError, NetworkProviderOps};
+use crate::chain::{FacilitatorLocalError, NetworkProviderOps, verify_warnings};
 use crate::facilitator::Facilitator;
 use crate::network::Network;
 use crate::types::{
@@ -6,8 +6,11 @@ use crate::types::{
     SettleRequest, SettleResponse, SupportedPaymentKind, SupportedPaymentKindExtra,
     SupportedPaymentKindsResponse, TokenAmount, TransactionHash, VerifyRequest, VerifyResponse,
 };
+use crate::verify_pool;
 use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_client::rpc_client::RpcClientConfig;
 use solana_client::rpc_config::{RpcSendTransactionConfig, RpcSimulateTransactionConfig};
+use solana_client::rpc_sender::RpcSender;
 use solana_commitment_config::CommitmentConfig;
 use solana_sdk::instruction::CompiledInstruction;
 use solana_sdk::pubkey::Pubkey;
@@ -42,6 +45,7 @@ impl TryFrom<Network> for SolanaChain {
             Network::Polygon => Err(FacilitatorLocalError::UnsupportedNetwork(None)),
             Network::Sei => Err(FacilitatorLocalError::UnsupportedNetwork(None)),
             Network::SeiTestnet => Err(FacilitatorLocalError::UnsupportedNetwork(None)),
+            Network::Test => Err(FacilitatorLocalError::UnsupportedNetwork(None)),
         }
     }
 }
@@ -107,6 +111,45 @@ impl SolanaProvider {
         })
     }
 
+    /// Like [`SolanaProvider::try_new`], sending RPC calls through `sender`, e.g. a
+    /// [`FailoverSender`](crate::chain::rpc_failover::FailoverSender).
+    pub fn try_with_sender<S: RpcSender + Send + Sync + 'static>(
+        keypair: Keypair,
+        sender: S,
+        network: Network,
+    ) -> Result<Self, FacilitatorLocalError> {
+        let chain = SolanaChain::try_from(network)?;
+        let config = RpcClientConfig::with_commitment(CommitmentConfig::default());
+        let rpc_client = RpcClient::new_sender(sender, config);
+        Ok(Self {
+            keypair: Arc::new(keypair),
+            chain,
+            rpc_client: Arc::new(rpc_client),
+        })
+    }
+
+    /// Latest slot processed by the node.
+    ///
+    /// # Errors
+    /// Return [`FacilitatorLocalError::ContractCall`] if the RPC call fails.
+    pub async fn slot(&self) -> Result<u64, FacilitatorLocalError> {
+        self.rpc_client
+            .get_slot()
+            .await
+            .map_err(|e| FacilitatorLocalError::ContractCall(format!("{e}")))
+    }
+
+    /// Balance of the fee payer, in lamports.
+    ///
+    /// # Errors
+    /// Return [`FacilitatorLocalError::ContractCall`] if the RPC call fails.
+    pub async fn fee_payer_balance(&self) -> Result<u64, FacilitatorLocalError> {
+        self.rpc_client
+            .get_balance(&self.keypair.pubkey())
+            .await
+            .map_err(|e| FacilitatorLocalError::ContractCall(format!("{e}")))
+    }
+
     pub fn verify_compute_limit_instruction(
         &self,
         transaction: &VersionedTransaction,
@@ -351,10 +394,15 @@ impl SolanaProvider {
 
         // Assert valid payment START
         let payment_payload = match &payload.payload {
-            ExactPaymentPayload::Evm(..) => {
+            ExactPaymentPayload::Evm(..) | ExactPaymentPayload::Permit(..) => {
                 return Err(FacilitatorLocalError::UnsupportedNetwork(None));
             }
             ExactPaymentPayload::Solana(payload) => payload,
+            ExactPaymentPayload::Custom(_) => {
+                return Err(FacilitatorLocalError::UnsupportedScheme(
+                    payload.scheme.clone(),
+                ));
+            }
         };
         if payload.network != self.network() {
             return Err(FacilitatorLocalError::NetworkMismatch(
@@ -373,16 +421,19 @@ impl SolanaProvider {
         if payload.scheme != requirements.scheme {
             return Err(FacilitatorLocalError::SchemeMismatch(
                 None,
-                requirements.scheme,
-                payload.scheme,
+                requirements.scheme.clone(),
+                payload.scheme.clone(),
             ));
         }
         let transaction_b64_string = payment_payload.transaction.clone();
-        let bytes = Base64Bytes::from(transaction_b64_string.as_bytes())
-            .decode()
-            .map_err(|e| FacilitatorLocalError::DecodingError(format!("{e}")))?;
-        let transaction = bincode::deserialize::<VersionedTransaction>(bytes.as_slice())
-            .map_err(|e| FacilitatorLocalError::DecodingError(format!("{e}")))?;
+        let transaction = verify_pool::spawn(move || {
+            let bytes = Base64Bytes::from(transaction_b64_string.as_bytes())
+                .decode()
+                .map_err(|e| FacilitatorLocalError::DecodingError(format!("{e}")))?;
+            bincode::deserialize::<VersionedTransaction>(bytes.as_slice())
+                .map_err(|e| FacilitatorLocalError::DecodingError(format!("{e}")))
+        })
+        .await?;
 
         // perform transaction introspection to validate the transaction structure and details
         let instructions = transaction.message.instructions();
@@ -403,7 +454,9 @@ impl SolanaProvider {
 
         // simulate the transaction to ensure it will execute successfully
         // JS: signAndSimulateTransaction
-        let tx = TransactionInt::new(transaction.clone()).sign(&self.keypair)?;
+        let tx = TransactionInt::new(transaction.clone())
+            .sign_in_pool(&self.keypair)
+            .await?;
         // let mut tx = transaction.clone();
         // let message_bytes = tx.message.serialize();
         // let sig = self
@@ -483,12 +536,15 @@ impl Facilitator for SolanaProvider {
 
     async fn verify(&self, request: &VerifyRequest) -> Result<VerifyResponse, Self::Error> {
         let verification = self.verify_transfer(request).await?;
-        Ok(VerifyResponse::valid(verification.payer.into()))
+        let warnings = verify_warnings(&request.payment_requirements, None, None);
+        Ok(VerifyResponse::valid(verification.payer.into()).with_warnings(warnings))
     }
 
     async fn settle(&self, request: &SettleRequest) -> Result<SettleResponse, Self::Error> {
         let verification = self.verify_transfer(request).await?;
-        let tx = TransactionInt::new(verification.transaction).sign(&self.keypair)?;
+        let tx = TransactionInt::new(verification.transaction)
+            .sign_in_pool(&self.keypair)
+            .await?;
         // Verify if fully signed
         if !tx.is_fully_signed() {
             tracing::event!(Level::WARN, status = "failed", "undersigned transaction");
@@ -498,6 +554,10 @@ impl Facilitator for SolanaProvider {
                 payer: verification.payer.into(),
                 transaction: None,
                 network: self.network(),
+                settlement: None,
+                confirmations: None,
+                metadata: None,
+                receipt: None,
             });
         }
         let tx_sig = tx
@@ -509,6 +569,10 @@ impl Facilitator for SolanaProvider {
             payer: verification.payer.into(),
             transaction: Some(TransactionHash::Solana(*tx_sig.as_array())),
             network: self.network(),
+            settlement: None,
+            confirmations: None,
+            metadata: None,
+            receipt: None,
         };
         Ok(settle_response)
     }
@@ -522,7 +586,11 @@ impl Facilitator for SolanaProvider {
                 fee_payer: self.signer_address(),
             }),
         }];
-        Ok(SupportedPaymentKindsResponse { kinds })
+        Ok(SupportedPaymentKindsResponse {
+            kinds,
+            extensions: Vec::new(),
+            gas: Vec::new(),
+        })
     }
 }
 
@@ -643,6 +711,12 @@ impl TransactionInt {
         Ok(Self { inner: tx })
     }
 
+    /// [`sign`](Self::sign) on the [verification pool](crate::verify_pool), off the async runtime.
+    pub async fn sign_in_pool(self, keypair: &Arc<Keypair>) -> Result<Self, FacilitatorLocalError> {
+        let keypair = keypair.clone();
+        verify_pool::spawn(move || self.sign(&keypair)).await
+    }
+
     pub async fn send(&self, rpc_client: &RpcClient) -> Result<Signature, FacilitatorLocalError> {
         rpc_client
             .send_transaction_with_config(
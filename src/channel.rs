@@ -0,0 +1,261 @@
+//! Off-chain payment channels of streams, see `x402-ws-stream.md`.
+//!
+//! Settling every slice of a stream on-chain costs more than slices of a few seconds are worth.
+//! A stream opened with a [`ChannelConfig`] is paid through a channel instead:
+//!
+//! - the Buyer opens the channel by paying the first `stream.require` with an `upto`
+//!   authorization of up to the channel capacity, which is verified and kept, not settled;
+//! - every slice is then paid with `stream.voucher`: a [`SignedVoucher`] of the payer of the
+//!   authorization, for the amount paid on the channel so far, slice included;
+//! - vouchers are checked off-chain: signed by the payer, their cumulative amount growing by at
+//!   least the price of a slice, and within the capacity;
+//! - the last voucher is settled with the authorization when the stream is finalized, and at
+//!   every checkpoint interval, if set. A checkpoint only settles the amount not settled yet.
+//!
+//! Vouchers are EIP-712 [`Voucher`]s in the domain `x402 Payment Channel`, version `1`, of the
+//! chain of the network, with the token as verifying contract. The channel id is the stream id.
+
+use alloy::primitives::{Address, Signature};
+use alloy::signers::Signer;
+use alloy::sol;
+use alloy::sol_types::{Eip712Domain, SolStruct, eip712_domain};
+use serde::{Deserialize, Serialize};
+
+use crate::chain::evm::EvmChain;
+use crate::duration::Seconds;
+use crate::network::Network;
+use crate::timestamp::UnixTimestampMs;
+use crate::types::{EvmAddress, EvmSignature, MixedAddress, PaymentPayload, TokenAmount};
+
+/// Name of the EIP-712 domain of vouchers.
+pub const VOUCHER_DOMAIN_NAME: &str = "x402 Payment Channel";
+/// Version of the EIP-712 domain of vouchers.
+pub const VOUCHER_DOMAIN_VERSION: &str = "1";
+
+sol!(
+    /// Solidity-compatible struct definition of a payment channel voucher.
+    ///
+    /// The payer of the channel `channelId` owes `cumulativeAmount` in total: every voucher
+    /// supersedes the previous ones.
+    #[derive(Serialize, Deserialize)]
+    struct Voucher {
+        string channelId;
+        uint256 cumulativeAmount;
+    }
+);
+
+#[derive(Debug, thiserror::Error)]
+pub enum ChannelError {
+    #[error("Payment channels are only available on EVM networks, not {0}")]
+    UnsupportedNetwork(Network),
+    #[error("Invalid channel asset {0}")]
+    InvalidAsset(String),
+    #[error("Payment channels require requirements of the upto scheme")]
+    SchemeRequired,
+    #[error("Channel capacity {capacity} is below the price of a slice {price}")]
+    CapacityBelowPrice {
+        capacity: TokenAmount,
+        price: TokenAmount,
+    },
+    #[error("Channel {0} is not open, pay the first stream.require to open it")]
+    NotOpen(String),
+    #[error("Channel {0} is open, pay its slices with stream.voucher")]
+    AlreadyOpen(String),
+    #[error("Malformed voucher signature: {0}")]
+    MalformedSignature(String),
+    #[error("Voucher signed by {actual}, not by the payer {expected}")]
+    WrongSigner { expected: Address, actual: Address },
+    #[error("Voucher of {cumulative} does not pay a slice: at least {required} required")]
+    NotIncreasing {
+        cumulative: TokenAmount,
+        required: TokenAmount,
+    },
+    #[error("Voucher of {cumulative} exceeds the channel capacity {capacity}")]
+    ExceedsCapacity {
+        cumulative: TokenAmount,
+        capacity: TokenAmount,
+    },
+    #[error("The amount of a channel is the amount of its last voucher, not consumedAmount")]
+    ConsumedAmountNotAllowed,
+    #[error("Checkpoint of channel {0} in progress")]
+    CheckpointInProgress(String),
+    #[error("Can not sign the voucher: {0}")]
+    Signing(String),
+}
+
+/// EIP-712 domain of the vouchers of channels paying `asset` on `network`.
+pub fn voucher_domain(
+    network: Network,
+    asset: &MixedAddress,
+) -> Result<Eip712Domain, ChannelError> {
+    let chain =
+        EvmChain::try_from(network).map_err(|_| ChannelError::UnsupportedNetwork(network))?;
+    let asset: EvmAddress = asset
+        .clone()
+        .try_into()
+        .map_err(|_| ChannelError::InvalidAsset(asset.to_string()))?;
+    Ok(eip712_domain! {
+        name: VOUCHER_DOMAIN_NAME,
+        version: VOUCHER_DOMAIN_VERSION,
+        chain_id: chain.chain_id,
+        verifying_contract: asset.0,
+    })
+}
+
+/// A voucher, with the signature of the payer: the params of `stream.voucher` besides the slice.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SignedVoucher {
+    /// Amount paid on the channel so far, in token base units.
+    pub cumulative_amount: TokenAmount,
+    pub signature: EvmSignature,
+}
+
+impl SignedVoucher {
+    /// Signs a voucher of `cumulative_amount` on the channel `channel_id` with `signer`, in
+    /// `domain` (see [`voucher_domain`]).
+    #[allow(dead_code)] // Public for consumption by downstream crates.
+    pub async fn sign<S: Signer + Sync>(
+        signer: &S,
+        channel_id: &str,
+        cumulative_amount: TokenAmount,
+        domain: &Eip712Domain,
+    ) -> Result<Self, ChannelError> {
+        let hash = Self::voucher(channel_id, cumulative_amount).eip712_signing_hash(domain);
+        let signature = signer
+            .sign_hash(&hash)
+            .await
+            .map_err(|e| ChannelError::Signing(format!("{e:?}")))?;
+        Ok(Self {
+            cumulative_amount,
+            signature: EvmSignature::from(signature.as_bytes()),
+        })
+    }
+
+    /// Recovers the signer of the voucher, for the channel `channel_id` in `domain`.
+    pub fn recover(
+        &self,
+        channel_id: &str,
+        domain: &Eip712Domain,
+    ) -> Result<Address, ChannelError> {
+        let signature = Signature::from_raw(&self.signature.0)
+            .map_err(|e| ChannelError::MalformedSignature(e.to_string()))?;
+        let hash = Self::voucher(channel_id, self.cumulative_amount).eip712_signing_hash(domain);
+        signature
+            .recover_address_from_prehash(&hash)
+            .map_err(|e| ChannelError::MalformedSignature(e.to_string()))
+    }
+
+    fn voucher(channel_id: &str, cumulative_amount: TokenAmount) -> Voucher {
+        Voucher {
+            channelId: channel_id.to_string(),
+            cumulativeAmount: cumulative_amount.into(),
+        }
+    }
+}
+
+/// Channel of a stream, the `channel` params of `stream.init`.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChannelConfig {
+    /// Most the channel can be paid, i.e. the amount of the authorization opening it.
+    pub capacity: TokenAmount,
+    /// Time between two settlements of the last voucher, while the stream runs. Without it, the
+    /// last voucher is only settled when the stream is finalized.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub checkpoint_seconds: Option<Seconds>,
+}
+
+/// State of the channel of a stream, as tracked by the
+/// [`StreamSessionManager`](crate::stream::StreamSessionManager).
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChannelState {
+    #[serde(flatten)]
+    pub config: ChannelConfig,
+    /// Amount of the last voucher accepted.
+    pub paid_amount: TokenAmount,
+    /// Amount settled by checkpoints.
+    pub settled_amount: TokenAmount,
+    /// Payer of the authorization opening the channel, `None` until open.
+    #[serde(skip)]
+    pub(crate) payer: Option<Address>,
+    /// Authorization opening the channel, settled by checkpoints and finalization.
+    #[serde(skip)]
+    pub(crate) authorization: Option<PaymentPayload>,
+    /// Time of the last checkpoint, or of the opening of the channel.
+    #[serde(skip)]
+    pub(crate) last_checkpoint: UnixTimestampMs,
+    /// Set while a checkpoint is being settled.
+    #[serde(skip)]
+    pub(crate) checkpointing: bool,
+}
+
+impl ChannelState {
+    pub(crate) fn new(config: ChannelConfig) -> Self {
+        Self {
+            config,
+            paid_amount: TokenAmount::from(0u64),
+            settled_amount: TokenAmount::from(0u64),
+            payer: None,
+            authorization: None,
+            last_checkpoint: UnixTimestampMs::default(),
+            checkpointing: false,
+        }
+    }
+
+    pub fn is_open(&self) -> bool {
+        self.authorization.is_some()
+    }
+
+    /// Amount paid by vouchers and not settled yet.
+    pub fn unsettled_amount(&self) -> TokenAmount {
+        self.paid_amount - self.settled_amount
+    }
+
+    /// Checks a voucher of `cumulative` signed by `signer`, paying a slice of `price`.
+    pub(crate) fn check_voucher(
+        &self,
+        channel_id: &str,
+        cumulative: TokenAmount,
+        signer: Address,
+        price: TokenAmount,
+    ) -> Result<(), ChannelError> {
+        let Some(payer) = self.payer else {
+            return Err(ChannelError::NotOpen(channel_id.to_string()));
+        };
+        if signer != payer {
+            return Err(ChannelError::WrongSigner {
+                expected: payer,
+                actual: signer,
+            });
+        }
+        let required = self
+            .paid_amount
+            .checked_add(price)
+            .unwrap_or(TokenAmount(alloy::primitives::U256::MAX));
+        if cumulative < required {
+            return Err(ChannelError::NotIncreasing {
+                cumulative,
+                required,
+            });
+        }
+        if cumulative > self.config.capacity {
+            return Err(ChannelError::ExceedsCapacity {
+                cumulative,
+                capacity: self.config.capacity,
+            });
+        }
+        Ok(())
+    }
+
+    /// Whether the unsettled amount is due for settlement at `now`.
+    pub(crate) fn is_checkpoint_due(&self, now: UnixTimestampMs) -> bool {
+        let Some(interval) = self.config.checkpoint_seconds else {
+            return false;
+        };
+        !self.checkpointing
+            && self.settled_amount < self.paid_amount
+            && self.last_checkpoint + interval.as_duration() <= now
+    }
+}
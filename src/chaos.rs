@@ -0,0 +1,131 @@
+//! Fault injection for resilience testing (`chaos` feature).
+//!
+//! A [`FaultInjector`] lets integrators exercise buyer and seller retry logic against
+//! realistic failures without touching a real chain:
+//! - drop a fraction of outgoing WebSocket frames,
+//! - delay settlements by a fixed number of seconds,
+//! - force `verify` or `settle` to fail with a specific [`FacilitatorLocalError`].
+//!
+//! The injector is shared by all connections of a facilitator and is reconfigured at runtime
+//! through the admin API (`GET`/`PUT`/`DELETE /admin/faults`).
+//!
+//! This module is only compiled with the `chaos` feature, and must never be enabled in production.
+
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+use crate::chain::FacilitatorLocalError;
+use crate::types::{ExactPaymentPayload, MixedAddress, VerifyRequest};
+
+/// Runtime configuration of a [`FaultInjector`]. Default injects no faults.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FaultConfig {
+    /// Fraction of outgoing WS frames to drop silently, from `0.0` to `1.0`.
+    #[serde(default)]
+    pub ws_drop_rate: f64,
+    /// Delay applied before every settlement, in seconds.
+    #[serde(default)]
+    pub settle_delay_seconds: u64,
+    /// Error returned by every `verify` call instead of verifying.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub verify_error: Option<InjectedError>,
+    /// Error returned by every `settle` call instead of settling.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub settle_error: Option<InjectedError>,
+}
+
+/// [`FacilitatorLocalError`] variants that can be forced by the fault injector.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum InjectedError {
+    UnsupportedNetwork,
+    InvalidSignature,
+    InvalidTiming,
+    InsufficientFunds,
+    InsufficientValue,
+    ContractCall,
+}
+
+impl InjectedError {
+    /// Build the error for `request`, attributing it to the payer where the variant requires one.
+    fn to_error(self, request: &VerifyRequest) -> FacilitatorLocalError {
+        let payer = payer_hint(request);
+        match self {
+            InjectedError::UnsupportedNetwork => FacilitatorLocalError::UnsupportedNetwork(None),
+            InjectedError::InvalidSignature => {
+                FacilitatorLocalError::InvalidSignature(payer, "Injected fault".to_string())
+            }
+            InjectedError::InvalidTiming => {
+                FacilitatorLocalError::InvalidTiming(payer, "Injected fault".to_string())
+            }
+            InjectedError::InsufficientFunds => FacilitatorLocalError::InsufficientFunds(payer),
+            InjectedError::InsufficientValue => FacilitatorLocalError::InsufficientValue(payer),
+            InjectedError::ContractCall => {
+                FacilitatorLocalError::ContractCall("Injected fault".to_string())
+            }
+        }
+    }
+}
+
+/// Best-effort payer for injected errors: the EVM authorizer, or the receiver otherwise.
+fn payer_hint(request: &VerifyRequest) -> MixedAddress {
+    match &request.payment_payload.payload {
+        ExactPaymentPayload::Evm(payload) => payload.authorization.from.into(),
+        ExactPaymentPayload::Permit(payload) => payload.permit.owner.into(),
+        ExactPaymentPayload::Solana(_) | ExactPaymentPayload::Custom(_) => {
+            request.payment_requirements.pay_to.clone()
+        }
+    }
+}
+
+/// Shared, runtime-configurable fault injector.
+#[derive(Clone, Default)]
+pub struct FaultInjector {
+    config: Arc<RwLock<FaultConfig>>,
+}
+
+impl FaultInjector {
+    pub fn config(&self) -> FaultConfig {
+        self.config.read().unwrap().clone()
+    }
+
+    pub fn set_config(&self, config: FaultConfig) {
+        tracing::warn!(?config, "Fault injection reconfigured");
+        *self.config.write().unwrap() = config;
+    }
+
+    /// Whether the next outgoing WS frame should be dropped.
+    pub fn should_drop_frame(&self) -> bool {
+        let rate = self.config.read().unwrap().ws_drop_rate;
+        let drop = rate > 0.0 && rand::thread_rng().gen_bool(rate.clamp(0.0, 1.0));
+        if drop {
+            tracing::warn!("Injected fault: dropping WS frame");
+        }
+        drop
+    }
+
+    /// Returns the forced verification error, if configured.
+    pub fn verify_fault(&self, request: &VerifyRequest) -> Option<FacilitatorLocalError> {
+        let error = self.config.read().unwrap().verify_error?;
+        tracing::warn!(?error, "Injected fault: failing verify");
+        Some(error.to_error(request))
+    }
+
+    /// Applies the configured settle delay, then returns the forced settlement error, if configured.
+    pub async fn settle_fault(&self, request: &VerifyRequest) -> Option<FacilitatorLocalError> {
+        let (delay, error) = {
+            let config = self.config.read().unwrap();
+            (config.settle_delay_seconds, config.settle_error)
+        };
+        if delay > 0 {
+            tracing::warn!(delay_seconds = delay, "Injected fault: delaying settle");
+            tokio::time::sleep(Duration::from_secs(delay)).await;
+        }
+        let error = error?;
+        tracing::warn!(?error, "Injected fault: failing settle");
+        Some(error.to_error(request))
+    }
+}
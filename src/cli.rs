@@ -0,0 +1,310 @@
+//! Command line of the facilitator binary.
+//!
+//! ```text
+//! x402-facilitator [serve]
+//! x402-facilitator verify <file>
+//! x402-facilitator settle <file>
+//! x402-facilitator supported
+//! x402-facilitator keys list
+//! x402-facilitator keys generate
+//! x402-facilitator config check
+//! ```
+//!
+//! `serve`, the default, runs the facilitator server. The other commands exercise the
+//! facilitator configured in the environment once, without serving:
+//! - `verify` and `settle` read a [`VerifyRequest`] (or [`SettleRequest`]) as JSON from a file,
+//!   or from stdin with `-`, and print the response. Settlements are sent right away, whatever
+//!   `SETTLE_MODE` and `MULTICALL_WINDOW_MS`.
+//! - `supported` prints the payment kinds supported on the networks configured.
+//! - `keys list` prints the settlement accounts of the configured signer, and `keys generate`
+//!   prints a new EVM private key and Solana keypair as env lines.
+//! - `config check` reads the configuration of the server from the environment and the
+//!   configuration file, and reports every invalid setting. Stores and RPC endpoints are not
+//!   connected to.
+//!
+//! Results are printed to stdout, logs to stderr. Commands exit with `0` on success, `1` if the
+//! payment is invalid, the settlement failed, or the configuration is invalid, and `2` on a
+//! usage error.
+
+use alloy::network::{Ethereum, NetworkWallet};
+use alloy::signers::local::PrivateKeySigner;
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use solana_sdk::signature::Keypair;
+use solana_sdk::signer::Signer;
+use std::env;
+use std::fmt::Display;
+use std::io::Read;
+use std::path::PathBuf;
+use tracing_subscriber::EnvFilter;
+use tracing_subscriber::filter::LevelFilter;
+
+use crate::chain::gas::GasPolicies;
+use crate::chain::multicall::SettlementBatching;
+use crate::chain::rpc_failover::RpcFailover;
+use crate::config_file::ConfigFile;
+use crate::disabled_networks::DisabledNetworks;
+use crate::facilitator::Facilitator;
+use crate::facilitator_cache::VerifyCacheTtls;
+use crate::facilitator_local::FacilitatorLocal;
+use crate::handlers::{
+    FacilitatorRoutes, RateLimits, Shutdown, WsAuth, WsCompression, WsErrorData, WsLiveness,
+};
+use crate::provider_cache::SignerType;
+use crate::settle_idempotency::SettleIdempotency;
+use crate::settlement_caps::SettlementCaps;
+use crate::settlement_events::SettlementEvents;
+use crate::strict_settle::StrictSettle;
+use crate::types::{SettleRequest, VerifyRequest, VerifyResponse};
+#[cfg(feature = "webhooks")]
+use crate::webhooks::Webhooks;
+
+const ENV_SOLANA_PRIVATE_KEY: &str = "SOLANA_PRIVATE_KEY";
+
+pub const USAGE: &str = "Usage: x402-facilitator [command]
+
+Commands:
+  serve           Runs the facilitator server (default)
+  verify <file>   Verifies the payment of a verify request, read as JSON from <file> or stdin (-)
+  settle <file>   Settles the payment of a settle request, read as JSON from <file> or stdin (-)
+  supported       Lists the payment kinds supported on the networks configured
+  keys list       Lists the settlement accounts of the signer configured
+  keys generate   Generates an EVM private key and a Solana keypair
+  config check    Checks the configuration read from the environment, .env and CONFIG_FILE
+  help            Prints this message";
+
+/// Command of the facilitator binary.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Command {
+    Serve,
+    Verify(Input),
+    Settle(Input),
+    Supported,
+    KeysList,
+    KeysGenerate,
+    ConfigCheck,
+    Help,
+}
+
+/// Where a request is read from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Input {
+    Stdin,
+    File(PathBuf),
+}
+
+impl Command {
+    /// Parses the arguments of the binary, without the name of the binary.
+    pub fn parse(mut args: impl Iterator<Item = String>) -> Result<Self, String> {
+        let command = match args.next().as_deref() {
+            None | Some("serve") => Command::Serve,
+            Some("verify") => Command::Verify(Input::parse(args.next(), "verify")?),
+            Some("settle") => Command::Settle(Input::parse(args.next(), "settle")?),
+            Some("supported") => Command::Supported,
+            Some("keys") => match args.next().as_deref() {
+                Some("list") => Command::KeysList,
+                Some("generate") => Command::KeysGenerate,
+                Some(other) => return Err(format!("Unknown keys command: {other}")),
+                None => return Err("keys requires a command: list or generate".to_string()),
+            },
+            Some("config") => match args.next().as_deref() {
+                Some("check") => Command::ConfigCheck,
+                Some(other) => return Err(format!("Unknown config command: {other}")),
+                None => return Err("config requires a command: check".to_string()),
+            },
+            Some("help" | "--help" | "-h") => Command::Help,
+            Some(other) => return Err(format!("Unknown command: {other}")),
+        };
+        match args.next() {
+            Some(extra) => Err(format!("Unexpected argument: {extra}")),
+            None => Ok(command),
+        }
+    }
+}
+
+impl Input {
+    fn parse(arg: Option<String>, command: &str) -> Result<Self, String> {
+        match arg.as_deref() {
+            None => Err(format!("{command} requires a file, or - for stdin")),
+            Some("-") => Ok(Input::Stdin),
+            Some(path) => Ok(Input::File(path.into())),
+        }
+    }
+
+    /// Reads a JSON value.
+    fn read<T: DeserializeOwned>(&self) -> Result<T, String> {
+        let json = match self {
+            Input::Stdin => {
+                let mut json = String::new();
+                std::io::stdin()
+                    .read_to_string(&mut json)
+                    .map_err(|e| format!("Can not read stdin: {e}"))?;
+                json
+            }
+            Input::File(path) => std::fs::read_to_string(path)
+                .map_err(|e| format!("Can not read {}: {e}", path.display()))?,
+        };
+        serde_json::from_str(&json).map_err(|e| format!("Invalid request: {e}"))
+    }
+}
+
+/// Runs a command other than [`Command::Serve`], and returns the exit code of the binary.
+pub async fn run(command: Command) -> i32 {
+    tracing_subscriber::fmt()
+        .with_env_filter(
+            EnvFilter::builder()
+                .with_default_directive(LevelFilter::WARN.into())
+                .from_env_lossy(),
+        )
+        .with_writer(std::io::stderr)
+        .init();
+    let result = match command {
+        Command::Serve => unreachable!("The server is run by main"),
+        Command::Verify(input) => verify(&input).await,
+        Command::Settle(input) => settle(&input).await,
+        Command::Supported => supported().await,
+        Command::KeysList => keys_list().await,
+        Command::KeysGenerate => keys_generate(),
+        Command::ConfigCheck => config_check().await,
+        Command::Help => {
+            println!("{USAGE}");
+            Ok(true)
+        }
+    };
+    match result {
+        Ok(true) => 0,
+        Ok(false) => 1,
+        Err(e) => {
+            eprintln!("{e}");
+            1
+        }
+    }
+}
+
+/// Prints `value` as pretty JSON.
+fn print_json<T: Serialize>(value: &T) -> Result<(), String> {
+    let json = serde_json::to_string_pretty(value).map_err(|e| e.to_string())?;
+    println!("{json}");
+    Ok(())
+}
+
+/// Builds the facilitator configured in the environment.
+async fn local_facilitator() -> Result<FacilitatorLocal, String> {
+    let disabled_networks = DisabledNetworks::from_env().map_err(|e| e.to_string())?;
+    Ok(crate::local_facilitator(disabled_networks).await)
+}
+
+/// Verifies the request of `input`. Returns whether the payment is valid.
+async fn verify(input: &Input) -> Result<bool, String> {
+    let request: VerifyRequest = input.read()?;
+    let facilitator = local_facilitator().await?;
+    let response = facilitator
+        .verify(&request)
+        .await
+        .map_err(|e| format!("Verification failed: {e}"))?;
+    print_json(&response)?;
+    Ok(matches!(response, VerifyResponse::Valid { .. }))
+}
+
+/// Settles the request of `input`. Returns whether the settlement succeeded.
+async fn settle(input: &Input) -> Result<bool, String> {
+    let request: SettleRequest = input.read()?;
+    let facilitator = local_facilitator().await?;
+    let response = facilitator
+        .settle(&request)
+        .await
+        .map_err(|e| format!("Settlement failed: {e}"))?;
+    print_json(&response)?;
+    Ok(response.success)
+}
+
+async fn supported() -> Result<bool, String> {
+    let facilitator = local_facilitator().await?;
+    let supported = facilitator
+        .supported()
+        .await
+        .map_err(|e| format!("Can not list the supported payment kinds: {e}"))?;
+    print_json(&supported)?;
+    Ok(true)
+}
+
+/// Prints the settlement accounts of the configured signer, the primary EVM account first.
+async fn keys_list() -> Result<bool, String> {
+    let signer_type = SignerType::from_env().map_err(|e| e.to_string())?;
+    let wallet = signer_type
+        .make_evm_wallet()
+        .await
+        .map_err(|e| format!("Can not read the EVM settlement accounts: {e}"))?;
+    let primary = NetworkWallet::<Ethereum>::default_signer_address(&wallet);
+    println!("evm     {primary} (primary)");
+    for address in NetworkWallet::<Ethereum>::signer_addresses(&wallet) {
+        if address != primary {
+            println!("evm     {address}");
+        }
+    }
+    if env::var(ENV_SOLANA_PRIVATE_KEY).is_ok() {
+        let keypair = signer_type
+            .make_solana_wallet()
+            .map_err(|e| format!("Can not read the Solana settlement account: {e}"))?;
+        println!("solana  {}", keypair.pubkey());
+    }
+    Ok(true)
+}
+
+/// Prints a new EVM private key and Solana keypair, as env lines.
+fn keys_generate() -> Result<bool, String> {
+    let evm = PrivateKeySigner::random();
+    let solana = Keypair::new();
+    println!("SIGNER_TYPE=private-key");
+    println!("# EVM address: {}", evm.address());
+    println!("EVM_PRIVATE_KEY=0x{}", alloy::hex::encode(evm.to_bytes()));
+    println!("# Solana address: {}", solana.pubkey());
+    println!("{ENV_SOLANA_PRIVATE_KEY}={}", solana.to_base58_string());
+    Ok(true)
+}
+
+/// Reads the configuration of the server, printing a line per setting. Returns whether it is
+/// valid.
+async fn config_check() -> Result<bool, String> {
+    fn check<T, E: Display>(name: &str, result: Result<T, E>) -> bool {
+        match result {
+            Ok(_) => {
+                println!("ok     {name}");
+                true
+            }
+            Err(e) => {
+                println!("error  {name}: {e}");
+                false
+            }
+        }
+    }
+    let signer = match SignerType::from_env() {
+        Ok(signer_type) => signer_type.make_evm_wallet().await.map(|_| ()),
+        Err(e) => Err(e),
+    };
+    let checks = [
+        check("configuration file", ConfigFile::from_env()),
+        check("routes", FacilitatorRoutes::from_env()),
+        check("WebSocket authentication", WsAuth::from_env()),
+        check("WebSocket error data", WsErrorData::from_env()),
+        check("WebSocket liveness", WsLiveness::from_env()),
+        check("WebSocket compression", WsCompression::from_env()),
+        check("rate limits", RateLimits::from_env()),
+        check("shutdown timeout", Shutdown::from_env()),
+        check("settlement signer", signer),
+        check("disabled networks", DisabledNetworks::from_env()),
+        check("RPC failover", RpcFailover::from_env()),
+        check("gas policies", GasPolicies::from_env()),
+        check("settlement caps", SettlementCaps::from_env()),
+        check("strict settlement window", StrictSettle::from_env()),
+        check("settlement idempotency", SettleIdempotency::from_env()),
+        check("settlement finality", SettlementEvents::from_env()),
+        check("verification cache", VerifyCacheTtls::from_env()),
+        #[cfg(feature = "webhooks")]
+        check("webhooks", Webhooks::from_env()),
+    ];
+    if let Some(batching) = SettlementBatching::from_env() {
+        println!("ok     multicall batching: {batching:?}");
+    }
+    Ok(checks.into_iter().all(|ok| ok))
+}
@@ -0,0 +1,459 @@
+//! Configuration file of the facilitator, layered under the environment.
+//!
+//! The facilitator is configured with environment variables. With `CONFIG_FILE` set to the path
+//! of a TOML file, the settings of the file are read as well, as the variables they stand for:
+//! a variable set in the environment or in `.env` overrides the file, so that a file shared by
+//! several deployments can be tuned per deployment.
+//!
+//! ```toml
+//! [server]
+//! port = 8080
+//! disabled_endpoints = ["discovery"]
+//!
+//! [signer]
+//! type = "private-key"
+//! evm_private_key = ["0x...", "0x..."]
+//!
+//! [networks.base]
+//! rpc_url = "https://mainnet.base.org"
+//! [networks.base.gas]
+//! max_fee_per_gas = 5000000000
+//!
+//! [networks.base-sepolia]
+//! rpc_url = "https://sepolia.base.org"
+//! enabled = false
+//!
+//! [settlement_caps]
+//! cap = 100
+//!
+//! [rate_limits]
+//! key = "forwarded"
+//! per_ip = { verify = 100, settle = 5 }
+//!
+//! [telemetry]
+//! log = "info,x402_rs::handlers::ws=debug"
+//!
+//! [env]
+//! SETTLE_REQUIRE_VERIFIED_SECS = "5m"
+//! ```
+//!
+//! | Setting                                       | Variable                                       |
+//! |-----------------------------------------------|------------------------------------------------|
+//! | `server.host`, `server.port`                  | `HOST`, `PORT`                                 |
+//! | `server.route_prefix`, `server.ws_path`       | `ROUTE_PREFIX`, `WS_PATH`                      |
+//! | `server.disabled_endpoints`                   | `DISABLED_ENDPOINTS`                           |
+//! | `server.admin_token`                          | `ADMIN_TOKEN`                                  |
+//! | `server.admin_host`, `server.admin_port`      | `ADMIN_HOST`, `ADMIN_PORT`                     |
+//! | `signer.type`                                 | `SIGNER_TYPE`                                  |
+//! | `signer.evm_private_key`                      | `EVM_PRIVATE_KEY`                              |
+//! | `signer.evm_mnemonic`, `signer.evm_signer_count` | `EVM_MNEMONIC`, `EVM_SIGNER_COUNT`          |
+//! | `signer.solana_private_key`                   | `SOLANA_PRIVATE_KEY`                           |
+//! | `signer.aws_kms_key_id`, `signer.gcp_kms_key` | `AWS_KMS_KEY_ID`, `GCP_KMS_KEY`                |
+//! | `signer.remote_signer_url`                    | `REMOTE_SIGNER_URL`                            |
+//! | `networks.<network>.rpc_url`                  | `RPC_URL_<NETWORK>`                            |
+//! | `networks.<network>.ws_url`                   | `RPC_WS_URL_POLYGON_AMOY`, on `polygon-amoy`   |
+//! | `networks.<network>.enabled`                  | `DISABLED_NETWORKS` if `false`, `TEST_NETWORK` on `test` |
+//! | `networks.<network>.verify_cache_ttl_ms`      | `VERIFY_CACHE_TTL_MS_<NETWORK>`                |
+//! | `gas.<setting>`                               | `GAS_<SETTING>`                                |
+//! | `networks.<network>.gas.<setting>`            | `GAS_<SETTING>_<NETWORK>`                      |
+//! | `settlement_caps.cap`                         | `SETTLEMENT_CAP`                               |
+//! | `settlement_caps.payer_daily`, `settlement_caps.daily` | `SETTLEMENT_CAP_PAYER_DAILY`, `SETTLEMENT_CAP_DAILY` |
+//! | `rate_limits.key`                             | `RATE_LIMIT_KEY`                               |
+//! | `rate_limits.per_ip`, `rate_limits.per_key`   | `RATE_LIMITS`, `RATE_LIMITS_PER_KEY`           |
+//! | `telemetry.log`                               | `RUST_LOG`                                     |
+//! | `telemetry.sampling_ratio`                    | `OTEL_TRACES_SAMPLER_ARG`                      |
+//! | `telemetry.otlp_endpoint`, `telemetry.otlp_headers`, `telemetry.otlp_protocol` | `OTEL_EXPORTER_OTLP_*` |
+//! | `telemetry.service_name`, `telemetry.deployment` | `OTEL_SERVICE_NAME`, `OTEL_SERVICE_DEPLOYMENT` |
+//! | `env.<VARIABLE>`                              | `<VARIABLE>`                                   |
+//!
+//! Arrays are joined with commas, e.g. the RPC endpoints of a network failing over to each
+//! other, and tables of rate limits as `<endpoint>=<requests per second>` overrides; `"off"`
+//! lifts them all. Amounts over 2⁶³ are written as strings. Unknown settings, and a variable set
+//! twice, are rejected.
+//!
+//! Signer keys are read once, at startup: a file holding keys should be readable by the
+//! facilitator only. The server reloads the file on `SIGHUP`, applying the settings it can
+//! change at runtime: the rate limits, the log filter, the trace sampling ratio and the networks
+//! disabled. Other settings are kept until the next restart. Settings overridden by the
+//! environment are not reloaded.
+
+use serde::Deserialize;
+use std::collections::{BTreeMap, HashSet};
+use std::env;
+use std::path::{Path, PathBuf};
+use toml::Value;
+
+use crate::network::{Network, UnknownNetwork};
+use crate::provider_cache::{rpc_env_var, rpc_ws_env_var};
+
+const ENV_CONFIG_FILE: &str = "CONFIG_FILE";
+
+#[derive(Debug, thiserror::Error)]
+pub enum ConfigFileError {
+    #[error("Can not read the configuration file {}: {}", .0.display(), .1)]
+    Read(PathBuf, std::io::Error),
+    #[error("Invalid configuration file: {0}")]
+    Parse(#[from] toml::de::Error),
+    #[error("Invalid setting {0}: {1}")]
+    InvalidSetting(String, String),
+    #[error(transparent)]
+    UnknownNetwork(#[from] UnknownNetwork),
+}
+
+/// Configuration file of the facilitator, see the [module documentation](self).
+#[derive(Debug, Clone)]
+pub struct ConfigFile {
+    path: PathBuf,
+    /// Variables of the settings of the file.
+    vars: BTreeMap<String, String>,
+    /// Variables set in the environment from the file, by [`ConfigFile::apply`].
+    applied: HashSet<String>,
+}
+
+impl ConfigFile {
+    /// Reads the file at `CONFIG_FILE`, if set.
+    pub fn from_env() -> Result<Option<Self>, ConfigFileError> {
+        match env::var(ENV_CONFIG_FILE) {
+            Ok(path) if !path.is_empty() => Self::load(path).map(Some),
+            _ => Ok(None),
+        }
+    }
+
+    /// Reads the file at `path`.
+    pub fn load(path: impl Into<PathBuf>) -> Result<Self, ConfigFileError> {
+        let path = path.into();
+        let vars = read(&path)?;
+        Ok(Self {
+            path,
+            vars,
+            applied: HashSet::new(),
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Variables of the settings of the file, by name.
+    #[allow(dead_code)] // Public for consumption by downstream crates.
+    pub fn vars(&self) -> &BTreeMap<String, String> {
+        &self.vars
+    }
+
+    /// Sets the variables of the file not set in the environment.
+    ///
+    /// # Safety
+    ///
+    /// No other thread may read or write the environment meanwhile, see [`env::set_var`].
+    pub unsafe fn apply(&mut self) {
+        for (name, value) in &self.vars {
+            if env::var_os(name).is_none() {
+                // SAFETY: guaranteed by the caller
+                unsafe { env::set_var(name, value) };
+                self.applied.insert(name.clone());
+            }
+        }
+    }
+
+    /// Reads the file again. The environment is left as is: the settings read are seen through
+    /// [`ConfigFile::var`].
+    pub fn reload(&mut self) -> Result<(), ConfigFileError> {
+        self.vars = read(&self.path)?;
+        Ok(())
+    }
+
+    /// Value of the variable `name`: the value of the environment if set there, and not from the
+    /// file, or else the value of the file as last read.
+    pub fn var(&self, name: &str) -> Option<String> {
+        match env::var(name) {
+            Ok(value) if !self.applied.contains(name) => Some(value),
+            _ => self.vars.get(name).cloned(),
+        }
+    }
+}
+
+/// Reads the variables of the settings of the file at `path`.
+fn read(path: &Path) -> Result<BTreeMap<String, String>, ConfigFileError> {
+    let toml =
+        std::fs::read_to_string(path).map_err(|e| ConfigFileError::Read(path.to_owned(), e))?;
+    parse(&toml)
+}
+
+/// Parses the settings of a configuration file into the variables they stand for.
+///
+/// ```rust
+/// use x402_rs::config_file;
+///
+/// let vars = config_file::parse(
+///     r#"
+///     [networks.base-sepolia]
+///     rpc_url = "https://sepolia.base.org"
+///
+///     [rate_limits]
+///     per_ip = { settle = 5 }
+///     "#,
+/// )
+/// .unwrap();
+/// assert_eq!(vars["RPC_URL_BASE_SEPOLIA"], "https://sepolia.base.org");
+/// assert_eq!(vars["RATE_LIMITS"], "settle=5");
+/// ```
+pub fn parse(toml: &str) -> Result<BTreeMap<String, String>, ConfigFileError> {
+    let settings: Settings = toml::from_str(toml)?;
+    settings.vars()
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct Settings {
+    server: ServerSettings,
+    signer: SignerSettings,
+    networks: BTreeMap<String, NetworkSettings>,
+    gas: GasSettings,
+    settlement_caps: SettlementCapsSettings,
+    rate_limits: RateLimitsSettings,
+    telemetry: TelemetrySettings,
+    env: BTreeMap<String, Value>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct ServerSettings {
+    host: Option<Value>,
+    port: Option<Value>,
+    route_prefix: Option<Value>,
+    ws_path: Option<Value>,
+    disabled_endpoints: Option<Value>,
+    admin_token: Option<Value>,
+    admin_host: Option<Value>,
+    admin_port: Option<Value>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct SignerSettings {
+    #[serde(rename = "type")]
+    signer_type: Option<Value>,
+    evm_private_key: Option<Value>,
+    evm_mnemonic: Option<Value>,
+    evm_signer_count: Option<Value>,
+    solana_private_key: Option<Value>,
+    aws_kms_key_id: Option<Value>,
+    gcp_kms_key: Option<Value>,
+    remote_signer_url: Option<Value>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct NetworkSettings {
+    rpc_url: Option<Value>,
+    ws_url: Option<Value>,
+    enabled: Option<bool>,
+    verify_cache_ttl_ms: Option<Value>,
+    gas: GasSettings,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct GasSettings {
+    balance_floor: Option<Value>,
+    max_fee_per_gas: Option<Value>,
+    priority_fee_per_gas: Option<Value>,
+    escalate_after_secs: Option<Value>,
+    escalation_percent: Option<Value>,
+    max_escalations: Option<Value>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct SettlementCapsSettings {
+    cap: Option<Value>,
+    payer_daily: Option<Value>,
+    daily: Option<Value>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct RateLimitsSettings {
+    key: Option<Value>,
+    per_ip: Option<Value>,
+    per_key: Option<Value>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct TelemetrySettings {
+    log: Option<Value>,
+    sampling_ratio: Option<Value>,
+    otlp_endpoint: Option<Value>,
+    otlp_headers: Option<Value>,
+    otlp_protocol: Option<Value>,
+    service_name: Option<Value>,
+    deployment: Option<Value>,
+}
+
+impl Settings {
+    fn vars(&self) -> Result<BTreeMap<String, String>, ConfigFileError> {
+        let mut vars = Vars::default();
+        let server = &self.server;
+        vars.set("HOST", &server.host)?;
+        vars.set("PORT", &server.port)?;
+        vars.set("ROUTE_PREFIX", &server.route_prefix)?;
+        vars.set("WS_PATH", &server.ws_path)?;
+        vars.set("DISABLED_ENDPOINTS", &server.disabled_endpoints)?;
+        vars.set("ADMIN_TOKEN", &server.admin_token)?;
+        vars.set("ADMIN_HOST", &server.admin_host)?;
+        vars.set("ADMIN_PORT", &server.admin_port)?;
+
+        let signer = &self.signer;
+        vars.set("SIGNER_TYPE", &signer.signer_type)?;
+        vars.set("EVM_PRIVATE_KEY", &signer.evm_private_key)?;
+        vars.set("EVM_MNEMONIC", &signer.evm_mnemonic)?;
+        vars.set("EVM_SIGNER_COUNT", &signer.evm_signer_count)?;
+        vars.set("SOLANA_PRIVATE_KEY", &signer.solana_private_key)?;
+        vars.set("AWS_KMS_KEY_ID", &signer.aws_kms_key_id)?;
+        vars.set("GCP_KMS_KEY", &signer.gcp_kms_key)?;
+        vars.set("REMOTE_SIGNER_URL", &signer.remote_signer_url)?;
+
+        vars.set_gas("", &self.gas)?;
+        let mut disabled = Vec::new();
+        for (name, settings) in &self.networks {
+            let network: Network = name.parse()?;
+            let setting = |setting: &str| format!("networks.{name}.{setting}");
+            match (rpc_env_var(network), &settings.rpc_url) {
+                (Some(env_var), rpc_url) => vars.set(env_var, rpc_url)?,
+                (None, Some(_)) => {
+                    return Err(ConfigFileError::InvalidSetting(
+                        setting("rpc_url"),
+                        format!("{network} has no RPC endpoint"),
+                    ));
+                }
+                (None, None) => {}
+            }
+            match (rpc_ws_env_var(network), &settings.ws_url) {
+                (Some(env_var), ws_url) => vars.set(env_var, ws_url)?,
+                (None, Some(_)) => {
+                    return Err(ConfigFileError::InvalidSetting(
+                        setting("ws_url"),
+                        format!("{network} has no WebSocket RPC endpoint"),
+                    ));
+                }
+                (None, None) => {}
+            }
+            match settings.enabled {
+                Some(false) => disabled.push(network.to_string()),
+                Some(true) if network == Network::Test => {
+                    vars.set("TEST_NETWORK", &Some(Value::Boolean(true)))?
+                }
+                _ => {}
+            }
+            let suffix = format!("_{}", network.to_string().to_uppercase().replace('-', "_"));
+            vars.set(
+                &format!("VERIFY_CACHE_TTL_MS{suffix}"),
+                &settings.verify_cache_ttl_ms,
+            )?;
+            vars.set_gas(&suffix, &settings.gas)?;
+        }
+        if !disabled.is_empty() {
+            vars.set(
+                "DISABLED_NETWORKS",
+                &Some(Value::String(disabled.join(","))),
+            )?;
+        }
+
+        let caps = &self.settlement_caps;
+        vars.set("SETTLEMENT_CAP", &caps.cap)?;
+        vars.set("SETTLEMENT_CAP_PAYER_DAILY", &caps.payer_daily)?;
+        vars.set("SETTLEMENT_CAP_DAILY", &caps.daily)?;
+
+        let rate_limits = &self.rate_limits;
+        vars.set("RATE_LIMIT_KEY", &rate_limits.key)?;
+        vars.set("RATE_LIMITS", &rate_limits.per_ip)?;
+        vars.set("RATE_LIMITS_PER_KEY", &rate_limits.per_key)?;
+
+        let telemetry = &self.telemetry;
+        vars.set("RUST_LOG", &telemetry.log)?;
+        vars.set("OTEL_TRACES_SAMPLER_ARG", &telemetry.sampling_ratio)?;
+        vars.set("OTEL_EXPORTER_OTLP_ENDPOINT", &telemetry.otlp_endpoint)?;
+        vars.set("OTEL_EXPORTER_OTLP_HEADERS", &telemetry.otlp_headers)?;
+        vars.set("OTEL_EXPORTER_OTLP_PROTOCOL", &telemetry.otlp_protocol)?;
+        vars.set("OTEL_SERVICE_NAME", &telemetry.service_name)?;
+        vars.set("OTEL_SERVICE_DEPLOYMENT", &telemetry.deployment)?;
+
+        for (name, value) in &self.env {
+            vars.set(name, &Some(value.clone()))?;
+        }
+        Ok(vars.0)
+    }
+}
+
+/// Variables of the settings, by name.
+#[derive(Default)]
+struct Vars(BTreeMap<String, String>);
+
+impl Vars {
+    /// Sets the variable `name` to `value`, if any.
+    fn set(&mut self, name: &str, value: &Option<Value>) -> Result<(), ConfigFileError> {
+        let Some(value) = value else {
+            return Ok(());
+        };
+        let invalid =
+            |reason: &str| ConfigFileError::InvalidSetting(name.to_string(), reason.to_string());
+        if self.0.contains_key(name) {
+            return Err(invalid("set twice"));
+        }
+        let value = match value {
+            Value::Array(values) => values
+                .iter()
+                .map(|value| scalar(value).ok_or_else(|| invalid("arrays hold strings or numbers")))
+                .collect::<Result<Vec<_>, _>>()?
+                .join(","),
+            Value::Table(overrides) => overrides
+                .iter()
+                .map(|(key, value)| match scalar(value) {
+                    Some(value) => Ok(format!("{key}={value}")),
+                    None => Err(invalid("tables hold strings or numbers")),
+                })
+                .collect::<Result<Vec<_>, _>>()?
+                .join(","),
+            value => scalar(value).ok_or_else(|| invalid("unsupported value"))?,
+        };
+        self.0.insert(name.to_string(), value);
+        Ok(())
+    }
+
+    /// Sets the `GAS_*` variables of `gas`, ending with `suffix`.
+    fn set_gas(&mut self, suffix: &str, gas: &GasSettings) -> Result<(), ConfigFileError> {
+        self.set(&format!("GAS_BALANCE_FLOOR{suffix}"), &gas.balance_floor)?;
+        self.set(
+            &format!("GAS_MAX_FEE_PER_GAS{suffix}"),
+            &gas.max_fee_per_gas,
+        )?;
+        self.set(
+            &format!("GAS_PRIORITY_FEE_PER_GAS{suffix}"),
+            &gas.priority_fee_per_gas,
+        )?;
+        self.set(
+            &format!("GAS_ESCALATE_AFTER_SECS{suffix}"),
+            &gas.escalate_after_secs,
+        )?;
+        self.set(
+            &format!("GAS_ESCALATION_PERCENT{suffix}"),
+            &gas.escalation_percent,
+        )?;
+        self.set(
+            &format!("GAS_MAX_ESCALATIONS{suffix}"),
+            &gas.max_escalations,
+        )
+    }
+}
+
+/// Value of a variable of a string, number or boolean setting.
+fn scalar(value: &Value) -> Option<String> {
+    match value {
+        Value::String(value) => Some(value.clone()),
+        Value::Integer(value) => Some(value.to_string()),
+        Value::Float(value) => Some(value.to_string()),
+        Value::Boolean(value) => Some(value.to_string()),
+        Value::Datetime(_) | Value::Array(_) | Value::Table(_) => None,
+    }
+}
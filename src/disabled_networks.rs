@@ -0,0 +1,89 @@
+//! Networks turned off at runtime, without restarting the facilitator.
+//!
+//! A network configured with an RPC endpoint is served from startup. An operator may stop serving
+//! it, e.g. while its RPC provider is degraded, without dropping the connections to the other
+//! networks: payments on a network of [`DisabledNetworks`] are refused with
+//! [`FacilitatorLocalError::UnsupportedNetwork`], and its payment kinds are no longer listed by
+//! `/supported`. Settlements already in flight complete.
+//!
+//! Clones share the same networks, so that the set can be changed at runtime, e.g. when the
+//! configuration file is reloaded (see [`config_file`](crate::config_file)). A network enabled
+//! again is served again; a network without a provider at startup needs a restart.
+//!
+//! Environment (see [`DisabledNetworks::from_env`]):
+//! - `DISABLED_NETWORKS` – Comma-separated networks not to serve, e.g. `base-sepolia,sei-testnet`
+
+use std::collections::HashSet;
+use std::env;
+use std::sync::{Arc, RwLock};
+
+use crate::chain::FacilitatorLocalError;
+use crate::network::{Network, UnknownNetwork};
+
+const ENV_DISABLED_NETWORKS: &str = "DISABLED_NETWORKS";
+
+/// Networks not served, see the [module documentation](self). All networks are served by default.
+#[derive(Debug, Clone, Default)]
+pub struct DisabledNetworks(Arc<RwLock<HashSet<Network>>>);
+
+impl DisabledNetworks {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Reads the networks from `DISABLED_NETWORKS`. All networks are served if unset.
+    pub fn from_env() -> Result<Self, UnknownNetwork> {
+        Self::from_vars(|name| env::var(name).ok())
+    }
+
+    /// Reads the networks from the `DISABLED_NETWORKS` value of `var`.
+    pub(crate) fn from_vars(var: impl Fn(&str) -> Option<String>) -> Result<Self, UnknownNetwork> {
+        let networks = match var(ENV_DISABLED_NETWORKS) {
+            Some(networks) => parse(&networks)?,
+            None => HashSet::new(),
+        };
+        Ok(Self(Arc::new(RwLock::new(networks))))
+    }
+
+    /// Networks not served.
+    pub fn networks(&self) -> HashSet<Network> {
+        self.0.read().unwrap().clone()
+    }
+
+    /// Replaces the networks not served, for every clone.
+    pub fn set(&self, networks: HashSet<Network>) {
+        let mut disabled = self.0.write().unwrap();
+        if *disabled != networks {
+            tracing::info!(?networks, "Disabled networks changed");
+            *disabled = networks;
+        }
+    }
+
+    /// Whether `network` is served.
+    pub fn is_enabled(&self, network: Network) -> bool {
+        !self
+            .0
+            .read()
+            .expect("Disabled networks lock poisoned")
+            .contains(&network)
+    }
+
+    /// Refuses payments on `network` if it is not served.
+    pub fn check(&self, network: Network) -> Result<(), FacilitatorLocalError> {
+        if self.is_enabled(network) {
+            Ok(())
+        } else {
+            Err(FacilitatorLocalError::UnsupportedNetwork(None))
+        }
+    }
+}
+
+/// Parses comma-separated network names, e.g. `base-sepolia,sei-testnet`.
+pub fn parse(networks: &str) -> Result<HashSet<Network>, UnknownNetwork> {
+    networks
+        .split(',')
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .map(str::parse)
+        .collect()
+}
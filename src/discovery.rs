@@ -0,0 +1,222 @@
+//! Discovery of payable resources (the x402 "bazaar").
+//!
+//! Resource servers register their priced endpoints with the facilitator, together with the
+//! [`PaymentRequirements`] they accept, and clients list them to find services to pay for:
+//! - `POST /discovery/resources` registers (or refreshes) a [`DiscoveryRegistration`],
+//! - `GET /discovery/resources` lists the registered resources matching a [`DiscoveryQuery`],
+//!   also served as `x402.discovery.list` over WebSocket.
+//!
+//! A resource matches a query if one of its payment requirements is on the queried network,
+//! in the queried asset, and priced (by `maxAmountRequired`) within the queried range. Resources
+//! are listed most recently updated first. Registrations are kept in memory, the least recently
+//! updated being dropped beyond [`DEFAULT_CAPACITY`] resources.
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+use url::Url;
+
+use crate::network::Network;
+use crate::timestamp::UnixTimestamp;
+use crate::types::{MixedAddress, PaymentRequirements, TokenAmount, X402Version};
+
+/// Resources kept by default.
+pub const DEFAULT_CAPACITY: usize = 10_000;
+/// Resources listed per page by default.
+const DEFAULT_LIMIT: usize = 100;
+/// Maximum number of resources listed per page.
+const MAX_LIMIT: usize = 1000;
+
+#[derive(Debug, thiserror::Error)]
+pub enum DiscoveryError {
+    #[error("A resource must accept at least one payment")]
+    NoPaymentRequirements,
+    #[error("Can not get system clock")]
+    ClockError(#[source] std::time::SystemTimeError),
+}
+
+/// Kind of a payable resource.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ResourceType {
+    #[default]
+    Http,
+}
+
+/// A resource registered by its server, as listed to clients.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DiscoveryResource {
+    pub resource: Url,
+    pub r#type: ResourceType,
+    pub x402_version: X402Version,
+    /// Payments the resource accepts.
+    pub accepts: Vec<PaymentRequirements>,
+    pub last_updated: UnixTimestamp,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub metadata: Option<serde_json::Value>,
+}
+
+/// Body of `POST /discovery/resources`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DiscoveryRegistration {
+    pub resource: Url,
+    #[serde(default)]
+    pub r#type: ResourceType,
+    pub accepts: Vec<PaymentRequirements>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub metadata: Option<serde_json::Value>,
+}
+
+/// Filters and page of a listing. Every filter is optional.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DiscoveryQuery {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub r#type: Option<ResourceType>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub network: Option<Network>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub asset: Option<MixedAddress>,
+    /// Lowest `maxAmountRequired`, in token base units.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min_price: Option<TokenAmount>,
+    /// Highest `maxAmountRequired`, in token base units.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_price: Option<TokenAmount>,
+    /// Resources per page (default `100`, at most `1000`).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub limit: Option<usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub offset: Option<usize>,
+}
+
+impl DiscoveryQuery {
+    fn matches(&self, resource: &DiscoveryResource) -> bool {
+        if self.r#type.is_some_and(|t| t != resource.r#type) {
+            return false;
+        }
+        resource.accepts.iter().any(|requirements| {
+            self.network.is_none_or(|n| n == requirements.network)
+                && self.asset.as_ref().is_none_or(|a| *a == requirements.asset)
+                && self
+                    .min_price
+                    .is_none_or(|p| requirements.max_amount_required >= p)
+                && self
+                    .max_price
+                    .is_none_or(|p| requirements.max_amount_required <= p)
+        })
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DiscoveryPagination {
+    pub limit: usize,
+    pub offset: usize,
+    /// Resources matching the query, on all pages.
+    pub total: usize,
+}
+
+/// Result of `GET /discovery/resources` and `x402.discovery.list`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DiscoveryResourcesResponse {
+    pub x402_version: X402Version,
+    pub items: Vec<DiscoveryResource>,
+    pub pagination: DiscoveryPagination,
+}
+
+/// Registered resources, keyed by URL.
+///
+/// Clones share the same registrations.
+#[derive(Debug, Clone)]
+pub struct DiscoveryRegistry {
+    resources: Arc<RwLock<HashMap<Url, DiscoveryResource>>>,
+    capacity: usize,
+}
+
+impl Default for DiscoveryRegistry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl DiscoveryRegistry {
+    pub fn new() -> Self {
+        Self {
+            resources: Arc::new(RwLock::new(HashMap::new())),
+            capacity: DEFAULT_CAPACITY,
+        }
+    }
+
+    /// Keeps up to `capacity` resources (at least one).
+    #[allow(dead_code)] // Public for consumption by downstream crates.
+    pub fn with_capacity(&self, capacity: usize) -> Self {
+        Self {
+            resources: self.resources.clone(),
+            capacity: capacity.max(1),
+        }
+    }
+
+    /// Registers a resource, replacing any previous registration of the same URL.
+    pub fn register(
+        &self,
+        registration: DiscoveryRegistration,
+    ) -> Result<DiscoveryResource, DiscoveryError> {
+        if registration.accepts.is_empty() {
+            return Err(DiscoveryError::NoPaymentRequirements);
+        }
+        let resource = DiscoveryResource {
+            resource: registration.resource,
+            r#type: registration.r#type,
+            x402_version: X402Version::V1,
+            accepts: registration.accepts,
+            last_updated: UnixTimestamp::try_now().map_err(DiscoveryError::ClockError)?,
+            metadata: registration.metadata,
+        };
+        let mut resources = self.resources.write().unwrap();
+        if resources.len() >= self.capacity && !resources.contains_key(&resource.resource) {
+            let oldest = resources
+                .values()
+                .min_by_key(|resource| resource.last_updated)
+                .map(|resource| resource.resource.clone());
+            if let Some(oldest) = oldest {
+                resources.remove(&oldest);
+            }
+        }
+        resources.insert(resource.resource.clone(), resource.clone());
+        Ok(resource)
+    }
+
+    /// Registered resources matching `query`, most recently updated first.
+    pub fn list(&self, query: &DiscoveryQuery) -> DiscoveryResourcesResponse {
+        let limit = query.limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT);
+        let offset = query.offset.unwrap_or(0);
+        let mut matching = self
+            .resources
+            .read()
+            .unwrap()
+            .values()
+            .filter(|resource| query.matches(resource))
+            .cloned()
+            .collect::<Vec<_>>();
+        matching.sort_by(|a, b| {
+            b.last_updated
+                .cmp(&a.last_updated)
+                .then_with(|| a.resource.cmp(&b.resource))
+        });
+        let total = matching.len();
+        let items = matching.into_iter().skip(offset).take(limit).collect();
+        DiscoveryResourcesResponse {
+            x402_version: X402Version::V1,
+            items,
+            pagination: DiscoveryPagination {
+                limit,
+                offset,
+                total,
+            },
+        }
+    }
+}
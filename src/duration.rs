@@ -0,0 +1,180 @@
+//! Typed durations of configuration and protocol fields.
+//!
+//! Durations are written as an integer with a unit: `250ms`, `30s`, `5m`, `2h` or `1d`. A bare
+//! integer is read in the unit of the field it is given for: seconds for `*_SECS` variables and
+//! `maxTimeoutSeconds`, milliseconds for `*_MS` variables, so existing configurations keep working.
+//! Every setting bounds its durations with a [`DurationRange`], and rejects values out of range.
+//!
+//! Protocol fields counted in seconds (`maxTimeoutSeconds`, `unitSeconds`) are [`Seconds`]:
+//! serialized as integers, as the protocol requires, and deserialized from integers or duration
+//! strings. Instants are [`UnixTimestamp`](crate::timestamp::UnixTimestamp)s, in seconds, or
+//! [`UnixTimestampMs`](crate::timestamp::UnixTimestampMs), in milliseconds (`prepaidUntilMs`),
+//! never bare integers.
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::env;
+use std::fmt::{Display, Formatter};
+use std::time::Duration;
+
+/// Unit of bare integers in `*_MS` variables.
+pub const MILLISECOND: Duration = Duration::from_millis(1);
+/// Unit of bare integers in `*_SECS` variables and protocol fields counted in seconds.
+pub const SECOND: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum DurationError {
+    #[error("Invalid duration {0:?}: expected an integer with a unit (ms, s, m, h, d)")]
+    Invalid(String),
+    #[error("Invalid {0}: {1}")]
+    InvalidVariable(String, Box<DurationError>),
+    #[error("Duration {value:?} out of range [{min:?}, {max:?}]")]
+    OutOfRange {
+        value: Duration,
+        min: Duration,
+        max: Duration,
+    },
+    #[error("Duration {0:?} is not a whole number of seconds")]
+    FractionalSeconds(Duration),
+}
+
+/// Bounds of the durations accepted by a setting, inclusive.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DurationRange {
+    pub min: Duration,
+    pub max: Duration,
+}
+
+impl DurationRange {
+    pub const fn new(min: Duration, max: Duration) -> Self {
+        Self { min, max }
+    }
+
+    /// Returns `value` if within the range.
+    pub fn check(&self, value: Duration) -> Result<Duration, DurationError> {
+        if value < self.min || value > self.max {
+            return Err(DurationError::OutOfRange {
+                value,
+                min: self.min,
+                max: self.max,
+            });
+        }
+        Ok(value)
+    }
+}
+
+/// Parses `value`, e.g. `30s` or `5m`, reading a bare integer in `unit`.
+pub fn parse(value: &str, unit: Duration) -> Result<Duration, DurationError> {
+    let invalid = || DurationError::Invalid(value.to_string());
+    let value = value.trim();
+    let split = value
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(value.len());
+    let (amount, suffix) = value.split_at(split);
+    let amount: u64 = amount.parse().map_err(|_| invalid())?;
+    let unit = match suffix.trim() {
+        "" => unit,
+        "ms" => MILLISECOND,
+        "s" => SECOND,
+        "m" => SECOND * 60,
+        "h" => SECOND * 3600,
+        "d" => SECOND * 86_400,
+        _ => return Err(invalid()),
+    };
+    let amount = u32::try_from(amount).map_err(|_| invalid())?;
+    unit.checked_mul(amount).ok_or_else(invalid)
+}
+
+/// Reads the variable `name` with [`parse`], and checks it is within `range`.
+/// Returns `None` if the variable is unset or blank.
+pub fn from_env(
+    name: &str,
+    unit: Duration,
+    range: DurationRange,
+) -> Result<Option<Duration>, DurationError> {
+    match env::var(name) {
+        Ok(value) if !value.trim().is_empty() => parse(&value, unit)
+            .and_then(|duration| range.check(duration))
+            .map(Some)
+            .map_err(|e| DurationError::InvalidVariable(name.to_string(), Box::new(e))),
+        _ => Ok(None),
+    }
+}
+
+/// A duration counted in whole seconds, as in `maxTimeoutSeconds`.
+///
+/// Serialized as an integer. Deserialized from an integer of seconds, or a duration string
+/// such as `"5m"`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct Seconds(u64);
+
+impl Seconds {
+    pub const fn new(seconds: u64) -> Self {
+        Self(seconds)
+    }
+
+    pub const fn as_secs(&self) -> u64 {
+        self.0
+    }
+
+    pub const fn as_duration(&self) -> Duration {
+        Duration::from_secs(self.0)
+    }
+
+    pub fn as_millis(&self) -> u64 {
+        self.0.saturating_mul(1000)
+    }
+
+    /// Returns `self` if within `range`.
+    pub fn check(self, range: DurationRange) -> Result<Self, DurationError> {
+        range.check(self.as_duration()).map(|_| self)
+    }
+}
+
+impl From<Seconds> for Duration {
+    fn from(value: Seconds) -> Self {
+        value.as_duration()
+    }
+}
+
+impl TryFrom<Duration> for Seconds {
+    type Error = DurationError;
+
+    fn try_from(value: Duration) -> Result<Self, Self::Error> {
+        if value.subsec_nanos() != 0 {
+            return Err(DurationError::FractionalSeconds(value));
+        }
+        Ok(Self(value.as_secs()))
+    }
+}
+
+impl Display for Seconds {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}s", self.0)
+    }
+}
+
+impl Serialize for Seconds {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_u64(self.0)
+    }
+}
+
+impl<'de> Deserialize<'de> for Seconds {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Repr {
+            Integer(u64),
+            Text(String),
+        }
+        match Repr::deserialize(deserializer)? {
+            Repr::Integer(seconds) => Ok(Self(seconds)),
+            Repr::Text(text) => parse(&text, SECOND)
+                .and_then(Seconds::try_from)
+                .map_err(serde::de::Error::custom),
+        }
+    }
+}
@@ -0,0 +1,172 @@
+//! Registry of the optional extensions of the WebSocket protocol, see `x402-ws-stream.md`.
+//!
+//! The core protocol (`x402.hello`, `x402.auth`, `x402.supported`, `x402.verify`, `x402.settle`
+//! and the deferred settlement and discovery methods) is served by every facilitator. Optional
+//! features are extensions, each described by an [`ExtensionDescriptor`]: a name, a version, and
+//! the methods it adds. The extensions a facilitator serves are listed in its
+//! [`ExtensionRegistry`] (see [`FacilitatorServices::extensions`]), surfaced as the `extensions`
+//! of `/supported` and `x402.supported`.
+//!
+//! Built-in extensions:
+//! - `stream` – pay-per-slice and metered streams: `stream.init`, `stream.require`, `stream.pay`,
+//!   `stream.finalize`
+//! - `balance` – prepaid balances of repeat buyers: `balance.deposit`, `balance.charge`,
+//!   `balance.get`
+//! - `subscriptions` – pushed notifications: `x402.subscribe`, `x402.watchPayments`
+//! - `batch` – `x402.verifyBatch`, `x402.settleBatch`
+//! - `binary` – CBOR envelopes, negotiated in the handshake with the `x402.cbor` subprotocol
+//!   (see [`crate::ws_codec`])
+//! - `compression` – compressed envelopes, negotiated in the handshake with the `x402.deflate` or
+//!   `x402.cbor.deflate` subprotocol (see [`crate::ws_codec`]); served by facilitators compressing
+//!   envelopes only
+//!
+//! A peer negotiates the extensions of its connection by listing the ones it wants as the
+//! `extensions` params of `x402.hello`, which replies with those served. Methods of the other
+//! extensions are then rejected as unavailable (`-32601`). A connection that does not negotiate
+//! can call the methods of every extension served, so peers unaware of extensions keep working.
+//! Extensions negotiated in the handshake are listed by `x402.hello` when in use, whatever the
+//! params.
+//!
+//! [`FacilitatorServices::extensions`]: crate::facilitator::FacilitatorServices::extensions
+
+use serde::{Deserialize, Serialize};
+
+/// Capability descriptor of an extension of the WebSocket protocol.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExtensionDescriptor {
+    /// Name of the extension, e.g. `stream`.
+    pub name: String,
+    /// Version of the extension, bumped on incompatible changes.
+    pub version: u32,
+    /// Methods added by the extension; empty for extensions negotiated in the handshake.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub methods: Vec<String>,
+}
+
+impl ExtensionDescriptor {
+    pub const STREAM: &str = "stream";
+    pub const SUBSCRIPTIONS: &str = "subscriptions";
+    pub const BATCH: &str = "batch";
+    pub const BINARY: &str = "binary";
+    pub const COMPRESSION: &str = "compression";
+    pub const BALANCE: &str = "balance";
+
+    /// An extension adding no method yet.
+    pub fn new(name: impl Into<String>, version: u32) -> Self {
+        Self {
+            name: name.into(),
+            version,
+            methods: Vec::new(),
+        }
+    }
+
+    /// Adds `method` to the methods of the extension.
+    pub fn with_method(&self, method: impl Into<String>) -> Self {
+        let mut this = self.clone();
+        this.methods.push(method.into());
+        this
+    }
+
+    /// Pay-per-slice streams, see [`crate::stream`].
+    pub fn stream() -> Self {
+        Self::new(Self::STREAM, 1)
+            .with_method("stream.init")
+            .with_method("stream.require")
+            .with_method("stream.require.cancel")
+            .with_method("stream.pay")
+            .with_method("stream.voucher")
+            .with_method("stream.finalize")
+    }
+
+    /// Prepaid balances of repeat buyers, see [`crate::balance`].
+    pub fn balance() -> Self {
+        Self::new(Self::BALANCE, 1)
+            .with_method("balance.deposit")
+            .with_method("balance.charge")
+            .with_method("balance.get")
+    }
+
+    /// Pushed notifications of settlements and inbound payments.
+    pub fn subscriptions() -> Self {
+        Self::new(Self::SUBSCRIPTIONS, 1)
+            .with_method("x402.subscribe")
+            .with_method("x402.watchPayments")
+    }
+
+    /// Batch verification and settlement.
+    pub fn batch() -> Self {
+        Self::new(Self::BATCH, 1)
+            .with_method("x402.verifyBatch")
+            .with_method("x402.settleBatch")
+    }
+
+    /// CBOR envelopes, see [`crate::ws_codec`].
+    pub fn binary() -> Self {
+        Self::new(Self::BINARY, 1)
+    }
+
+    /// Compressed envelopes, see [`crate::ws_codec`].
+    pub fn compression() -> Self {
+        Self::new(Self::COMPRESSION, 1)
+    }
+
+    /// Whether the extension is negotiated in the WebSocket handshake rather than in `x402.hello`.
+    pub fn is_handshake(&self) -> bool {
+        self.methods.is_empty()
+    }
+}
+
+/// Extensions served by a facilitator, or negotiated by a connection.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ExtensionRegistry {
+    extensions: Vec<ExtensionDescriptor>,
+}
+
+impl ExtensionRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registers `extension`, replacing any extension of the same name.
+    pub fn with(&self, extension: ExtensionDescriptor) -> Self {
+        let mut this = self.without(&extension.name);
+        this.extensions.push(extension);
+        this
+    }
+
+    /// Unregisters the extension named `name`, if any.
+    pub fn without(&self, name: &str) -> Self {
+        let mut this = self.clone();
+        this.extensions.retain(|extension| extension.name != name);
+        this
+    }
+
+    pub fn get(&self, name: &str) -> Option<&ExtensionDescriptor> {
+        self.extensions
+            .iter()
+            .find(|extension| extension.name == name)
+    }
+
+    pub fn descriptors(&self) -> &[ExtensionDescriptor] {
+        &self.extensions
+    }
+
+    /// Extension adding `method`, `None` for methods of the core protocol.
+    pub fn extension_of(&self, method: &str) -> Option<&ExtensionDescriptor> {
+        self.extensions
+            .iter()
+            .find(|extension| extension.methods.iter().any(|m| m == method))
+    }
+
+    /// Extensions both registered and `requested`, in the order they were registered.
+    pub fn negotiate<S: AsRef<str>>(&self, requested: &[S]) -> Self {
+        let extensions = self
+            .extensions
+            .iter()
+            .filter(|extension| requested.iter().any(|name| name.as_ref() == extension.name))
+            .cloned()
+            .collect();
+        Self { extensions }
+    }
+}
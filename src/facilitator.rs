@@ -2,9 +2,37 @@
 //!
 //! Implementors of this trait are responsible for validating incoming payment payloads
 //! against specified requirements [`Facilitator::verify`] and executing on-chain transfers [`Facilitator::settle`].
+//!
+//! Everything consuming a facilitator is generic over [`Facilitator`]: the `x402-axum` middleware,
+//! and the HTTP and WebSocket endpoints of the facilitator server. Implementations can be swapped
+//! for one another, e.g. the on-chain [`FacilitatorLocal`](crate::facilitator_local::FacilitatorLocal),
+//! a remote facilitator over HTTP or WebSocket (see `facilitator_remote`, `remote` feature), a [`FacilitatorPool`](crate::facilitator_pool::FacilitatorPool),
+//! a caching decorator, or a mock in tests.
+//!
+//! [`FacilitatorServices`] exposes the optional capabilities served by the WebSocket endpoint
+//! on top of verification and settlement, and the protocol extensions they make up.
 
 use std::fmt::{Debug, Display};
+use std::sync::Arc;
 
+use crate::balance::BalanceLedger;
+#[cfg(feature = "chaos")]
+use crate::chaos::FaultInjector;
+use crate::discovery::DiscoveryRegistry;
+use crate::extensions::{ExtensionDescriptor, ExtensionRegistry};
+use crate::nonce_store::PaymentNonceStore;
+#[cfg(feature = "webhooks")]
+use crate::payment_watch::PaymentWatcher;
+use crate::provider_cache::ProviderCache;
+use crate::receipts::Receipts;
+use crate::refunds::Refunds;
+use crate::settlement_events::SettlementEvents;
+use crate::settlement_pause::SettlementPause;
+use crate::settlement_queue::SettlementQueue;
+use crate::settlement_stats::SettlementStats;
+use crate::storage::PaymentHistory;
+use crate::stream::StreamSessionManager;
+use crate::strict_settle::StrictSettle;
 use crate::types::{
     SettleRequest, SettleResponse, SupportedPaymentKindsResponse, VerifyRequest, VerifyResponse,
 };
@@ -52,8 +80,205 @@ pub trait Facilitator {
         request: &SettleRequest,
     ) -> impl Future<Output = Result<SettleResponse, Self::Error>> + Send;
 
-    #[allow(dead_code)] // For some reason clippy believes it is not used.
+    /// Lists the payment kinds (version, scheme, network) this facilitator can verify and settle.
     fn supported(
         &self,
     ) -> impl Future<Output = Result<SupportedPaymentKindsResponse, Self::Error>> + Send;
 }
+
+impl<F: Facilitator + Send + Sync> Facilitator for Arc<F> {
+    type Error = F::Error;
+
+    fn verify(
+        &self,
+        request: &VerifyRequest,
+    ) -> impl Future<Output = Result<VerifyResponse, Self::Error>> + Send {
+        self.as_ref().verify(request)
+    }
+
+    fn settle(
+        &self,
+        request: &SettleRequest,
+    ) -> impl Future<Output = Result<SettleResponse, Self::Error>> + Send {
+        self.as_ref().settle(request)
+    }
+
+    fn supported(
+        &self,
+    ) -> impl Future<Output = Result<SupportedPaymentKindsResponse, Self::Error>> + Send {
+        self.as_ref().supported()
+    }
+}
+
+/// Optional capabilities of a facilitator, beyond verification and settlement.
+///
+/// Every capability is unavailable by default: a facilitator only implementing [`Facilitator`]
+/// serves the core WebSocket methods, and answers the others with "Method not found".
+pub trait FacilitatorServices {
+    /// Settlement lifecycle events, serving `x402.subscribe`.
+    fn settlement_events(&self) -> Option<&SettlementEvents> {
+        None
+    }
+
+    /// Sessions of pay-per-slice streams, serving the `stream.*` methods.
+    fn stream_sessions(&self) -> Option<&StreamSessionManager> {
+        None
+    }
+
+    /// Queue of deferred settlements, serving `x402.settlementStatus` and deferring `x402.settle`.
+    fn settlement_queue(&self) -> Option<&SettlementQueue> {
+        None
+    }
+
+    /// Providers of the chains settled on, serving the nonce resynchronization of the admin API.
+    fn provider_cache(&self) -> Option<&ProviderCache> {
+        None
+    }
+
+    /// Nonces of settled payments, checked by `/readyz`.
+    fn nonce_store(&self) -> Option<&dyn PaymentNonceStore> {
+        None
+    }
+
+    /// Registry of payable resources, serving `/discovery/resources` and `x402.discovery.list`.
+    fn discovery(&self) -> Option<&DiscoveryRegistry> {
+        None
+    }
+
+    /// Statistics of the settlements, serving `/stats`.
+    fn settlement_stats(&self) -> Option<&SettlementStats> {
+        None
+    }
+
+    /// History of verified and settled payments, serving `/payments`.
+    fn payment_history(&self) -> Option<&PaymentHistory> {
+        None
+    }
+
+    /// Signed receipts of settled payments, serving `/receipts/{transaction}` and `x402.receipt`.
+    fn receipts(&self) -> Option<&Receipts> {
+        None
+    }
+
+    /// Refunds of settled payments, serving `/refund` and `x402.refund`.
+    fn refunds(&self) -> Option<&Refunds> {
+        None
+    }
+
+    /// Prepaid balances of repeat buyers, serving the `balance.*` methods and `/balance`.
+    fn balances(&self) -> Option<&BalanceLedger> {
+        None
+    }
+
+    /// Verifications of the payments to settle, tuned through the admin API.
+    fn strict_settle(&self) -> Option<&StrictSettle> {
+        None
+    }
+
+    /// Networks whose settlements are paused, through the admin API.
+    fn settlement_pause(&self) -> Option<&SettlementPause> {
+        None
+    }
+
+    /// Watcher of inbound payments, serving `x402.watchPayments`.
+    #[cfg(feature = "webhooks")]
+    fn payment_watcher(&self) -> Option<&PaymentWatcher> {
+        None
+    }
+
+    /// Fault injector, tuned through the admin API.
+    #[cfg(feature = "chaos")]
+    fn faults(&self) -> Option<&FaultInjector> {
+        None
+    }
+
+    /// Protocol extensions served, see [`crate::extensions`]. Defaults to the built-in extensions
+    /// of the capabilities above.
+    fn extensions(&self) -> ExtensionRegistry {
+        let mut extensions = ExtensionRegistry::new()
+            .with(ExtensionDescriptor::batch())
+            .with(ExtensionDescriptor::binary());
+        if self.stream_sessions().is_some() {
+            extensions = extensions.with(ExtensionDescriptor::stream());
+        }
+        if self.balances().is_some() {
+            extensions = extensions.with(ExtensionDescriptor::balance());
+        }
+        #[cfg(feature = "webhooks")]
+        let watching = self.payment_watcher().is_some();
+        #[cfg(not(feature = "webhooks"))]
+        let watching = false;
+        if self.settlement_events().is_some() || watching {
+            extensions = extensions.with(ExtensionDescriptor::subscriptions());
+        }
+        extensions
+    }
+}
+
+impl<F: FacilitatorServices> FacilitatorServices for Arc<F> {
+    fn settlement_events(&self) -> Option<&SettlementEvents> {
+        self.as_ref().settlement_events()
+    }
+
+    fn stream_sessions(&self) -> Option<&StreamSessionManager> {
+        self.as_ref().stream_sessions()
+    }
+
+    fn settlement_queue(&self) -> Option<&SettlementQueue> {
+        self.as_ref().settlement_queue()
+    }
+
+    fn provider_cache(&self) -> Option<&ProviderCache> {
+        self.as_ref().provider_cache()
+    }
+
+    fn nonce_store(&self) -> Option<&dyn PaymentNonceStore> {
+        self.as_ref().nonce_store()
+    }
+
+    fn discovery(&self) -> Option<&DiscoveryRegistry> {
+        self.as_ref().discovery()
+    }
+
+    fn settlement_stats(&self) -> Option<&SettlementStats> {
+        self.as_ref().settlement_stats()
+    }
+
+    fn payment_history(&self) -> Option<&PaymentHistory> {
+        self.as_ref().payment_history()
+    }
+
+    fn receipts(&self) -> Option<&Receipts> {
+        self.as_ref().receipts()
+    }
+
+    fn refunds(&self) -> Option<&Refunds> {
+        self.as_ref().refunds()
+    }
+
+    fn balances(&self) -> Option<&BalanceLedger> {
+        self.as_ref().balances()
+    }
+
+    fn strict_settle(&self) -> Option<&StrictSettle> {
+        self.as_ref().strict_settle()
+    }
+
+    fn settlement_pause(&self) -> Option<&SettlementPause> {
+        self.as_ref().settlement_pause()
+    }
+
+    #[cfg(feature = "webhooks")]
+    fn payment_watcher(&self) -> Option<&PaymentWatcher> {
+        self.as_ref().payment_watcher()
+    }
+
+    #[cfg(feature = "chaos")]
+    fn faults(&self) -> Option<&FaultInjector> {
+        self.as_ref().faults()
+    }
+
+    fn extensions(&self) -> ExtensionRegistry {
+        self.as_ref().extensions()
+    }
+}
@@ -0,0 +1,440 @@
+//! Short-lived caching of verification results in front of another facilitator.
+//!
+//! Sellers typically verify a payment on every request, and a burst of identical requests (retries,
+//! parallel page loads, many edges behind one upstream) would hit the chain once per request.
+//! [`CachedFacilitator`] wraps any [`Facilitator`] and:
+//!
+//! - runs identical concurrent verifications once: the first caller verifies, the others wait for
+//!   its result (single-flight). If the first caller fails or is cancelled, the others verify
+//!   on their own;
+//! - remembers verification results for a short [TTL](CachedFacilitator::with_ttl), in an
+//!   [`LruCache`] reported as `verify`. The TTL may differ per network, e.g. shorter on a chain
+//!   with fast blocks, or caching may be turned off on some networks, see [`VerifyCacheTtls`];
+//! - forwards settlements unchanged, and forgets the cached verification of a settled payment,
+//!   whose nonce is now spent.
+//!
+//! Results are keyed by the hash of the payment payload, and served only to requests with the
+//! same payment requirements: seller metadata and idempotency keys do not take part in
+//! verification, and a settlement forgets the verification of its payload whatever they are.
+//! Hashes are those of the canonical JSON encodings. Errors are never cached.
+//!
+//! ```rust,no_run
+//! use std::time::Duration;
+//! use x402_rs::facilitator_cache::{CachedFacilitator, VerifyCacheTtls};
+//! # use x402_rs::facilitator_local::FacilitatorLocal;
+//! use x402_rs::network::Network;
+//! # fn facilitator() -> FacilitatorLocal { unimplemented!() }
+//!
+//! let ttls = VerifyCacheTtls::new(Some(Duration::from_secs(5)))
+//!     .with_network(Network::Base, Duration::from_secs(1))
+//!     .with_network(Network::Solana, Duration::ZERO);
+//! let facilitator = CachedFacilitator::new(facilitator()).with_ttls(&ttls);
+//! ```
+//!
+//! Environment (see [`VerifyCacheTtls::from_env`]):
+//! - `VERIFY_CACHE_TTL_MS` – Time results are cached for, in milliseconds or with a unit (e.g.
+//!   `2s`), up to 1 hour (default: not cached)
+//! - `VERIFY_CACHE_TTL_MS_<NETWORK>` – The same on one network, e.g.
+//!   `VERIFY_CACHE_TTL_MS_BASE_SEPOLIA`, `0` not to cache its results
+
+use alloy::primitives::{B256, keccak256};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::watch;
+
+use crate::balance::BalanceLedger;
+use crate::canonical_json;
+#[cfg(feature = "chaos")]
+use crate::chaos::FaultInjector;
+use crate::discovery::DiscoveryRegistry;
+use crate::duration::{self, DurationError, DurationRange};
+use crate::extensions::ExtensionRegistry;
+use crate::facilitator::{Facilitator, FacilitatorServices};
+use crate::lru_cache::LruCache;
+use crate::network::Network;
+use crate::nonce_store::PaymentNonceStore;
+#[cfg(feature = "webhooks")]
+use crate::payment_watch::PaymentWatcher;
+use crate::provider_cache::ProviderCache;
+use crate::receipts::Receipts;
+use crate::refunds::Refunds;
+use crate::settlement_events::SettlementEvents;
+use crate::settlement_pause::SettlementPause;
+use crate::settlement_queue::SettlementQueue;
+use crate::settlement_stats::SettlementStats;
+use crate::storage::PaymentHistory;
+use crate::stream::StreamSessionManager;
+use crate::strict_settle::StrictSettle;
+use crate::types::{
+    SettleRequest, SettleResponse, SupportedPaymentKindsResponse, VerifyRequest, VerifyResponse,
+};
+
+/// Time to cache verification results for, in milliseconds, suffixed with a network for its own
+/// TTL. Caching is disabled if unset.
+const ENV_VERIFY_CACHE_TTL_MS: &str = "VERIFY_CACHE_TTL_MS";
+
+/// Default time a verification result is served from the cache.
+const DEFAULT_TTL: Duration = Duration::from_secs(2);
+/// Default number of cached verification results.
+const DEFAULT_CAPACITY: usize = 10_000;
+
+/// TTLs accepted from `VERIFY_CACHE_TTL_MS`: up to 1 hour.
+const TTL_RANGE: DurationRange = DurationRange::new(Duration::ZERO, Duration::from_secs(3600));
+
+/// Error returned by [`VerifyCacheTtls::from_env`] for a malformed or out of range
+/// `VERIFY_CACHE_TTL_MS` or `VERIFY_CACHE_TTL_MS_<NETWORK>`.
+#[derive(Debug, thiserror::Error)]
+#[error(transparent)]
+pub struct InvalidCacheTtl(#[from] DurationError);
+
+/// Time verification results are cached for: a default, and overrides per network.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct VerifyCacheTtls {
+    default: Option<Duration>,
+    networks: HashMap<Network, Duration>,
+}
+
+impl VerifyCacheTtls {
+    /// Caches the results of every network for `default`, or none if `None`.
+    pub fn new(default: Option<Duration>) -> Self {
+        Self {
+            default,
+            networks: HashMap::new(),
+        }
+    }
+
+    /// Reads `VERIFY_CACHE_TTL_MS` and `VERIFY_CACHE_TTL_MS_<NETWORK>`, see the
+    /// [module documentation](self). Nothing is cached if all are unset.
+    pub fn from_env() -> Result<Self, InvalidCacheTtl> {
+        let mut ttls = Self::new(ttl_from_env(ENV_VERIFY_CACHE_TTL_MS)?);
+        for network in Network::variants() {
+            let suffix = network.to_string().to_uppercase().replace('-', "_");
+            if let Some(ttl) = ttl_from_env(&format!("{ENV_VERIFY_CACHE_TTL_MS}_{suffix}"))? {
+                ttls = ttls.with_network(*network, ttl);
+            }
+        }
+        Ok(ttls)
+    }
+
+    /// Caches the results of `network` for `ttl` instead of the default, none if zero.
+    pub fn with_network(self, network: Network, ttl: Duration) -> Self {
+        let mut networks = self.networks;
+        networks.insert(network, ttl);
+        Self { networks, ..self }
+    }
+
+    /// Time the results of `network` are cached for, `None` if they are not.
+    pub fn for_network(&self, network: Network) -> Option<Duration> {
+        self.networks
+            .get(&network)
+            .copied()
+            .or(self.default)
+            .filter(|ttl| !ttl.is_zero())
+    }
+
+    /// Whether the results of any network are cached.
+    pub fn is_enabled(&self) -> bool {
+        Network::variants()
+            .iter()
+            .any(|network| self.for_network(*network).is_some())
+    }
+}
+
+/// Verification result of a payload, for the payment requirements it was verified against.
+#[derive(Debug, Clone)]
+struct CachedVerification {
+    requirements: B256,
+    response: VerifyResponse,
+}
+
+/// Caches of verification results, by payload hash: one per network with its own TTL.
+#[derive(Clone)]
+struct Verifications {
+    default: Option<LruCache<B256, CachedVerification>>,
+    networks: Arc<HashMap<Network, Option<LruCache<B256, CachedVerification>>>>,
+}
+
+impl Verifications {
+    fn new(ttls: &VerifyCacheTtls, capacity: usize) -> Self {
+        let cache = |ttl: Option<Duration>| {
+            ttl.filter(|ttl| !ttl.is_zero())
+                .map(|ttl| LruCache::new("verify", capacity).with_ttl(ttl))
+        };
+        let networks = ttls
+            .networks
+            .keys()
+            .map(|network| (*network, cache(ttls.for_network(*network))))
+            .collect();
+        Self {
+            default: cache(ttls.default),
+            networks: Arc::new(networks),
+        }
+    }
+
+    /// Cache of the results of `network`, `None` if they are not cached.
+    fn for_network(&self, network: Network) -> Option<&LruCache<B256, CachedVerification>> {
+        match self.networks.get(&network) {
+            Some(cache) => cache.as_ref(),
+            None => self.default.as_ref(),
+        }
+    }
+}
+
+/// Verifications in progress, keyed by payload and requirements hash. Followers wait for the
+/// value to be set.
+type InFlight = Arc<Mutex<HashMap<B256, watch::Receiver<Option<VerifyResponse>>>>>;
+
+/// A [`Facilitator`] caching the verification results of another one, see the
+/// [module documentation](self).
+pub struct CachedFacilitator<F> {
+    inner: F,
+    ttls: VerifyCacheTtls,
+    capacity: usize,
+    verifications: Verifications,
+    in_flight: InFlight,
+}
+
+impl<F: Clone> Clone for CachedFacilitator<F> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            ttls: self.ttls.clone(),
+            capacity: self.capacity,
+            verifications: self.verifications.clone(),
+            in_flight: self.in_flight.clone(),
+        }
+    }
+}
+
+impl<F> CachedFacilitator<F> {
+    /// Caches the verifications of `inner` for 2 seconds, up to 10 000 results per network
+    /// with its own TTL, and as many for the others.
+    pub fn new(inner: F) -> Self {
+        let ttls = VerifyCacheTtls::new(Some(DEFAULT_TTL));
+        Self {
+            inner,
+            verifications: Verifications::new(&ttls, DEFAULT_CAPACITY),
+            ttls,
+            capacity: DEFAULT_CAPACITY,
+            in_flight: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// The wrapped facilitator.
+    #[allow(dead_code)] // Public for consumption by downstream crates.
+    pub fn inner(&self) -> &F {
+        &self.inner
+    }
+
+    /// Serves verification results from the cache for `ttl`, on every network. Starts from an
+    /// empty cache.
+    #[allow(dead_code)] // Public for consumption by downstream crates.
+    pub fn with_ttl(self, ttl: Duration) -> Self {
+        self.with_ttls(&VerifyCacheTtls::new(Some(ttl)))
+    }
+
+    /// Serves verification results from the cache for the TTL of their network. Starts from an
+    /// empty cache.
+    pub fn with_ttls(self, ttls: &VerifyCacheTtls) -> Self {
+        Self {
+            verifications: Verifications::new(ttls, self.capacity),
+            ttls: ttls.clone(),
+            ..self
+        }
+    }
+
+    /// Caches at most `capacity` verification results per cache. Starts from an empty cache.
+    #[allow(dead_code)] // Public for consumption by downstream crates.
+    pub fn with_capacity(self, capacity: usize) -> Self {
+        Self {
+            verifications: Verifications::new(&self.ttls, capacity),
+            capacity,
+            ..self
+        }
+    }
+
+    /// Reads the default cache TTL from `VERIFY_CACHE_TTL_MS`, in milliseconds or with a unit
+    /// (e.g. `2s`), up to 1 hour: `None` if unset. See [`VerifyCacheTtls::from_env`] for the
+    /// TTLs per network.
+    #[allow(dead_code)] // Public for consumption by downstream crates.
+    pub fn ttl_from_env() -> Result<Option<Duration>, InvalidCacheTtl> {
+        ttl_from_env(ENV_VERIFY_CACHE_TTL_MS)
+    }
+}
+
+/// Reads the cache TTL of the variable `name`, `None` if unset.
+fn ttl_from_env(name: &str) -> Result<Option<Duration>, InvalidCacheTtl> {
+    Ok(duration::from_env(name, duration::MILLISECOND, TTL_RANGE)?)
+}
+
+/// Hash of the payment payload of a request, the key of its cached verification.
+fn payload_key(request: &VerifyRequest) -> Option<B256> {
+    canonical_json::keccak256(&request.payment_payload).ok()
+}
+
+/// Hash of the payment requirements of a request.
+fn requirements_key(request: &VerifyRequest) -> Option<B256> {
+    canonical_json::keccak256(&request.payment_requirements).ok()
+}
+
+/// Removes the in-flight entry of a verification when dropped, so a failed or cancelled leader
+/// releases its followers.
+struct Leader<'a> {
+    in_flight: &'a InFlight,
+    key: B256,
+    sender: watch::Sender<Option<VerifyResponse>>,
+}
+
+impl Drop for Leader<'_> {
+    fn drop(&mut self) {
+        self.in_flight.lock().unwrap().remove(&self.key);
+    }
+}
+
+impl<F> Facilitator for CachedFacilitator<F>
+where
+    F: Facilitator + Sync,
+    F::Error: Send,
+{
+    type Error = F::Error;
+
+    async fn verify(&self, request: &VerifyRequest) -> Result<VerifyResponse, Self::Error> {
+        let cache = self
+            .verifications
+            .for_network(request.payment_payload.network);
+        let (Some(cache), Some(payload), Some(requirements)) =
+            (cache, payload_key(request), requirements_key(request))
+        else {
+            return self.inner.verify(request).await;
+        };
+        if let Some(cached) = cache.get(&payload)
+            && cached.requirements == requirements
+        {
+            return Ok(cached.response);
+        }
+        let key = keccak256([payload.as_slice(), requirements.as_slice()].concat());
+        let role = {
+            let mut in_flight = self.in_flight.lock().unwrap();
+            match in_flight.get(&key) {
+                Some(receiver) => Err(receiver.clone()),
+                None => {
+                    let (sender, receiver) = watch::channel(None);
+                    in_flight.insert(key, receiver);
+                    Ok(Leader {
+                        in_flight: &self.in_flight,
+                        key,
+                        sender,
+                    })
+                }
+            }
+        };
+        match role {
+            Ok(leader) => {
+                let response = self.inner.verify(request).await?;
+                let cached = CachedVerification {
+                    requirements,
+                    response: response.clone(),
+                };
+                cache.insert(payload, cached);
+                leader.sender.send_replace(Some(response.clone()));
+                Ok(response)
+            }
+            Err(mut receiver) => {
+                let shared = receiver
+                    .wait_for(Option::is_some)
+                    .await
+                    .ok()
+                    .and_then(|response| response.clone());
+                match shared {
+                    Some(response) => Ok(response),
+                    // The leader failed: its error is not shared
+                    None => self.inner.verify(request).await,
+                }
+            }
+        }
+    }
+
+    async fn settle(&self, request: &SettleRequest) -> Result<SettleResponse, Self::Error> {
+        let response = self.inner.settle(request).await;
+        let cache = self
+            .verifications
+            .for_network(request.payment_payload.network);
+        if let (Some(cache), Some(payload)) = (cache, payload_key(request)) {
+            cache.remove(&payload);
+        }
+        response
+    }
+
+    async fn supported(&self) -> Result<SupportedPaymentKindsResponse, Self::Error> {
+        self.inner.supported().await
+    }
+}
+
+impl<F: FacilitatorServices> FacilitatorServices for CachedFacilitator<F> {
+    fn settlement_events(&self) -> Option<&SettlementEvents> {
+        self.inner.settlement_events()
+    }
+
+    fn stream_sessions(&self) -> Option<&StreamSessionManager> {
+        self.inner.stream_sessions()
+    }
+
+    fn settlement_queue(&self) -> Option<&SettlementQueue> {
+        self.inner.settlement_queue()
+    }
+
+    fn provider_cache(&self) -> Option<&ProviderCache> {
+        self.inner.provider_cache()
+    }
+
+    fn nonce_store(&self) -> Option<&dyn PaymentNonceStore> {
+        self.inner.nonce_store()
+    }
+
+    fn discovery(&self) -> Option<&DiscoveryRegistry> {
+        self.inner.discovery()
+    }
+
+    fn settlement_stats(&self) -> Option<&SettlementStats> {
+        self.inner.settlement_stats()
+    }
+
+    fn payment_history(&self) -> Option<&PaymentHistory> {
+        self.inner.payment_history()
+    }
+
+    fn receipts(&self) -> Option<&Receipts> {
+        self.inner.receipts()
+    }
+
+    fn refunds(&self) -> Option<&Refunds> {
+        self.inner.refunds()
+    }
+
+    fn balances(&self) -> Option<&BalanceLedger> {
+        self.inner.balances()
+    }
+
+    fn strict_settle(&self) -> Option<&StrictSettle> {
+        self.inner.strict_settle()
+    }
+
+    fn settlement_pause(&self) -> Option<&SettlementPause> {
+        self.inner.settlement_pause()
+    }
+
+    #[cfg(feature = "webhooks")]
+    fn payment_watcher(&self) -> Option<&PaymentWatcher> {
+        self.inner.payment_watcher()
+    }
+
+    #[cfg(feature = "chaos")]
+    fn faults(&self) -> Option<&FaultInjector> {
+        self.inner.faults()
+    }
+
+    fn extensions(&self) -> ExtensionRegistry {
+        self.inner.extensions()
+    }
+}
@@ -1,24 +1,69 @@
 //! Facilitator implementation for x402 payments using on-chain verification and settlement.
 //!
 //! This module provides a [`Facilitator`] implementation that validates x402 payment payloads
-//! and performs on-chain settlements using ERC-3009 `transferWithAuthorization`.
+//! and performs on-chain settlements using ERC-3009 `transferWithAuthorization`, or EIP-2612
+//! `permit` and `transferFrom` for tokens without ERC-3009 (the `permit` scheme) and for
+//! metered payments settling the amount consumed (the `upto` scheme).
 //!
 //! Features include:
 //! - EIP-712 signature recovery
 //! - ERC-20 balance checks
 //! - Contract interaction using Alloy
 //! - Network-specific configuration via [`ProviderCache`] and [`USDCDeployment`]
+//! - Custom payment schemes via [`SchemeHandler`]
+//! - Custom chains via [`ChainProvider`]
+//! - Replay protection via [`PaymentNonceStore`]
+//! - Optional batching of EVM settlements into Multicall3 transactions via [`SettlementBatching`]
+//! - Optional deferred settlement of `x402.settle` requests via [`SettleMode`]
+//! - Span links from settlement to verification traces via [`PaymentSpans`]
+//! - A registry of payable resources, listed to clients, via [`DiscoveryRegistry`]
+//! - Hard caps on settled amounts via [`SettlementCaps`]
+//! - Optional refusal to settle payments not verified recently via [`StrictSettle`]
+//! - Optional statistics of settlements per network and asset via [`SettlementStats`]
+//! - Optional history of verified and settled payments via [`PaymentHistory`]
+//! - Networks turned off at runtime via [`DisabledNetworks`]
+//! - Settlements paused per network at runtime via [`SettlementPause`]
+//! - Optional refusal to settle on networks whose settlement accounts are short of gas via [`GasMonitor`]
 
+use std::borrow::Cow;
+use std::sync::Arc;
+use std::time::Instant;
 use tracing::instrument;
 
-use crate::chain::{FacilitatorLocalError, NetworkProvider, NetworkProviderOps};
-use crate::facilitator::Facilitator;
+use crate::balance::BalanceLedger;
+use crate::chain::multicall::SettlementBatching;
+use crate::chain::{ChainProvider, FacilitatorLocalError, NetworkProvider, NetworkProviderOps};
+#[cfg(feature = "chaos")]
+use crate::chaos::FaultInjector;
+use crate::disabled_networks::DisabledNetworks;
+use crate::discovery::DiscoveryRegistry;
+use crate::facilitator::{Facilitator, FacilitatorServices};
+use crate::gas_monitor::GasMonitor;
+use crate::nonce_store::{InMemoryNonceStore, PaymentNonceStore, nonce_key, settled_key};
+use crate::payment_spans::PaymentSpans;
+#[cfg(feature = "webhooks")]
+use crate::payment_watch::PaymentWatcher;
 use crate::provider_cache::ProviderCache;
 use crate::provider_cache::ProviderMap;
+use crate::receipts::Receipts;
+use crate::refunds::Refunds;
+use crate::scheme::{SchemeHandler, SchemeRegistry};
+use crate::settle_idempotency::SettleIdempotency;
+use crate::settlement_caps::{self, SettlementCaps};
+use crate::settlement_events::SettlementEvents;
+use crate::settlement_pause::SettlementPause;
+use crate::settlement_queue::{SettleMode, SettlementQueue};
+use crate::settlement_stats::SettlementStats;
+use crate::storage::PaymentHistory;
+use crate::stream::StreamSessionManager;
+use crate::strict_settle::StrictSettle;
+use crate::timestamp::UnixTimestampMs;
 use crate::types::{
     Scheme, SettleRequest, SettleResponse, SupportedPaymentKind, SupportedPaymentKindExtra,
     SupportedPaymentKindsResponse, VerifyRequest, VerifyResponse, X402Version,
 };
+#[cfg(feature = "webhooks")]
+use crate::webhooks::Webhooks;
 
 /// A concrete [`Facilitator`] implementation that verifies and settles x402 payments
 /// using a network-aware provider cache.
@@ -28,6 +73,52 @@ use crate::types::{
 #[derive(Clone)]
 pub struct FacilitatorLocal {
     pub provider_cache: ProviderCache,
+    /// Handlers of payment schemes other than `exact`.
+    pub schemes: SchemeRegistry,
+    /// Fault injector consulted before every verification and settlement.
+    #[cfg(feature = "chaos")]
+    pub faults: FaultInjector,
+    /// Watcher of inbound payments, serving `x402.watchPayments` subscriptions.
+    #[cfg(feature = "webhooks")]
+    pub payment_watcher: Option<PaymentWatcher>,
+    /// Settlement lifecycle events, serving `x402.subscribe` subscriptions.
+    pub settlement_events: SettlementEvents,
+    /// Nonces of settled payments, rejected when presented again.
+    pub nonce_store: Arc<dyn PaymentNonceStore>,
+    /// Sessions of pay-per-slice streams, serving the `stream.*` WS methods.
+    pub streams: StreamSessionManager,
+    /// Verification spans, linked to from the settlement spans of the same payment.
+    pub payment_spans: PaymentSpans,
+    /// How `x402.settle` settles payments.
+    pub settle_mode: SettleMode,
+    /// Payable resources registered by resource servers.
+    pub discovery: DiscoveryRegistry,
+    /// Caps on settled amounts, enforced before broadcasting.
+    pub caps: SettlementCaps,
+    /// Verifications of the payments to settle, required recent if strict.
+    pub strict_settle: StrictSettle,
+    /// Responses of settlements by idempotency key, answering retried settlements.
+    pub idempotency: SettleIdempotency,
+    /// Statistics of settlements, serving `/stats`, if collected.
+    pub stats: Option<SettlementStats>,
+    /// History of verified and settled payments, serving `/payments`, if recorded.
+    pub history: Option<PaymentHistory>,
+    /// Receipts of settled payments, returned with their settle responses, if signed.
+    pub receipts: Option<Receipts>,
+    /// Refunds of settled payments and their ledger, serving `/refund`, if enabled.
+    pub refunds: Option<Refunds>,
+    /// Prepaid balances of repeat buyers, serving the `balance.*` WS methods and `/balance`.
+    pub balances: BalanceLedger,
+    /// Networks not served, whatever their providers.
+    pub disabled_networks: DisabledNetworks,
+    /// Networks whose settlements are paused, refused until resumed.
+    pub settlement_pause: SettlementPause,
+    /// Gas of the settlement accounts, reported by `/supported`, refusing settlements below the
+    /// floor, if monitored.
+    pub gas_monitor: Option<GasMonitor>,
+    /// Webhooks notified of verified, settled and failed payments, if configured.
+    #[cfg(feature = "webhooks")]
+    pub webhooks: Option<Webhooks>,
 }
 
 impl FacilitatorLocal {
@@ -35,30 +126,432 @@ impl FacilitatorLocal {
     ///
     /// The provider cache is used to resolve the appropriate EVM provider for each payment's target network.
     pub fn new(provider_cache: ProviderCache) -> Self {
-        FacilitatorLocal { provider_cache }
+        FacilitatorLocal {
+            provider_cache,
+            schemes: SchemeRegistry::new(),
+            #[cfg(feature = "chaos")]
+            faults: FaultInjector::default(),
+            #[cfg(feature = "webhooks")]
+            payment_watcher: None,
+            settlement_events: SettlementEvents::new(),
+            nonce_store: Arc::new(InMemoryNonceStore::new()),
+            streams: StreamSessionManager::new(),
+            payment_spans: PaymentSpans::new(),
+            settle_mode: SettleMode::Immediate,
+            discovery: DiscoveryRegistry::new(),
+            caps: SettlementCaps::new(),
+            strict_settle: StrictSettle::new(),
+            idempotency: SettleIdempotency::new(),
+            stats: None,
+            history: None,
+            receipts: None,
+            refunds: None,
+            balances: BalanceLedger::new(),
+            disabled_networks: DisabledNetworks::new(),
+            settlement_pause: SettlementPause::new(),
+            gas_monitor: None,
+            #[cfg(feature = "webhooks")]
+            webhooks: None,
+        }
+    }
+
+    /// Records the nonces of settled payments in `store`, in place of the default
+    /// in-memory store.
+    pub fn with_nonce_store(&self, store: Arc<dyn PaymentNonceStore>) -> Self {
+        let mut this = self.clone();
+        this.nonce_store = store;
+        this
+    }
+
+    /// Settles EVM payments in Multicall3 batches, see [`multicall`](crate::chain::multicall).
+    /// Must be called from within a Tokio runtime.
+    pub fn with_settlement_batching(&self, batching: SettlementBatching) -> Self {
+        let mut this = self.clone();
+        this.provider_cache = self.provider_cache.with_settlement_batching(batching);
+        this
+    }
+
+    /// Settles `x402.settle` requests as `mode` says. The worker of a deferred queue must be
+    /// started separately, see [`SettlementQueue::start`].
+    pub fn with_settle_mode(&self, mode: SettleMode) -> Self {
+        let mut this = self.clone();
+        this.settle_mode = mode;
+        this
+    }
+
+    /// Keeps the payable resources registered by resource servers in `discovery`.
+    #[allow(dead_code)] // Public for consumption by downstream crates.
+    pub fn with_discovery(&self, discovery: DiscoveryRegistry) -> Self {
+        let mut this = self.clone();
+        this.discovery = discovery;
+        this
+    }
+
+    /// Rejects payments exceeding `caps`, see [`settlement_caps`](crate::settlement_caps).
+    pub fn with_settlement_caps(&self, caps: SettlementCaps) -> Self {
+        let mut this = self.clone();
+        this.caps = caps;
+        this
+    }
+
+    /// Refuses to settle payments not verified as `strict_settle` says, see
+    /// [`strict_settle`](crate::strict_settle).
+    pub fn with_strict_settle(&self, strict_settle: StrictSettle) -> Self {
+        let mut this = self.clone();
+        this.strict_settle = strict_settle;
+        this
+    }
+
+    /// Refuses payments on the networks of `disabled_networks`, see
+    /// [`disabled_networks`](crate::disabled_networks).
+    pub fn with_disabled_networks(&self, disabled_networks: DisabledNetworks) -> Self {
+        let mut this = self.clone();
+        this.disabled_networks = disabled_networks;
+        this
+    }
+
+    /// Remembers the responses of settlements by idempotency key in `idempotency`, see
+    /// [`settle_idempotency`](crate::settle_idempotency).
+    pub fn with_settle_idempotency(&self, idempotency: SettleIdempotency) -> Self {
+        let mut this = self.clone();
+        this.idempotency = idempotency;
+        this
+    }
+
+    /// Signs a receipt of every successful settlement with `receipts`, see
+    /// [`receipts`](crate::receipts).
+    pub fn with_receipts(&self, receipts: Receipts) -> Self {
+        let mut this = self.clone();
+        this.receipts = Some(receipts);
+        this
+    }
+
+    /// Refunds settled payments with `refunds`, see [`refunds`](crate::refunds).
+    pub fn with_refunds(&self, refunds: Refunds) -> Self {
+        let mut this = self.clone();
+        this.refunds = Some(refunds);
+        this
+    }
+
+    /// Records every settlement in `stats`, see [`settlement_stats`](crate::settlement_stats).
+    pub fn with_settlement_stats(&self, stats: SettlementStats) -> Self {
+        let mut this = self.clone();
+        this.stats = Some(stats);
+        this
+    }
+
+    /// Records every verification and settlement in `history`, see [`storage`](crate::storage).
+    pub fn with_payment_history(&self, history: PaymentHistory) -> Self {
+        let mut this = self.clone();
+        this.history = Some(history);
+        this
+    }
+
+    /// Refuses to settle on the networks whose settlement accounts are below the gas floor of
+    /// `monitor`, and reports their balances in `/supported`. The monitor is started separately,
+    /// see [`GasMonitor::start`].
+    pub fn with_gas_monitor(&self, monitor: GasMonitor) -> Self {
+        let mut this = self.clone();
+        this.gas_monitor = Some(monitor);
+        this
+    }
+
+    /// Publishes settlement lifecycle events to `events`.
+    pub fn with_settlement_events(&self, events: SettlementEvents) -> Self {
+        let mut this = self.clone();
+        this.settlement_events = events;
+        this
+    }
+
+    /// Tracks the sessions of pay-per-slice streams in `streams`.
+    #[allow(dead_code)] // Public for consumption by downstream crates.
+    pub fn with_stream_sessions(&self, streams: StreamSessionManager) -> Self {
+        let mut this = self.clone();
+        this.streams = streams;
+        this
+    }
+
+    /// Keeps the prepaid balances of repeat buyers in `balances`, see [`balance`](crate::balance).
+    #[allow(dead_code)] // Public for consumption by downstream crates.
+    pub fn with_balances(&self, balances: BalanceLedger) -> Self {
+        let mut this = self.clone();
+        this.balances = balances;
+        this
+    }
+
+    /// Links settlement spans to the verification spans recorded in `spans`.
+    #[allow(dead_code)] // Public for consumption by downstream crates.
+    pub fn with_payment_spans(&self, spans: PaymentSpans) -> Self {
+        let mut this = self.clone();
+        this.payment_spans = spans;
+        this
+    }
+
+    /// Serves `x402.watchPayments` subscriptions from `watcher`. The watcher must be started
+    /// separately, see [`PaymentWatcher::start`].
+    #[cfg(feature = "webhooks")]
+    pub fn with_payment_watcher(&self, watcher: PaymentWatcher) -> Self {
+        let mut this = self.clone();
+        this.payment_watcher = Some(watcher);
+        this
+    }
+
+    /// Notifies `webhooks` of verified, settled and failed payments. Closed stream sessions are
+    /// reported separately, see [`Webhooks::watch_streams`].
+    #[cfg(feature = "webhooks")]
+    pub fn with_webhooks(&self, webhooks: Webhooks) -> Self {
+        let mut this = self.clone();
+        this.webhooks = Some(webhooks);
+        this
+    }
+
+    /// Registers a backend for a chain not natively supported, see [`ChainProvider`].
+    ///
+    /// Payments on its network are verified and settled by the backend, in place of any
+    /// provider configured from the environment for that network.
+    #[allow(dead_code)] // Public for consumption by downstream crates.
+    pub fn with_chain_provider<P: ChainProvider + 'static>(&self, provider: P) -> Self {
+        let mut this = self.clone();
+        this.provider_cache = self.provider_cache.with_chain_provider(provider);
+        this
+    }
+
+    /// Registers a handler for a custom payment scheme.
+    ///
+    /// Payments of that scheme are verified and settled by the handler, and its payment kinds
+    /// are advertised alongside the native ones.
+    #[allow(dead_code)] // Public for consumption by downstream crates.
+    pub fn with_scheme<H: SchemeHandler + 'static>(&self, handler: H) -> Self {
+        let mut this = self.clone();
+        this.schemes.register(handler);
+        this
     }
 
     pub fn kinds(&self) -> Vec<SupportedPaymentKind> {
-        self.provider_cache
-            .into_iter()
-            .map(|(network, provider)| match provider {
-                NetworkProvider::Evm(_) => SupportedPaymentKind {
-                    x402_version: X402Version::V1,
-                    scheme: Scheme::Exact,
-                    network: *network,
-                    extra: None,
-                },
-                NetworkProvider::Solana(provider) => SupportedPaymentKind {
-                    x402_version: X402Version::V1,
-                    scheme: Scheme::Exact,
-                    network: *network,
-                    extra: Some(SupportedPaymentKindExtra {
-                        fee_payer: provider.signer_address(),
-                    }),
-                },
-            })
+        let native =
+            self.provider_cache
+                .into_iter()
+                .flat_map(|(network, provider)| match provider {
+                    NetworkProvider::Evm(provider) => vec![
+                        SupportedPaymentKind {
+                            x402_version: X402Version::V1,
+                            scheme: Scheme::Exact,
+                            network: *network,
+                            extra: None,
+                        },
+                        SupportedPaymentKind {
+                            x402_version: X402Version::V1,
+                            scheme: Scheme::Permit,
+                            network: *network,
+                            extra: Some(SupportedPaymentKindExtra {
+                                fee_payer: provider.signer_address(),
+                            }),
+                        },
+                        SupportedPaymentKind {
+                            x402_version: X402Version::V1,
+                            scheme: Scheme::Upto,
+                            network: *network,
+                            extra: Some(SupportedPaymentKindExtra {
+                                fee_payer: provider.signer_address(),
+                            }),
+                        },
+                    ],
+                    NetworkProvider::Solana(provider) => vec![SupportedPaymentKind {
+                        x402_version: X402Version::V1,
+                        scheme: Scheme::Exact,
+                        network: *network,
+                        extra: Some(SupportedPaymentKindExtra {
+                            fee_payer: provider.signer_address(),
+                        }),
+                    }],
+                    NetworkProvider::Custom(provider) => provider.kinds(),
+                });
+        native
+            .chain(self.schemes.kinds())
+            .filter(|kind| self.disabled_networks.is_enabled(kind.network))
             .collect()
     }
+
+    /// Settles `request` and signs its receipt, publishing its progress and recording it in the
+    /// statistics, the payment history, the payment watcher and the webhooks. Not called for
+    /// settlements replayed by idempotency key.
+    async fn settle_observed(
+        &self,
+        request: &SettleRequest,
+    ) -> Result<SettleResponse, FacilitatorLocalError> {
+        let payment_id = request.payment_payload.id();
+        if let Some(payment_id) = &payment_id {
+            self.payment_spans.settling(payment_id);
+        }
+        let observed_id = payment_id.filter(|_| self.settlement_events.is_observed());
+        if let Some(payment_id) = &observed_id {
+            self.settlement_events.submitted(payment_id, request);
+        }
+        let submitted_at = Instant::now();
+        let started_at = UnixTimestampMs::now();
+        let mut result = self
+            .settle_payment(request)
+            .await
+            .map(|response| SettleResponse {
+                metadata: request.metadata.clone(),
+                ..response
+            });
+        if let (Ok(response), Some(receipts)) = (&mut result, &self.receipts) {
+            match receipts.issue(request, response).await {
+                Ok(receipt) => response.receipt = receipt,
+                Err(error) => tracing::warn!(%error, "Can not sign the settlement receipt"),
+            }
+        }
+        if let Some(payment_id) = &observed_id {
+            self.settlement_events
+                .settled(payment_id, request, &result, &self.provider_cache);
+        }
+        if let Some(stats) = &self.stats {
+            stats.record(request, &result, submitted_at.elapsed());
+        }
+        if let Some(history) = &self.history {
+            history.settled(request, &result, started_at);
+        }
+        #[cfg(feature = "webhooks")]
+        if let (Some(watcher), Ok(response)) = (&self.payment_watcher, &result)
+            && let (Some(transaction), Some(metadata)) = (&response.transaction, &response.metadata)
+        {
+            watcher.annotate(transaction.clone(), metadata.clone());
+        }
+        #[cfg(feature = "webhooks")]
+        if let Some(webhooks) = &self.webhooks {
+            webhooks.payment_settled(request, &result);
+        }
+        result
+    }
+
+    /// Settles `request` if verified as [`FacilitatorLocal::strict_settle`] requires and its network
+    /// is not paused, reserving its nonce for the duration of the settlement, and for good if it
+    /// succeeds, and its amount under the settlement caps unless the settlement is known to have
+    /// moved nothing.
+    async fn settle_payment(
+        &self,
+        request: &SettleRequest,
+    ) -> Result<SettleResponse, FacilitatorLocalError> {
+        #[cfg(feature = "chaos")]
+        if let Some(error) = self.faults.settle_fault(request).await {
+            return Err(error);
+        }
+        self.disabled_networks.check(request.network())?;
+        self.settlement_pause.check(request.network())?;
+        if let Some(gas_monitor) = &self.gas_monitor {
+            gas_monitor.check(request.network())?;
+        }
+        self.strict_settle.check(request)?;
+        let settling = self.with_settled_amount(request).await?;
+        let nonce = nonce_key(&settling);
+        if let Some((key, payer)) = &nonce
+            && !self.nonce_store.reserve(key).await?
+        {
+            return Err(FacilitatorLocalError::NonceReused(payer.clone()));
+        }
+        let result = match self.caps.reserve(request) {
+            Ok(reservation) => {
+                let result = self.settle_unchecked(&settling).await;
+                if let Some(reservation) = reservation
+                    && settlement_caps::is_unsettled(&result)
+                {
+                    self.caps.release(reservation);
+                }
+                result
+            }
+            Err(error) => Err(error),
+        };
+        if matches!(&result, Ok(response) if response.success) {
+            self.strict_settle.forget(request);
+            if let Some(key) = settled_key(&settling) {
+                let requirements = &settling.payment_requirements;
+                let consumed = requirements
+                    .consumed_amount
+                    .unwrap_or(requirements.max_amount_required);
+                if let Err(error) = self.nonce_store.add_settled(&key, consumed).await {
+                    tracing::warn!(%error, "Failed to record the amount settled of an authorization");
+                }
+            }
+        } else if let Some((key, _)) = &nonce
+            && let Err(error) = self.nonce_store.release(key).await
+        {
+            tracing::warn!(%error, "Failed to release the nonce of a failed settlement");
+        }
+        result
+    }
+
+    /// `request` with the `settledAmount` of its `upto` authorization replaced by the amount
+    /// recorded by the nonce store, so that neither its nonce key nor the amount left to settle
+    /// depend on the seller. Other payments are borrowed unchanged.
+    async fn with_settled_amount<'a>(
+        &self,
+        request: &'a VerifyRequest,
+    ) -> Result<Cow<'a, VerifyRequest>, FacilitatorLocalError> {
+        let Some(key) = settled_key(request) else {
+            return Ok(Cow::Borrowed(request));
+        };
+        let settled = self.nonce_store.settled(&key).await?;
+        let mut request = request.clone();
+        request.payment_requirements.settled_amount = (!settled.0.is_zero()).then_some(settled);
+        Ok(Cow::Owned(request))
+    }
+
+    /// Settles `request` with its scheme handler or network provider.
+    async fn settle_unchecked(
+        &self,
+        request: &SettleRequest,
+    ) -> Result<SettleResponse, FacilitatorLocalError> {
+        if let Some(handler) = self.schemes.get(&request.payment_payload.scheme) {
+            return handler.settle(request).await;
+        }
+        let network = request.network();
+        let provider = self
+            .provider_cache
+            .by_network(network)
+            .ok_or(FacilitatorLocalError::UnsupportedNetwork(None))?;
+        provider.settle(request).await
+    }
+
+    /// Verifies `request` with its scheme handler or network provider, unless its network is
+    /// disabled, its nonce was settled already, or its amount is over the caps. Valid payments are
+    /// recorded for strict settlement and notified to the webhooks.
+    async fn verify_payment(
+        &self,
+        request: &VerifyRequest,
+    ) -> Result<VerifyResponse, FacilitatorLocalError> {
+        #[cfg(feature = "chaos")]
+        if let Some(error) = self.faults.verify_fault(request) {
+            return Err(error);
+        }
+        self.disabled_networks.check(request.network())?;
+        let verifying = self.with_settled_amount(request).await?;
+        if let Some((key, payer)) = nonce_key(&verifying)
+            && self.nonce_store.contains(&key).await?
+        {
+            return Err(FacilitatorLocalError::NonceReused(payer));
+        }
+        self.caps.check(request)?;
+        let response = match self.schemes.get(&request.payment_payload.scheme) {
+            Some(handler) => handler.verify(&verifying).await?,
+            None => {
+                let network = request.network();
+                let provider = self
+                    .provider_cache
+                    .by_network(network)
+                    .ok_or(FacilitatorLocalError::UnsupportedNetwork(None))?;
+                provider.verify(&verifying).await?
+            }
+        };
+        if let VerifyResponse::Valid { .. } = response {
+            self.strict_settle.record(request);
+        }
+        #[cfg(feature = "webhooks")]
+        if let (Some(webhooks), VerifyResponse::Valid { payer, .. }) = (&self.webhooks, &response) {
+            webhooks.payment_verified(request, payer);
+        }
+        Ok(response)
+    }
 }
 
 impl Facilitator for FacilitatorLocal {
@@ -79,15 +572,26 @@ impl Facilitator for FacilitatorLocal {
     /// - invalid signature,
     /// - expired or future-dated timing,
     /// - insufficient funds,
-    /// - unsupported network.
-    #[instrument(skip_all, err, fields(network = %request.payment_payload.network))]
+    /// - amount over the per-settlement cap,
+    /// - unsupported or disabled network.
+    ///
+    /// The span is tagged with the payment id, and recorded in [`FacilitatorLocal::payment_spans`].
+    /// Valid payments are recorded in [`FacilitatorLocal::strict_settle`], and every verification
+    /// in the [`FacilitatorLocal::history`].
+    #[instrument(skip_all, err, fields(
+        network = %request.payment_payload.network,
+        x402.payment_id = tracing::field::Empty,
+    ))]
     async fn verify(&self, request: &VerifyRequest) -> Result<VerifyResponse, Self::Error> {
-        let network = request.network();
-        let provider = self
-            .provider_cache
-            .by_network(network)
-            .ok_or(FacilitatorLocalError::UnsupportedNetwork(None))?;
-        provider.verify(request).await
+        if let Some(payment_id) = request.payment_payload.id() {
+            self.payment_spans.verified(&payment_id);
+        }
+        let started_at = UnixTimestampMs::now();
+        let result = self.verify_payment(request).await;
+        if let Some(history) = &self.history {
+            history.verified(request, &result, started_at);
+        }
+        result
     }
 
     /// Executes an x402 payment on-chain using ERC-3009 `transferWithAuthorization`.
@@ -101,18 +605,101 @@ impl Facilitator for FacilitatorLocal {
     ///
     /// Returns [`FacilitatorLocalError`] if validation or contract call fails. Transaction receipt is included
     /// in the response on success or failure.
-    #[instrument(skip_all, err, fields(network = %request.payment_payload.network))]
+    ///
+    /// Progress of the settlement is published to [`FacilitatorLocal::settlement_events`].
+    /// The request metadata is echoed in the response, the settlement events, and the
+    /// inbound payment webhooks. Successful settlements carry a signed receipt, if
+    /// [`FacilitatorLocal::receipts`] are enabled.
+    ///
+    /// The span is tagged with the payment id, and linked to the last verification span of the
+    /// same payment, if any.
+    ///
+    /// A request carrying an idempotency key settled before is answered with the original
+    /// response, see [`FacilitatorLocal::idempotency`].
+    #[instrument(skip_all, err, fields(
+        network = %request.payment_payload.network,
+        x402.payment_id = tracing::field::Empty,
+    ))]
     async fn settle(&self, request: &SettleRequest) -> Result<SettleResponse, Self::Error> {
-        let network = request.network();
-        let provider = self
-            .provider_cache
-            .by_network(network)
-            .ok_or(FacilitatorLocalError::UnsupportedNetwork(None))?;
-        provider.settle(request).await
+        self.idempotency
+            .settle(request, || self.settle_observed(request))
+            .await
     }
 
     async fn supported(&self) -> Result<SupportedPaymentKindsResponse, Self::Error> {
         let kinds = self.kinds();
-        Ok(SupportedPaymentKindsResponse { kinds })
+        Ok(SupportedPaymentKindsResponse {
+            kinds,
+            extensions: Vec::new(),
+            gas: self
+                .gas_monitor
+                .as_ref()
+                .map(GasMonitor::balances)
+                .unwrap_or_default(),
+        })
+    }
+}
+
+impl FacilitatorServices for FacilitatorLocal {
+    fn settlement_events(&self) -> Option<&SettlementEvents> {
+        Some(&self.settlement_events)
+    }
+
+    fn stream_sessions(&self) -> Option<&StreamSessionManager> {
+        Some(&self.streams)
+    }
+
+    fn settlement_queue(&self) -> Option<&SettlementQueue> {
+        self.settle_mode.queue()
+    }
+
+    fn provider_cache(&self) -> Option<&ProviderCache> {
+        Some(&self.provider_cache)
+    }
+
+    fn nonce_store(&self) -> Option<&dyn PaymentNonceStore> {
+        Some(self.nonce_store.as_ref())
+    }
+
+    fn discovery(&self) -> Option<&DiscoveryRegistry> {
+        Some(&self.discovery)
+    }
+
+    fn settlement_stats(&self) -> Option<&SettlementStats> {
+        self.stats.as_ref()
+    }
+
+    fn payment_history(&self) -> Option<&PaymentHistory> {
+        self.history.as_ref()
+    }
+
+    fn receipts(&self) -> Option<&Receipts> {
+        self.receipts.as_ref()
+    }
+
+    fn refunds(&self) -> Option<&Refunds> {
+        self.refunds.as_ref()
+    }
+
+    fn balances(&self) -> Option<&BalanceLedger> {
+        Some(&self.balances)
+    }
+
+    fn strict_settle(&self) -> Option<&StrictSettle> {
+        Some(&self.strict_settle)
+    }
+
+    fn settlement_pause(&self) -> Option<&SettlementPause> {
+        Some(&self.settlement_pause)
+    }
+
+    #[cfg(feature = "webhooks")]
+    fn payment_watcher(&self) -> Option<&PaymentWatcher> {
+        self.payment_watcher.as_ref()
+    }
+
+    #[cfg(feature = "chaos")]
+    fn faults(&self) -> Option<&FaultInjector> {
+        Some(&self.faults)
     }
 }
@@ -0,0 +1,293 @@
+//! Latency-aware selection among multiple facilitators.
+//!
+//! [`FacilitatorPool`] wraps several [`Facilitator`]s (e.g. remote facilitator clients) and routes
+//! every request to the best performing one. It continuously measures the latency and error rate
+//! of `verify` calls as exponentially weighted moving averages, and combines them into a score.
+//!
+//! - Requests go to the _active_ facilitator. Settlements never leave it, so a payment is only
+//!   ever settled once.
+//! - Every [`with_probe_interval`](FacilitatorPool::with_probe_interval)-th verification goes to
+//!   another facilitator instead (round-robin), keeping the measurements of the standby
+//!   facilitators fresh. Verification has no side effects, so probing is safe.
+//! - The active facilitator is only replaced when another one scores better by more than the
+//!   [`hysteresis`](FacilitatorPool::with_hysteresis) margin, which avoids flapping between
+//!   facilitators with similar performance.
+//!
+//! Every switch is reported as a tracing event, and every request records the selected
+//! facilitator on the `x402.facilitator_pool.*` spans.
+//!
+//! ```rust,no_run
+//! use x402_rs::facilitator_pool::FacilitatorPool;
+//! # use x402_rs::facilitator_local::FacilitatorLocal;
+//! # fn facilitators() -> (FacilitatorLocal, FacilitatorLocal) { unimplemented!() }
+//!
+//! let (primary, secondary) = facilitators();
+//! let pool = FacilitatorPool::new([("primary", primary), ("secondary", secondary)])
+//!     .with_hysteresis(0.25);
+//! ```
+
+use std::fmt::Debug;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tracing::Instrument;
+
+use crate::facilitator::Facilitator;
+use crate::types::{
+    SettleRequest, SettleResponse, SupportedPaymentKindsResponse, VerifyRequest, VerifyResponse,
+};
+
+/// Penalty added to the latency score per unit of error rate, in milliseconds:
+/// a facilitator failing every request scores as if it took 5 seconds to respond.
+const ERROR_PENALTY_MS: f64 = 5000.0;
+
+struct Member<F> {
+    name: String,
+    facilitator: F,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct MemberStats {
+    /// Moving average of the verify latency, in milliseconds.
+    latency_ms: f64,
+    /// Moving average of the error rate, from `0.0` to `1.0`.
+    error_rate: f64,
+    /// Number of measured requests.
+    samples: u64,
+}
+
+impl MemberStats {
+    fn record(&mut self, latency: Duration, is_error: bool, smoothing: f64) {
+        let latency_ms = latency.as_secs_f64() * 1000.0;
+        let error = if is_error { 1.0 } else { 0.0 };
+        if self.samples == 0 {
+            self.latency_ms = latency_ms;
+            self.error_rate = error;
+        } else {
+            self.latency_ms += smoothing * (latency_ms - self.latency_ms);
+            self.error_rate += smoothing * (error - self.error_rate);
+        }
+        self.samples += 1;
+    }
+
+    /// Lower is better.
+    fn score(&self) -> f64 {
+        self.latency_ms + ERROR_PENALTY_MS * self.error_rate
+    }
+}
+
+#[derive(Debug)]
+struct PoolState {
+    active: usize,
+    stats: Vec<MemberStats>,
+    /// Number of verifications routed so far, used to schedule probes.
+    requests: u64,
+    /// Last probed standby member.
+    last_probe: usize,
+}
+
+/// Performance of a single facilitator of a [`FacilitatorPool`], see [`FacilitatorPool::stats`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct FacilitatorStats {
+    pub name: String,
+    /// Moving average of the verify latency.
+    pub latency: Duration,
+    /// Moving average of the error rate, from `0.0` to `1.0`.
+    pub error_rate: f64,
+    /// Number of measured requests.
+    pub samples: u64,
+    /// Whether the facilitator currently receives the traffic.
+    pub active: bool,
+}
+
+/// A [`Facilitator`] that routes requests to the best performing of several facilitators.
+pub struct FacilitatorPool<F> {
+    members: Arc<[Member<F>]>,
+    state: Arc<Mutex<PoolState>>,
+    /// Weight of the latest measurement in the moving averages.
+    smoothing: f64,
+    /// Relative score improvement required to switch the active facilitator.
+    hysteresis: f64,
+    /// Route every n-th verification to a standby facilitator. `0` disables probing.
+    probe_interval: u64,
+    /// Measurements required before a facilitator is considered for activation.
+    min_samples: u64,
+}
+
+impl<F> Clone for FacilitatorPool<F> {
+    fn clone(&self) -> Self {
+        Self {
+            members: self.members.clone(),
+            state: self.state.clone(),
+            smoothing: self.smoothing,
+            hysteresis: self.hysteresis,
+            probe_interval: self.probe_interval,
+            min_samples: self.min_samples,
+        }
+    }
+}
+
+impl<F> FacilitatorPool<F> {
+    /// Creates a pool of named facilitators. The first one is active initially.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `facilitators` is empty.
+    pub fn new<N: Into<String>, I: IntoIterator<Item = (N, F)>>(facilitators: I) -> Self {
+        let members: Arc<[Member<F>]> = facilitators
+            .into_iter()
+            .map(|(name, facilitator)| Member {
+                name: name.into(),
+                facilitator,
+            })
+            .collect();
+        assert!(
+            !members.is_empty(),
+            "FacilitatorPool requires at least one facilitator"
+        );
+        let state = PoolState {
+            active: 0,
+            stats: vec![MemberStats::default(); members.len()],
+            requests: 0,
+            last_probe: 0,
+        };
+        Self {
+            members,
+            state: Arc::new(Mutex::new(state)),
+            smoothing: 0.2,
+            hysteresis: 0.2,
+            probe_interval: 10,
+            min_samples: 3,
+        }
+    }
+
+    /// Sets the weight of the latest measurement in the moving averages, from `0.0` to `1.0`.
+    /// Defaults to `0.2`.
+    pub fn with_smoothing(&self, smoothing: f64) -> Self {
+        let mut this = self.clone();
+        this.smoothing = smoothing.clamp(0.0, 1.0);
+        this
+    }
+
+    /// Sets the relative score improvement (e.g. `0.2` for 20%) another facilitator needs
+    /// over the active one to take over. Defaults to `0.2`.
+    pub fn with_hysteresis(&self, hysteresis: f64) -> Self {
+        let mut this = self.clone();
+        this.hysteresis = hysteresis.max(0.0);
+        this
+    }
+
+    /// Routes every `interval`-th verification to a standby facilitator to measure it.
+    /// Defaults to `10`; `0` disables probing.
+    pub fn with_probe_interval(&self, interval: u64) -> Self {
+        let mut this = self.clone();
+        this.probe_interval = interval;
+        this
+    }
+
+    /// Name of the facilitator currently receiving the traffic.
+    pub fn active(&self) -> &str {
+        let active = self.state.lock().unwrap().active;
+        &self.members[active].name
+    }
+
+    /// Current measurements of all facilitators, in pool order.
+    pub fn stats(&self) -> Vec<FacilitatorStats> {
+        let state = self.state.lock().unwrap();
+        self.members
+            .iter()
+            .zip(state.stats.iter())
+            .enumerate()
+            .map(|(index, (member, stats))| FacilitatorStats {
+                name: member.name.clone(),
+                latency: Duration::from_secs_f64(stats.latency_ms / 1000.0),
+                error_rate: stats.error_rate,
+                samples: stats.samples,
+                active: index == state.active,
+            })
+            .collect()
+    }
+
+    /// Picks the facilitator for the next verification: the active one, or a standby to probe.
+    fn select_for_verify(&self) -> usize {
+        let mut state = self.state.lock().unwrap();
+        state.requests += 1;
+        let members = self.members.len();
+        if members > 1
+            && self.probe_interval > 0
+            && state.requests.is_multiple_of(self.probe_interval)
+        {
+            let mut probe = (state.last_probe + 1) % members;
+            if probe == state.active {
+                probe = (probe + 1) % members;
+            }
+            state.last_probe = probe;
+            return probe;
+        }
+        state.active
+    }
+
+    /// Records a measurement, and switches the active facilitator if another one
+    /// now performs better by more than the hysteresis margin.
+    fn record(&self, index: usize, latency: Duration, is_error: bool) {
+        let mut state = self.state.lock().unwrap();
+        state.stats[index].record(latency, is_error, self.smoothing);
+        let active = state.active;
+        let active_score = state.stats[active].score();
+        let best = state
+            .stats
+            .iter()
+            .enumerate()
+            .filter(|(i, stats)| *i != active && stats.samples >= self.min_samples)
+            .min_by(|(_, a), (_, b)| a.score().total_cmp(&b.score()));
+        if let Some((candidate, stats)) = best
+            && stats.score() < active_score * (1.0 - self.hysteresis)
+        {
+            tracing::info!(
+                from = %self.members[active].name,
+                to = %self.members[candidate].name,
+                from_score = active_score,
+                to_score = stats.score(),
+                "Switched active facilitator"
+            );
+            state.active = candidate;
+        }
+    }
+}
+
+impl<F> Facilitator for FacilitatorPool<F>
+where
+    F: Facilitator + Send + Sync,
+    F::Error: Send,
+{
+    type Error = F::Error;
+
+    async fn verify(&self, request: &VerifyRequest) -> Result<VerifyResponse, Self::Error> {
+        let index = self.select_for_verify();
+        let member = &self.members[index];
+        let span = tracing::info_span!("x402.facilitator_pool.verify", facilitator = %member.name);
+        let started = Instant::now();
+        let result = member.facilitator.verify(request).instrument(span).await;
+        self.record(index, started.elapsed(), result.is_err());
+        result
+    }
+
+    async fn settle(&self, request: &SettleRequest) -> Result<SettleResponse, Self::Error> {
+        let index = self.state.lock().unwrap().active;
+        let member = &self.members[index];
+        let span = tracing::info_span!("x402.facilitator_pool.settle", facilitator = %member.name);
+        member.facilitator.settle(request).instrument(span).await
+    }
+
+    async fn supported(&self) -> Result<SupportedPaymentKindsResponse, Self::Error> {
+        let index = self.state.lock().unwrap().active;
+        self.members[index].facilitator.supported().await
+    }
+}
+
+impl<F> Debug for FacilitatorPool<F> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("FacilitatorPool")
+            .field("stats", &self.stats())
+            .finish()
+    }
+}
@@ -0,0 +1,162 @@
+use reqwest::header::HeaderMap;
+use reqwest::{Client, RequestBuilder, StatusCode};
+use serde::de::DeserializeOwned;
+use std::time::Duration;
+use url::Url;
+
+use crate::chain::FacilitatorLocalError;
+use crate::facilitator::{Facilitator, FacilitatorServices};
+use crate::types::{
+    SettleRequest, SettleResponse, SupportedPaymentKindsResponse, VerifyRequest, VerifyResponse,
+};
+
+/// Errors that can occur while interacting with a facilitator over HTTP.
+#[derive(Debug, thiserror::Error)]
+pub enum HttpClientError {
+    #[error("URL parse error: {0}")]
+    UrlParse(#[from] url::ParseError),
+    #[error("HTTP error: {0}")]
+    Http(#[from] reqwest::Error),
+    #[error("Unexpected HTTP status {status}: {body}")]
+    UnexpectedStatus { status: StatusCode, body: String },
+    #[error("Invalid response of {endpoint}: {source}")]
+    InvalidResponse {
+        endpoint: &'static str,
+        #[source]
+        source: serde_json::Error,
+    },
+    /// The facilitator refused to settle the payment, for the reason given.
+    #[error("Payment rejected by the facilitator")]
+    Rejected(Box<VerifyResponse>),
+}
+
+impl From<HttpClientError> for FacilitatorLocalError {
+    fn from(error: HttpClientError) -> Self {
+        match error {
+            HttpClientError::Rejected(response) => {
+                FacilitatorLocalError::Upstream("Payment rejected".to_string(), Some(response))
+            }
+            error => FacilitatorLocalError::Upstream(error.to_string(), None),
+        }
+    }
+}
+
+/// A client for a remote x402 facilitator, over its HTTP endpoints.
+///
+/// Cheap to clone: clones share the connection pool.
+#[derive(Clone, Debug)]
+pub struct FacilitatorHttpClient {
+    #[allow(dead_code)] // Public for consumption by downstream crates.
+    base_url: Url,
+    verify_url: Url,
+    settle_url: Url,
+    supported_url: Url,
+    client: Client,
+    /// Headers sent with every request, e.g. an API key
+    headers: HeaderMap,
+    timeout: Option<Duration>,
+}
+
+impl FacilitatorHttpClient {
+    /// Constructs a client of the facilitator at `base_url`, its endpoints being relative to it.
+    pub fn try_new(base_url: Url) -> Result<Self, HttpClientError> {
+        Ok(Self {
+            verify_url: base_url.join("./verify")?,
+            settle_url: base_url.join("./settle")?,
+            supported_url: base_url.join("./supported")?,
+            base_url,
+            client: Client::new(),
+            headers: HeaderMap::new(),
+            timeout: None,
+        })
+    }
+
+    /// Base URL of the facilitator.
+    #[allow(dead_code)] // Public for consumption by downstream crates.
+    pub fn base_url(&self) -> &Url {
+        &self.base_url
+    }
+
+    /// Sends `headers` with every request.
+    #[allow(dead_code)] // Public for consumption by downstream crates.
+    pub fn with_headers(&self, headers: HeaderMap) -> Self {
+        let mut this = self.clone();
+        this.headers = headers;
+        this
+    }
+
+    /// Fails requests not answered within `timeout`.
+    #[allow(dead_code)] // Public for consumption by downstream crates.
+    pub fn with_timeout(&self, timeout: Duration) -> Self {
+        let mut this = self.clone();
+        this.timeout = Some(timeout);
+        this
+    }
+
+    async fn send<R: DeserializeOwned>(
+        &self,
+        request: RequestBuilder,
+        endpoint: &'static str,
+    ) -> Result<R, HttpClientError> {
+        let mut request = request.headers(self.headers.clone());
+        if let Some(timeout) = self.timeout {
+            request = request.timeout(timeout);
+        }
+        let response = request.send().await?;
+        let status = response.status();
+        let body = response.text().await?;
+        if status != StatusCode::OK {
+            return Err(HttpClientError::UnexpectedStatus { status, body });
+        }
+        serde_json::from_str(&body)
+            .map_err(|source| HttpClientError::InvalidResponse { endpoint, source })
+    }
+}
+
+impl Facilitator for FacilitatorHttpClient {
+    type Error = HttpClientError;
+
+    /// Sends a `POST /verify` request.
+    async fn verify(&self, request: &VerifyRequest) -> Result<VerifyResponse, Self::Error> {
+        let post = self.client.post(self.verify_url.clone()).json(request);
+        self.send(post, "POST /verify").await
+    }
+
+    /// Sends a `POST /settle` request. Payments the facilitator refuses to settle are
+    /// [`HttpClientError::Rejected`] errors.
+    async fn settle(&self, request: &SettleRequest) -> Result<SettleResponse, Self::Error> {
+        let post = self.client.post(self.settle_url.clone()).json(request);
+        // A refused settlement is answered with the verification result instead.
+        let response: serde_json::Value = self.send(post, "POST /settle").await?;
+        match serde_json::from_value::<SettleResponse>(response.clone()) {
+            Ok(settle) => Ok(settle),
+            Err(source) => match serde_json::from_value::<VerifyResponse>(response) {
+                Ok(rejection) => Err(HttpClientError::Rejected(Box::new(rejection))),
+                Err(_) => Err(HttpClientError::InvalidResponse {
+                    endpoint: "POST /settle",
+                    source,
+                }),
+            },
+        }
+    }
+
+    /// Sends a `GET /supported` request.
+    async fn supported(&self) -> Result<SupportedPaymentKindsResponse, Self::Error> {
+        let get = self.client.get(self.supported_url.clone());
+        self.send(get, "GET /supported").await
+    }
+}
+
+/// A remote facilitator only serves verification and settlement.
+impl FacilitatorServices for FacilitatorHttpClient {}
+
+/// Parses `value` as the base URL of the facilitator, with or without a trailing slash.
+impl TryFrom<&str> for FacilitatorHttpClient {
+    type Error = HttpClientError;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        let mut normalized = value.trim_end_matches('/').to_string();
+        normalized.push('/');
+        FacilitatorHttpClient::try_new(Url::parse(&normalized)?)
+    }
+}
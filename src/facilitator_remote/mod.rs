@@ -0,0 +1,35 @@
+//! Clients of a remote facilitator, implementing the [`Facilitator`](crate::facilitator::Facilitator) trait.
+//!
+//! - [`FacilitatorHttpClient`] calls the HTTP endpoints (`/verify`, `/settle`, `/supported`).
+//! - [`FacilitatorWsClient`] calls the WebSocket endpoint (`/ws`) over a single long-lived
+//!   connection, see `x402-ws-stream.md`.
+//!
+//! Both are drop-in replacements for an in-process facilitator: behind the `x402-axum` middleware,
+//! or served by the facilitator server itself, which then runs as a thin edge delegating
+//! verification and settlement to a central facilitator (`UPSTREAM_FACILITATOR_URL`).
+//! Their errors convert into [`FacilitatorLocalError::Upstream`](crate::chain::FacilitatorLocalError::Upstream),
+//! carrying the reason of rejected payments.
+//!
+//! ```rust,no_run
+//! use x402_rs::facilitator::Facilitator;
+//! use x402_rs::facilitator_remote::{FacilitatorHttpClient, FacilitatorWsClient};
+//!
+//! # async fn run() -> Result<(), Box<dyn std::error::Error>> {
+//! let http = FacilitatorHttpClient::try_from("https://facilitator.example/")?;
+//! let ws = FacilitatorWsClient::try_from("wss://facilitator.example/ws")?;
+//! assert_eq!(http.supported().await?.kinds.len(), ws.supported().await?.kinds.len());
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Only with the `remote` feature.
+
+mod http;
+mod reconnect;
+mod ws;
+
+#[allow(unused_imports)] // Public for consumption by downstream crates.
+pub use http::{FacilitatorHttpClient, HttpClientError};
+pub use reconnect::ReconnectPolicy;
+#[allow(unused_imports)] // Public for consumption by downstream crates.
+pub use ws::{FacilitatorWsClient, WsClientError, WsNotification};
@@ -0,0 +1,83 @@
+use std::time::Duration;
+
+/// How [`FacilitatorWsClient`](crate::facilitator_remote::FacilitatorWsClient) re-establishes a dropped connection.
+///
+/// While requests are pending, the client reconnects after an exponential backoff: the first
+/// retry waits `initial_backoff`, every next one twice as long, up to `max_backoff`. Once
+/// reconnected, requests left unanswered by the dropped connection are sent again, with the
+/// same envelope ids. Without pending requests, the client reconnects on the next request.
+///
+/// Retries stop once every pending request has timed out, or after `max_retries` in a row;
+/// pending requests then fail.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ReconnectPolicy {
+    initial_backoff: Duration,
+    max_backoff: Duration,
+    max_retries: Option<u32>,
+}
+
+impl Default for ReconnectPolicy {
+    fn default() -> Self {
+        Self {
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_secs(10),
+            max_retries: None,
+        }
+    }
+}
+
+impl ReconnectPolicy {
+    /// Never retries: requests in flight fail as soon as the connection drops.
+    #[allow(dead_code)] // Public for consumption by downstream crates.
+    pub fn disabled() -> Self {
+        Self {
+            max_retries: Some(0),
+            ..Self::default()
+        }
+    }
+
+    /// Sets the wait before the first retry (100 milliseconds by default).
+    #[allow(dead_code)] // Public for consumption by downstream crates.
+    pub fn with_initial_backoff(&self, initial_backoff: Duration) -> Self {
+        let mut this = *self;
+        this.initial_backoff = initial_backoff;
+        this
+    }
+
+    /// Sets the longest wait between two retries (10 seconds by default).
+    #[allow(dead_code)] // Public for consumption by downstream crates.
+    pub fn with_max_backoff(&self, max_backoff: Duration) -> Self {
+        let mut this = *self;
+        this.max_backoff = max_backoff;
+        this
+    }
+
+    /// Gives up after `max_retries` failed retries in a row (unbounded by default).
+    #[allow(dead_code)] // Public for consumption by downstream crates.
+    pub fn with_max_retries(&self, max_retries: u32) -> Self {
+        let mut this = *self;
+        this.max_retries = Some(max_retries);
+        this
+    }
+
+    /// Whether requests in flight survive a dropped connection.
+    pub(crate) fn is_enabled(&self) -> bool {
+        self.max_retries != Some(0)
+    }
+
+    /// Wait before retry `retry` (starting at 1), if still allowed.
+    pub(crate) fn backoff(&self, retry: u32) -> Option<Duration> {
+        if self
+            .max_retries
+            .is_some_and(|max_retries| retry > max_retries)
+        {
+            return None;
+        }
+        let factor = 2u32.saturating_pow(retry.saturating_sub(1));
+        Some(
+            self.initial_backoff
+                .saturating_mul(factor)
+                .min(self.max_backoff),
+        )
+    }
+}
@@ -0,0 +1,762 @@
+use futures_util::{SinkExt, StreamExt};
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use std::collections::BTreeMap;
+use std::fmt::{Debug, Formatter};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::Duration;
+use tokio::net::TcpStream;
+use tokio::sync::{broadcast, mpsc, oneshot};
+use tokio_tungstenite::tungstenite::client::IntoClientRequest;
+use tokio_tungstenite::tungstenite::http::HeaderValue;
+use tokio_tungstenite::tungstenite::http::header::SEC_WEBSOCKET_PROTOCOL;
+use tokio_tungstenite::tungstenite::{self, Message};
+use tokio_tungstenite::{MaybeTlsStream, WebSocketStream, connect_async};
+use url::Url;
+
+use crate::chain::FacilitatorLocalError;
+use crate::facilitator::{Facilitator, FacilitatorServices};
+use crate::facilitator_remote::ReconnectPolicy;
+use crate::retry::{CircuitBreaker, Overload, RetryPolicy};
+use crate::types::{
+    ConnectionError, SettleRequest, SettleResponse, SupportedPaymentKindsResponse, VerifyRequest,
+    VerifyResponse,
+};
+use crate::ws_codec::{WsCodec, WsCodecError, WsDeflate, WsFrame};
+
+/// Default time to wait for the connection to open, and for the reply to a request.
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+/// Notifications buffered per [`FacilitatorWsClient::notifications`] receiver.
+const NOTIFICATIONS_CAPACITY: usize = 256;
+
+type Socket = WebSocketStream<MaybeTlsStream<TcpStream>>;
+/// Requests awaiting their reply, keyed by envelope id.
+type Pending = Arc<Mutex<BTreeMap<u64, PendingRequest>>>;
+
+/// Errors that can occur while interacting with a facilitator over WebSocket.
+#[derive(Debug, thiserror::Error)]
+pub enum WsClientError {
+    #[error("URL parse error: {0}")]
+    UrlParse(#[from] url::ParseError),
+    #[error("WebSocket error: {0}")]
+    WebSocket(Arc<tungstenite::Error>),
+    #[error(transparent)]
+    Codec(#[from] WsCodecError),
+    #[error("Invalid result of {method}: {source}")]
+    InvalidResult {
+        method: String,
+        #[source]
+        source: serde_json::Error,
+    },
+    #[error("Connection to the facilitator closed before the reply")]
+    Disconnected,
+    #[error("No reply from the facilitator within {0:?}")]
+    Timeout(Duration),
+    /// Error envelope returned by the facilitator, see `x402-ws-stream.md`.
+    #[error("Facilitator error {code}: {message}")]
+    Rpc {
+        code: i64,
+        message: String,
+        data: Option<serde_json::Value>,
+    },
+    /// The facilitator turned away too many requests in a row: requests are not sent for now.
+    #[error("Facilitator overloaded, not sending requests for {0:?}")]
+    CircuitOpen(Duration),
+}
+
+impl From<tungstenite::Error> for WsClientError {
+    fn from(error: tungstenite::Error) -> Self {
+        WsClientError::WebSocket(Arc::new(error))
+    }
+}
+
+impl From<WsClientError> for FacilitatorLocalError {
+    fn from(error: WsClientError) -> Self {
+        let message = error.to_string();
+        let response = match error {
+            WsClientError::Rpc {
+                data: Some(data), ..
+            } => serde_json::from_value(data).ok(),
+            _ => None,
+        };
+        FacilitatorLocalError::Upstream(message, response.map(Box::new))
+    }
+}
+
+impl WsClientError {
+    /// Overload of the facilitator, if it turned the request away (`-32029`).
+    pub fn overload(&self) -> Option<Overload> {
+        match self {
+            WsClientError::Rpc { code, data, .. } => Overload::from_ws_error(*code, data.as_ref()),
+            _ => None,
+        }
+    }
+
+    /// Copy of a connection error, to fail every pending request with.
+    fn duplicate(&self) -> Self {
+        match self {
+            WsClientError::WebSocket(error) => WsClientError::WebSocket(error.clone()),
+            WsClientError::Timeout(timeout) => WsClientError::Timeout(*timeout),
+            _ => WsClientError::Disconnected,
+        }
+    }
+}
+
+/// Server-pushed notification, e.g. `x402.settlement` after `x402.subscribe`.
+#[derive(Debug, Clone, serde::Deserialize)]
+#[allow(dead_code)] // Public for consumption by downstream crates.
+pub struct WsNotification {
+    pub method: String,
+    #[serde(default)]
+    pub params: serde_json::Value,
+}
+
+#[derive(Serialize)]
+struct RequestEnvelope<'a, P: Serialize> {
+    id: u64,
+    method: &'a str,
+    params: &'a P,
+}
+
+/// Any envelope sent by the facilitator: a reply if it has an `id`, a notification otherwise.
+#[derive(serde::Deserialize)]
+struct IncomingEnvelope {
+    #[serde(default)]
+    id: Option<serde_json::Value>,
+    #[serde(default)]
+    method: Option<String>,
+    #[serde(default)]
+    params: serde_json::Value,
+    #[serde(default)]
+    result: serde_json::Value,
+    #[serde(default)]
+    error: Option<ErrorBody>,
+}
+
+#[derive(serde::Deserialize)]
+struct ErrorBody {
+    code: i64,
+    message: String,
+    #[serde(default)]
+    data: Option<serde_json::Value>,
+}
+
+struct PendingRequest {
+    /// Encoded request envelope, sent again after a reconnection.
+    frame: WsFrame,
+    /// Generation of the connection the request was last sent on.
+    sent_on: Option<u64>,
+    reply: oneshot::Sender<Result<serde_json::Value, WsClientError>>,
+}
+
+/// State shared by the clones of a client.
+struct Shared {
+    next_id: AtomicU64,
+    pending: Pending,
+    /// Wakes the connection task up on new requests. The task is spawned on the first request,
+    /// and ends once every clone of the client is dropped.
+    driver: OnceLock<mpsc::UnboundedSender<u64>>,
+    notifications: broadcast::Sender<WsNotification>,
+    connection_errors: broadcast::Sender<ConnectionError>,
+}
+
+impl Default for Shared {
+    fn default() -> Self {
+        Self {
+            next_id: AtomicU64::new(1),
+            pending: Arc::new(Mutex::new(BTreeMap::new())),
+            driver: OnceLock::new(),
+            notifications: broadcast::channel(NOTIFICATIONS_CAPACITY).0,
+            connection_errors: broadcast::channel(NOTIFICATIONS_CAPACITY).0,
+        }
+    }
+}
+
+/// A client for a remote x402 facilitator, over its WebSocket endpoint.
+///
+/// Clones share the same connection. Clients built with the `with_*` methods open their own.
+#[derive(Clone)]
+pub struct FacilitatorWsClient {
+    url: Url,
+    codec: WsCodec,
+    compression: Option<WsDeflate>,
+    timeout: Duration,
+    reconnect: ReconnectPolicy,
+    retry: RetryPolicy,
+    breaker: CircuitBreaker,
+    shared: Arc<Shared>,
+}
+
+impl Debug for FacilitatorWsClient {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("FacilitatorWsClient")
+            .field("url", &self.url)
+            .field("codec", &self.codec)
+            .field("compression", &self.compression)
+            .field("timeout", &self.timeout)
+            .field("reconnect", &self.reconnect)
+            .field("retry", &self.retry)
+            .finish_non_exhaustive()
+    }
+}
+
+impl FacilitatorWsClient {
+    /// Constructs a client of the WebSocket endpoint at `url`, e.g. `wss://facilitator.example/ws`.
+    ///
+    /// The connection is opened on the first request.
+    pub fn new(url: Url) -> Self {
+        Self {
+            url,
+            codec: WsCodec::default(),
+            compression: None,
+            timeout: DEFAULT_TIMEOUT,
+            reconnect: ReconnectPolicy::default(),
+            retry: RetryPolicy::default(),
+            breaker: CircuitBreaker::default(),
+            shared: Arc::new(Shared::default()),
+        }
+    }
+
+    /// URL of the WebSocket endpoint.
+    #[allow(dead_code)] // Public for consumption by downstream crates.
+    pub fn url(&self) -> &Url {
+        &self.url
+    }
+
+    /// Encoding of the envelopes.
+    #[allow(dead_code)] // Public for consumption by downstream crates.
+    pub fn codec(&self) -> WsCodec {
+        self.codec
+    }
+
+    /// Compression of the envelopes offered in the handshake, if any.
+    #[allow(dead_code)] // Public for consumption by downstream crates.
+    pub fn compression(&self) -> Option<WsDeflate> {
+        self.compression
+    }
+
+    /// Time to wait for the connection to open, and for the reply to a request.
+    #[allow(dead_code)] // Public for consumption by downstream crates.
+    pub fn timeout(&self) -> Duration {
+        self.timeout
+    }
+
+    /// How a dropped connection is re-established.
+    #[allow(dead_code)] // Public for consumption by downstream crates.
+    pub fn reconnect(&self) -> ReconnectPolicy {
+        self.reconnect
+    }
+
+    /// How requests turned away by an overloaded facilitator are retried.
+    #[allow(dead_code)] // Public for consumption by downstream crates.
+    pub fn retry(&self) -> RetryPolicy {
+        self.retry
+    }
+
+    /// Encodes envelopes with `codec`, negotiated in the handshake.
+    #[allow(dead_code)] // Public for consumption by downstream crates.
+    pub fn with_codec(&self, codec: WsCodec) -> Self {
+        Self {
+            codec,
+            shared: Arc::new(Shared::default()),
+            ..self.clone()
+        }
+    }
+
+    /// Offers to compress the envelopes in the handshake, see [`crate::ws_codec`]. If the
+    /// facilitator accepts, requests are compressed per `deflate`, and compressed replies
+    /// decompressed; otherwise envelopes are sent as they are.
+    #[allow(dead_code)] // Public for consumption by downstream crates.
+    pub fn with_compression(&self, deflate: WsDeflate) -> Self {
+        Self {
+            compression: Some(deflate),
+            shared: Arc::new(Shared::default()),
+            ..self.clone()
+        }
+    }
+
+    /// Sets the time to wait for the connection to open, and for the reply to a request
+    /// (30 seconds by default). Reconnections count towards the timeout of the requests
+    /// waiting for them.
+    #[allow(dead_code)] // Public for consumption by downstream crates.
+    pub fn with_timeout(&self, timeout: Duration) -> Self {
+        Self {
+            timeout,
+            shared: Arc::new(Shared::default()),
+            ..self.clone()
+        }
+    }
+
+    /// Sets how a dropped connection is re-established, see [`ReconnectPolicy`].
+    #[allow(dead_code)] // Public for consumption by downstream crates.
+    pub fn with_reconnect(&self, reconnect: ReconnectPolicy) -> Self {
+        Self {
+            reconnect,
+            shared: Arc::new(Shared::default()),
+            ..self.clone()
+        }
+    }
+
+    /// Sets how requests turned away by an overloaded facilitator are retried, see
+    /// [`RetryPolicy`].
+    #[allow(dead_code)] // Public for consumption by downstream crates.
+    pub fn with_retry(&self, retry: RetryPolicy) -> Self {
+        Self {
+            retry,
+            shared: Arc::new(Shared::default()),
+            ..self.clone()
+        }
+    }
+
+    /// Stops sending requests to the facilitator per `breaker`, see [`CircuitBreaker`].
+    #[allow(dead_code)] // Public for consumption by downstream crates.
+    pub fn with_circuit_breaker(&self, breaker: CircuitBreaker) -> Self {
+        Self {
+            breaker,
+            shared: Arc::new(Shared::default()),
+            ..self.clone()
+        }
+    }
+
+    /// Receives the notifications pushed by the facilitator from now on.
+    ///
+    /// Subscriptions (`x402.subscribe`, `x402.watchPayments`) are scoped to the connection:
+    /// they end when it drops, and must be renewed on the new connection.
+    #[allow(dead_code)] // Public for consumption by downstream crates.
+    pub fn notifications(&self) -> broadcast::Receiver<WsNotification> {
+        self.shared.notifications.subscribe()
+    }
+
+    /// Receives the connection-level problems reported by the facilitator from now on, e.g.
+    /// an upcoming shutdown or dropped notifications.
+    ///
+    /// They are pushed as `x402.error` notifications, delivered here instead of
+    /// [`notifications`](Self::notifications).
+    #[allow(dead_code)] // Public for consumption by downstream crates.
+    pub fn connection_errors(&self) -> broadcast::Receiver<ConnectionError> {
+        self.shared.connection_errors.subscribe()
+    }
+
+    /// Calls `method` with `params`, returning its result.
+    ///
+    /// If the connection drops before the reply, the request is sent again on the next
+    /// connection, with the same envelope id: non-idempotent methods may then run twice.
+    /// Settling a payment twice is harmless, the facilitator rejects reused nonces.
+    ///
+    /// Requests turned away by an overloaded facilitator (`-32029`) are retried per the
+    /// [`RetryPolicy`], with a new envelope id, and not sent while the [`CircuitBreaker`] is open.
+    pub async fn call<P: Serialize, R: DeserializeOwned>(
+        &self,
+        method: &str,
+        params: &P,
+    ) -> Result<R, WsClientError> {
+        self.retry
+            .run(
+                &self.breaker,
+                WsClientError::overload,
+                WsClientError::CircuitOpen,
+                || self.call_once(method, params),
+            )
+            .await
+    }
+
+    async fn call_once<P: Serialize, R: DeserializeOwned>(
+        &self,
+        method: &str,
+        params: &P,
+    ) -> Result<R, WsClientError> {
+        let id = self.shared.next_id.fetch_add(1, Ordering::Relaxed);
+        let frame = self.codec.encode(&RequestEnvelope { id, method, params })?;
+        let (reply, receiver) = oneshot::channel();
+        let pending = &self.shared.pending;
+        pending.lock().unwrap().insert(
+            id,
+            PendingRequest {
+                frame,
+                sent_on: None,
+                reply,
+            },
+        );
+        let driver = self.shared.driver.get_or_init(|| self.spawn_driver());
+        if driver.send(id).is_err() {
+            pending.lock().unwrap().remove(&id);
+            return Err(WsClientError::Disconnected);
+        }
+        let result = match tokio::time::timeout(self.timeout, receiver).await {
+            Ok(Ok(result)) => result?,
+            Ok(Err(_)) => return Err(WsClientError::Disconnected),
+            Err(_) => {
+                pending.lock().unwrap().remove(&id);
+                tracing::warn!(method, id, timeout = ?self.timeout, "Facilitator WS request timed out");
+                return Err(WsClientError::Timeout(self.timeout));
+            }
+        };
+        serde_json::from_value(result).map_err(|source| WsClientError::InvalidResult {
+            method: method.to_string(),
+            source,
+        })
+    }
+
+    fn spawn_driver(&self) -> mpsc::UnboundedSender<u64> {
+        let (requests, receiver) = mpsc::unbounded_channel();
+        let driver = Driver {
+            url: self.url.clone(),
+            codec: self.codec,
+            compression: self.compression,
+            deflate: None,
+            timeout: self.timeout,
+            reconnect: self.reconnect,
+            pending: self.shared.pending.clone(),
+            notifications: self.shared.notifications.clone(),
+            connection_errors: self.shared.connection_errors.clone(),
+            generation: 0,
+            failures: 0,
+        };
+        tokio::spawn(driver.run(receiver));
+        requests
+    }
+}
+
+/// Why a connection stopped being served.
+enum Closed {
+    /// Every clone of the client is gone.
+    ByClient,
+    /// The socket failed or was closed by the facilitator.
+    Dropped,
+}
+
+/// Background task owning the connection: opens it when requests are pending, sends them,
+/// dispatches what the facilitator sends, and reconnects per the [`ReconnectPolicy`].
+struct Driver {
+    url: Url,
+    codec: WsCodec,
+    /// Compression offered in the handshake.
+    compression: Option<WsDeflate>,
+    /// Compression of the current connection, if the facilitator accepted it.
+    deflate: Option<WsDeflate>,
+    timeout: Duration,
+    reconnect: ReconnectPolicy,
+    pending: Pending,
+    notifications: broadcast::Sender<WsNotification>,
+    connection_errors: broadcast::Sender<ConnectionError>,
+    /// Number of connections opened so far.
+    generation: u64,
+    /// Failed connection attempts and dropped connections since the last reply.
+    failures: u32,
+}
+
+impl Driver {
+    async fn run(mut self, mut requests: mpsc::UnboundedReceiver<u64>) {
+        loop {
+            while self.is_idle() {
+                if requests.recv().await.is_none() {
+                    return;
+                }
+            }
+            let Some(socket) = self.connect().await else {
+                continue;
+            };
+            self.generation += 1;
+            match self.serve(socket, &mut requests).await {
+                Closed::ByClient => return,
+                Closed::Dropped => {
+                    tracing::debug!(url = %self.url, "Facilitator WS connection dropped");
+                    if self.reconnect.is_enabled() {
+                        self.failures += 1;
+                    } else {
+                        self.fail_pending(&WsClientError::Disconnected);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Whether no request awaits a reply. Requests whose caller is gone are forgotten.
+    fn is_idle(&self) -> bool {
+        let mut pending = self.pending.lock().unwrap();
+        pending.retain(|_, request| !request.reply.is_closed());
+        pending.is_empty()
+    }
+
+    /// Opens a connection, retrying with backoff while requests are pending. Fails the
+    /// pending requests when giving up.
+    async fn connect(&mut self) -> Option<Socket> {
+        let mut last_error = WsClientError::Disconnected;
+        loop {
+            if self.failures > 0 {
+                match self.reconnect.backoff(self.failures) {
+                    Some(backoff) if !self.is_idle() => {
+                        tracing::info!(url = %self.url, retry = self.failures, ?backoff, "Reconnecting to the facilitator WS");
+                        tokio::time::sleep(backoff).await;
+                    }
+                    Some(_) => {
+                        self.failures = 0;
+                        return None;
+                    }
+                    None => {
+                        tracing::warn!(url = %self.url, error = %last_error, "Giving up connecting to the facilitator WS");
+                        self.fail_pending(&last_error);
+                        return None;
+                    }
+                }
+            }
+            match self.open().await {
+                Ok((socket, deflate)) => {
+                    self.deflate = deflate;
+                    tracing::debug!(url = %self.url, "Facilitator WS connection opened");
+                    return Some(socket);
+                }
+                Err(error) => {
+                    tracing::warn!(url = %self.url, %error, "Can not connect to the facilitator WS");
+                    last_error = error;
+                    self.failures += 1;
+                }
+            }
+        }
+    }
+
+    /// Opens a connection, returning its compression if the facilitator accepted it.
+    ///
+    /// A JSON client offering compression to a facilitator not compressing offers no subprotocol
+    /// it selects, which fails the handshake: the connection is then opened again without it.
+    async fn open(&self) -> Result<(Socket, Option<WsDeflate>), WsClientError> {
+        let Some(compression) = self.compression else {
+            let (socket, _) = self.handshake(false).await?;
+            return Ok((socket, None));
+        };
+        let (socket, selected) = match self.handshake(true).await {
+            Err(WsClientError::WebSocket(error))
+                if self.codec == WsCodec::Json && is_no_subprotocol(&error) =>
+            {
+                tracing::debug!(url = %self.url, "Facilitator WS does not compress envelopes");
+                let (socket, _) = self.handshake(false).await?;
+                return Ok((socket, None));
+            }
+            opened => opened?,
+        };
+        let deflate = WsDeflate::from_subprotocol(selected.as_deref()).map(|_| compression);
+        Ok((socket, deflate))
+    }
+
+    /// Opens a connection, offering the subprotocols of the codec, with compression or not.
+    /// Returns the subprotocol selected, if any.
+    async fn handshake(&self, compress: bool) -> Result<(Socket, Option<String>), WsClientError> {
+        let mut request = self.url.as_str().into_client_request()?;
+        let protocols = match (compress, self.codec.subprotocol()) {
+            (true, Some(protocol)) => {
+                Some(format!("{}, {protocol}", self.codec.deflate_subprotocol()))
+            }
+            (true, None) => Some(self.codec.deflate_subprotocol().to_string()),
+            (false, protocol) => protocol.map(str::to_string),
+        };
+        if let Some(protocols) = protocols {
+            let protocols = HeaderValue::from_str(&protocols).expect("Subprotocols are ASCII");
+            request
+                .headers_mut()
+                .insert(SEC_WEBSOCKET_PROTOCOL, protocols);
+        }
+        let (socket, response) = tokio::time::timeout(self.timeout, connect_async(request))
+            .await
+            .map_err(|_| WsClientError::Timeout(self.timeout))??;
+        let selected = response
+            .headers()
+            .get(SEC_WEBSOCKET_PROTOCOL)
+            .and_then(|protocol| protocol.to_str().ok())
+            .map(str::to_string);
+        Ok((socket, selected))
+    }
+
+    /// Sends the pending requests, then new ones as they come, and dispatches what the
+    /// facilitator sends, until either side closes.
+    async fn serve(
+        &mut self,
+        mut socket: Socket,
+        requests: &mut mpsc::UnboundedReceiver<u64>,
+    ) -> Closed {
+        if !self.send_pending(&mut socket).await {
+            return Closed::Dropped;
+        }
+        loop {
+            tokio::select! {
+                request = requests.recv() => match request {
+                    Some(_) => {
+                        if !self.send_pending(&mut socket).await {
+                            return Closed::Dropped;
+                        }
+                    }
+                    None => {
+                        let _ = socket.close(None).await;
+                        return Closed::ByClient;
+                    }
+                },
+                message = socket.next() => match message {
+                    Some(Ok(message)) => self.dispatch(message),
+                    Some(Err(error)) => {
+                        tracing::warn!(%error, "Facilitator WS connection failed");
+                        return Closed::Dropped;
+                    }
+                    None => return Closed::Dropped,
+                },
+            }
+        }
+    }
+
+    /// Sends the pending requests not sent on the current connection yet, in id order.
+    /// Returns `false` if the socket is gone.
+    async fn send_pending(&self, socket: &mut Socket) -> bool {
+        let mut replayed = 0;
+        let messages = self
+            .pending
+            .lock()
+            .unwrap()
+            .iter_mut()
+            .filter(|(_, request)| request.sent_on != Some(self.generation))
+            .map(|(id, request)| {
+                if request.sent_on.replace(self.generation).is_some() {
+                    replayed += 1;
+                }
+                (*id, self.message(request.frame.clone()))
+            })
+            .collect::<Vec<_>>();
+        if replayed > 0 {
+            tracing::info!(
+                replayed,
+                "Replaying requests unanswered by the dropped connection"
+            );
+        }
+        for (id, message) in messages {
+            if let Err(error) = socket.send(message).await {
+                tracing::warn!(id, %error, "Can not send to the facilitator WS");
+                return false;
+            }
+        }
+        true
+    }
+
+    /// Message carrying `frame`, compressed if the connection is.
+    fn message(&self, frame: WsFrame) -> Message {
+        let frame = match &self.deflate {
+            Some(deflate) => deflate.compress(frame),
+            None => frame,
+        };
+        match frame {
+            WsFrame::Text(text) => Message::Text(text.into()),
+            WsFrame::Binary(bytes) => Message::Binary(bytes.into()),
+        }
+    }
+
+    fn dispatch(&mut self, message: Message) {
+        let frame = match message {
+            Message::Text(text) => WsFrame::Text(text.to_string()),
+            Message::Binary(bytes) => WsFrame::Binary(bytes.to_vec()),
+            _ => return,
+        };
+        let frame = match self.deflate {
+            Some(_) => WsDeflate::decompress(frame),
+            None => Ok(frame),
+        };
+        let decoded = frame.and_then(|frame| self.codec.decode::<IncomingEnvelope>(&frame));
+        let envelope = match decoded {
+            Ok(envelope) => envelope,
+            Err(error) => {
+                tracing::warn!(%error, "Invalid envelope from the facilitator WS");
+                return;
+            }
+        };
+        self.failures = 0;
+        match (envelope.id, envelope.method) {
+            (Some(id), _) => {
+                let Some(request) = id
+                    .as_u64()
+                    .and_then(|id| self.pending.lock().unwrap().remove(&id))
+                else {
+                    tracing::debug!(%id, "Reply to no pending request, dropped");
+                    return;
+                };
+                let result = match envelope.error {
+                    Some(error) => Err(WsClientError::Rpc {
+                        code: error.code,
+                        message: error.message,
+                        data: error.data,
+                    }),
+                    None => Ok(envelope.result),
+                };
+                let _ = request.reply.send(result);
+            }
+            (None, Some(method)) if method == ConnectionError::METHOD => {
+                match serde_json::from_value::<ConnectionError>(envelope.params) {
+                    Ok(error) => {
+                        tracing::warn!(kind = ?error.kind, message = %error.message, "Connection error reported by the facilitator WS");
+                        let _ = self.connection_errors.send(error);
+                    }
+                    Err(error) => {
+                        tracing::warn!(%error, "Invalid x402.error notification from the facilitator WS");
+                    }
+                }
+            }
+            (None, Some(method)) => {
+                // No receiver is not an error: nobody listens to notifications.
+                let _ = self.notifications.send(WsNotification {
+                    method,
+                    params: envelope.params,
+                });
+            }
+            (None, None) => tracing::debug!("Envelope without id nor method, dropped"),
+        }
+    }
+
+    /// Fails every pending request with `error`, and starts afresh on the next request.
+    fn fail_pending(&mut self, error: &WsClientError) {
+        let pending = std::mem::take(&mut *self.pending.lock().unwrap());
+        for (_, request) in pending {
+            let _ = request.reply.send(Err(error.duplicate()));
+        }
+        self.failures = 0;
+    }
+}
+
+/// Whether `error` is a handshake answered with none of the subprotocols offered.
+fn is_no_subprotocol(error: &tungstenite::Error) -> bool {
+    matches!(
+        error,
+        tungstenite::Error::Protocol(
+            tungstenite::error::ProtocolError::SecWebSocketSubProtocolError(
+                tungstenite::error::SubProtocolError::NoSubProtocol
+            )
+        )
+    )
+}
+
+impl Facilitator for FacilitatorWsClient {
+    type Error = WsClientError;
+
+    /// Calls `x402.verify`.
+    async fn verify(&self, request: &VerifyRequest) -> Result<VerifyResponse, Self::Error> {
+        self.call("x402.verify", request).await
+    }
+
+    /// Calls `x402.settle`. Failed settlements are [`WsClientError::Rpc`] errors, with the
+    /// reason as a `VerifyResponse` in `data`.
+    async fn settle(&self, request: &SettleRequest) -> Result<SettleResponse, Self::Error> {
+        self.call("x402.settle", request).await
+    }
+
+    /// Calls `x402.supported`.
+    async fn supported(&self) -> Result<SupportedPaymentKindsResponse, Self::Error> {
+        self.call("x402.supported", &()).await
+    }
+}
+
+/// A remote facilitator only serves verification and settlement: notifications are available
+/// with [`FacilitatorWsClient::notifications`] instead.
+impl FacilitatorServices for FacilitatorWsClient {}
+
+/// Parses `value` as the URL of the WebSocket endpoint.
+impl TryFrom<&str> for FacilitatorWsClient {
+    type Error = WsClientError;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        Ok(FacilitatorWsClient::new(Url::parse(value)?))
+    }
+}
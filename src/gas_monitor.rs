@@ -0,0 +1,321 @@
+//! Native token balance of the settlement accounts, monitored in the background.
+//!
+//! Settlement transactions are paid for in the native token of their chain, e.g. ETH on Base or
+//! SOL on Solana. An account running dry fails every settlement it sends with an opaque error of
+//! the node (`insufficient funds for gas * price + value`), once the payment was verified and its
+//! nonce reserved. [`GasMonitor`] polls the balance of every settlement account, each account of
+//! the signer pool on EVM networks and the fee payer on Solana, and:
+//! - records it in the `x402_gas_balance` gauge, by `network` and `account`, in base units of the
+//!   native token (wei, lamports),
+//! - reports it in the `gas` field of `/supported` and `x402.supported`, with the floor of the
+//!   network and whether its settlements are refused,
+//! - refuses the settlements on a network once any of its accounts is below the floor of the
+//!   network, with [`FacilitatorLocalError::InsufficientGas`], reported over HTTP as
+//!   `503 Service Unavailable` and as the `insufficient_gas` reason otherwise. Verifications go
+//!   on. Settlements resume once the account is topped up, or rotated out (see
+//!   [`signer_pool`](crate::chain::signer_pool)), and polled again.
+//!
+//! Balances are polled at startup, then every poll interval. A balance that can not be fetched
+//! keeps its last known value, and the failure is logged. Networks of custom chain providers are
+//! not monitored. Payments queued for deferred settlement (see
+//! [`settlement_queue`](crate::settlement_queue)) wait while their network is short of gas,
+//! without using up their attempts.
+//!
+//! Environment (see [`GasMonitor::from_env`]):
+//! - `GAS_BALANCE_FLOOR` – Floor of every network, in base units of its native token (default:
+//!   none, balances are monitored without refusing settlements)
+//! - `GAS_BALANCE_FLOOR_<NETWORK>` – Floor of one network, e.g. `GAS_BALANCE_FLOOR_BASE_SEPOLIA`
+//! - `GAS_MONITOR_INTERVAL_SECS` – Interval between two polls, from 5 seconds to 1 hour, or `off`
+//!   to turn the monitor off (default `60`)
+
+use alloy::primitives::U256;
+use once_cell::sync::Lazy;
+use opentelemetry::KeyValue;
+use opentelemetry::metrics::Gauge;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::env;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+use crate::chain::{FacilitatorLocalError, NetworkProvider, NetworkProviderOps};
+use crate::duration::{self, DurationError, DurationRange};
+use crate::network::Network;
+use crate::provider_cache::ProviderCache;
+use crate::timestamp::UnixTimestampMs;
+use crate::types::{MixedAddress, TokenAmount};
+
+const ENV_GAS_BALANCE_FLOOR: &str = "GAS_BALANCE_FLOOR";
+const ENV_GAS_MONITOR_INTERVAL_SECS: &str = "GAS_MONITOR_INTERVAL_SECS";
+
+const DEFAULT_INTERVAL: Duration = Duration::from_secs(60);
+/// Poll intervals accepted: from 5 seconds to 1 hour.
+pub const INTERVAL_RANGE: DurationRange =
+    DurationRange::new(Duration::from_secs(5), Duration::from_secs(3600));
+
+static GAS_BALANCE: Lazy<Gauge<f64>> = Lazy::new(|| {
+    opentelemetry::global::meter("x402-rs")
+        .f64_gauge("x402_gas_balance")
+        .build()
+});
+
+#[derive(Debug, thiserror::Error)]
+pub enum GasMonitorError {
+    #[error("Invalid {0}: {1}, expected an amount in base units of the native token")]
+    InvalidFloor(String, String),
+    #[error(transparent)]
+    InvalidDuration(#[from] DurationError),
+}
+
+/// Balance of a settlement account, as last polled.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AccountGas {
+    pub account: MixedAddress,
+    /// Balance in base units of the native token (wei, lamports).
+    pub balance: TokenAmount,
+    /// Time of the poll, in milliseconds since the Unix epoch.
+    pub checked_at: UnixTimestampMs,
+}
+
+/// Balances of the settlement accounts of a network, reported by `/supported`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NetworkGas {
+    pub network: Network,
+    /// Balance below which settlements are refused, if any.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub floor: Option<TokenAmount>,
+    /// Whether settlements are refused, an account being below the floor.
+    pub insufficient: bool,
+    pub accounts: Vec<AccountGas>,
+}
+
+/// Monitor of the gas of the settlement accounts, see the [module documentation](self).
+///
+/// Clones share the same balances.
+#[derive(Debug, Clone)]
+pub struct GasMonitor {
+    floor: Option<U256>,
+    floors: HashMap<Network, U256>,
+    interval: Duration,
+    balances: Arc<RwLock<HashMap<Network, Vec<AccountGas>>>>,
+}
+
+impl Default for GasMonitor {
+    fn default() -> Self {
+        Self {
+            floor: None,
+            floors: HashMap::new(),
+            interval: DEFAULT_INTERVAL,
+            balances: Arc::new(RwLock::new(HashMap::new())),
+        }
+    }
+}
+
+impl GasMonitor {
+    /// Polls every minute, without floor.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Reads the monitor from `GAS_BALANCE_FLOOR`, `GAS_BALANCE_FLOOR_<NETWORK>` and
+    /// `GAS_MONITOR_INTERVAL_SECS`. Returns `None` if the monitor is turned off.
+    pub fn from_env() -> Result<Option<Self>, GasMonitorError> {
+        let off = env::var(ENV_GAS_MONITOR_INTERVAL_SECS).is_ok_and(|value| value.trim() == "off");
+        if off {
+            return Ok(None);
+        }
+        let mut monitor = Self::new();
+        if let Some(floor) = floor_from_env(ENV_GAS_BALANCE_FLOOR)? {
+            monitor = monitor.with_floor(floor);
+        }
+        for network in Network::variants() {
+            let suffix = network.to_string().to_uppercase().replace('-', "_");
+            let name = format!("{ENV_GAS_BALANCE_FLOOR}_{suffix}");
+            if let Some(floor) = floor_from_env(&name)? {
+                monitor = monitor.with_network_floor(*network, floor);
+            }
+        }
+        if let Some(interval) = duration::from_env(
+            ENV_GAS_MONITOR_INTERVAL_SECS,
+            duration::SECOND,
+            INTERVAL_RANGE,
+        )? {
+            monitor = monitor.with_interval(interval)?;
+        }
+        Ok(Some(monitor))
+    }
+
+    /// Refuses the settlements on every network with an account holding less than `floor`, in
+    /// base units of its native token.
+    pub fn with_floor(&self, floor: U256) -> Self {
+        Self {
+            floor: Some(floor),
+            ..self.clone()
+        }
+    }
+
+    /// Refuses the settlements on `network` once an account holds less than `floor`, instead of
+    /// the floor of every network.
+    pub fn with_network_floor(&self, network: Network, floor: U256) -> Self {
+        let mut floors = self.floors.clone();
+        floors.insert(network, floor);
+        Self {
+            floors,
+            ..self.clone()
+        }
+    }
+
+    /// Polls the balances every `interval`.
+    pub fn with_interval(&self, interval: Duration) -> Result<Self, DurationError> {
+        Ok(Self {
+            interval: INTERVAL_RANGE.check(interval)?,
+            ..self.clone()
+        })
+    }
+
+    /// Floor of `network`, if any.
+    pub fn floor(&self, network: Network) -> Option<U256> {
+        self.floors.get(&network).copied().or(self.floor)
+    }
+
+    /// Polls the balances of the settlement accounts of every network of `provider_cache`, in the
+    /// background, forever.
+    pub fn start(&self, provider_cache: &ProviderCache) {
+        for (network, provider) in provider_cache {
+            if let NetworkProvider::Custom(_) = provider {
+                continue;
+            }
+            let floor = self.floor(*network).map(|floor| floor.to_string());
+            tracing::info!(%network, floor = floor.as_deref(), "Monitoring the gas of the settlement accounts");
+            let monitor = self.clone();
+            let provider = provider.clone();
+            tokio::spawn(async move { monitor.watch(provider).await });
+        }
+    }
+
+    /// Polls the balances of the accounts of `provider` every interval, forever.
+    async fn watch(self, provider: NetworkProvider) {
+        let mut interval = tokio::time::interval(self.interval);
+        interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        loop {
+            interval.tick().await;
+            self.poll(&provider).await;
+        }
+    }
+
+    /// Fetches the balances of the accounts of `provider`, keeping the last known balance of
+    /// those that can not be fetched.
+    async fn poll(&self, provider: &NetworkProvider) {
+        let network = provider.network();
+        let balances = match provider {
+            NetworkProvider::Evm(provider) => {
+                let mut balances = Vec::new();
+                for account in provider.signers().addresses() {
+                    let balance = provider.native_balance(account).await;
+                    balances.push((MixedAddress::from(account), balance));
+                }
+                balances
+            }
+            NetworkProvider::Solana(provider) => {
+                let balance = provider.fee_payer_balance().await.map(U256::from);
+                vec![(provider.signer_address(), balance)]
+            }
+            NetworkProvider::Custom(_) => return,
+        };
+        let floor = self.floor(network);
+        let previous = self.accounts(network);
+        let mut accounts = Vec::new();
+        for (account, balance) in balances {
+            match balance {
+                Ok(balance) => {
+                    GAS_BALANCE.record(
+                        u128::try_from(balance).map_or(f64::MAX, |balance| balance as f64),
+                        &[
+                            KeyValue::new("network", network.to_string()),
+                            KeyValue::new("account", account.to_string()),
+                        ],
+                    );
+                    if floor.is_some_and(|floor| balance < floor) {
+                        tracing::error!(%network, %account, %balance, "Settlement account below the gas floor");
+                    }
+                    accounts.push(AccountGas {
+                        account,
+                        balance: balance.into(),
+                        checked_at: UnixTimestampMs::now(),
+                    });
+                }
+                Err(error) => {
+                    tracing::warn!(%network, %account, %error, "Failed to fetch the gas balance");
+                    if let Some(last) = previous.iter().find(|last| last.account == account) {
+                        accounts.push(last.clone());
+                    }
+                }
+            }
+        }
+        self.balances.write().unwrap().insert(network, accounts);
+    }
+
+    /// Accounts of `network` as last polled.
+    fn accounts(&self, network: Network) -> Vec<AccountGas> {
+        self.balances
+            .read()
+            .unwrap()
+            .get(&network)
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    /// Whether an account of `network` is below the floor of the network, as last polled.
+    pub fn is_insufficient(&self, network: Network) -> bool {
+        let Some(floor) = self.floor(network) else {
+            return false;
+        };
+        self.accounts(network)
+            .iter()
+            .any(|account| account.balance.0 < floor)
+    }
+
+    /// Refuses the settlements on `network` if an account is below the floor.
+    pub fn check(&self, network: Network) -> Result<(), FacilitatorLocalError> {
+        if self.is_insufficient(network) {
+            Err(FacilitatorLocalError::InsufficientGas(network))
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Balances of the accounts of every network polled, by network.
+    pub fn balances(&self) -> Vec<NetworkGas> {
+        let mut networks = self
+            .balances
+            .read()
+            .unwrap()
+            .keys()
+            .copied()
+            .collect::<Vec<_>>();
+        networks.sort_by_key(|network| network.to_string());
+        networks
+            .into_iter()
+            .map(|network| NetworkGas {
+                network,
+                floor: self.floor(network).map(TokenAmount::from),
+                insufficient: self.is_insufficient(network),
+                accounts: self.accounts(network),
+            })
+            .collect()
+    }
+}
+
+/// Reads the floor `name`, if set.
+fn floor_from_env(name: &str) -> Result<Option<U256>, GasMonitorError> {
+    match env::var(name) {
+        Ok(value) if !value.trim().is_empty() => value
+            .trim()
+            .parse()
+            .map(Some)
+            .map_err(|_| GasMonitorError::InvalidFloor(name.to_string(), value)),
+        _ => Ok(None),
+    }
+}
@@ -0,0 +1,829 @@
+//! Operator (admin) API of the facilitator, served under `/admin`.
+//!
+//! The API is disabled unless `ADMIN_TOKEN` is set; every request must then carry
+//! `Authorization: Bearer <ADMIN_TOKEN>`. Requests are rejected with `404 Not Found` when the
+//! API is disabled, and with `401 Unauthorized` when the token does not match.
+//!
+//! With `ADMIN_PORT` set, the API is served on a listener of its own, bound to `ADMIN_HOST`
+//! (default `127.0.0.1`), see [`AdminListener`], e.g. to keep it off the public network: requests
+//! reaching the public listener are rejected with `404 Not Found`, as if the API were disabled.
+//! The same goes for the other endpoints authenticated with `ADMIN_TOKEN`, e.g. `/payments`. The
+//! admin listener serves the public endpoints too.
+//!
+//! Endpoints:
+//! - `GET /admin/log-filter` – Current log filter directives
+//! - `PUT /admin/log-filter` – Replace the log filter, e.g. `{"filter": "info,x402_rs::handlers::ws=debug"}`
+//! - `GET /admin/sampling` – Current trace sampling ratio (`null` if OpenTelemetry is disabled)
+//! - `PUT /admin/sampling` – Set the trace sampling ratio, e.g. `{"ratio": 0.1}`
+//! - `POST /admin/nonce-resync` – Resynchronize the settlement account nonce of every EVM network
+//!   with the chain, or of one with `?network=base-sepolia` (see `chain::account_nonce`)
+//! - `GET /admin/strict-settle` – Window within which payments must have been verified to be
+//!   settled (`null` if settlement is not restricted, see `strict_settle`)
+//! - `PUT /admin/strict-settle` – Set the window, e.g. `{"windowSeconds": 30}`, or lift the
+//!   restriction with `{"windowSeconds": null}`
+//! - `GET /admin/connections` – Open WebSocket connections
+//! - `GET /admin/streams` – Sessions of the pay-per-slice streams not expired (see `stream`)
+//! - `GET /admin/paused-networks` – Networks whose settlements are paused (see `settlement_pause`)
+//! - `PUT /admin/paused-networks/{network}` – Pause the settlements on a network
+//! - `DELETE /admin/paused-networks/{network}` – Resume the settlements on a network
+//! - `GET /admin/settlement-queue` – Payments queued for deferred settlement (see
+//!   `settlement_queue`)
+//! - `POST /admin/settlement-queue/drain` – Attempt every queued payment right away, regardless
+//!   of backoff
+//! - `GET /admin/signers` – Settlement accounts of every EVM network (see `chain::signer_pool`)
+//! - `POST /admin/signers/rotate` – Replace the settlement accounts of every EVM network, or of
+//!   one with `?network=base-sepolia`, with those of `{"privateKeys": ["0x..."]}`, or with those
+//!   of the signer configuration (`SIGNER_TYPE`), read again, without a body
+//! - `GET /admin/rate-limits` – Rate limits enforced, per client address and per API key
+//! - `PUT /admin/rate-limits` – Adjust rate limits, e.g. `{"perIp": {"settle": 5}, "perKey":
+//!   {"settle": 20}}`, `0` lifting a limit, until the next configuration reload or restart
+//! - `GET /admin/faults` – Current fault injection config (`chaos` feature)
+//! - `PUT /admin/faults` – Replace the fault injection config (`chaos` feature)
+//! - `DELETE /admin/faults` – Disable all injected faults (`chaos` feature)
+
+use axum::extract::Request;
+use axum::http::{StatusCode, header};
+use axum::middleware::{self, Next};
+use axum::response::{IntoResponse, Response};
+use axum::{Extension, Router};
+use once_cell::sync::Lazy;
+use sha2::{Digest, Sha256};
+use std::env;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+
+use crate::handlers::ServedFacilitator;
+
+const ENV_ADMIN_TOKEN: &str = "ADMIN_TOKEN";
+const ENV_ADMIN_HOST: &str = "ADMIN_HOST";
+const ENV_ADMIN_PORT: &str = "ADMIN_PORT";
+
+static ADMIN_TOKEN: Lazy<Option<String>> = Lazy::new(|| {
+    env::var(ENV_ADMIN_TOKEN)
+        .ok()
+        .filter(|token| !token.is_empty())
+});
+
+/// Whether the admin API is served on a listener of its own, see [`AdminListener`].
+static SEPARATE_LISTENER: Lazy<bool> =
+    Lazy::new(|| env::var(ENV_ADMIN_PORT).is_ok_and(|port| !port.is_empty()));
+
+#[derive(Debug, thiserror::Error)]
+pub enum AdminListenerError {
+    #[error("Invalid {ENV_ADMIN_PORT}: {0}, expected a port number")]
+    InvalidPort(String),
+    #[error("Invalid {ENV_ADMIN_HOST}: {0}, expected an IP address")]
+    InvalidHost(String),
+}
+
+/// Listener dedicated to the admin API, apart from the public facilitator endpoints.
+///
+/// The router served on it is marked with [`AdminListener::apply`]; with `ADMIN_PORT` set, admin
+/// requests are only served on routers so marked.
+#[derive(Debug, Clone, Copy)]
+pub struct AdminListener {
+    addr: SocketAddr,
+}
+
+impl AdminListener {
+    /// Reads the address of the listener from `ADMIN_PORT` and `ADMIN_HOST` (default
+    /// `127.0.0.1`). Returns `None` if `ADMIN_PORT` is unset: the admin API is then served with
+    /// the public endpoints.
+    pub fn from_env() -> Result<Option<Self>, AdminListenerError> {
+        let Some(port) = env::var(ENV_ADMIN_PORT)
+            .ok()
+            .filter(|port| !port.is_empty())
+        else {
+            return Ok(None);
+        };
+        let port = port
+            .trim()
+            .parse::<u16>()
+            .map_err(|_| AdminListenerError::InvalidPort(port))?;
+        let host = match env::var(ENV_ADMIN_HOST)
+            .ok()
+            .filter(|host| !host.is_empty())
+        {
+            Some(host) => host
+                .trim()
+                .parse::<IpAddr>()
+                .map_err(|_| AdminListenerError::InvalidHost(host))?,
+            None => IpAddr::V4(Ipv4Addr::LOCALHOST),
+        };
+        Ok(Some(Self {
+            addr: SocketAddr::new(host, port),
+        }))
+    }
+
+    /// Address to bind the listener to.
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// Marks `router` as served on the admin listener, serving the admin API.
+    pub fn apply(&self, router: Router) -> Router {
+        router.layer(Extension(*self))
+    }
+}
+
+/// Routes of the admin API for facilitator `F`, to be nested under `/admin`.
+pub fn admin_routes<F: ServedFacilitator>() -> Router {
+    let router = Router::new()
+        .route(
+            "/log-filter",
+            axum::routing::get(telemetry::get_log_filter).put(telemetry::put_log_filter),
+        )
+        .route(
+            "/sampling",
+            axum::routing::get(telemetry::get_sampling).put(telemetry::put_sampling),
+        )
+        .route(
+            "/nonce-resync",
+            axum::routing::post(nonces::post_nonce_resync::<F>),
+        )
+        .route(
+            "/strict-settle",
+            axum::routing::get(strict_settle::get_strict_settle::<F>)
+                .put(strict_settle::put_strict_settle::<F>),
+        )
+        .route(
+            "/connections",
+            axum::routing::get(connections::get_connections),
+        )
+        .route(
+            "/streams",
+            axum::routing::get(connections::get_streams::<F>),
+        )
+        .route(
+            "/paused-networks",
+            axum::routing::get(settlement::get_paused_networks::<F>),
+        )
+        .route(
+            "/paused-networks/{network}",
+            axum::routing::put(settlement::put_paused_network::<F>)
+                .delete(settlement::delete_paused_network::<F>),
+        )
+        .route(
+            "/settlement-queue",
+            axum::routing::get(settlement::get_settlement_queue::<F>),
+        )
+        .route(
+            "/settlement-queue/drain",
+            axum::routing::post(settlement::post_settlement_queue_drain::<F>),
+        )
+        .route("/signers", axum::routing::get(signers::get_signers::<F>))
+        .route(
+            "/signers/rotate",
+            axum::routing::post(signers::post_signers_rotate::<F>),
+        )
+        .route(
+            "/rate-limits",
+            axum::routing::get(rate_limits::get_rate_limits).put(rate_limits::put_rate_limits),
+        );
+    #[cfg(feature = "chaos")]
+    let router = router.route(
+        "/faults",
+        axum::routing::get(faults::get_faults::<F>)
+            .put(faults::put_faults::<F>)
+            .delete(faults::delete_faults::<F>),
+    );
+    router.layer(middleware::from_fn(require_admin_token))
+}
+
+/// Rejects requests that do not carry the configured admin bearer token, and with `ADMIN_PORT`
+/// set, requests not reaching the [`AdminListener`].
+pub(super) async fn require_admin_token(request: Request, next: Next) -> Response {
+    let Some(expected) = ADMIN_TOKEN.as_deref() else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+    if *SEPARATE_LISTENER && request.extensions().get::<AdminListener>().is_none() {
+        return StatusCode::NOT_FOUND.into_response();
+    }
+    let provided = request
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "));
+    if !provided.is_some_and(|provided| token_matches(provided, expected)) {
+        tracing::warn!(uri = %request.uri(), "Rejected unauthorized admin request");
+        return StatusCode::UNAUTHORIZED.into_response();
+    }
+    next.run(request).await
+}
+
+/// Whether `provided` is the `expected` token, in constant time: their SHA-256 digests are
+/// compared, so that neither the length nor the bytes of the token leak through the time taken.
+pub(super) fn token_matches(provided: &str, expected: &str) -> bool {
+    let provided = Sha256::digest(provided.as_bytes());
+    let expected = Sha256::digest(expected.as_bytes());
+    provided
+        .iter()
+        .zip(expected.iter())
+        .fold(0u8, |difference, (a, b)| difference | (a ^ b))
+        == 0
+}
+
+/// Runtime tuning of logging and tracing. Handlers expect the
+/// [`TelemetryControl`](crate::telemetry::TelemetryControl) as an [`Extension`](axum::Extension) layer.
+mod telemetry {
+    use axum::http::StatusCode;
+    use axum::response::{IntoResponse, Response};
+    use axum::{Extension, Json};
+    use serde::{Deserialize, Serialize};
+
+    use crate::telemetry::{TelemetryControl, TelemetryControlError};
+    use crate::types::ErrorResponse;
+
+    #[derive(Debug, Serialize, Deserialize)]
+    pub struct LogFilter {
+        /// Directives in `RUST_LOG` syntax.
+        pub filter: String,
+    }
+
+    #[derive(Debug, Serialize)]
+    pub struct Sampling {
+        /// Ratio of new root traces sampled, `None` if OpenTelemetry is disabled.
+        pub ratio: Option<f64>,
+    }
+
+    #[derive(Debug, Deserialize)]
+    pub struct SetSampling {
+        pub ratio: f64,
+    }
+
+    impl IntoResponse for TelemetryControlError {
+        fn into_response(self) -> Response {
+            let status = match self {
+                TelemetryControlError::InvalidFilter(_)
+                | TelemetryControlError::InvalidSamplingRatio(_) => StatusCode::BAD_REQUEST,
+                TelemetryControlError::TracingDisabled => StatusCode::CONFLICT,
+                TelemetryControlError::Reload(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            };
+            let error = ErrorResponse {
+                error: self.to_string(),
+            };
+            (status, Json(error)).into_response()
+        }
+    }
+
+    /// `GET /admin/log-filter`: current log filter.
+    pub async fn get_log_filter(
+        Extension(control): Extension<TelemetryControl>,
+    ) -> Json<LogFilter> {
+        Json(LogFilter {
+            filter: control.filter(),
+        })
+    }
+
+    /// `PUT /admin/log-filter`: replaces the log filter.
+    pub async fn put_log_filter(
+        Extension(control): Extension<TelemetryControl>,
+        Json(body): Json<LogFilter>,
+    ) -> Result<Json<LogFilter>, TelemetryControlError> {
+        control.set_filter(&body.filter)?;
+        Ok(get_log_filter(Extension(control)).await)
+    }
+
+    /// `GET /admin/sampling`: current trace sampling ratio.
+    pub async fn get_sampling(Extension(control): Extension<TelemetryControl>) -> Json<Sampling> {
+        Json(Sampling {
+            ratio: control.sampling_ratio(),
+        })
+    }
+
+    /// `PUT /admin/sampling`: sets the trace sampling ratio.
+    pub async fn put_sampling(
+        Extension(control): Extension<TelemetryControl>,
+        Json(body): Json<SetSampling>,
+    ) -> Result<Json<Sampling>, TelemetryControlError> {
+        control.set_sampling_ratio(body.ratio)?;
+        Ok(get_sampling(Extension(control)).await)
+    }
+}
+
+/// Resynchronization of the settlement account nonces, see
+/// [`account_nonce`](crate::chain::account_nonce).
+mod nonces {
+    use axum::extract::Query;
+    use axum::http::StatusCode;
+    use axum::response::{IntoResponse, Response};
+    use axum::{Extension, Json};
+    use serde::Deserialize;
+
+    use crate::chain::NetworkProvider;
+    use crate::handlers::ServedFacilitator;
+    use crate::network::Network;
+    use crate::types::ErrorResponse;
+
+    #[derive(Debug, Deserialize)]
+    pub struct NonceResyncQuery {
+        /// Network to resynchronize, all EVM networks if unset.
+        pub network: Option<Network>,
+    }
+
+    pub(super) fn error(status: StatusCode, error: String) -> Response {
+        (status, Json(ErrorResponse { error })).into_response()
+    }
+
+    /// `POST /admin/nonce-resync`: resynchronizes the settlement account nonces with the chain.
+    pub async fn post_nonce_resync<F: ServedFacilitator>(
+        Extension(facilitator): Extension<F>,
+        Query(query): Query<NonceResyncQuery>,
+    ) -> Response {
+        let Some(provider_cache) = facilitator.provider_cache() else {
+            return StatusCode::NOT_FOUND.into_response();
+        };
+        let providers = provider_cache
+            .into_iter()
+            .filter(|(network, _)| query.network.is_none_or(|n| n == **network))
+            .filter_map(|(_, provider)| match provider {
+                NetworkProvider::Evm(provider) => Some(provider),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        if let Some(network) = query.network
+            && providers.is_empty()
+        {
+            return error(
+                StatusCode::BAD_REQUEST,
+                format!("No EVM provider configured for {network}"),
+            );
+        }
+        let mut resyncs = Vec::with_capacity(providers.len());
+        for provider in providers {
+            match provider.resync_nonces().await {
+                Ok(resync) => resyncs.extend(resync),
+                Err(e) => return error(StatusCode::BAD_GATEWAY, e.to_string()),
+            }
+        }
+        Json(resyncs).into_response()
+    }
+}
+
+/// Runtime toggle of strict verify-before-settle, see [`strict_settle`](crate::strict_settle).
+mod strict_settle {
+    use axum::http::StatusCode;
+    use axum::response::{IntoResponse, Response};
+    use axum::{Extension, Json};
+    use serde::{Deserialize, Serialize};
+
+    use crate::duration::Seconds;
+    use crate::handlers::ServedFacilitator;
+    use crate::types::ErrorResponse;
+
+    #[derive(Debug, Serialize, Deserialize)]
+    #[serde(rename_all = "camelCase")]
+    pub struct StrictSettleWindow {
+        /// Window within which payments must have been verified to be settled, `None` if
+        /// settlement is not restricted.
+        pub window_seconds: Option<Seconds>,
+    }
+
+    /// `GET /admin/strict-settle`: current window, `404 Not Found` if the facilitator does not
+    /// settle payments itself.
+    pub async fn get_strict_settle<F: ServedFacilitator>(
+        Extension(facilitator): Extension<F>,
+    ) -> Response {
+        let Some(strict_settle) = facilitator.strict_settle() else {
+            return StatusCode::NOT_FOUND.into_response();
+        };
+        Json(StrictSettleWindow {
+            window_seconds: strict_settle
+                .window()
+                .map(|window| Seconds::new(window.as_secs())),
+        })
+        .into_response()
+    }
+
+    /// `PUT /admin/strict-settle`: sets the window, or lifts the restriction.
+    pub async fn put_strict_settle<F: ServedFacilitator>(
+        Extension(facilitator): Extension<F>,
+        Json(body): Json<StrictSettleWindow>,
+    ) -> Response {
+        let Some(strict_settle) = facilitator.strict_settle() else {
+            return StatusCode::NOT_FOUND.into_response();
+        };
+        if let Err(e) = strict_settle.set_window(body.window_seconds.map(Into::into)) {
+            let error = ErrorResponse {
+                error: e.to_string(),
+            };
+            return (StatusCode::BAD_REQUEST, Json(error)).into_response();
+        }
+        tracing::info!(window = ?strict_settle.window(), "Strict settlement window set");
+        get_strict_settle(Extension(facilitator)).await
+    }
+}
+
+/// Open WebSocket connections, registered by the WebSocket endpoint (see
+/// [`WsConnections`](crate::handlers::WsConnections)), and stream sessions.
+mod connections {
+    use axum::http::StatusCode;
+    use axum::response::{IntoResponse, Response};
+    use axum::{Extension, Json};
+
+    use crate::handlers::ServedFacilitator;
+    use crate::handlers::ws_connections::WsConnections;
+
+    /// `GET /admin/connections`: open WebSocket connections, oldest first, `404 Not Found` if the
+    /// WebSocket endpoint does not register them.
+    pub async fn get_connections(connections: Option<Extension<WsConnections>>) -> Response {
+        match connections {
+            Some(Extension(connections)) => Json(connections.list()).into_response(),
+            None => StatusCode::NOT_FOUND.into_response(),
+        }
+    }
+
+    /// `GET /admin/streams`: sessions of the streams not expired, `404 Not Found` if the
+    /// facilitator does not serve streams.
+    pub async fn get_streams<F: ServedFacilitator>(
+        Extension(facilitator): Extension<F>,
+    ) -> Response {
+        match facilitator.stream_sessions() {
+            Some(streams) => Json(streams.sessions()).into_response(),
+            None => StatusCode::NOT_FOUND.into_response(),
+        }
+    }
+}
+
+/// Settlements paused per network, see [`settlement_pause`](crate::settlement_pause), and the
+/// queue of deferred settlements, see [`settlement_queue`](crate::settlement_queue).
+mod settlement {
+    use axum::extract::Path;
+    use axum::http::StatusCode;
+    use axum::response::{IntoResponse, Response};
+    use axum::{Extension, Json};
+    use serde::Serialize;
+
+    use super::nonces::error;
+    use crate::handlers::ServedFacilitator;
+    use crate::network::Network;
+    use crate::settlement_pause::SettlementPause;
+    use crate::settlement_queue::DeferredSettlement;
+    use crate::timestamp::UnixTimestamp;
+
+    #[derive(Debug, Serialize)]
+    pub struct PausedNetworks {
+        pub networks: Vec<Network>,
+    }
+
+    /// A payment queued for deferred settlement.
+    #[derive(Debug, Serialize)]
+    #[serde(rename_all = "camelCase")]
+    pub struct QueueEntry {
+        pub network: Network,
+        #[serde(flatten)]
+        pub progress: DeferredSettlement,
+        /// Earliest time of the next attempt.
+        pub next_attempt_at: UnixTimestamp,
+    }
+
+    fn paused_networks(pause: &SettlementPause) -> Response {
+        let mut networks = pause.networks().into_iter().collect::<Vec<_>>();
+        networks.sort_by_key(Network::to_string);
+        Json(PausedNetworks { networks }).into_response()
+    }
+
+    /// `GET /admin/paused-networks`: networks whose settlements are paused, `404 Not Found` if
+    /// the facilitator does not settle payments itself.
+    pub async fn get_paused_networks<F: ServedFacilitator>(
+        Extension(facilitator): Extension<F>,
+    ) -> Response {
+        match facilitator.settlement_pause() {
+            Some(pause) => paused_networks(pause),
+            None => StatusCode::NOT_FOUND.into_response(),
+        }
+    }
+
+    /// `PUT /admin/paused-networks/{network}`: pauses the settlements on `network`.
+    pub async fn put_paused_network<F: ServedFacilitator>(
+        Extension(facilitator): Extension<F>,
+        Path(network): Path<Network>,
+    ) -> Response {
+        let Some(pause) = facilitator.settlement_pause() else {
+            return StatusCode::NOT_FOUND.into_response();
+        };
+        pause.pause(network);
+        paused_networks(pause)
+    }
+
+    /// `DELETE /admin/paused-networks/{network}`: resumes the settlements on `network`, and has
+    /// the payments queued meanwhile attempted right away.
+    pub async fn delete_paused_network<F: ServedFacilitator>(
+        Extension(facilitator): Extension<F>,
+        Path(network): Path<Network>,
+    ) -> Response {
+        let Some(pause) = facilitator.settlement_pause() else {
+            return StatusCode::NOT_FOUND.into_response();
+        };
+        if pause.resume(network)
+            && let Some(queue) = facilitator.settlement_queue()
+        {
+            queue.drain();
+        }
+        paused_networks(pause)
+    }
+
+    /// `GET /admin/settlement-queue`: payments queued or being settled, oldest first,
+    /// `404 Not Found` if settlements are not deferred.
+    pub async fn get_settlement_queue<F: ServedFacilitator>(
+        Extension(facilitator): Extension<F>,
+    ) -> Response {
+        let Some(queue) = facilitator.settlement_queue() else {
+            return StatusCode::NOT_FOUND.into_response();
+        };
+        let pending = match queue.pending().await {
+            Ok(pending) => pending,
+            Err(e) => return error(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()),
+        };
+        let mut entries = pending
+            .into_iter()
+            .map(|entry| QueueEntry {
+                network: entry.request.network(),
+                progress: entry.progress,
+                next_attempt_at: entry.next_attempt_at,
+            })
+            .collect::<Vec<_>>();
+        entries.sort_by_key(|entry| entry.progress.queued_at);
+        Json(entries).into_response()
+    }
+
+    /// `POST /admin/settlement-queue/drain`: has the worker attempt every queued payment right
+    /// away, regardless of backoff.
+    pub async fn post_settlement_queue_drain<F: ServedFacilitator>(
+        Extension(facilitator): Extension<F>,
+    ) -> Response {
+        match facilitator.settlement_queue() {
+            Some(queue) => {
+                queue.drain();
+                StatusCode::ACCEPTED.into_response()
+            }
+            None => StatusCode::NOT_FOUND.into_response(),
+        }
+    }
+}
+
+/// Settlement accounts of the EVM networks, and their rotation, see
+/// [`signer_pool`](crate::chain::signer_pool).
+mod signers {
+    use alloy::primitives::Address;
+    use axum::extract::Query;
+    use axum::http::StatusCode;
+    use axum::response::{IntoResponse, Response};
+    use axum::{Extension, Json};
+    use serde::{Deserialize, Serialize};
+    use std::sync::Arc;
+
+    use super::nonces::error;
+    use crate::chain::evm::EvmProvider;
+    use crate::chain::settlement_signer::SettlementSigner;
+    use crate::chain::signer_pool;
+    use crate::chain::{NetworkProvider, NetworkProviderOps};
+    use crate::handlers::ServedFacilitator;
+    use crate::network::Network;
+    use crate::provider_cache::{self, SignerType};
+
+    #[derive(Debug, Deserialize)]
+    pub struct SignersQuery {
+        /// Network to rotate the accounts of, all EVM networks if unset.
+        pub network: Option<Network>,
+    }
+
+    /// Keys of the accounts to rotate to. Not `Debug`, for the keys to stay out of logs.
+    #[derive(Deserialize)]
+    #[serde(rename_all = "camelCase")]
+    pub struct RotateSigners {
+        pub private_keys: Vec<String>,
+    }
+
+    #[derive(Debug, Serialize)]
+    pub struct NetworkSigners {
+        pub network: Network,
+        /// Accounts sending transactions, the primary one first.
+        pub accounts: Vec<Address>,
+        /// Accounts rotated out, still settling the payments naming them as spender.
+        pub retired: Vec<Address>,
+    }
+
+    /// Providers of the EVM networks, or of `network` only.
+    fn evm_providers<F: ServedFacilitator>(
+        facilitator: &F,
+        network: Option<Network>,
+    ) -> Result<Vec<&EvmProvider>, Response> {
+        let Some(provider_cache) = facilitator.provider_cache() else {
+            return Err(StatusCode::NOT_FOUND.into_response());
+        };
+        let providers = provider_cache
+            .into_iter()
+            .filter(|(n, _)| network.is_none_or(|network| network == **n))
+            .filter_map(|(_, provider)| match provider {
+                NetworkProvider::Evm(provider) => Some(provider),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        if let Some(network) = network
+            && providers.is_empty()
+        {
+            return Err(error(
+                StatusCode::BAD_REQUEST,
+                format!("No EVM provider configured for {network}"),
+            ));
+        }
+        Ok(providers)
+    }
+
+    /// Signers of the configuration (`SIGNER_TYPE`), read again, e.g. once a KMS key is rotated.
+    async fn configured_signers() -> Result<Vec<Arc<dyn SettlementSigner>>, Response> {
+        let signer_type = SignerType::from_env()
+            .map_err(|e| error(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+        signer_type
+            .make_evm_signers()
+            .await
+            .map_err(|e| error(StatusCode::BAD_GATEWAY, e.to_string()))
+    }
+
+    /// `GET /admin/signers`: settlement accounts of every EVM network.
+    pub async fn get_signers<F: ServedFacilitator>(
+        Extension(facilitator): Extension<F>,
+    ) -> Response {
+        let providers = match evm_providers(&facilitator, None) {
+            Ok(providers) => providers,
+            Err(response) => return response,
+        };
+        let signers = providers
+            .into_iter()
+            .map(|provider| NetworkSigners {
+                network: provider.network(),
+                accounts: provider.signers().addresses(),
+                retired: provider.signers().retired_addresses(),
+            })
+            .collect::<Vec<_>>();
+        Json(signers).into_response()
+    }
+
+    /// `POST /admin/signers/rotate`: replaces the settlement accounts with those of the private
+    /// keys of the body, or of the signer configuration without a body.
+    pub async fn post_signers_rotate<F: ServedFacilitator>(
+        Extension(facilitator): Extension<F>,
+        Query(query): Query<SignersQuery>,
+        body: Option<Json<RotateSigners>>,
+    ) -> Response {
+        let providers = match evm_providers(&facilitator, query.network) {
+            Ok(providers) => providers,
+            Err(response) => return response,
+        };
+        let signers = match body {
+            Some(Json(body)) => signer_pool::private_key_signers(&body.private_keys.join(","))
+                .map(|signers| {
+                    signers
+                        .into_iter()
+                        .map(|signer| Arc::new(signer) as Arc<dyn SettlementSigner>)
+                        .collect()
+                })
+                .map_err(|e| error(StatusCode::BAD_REQUEST, e.to_string())),
+            None => configured_signers().await,
+        };
+        let signers = match signers {
+            Ok(signers) => signers,
+            Err(response) => return response,
+        };
+        let wallet = match provider_cache::evm_wallet(signers) {
+            Ok(wallet) => wallet,
+            Err(e) => return error(StatusCode::BAD_REQUEST, e.to_string()),
+        };
+        let rotations = providers
+            .into_iter()
+            .map(|provider| provider.rotate_signers(wallet.clone()))
+            .collect::<Vec<_>>();
+        Json(rotations).into_response()
+    }
+}
+
+/// Runtime adjustment of the rate limits, see [`rate_limit`](crate::handlers::rate_limit).
+/// Handlers expect the [`RateLimitsControl`] as an [`Extension`] layer.
+mod rate_limits {
+    use axum::http::StatusCode;
+    use axum::response::{IntoResponse, Response};
+    use axum::{Extension, Json};
+    use serde::{Deserialize, Serialize};
+    use std::collections::{BTreeMap, HashMap};
+
+    use super::nonces::error;
+    use crate::handlers::RateLimitsControl;
+    use crate::handlers::router::Endpoint;
+
+    #[derive(Debug, Serialize)]
+    #[serde(rename_all = "camelCase")]
+    pub struct RateLimitsView {
+        /// Requests per second allowed to each client address, by limited endpoint.
+        pub per_ip: BTreeMap<String, u32>,
+        /// Requests per second allowed to each API key, by limited endpoint.
+        pub per_key: BTreeMap<String, u32>,
+        /// How clients are told apart, `peer` or `forwarded`.
+        pub key: String,
+    }
+
+    /// Limits to change, by endpoint; `0` lifts the limit of an endpoint.
+    #[derive(Debug, Deserialize)]
+    #[serde(rename_all = "camelCase")]
+    pub struct RateLimitsPatch {
+        #[serde(default)]
+        pub per_ip: BTreeMap<String, u32>,
+        #[serde(default)]
+        pub per_key: BTreeMap<String, u32>,
+    }
+
+    fn by_name(limits: &HashMap<Endpoint, u32>) -> BTreeMap<String, u32> {
+        limits
+            .iter()
+            .map(|(endpoint, per_second)| (endpoint.to_string(), *per_second))
+            .collect()
+    }
+
+    /// `GET /admin/rate-limits`: rate limits enforced, `404 Not Found` if requests are not
+    /// limited.
+    pub async fn get_rate_limits(control: Option<Extension<RateLimitsControl>>) -> Response {
+        let Some(Extension(control)) = control else {
+            return StatusCode::NOT_FOUND.into_response();
+        };
+        let limits = control.limits();
+        Json(RateLimitsView {
+            per_ip: by_name(limits.per_second()),
+            per_key: by_name(limits.per_key()),
+            key: limits.key().to_string(),
+        })
+        .into_response()
+    }
+
+    /// `PUT /admin/rate-limits`: changes the limits of the endpoints of the body, keeping the
+    /// others.
+    pub async fn put_rate_limits(
+        control: Option<Extension<RateLimitsControl>>,
+        Json(patch): Json<RateLimitsPatch>,
+    ) -> Response {
+        let Some(Extension(control)) = control else {
+            return StatusCode::NOT_FOUND.into_response();
+        };
+        let mut limits = control.limits();
+        for (endpoint, per_second) in &patch.per_ip {
+            match endpoint.parse::<Endpoint>() {
+                Ok(endpoint) => limits = limits.with_limit(endpoint, *per_second),
+                Err(e) => return error(StatusCode::BAD_REQUEST, e.to_string()),
+            }
+        }
+        for (endpoint, per_second) in &patch.per_key {
+            match endpoint.parse::<Endpoint>() {
+                Ok(endpoint) => limits = limits.with_key_limit(endpoint, *per_second),
+                Err(e) => return error(StatusCode::BAD_REQUEST, e.to_string()),
+            }
+        }
+        control.set(&limits);
+        get_rate_limits(Some(Extension(control))).await
+    }
+}
+
+#[cfg(feature = "chaos")]
+mod faults {
+    use axum::http::StatusCode;
+    use axum::response::Response;
+    use axum::{Extension, Json, response::IntoResponse};
+
+    use crate::chaos::{FaultConfig, FaultInjector};
+    use crate::handlers::ServedFacilitator;
+
+    /// Runs `f` on the fault injector of the facilitator, `404 Not Found` if it has none.
+    fn with_faults<F: ServedFacilitator>(
+        facilitator: &F,
+        f: impl FnOnce(&FaultInjector),
+    ) -> Response {
+        match facilitator.faults() {
+            Some(faults) => {
+                f(faults);
+                Json(faults.config()).into_response()
+            }
+            None => StatusCode::NOT_FOUND.into_response(),
+        }
+    }
+
+    /// `GET /admin/faults`: current fault injection config.
+    pub async fn get_faults<F: ServedFacilitator>(
+        Extension(facilitator): Extension<F>,
+    ) -> impl IntoResponse {
+        with_faults(&facilitator, |_| {})
+    }
+
+    /// `PUT /admin/faults`: replaces the fault injection config.
+    pub async fn put_faults<F: ServedFacilitator>(
+        Extension(facilitator): Extension<F>,
+        Json(config): Json<FaultConfig>,
+    ) -> impl IntoResponse {
+        with_faults(&facilitator, |faults| faults.set_config(config))
+    }
+
+    /// `DELETE /admin/faults`: disables all injected faults.
+    pub async fn delete_faults<F: ServedFacilitator>(
+        Extension(facilitator): Extension<F>,
+    ) -> impl IntoResponse {
+        with_faults(&facilitator, |faults| {
+            faults.set_config(FaultConfig::default())
+        })
+    }
+}
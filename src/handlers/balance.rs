@@ -0,0 +1,53 @@
+//! Prepaid balances: `GET /balance`, see [`crate::balance`].
+
+use axum::http::{HeaderMap, StatusCode};
+use axum::response::Response;
+use axum::{Extension, Json, response::IntoResponse};
+use tracing::instrument;
+
+use crate::balance::{BALANCE_HEADER, BalanceError};
+use crate::handlers::ServedFacilitator;
+use crate::types::ErrorResponse;
+
+impl IntoResponse for BalanceError {
+    fn into_response(self) -> Response {
+        let status = match self {
+            BalanceError::UnknownBalance => StatusCode::NOT_FOUND,
+            BalanceError::Insufficient { .. } | BalanceError::NoMatchingCharge => {
+                StatusCode::PAYMENT_REQUIRED
+            }
+            BalanceError::UnsupportedScheme | BalanceError::DepositRejected(_) => {
+                StatusCode::BAD_REQUEST
+            }
+            BalanceError::Unavailable(_) => StatusCode::SERVICE_UNAVAILABLE,
+        };
+        let error = ErrorResponse {
+            error: self.to_string(),
+        };
+        (status, Json(error)).into_response()
+    }
+}
+
+/// `GET /balance`: the prepaid balance whose token is the `X-Payment-Balance` header.
+#[instrument(skip_all)]
+pub async fn get_balance<F: ServedFacilitator>(
+    Extension(facilitator): Extension<F>,
+    headers: HeaderMap,
+) -> Response {
+    let Some(ledger) = facilitator.balances() else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+    let Some(token) = headers
+        .get(BALANCE_HEADER)
+        .and_then(|token| token.to_str().ok())
+    else {
+        let error = ErrorResponse {
+            error: format!("Missing {BALANCE_HEADER} header"),
+        };
+        return (StatusCode::BAD_REQUEST, Json(error)).into_response();
+    };
+    match ledger.get(token) {
+        Some(account) => Json(account).into_response(),
+        None => BalanceError::UnknownBalance.into_response(),
+    }
+}
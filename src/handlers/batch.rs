@@ -0,0 +1,238 @@
+//! Batch verification and settlement: `POST /verify/batch`, and the `x402.verifyBatch` and
+//! `x402.settleBatch` WebSocket methods.
+//!
+//! Sellers that accumulate slice payments of a stream can validate and settle them in bulk.
+//! Items are processed concurrently, up to a server-side concurrency limit, and responses are
+//! returned in request order. A failed or timed out item does not fail the batch.
+//!
+//! Environment:
+//! - `VERIFY_BATCH_MAX_SIZE` – Maximum number of items in a verification batch (default `100`)
+//! - `VERIFY_BATCH_ITEM_TIMEOUT_MS` – Timeout of every verified item, in milliseconds or with a
+//!   unit (e.g. `5s`), up to 5 minutes (default `10000`)
+//! - `VERIFY_BATCH_CONCURRENCY` – Items of a batch verified at once (default `32`)
+//! - `SETTLE_BATCH_MAX_SIZE` – Maximum number of items in a settlement batch (default `100`)
+//! - `SETTLE_BATCH_CONCURRENCY` – Items of a batch settled at once (default `8`)
+//!
+//! Settled items are not bounded by a timeout: abandoning a settlement midway could leave a
+//! transaction in flight without reporting it. In [deferred](crate::settlement_queue::SettleMode)
+//! settlement mode, items are verified and queued as by `x402.settle`, and answered with their
+//! progress.
+
+use axum::http::StatusCode;
+use axum::{Extension, Json, response::IntoResponse};
+use futures_util::StreamExt;
+use futures_util::stream;
+use once_cell::sync::Lazy;
+use serde::Serialize;
+use std::env;
+use std::time::Duration;
+use tracing::instrument;
+
+use crate::duration::{self, DurationRange};
+use crate::handlers::ServedFacilitator;
+use crate::handlers::map_error_to_verify_response;
+use crate::settlement_queue::{DeferredSettlement, SettlementQueue};
+use crate::types::{
+    ErrorResponse, FacilitatorErrorReason, SettleRequest, SettleResponse, VerifyRequest,
+    VerifyResponse,
+};
+use crate::ws_error::WsErrorCode;
+
+const ENV_VERIFY_BATCH_MAX_SIZE: &str = "VERIFY_BATCH_MAX_SIZE";
+const ENV_VERIFY_BATCH_ITEM_TIMEOUT_MS: &str = "VERIFY_BATCH_ITEM_TIMEOUT_MS";
+const ENV_VERIFY_BATCH_CONCURRENCY: &str = "VERIFY_BATCH_CONCURRENCY";
+const ENV_SETTLE_BATCH_MAX_SIZE: &str = "SETTLE_BATCH_MAX_SIZE";
+const ENV_SETTLE_BATCH_CONCURRENCY: &str = "SETTLE_BATCH_CONCURRENCY";
+
+pub(super) fn env_or(name: &str, default: usize) -> usize {
+    env::var(name)
+        .ok()
+        .and_then(|s| s.parse().ok())
+        .filter(|value| *value > 0)
+        .unwrap_or(default)
+}
+
+static VERIFY_BATCH_MAX_SIZE: Lazy<usize> = Lazy::new(|| env_or(ENV_VERIFY_BATCH_MAX_SIZE, 100));
+static VERIFY_BATCH_CONCURRENCY: Lazy<usize> =
+    Lazy::new(|| env_or(ENV_VERIFY_BATCH_CONCURRENCY, 32));
+static SETTLE_BATCH_MAX_SIZE: Lazy<usize> = Lazy::new(|| env_or(ENV_SETTLE_BATCH_MAX_SIZE, 100));
+static SETTLE_BATCH_CONCURRENCY: Lazy<usize> =
+    Lazy::new(|| env_or(ENV_SETTLE_BATCH_CONCURRENCY, 8));
+
+/// Item timeouts accepted from `VERIFY_BATCH_ITEM_TIMEOUT_MS`.
+const ITEM_TIMEOUT_RANGE: DurationRange =
+    DurationRange::new(Duration::from_millis(1), Duration::from_secs(300));
+
+static VERIFY_BATCH_ITEM_TIMEOUT: Lazy<Duration> = Lazy::new(|| {
+    duration::from_env(
+        ENV_VERIFY_BATCH_ITEM_TIMEOUT_MS,
+        duration::MILLISECOND,
+        ITEM_TIMEOUT_RANGE,
+    )
+    .inspect_err(|e| tracing::warn!(error = %e, "Ignoring {ENV_VERIFY_BATCH_ITEM_TIMEOUT_MS}"))
+    .ok()
+    .flatten()
+    .unwrap_or(Duration::from_millis(10_000))
+});
+
+/// Error returned when a batch can not be processed as a whole.
+#[derive(Debug, thiserror::Error)]
+pub(crate) enum BatchError {
+    #[error("Batch of {0} items exceeds the maximum of {1}")]
+    TooLarge(usize, usize),
+}
+
+/// Outcome of one item of `x402.settleBatch`, shaped like the reply to `x402.settle`:
+/// `{ result: SettleResponse }`, `{ result: DeferredSettlement }` in deferred settlement mode,
+/// or `{ error: { code: 1001, message, data: VerifyResponse } }`.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) enum SettleBatchItem {
+    Result(Box<SettleResponse>),
+    #[serde(rename = "result")]
+    Queued(Box<DeferredSettlement>),
+    Error {
+        code: WsErrorCode,
+        message: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        data: Option<VerifyResponse>,
+    },
+}
+
+impl SettleBatchItem {
+    /// Failed settlement, for the reason in `data`.
+    fn failed(data: VerifyResponse) -> Self {
+        SettleBatchItem::Error {
+            code: WsErrorCode::PAYMENT_FAILED,
+            message: "Settlement failed".to_string(),
+            data: Some(data),
+        }
+    }
+}
+
+/// Verifies `requests` concurrently, each bounded by the per-item timeout.
+///
+/// Returns one [`VerifyResponse`] per request, in request order. Failed verifications are mapped
+/// to invalid responses as for `POST /verify`, timed out ones to [`FacilitatorErrorReason::Timeout`].
+pub(crate) async fn verify_batch<F: ServedFacilitator>(
+    facilitator: &F,
+    requests: &[VerifyRequest],
+) -> Result<Vec<VerifyResponse>, BatchError> {
+    let max_size = *VERIFY_BATCH_MAX_SIZE;
+    if requests.len() > max_size {
+        return Err(BatchError::TooLarge(requests.len(), max_size));
+    }
+    let timeout = *VERIFY_BATCH_ITEM_TIMEOUT;
+    let verifications = requests
+        .iter()
+        .enumerate()
+        .map(|(index, request)| async move {
+            match tokio::time::timeout(timeout, facilitator.verify(request)).await {
+                Ok(Ok(response)) => response,
+                Ok(Err(error)) => {
+                    tracing::warn!(index, error = ?error, "Batch item verification failed");
+                    map_error_to_verify_response(error)
+                }
+                Err(_) => {
+                    tracing::warn!(index, ?timeout, "Batch item verification timed out");
+                    VerifyResponse::invalid(None, FacilitatorErrorReason::Timeout)
+                }
+            }
+        })
+        .collect::<Vec<_>>();
+    Ok(stream::iter(verifications)
+        .buffered(*VERIFY_BATCH_CONCURRENCY)
+        .collect()
+        .await)
+}
+
+/// Settles `requests` concurrently, or verifies and queues them if the facilitator defers
+/// settlement.
+///
+/// Returns one [`SettleBatchItem`] per request, in request order. Failed settlements are mapped
+/// to errors as for `x402.settle`.
+pub(crate) async fn settle_batch<F: ServedFacilitator>(
+    facilitator: &F,
+    requests: &[SettleRequest],
+) -> Result<Vec<SettleBatchItem>, BatchError> {
+    let max_size = *SETTLE_BATCH_MAX_SIZE;
+    if requests.len() > max_size {
+        return Err(BatchError::TooLarge(requests.len(), max_size));
+    }
+    let settlements = requests
+        .iter()
+        .enumerate()
+        .map(|(index, request)| async move {
+            let deferred = facilitator
+                .settlement_queue()
+                .zip(request.payment_payload.id());
+            if let Some((queue, payment_id)) = deferred {
+                return enqueue(facilitator, queue, &payment_id, request, index).await;
+            }
+            match facilitator.settle(request).await {
+                Ok(response) => SettleBatchItem::Result(Box::new(response)),
+                Err(error) => {
+                    tracing::warn!(index, error = ?error, "Batch item settlement failed");
+                    SettleBatchItem::failed(map_error_to_verify_response(error))
+                }
+            }
+        })
+        .collect::<Vec<_>>();
+    Ok(stream::iter(settlements)
+        .buffered(*SETTLE_BATCH_CONCURRENCY)
+        .collect()
+        .await)
+}
+
+/// Verifies the payment of item `index`, and queues its settlement, as `x402.settle` does in
+/// deferred settlement mode. Invalid payments are answered like failed settlements, and never
+/// queued.
+async fn enqueue<F: ServedFacilitator>(
+    facilitator: &F,
+    queue: &SettlementQueue,
+    payment_id: &str,
+    request: &SettleRequest,
+    index: usize,
+) -> SettleBatchItem {
+    let verification = match facilitator.verify(request).await {
+        Ok(verification) => verification,
+        Err(error) => {
+            tracing::warn!(index, error = ?error, "Batch item verification before queuing failed");
+            map_error_to_verify_response(error)
+        }
+    };
+    if let VerifyResponse::Invalid { .. } = verification {
+        return SettleBatchItem::failed(verification);
+    }
+    match queue.enqueue(payment_id, request).await {
+        Ok(settlement) => SettleBatchItem::Queued(Box::new(settlement)),
+        Err(error) => {
+            tracing::warn!(index, %error, payment_id, "Queuing the batch item settlement failed");
+            SettleBatchItem::Error {
+                code: WsErrorCode::INTERNAL_ERROR,
+                message: "Internal error".to_string(),
+                data: None,
+            }
+        }
+    }
+}
+
+/// `POST /verify/batch`: verifies an array of [`VerifyRequest`]s concurrently.
+///
+/// Responds with an array of [`VerifyResponse`]s, in request order.
+#[instrument(skip_all, fields(batch_size = body.len()))]
+pub async fn post_verify_batch<F: ServedFacilitator>(
+    Extension(facilitator): Extension<F>,
+    Json(body): Json<Vec<VerifyRequest>>,
+) -> impl IntoResponse {
+    match verify_batch(&facilitator, &body).await {
+        Ok(responses) => (StatusCode::OK, Json(responses)).into_response(),
+        Err(error) => (
+            StatusCode::BAD_REQUEST,
+            Json(ErrorResponse {
+                error: error.to_string(),
+            }),
+        )
+            .into_response(),
+    }
+}
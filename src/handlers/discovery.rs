@@ -0,0 +1,56 @@
+//! Resource discovery: `GET /discovery/resources` and `POST /discovery/resources`, see
+//! [`crate::discovery`]. Listing is also served as `x402.discovery.list` over WebSocket.
+
+use axum::extract::Query;
+use axum::http::StatusCode;
+use axum::response::Response;
+use axum::{Extension, Json, response::IntoResponse};
+use tracing::instrument;
+
+use crate::discovery::{DiscoveryError, DiscoveryQuery, DiscoveryRegistration};
+use crate::handlers::ServedFacilitator;
+use crate::types::ErrorResponse;
+
+impl IntoResponse for DiscoveryError {
+    fn into_response(self) -> Response {
+        let status = match self {
+            DiscoveryError::NoPaymentRequirements => StatusCode::BAD_REQUEST,
+            DiscoveryError::ClockError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        };
+        let error = ErrorResponse {
+            error: self.to_string(),
+        };
+        (status, Json(error)).into_response()
+    }
+}
+
+/// `GET /discovery/resources`: lists the registered resources matching the query string,
+/// e.g. `?network=base-sepolia&maxPrice=10000&limit=20`.
+#[instrument(skip_all)]
+pub async fn get_discovery_resources<F: ServedFacilitator>(
+    Extension(facilitator): Extension<F>,
+    Query(query): Query<DiscoveryQuery>,
+) -> Response {
+    match facilitator.discovery() {
+        Some(discovery) => Json(discovery.list(&query)).into_response(),
+        None => StatusCode::NOT_FOUND.into_response(),
+    }
+}
+
+/// `POST /discovery/resources`: registers a resource, or refreshes its registration.
+#[instrument(skip_all, fields(resource = %body.resource))]
+pub async fn post_discovery_resources<F: ServedFacilitator>(
+    Extension(facilitator): Extension<F>,
+    Json(body): Json<DiscoveryRegistration>,
+) -> Response {
+    let Some(discovery) = facilitator.discovery() else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+    match discovery.register(body) {
+        Ok(resource) => {
+            tracing::info!("Registered discoverable resource");
+            Json(resource).into_response()
+        }
+        Err(error) => error.into_response(),
+    }
+}
@@ -0,0 +1,484 @@
+//! Health and readiness probes: `GET /healthz` and `GET /readyz`, e.g. for Kubernetes.
+//!
+//! Both run the same checks, concurrently, and answer with the outcome of each:
+//! - `rpc` – the RPC endpoint of every network served answers, with its latest block (EVM) or
+//!   slot (Solana); networks of custom chain providers are not checked
+//! - `gas` – the native token balance of every settlement account (each account of the signer
+//!   pool on EVM, the fee payer on Solana) is above the gas threshold of its network, if any:
+//!   below twice the threshold, the check warns
+//! - `nonce-store`, `payment-history` – the storage of the nonces of settled payments and of the
+//!   payment history answers
+//! - `settlement-queue` – the store of the settlement queue answers, with at most the maximum
+//!   depth of payments queued: over half of it, the check warns
+//!
+//! Checks not applying to the facilitator served, e.g. the settlement queue of a facilitator
+//! settling payments as they come, are left out. A check not answering within the check timeout
+//! fails.
+//!
+//! `/healthz` answers `200 OK` as long as the facilitator serves requests, whatever the outcome
+//! of the checks: restarting the facilitator would not fix a failing dependency. `/readyz` answers
+//! `503 Service Unavailable` once any check fails, or the shutdown started (see
+//! [`Shutdown`]), so that the instance is taken out of the load balancer until it recovers.
+//!
+//! ```json
+//! {
+//!   "status": "fail",
+//!   "checks": [
+//!     { "name": "rpc", "network": "base-sepolia", "status": "pass", "observed": "28131722", "durationMs": 84 },
+//!     { "name": "gas", "network": "base-sepolia", "account": "0x…", "status": "fail",
+//!       "observed": "1200000000000", "threshold": "5000000000000000",
+//!       "message": "Balance below the gas threshold", "durationMs": 91 }
+//!   ]
+//! }
+//! ```
+//!
+//! Environment (see [`HealthChecks::from_env`]):
+//! - `HEALTH_MIN_GAS_BALANCE` – Gas threshold of every network, in base units of its native token
+//!   (wei, lamports) (default: none, balances are reported without being checked)
+//! - `HEALTH_MIN_GAS_BALANCE_<NETWORK>` – Gas threshold of one network, e.g.
+//!   `HEALTH_MIN_GAS_BALANCE_BASE_SEPOLIA`
+//! - `HEALTH_MAX_QUEUE_DEPTH` – Maximum depth of the settlement queue (default `1000`)
+//! - `HEALTH_CHECK_TIMEOUT_MS` – Time given to every check, up to 1 minute (default `2000`)
+
+use alloy::primitives::U256;
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use axum::{Extension, Json};
+use futures_util::FutureExt;
+use futures_util::future::{BoxFuture, join_all};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::env;
+use std::time::Duration;
+use tokio::time::Instant;
+use tracing::instrument;
+
+use crate::chain::{NetworkProvider, NetworkProviderOps};
+use crate::duration::{self, DurationError, DurationRange};
+use crate::handlers::router::{Endpoint, FacilitatorRoutes};
+use crate::handlers::{ServedFacilitator, Shutdown};
+use crate::network::Network;
+use crate::storage::PaymentQuery;
+use crate::types::MixedAddress;
+
+const ENV_HEALTH_MIN_GAS_BALANCE: &str = "HEALTH_MIN_GAS_BALANCE";
+const ENV_HEALTH_MAX_QUEUE_DEPTH: &str = "HEALTH_MAX_QUEUE_DEPTH";
+const ENV_HEALTH_CHECK_TIMEOUT_MS: &str = "HEALTH_CHECK_TIMEOUT_MS";
+
+const DEFAULT_MAX_QUEUE_DEPTH: usize = 1000;
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(2);
+/// Check timeouts accepted: up to 1 minute.
+pub const TIMEOUT_RANGE: DurationRange =
+    DurationRange::new(Duration::from_millis(1), Duration::from_secs(60));
+
+/// Key looked up in the nonce store to check it answers, never reserved.
+const PROBE_NONCE_KEY: &str = "health-probe";
+
+#[derive(Debug, thiserror::Error)]
+pub enum HealthConfigError {
+    #[error("Invalid {0}: {1}, expected an amount in base units of the native token")]
+    InvalidGasBalance(String, String),
+    #[error("Invalid {ENV_HEALTH_MAX_QUEUE_DEPTH}: {0}, expected a number of payments")]
+    InvalidQueueDepth(String),
+    #[error(transparent)]
+    InvalidDuration(#[from] DurationError),
+}
+
+/// Outcome of a check, the worst of the checks for a report.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HealthStatus {
+    Pass,
+    Warn,
+    Fail,
+}
+
+/// A check of a dependency of the facilitator.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HealthCheck {
+    pub name: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub network: Option<Network>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub account: Option<MixedAddress>,
+    pub status: HealthStatus,
+    /// Value observed, e.g. the latest block, a balance or the depth of the queue.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub observed: Option<String>,
+    /// Limit the observed value is checked against.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub threshold: Option<String>,
+    /// Reason the check warns or fails.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+    pub duration_ms: u64,
+}
+
+/// Outcome of the checks, answered by `/healthz` and `/readyz`.
+#[derive(Debug, Clone, Serialize)]
+pub struct HealthReport {
+    pub status: HealthStatus,
+    pub checks: Vec<HealthCheck>,
+}
+
+/// Outcome of a check, before it is timed.
+struct Outcome {
+    status: HealthStatus,
+    observed: Option<String>,
+    threshold: Option<String>,
+    message: Option<String>,
+}
+
+impl Outcome {
+    fn pass(observed: Option<String>) -> Self {
+        Self {
+            status: HealthStatus::Pass,
+            observed,
+            threshold: None,
+            message: None,
+        }
+    }
+
+    fn fail(message: String) -> Self {
+        Self {
+            status: HealthStatus::Fail,
+            observed: None,
+            threshold: None,
+            message: Some(message),
+        }
+    }
+
+    /// A gas `balance`, failing below `threshold`, and warning below twice the threshold.
+    fn gas(balance: U256, threshold: Option<U256>) -> Self {
+        let (status, message) = match threshold {
+            Some(threshold) if balance < threshold => {
+                (HealthStatus::Fail, Some("Balance below the gas threshold"))
+            }
+            Some(threshold) if balance < threshold.saturating_mul(U256::from(2)) => (
+                HealthStatus::Warn,
+                Some("Balance below twice the gas threshold"),
+            ),
+            _ => (HealthStatus::Pass, None),
+        };
+        Self {
+            status,
+            observed: Some(balance.to_string()),
+            threshold: threshold.map(|threshold| threshold.to_string()),
+            message: message.map(str::to_string),
+        }
+    }
+
+    /// A queue `depth`, failing over `max`, and warning over half of it.
+    fn depth(depth: usize, max: usize) -> Self {
+        let (status, message) = if depth > max {
+            (HealthStatus::Fail, Some("Queue deeper than its maximum"))
+        } else if depth > max / 2 {
+            (
+                HealthStatus::Warn,
+                Some("Queue deeper than half its maximum"),
+            )
+        } else {
+            (HealthStatus::Pass, None)
+        };
+        Self {
+            status,
+            observed: Some(depth.to_string()),
+            threshold: Some(max.to_string()),
+            message: message.map(str::to_string),
+        }
+    }
+}
+
+/// Checks run by the probes, see the [module documentation](self).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HealthChecks {
+    min_gas_balance: Option<U256>,
+    networks: HashMap<Network, U256>,
+    max_queue_depth: usize,
+    timeout: Duration,
+}
+
+impl Default for HealthChecks {
+    fn default() -> Self {
+        Self {
+            min_gas_balance: None,
+            networks: HashMap::new(),
+            max_queue_depth: DEFAULT_MAX_QUEUE_DEPTH,
+            timeout: DEFAULT_TIMEOUT,
+        }
+    }
+}
+
+impl HealthChecks {
+    /// Checks without gas threshold, a maximum queue depth of 1000 and a timeout of 2 seconds.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Reads the checks from `HEALTH_MIN_GAS_BALANCE`, `HEALTH_MIN_GAS_BALANCE_<NETWORK>`,
+    /// `HEALTH_MAX_QUEUE_DEPTH` and `HEALTH_CHECK_TIMEOUT_MS`.
+    pub fn from_env() -> Result<Self, HealthConfigError> {
+        let mut checks = Self::new();
+        if let Some(balance) = gas_balance_from_env(ENV_HEALTH_MIN_GAS_BALANCE)? {
+            checks = checks.with_min_gas_balance(balance);
+        }
+        for network in Network::variants() {
+            let suffix = network.to_string().to_uppercase().replace('-', "_");
+            let name = format!("{ENV_HEALTH_MIN_GAS_BALANCE}_{suffix}");
+            if let Some(balance) = gas_balance_from_env(&name)? {
+                checks = checks.with_network_min_gas_balance(*network, balance);
+            }
+        }
+        if let Ok(depth) = env::var(ENV_HEALTH_MAX_QUEUE_DEPTH) {
+            let depth = depth
+                .trim()
+                .parse()
+                .map_err(|_| HealthConfigError::InvalidQueueDepth(depth))?;
+            checks = checks.with_max_queue_depth(depth);
+        }
+        if let Some(timeout) = duration::from_env(
+            ENV_HEALTH_CHECK_TIMEOUT_MS,
+            duration::MILLISECOND,
+            TIMEOUT_RANGE,
+        )? {
+            checks = checks.with_timeout(timeout)?;
+        }
+        Ok(checks)
+    }
+
+    /// Fails the `gas` check of every network whose settlement accounts hold less than
+    /// `balance`, in base units of its native token.
+    pub fn with_min_gas_balance(&self, balance: U256) -> Self {
+        Self {
+            min_gas_balance: Some(balance),
+            ..self.clone()
+        }
+    }
+
+    /// Fails the `gas` check of `network` if its settlement accounts hold less than `balance`,
+    /// instead of the threshold of every network.
+    pub fn with_network_min_gas_balance(&self, network: Network, balance: U256) -> Self {
+        let mut networks = self.networks.clone();
+        networks.insert(network, balance);
+        Self {
+            networks,
+            ..self.clone()
+        }
+    }
+
+    /// Fails the `settlement-queue` check once more than `depth` payments are queued.
+    pub fn with_max_queue_depth(&self, depth: usize) -> Self {
+        Self {
+            max_queue_depth: depth,
+            ..self.clone()
+        }
+    }
+
+    /// Fails the checks not answering within `timeout`.
+    pub fn with_timeout(&self, timeout: Duration) -> Result<Self, DurationError> {
+        Ok(Self {
+            timeout: TIMEOUT_RANGE.check(timeout)?,
+            ..self.clone()
+        })
+    }
+
+    /// Gas threshold of `network`, if any.
+    pub fn min_gas_balance(&self, network: Network) -> Option<U256> {
+        self.networks
+            .get(&network)
+            .copied()
+            .or(self.min_gas_balance)
+    }
+
+    /// Runs these checks on the probes of `routes`.
+    pub fn apply(&self, routes: &FacilitatorRoutes) -> FacilitatorRoutes {
+        routes.layer(Endpoint::Health, Extension(self.clone()))
+    }
+
+    /// Runs the checks applying to `facilitator`, concurrently.
+    pub async fn run<F: ServedFacilitator>(&self, facilitator: &F) -> HealthReport {
+        let mut checks: Vec<BoxFuture<'_, HealthCheck>> = Vec::new();
+        if let Some(provider_cache) = facilitator.provider_cache() {
+            let mut providers = provider_cache.into_iter().collect::<Vec<_>>();
+            providers.sort_by_key(|(network, _)| network.to_string());
+            for (network, provider) in providers {
+                let network = *network;
+                let threshold = self.min_gas_balance(network);
+                match provider {
+                    NetworkProvider::Evm(provider) => {
+                        let rpc = async move {
+                            let block = provider.block_number().await;
+                            block
+                                .map(|block| Outcome::pass(Some(block.to_string())))
+                                .map_err(|e| e.to_string())
+                        };
+                        checks.push(self.check("rpc", Some(network), None, rpc).boxed());
+                        for account in provider.signers().addresses() {
+                            let gas = async move {
+                                let balance = provider.native_balance(account).await;
+                                balance
+                                    .map(|balance| Outcome::gas(balance, threshold))
+                                    .map_err(|e| e.to_string())
+                            };
+                            let account = Some(account.into());
+                            checks.push(self.check("gas", Some(network), account, gas).boxed());
+                        }
+                    }
+                    NetworkProvider::Solana(provider) => {
+                        let rpc = async move {
+                            let slot = provider.slot().await;
+                            slot.map(|slot| Outcome::pass(Some(slot.to_string())))
+                                .map_err(|e| e.to_string())
+                        };
+                        checks.push(self.check("rpc", Some(network), None, rpc).boxed());
+                        let gas = async move {
+                            let balance = provider.fee_payer_balance().await;
+                            balance
+                                .map(|balance| Outcome::gas(U256::from(balance), threshold))
+                                .map_err(|e| e.to_string())
+                        };
+                        let account = Some(provider.signer_address());
+                        checks.push(self.check("gas", Some(network), account, gas).boxed());
+                    }
+                    NetworkProvider::Custom(_) => {}
+                }
+            }
+        }
+        if let Some(store) = facilitator.nonce_store() {
+            let nonce_store = async move {
+                let contains = store.contains(PROBE_NONCE_KEY).await;
+                contains
+                    .map(|_| Outcome::pass(None))
+                    .map_err(|e| e.to_string())
+            };
+            checks.push(self.check("nonce-store", None, None, nonce_store).boxed());
+        }
+        if let Some(queue) = facilitator.settlement_queue() {
+            let max_queue_depth = self.max_queue_depth;
+            let settlement_queue = async move {
+                let pending = queue.pending().await;
+                pending
+                    .map(|pending| Outcome::depth(pending.len(), max_queue_depth))
+                    .map_err(|e| e.to_string())
+            };
+            checks.push(
+                self.check("settlement-queue", None, None, settlement_queue)
+                    .boxed(),
+            );
+        }
+        if let Some(history) = facilitator.payment_history() {
+            let payment_history = async move {
+                let query = PaymentQuery {
+                    limit: Some(1),
+                    ..PaymentQuery::default()
+                };
+                let page = history.query(&query).await;
+                page.map(|_| Outcome::pass(None)).map_err(|e| e.to_string())
+            };
+            checks.push(
+                self.check("payment-history", None, None, payment_history)
+                    .boxed(),
+            );
+        }
+        let checks = join_all(checks).await;
+        let status = checks
+            .iter()
+            .map(|check| check.status)
+            .max()
+            .unwrap_or(HealthStatus::Pass);
+        HealthReport { status, checks }
+    }
+
+    /// Runs `check`, failing it if it does not answer within the timeout.
+    async fn check(
+        &self,
+        name: &'static str,
+        network: Option<Network>,
+        account: Option<MixedAddress>,
+        check: impl Future<Output = Result<Outcome, String>>,
+    ) -> HealthCheck {
+        let start = Instant::now();
+        let outcome = match tokio::time::timeout(self.timeout, check).await {
+            Ok(Ok(outcome)) => outcome,
+            Ok(Err(error)) => Outcome::fail(error),
+            Err(_) => Outcome::fail(format!("No answer within {}ms", self.timeout.as_millis())),
+        };
+        if outcome.status == HealthStatus::Fail {
+            tracing::warn!(
+                check = name,
+                network = network.map(tracing::field::display),
+                account = account.as_ref().map(tracing::field::display),
+                message = outcome.message.as_deref(),
+                "Health check failed"
+            );
+        }
+        HealthCheck {
+            name,
+            network,
+            account,
+            status: outcome.status,
+            observed: outcome.observed,
+            threshold: outcome.threshold,
+            message: outcome.message,
+            duration_ms: start.elapsed().as_millis() as u64,
+        }
+    }
+}
+
+/// Reads the gas threshold `name`, if set.
+fn gas_balance_from_env(name: &str) -> Result<Option<U256>, HealthConfigError> {
+    match env::var(name) {
+        Ok(value) if !value.trim().is_empty() => value
+            .trim()
+            .parse()
+            .map(Some)
+            .map_err(|_| HealthConfigError::InvalidGasBalance(name.to_string(), value)),
+        _ => Ok(None),
+    }
+}
+
+/// `GET /healthz`: outcome of the checks, with `200 OK` whatever it is.
+#[instrument(skip_all)]
+pub async fn get_healthz<F: ServedFacilitator>(
+    Extension(facilitator): Extension<F>,
+    checks: Option<Extension<HealthChecks>>,
+) -> Response {
+    let checks = checks.map(|Extension(checks)| checks).unwrap_or_default();
+    Json(checks.run(&facilitator).await).into_response()
+}
+
+/// `GET /readyz`: outcome of the checks, with `503 Service Unavailable` if any fails or the
+/// shutdown started.
+#[instrument(skip_all)]
+pub async fn get_readyz<F: ServedFacilitator>(
+    Extension(facilitator): Extension<F>,
+    checks: Option<Extension<HealthChecks>>,
+    shutdown: Option<Extension<Shutdown>>,
+) -> Response {
+    let checks = checks.map(|Extension(checks)| checks).unwrap_or_default();
+    let mut report = checks.run(&facilitator).await;
+    if let Some(Extension(shutdown)) = shutdown {
+        let outcome = if shutdown.is_requested() {
+            Outcome::fail("Shutting down".to_string())
+        } else {
+            Outcome::pass(None)
+        };
+        report.status = report.status.max(outcome.status);
+        report.checks.push(HealthCheck {
+            name: "shutdown",
+            network: None,
+            account: None,
+            status: outcome.status,
+            observed: None,
+            threshold: None,
+            message: outcome.message,
+            duration_ms: 0,
+        });
+    }
+    let status = match report.status {
+        HealthStatus::Fail => StatusCode::SERVICE_UNAVAILABLE,
+        HealthStatus::Pass | HealthStatus::Warn => StatusCode::OK,
+    };
+    (status, Json(report)).into_response()
+}
@@ -0,0 +1,354 @@
+//! HTTP endpoints implemented by the x402 **facilitator**.
+//!
+//! These are the server-side handlers for processing client-submitted x402 payments.
+//! They include both protocol-critical endpoints (`/verify`, `/settle`) and discovery endpoints (`/supported`, etc).
+//!
+//! All payloads follow the types defined in the `x402-rs` crate, and are compatible
+//! with the TypeScript and Go client SDKs.
+//!
+//! Each endpoint consumes or produces structured JSON payloads defined in `x402-rs`,
+//! and is compatible with official x402 client SDKs.
+//!
+//! The WebSocket endpoint (`/ws`) lives in the [`ws`] submodule, with its optional authentication
+//! in the [`ws_auth`] submodule, its liveness policy in the [`ws_liveness`] submodule, its
+//! compression in the [`ws_compression`] submodule and the registry of its open connections in the
+//! [`ws_connections`] submodule, batch verification (`/verify/batch`) in the
+//! [`batch`] submodule, resource discovery (`/discovery/resources`) in the [`discovery`]
+//! submodule, settlement statistics (`/stats`) in the [`stats`] submodule, the payment history
+//! (`/payments`) in the [`payments`] submodule, settlement status
+//! polling (`/settlement/{transaction}`) and receipts (`/receipts/{transaction}`) in the
+//! [`settlement`] submodule, refunds (`/refund`, `/refunds/{transaction}`) in the [`refund`]
+//! submodule, the health and readiness probes (`/healthz`, `/readyz`) in the [`health`] submodule,
+//! and the operator API (`/admin`) in the [`admin`] submodule. The [`shutdown`] submodule drains the connections on
+//! shutdown. The [`router`] submodule assembles them into the configurable route table, with the
+//! per-client limits of the [`rate_limit`] submodule.
+//!
+//! Handlers are generic over the facilitator they serve, see [`ServedFacilitator`]. The facilitator
+//! is expected as an axum [`Extension`].
+
+mod admin;
+mod balance;
+mod batch;
+mod discovery;
+mod health;
+mod payments;
+mod rate_limit;
+mod refund;
+mod router;
+mod settlement;
+mod shutdown;
+mod stats;
+mod ws;
+mod ws_auth;
+mod ws_compression;
+mod ws_connections;
+pub mod ws_error_data;
+pub mod ws_liveness;
+
+pub use admin::{AdminListener, admin_routes};
+pub use balance::get_balance;
+pub use batch::post_verify_batch;
+pub use discovery::{get_discovery_resources, post_discovery_resources};
+pub use health::{HealthChecks, get_healthz, get_readyz};
+pub use payments::payments_routes;
+pub use rate_limit::{RateLimits, RateLimitsControl};
+pub use refund::{get_refunds, post_refund};
+pub use router::FacilitatorRoutes;
+pub use settlement::{get_receipt, get_settlement};
+pub use shutdown::Shutdown;
+pub use stats::get_stats;
+pub use ws::ws_handler;
+pub use ws_auth::WsAuth;
+pub use ws_compression::WsCompression;
+pub use ws_connections::WsConnections;
+pub use ws_error_data::WsErrorData;
+pub use ws_liveness::WsLiveness;
+
+use axum::http::{HeaderMap, StatusCode};
+use axum::response::Response;
+use axum::{Extension, Json, response::IntoResponse};
+use serde_json::json;
+use tracing::instrument;
+
+use crate::chain::FacilitatorLocalError;
+use crate::facilitator::{Facilitator, FacilitatorServices};
+use crate::types::{
+    ErrorResponse, FacilitatorErrorReason, MixedAddress, SettleRequest, VerifyRequest,
+    VerifyResponse,
+};
+
+/// Header of `/settle` carrying the idempotency key of the settlement.
+const IDEMPOTENCY_KEY_HEADER: &str = "idempotency-key";
+
+/// A facilitator served by the HTTP and WebSocket endpoints.
+///
+/// Implemented by every cloneable [`Facilitator`] whose errors convert into [`FacilitatorLocalError`]s,
+/// and implementing [`FacilitatorServices`]: the on-chain [`FacilitatorLocal`](crate::facilitator_local::FacilitatorLocal),
+/// a remote facilitator (see [`facilitator_remote`](crate::facilitator_remote)) when running as a thin edge,
+/// or any alternative, e.g. a caching decorator.
+pub trait ServedFacilitator:
+    Facilitator<Error: Into<FacilitatorLocalError> + Send>
+    + FacilitatorServices
+    + Clone
+    + Send
+    + Sync
+    + 'static
+{
+}
+
+impl<F> ServedFacilitator for F where
+    F: Facilitator<Error: Into<FacilitatorLocalError> + Send>
+        + FacilitatorServices
+        + Clone
+        + Send
+        + Sync
+        + 'static
+{
+}
+
+/// `GET /verify`: Returns a machine-readable description of the `/verify` endpoint.
+///
+/// This is served by the facilitator to help clients understand how to construct
+/// a valid [`VerifyRequest`] for payment verification.
+///
+/// This is optional metadata and primarily useful for discoverability and debugging tools.
+#[instrument(skip_all)]
+pub async fn get_verify_info() -> impl IntoResponse {
+    Json(json!({
+        "endpoint": "/verify",
+        "description": "POST to verify x402 payments",
+        "body": {
+            "paymentPayload": "PaymentPayload",
+            "paymentRequirements": "PaymentRequirements",
+        }
+    }))
+}
+
+/// `GET /settle`: Returns a machine-readable description of the `/settle` endpoint.
+///
+/// This is served by the facilitator to describe the structure of a valid
+/// [`SettleRequest`] used to initiate on-chain payment settlement.
+#[instrument(skip_all)]
+pub async fn get_settle_info() -> impl IntoResponse {
+    Json(json!({
+        "endpoint": "/settle",
+        "description": "POST to settle x402 payments",
+        "body": {
+            "paymentPayload": "PaymentPayload",
+            "paymentRequirements": "PaymentRequirements",
+        }
+    }))
+}
+
+/// `GET /supported`: Lists the x402 payment schemes and networks supported by this facilitator.
+///
+/// Facilitators may expose this to help clients dynamically configure their payment requests
+/// based on available network and scheme support. The protocol extensions served are listed as
+/// `extensions`, see [`crate::extensions`].
+#[instrument(skip_all)]
+pub async fn get_supported<F: ServedFacilitator>(
+    Extension(facilitator): Extension<F>,
+) -> impl IntoResponse {
+    match facilitator.supported().await {
+        Ok(mut supported) => {
+            supported.extensions = facilitator.extensions().descriptors().to_vec();
+            (StatusCode::OK, Json(supported)).into_response()
+        }
+        Err(error) => error.into().into_response(),
+    }
+}
+
+/// `POST /verify`: Facilitator-side verification of a proposed x402 payment.
+///
+/// This endpoint checks whether a given payment payload satisfies the declared
+/// [`PaymentRequirements`], including signature validity, scheme match, and fund sufficiency.
+///
+/// Responds with a [`VerifyResponse`] indicating whether the payment can be accepted.
+#[instrument(skip_all)]
+pub async fn post_verify<F: ServedFacilitator>(
+    Extension(facilitator): Extension<F>,
+    Json(body): Json<VerifyRequest>,
+) -> impl IntoResponse {
+    match facilitator.verify(&body).await {
+        Ok(valid_response) => (StatusCode::OK, Json(valid_response)).into_response(),
+        Err(error) => {
+            tracing::warn!(
+                error = ?error,
+                body = %serde_json::to_string(&body).unwrap_or_else(|_| "<can-not-serialize>".to_string()),
+                "Verification failed"
+            );
+            error.into().into_response()
+        }
+    }
+}
+
+/// `POST /settle`: Facilitator-side execution of a valid x402 payment on-chain.
+///
+/// Given a valid [`SettleRequest`], this endpoint attempts to execute the payment
+/// via ERC-3009 `transferWithAuthorization`, and returns a [`SettleResponse`] with transaction details.
+///
+/// This endpoint is typically called after a successful `/verify` step.
+///
+/// The `Idempotency-Key` header sets the `idempotencyKey` of a request without one, see
+/// [`settle_idempotency`](crate::settle_idempotency).
+#[instrument(skip_all)]
+pub async fn post_settle<F: ServedFacilitator>(
+    Extension(facilitator): Extension<F>,
+    headers: HeaderMap,
+    Json(mut body): Json<SettleRequest>,
+) -> impl IntoResponse {
+    if body.idempotency_key.is_none() {
+        body.idempotency_key = headers
+            .get(IDEMPOTENCY_KEY_HEADER)
+            .and_then(|key| key.to_str().ok())
+            .map(str::to_string);
+    }
+    match facilitator.settle(&body).await {
+        Ok(valid_response) => (StatusCode::OK, Json(valid_response)).into_response(),
+        Err(error) => {
+            tracing::warn!(
+                error = ?error,
+                body = %serde_json::to_string(&body).unwrap_or_else(|_| "<can-not-serialize>".to_string()),
+                "Settlement failed"
+            );
+            error.into().into_response()
+        }
+    }
+}
+
+pub(crate) fn map_error_to_verify_response(error: impl Into<FacilitatorLocalError>) -> VerifyResponse {
+    match error.into() {
+        FacilitatorLocalError::SchemeMismatch(payer, ..) => VerifyResponse::invalid(payer, FacilitatorErrorReason::InvalidScheme),
+        FacilitatorLocalError::NonceReused(payer) => VerifyResponse::invalid(payer, FacilitatorErrorReason::InvalidScheme),
+        FacilitatorLocalError::UnsupportedScheme(..) => VerifyResponse::invalid(None, FacilitatorErrorReason::InvalidScheme),
+        FacilitatorLocalError::ReceiverMismatch(payer, ..)
+        | FacilitatorLocalError::InvalidSignature(payer, ..)
+        | FacilitatorLocalError::InvalidTiming(payer, ..)
+        | FacilitatorLocalError::InsufficientValue(payer) => VerifyResponse::invalid(Some(payer), FacilitatorErrorReason::InvalidScheme),
+        FacilitatorLocalError::NetworkMismatch(payer, ..)
+        | FacilitatorLocalError::UnsupportedNetwork(payer) => VerifyResponse::invalid(payer, FacilitatorErrorReason::InvalidNetwork),
+        FacilitatorLocalError::ContractCall(..)
+        | FacilitatorLocalError::InvalidAddress(..)
+        | FacilitatorLocalError::DecodingError(..)
+        | FacilitatorLocalError::NonceStore(..)
+        | FacilitatorLocalError::ClockError(_) => VerifyResponse::invalid(None, FacilitatorErrorReason::UnexpectedSettleError),
+        FacilitatorLocalError::InsufficientFunds(payer) => VerifyResponse::invalid(Some(payer), FacilitatorErrorReason::InsufficientFunds),
+        FacilitatorLocalError::AmountCapExceeded(payer, _) => VerifyResponse::invalid(payer, FacilitatorErrorReason::AmountCapExceeded),
+        FacilitatorLocalError::NotVerified(payer) => VerifyResponse::invalid(payer, FacilitatorErrorReason::UnverifiedPayment),
+        FacilitatorLocalError::IdempotencyKeyReused(payer) => VerifyResponse::invalid(payer, FacilitatorErrorReason::IdempotencyKeyReused),
+        FacilitatorLocalError::SettlementPaused(_) => VerifyResponse::invalid(None, FacilitatorErrorReason::SettlementPaused),
+        FacilitatorLocalError::InsufficientGas(_) => VerifyResponse::invalid(None, FacilitatorErrorReason::InsufficientGas),
+        FacilitatorLocalError::Reverted(payer, reason) => VerifyResponse::invalid(Some(payer), reason.error_reason()),
+        FacilitatorLocalError::Upstream(_, Some(response)) => *response,
+        FacilitatorLocalError::Upstream(_, None) => VerifyResponse::invalid(None, FacilitatorErrorReason::UnexpectedSettleError),
+    }
+}
+
+fn invalid_schema(payer: Option<MixedAddress>) -> VerifyResponse {
+    VerifyResponse::invalid(payer, FacilitatorErrorReason::InvalidScheme)
+}
+
+impl IntoResponse for FacilitatorLocalError {
+    fn into_response(self) -> Response {
+        let error = self;
+
+        let bad_request = (
+            StatusCode::BAD_REQUEST,
+            Json(ErrorResponse {
+                error: "Invalid request".to_string(),
+            }),
+        )
+            .into_response();
+
+        match error {
+            FacilitatorLocalError::SchemeMismatch(payer, ..)
+            | FacilitatorLocalError::NonceReused(payer) => {
+                (StatusCode::OK, Json(invalid_schema(payer))).into_response()
+            }
+            FacilitatorLocalError::UnsupportedScheme(..) => {
+                (StatusCode::OK, Json(invalid_schema(None))).into_response()
+            }
+            FacilitatorLocalError::ReceiverMismatch(payer, ..)
+            | FacilitatorLocalError::InvalidSignature(payer, ..)
+            | FacilitatorLocalError::InvalidTiming(payer, ..)
+            | FacilitatorLocalError::InsufficientValue(payer) => {
+                (StatusCode::OK, Json(invalid_schema(Some(payer)))).into_response()
+            }
+            FacilitatorLocalError::NetworkMismatch(payer, ..)
+            | FacilitatorLocalError::UnsupportedNetwork(payer) => (
+                StatusCode::OK,
+                Json(VerifyResponse::invalid(
+                    payer,
+                    FacilitatorErrorReason::InvalidNetwork,
+                )),
+            )
+                .into_response(),
+            FacilitatorLocalError::ContractCall(..)
+            | FacilitatorLocalError::InvalidAddress(..)
+            | FacilitatorLocalError::DecodingError(..)
+            | FacilitatorLocalError::NonceStore(..)
+            | FacilitatorLocalError::ClockError(_) => bad_request,
+            FacilitatorLocalError::InsufficientFunds(payer) => (
+                StatusCode::OK,
+                Json(VerifyResponse::invalid(
+                    Some(payer),
+                    FacilitatorErrorReason::InsufficientFunds,
+                )),
+            )
+                .into_response(),
+            FacilitatorLocalError::AmountCapExceeded(payer, _) => (
+                StatusCode::OK,
+                Json(VerifyResponse::invalid(
+                    payer,
+                    FacilitatorErrorReason::AmountCapExceeded,
+                )),
+            )
+                .into_response(),
+            FacilitatorLocalError::NotVerified(payer) => (
+                StatusCode::OK,
+                Json(VerifyResponse::invalid(
+                    payer,
+                    FacilitatorErrorReason::UnverifiedPayment,
+                )),
+            )
+                .into_response(),
+            FacilitatorLocalError::IdempotencyKeyReused(payer) => (
+                StatusCode::OK,
+                Json(VerifyResponse::invalid(
+                    payer,
+                    FacilitatorErrorReason::IdempotencyKeyReused,
+                )),
+            )
+                .into_response(),
+            FacilitatorLocalError::SettlementPaused(network) => (
+                StatusCode::SERVICE_UNAVAILABLE,
+                Json(ErrorResponse {
+                    error: format!("Settlement paused on {network}"),
+                }),
+            )
+                .into_response(),
+            FacilitatorLocalError::InsufficientGas(network) => (
+                StatusCode::SERVICE_UNAVAILABLE,
+                Json(ErrorResponse {
+                    error: format!("Insufficient gas on {network}"),
+                }),
+            )
+                .into_response(),
+            FacilitatorLocalError::Reverted(payer, reason) => (
+                StatusCode::OK,
+                Json(VerifyResponse::invalid(Some(payer), reason.error_reason())),
+            )
+                .into_response(),
+            FacilitatorLocalError::Upstream(_, Some(response)) => {
+                (StatusCode::OK, Json(*response)).into_response()
+            }
+            FacilitatorLocalError::Upstream(_, None) => (
+                StatusCode::BAD_GATEWAY,
+                Json(ErrorResponse {
+                    error: "Upstream facilitator error".to_string(),
+                }),
+            )
+                .into_response(),
+        }
+    }
+}
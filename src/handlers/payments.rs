@@ -0,0 +1,52 @@
+//! Payment history: `GET /payments`, see [`crate::storage`].
+//!
+//! The history names payers and recipients: like the admin API, it is only served with
+//! `ADMIN_TOKEN` set, to requests carrying `Authorization: Bearer <ADMIN_TOKEN>`.
+
+use axum::extract::Query;
+use axum::http::StatusCode;
+use axum::middleware;
+use axum::response::Response;
+use axum::routing::get;
+use axum::{Extension, Json, Router, response::IntoResponse};
+use tracing::instrument;
+
+use crate::handlers::ServedFacilitator;
+use crate::handlers::admin::require_admin_token;
+use crate::storage::{PaymentQuery, StorageError};
+use crate::types::ErrorResponse;
+
+impl IntoResponse for StorageError {
+    fn into_response(self) -> Response {
+        let error = ErrorResponse {
+            error: self.to_string(),
+        };
+        (StatusCode::INTERNAL_SERVER_ERROR, Json(error)).into_response()
+    }
+}
+
+/// Routes of the payment history for facilitator `F`, behind the admin token.
+pub fn payments_routes<F: ServedFacilitator>() -> Router {
+    Router::new()
+        .route("/payments", get(get_payments::<F>))
+        .layer(middleware::from_fn(require_admin_token))
+}
+
+/// `GET /payments`: the verifications and settlements matching the filters of the query
+/// string, newest first, e.g. `?payer=0x...&status=settled&limit=20`.
+#[instrument(skip_all)]
+async fn get_payments<F: ServedFacilitator>(
+    Extension(facilitator): Extension<F>,
+    Query(query): Query<PaymentQuery>,
+) -> Response {
+    let Some(history) = facilitator.payment_history() else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+    match history.query(&query).await {
+        Ok(page) => Json(page).into_response(),
+        Err(error) => {
+            tracing::error!(%error, "Failed to query the payment history");
+            error.into_response()
+        }
+    }
+}
@@ -0,0 +1,558 @@
+//! Built-in per-client rate limits of the facilitator endpoints.
+//!
+//! Each endpoint gets its own token bucket per client IP, refilled at a steady number of requests
+//! per second, with a burst of one second worth of requests. Settlement is the expensive endpoint,
+//! so it is limited tighter than verification by default:
+//!
+//! | Endpoint       | Requests per second |
+//! |----------------|---------------------|
+//! | `verify`       | 50                  |
+//! | `verify-batch` | 5                   |
+//! | `settle`       | 10                  |
+//! | `supported`    | 50                  |
+//! | `discovery`    | 20                  |
+//! | `settlement`   | 50                  |
+//! | `stats`        | 10                  |
+//! | `balance`      | 100                 |
+//! | `ws`           | 10 (connections)    |
+//! | `health`       | unlimited           |
+//! | `admin`        | unlimited           |
+//!
+//! Requests carrying an API key, as `Authorization: Bearer <key>` or `X-API-Key: <key>`, are also
+//! limited per key, with limits of their own (the same by default): a client must stay within both
+//! the limits of its address and the limits of its key.
+//!
+//! Requests over the limit are rejected with `429 Too Many Requests`, a JSON [`ErrorResponse`]
+//! and a `retry-after` header.
+//!
+//! WebSocket requests are limited the same way, per client address and per API key of the
+//! connection (see [`WsAuth`](crate::handlers::WsAuth)), against the limits of the endpoint they
+//! mirror: `x402.verify` and `stream.pay` count as `verify`, `x402.verifyBatch` as `verify-batch`,
+//! `x402.settle`, `x402.settleBatch` and `balance.deposit` as `settle`, `x402.supported` as
+//! `supported`, `x402.discovery.list` as `discovery`, and `balance.charge` and `balance.get` as
+//! `balance`. They are counted apart from HTTP requests, and answered
+//! with a `-32029` error carrying `{ retryAfterMs }` when over the limit. An `x402.settleBatch`
+//! takes one `settle` token per item, counted as as many `x402.settle` requests; a batch larger
+//! than the limit per second is refused with an `Invalid params` error.
+//!
+//! The limits can be changed at runtime through a [`RateLimitsControl`], e.g. when the
+//! configuration file is reloaded (see [`config_file`](crate::config_file)): clients start over
+//! with full buckets, and open WebSocket connections follow the new limits. They can also be
+//! adjusted through the admin API (`/admin/rate-limits`), until the next reload or restart.
+//!
+//! Rejections are counted in the `x402_rate_limited` metric, by `endpoint`, `transport` (`http` or
+//! `ws`) and `scope` (`ip` or `key`).
+//!
+//! Environment (see [`RateLimits::from_env`]):
+//! - `RATE_LIMITS` – Comma-separated overrides, e.g. `verify=100,settle=5`; `0` lifts the limit of
+//!   an endpoint, and `off` lifts all limits, per address and per key
+//! - `RATE_LIMITS_PER_KEY` – Comma-separated overrides of the limits per API key, same syntax
+//! - `RATE_LIMIT_KEY` – `peer` (default) keys clients by peer address; `forwarded` by the
+//!   `X-Forwarded-For`, `X-Real-IP` or `Forwarded` headers set by a reverse proxy, falling back
+//!   to the peer address
+
+use axum::extract::Request;
+use axum::http::{HeaderMap, HeaderValue, StatusCode, header};
+use axum::middleware::{self, Next};
+use axum::response::IntoResponse;
+use axum::{Extension, Json};
+use governor::clock::{Clock, DefaultClock};
+use governor::{DefaultKeyedRateLimiter, InsufficientCapacity, Quota, RateLimiter};
+use once_cell::sync::Lazy;
+use opentelemetry::KeyValue;
+use opentelemetry::metrics::Counter;
+use std::collections::HashMap;
+use std::env;
+use std::fmt::{Display, Formatter};
+use std::net::IpAddr;
+use std::num::NonZeroU32;
+use std::str::FromStr;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+use tower_governor::key_extractor::{KeyExtractor, PeerIpKeyExtractor, SmartIpKeyExtractor};
+
+use crate::handlers::router::{Endpoint, FacilitatorRoutes, RoutesConfigError};
+use crate::types::ErrorResponse;
+
+const ENV_RATE_LIMITS: &str = "RATE_LIMITS";
+const ENV_RATE_LIMITS_PER_KEY: &str = "RATE_LIMITS_PER_KEY";
+const ENV_RATE_LIMIT_KEY: &str = "RATE_LIMIT_KEY";
+
+const API_KEY_HEADER: &str = "x-api-key";
+
+static RATE_LIMITED: Lazy<Counter<u64>> = Lazy::new(|| {
+    opentelemetry::global::meter("x402-rs")
+        .u64_counter("x402_rate_limited")
+        .build()
+});
+
+/// Interval at which the state of clients with a full bucket is dropped.
+const CLEANUP_INTERVAL: Duration = Duration::from_secs(60);
+
+/// How clients are told apart by the rate limiter.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RateLimitKey {
+    /// Peer address of the connection.
+    #[default]
+    Peer,
+    /// Client address forwarded by a reverse proxy, or the peer address.
+    Forwarded,
+}
+
+impl Display for RateLimitKey {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RateLimitKey::Peer => write!(f, "peer"),
+            RateLimitKey::Forwarded => write!(f, "forwarded"),
+        }
+    }
+}
+
+impl FromStr for RateLimitKey {
+    type Err = RoutesConfigError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim() {
+            "peer" => Ok(RateLimitKey::Peer),
+            "forwarded" => Ok(RateLimitKey::Forwarded),
+            other => Err(RoutesConfigError::InvalidRateLimitKey(other.to_string())),
+        }
+    }
+}
+
+/// Requests per second allowed to each client, per endpoint.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RateLimits {
+    per_second: HashMap<Endpoint, u32>,
+    /// Requests per second allowed to each API key, per endpoint.
+    per_key: HashMap<Endpoint, u32>,
+    key: RateLimitKey,
+}
+
+impl Default for RateLimits {
+    fn default() -> Self {
+        let per_second = HashMap::from([
+            (Endpoint::Verify, 50),
+            (Endpoint::VerifyBatch, 5),
+            (Endpoint::Settle, 10),
+            (Endpoint::Supported, 50),
+            (Endpoint::Discovery, 20),
+            (Endpoint::Settlement, 50),
+            (Endpoint::Refund, 5),
+            (Endpoint::Stats, 10),
+            (Endpoint::Payments, 10),
+            (Endpoint::Balance, 100),
+            (Endpoint::Ws, 10),
+        ]);
+        Self {
+            per_key: per_second.clone(),
+            per_second,
+            key: RateLimitKey::default(),
+        }
+    }
+}
+
+impl Display for RateLimits {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let format = |per_second: &HashMap<Endpoint, u32>| {
+            if per_second.is_empty() {
+                return "off".to_string();
+            }
+            let mut limits = per_second
+                .iter()
+                .map(|(endpoint, per_second)| format!("{endpoint}={per_second}"))
+                .collect::<Vec<_>>();
+            limits.sort();
+            limits.join(",")
+        };
+        write!(
+            f,
+            "{} (per key: {})",
+            format(&self.per_second),
+            format(&self.per_key)
+        )
+    }
+}
+
+impl RateLimits {
+    /// No limit on any endpoint.
+    pub fn disabled() -> Self {
+        Self {
+            per_second: HashMap::new(),
+            per_key: HashMap::new(),
+            key: RateLimitKey::default(),
+        }
+    }
+
+    /// Reads the default limits, overridden by `RATE_LIMITS`, `RATE_LIMITS_PER_KEY` and
+    /// `RATE_LIMIT_KEY`.
+    pub fn from_env() -> Result<Self, RoutesConfigError> {
+        Self::from_vars(|name| env::var(name).ok())
+    }
+
+    /// Reads the default limits, overridden by the `RATE_LIMITS`, `RATE_LIMITS_PER_KEY` and
+    /// `RATE_LIMIT_KEY` values of `var`.
+    pub(crate) fn from_vars(
+        var: impl Fn(&str) -> Option<String>,
+    ) -> Result<Self, RoutesConfigError> {
+        let mut limits = Self::default();
+        if let Some(key) = var(ENV_RATE_LIMIT_KEY)
+            && !key.is_empty()
+        {
+            limits = limits.with_key(key.parse()?);
+        }
+        if let Some(overrides) = var(ENV_RATE_LIMITS) {
+            if overrides.trim() == "off" {
+                limits = Self::disabled().with_key(limits.key);
+            }
+            for (endpoint, per_second) in parse_overrides(&overrides)? {
+                limits = limits.with_limit(endpoint, per_second);
+            }
+        }
+        if let Some(overrides) = var(ENV_RATE_LIMITS_PER_KEY) {
+            if overrides.trim() == "off" {
+                limits.per_key.clear();
+            }
+            for (endpoint, per_second) in parse_overrides(&overrides)? {
+                limits = limits.with_key_limit(endpoint, per_second);
+            }
+        }
+        Ok(limits)
+    }
+
+    /// Limits `endpoint` to `per_second` requests per second per client; `0` lifts the limit.
+    pub fn with_limit(&self, endpoint: Endpoint, per_second: u32) -> Self {
+        let mut this = self.clone();
+        if per_second == 0 {
+            this.per_second.remove(&endpoint);
+        } else {
+            this.per_second.insert(endpoint, per_second);
+        }
+        this
+    }
+
+    /// Limits `endpoint` to `per_second` requests per second per API key; `0` lifts the limit.
+    pub fn with_key_limit(&self, endpoint: Endpoint, per_second: u32) -> Self {
+        let mut this = self.clone();
+        if per_second == 0 {
+            this.per_key.remove(&endpoint);
+        } else {
+            this.per_key.insert(endpoint, per_second);
+        }
+        this
+    }
+
+    /// Sets how clients are told apart.
+    pub fn with_key(&self, key: RateLimitKey) -> Self {
+        let mut this = self.clone();
+        this.key = key;
+        this
+    }
+
+    /// Requests per second allowed to each client, by limited endpoint.
+    pub fn per_second(&self) -> &HashMap<Endpoint, u32> {
+        &self.per_second
+    }
+
+    /// Requests per second allowed to each API key, by limited endpoint.
+    pub fn per_key(&self) -> &HashMap<Endpoint, u32> {
+        &self.per_key
+    }
+
+    /// How clients are told apart.
+    pub fn key(&self) -> RateLimitKey {
+        self.key
+    }
+
+    /// Adds a rate limiting layer to every endpoint of `routes`, and limits the requests of
+    /// WebSocket connections.
+    ///
+    /// Must be called within a Tokio runtime, which periodically drops idle client state.
+    pub fn apply(&self, routes: &FacilitatorRoutes) -> FacilitatorRoutes {
+        self.control().apply(routes)
+    }
+
+    /// Handle enforcing the limits, which can be changed at runtime.
+    ///
+    /// Must be called within a Tokio runtime, which periodically drops idle client state.
+    pub fn control(&self) -> RateLimitsControl {
+        RateLimitsControl(Arc::new(RwLock::new(Arc::new(Limiters::new(self)))))
+    }
+}
+
+/// Parses comma-separated `<endpoint>=<requests per second>` overrides. `off` parses as none.
+fn parse_overrides(overrides: &str) -> Result<Vec<(Endpoint, u32)>, RoutesConfigError> {
+    overrides
+        .split(',')
+        .map(str::trim)
+        .filter(|s| !s.is_empty() && *s != "off")
+        .map(|entry| {
+            let invalid = || RoutesConfigError::InvalidRateLimit(entry.to_string());
+            let (endpoint, per_second) = entry.split_once('=').ok_or_else(invalid)?;
+            let per_second = per_second.trim().parse().map_err(|_| invalid())?;
+            Ok((endpoint.trim().parse()?, per_second))
+        })
+        .collect()
+}
+
+/// API key of a request, from `Authorization: Bearer <key>` or `X-API-Key: <key>`.
+fn api_key(headers: &HeaderMap) -> Option<&str> {
+    headers
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .or_else(|| {
+            headers
+                .get(API_KEY_HEADER)
+                .and_then(|value| value.to_str().ok())
+        })
+}
+
+/// Handle to the rate limits enforced by the endpoints of [`RateLimitsControl::apply`], changed
+/// at runtime with [`RateLimitsControl::set`], e.g. when the configuration file is reloaded.
+///
+/// Obtained from [`RateLimits::control`]. Cheap to clone; all clones act on the same limits.
+#[derive(Clone)]
+pub struct RateLimitsControl(Arc<RwLock<Arc<Limiters>>>);
+
+impl RateLimitsControl {
+    /// Enforces `limits` in place of the current ones, on every endpoint and open WebSocket
+    /// connection. Clients start over with full buckets.
+    ///
+    /// Must be called within a Tokio runtime, which periodically drops idle client state.
+    pub fn set(&self, limits: &RateLimits) {
+        let mut limiters = self.0.write().unwrap();
+        if limiters.limits != *limits {
+            tracing::info!(%limits, "Rate limits changed");
+            *limiters = Arc::new(Limiters::new(limits));
+        }
+    }
+
+    /// Limits currently enforced.
+    pub fn limits(&self) -> RateLimits {
+        self.limiters().limits.clone()
+    }
+
+    /// Adds a rate limiting layer to every endpoint of `routes`, and limits the requests of
+    /// WebSocket connections. Endpoints without limits let requests through. The admin API is
+    /// given the control, to adjust the limits.
+    pub fn apply(&self, routes: &FacilitatorRoutes) -> FacilitatorRoutes {
+        let mut routes = routes.layer(Endpoint::Admin, Extension(self.clone()));
+        for endpoint in Endpoint::ALL {
+            let control = self.clone();
+            routes = routes.layer(
+                endpoint,
+                middleware::from_fn(move |request: Request, next: Next| {
+                    limit(endpoint, control.clone(), request, next)
+                }),
+            );
+        }
+        routes
+    }
+
+    fn limiters(&self) -> Arc<Limiters> {
+        self.0.read().unwrap().clone()
+    }
+}
+
+/// Token buckets of a set of limits.
+struct Limiters {
+    limits: RateLimits,
+    per_ip: KeyedLimits,
+    per_key: KeyedLimits,
+}
+
+impl Limiters {
+    /// Must be called within a Tokio runtime, which periodically drops idle client state.
+    fn new(limits: &RateLimits) -> Self {
+        Self {
+            limits: limits.clone(),
+            per_ip: KeyedLimits::new(&limits.per_second),
+            per_key: KeyedLimits::new(&limits.per_key),
+        }
+    }
+
+    /// Address of the client of `request`, as told apart by the limits.
+    fn client(&self, request: &Request) -> Option<IpAddr> {
+        match self.limits.key {
+            RateLimitKey::Peer => PeerIpKeyExtractor.extract(request).ok(),
+            RateLimitKey::Forwarded => SmartIpKeyExtractor.extract(request).ok(),
+        }
+    }
+}
+
+/// Token buckets per endpoint, keyed by client address or API key.
+#[derive(Clone, Default)]
+struct KeyedLimits(HashMap<Endpoint, Arc<DefaultKeyedRateLimiter<String>>>);
+
+impl KeyedLimits {
+    /// Must be called within a Tokio runtime, which periodically drops idle client state until
+    /// the limits are dropped.
+    fn new(per_second: &HashMap<Endpoint, u32>) -> Self {
+        let limiters = per_second
+            .iter()
+            .filter_map(|(&endpoint, &per_second)| {
+                let per_second = NonZeroU32::new(per_second)?;
+                let limiter = Arc::new(RateLimiter::keyed(Quota::per_second(per_second)));
+                let cleaned = Arc::downgrade(&limiter);
+                tokio::spawn(async move {
+                    let mut interval = tokio::time::interval(CLEANUP_INTERVAL);
+                    loop {
+                        interval.tick().await;
+                        match cleaned.upgrade() {
+                            Some(limiter) => limiter.retain_recent(),
+                            None => break,
+                        }
+                    }
+                });
+                Some((endpoint, limiter))
+            })
+            .collect();
+        Self(limiters)
+    }
+
+    fn is_limited(&self, endpoint: Endpoint) -> bool {
+        self.0.contains_key(&endpoint)
+    }
+
+    /// Takes a token of `key` for `endpoint`, or returns the time until one is available.
+    fn check(&self, endpoint: Endpoint, key: &str) -> Result<(), Duration> {
+        let Some(limiter) = self.0.get(&endpoint) else {
+            return Ok(());
+        };
+        limiter
+            .check_key(&key.to_string())
+            .map_err(|not_until| not_until.wait_time_from(DefaultClock::default().now()))
+    }
+
+    /// Takes `n` tokens of `key` for `endpoint`, or returns the time until they are available.
+    ///
+    /// # Errors
+    /// Returns [`InsufficientCapacity`] if the bucket can never hold `n` tokens.
+    fn check_n(
+        &self,
+        endpoint: Endpoint,
+        key: &str,
+        n: NonZeroU32,
+    ) -> Result<Result<(), Duration>, InsufficientCapacity> {
+        let Some(limiter) = self.0.get(&endpoint) else {
+            return Ok(Ok(()));
+        };
+        Ok(limiter
+            .check_key_n(&key.to_string(), n)?
+            .map_err(|not_until| not_until.wait_time_from(DefaultClock::default().now())))
+    }
+}
+
+/// Rejects requests whose client address or API key is over its limit for `endpoint`, and hands
+/// connections to the WebSocket endpoint their [`WsClientLimits`].
+async fn limit(
+    endpoint: Endpoint,
+    control: RateLimitsControl,
+    mut request: Request,
+    next: Next,
+) -> axum::response::Response {
+    let limiters = control.limiters();
+    let ip = limiters.client(&request);
+    if limiters.per_ip.is_limited(endpoint) {
+        let Some(ip) = ip else {
+            return error_response(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Unable to identify the client".to_string(),
+            );
+        };
+        if let Err(wait) = limiters.per_ip.check(endpoint, &ip.to_string()) {
+            rate_limited(endpoint, "http", "ip");
+            return too_many_requests(wait);
+        }
+    }
+    if let Some(key) = api_key(request.headers())
+        && let Err(wait) = limiters.per_key.check(endpoint, key)
+    {
+        rate_limited(endpoint, "http", "key");
+        return too_many_requests(wait);
+    }
+    if endpoint == Endpoint::Ws
+        && let Some(ip) = ip
+    {
+        let key = api_key(request.headers()).map(str::to_string);
+        request.extensions_mut().insert(WsClientLimits {
+            control,
+            ip: ip.to_string(),
+            key,
+        });
+    }
+    next.run(request).await
+}
+
+/// Counts a rejected request in the `x402_rate_limited` metric.
+fn rate_limited(endpoint: Endpoint, transport: &'static str, scope: &'static str) {
+    tracing::debug!(%endpoint, transport, scope, "Rate limited");
+    RATE_LIMITED.add(
+        1,
+        &[
+            KeyValue::new("endpoint", endpoint.as_str()),
+            KeyValue::new("transport", transport),
+            KeyValue::new("scope", scope),
+        ],
+    );
+}
+
+/// Rate limits of the requests of a WebSocket connection, following the limits in force.
+#[derive(Clone)]
+pub(crate) struct WsClientLimits {
+    control: RateLimitsControl,
+    /// Address of the client.
+    ip: String,
+    /// API key sent with the handshake, if any.
+    key: Option<String>,
+}
+
+impl WsClientLimits {
+    /// Takes `n` tokens for a request mirroring `endpoint`, from the address of the client and
+    /// from the API key of the connection if any, or returns the time until both are available.
+    ///
+    /// `key` is the credential the connection authenticated with, if any; it takes precedence
+    /// over the API key of the handshake.
+    ///
+    /// # Errors
+    /// Returns [`InsufficientCapacity`] if `n` is over the limit per second of `endpoint`.
+    pub fn check(
+        &self,
+        endpoint: Endpoint,
+        key: Option<&str>,
+        n: NonZeroU32,
+    ) -> Result<Result<(), Duration>, InsufficientCapacity> {
+        let limiters = self.control.limiters();
+        let checked = limiters.per_ip.check_n(endpoint, &self.ip, n);
+        if !matches!(checked, Ok(Ok(()))) {
+            rate_limited(endpoint, "ws", "ip");
+            return checked;
+        }
+        if let Some(key) = key.or(self.key.as_deref()) {
+            let checked = limiters.per_key.check_n(endpoint, key, n);
+            if !matches!(checked, Ok(Ok(()))) {
+                rate_limited(endpoint, "ws", "key");
+                return checked;
+            }
+        }
+        Ok(Ok(()))
+    }
+}
+
+/// `429 Too Many Requests`, with a `retry-after` header of `wait`.
+fn too_many_requests(wait: Duration) -> axum::response::Response {
+    let wait_secs = wait.as_secs_f64().ceil() as u64;
+    let mut response = error_response(
+        StatusCode::TOO_MANY_REQUESTS,
+        format!("Too many requests, retry in {wait_secs}s"),
+    );
+    response
+        .headers_mut()
+        .insert(header::RETRY_AFTER, HeaderValue::from(wait_secs));
+    response
+}
+
+fn error_response(status: StatusCode, error: String) -> axum::response::Response {
+    (status, Json(ErrorResponse { error })).into_response()
+}
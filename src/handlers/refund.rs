@@ -0,0 +1,83 @@
+//! Refunds of settled payments: `POST /refund` and `GET /refunds/{transaction}`, see
+//! [`crate::refunds`].
+
+use axum::extract::Path;
+use axum::http::StatusCode;
+use axum::response::Response;
+use axum::{Extension, Json, response::IntoResponse};
+use tracing::instrument;
+
+use crate::handlers::ServedFacilitator;
+use crate::handlers::settlement::parse_transaction;
+use crate::refunds::{RefundError, RefundRequest};
+use crate::types::ErrorResponse;
+
+impl IntoResponse for RefundError {
+    fn into_response(self) -> Response {
+        let status = match self {
+            RefundError::UnknownSettlement(_) => StatusCode::NOT_FOUND,
+            RefundError::NotPayee => StatusCode::FORBIDDEN,
+            RefundError::Exceeded { .. } | RefundError::InProgress(_) => StatusCode::CONFLICT,
+            RefundError::InvalidReceipt(_)
+            | RefundError::UnsupportedScheme
+            | RefundError::Mismatch(_)
+            | RefundError::Rejected(_) => StatusCode::BAD_REQUEST,
+            RefundError::UnsupportedUrl(_) | RefundError::Backend(_) => {
+                StatusCode::SERVICE_UNAVAILABLE
+            }
+        };
+        let error = ErrorResponse {
+            error: self.to_string(),
+        };
+        (status, Json(error)).into_response()
+    }
+}
+
+/// `POST /refund`: sends back part or all of a settled payment, with an authorization signed by
+/// its payee, and records the refund.
+///
+/// Responds with `404 Not Found` if the facilitator does not serve refunds.
+#[instrument(skip_all, fields(settlement = %body.transaction))]
+pub async fn post_refund<F: ServedFacilitator>(
+    Extension(facilitator): Extension<F>,
+    Json(body): Json<RefundRequest>,
+) -> Response {
+    let Some(refunds) = facilitator.refunds() else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+    match refunds.refund(&facilitator, body).await {
+        Ok(refund) => {
+            tracing::info!(
+                transaction = %refund.refund.transaction,
+                amount = %refund.refund.amount,
+                "Payment refunded"
+            );
+            Json(refund).into_response()
+        }
+        Err(error) => {
+            tracing::warn!(%error, "Refund failed");
+            error.into_response()
+        }
+    }
+}
+
+/// `GET /refunds/{transaction}`: refunds of the settlement by `transaction`, oldest first.
+///
+/// Responds with `404 Not Found` if the facilitator does not serve refunds.
+#[instrument(skip_all)]
+pub async fn get_refunds<F: ServedFacilitator>(
+    Extension(facilitator): Extension<F>,
+    Path(transaction): Path<String>,
+) -> Response {
+    let Some(refunds) = facilitator.refunds() else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+    let transaction = match parse_transaction(transaction) {
+        Ok(transaction) => transaction,
+        Err(response) => return response,
+    };
+    match refunds.of_settlement(&transaction).await {
+        Ok(records) => Json(records).into_response(),
+        Err(error) => error.into_response(),
+    }
+}
@@ -0,0 +1,405 @@
+//! Route table of the facilitator.
+//!
+//! [`FacilitatorRoutes`] builds the [`Router`] of the facilitator endpoints. Deployments can
+//! serve them under a path prefix, move the WebSocket endpoint, and turn endpoints off, e.g. to
+//! keep `/settle` off a public listener.
+//!
+//! Environment (see [`FacilitatorRoutes::from_env`]):
+//! - `ROUTE_PREFIX` – Path prefix of all endpoints, e.g. `/api/v1` (default: none)
+//! - `WS_PATH` – Path of the WebSocket endpoint, below the prefix (default `/ws`)
+//! - `DISABLED_ENDPOINTS` – Comma-separated endpoints to turn off:
+//!   `verify`, `verify-batch`, `settle`, `supported`, `discovery`, `settlement`, `refund`, `stats`, `payments`, `balance`, `health`, `ws`, `admin`
+//! - `RATE_LIMITS`, `RATE_LIMITS_PER_KEY`, `RATE_LIMIT_KEY` – Per-client and per-API-key rate limits, see [`RateLimits`](crate::handlers::RateLimits)
+//! - `WS_API_KEYS`, `WS_AUTH_ADDRESSES` – Authentication of WebSocket connections, see [`WsAuth`](crate::handlers::WsAuth)
+//! - `WS_ERROR_DATA`, `WS_ERROR_DATA_MAX_BYTES` – Details of the WebSocket errors sent to unauthenticated connections, see [`WsErrorData`](crate::handlers::WsErrorData)
+//!
+//! Any tower layer can wrap the routes of a single endpoint with [`FacilitatorRoutes::layer`],
+//! e.g. to limit the concurrency of `/settle` independently of `/verify`, and
+//! [`FacilitatorRoutes::with_ws_methods`] serves custom methods on the WebSocket endpoint.
+
+use axum::Extension;
+use axum::Router;
+use axum::extract::Request;
+use axum::response::IntoResponse;
+use axum::routing::{Route, get, post};
+use std::collections::HashSet;
+use std::convert::Infallible;
+use std::env;
+use std::fmt::{Debug, Display, Formatter};
+use std::str::FromStr;
+use std::sync::Arc;
+use tower::{Layer, Service};
+
+use crate::duration::DurationError;
+use crate::handlers::{self, ServedFacilitator};
+use crate::ws_methods::WsMethods;
+
+const ENV_ROUTE_PREFIX: &str = "ROUTE_PREFIX";
+const ENV_WS_PATH: &str = "WS_PATH";
+const ENV_DISABLED_ENDPOINTS: &str = "DISABLED_ENDPOINTS";
+
+/// A group of facilitator routes that can be enabled or disabled as a whole.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Endpoint {
+    /// `GET /verify` and `POST /verify`
+    Verify,
+    /// `POST /verify/batch`
+    VerifyBatch,
+    /// `GET /settle` and `POST /settle`
+    Settle,
+    /// `GET /supported`
+    Supported,
+    /// `GET /discovery/resources` and `POST /discovery/resources`
+    Discovery,
+    /// `GET /settlement/{transaction}` and `GET /receipts/{transaction}`
+    Settlement,
+    /// `POST /refund` and `GET /refunds/{transaction}`
+    Refund,
+    /// `GET /stats`
+    Stats,
+    /// `GET /payments`
+    Payments,
+    /// `GET /balance`
+    Balance,
+    /// `GET /healthz` and `GET /readyz`
+    Health,
+    /// The WebSocket endpoint
+    Ws,
+    /// The operator API under `/admin`
+    Admin,
+}
+
+impl Endpoint {
+    /// Every endpoint.
+    pub const ALL: [Endpoint; 13] = [
+        Endpoint::Verify,
+        Endpoint::VerifyBatch,
+        Endpoint::Settle,
+        Endpoint::Supported,
+        Endpoint::Discovery,
+        Endpoint::Settlement,
+        Endpoint::Refund,
+        Endpoint::Stats,
+        Endpoint::Payments,
+        Endpoint::Balance,
+        Endpoint::Health,
+        Endpoint::Ws,
+        Endpoint::Admin,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Endpoint::Verify => "verify",
+            Endpoint::VerifyBatch => "verify-batch",
+            Endpoint::Settle => "settle",
+            Endpoint::Supported => "supported",
+            Endpoint::Discovery => "discovery",
+            Endpoint::Settlement => "settlement",
+            Endpoint::Refund => "refund",
+            Endpoint::Stats => "stats",
+            Endpoint::Payments => "payments",
+            Endpoint::Balance => "balance",
+            Endpoint::Health => "health",
+            Endpoint::Ws => "ws",
+            Endpoint::Admin => "admin",
+        }
+    }
+}
+
+impl Display for Endpoint {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum RoutesConfigError {
+    #[error("Unknown endpoint: {0}")]
+    UnknownEndpoint(String),
+    #[error("Invalid path {0}: must start with '/'")]
+    InvalidPath(String),
+    #[error("Invalid rate limit {0}: expected <endpoint>=<requests per second>")]
+    InvalidRateLimit(String),
+    #[error("Invalid rate limit key {0}: expected 'peer' or 'forwarded'")]
+    InvalidRateLimitKey(String),
+    #[error("Invalid WebSocket authentication setting {0}")]
+    InvalidWsAuth(String),
+    #[error("Invalid WebSocket error data setting {0}")]
+    InvalidWsErrorData(String),
+    #[error("Invalid WebSocket compression setting {0}")]
+    InvalidWsCompression(String),
+    #[error(transparent)]
+    InvalidDuration(#[from] DurationError),
+}
+
+impl FromStr for Endpoint {
+    type Err = RoutesConfigError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "verify" => Ok(Endpoint::Verify),
+            "verify-batch" => Ok(Endpoint::VerifyBatch),
+            "settle" => Ok(Endpoint::Settle),
+            "supported" => Ok(Endpoint::Supported),
+            "discovery" => Ok(Endpoint::Discovery),
+            "settlement" => Ok(Endpoint::Settlement),
+            "refund" => Ok(Endpoint::Refund),
+            "stats" => Ok(Endpoint::Stats),
+            "payments" => Ok(Endpoint::Payments),
+            "balance" => Ok(Endpoint::Balance),
+            "health" => Ok(Endpoint::Health),
+            "ws" => Ok(Endpoint::Ws),
+            "admin" => Ok(Endpoint::Admin),
+            _ => Err(RoutesConfigError::UnknownEndpoint(s.to_string())),
+        }
+    }
+}
+
+/// A layer wrapping the routes of an endpoint, see [`FacilitatorRoutes::layer`].
+#[derive(Clone)]
+struct EndpointLayer {
+    endpoint: Endpoint,
+    apply: Arc<dyn Fn(Router) -> Router + Send + Sync>,
+}
+
+impl Debug for EndpointLayer {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("EndpointLayer")
+            .field("endpoint", &self.endpoint)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Builder of the facilitator [`Router`].
+///
+/// By default, all endpoints are enabled, served at the root, with the WebSocket
+/// endpoint at `/ws`, without any layer.
+#[derive(Debug, Clone)]
+pub struct FacilitatorRoutes {
+    prefix: String,
+    ws_path: String,
+    disabled: HashSet<Endpoint>,
+    layers: Vec<EndpointLayer>,
+}
+
+impl Default for FacilitatorRoutes {
+    fn default() -> Self {
+        Self {
+            prefix: String::new(),
+            ws_path: "/ws".to_string(),
+            disabled: HashSet::new(),
+            layers: Vec::new(),
+        }
+    }
+}
+
+impl FacilitatorRoutes {
+    /// Reads the route configuration from `ROUTE_PREFIX`, `WS_PATH` and `DISABLED_ENDPOINTS`.
+    /// Rate limits are read separately, by [`RateLimits::from_env`](crate::handlers::RateLimits::from_env).
+    pub fn from_env() -> Result<Self, RoutesConfigError> {
+        let mut routes = Self::default();
+        if let Ok(prefix) = env::var(ENV_ROUTE_PREFIX) {
+            routes = routes.with_prefix(&prefix)?;
+        }
+        if let Ok(ws_path) = env::var(ENV_WS_PATH) {
+            routes = routes.with_ws_path(&ws_path)?;
+        }
+        if let Ok(disabled) = env::var(ENV_DISABLED_ENDPOINTS) {
+            for endpoint in disabled.split(',').map(str::trim).filter(|s| !s.is_empty()) {
+                routes = routes.disable(endpoint.parse()?);
+            }
+        }
+        Ok(routes)
+    }
+
+    /// Serves all endpoints below `prefix`, e.g. `/api/v1`. An empty prefix serves them at the root.
+    pub fn with_prefix(&self, prefix: &str) -> Result<Self, RoutesConfigError> {
+        let prefix = prefix.trim_end_matches('/');
+        if !prefix.is_empty() && !prefix.starts_with('/') {
+            return Err(RoutesConfigError::InvalidPath(prefix.to_string()));
+        }
+        let mut this = self.clone();
+        this.prefix = prefix.to_string();
+        Ok(this)
+    }
+
+    /// Serves the WebSocket endpoint at `path`, below the prefix.
+    pub fn with_ws_path(&self, path: &str) -> Result<Self, RoutesConfigError> {
+        if !path.starts_with('/') || path.len() < 2 {
+            return Err(RoutesConfigError::InvalidPath(path.to_string()));
+        }
+        let mut this = self.clone();
+        this.ws_path = path.trim_end_matches('/').to_string();
+        Ok(this)
+    }
+
+    /// Turns `endpoint` off.
+    pub fn disable(&self, endpoint: Endpoint) -> Self {
+        let mut this = self.clone();
+        this.disabled.insert(endpoint);
+        this
+    }
+
+    pub fn is_enabled(&self, endpoint: Endpoint) -> bool {
+        !self.disabled.contains(&endpoint)
+    }
+
+    /// Wraps the routes of `endpoint` in `layer`, e.g. a `tower_governor` rate limiter or a
+    /// [`GlobalConcurrencyLimitLayer`](tower::limit::GlobalConcurrencyLimitLayer). Of several
+    /// layers of the same endpoint, the one added last runs first.
+    pub fn layer<L>(&self, endpoint: Endpoint, layer: L) -> Self
+    where
+        L: Layer<Route> + Clone + Send + Sync + 'static,
+        L::Service: Service<Request> + Clone + Send + Sync + 'static,
+        <L::Service as Service<Request>>::Response: IntoResponse + 'static,
+        <L::Service as Service<Request>>::Error: Into<Infallible> + 'static,
+        <L::Service as Service<Request>>::Future: Send + 'static,
+    {
+        let mut this = self.clone();
+        this.layers.push(EndpointLayer {
+            endpoint,
+            apply: Arc::new(move |router: Router| router.layer(layer.clone())),
+        });
+        this
+    }
+
+    /// Serves the custom `methods` on the WebSocket endpoint, next to those of the protocol, see
+    /// [`crate::ws_methods`]. `F` must be the facilitator the router is built for.
+    #[allow(dead_code)] // Public for consumption by downstream crates.
+    pub fn with_ws_methods<F: ServedFacilitator>(&self, methods: WsMethods<F>) -> Self {
+        self.layer(Endpoint::Ws, Extension(methods))
+    }
+
+    /// Adds the routes of `endpoint` to `router`, wrapped in the layers of the endpoint.
+    fn merge(&self, router: Router, endpoint: Endpoint, routes: Router) -> Router {
+        if !self.is_enabled(endpoint) {
+            return router;
+        }
+        let routes = self
+            .layers
+            .iter()
+            .filter(|layer| layer.endpoint == endpoint)
+            .fold(routes, |routes, layer| (layer.apply)(routes));
+        router.merge(routes)
+    }
+
+    /// Builds the router serving facilitator `F`, e.g. [`FacilitatorLocal`](crate::facilitator_local::FacilitatorLocal).
+    /// Handlers expect the facilitator as an [`Extension`](axum::Extension) layer.
+    ///
+    /// Rate limits identify clients by their address: serve the router with
+    /// [`into_make_service_with_connect_info`](Router::into_make_service_with_connect_info).
+    pub fn router<F: ServedFacilitator>(&self) -> Router {
+        let mut router = Router::new();
+        router = self.merge(
+            router,
+            Endpoint::Verify,
+            Router::new().route(
+                "/verify",
+                get(handlers::get_verify_info).post(handlers::post_verify::<F>),
+            ),
+        );
+        router = self.merge(
+            router,
+            Endpoint::VerifyBatch,
+            Router::new().route("/verify/batch", post(handlers::post_verify_batch::<F>)),
+        );
+        router = self.merge(
+            router,
+            Endpoint::Settle,
+            Router::new().route(
+                "/settle",
+                get(handlers::get_settle_info).post(handlers::post_settle::<F>),
+            ),
+        );
+        router = self.merge(
+            router,
+            Endpoint::Ws,
+            Router::new().route(&self.ws_path, get(handlers::ws_handler::<F>)),
+        );
+        router = self.merge(
+            router,
+            Endpoint::Supported,
+            Router::new().route("/supported", get(handlers::get_supported::<F>)),
+        );
+        router = self.merge(
+            router,
+            Endpoint::Discovery,
+            Router::new().route(
+                "/discovery/resources",
+                get(handlers::get_discovery_resources::<F>)
+                    .post(handlers::post_discovery_resources::<F>),
+            ),
+        );
+        router = self.merge(
+            router,
+            Endpoint::Settlement,
+            Router::new()
+                .route(
+                    "/settlement/{transaction}",
+                    get(handlers::get_settlement::<F>),
+                )
+                .route("/receipts/{transaction}", get(handlers::get_receipt::<F>)),
+        );
+        router = self.merge(
+            router,
+            Endpoint::Refund,
+            Router::new()
+                .route("/refund", post(handlers::post_refund::<F>))
+                .route("/refunds/{transaction}", get(handlers::get_refunds::<F>)),
+        );
+        router = self.merge(
+            router,
+            Endpoint::Stats,
+            Router::new().route("/stats", get(handlers::get_stats::<F>)),
+        );
+        router = self.merge(router, Endpoint::Payments, handlers::payments_routes::<F>());
+        router = self.merge(
+            router,
+            Endpoint::Balance,
+            Router::new().route("/balance", get(handlers::get_balance::<F>)),
+        );
+        router = self.merge(
+            router,
+            Endpoint::Health,
+            Router::new()
+                .route("/healthz", get(handlers::get_healthz::<F>))
+                .route("/readyz", get(handlers::get_readyz::<F>)),
+        );
+        router = self.merge(
+            router,
+            Endpoint::Admin,
+            Router::new().nest("/admin", handlers::admin_routes::<F>()),
+        );
+        if self.prefix.is_empty() {
+            router
+        } else {
+            Router::new().nest(&self.prefix, router)
+        }
+    }
+
+    /// Paths of the enabled endpoints, for logging at startup.
+    pub fn paths(&self) -> Vec<String> {
+        [
+            (Endpoint::Verify, "/verify"),
+            (Endpoint::VerifyBatch, "/verify/batch"),
+            (Endpoint::Settle, "/settle"),
+            (Endpoint::Ws, self.ws_path.as_str()),
+            (Endpoint::Supported, "/supported"),
+            (Endpoint::Discovery, "/discovery/resources"),
+            (Endpoint::Settlement, "/settlement/{transaction}"),
+            (Endpoint::Settlement, "/receipts/{transaction}"),
+            (Endpoint::Refund, "/refund"),
+            (Endpoint::Refund, "/refunds/{transaction}"),
+            (Endpoint::Stats, "/stats"),
+            (Endpoint::Payments, "/payments"),
+            (Endpoint::Balance, "/balance"),
+            (Endpoint::Health, "/healthz"),
+            (Endpoint::Health, "/readyz"),
+            (Endpoint::Admin, "/admin"),
+        ]
+        .into_iter()
+        .filter(|(endpoint, _)| self.is_enabled(*endpoint))
+        .map(|(_, path)| format!("{}{}", self.prefix, path))
+        .collect()
+    }
+}
@@ -0,0 +1,76 @@
+//! Settlement status polling: `GET /settlement/{transaction}`, see [`crate::settlement_events`],
+//! and settlement receipts: `GET /receipts/{transaction}`, see [`crate::receipts`].
+
+use axum::extract::Path;
+use axum::http::StatusCode;
+use axum::response::Response;
+use axum::{Extension, Json, response::IntoResponse};
+use tracing::instrument;
+
+use crate::handlers::ServedFacilitator;
+use crate::types::{ErrorResponse, TransactionHash};
+
+/// `GET /settlement/{transaction}`: latest event of the settlement by `transaction`, i.e. its
+/// status (`mined`, `confirmed`, `reorged`, `failed`) and confirmations so far.
+///
+/// Responds with `404 Not Found` for settlements the facilitator does not keep.
+#[instrument(skip_all)]
+pub async fn get_settlement<F: ServedFacilitator>(
+    Extension(facilitator): Extension<F>,
+    Path(transaction): Path<String>,
+) -> Response {
+    let transaction = match parse_transaction(transaction) {
+        Ok(transaction) => transaction,
+        Err(response) => return response,
+    };
+    match facilitator
+        .settlement_events()
+        .and_then(|events| events.status(&transaction))
+    {
+        Some(event) => Json(event).into_response(),
+        None => {
+            let error = ErrorResponse {
+                error: format!("Unknown settlement {transaction}"),
+            };
+            (StatusCode::NOT_FOUND, Json(error)).into_response()
+        }
+    }
+}
+
+/// `GET /receipts/{transaction}`: receipt of the settlement by `transaction`, signed by the
+/// facilitator.
+///
+/// Responds with `404 Not Found` for settlements the facilitator keeps no receipt of, and for
+/// every settlement if it does not sign receipts.
+#[instrument(skip_all)]
+pub async fn get_receipt<F: ServedFacilitator>(
+    Extension(facilitator): Extension<F>,
+    Path(transaction): Path<String>,
+) -> Response {
+    let transaction = match parse_transaction(transaction) {
+        Ok(transaction) => transaction,
+        Err(response) => return response,
+    };
+    match facilitator
+        .receipts()
+        .and_then(|receipts| receipts.get(&transaction))
+    {
+        Some(receipt) => Json(receipt).into_response(),
+        None => {
+            let error = ErrorResponse {
+                error: format!("No receipt of settlement {transaction}"),
+            };
+            (StatusCode::NOT_FOUND, Json(error)).into_response()
+        }
+    }
+}
+
+/// Parses the transaction hash of a path, or answers `400 Bad Request`.
+pub(super) fn parse_transaction(transaction: String) -> Result<TransactionHash, Response> {
+    serde_json::from_value(serde_json::Value::String(transaction)).map_err(|_| {
+        let error = ErrorResponse {
+            error: "Invalid transaction hash".to_string(),
+        };
+        (StatusCode::BAD_REQUEST, Json(error)).into_response()
+    })
+}
@@ -0,0 +1,233 @@
+//! Graceful shutdown of the facilitator.
+//!
+//! Once [`Shutdown::signal`] sees `SIGTERM` or `Ctrl-C`, the facilitator:
+//! 1. stops accepting connections, fails its readiness probe (`/readyz`), and lets the HTTP
+//!    requests in flight complete,
+//! 2. refuses WebSocket handshakes with `503 Service Unavailable`, and winds down the open
+//!    connections: each is sent an `x402.error` notification of kind `shuttingDown`, stops reading
+//!    requests, and is closed with code `1001` (going away) and reason `server-shutdown` once its
+//!    requests in flight are answered,
+//! 3. waits for the WebSocket requests in flight, settlements included, up to the drain timeout,
+//! 4. stops the worker of the settlement queue (see [`crate::settlement_queue`]) once its
+//!    settlement attempts in flight are recorded, and flushes the store.
+//!
+//! Payments still queued are settled after the restart with a persistent settlement store; an
+//! in-memory queue loses them, which is logged as an error. Work still in flight when the timeout
+//! elapses is abandoned: replay protection (see [`crate::nonce_store`]) keeps a settlement
+//! attempted again from being executed twice.
+//!
+//! Environment (see [`Shutdown::from_env`]):
+//! - `SHUTDOWN_TIMEOUT_SECS` – Time given to the work in flight to complete, up to 10 minutes
+//!   (default `30`)
+
+use axum::Extension;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::{Notify, watch};
+use tokio::time::Instant;
+
+use crate::duration::{self, DurationError, DurationRange};
+use crate::handlers::router::{Endpoint, FacilitatorRoutes};
+use crate::settlement_queue::SettlementQueue;
+
+const ENV_SHUTDOWN_TIMEOUT_SECS: &str = "SHUTDOWN_TIMEOUT_SECS";
+
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+/// Drain timeouts accepted: up to 10 minutes.
+pub const TIMEOUT_RANGE: DurationRange =
+    DurationRange::new(Duration::from_secs(1), Duration::from_secs(600));
+
+/// Reason of the close frames of the WebSocket connections closed by a shutdown.
+pub const CLOSE_REASON: &str = "server-shutdown";
+
+/// Shutdown controller of the facilitator, see the [module documentation](self).
+///
+/// Clones share the same shutdown.
+#[derive(Clone)]
+pub struct Shutdown {
+    timeout: Duration,
+    state: Arc<ShutdownState>,
+}
+
+struct ShutdownState {
+    /// Time the shutdown started at, once it did.
+    requested: watch::Sender<Option<Instant>>,
+    /// Work in flight, see [`Shutdown::in_flight`].
+    in_flight: AtomicUsize,
+    /// Notified when the last work in flight completes.
+    drained: Notify,
+    /// Queues whose worker is stopped on shutdown.
+    queues: Mutex<Vec<SettlementQueue>>,
+}
+
+impl Default for Shutdown {
+    fn default() -> Self {
+        Self {
+            timeout: DEFAULT_TIMEOUT,
+            state: Arc::new(ShutdownState {
+                requested: watch::Sender::new(None),
+                in_flight: AtomicUsize::new(0),
+                drained: Notify::new(),
+                queues: Mutex::new(Vec::new()),
+            }),
+        }
+    }
+}
+
+impl Shutdown {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Reads the drain timeout from `SHUTDOWN_TIMEOUT_SECS`, in seconds or with a unit (e.g.
+    /// `1m`).
+    pub fn from_env() -> Result<Self, DurationError> {
+        let timeout =
+            duration::from_env(ENV_SHUTDOWN_TIMEOUT_SECS, duration::SECOND, TIMEOUT_RANGE)?;
+        let this = Self::new();
+        match timeout {
+            Some(timeout) => this.with_timeout(timeout),
+            None => Ok(this),
+        }
+    }
+
+    /// Gives the work in flight `timeout` to complete.
+    pub fn with_timeout(&self, timeout: Duration) -> Result<Self, DurationError> {
+        Ok(Self {
+            timeout: TIMEOUT_RANGE.check(timeout)?,
+            state: self.state.clone(),
+        })
+    }
+
+    /// Time given to the work in flight to complete.
+    pub fn timeout(&self) -> Duration {
+        self.timeout
+    }
+
+    /// Winds down the WebSocket connections of `routes` on shutdown, and fails their readiness
+    /// probe (`/readyz`) once it started.
+    pub fn apply(&self, routes: &FacilitatorRoutes) -> FacilitatorRoutes {
+        routes
+            .layer(Endpoint::Ws, Extension(self.clone()))
+            .layer(Endpoint::Health, Extension(self.clone()))
+    }
+
+    /// Stops the worker of `queue` on shutdown.
+    pub fn stop_queue(&self, queue: SettlementQueue) {
+        self.state.queues.lock().unwrap().push(queue);
+    }
+
+    /// Starts the shutdown, unless already started.
+    pub fn request(&self) {
+        self.state.requested.send_if_modified(|requested| {
+            let start = requested.is_none();
+            if start {
+                *requested = Some(Instant::now());
+            }
+            start
+        });
+    }
+
+    /// Whether the shutdown started.
+    pub fn is_requested(&self) -> bool {
+        self.state.requested.borrow().is_some()
+    }
+
+    /// Resolves once the shutdown starts, with the time the work in flight must complete by.
+    pub async fn requested(&self) -> Instant {
+        let mut requested = self.state.requested.subscribe();
+        let requested_at = match requested.wait_for(Option::is_some).await {
+            Ok(requested_at) => *requested_at,
+            // The sender lives as long as `self`.
+            Err(_) => None,
+        };
+        requested_at.unwrap_or_else(Instant::now) + self.timeout
+    }
+
+    /// Resolves once the drain timeout elapsed since the shutdown started.
+    pub async fn elapsed(&self) {
+        let deadline = self.requested().await;
+        tokio::time::sleep_until(deadline).await;
+    }
+
+    /// Resolves on `SIGTERM` or `Ctrl-C`, starting the shutdown, or once the shutdown is started
+    /// otherwise. To pass to [`axum::serve::Serve::with_graceful_shutdown`].
+    pub async fn signal(self) {
+        tokio::select! {
+            _ = terminate() => {
+                tracing::info!(timeout = ?self.timeout(), "Shutting down");
+                self.request();
+            }
+            _ = self.requested() => {}
+        }
+    }
+
+    /// Marks work in flight until the returned guard is dropped: [`Self::drain`] waits for it.
+    pub fn in_flight(&self) -> InFlight {
+        self.state.in_flight.fetch_add(1, Ordering::SeqCst);
+        InFlight(self.state.clone())
+    }
+
+    /// Waits for the work in flight, up to the drain timeout counted from the start of the
+    /// shutdown, then stops the settlement queues. Starts the shutdown if not started yet.
+    pub async fn drain(&self) {
+        self.request();
+        let deadline = self.requested().await;
+        let drained = async {
+            loop {
+                let drained = self.state.drained.notified();
+                if self.state.in_flight.load(Ordering::SeqCst) == 0 {
+                    break;
+                }
+                drained.await;
+            }
+        };
+        if tokio::time::timeout_at(deadline, drained).await.is_err() {
+            tracing::warn!(
+                in_flight = self.state.in_flight.load(Ordering::SeqCst),
+                "Shutdown timeout elapsed, abandoning the requests in flight"
+            );
+        }
+        let queues = std::mem::take(&mut *self.state.queues.lock().unwrap());
+        for queue in queues {
+            queue.stop(deadline).await;
+        }
+    }
+}
+
+/// Work in flight, see [`Shutdown::in_flight`].
+pub struct InFlight(Arc<ShutdownState>);
+
+impl Drop for InFlight {
+    fn drop(&mut self) {
+        if self.0.in_flight.fetch_sub(1, Ordering::SeqCst) == 1 {
+            self.0.drained.notify_waiters();
+        }
+    }
+}
+
+/// Resolves on `SIGTERM` or `Ctrl-C`.
+async fn terminate() {
+    let ctrl_c = async {
+        if tokio::signal::ctrl_c().await.is_err() {
+            std::future::pending::<()>().await;
+        }
+    };
+    #[cfg(unix)]
+    let sigterm = async {
+        use tokio::signal::unix::{SignalKind, signal};
+        match signal(SignalKind::terminate()) {
+            Ok(mut sigterm) => {
+                sigterm.recv().await;
+            }
+            Err(_) => std::future::pending::<()>().await,
+        }
+    };
+    #[cfg(not(unix))]
+    let sigterm = std::future::pending::<()>();
+    tokio::select! {
+        _ = ctrl_c => {}
+        _ = sigterm => {}
+    }
+}
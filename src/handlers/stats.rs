@@ -0,0 +1,60 @@
+//! Settlement statistics: `GET /stats`, see [`crate::settlement_stats`].
+
+use axum::extract::Query;
+use axum::http::StatusCode;
+use axum::response::Response;
+use axum::{Extension, Json, response::IntoResponse};
+use serde::Deserialize;
+use tracing::instrument;
+
+use crate::duration;
+use crate::handlers::ServedFacilitator;
+use crate::settlement_stats::{DEFAULT_WINDOW, StatsError};
+use crate::types::ErrorResponse;
+
+impl IntoResponse for StatsError {
+    fn into_response(self) -> Response {
+        let status = match self {
+            StatsError::InvalidWindow(_) => StatusCode::BAD_REQUEST,
+            _ => StatusCode::INTERNAL_SERVER_ERROR,
+        };
+        let error = ErrorResponse {
+            error: self.to_string(),
+        };
+        (status, Json(error)).into_response()
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct StatsQuery {
+    /// Duration of the window, e.g. `1h` or `7d`; a bare integer counts seconds.
+    window: Option<String>,
+}
+
+/// `GET /stats`: statistics of the settlements of the last 24 hours, or of the window of the
+/// query string, e.g. `?window=7d`.
+#[instrument(skip_all)]
+pub async fn get_stats<F: ServedFacilitator>(
+    Extension(facilitator): Extension<F>,
+    Query(query): Query<StatsQuery>,
+) -> Response {
+    let Some(stats) = facilitator.settlement_stats() else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+    let window = match query.window {
+        Some(window) => match duration::parse(&window, duration::SECOND) {
+            Ok(window) => window,
+            Err(error) => return StatsError::InvalidWindow(error).into_response(),
+        },
+        None => DEFAULT_WINDOW,
+    };
+    match stats.report(window).await {
+        Ok(report) => Json(report.as_ref()).into_response(),
+        Err(error) => {
+            if !matches!(error, StatsError::InvalidWindow(_)) {
+                tracing::error!(%error, "Failed to compute settlement stats");
+            }
+            error.into_response()
+        }
+    }
+}
@@ -0,0 +1,2387 @@
+//! WebSocket endpoint implemented by the x402 **facilitator**.
+//!
+//! Mirrors the HTTP endpoints over a single long-lived connection using a
+//! JSON-RPC-like envelope (`{ id, method, params }` → `{ id, result }` or `{ id, error }`),
+//! as described in `x402-ws-stream.md`.
+//!
+//! Methods:
+//! - `x402.hello` → connection metadata, including the connection id, the extensions negotiated,
+//!   and the challenge to sign if authentication is required
+//! - `x402.auth` → authenticate the connection, see [`ws_auth`](crate::handlers::ws_auth)
+//! - `x402.supported` → lists supported kinds
+//! - `x402.verify` → verify a [`VerifyRequest`]
+//! - `x402.verifyBatch` → verify an array of [`VerifyRequest`]s concurrently
+//! - `x402.settle` → settle a [`SettleRequest`], or queue its settlement in deferred mode
+//!   (see [`crate::settlement_queue`])
+//! - `x402.settleBatch` → settle an array of [`SettleRequest`]s concurrently
+//! - `x402.settlementStatus` → progress of a deferred settlement
+//! - `x402.receipt` → signed receipt of a settlement, see [`crate::receipts`]
+//! - `x402.refund` → refund part or all of a settled payment, see [`crate::refunds`]
+//! - `x402.subscribe` → follow the settlement of a payment (`x402.settlement` notifications)
+//! - `x402.watchPayments` → subscribe to inbound payments (`webhooks` feature)
+//! - `x402.discovery.list` → list payable resources, see [`crate::discovery`]
+//! - `stream.init` → open a pay-per-slice stream session, see [`crate::stream`]
+//! - `stream.require` → issue the requirements of the next slice of a stream
+//! - `stream.require.cancel` → cancel the pending requirements of a stream, or reprice them
+//! - `stream.pay` → verify (and settle) the payment of the required slice
+//! - `balance.deposit` → settle a deposit to a prepaid balance, see [`crate::balance`]
+//! - `balance.charge` → charge the price of a request against a prepaid balance
+//! - `balance.get` → a prepaid balance
+//!
+//! Methods of optional features belong to protocol extensions, which a peer may negotiate in
+//! `x402.hello`; methods of extensions the connection did not negotiate are rejected as
+//! unavailable (`-32601`), see [`crate::extensions`].
+//!
+//! Envelopes are JSON text frames, or CBOR binary frames if the peer selects the `x402.cbor`
+//! subprotocol in the handshake, see [`crate::ws_codec`]. With [`WsCompression`] applied to the
+//! routes, peers selecting the `x402.deflate` or `x402.cbor.deflate` subprotocol instead get large
+//! envelopes compressed, see [`ws_compression`](crate::handlers::ws_compression). Peers selecting
+//! the `x402.jsonrpc` subprotocol get strict JSON-RPC 2.0 envelopes instead, with notifications
+//! and batches, see [`crate::ws_jsonrpc`]; the requests of a batch are handled concurrently.
+//!
+//! Subscriptions push notifications `{ method, params }` (without `id`) on the connection until
+//! it closes.
+//!
+//! Problems with the connection as a whole, rather than with a request, are pushed as `x402.error`
+//! notifications carrying a [`ConnectionError`], e.g. when a subscription dropped events because
+//! the peer did not read them fast enough.
+//!
+//! Every connection is assigned a connection id. It is recorded, together with the envelope id
+//! and method of each request, on the tracing spans wrapping the connection and every request,
+//! so all events emitted while serving a request can be correlated with a buyer report.
+//!
+//! With [`WsAuth`] applied to the routes, connections must authenticate before calling other
+//! methods than `x402.hello` and `x402.auth`; requests of unauthenticated connections are answered
+//! with an `Unauthorized` error (`-32001`).
+//!
+//! Requests mirroring a rate limited HTTP endpoint are limited the same way, see
+//! [`RateLimits`](crate::handlers::RateLimits); requests over the limit are answered with a
+//! `Rate limited` error (`-32029`) whose data carries `retryAfterMs`.
+//!
+//! With [`WsErrorData`] applied to the routes, the `data` of the errors sent to connections that
+//! did not authenticate is redacted, bounded or omitted, see [`ws_error_data`](crate::handlers::ws_error_data).
+//!
+//! Requests are isolated from each other: a panic while handling one request is caught and
+//! answered with an internal error (`-32603`), and the connection keeps serving. Caught panics
+//! are counted in the `x402_ws_panics` metric.
+//!
+//! Requests of a connection are handled concurrently, up to `WS_MAX_CONCURRENT_REQUESTS` at
+//! once (default `16`), so a slow `x402.settle` does not hold back the `x402.verify` sent after
+//! it. Responses are sent as they complete, possibly out of order, and peers correlate them by
+//! `id`. `x402.hello` and `x402.auth`, which change the state of the connection, are handled in
+//! order: requests sent after them see the extensions negotiated and the authentication. Once
+//! the limit is reached, the connection is not read until a request completes. Requests in
+//! flight when the peer disconnects run to completion, so no settlement is abandoned midway.
+//!
+//! With [`Shutdown`] applied to the routes, handshakes are refused with `503 Service Unavailable`
+//! once the facilitator shuts down, and open connections are sent an `x402.error` notification of
+//! kind `shuttingDown`, then closed with code `1001` and reason `server-shutdown` once their
+//! requests in flight are answered, see [`shutdown`](crate::handlers::shutdown).
+//!
+//! With [`WsLiveness`] applied to the routes, connections are pinged, and closed once their peer
+//! stops answering, once idle, or once open for their maximum lifetime, see
+//! [`ws_liveness`](crate::handlers::ws_liveness).
+//!
+//! Embedders may serve their own methods next to these, registered in a [`WsMethods`] handed to
+//! the routes with [`FacilitatorRoutes::with_ws_methods`](crate::handlers::FacilitatorRoutes::with_ws_methods),
+//! see [`crate::ws_methods`].
+
+use axum::extract::ws::{CloseFrame, Message, WebSocket, close_code};
+use axum::extract::{ConnectInfo, WebSocketUpgrade};
+use axum::http::{HeaderMap, StatusCode};
+use axum::response::Response;
+use axum::{Extension, response::IntoResponse};
+use futures_util::future::join_all;
+use futures_util::stream::SplitSink;
+use futures_util::{FutureExt, SinkExt, StreamExt};
+use governor::InsufficientCapacity;
+use once_cell::sync::Lazy;
+use std::any::Any;
+use std::future::Future;
+use std::net::SocketAddr;
+use std::num::NonZeroU32;
+use std::panic::AssertUnwindSafe;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::{Semaphore, mpsc};
+use tokio::task::AbortHandle;
+use tokio::time::Instant;
+use tracing::{Instrument, instrument};
+
+use crate::extensions::{ExtensionDescriptor, ExtensionRegistry};
+use crate::handlers::ServedFacilitator;
+use crate::handlers::batch::{env_or, settle_batch, verify_batch};
+use crate::handlers::map_error_to_verify_response;
+use crate::handlers::rate_limit::WsClientLimits;
+use crate::handlers::router::Endpoint;
+use crate::handlers::shutdown::{self, Shutdown};
+use crate::handlers::ws_auth::{self, WsAuth, WsAuthParams, WsAuthState};
+use crate::handlers::ws_compression::WsCompression;
+use crate::handlers::ws_connections::{WsConnectionInfo, WsConnections};
+use crate::handlers::ws_error_data::WsErrorData;
+use crate::handlers::ws_liveness::{self, Heartbeat, WsLiveness, WsLivenessHello};
+use crate::timestamp::UnixTimestampMs;
+use crate::types::{ConnectionError, ConnectionErrorKind, SettleRequest, VerifyRequest};
+use crate::verify_pool;
+use crate::ws_codec::{
+    CBOR_DEFLATE_SUBPROTOCOL, CBOR_SUBPROTOCOL, DEFLATE_SUBPROTOCOL, WsCodec, WsDeflate, WsFrame,
+};
+use crate::ws_error::WsError;
+use crate::ws_jsonrpc::{
+    self, JSONRPC_SUBPROTOCOL, JsonRpcInvalid, JsonRpcMessage, JsonRpcRequest,
+};
+use crate::ws_methods::{WsMethodContext, WsMethods};
+
+const ENV_WS_MAX_CONCURRENT_REQUESTS: &str = "WS_MAX_CONCURRENT_REQUESTS";
+
+/// Requests of a connection handled at once.
+static WS_MAX_CONCURRENT_REQUESTS: Lazy<usize> =
+    Lazy::new(|| env_or(ENV_WS_MAX_CONCURRENT_REQUESTS, 16));
+/// Control frames (pings, pongs, close) queued for the writer of a connection.
+const CONTROL_FRAMES: usize = 8;
+
+/// `GET /ws`: WebSocket endpoint that mirrors facilitator methods per x402-ws-stream.
+///
+/// Handshakes carrying a bearer token not allowed by the [`WsAuth`] of the endpoint, if any,
+/// are rejected with `401 Unauthorized`.
+#[instrument(skip_all)]
+pub async fn ws_handler<F: ServedFacilitator>(
+    Extension(facilitator): Extension<F>,
+    auth: Option<Extension<WsAuth>>,
+    limits: Option<Extension<WsClientLimits>>,
+    error_data: Option<Extension<WsErrorData>>,
+    liveness: Option<Extension<WsLiveness>>,
+    compression: Option<Extension<WsCompression>>,
+    methods: Option<Extension<WsMethods<F>>>,
+    shutdown: Option<Extension<Shutdown>>,
+    connections: Option<Extension<WsConnections>>,
+    connect_info: Option<Extension<ConnectInfo<SocketAddr>>>,
+    headers: HeaderMap,
+    ws: WebSocketUpgrade,
+) -> Response {
+    let shutdown = shutdown.map(|Extension(shutdown)| shutdown);
+    if shutdown.as_ref().is_some_and(Shutdown::is_requested) {
+        tracing::info!("Refused WS handshake during shutdown");
+        return StatusCode::SERVICE_UNAVAILABLE.into_response();
+    }
+    let auth = match auth {
+        Some(Extension(auth)) => match auth.authenticate_headers(&headers) {
+            Ok(session) => Some((auth.state(session), auth)),
+            Err(()) => {
+                tracing::warn!("Rejected WS handshake with an unknown bearer token");
+                return StatusCode::UNAUTHORIZED.into_response();
+            }
+        },
+        None => None,
+    };
+    let limits = limits.map(|Extension(limits)| limits);
+    let error_data = error_data.map(|Extension(error_data)| error_data);
+    let liveness = liveness.map(|Extension(liveness)| liveness);
+    let compression = compression.map(|Extension(compression)| compression);
+    let methods = methods.map(|Extension(methods)| methods);
+    let connections = connections.map(|Extension(connections)| connections);
+    let peer = connect_info.map(|Extension(ConnectInfo(peer))| peer);
+    let protocols = match compression {
+        Some(_) => vec![
+            CBOR_DEFLATE_SUBPROTOCOL,
+            DEFLATE_SUBPROTOCOL,
+            CBOR_SUBPROTOCOL,
+            JSONRPC_SUBPROTOCOL,
+        ],
+        None => vec![CBOR_SUBPROTOCOL, JSONRPC_SUBPROTOCOL],
+    };
+    ws.protocols(protocols)
+        .on_upgrade(move |socket| {
+            let protocol = socket
+                .protocol()
+                .and_then(|protocol| protocol.to_str().ok());
+            let subprotocol = protocol.map(str::to_string);
+            let (connection, outgoing) = WsConnection::new(
+                facilitator,
+                protocol,
+                compression,
+                auth,
+                limits,
+                error_data,
+                liveness,
+                methods,
+            );
+            let span = tracing::info_span!(
+                "ws_connection",
+                connection_id = %connection.id,
+                rtt_ms = tracing::field::Empty,
+                close_reason = tracing::field::Empty,
+            );
+            let registration = connections.map(|connections| {
+                connections.register(WsConnectionInfo {
+                    id: connection.id.clone(),
+                    subprotocol,
+                    peer,
+                    opened_at: UnixTimestampMs::now(),
+                })
+            });
+            async move {
+                let _registration = registration;
+                ws_serve(socket, connection, outgoing, shutdown).await
+            }
+            .instrument(span)
+        })
+        .into_response()
+}
+
+/// Authentication of a connection to an endpoint requiring it.
+struct WsConnectionAuth {
+    auth: WsAuth,
+    state: Mutex<WsAuthState>,
+    /// Challenge to sign for the next authentication by signature.
+    challenge: Mutex<String>,
+}
+
+/// State scoped to a single WebSocket connection.
+struct WsConnection<F> {
+    /// Unique id of the connection, returned to the peer in `x402.hello`.
+    id: String,
+    facilitator: F,
+    /// Encoding of the envelopes, negotiated in the handshake.
+    codec: WsCodec,
+    /// Compression of the envelopes, negotiated in the handshake, `None` if not compressed.
+    deflate: Option<WsDeflate>,
+    /// Whether the envelopes are strict JSON-RPC 2.0, negotiated in the handshake.
+    jsonrpc: bool,
+    /// Queue of the envelopes to send, responses and server-pushed notifications, drained by
+    /// [`ws_write`].
+    outgoing: mpsc::UnboundedSender<String>,
+    /// Tasks feeding subscriptions, aborted when the connection closes.
+    subscriptions: Mutex<Vec<AbortHandle>>,
+    /// Authentication of the connection, `None` if the endpoint does not require it.
+    auth: Option<WsConnectionAuth>,
+    /// Rate limits of the requests, `None` if the endpoint does not limit them.
+    limits: Option<WsClientLimits>,
+    /// Policy on the `data` of errors sent while not authenticated, `None` to send it as is.
+    error_data: Option<WsErrorData>,
+    /// Liveness policy of the connection, `None` to leave dead peers to TCP.
+    liveness: Option<WsLiveness>,
+    /// Custom methods served next to those of the protocol, `None` if there are none.
+    methods: Option<WsMethods<F>>,
+    /// Time a request was last received or an envelope last sent, see [`Self::touch`].
+    last_activity: Mutex<Instant>,
+    /// Extensions served by the facilitator.
+    extensions: ExtensionRegistry,
+    /// Extensions negotiated by the connection, all those served until it negotiates.
+    negotiated: Mutex<ExtensionRegistry>,
+}
+
+impl<F: ServedFacilitator> WsConnection<F> {
+    /// State of a connection that selected the subprotocol `protocol` in the handshake, if any.
+    fn new(
+        facilitator: F,
+        protocol: Option<&str>,
+        compression: Option<WsCompression>,
+        auth: Option<(WsAuthState, WsAuth)>,
+        limits: Option<WsClientLimits>,
+        error_data: Option<WsErrorData>,
+        liveness: Option<WsLiveness>,
+        methods: Option<WsMethods<F>>,
+    ) -> (Self, mpsc::UnboundedReceiver<String>) {
+        let (outgoing, receiver) = mpsc::unbounded_channel();
+        let id = uuid::Uuid::new_v4().to_string();
+        let auth = auth.map(|(state, auth)| WsConnectionAuth {
+            auth,
+            state: Mutex::new(state),
+            challenge: Mutex::new(ws_auth::challenge(&id)),
+        });
+        let codec = WsCodec::from_subprotocol(protocol);
+        let mut extensions = facilitator.extensions();
+        let mut deflate = None;
+        if let Some(compression) = compression {
+            extensions = extensions.with(ExtensionDescriptor::compression());
+            deflate = WsDeflate::from_subprotocol(protocol).map(|_| compression.deflate());
+        }
+        if let Some(methods) = &methods {
+            extensions = extensions.with(methods.extension().clone());
+        }
+        let negotiated = Mutex::new(negotiate(&extensions, codec, deflate.is_some(), None));
+        let connection = Self {
+            id,
+            facilitator,
+            codec,
+            deflate,
+            jsonrpc: protocol == Some(JSONRPC_SUBPROTOCOL),
+            outgoing,
+            subscriptions: Mutex::new(Vec::new()),
+            auth,
+            limits,
+            error_data,
+            liveness,
+            methods,
+            last_activity: Mutex::new(Instant::now()),
+            extensions,
+            negotiated,
+        };
+        (connection, receiver)
+    }
+
+    /// Records activity on the connection, which is then not idle.
+    fn touch(&self) {
+        *self.last_activity.lock().unwrap() = Instant::now();
+    }
+
+    /// Time the connection was last active, see [`Self::touch`].
+    fn last_activity(&self) -> Instant {
+        *self.last_activity.lock().unwrap()
+    }
+
+    /// Next time the authentication of the connection must be looked at, see [`Self::on_auth_deadline`].
+    fn auth_deadline(&self) -> Option<Instant> {
+        self.auth.as_ref()?.state.lock().unwrap().deadline()
+    }
+
+    /// Expires the session of the connection if it is over, notifying the peer.
+    /// Returns `false` if the connection did not authenticate in time, and must be closed.
+    fn on_auth_deadline(&self) -> bool {
+        let Some(auth) = &self.auth else {
+            return true;
+        };
+        let mut state = auth.state.lock().unwrap();
+        match &*state {
+            WsAuthState::Pending { until } => *until > Instant::now(),
+            WsAuthState::Authenticated(session) => {
+                if session.is_expired() {
+                    *state = auth.auth.state(None);
+                    let _ = self
+                        .outgoing
+                        .send(connection_error(ConnectionError::new(
+                            ConnectionErrorKind::AuthExpired,
+                            "Authentication expired, authenticate again with x402.auth",
+                        )));
+                }
+                true
+            }
+        }
+    }
+
+    /// Takes `n` rate limit tokens for a request mirroring `endpoint`, or returns the time until
+    /// they are available, see [`WsClientLimits::check`].
+    fn check_rate_limit(
+        &self,
+        endpoint: Endpoint,
+        n: NonZeroU32,
+    ) -> Result<Result<(), Duration>, InsufficientCapacity> {
+        let Some(limits) = &self.limits else {
+            return Ok(Ok(()));
+        };
+        let key = self
+            .auth
+            .as_ref()
+            .and_then(|auth| match &*auth.state.lock().unwrap() {
+                WsAuthState::Authenticated(session) => session.rate_limit_key(),
+                WsAuthState::Pending { .. } => None,
+            });
+        limits.check(endpoint, key.as_deref(), n)
+    }
+
+    /// Context of a request to a custom method, with the identity the connection authenticated
+    /// with, if any.
+    fn method_context(&self) -> WsMethodContext<F> {
+        let mut context = WsMethodContext::new(self.facilitator.clone(), &self.id);
+        if let Some(auth) = &self.auth
+            && let WsAuthState::Authenticated(session) = &*auth.state.lock().unwrap()
+        {
+            if let Some(address) = session.address {
+                context = context.with_address(address);
+            }
+            if let Some(api_key) = &session.api_key {
+                context = context.with_api_key(api_key.clone());
+            }
+        }
+        context
+    }
+
+    /// Extension adding `method`, if the connection did not negotiate it.
+    fn missing_extension(&self, method: &str) -> Option<&ExtensionDescriptor> {
+        let extension = self.extensions.extension_of(method)?;
+        let negotiated = self.negotiated.lock().unwrap();
+        negotiated
+            .get(&extension.name)
+            .is_none()
+            .then_some(extension)
+    }
+
+    /// Whether the connection may call methods other than `x402.hello` and `x402.auth`.
+    fn is_authorized(&self) -> bool {
+        self.auth
+            .as_ref()
+            .is_none_or(|auth| auth.state.lock().unwrap().is_authenticated())
+    }
+
+    /// Whether the connection authenticated, and is trusted with the details of errors.
+    fn is_authenticated(&self) -> bool {
+        self.auth
+            .as_ref()
+            .is_some_and(|auth| auth.state.lock().unwrap().is_authenticated())
+    }
+
+    /// Queues the response to a request, the `data` of an error being filtered by the
+    /// [`WsErrorData`] policy unless the connection authenticated.
+    fn respond(&self, response: String) {
+        let response = match &self.error_data {
+            Some(policy) if policy.is_restrictive() && !self.is_authenticated() => {
+                filter_error_data(policy, response)
+            }
+            _ => response,
+        };
+        let _ = self.outgoing.send(response);
+    }
+
+    /// Runs a subscription task for at most the lifetime of the connection.
+    fn subscribe<T: Future<Output = ()> + Send + 'static>(&self, task: T) {
+        let handle = tokio::spawn(task.in_current_span()).abort_handle();
+        let mut subscriptions = self.subscriptions.lock().unwrap();
+        subscriptions.retain(|subscription| !subscription.is_finished());
+        subscriptions.push(handle);
+    }
+
+    /// Sends a JSON envelope to the peer, transcoded to the codec of the connection, and
+    /// compressed if negotiated. Returns `false` if the socket is gone.
+    async fn send(&self, sink: &mut SplitSink<WebSocket, Message>, text: String) -> bool {
+        #[cfg(feature = "chaos")]
+        if let Some(faults) = self.facilitator.faults()
+            && faults.should_drop_frame()
+        {
+            return true;
+        }
+        let text = if self.jsonrpc {
+            stamp_jsonrpc(text)
+        } else {
+            text
+        };
+        let frame = match self.codec {
+            WsCodec::Json => WsFrame::Text(text),
+            codec => {
+                let frame = serde_json::from_str::<serde_json::Value>(&text)
+                    .map_err(Into::into)
+                    .and_then(|envelope| codec.encode(&envelope));
+                match frame {
+                    Ok(frame) => frame,
+                    Err(error) => {
+                        tracing::warn!(%error, "Can not encode WS envelope, sending JSON");
+                        WsFrame::Text(text)
+                    }
+                }
+            }
+        };
+        let frame = match &self.deflate {
+            Some(deflate) => deflate.compress(frame),
+            None => frame,
+        };
+        let message = match frame {
+            WsFrame::Binary(bytes) => Message::Binary(bytes.into()),
+            WsFrame::Text(text) => Message::Text(text.into()),
+        };
+        sink.send(message).await.is_ok()
+    }
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct WsEnvelopeReq {
+    id: serde_json::Value,
+    method: String,
+    #[serde(default)]
+    params: serde_json::Value,
+    /// Whether the request is a JSON-RPC 2.0 notification, which is not answered.
+    #[serde(skip)]
+    notification: bool,
+}
+
+/// Requests of a frame received.
+enum WsIncoming {
+    Request(WsEnvelopeReq),
+    /// JSON-RPC 2.0 batch, its invalid entries being answered with the errors they hold.
+    Batch(Vec<Result<WsEnvelopeReq, String>>),
+    /// Error answering a frame holding no valid request, in JSON-RPC 2.0 mode.
+    Invalid(String),
+}
+
+#[derive(serde::Serialize)]
+struct WsEnvelopeOk<'a, T: serde::Serialize> {
+    id: &'a serde_json::Value,
+    result: T,
+}
+
+#[derive(serde::Serialize)]
+struct WsEnvelopeErr<'a> {
+    id: &'a serde_json::Value,
+    error: WsError,
+}
+
+/// Server-pushed notification of a subscription.
+#[derive(serde::Serialize)]
+struct WsNotification<T: serde::Serialize> {
+    method: &'static str,
+    params: T,
+}
+
+/// Result of the `x402.hello` method.
+#[derive(serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+struct WsHello<'a> {
+    connection_id: &'a str,
+    version: &'static str,
+    /// Extensions negotiated by the connection.
+    extensions: Vec<ExtensionDescriptor>,
+    /// Challenge to sign with `x402.auth`, if the endpoint requires authentication.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    challenge: Option<String>,
+    /// Liveness policy of the connection, if the endpoint has one.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    liveness: Option<WsLivenessHello>,
+}
+
+/// Params of the `x402.hello` method.
+#[derive(Debug, Default, serde::Deserialize)]
+struct WsHelloParams {
+    /// Names of the extensions the peer wants, `None` to keep those negotiated.
+    #[serde(default)]
+    extensions: Option<Vec<String>>,
+}
+
+/// Extensions of `served` negotiated by a connection encoded with `codec`, and `compressed` or
+/// not: those `requested`, or all of them if `None`, with the extensions negotiated in the
+/// handshake only if in use.
+fn negotiate(
+    served: &ExtensionRegistry,
+    codec: WsCodec,
+    compressed: bool,
+    requested: Option<&[String]>,
+) -> ExtensionRegistry {
+    let mut negotiated = match requested {
+        Some(requested) => served.negotiate(requested),
+        None => served.clone(),
+    };
+    for extension in served.descriptors().iter().filter(|e| e.is_handshake()) {
+        let in_use = match extension.name.as_str() {
+            ExtensionDescriptor::BINARY => codec == WsCodec::Cbor,
+            ExtensionDescriptor::COMPRESSION => compressed,
+            _ => false,
+        };
+        negotiated = if in_use {
+            negotiated.with(extension.clone())
+        } else {
+            negotiated.without(&extension.name)
+        };
+    }
+    negotiated
+}
+
+impl<F> Drop for WsConnection<F> {
+    fn drop(&mut self) {
+        for subscription in self.subscriptions.lock().unwrap().drain(..) {
+            subscription.abort();
+        }
+    }
+}
+
+/// Reads the requests of a connection, and hands every one to a task of its own, up to
+/// [`WS_MAX_CONCURRENT_REQUESTS`] at once. Frames are written by [`ws_write`].
+///
+/// Once `shutdown` starts, or the connection reaches its maximum lifetime, stops reading, and
+/// closes the connection when the requests in flight are answered. Pings the peer, and closes
+/// idle connections, per the [`WsLiveness`] of the connection.
+async fn ws_serve<F: ServedFacilitator>(
+    socket: WebSocket,
+    connection: WsConnection<F>,
+    outgoing: mpsc::UnboundedReceiver<String>,
+    shutdown: Option<Shutdown>,
+) {
+    tracing::info!(counter.x402_ws_connections = 1, "WS connection opened");
+    let _in_flight = shutdown.as_ref().map(Shutdown::in_flight);
+    let shutting_down = async {
+        match &shutdown {
+            Some(shutdown) => shutdown.requested().await,
+            None => std::future::pending().await,
+        }
+    };
+    tokio::pin!(shutting_down);
+    let connection = Arc::new(connection);
+    let (sink, mut stream) = socket.split();
+    let (frames, frames_rx) = mpsc::channel(CONTROL_FRAMES);
+    let writer =
+        tokio::spawn(ws_write(sink, connection.clone(), outgoing, frames_rx).in_current_span());
+    let requests = Arc::new(Semaphore::new(*WS_MAX_CONCURRENT_REQUESTS));
+    let mut heartbeat = Heartbeat::new(connection.liveness.clone());
+    let lifetime_deadline = heartbeat.lifetime_deadline();
+    loop {
+        let auth_deadline = connection.auth_deadline();
+        let idle_deadline = heartbeat.idle_deadline(connection.last_activity());
+        let msg = tokio::select! {
+            msg = stream.next() => match msg {
+                Some(Ok(msg)) => msg,
+                _ => break,
+            },
+            // The writer stops once the socket is gone.
+            _ = frames.closed() => break,
+            _ = &mut shutting_down => {
+                close_on_shutdown(&connection, &frames, &requests).await;
+                break;
+            }
+            _ = sleep_until(auth_deadline) => {
+                if !connection.on_auth_deadline() {
+                    tracing::info!("Closing WS connection not authenticated in time");
+                    let _ = frames
+                        .send(Message::Close(Some(CloseFrame {
+                            code: close_code::POLICY,
+                            reason: "Authentication timeout".into(),
+                        })))
+                        .await;
+                    break;
+                }
+                continue;
+            }
+            _ = sleep_until(heartbeat.next_ping()) => {
+                let _ = frames.send(heartbeat.ping()).await;
+                continue;
+            }
+            _ = sleep_until(heartbeat.pong_deadline()) => {
+                tracing::info!("Closing WS connection not answering pings");
+                close_on_liveness(&frames, close_code::AWAY, ws_liveness::PING_TIMEOUT_REASON)
+                    .await;
+                break;
+            }
+            _ = sleep_until(idle_deadline) => {
+                // Requests in flight keep the connection active.
+                if requests.available_permits() < *WS_MAX_CONCURRENT_REQUESTS {
+                    connection.touch();
+                    continue;
+                }
+                tracing::info!("Closing idle WS connection");
+                close_on_liveness(&frames, close_code::NORMAL, ws_liveness::IDLE_TIMEOUT_REASON)
+                    .await;
+                break;
+            }
+            _ = sleep_until(lifetime_deadline) => {
+                close_on_lifetime(&connection, &frames, &requests).await;
+                break;
+            }
+        };
+        if let Some(rtt) = heartbeat.received(matches!(msg, Message::Pong(_))) {
+            tracing::Span::current().record("rtt_ms", rtt.as_millis() as u64);
+        }
+        let frame = match msg {
+            Message::Text(text) => WsFrame::Text(text.to_string()),
+            Message::Binary(bin) => WsFrame::Binary(bin.to_vec()),
+            Message::Ping(p) => {
+                let _ = frames.send(Message::Pong(p)).await;
+                continue;
+            }
+            Message::Close(_) => break,
+            _ => continue,
+        };
+        let Some(incoming) = decode_request(frame, &connection) else {
+            continue;
+        };
+        connection.touch();
+        // Requests changing the state of the connection are handled before reading the next.
+        if let WsIncoming::Request(req) = &incoming
+            && matches!(req.method.as_str(), "x402.hello" | "x402.auth")
+        {
+            if let Some(response) = handle_ws_envelope(req, &connection).await {
+                connection.respond(response);
+            }
+            continue;
+        }
+        let permit = tokio::select! {
+            permit = requests.clone().acquire_owned() => permit.expect("Semaphore is never closed"),
+            _ = frames.closed() => break,
+        };
+        let connection = connection.clone();
+        let in_flight = shutdown.as_ref().map(Shutdown::in_flight);
+        tokio::spawn(
+            async move {
+                if let Some(response) = handle_ws_incoming(incoming, &connection).await {
+                    connection.respond(response);
+                }
+                drop(permit);
+                drop(in_flight);
+            }
+            .in_current_span(),
+        );
+    }
+    drop(frames);
+    let _ = writer.await;
+    tracing::info!(
+        counter.x402_ws_connections = -1,
+        age = ?heartbeat.age(),
+        rtt = ?heartbeat.rtt(),
+        "WS connection closed"
+    );
+}
+
+/// Tells the peer of `connection` that the facilitator shuts down, waits for its requests in
+/// flight to be answered, and closes it.
+async fn close_on_shutdown<F: ServedFacilitator>(
+    connection: &WsConnection<F>,
+    frames: &mpsc::Sender<Message>,
+    requests: &Semaphore,
+) {
+    tracing::info!("Closing WS connection on shutdown");
+    wind_down(
+        connection,
+        frames,
+        requests,
+        "The facilitator is shutting down",
+        shutdown::CLOSE_REASON,
+    )
+    .await;
+}
+
+/// Tells the peer of `connection` that it reached its maximum lifetime, waits for its requests in
+/// flight to be answered, and closes it.
+async fn close_on_lifetime<F: ServedFacilitator>(
+    connection: &WsConnection<F>,
+    frames: &mpsc::Sender<Message>,
+    requests: &Semaphore,
+) {
+    tracing::info!("Closing WS connection at its maximum lifetime");
+    record_liveness_close(ws_liveness::MAX_LIFETIME_REASON);
+    wind_down(
+        connection,
+        frames,
+        requests,
+        "The connection reached its maximum lifetime, reconnect",
+        ws_liveness::MAX_LIFETIME_REASON,
+    )
+    .await;
+}
+
+/// Sends the peer of `connection` an `x402.error` notification of kind `shuttingDown` carrying
+/// `message`, waits for its requests in flight to be answered, and closes it with code `1001`
+/// and `reason`.
+async fn wind_down<F: ServedFacilitator>(
+    connection: &WsConnection<F>,
+    frames: &mpsc::Sender<Message>,
+    requests: &Semaphore,
+    message: &str,
+    reason: &'static str,
+) {
+    connection.respond(connection_error(ConnectionError::new(
+        ConnectionErrorKind::ShuttingDown,
+        message,
+    )));
+    let _ = requests
+        .acquire_many(*WS_MAX_CONCURRENT_REQUESTS as u32)
+        .await;
+    let _ = frames
+        .send(Message::Close(Some(CloseFrame {
+            code: close_code::AWAY,
+            reason: reason.into(),
+        })))
+        .await;
+}
+
+/// Closes a connection right away, with `code` and `reason`, per its liveness policy.
+async fn close_on_liveness(frames: &mpsc::Sender<Message>, code: u16, reason: &'static str) {
+    record_liveness_close(reason);
+    let _ = frames
+        .send(Message::Close(Some(CloseFrame {
+            code,
+            reason: reason.into(),
+        })))
+        .await;
+}
+
+/// Counts a connection closed by its liveness policy, and records `reason` on its span.
+fn record_liveness_close(reason: &'static str) {
+    tracing::Span::current().record("close_reason", reason);
+    tracing::info!(
+        monotonic_counter.x402_ws_liveness_closes = 1,
+        reason,
+        "WS connection closed by its liveness policy"
+    );
+}
+
+/// Writes the frames of a connection: the control frames of [`ws_serve`] first, then the
+/// envelopes queued on the connection. Envelopes queued before a close frame are written before
+/// it. Stops once the socket is gone, a close frame is sent, or [`ws_serve`] stops reading.
+async fn ws_write<F: ServedFacilitator>(
+    mut sink: SplitSink<WebSocket, Message>,
+    connection: Arc<WsConnection<F>>,
+    mut outgoing: mpsc::UnboundedReceiver<String>,
+    mut frames: mpsc::Receiver<Message>,
+) {
+    loop {
+        tokio::select! {
+            biased;
+            frame = frames.recv() => {
+                let Some(frame) = frame else {
+                    break;
+                };
+                let close = matches!(frame, Message::Close(_));
+                if close {
+                    while let Ok(envelope) = outgoing.try_recv() {
+                        if !connection.send(&mut sink, envelope).await {
+                            return;
+                        }
+                    }
+                }
+                if sink.send(frame).await.is_err() || close {
+                    break;
+                }
+            }
+            Some(envelope) = outgoing.recv() => {
+                if !connection.send(&mut sink, envelope).await {
+                    break;
+                }
+                connection.touch();
+            }
+        }
+    }
+}
+
+/// Applies `policy` to the `data` of `response`, if it is an error envelope, or to the `data` of
+/// the error envelopes of a JSON-RPC 2.0 batch.
+fn filter_error_data(policy: &WsErrorData, response: String) -> String {
+    let Ok(mut envelope) = serde_json::from_str::<serde_json::Value>(&response) else {
+        return response;
+    };
+    let filtered = match &mut envelope {
+        serde_json::Value::Array(envelopes) => {
+            envelopes.iter_mut().fold(false, |filtered, envelope| {
+                filter_envelope_error_data(policy, envelope) || filtered
+            })
+        }
+        envelope => filter_envelope_error_data(policy, envelope),
+    };
+    if !filtered {
+        return response;
+    }
+    serde_json::to_string(&envelope).unwrap_or(response)
+}
+
+/// Applies `policy` to the `data` of `envelope`, returning whether it has one.
+fn filter_envelope_error_data(policy: &WsErrorData, envelope: &mut serde_json::Value) -> bool {
+    let Some(error) = envelope
+        .get_mut("error")
+        .and_then(serde_json::Value::as_object_mut)
+    else {
+        return false;
+    };
+    let Some(data) = error.remove("data") else {
+        return false;
+    };
+    if let Some(data) = policy.filter(data) {
+        error.insert("data".to_string(), data);
+    }
+    true
+}
+
+/// Adds `"jsonrpc": "2.0"` to an envelope, or to the envelopes of a batch.
+fn stamp_jsonrpc(text: String) -> String {
+    let Ok(mut envelope) = serde_json::from_str::<serde_json::Value>(&text) else {
+        return text;
+    };
+    ws_jsonrpc::stamp(&mut envelope);
+    serde_json::to_string(&envelope).unwrap_or(text)
+}
+
+/// Sleeps until `deadline`, or forever if there is none.
+async fn sleep_until(deadline: Option<Instant>) {
+    match deadline {
+        Some(deadline) => tokio::time::sleep_until(deadline).await,
+        None => std::future::pending().await,
+    }
+}
+
+/// Decodes the requests of `frame`, `None` if it holds none and is not to be answered.
+fn decode_request<F>(frame: WsFrame, connection: &WsConnection<F>) -> Option<WsIncoming> {
+    if connection.jsonrpc {
+        return Some(decode_jsonrpc(frame));
+    }
+    let frame = match connection.deflate {
+        Some(_) => WsDeflate::decompress(frame),
+        None => Ok(frame),
+    };
+    match frame.and_then(|frame| connection.codec.decode(&frame)) {
+        Ok(req) => Some(WsIncoming::Request(req)),
+        Err(e) => {
+            // Cannot parse envelope; no id to respond to
+            tracing::warn!(error = %e, "Invalid WS envelope");
+            None
+        }
+    }
+}
+
+/// Decodes the requests of a frame of a JSON-RPC 2.0 connection.
+fn decode_jsonrpc(frame: WsFrame) -> WsIncoming {
+    let request = |request: Result<JsonRpcRequest, JsonRpcInvalid>| match request {
+        Ok(JsonRpcRequest { id, method, params }) => Ok(WsEnvelopeReq {
+            notification: id.is_none(),
+            id: id.unwrap_or_default(),
+            method,
+            params,
+        }),
+        Err(JsonRpcInvalid { id, error }) => {
+            tracing::debug!(%error, "Invalid JSON-RPC envelope");
+            Err(serde_json::to_string(&WsEnvelopeErr { id: &id, error }).unwrap())
+        }
+    };
+    let message = match &frame {
+        WsFrame::Text(text) => ws_jsonrpc::parse(text.as_bytes()),
+        WsFrame::Binary(bytes) => ws_jsonrpc::parse(bytes),
+    };
+    match message {
+        JsonRpcMessage::Single(single) => match request(single) {
+            Ok(req) => WsIncoming::Request(req),
+            Err(response) => WsIncoming::Invalid(response),
+        },
+        JsonRpcMessage::Batch(batch) => WsIncoming::Batch(batch.into_iter().map(request).collect()),
+    }
+}
+
+/// Handles the requests of a frame, returning the response to send, if any.
+async fn handle_ws_incoming<F: ServedFacilitator>(
+    incoming: WsIncoming,
+    connection: &WsConnection<F>,
+) -> Option<String> {
+    match incoming {
+        WsIncoming::Request(req) => handle_ws_envelope(&req, connection).await,
+        WsIncoming::Batch(batch) => {
+            let responses = join_all(batch.iter().map(|entry| async move {
+                match entry {
+                    Ok(req) => handle_ws_envelope(req, connection).await,
+                    Err(response) => Some(response.clone()),
+                }
+            }))
+            .await;
+            let responses = responses.into_iter().flatten().collect::<Vec<_>>();
+            // A batch of notifications is not answered.
+            (!responses.is_empty()).then(|| format!("[{}]", responses.join(",")))
+        }
+        WsIncoming::Invalid(response) => Some(response),
+    }
+}
+
+/// Handles a request, returning its response unless it is a notification.
+async fn handle_ws_envelope<F: ServedFacilitator>(
+    req: &WsEnvelopeReq,
+    connection: &WsConnection<F>,
+) -> Option<String> {
+    let span = tracing::info_span!(
+        "ws_request",
+        connection_id = %connection.id,
+        envelope_id = %req.id,
+        method = %req.method,
+    );
+    // Contain panics to the request that caused them.
+    let handled = AssertUnwindSafe(handle_ws_request(req, connection).instrument(span.clone()))
+        .catch_unwind()
+        .await;
+    let response = match handled {
+        Ok(response) => response,
+        Err(panic) => {
+            span.in_scope(|| {
+                tracing::error!(
+                    monotonic_counter.x402_ws_panics = 1,
+                    panic = %panic_message(panic.as_ref()),
+                    "WS request handler panicked"
+                );
+            });
+            Some(
+                serde_json::to_string(&WsEnvelopeErr {
+                    id: &req.id,
+                    error: WsError::internal(),
+                })
+                .unwrap(),
+            )
+        }
+    };
+    response.filter(|_| !req.notification)
+}
+
+/// Extracts the message of a caught panic, if it is a string.
+fn panic_message(panic: &(dyn Any + Send)) -> &str {
+    panic
+        .downcast_ref::<&str>()
+        .copied()
+        .or_else(|| panic.downcast_ref::<String>().map(String::as_str))
+        .unwrap_or("<non-string panic payload>")
+}
+
+async fn handle_ws_request<F: ServedFacilitator>(
+    req: &WsEnvelopeReq,
+    connection: &WsConnection<F>,
+) -> Option<String> {
+    let facilitator = &connection.facilitator;
+    let method = req.method.as_str();
+    if !matches!(method, "x402.hello" | "x402.auth") && !connection.is_authorized() {
+        return Some(unauthorized(
+            &req.id,
+            "Unauthorized: authenticate with x402.auth",
+        ));
+    }
+    if let Some(extension) = connection.missing_extension(method) {
+        let message = format!("Extension {} not negotiated", extension.name);
+        return Some(unavailable(&req.id, &message));
+    }
+    if let Some(endpoint) = rate_limited_endpoint(method) {
+        let cost = rate_limit_cost(method, &req.params);
+        match connection.check_rate_limit(endpoint, cost) {
+            Ok(Ok(())) => {}
+            Ok(Err(wait)) => return Some(rate_limited(&req.id, wait)),
+            Err(InsufficientCapacity(capacity)) => {
+                let message = format!(
+                    "{cost} items exceed the {endpoint} rate limit of {capacity} per second"
+                );
+                return Some(
+                    serde_json::to_string(&WsEnvelopeErr {
+                        id: &req.id,
+                        error: WsError::invalid_params(message),
+                    })
+                    .unwrap(),
+                );
+            }
+        }
+    }
+    match method {
+        "x402.hello" => {
+            let params = match req.params {
+                serde_json::Value::Null => Ok(WsHelloParams::default()),
+                ref params => serde_json::from_value::<WsHelloParams>(params.clone()),
+            };
+            let params = match params {
+                Ok(params) => params,
+                Err(e) => return Some(invalid_params(&req.id, e)),
+            };
+            let extensions = {
+                let mut negotiated = connection.negotiated.lock().unwrap();
+                if let Some(requested) = &params.extensions {
+                    *negotiated = negotiate(
+                        &connection.extensions,
+                        connection.codec,
+                        connection.deflate.is_some(),
+                        Some(requested),
+                    );
+                }
+                negotiated.descriptors().to_vec()
+            };
+            let result = WsHello {
+                connection_id: &connection.id,
+                version: env!("CARGO_PKG_VERSION"),
+                extensions,
+                challenge: connection
+                    .auth
+                    .as_ref()
+                    .map(|auth| auth.challenge.lock().unwrap().clone()),
+                liveness: connection.liveness.as_ref().map(WsLiveness::hello),
+            };
+            Some(
+                serde_json::to_string(&WsEnvelopeOk {
+                    id: &req.id,
+                    result,
+                })
+                .unwrap(),
+            )
+        }
+        "x402.supported" => match facilitator.supported().await {
+            Ok(mut supported) => {
+                supported.extensions = connection.extensions.descriptors().to_vec();
+                Some(
+                    serde_json::to_string(&WsEnvelopeOk {
+                        id: &req.id,
+                        result: supported,
+                    })
+                    .unwrap(),
+                )
+            }
+            Err(error) => {
+                tracing::warn!(error = ?error, "Listing supported kinds failed");
+                Some(
+                    serde_json::to_string(&WsEnvelopeErr {
+                        id: &req.id,
+                        error: WsError::internal(),
+                    })
+                    .unwrap(),
+                )
+            }
+        },
+        "x402.verify" => {
+            let parsed: Result<VerifyRequest, _> = serde_json::from_value(req.params.clone());
+            match parsed {
+                Ok(body) => match facilitator.verify(&body).await {
+                    Ok(valid_response) => Some(
+                        serde_json::to_string(&WsEnvelopeOk {
+                            id: &req.id,
+                            result: valid_response,
+                        })
+                        .unwrap(),
+                    ),
+                    Err(error) => {
+                        tracing::warn!(error = ?error, "Verification failed");
+                        Some(
+                            serde_json::to_string(&WsEnvelopeOk {
+                                id: &req.id,
+                                result: map_error_to_verify_response(error),
+                            })
+                            .unwrap(),
+                        )
+                    }
+                },
+                Err(e) => Some(invalid_params(&req.id, e)),
+            }
+        }
+        "x402.verifyBatch" => {
+            let parsed: Result<Vec<VerifyRequest>, _> = serde_json::from_value(req.params.clone());
+            match parsed {
+                Ok(body) => match verify_batch(facilitator, &body).await {
+                    Ok(responses) => Some(
+                        serde_json::to_string(&WsEnvelopeOk {
+                            id: &req.id,
+                            result: responses,
+                        })
+                        .unwrap(),
+                    ),
+                    Err(error) => Some(
+                        serde_json::to_string(&WsEnvelopeErr {
+                            id: &req.id,
+                            error: WsError::invalid_params(error),
+                        })
+                        .unwrap(),
+                    ),
+                },
+                Err(e) => Some(invalid_params(&req.id, e)),
+            }
+        }
+        "x402.settle" => {
+            let parsed: Result<SettleRequest, _> = serde_json::from_value(req.params.clone());
+            let deferred = parsed.as_ref().ok().and_then(|body| {
+                let queue = facilitator.settlement_queue()?;
+                Some((queue, body.payment_payload.id()?))
+            });
+            if let (Ok(body), Some((queue, payment_id))) = (&parsed, deferred) {
+                return Some(deferred::settle(req, connection, queue, &payment_id, body).await);
+            }
+            match parsed {
+                Ok(body) => match facilitator.settle(&body).await {
+                    Ok(settle_response) => Some(
+                        serde_json::to_string(&WsEnvelopeOk {
+                            id: &req.id,
+                            result: settle_response,
+                        })
+                        .unwrap(),
+                    ),
+                    Err(error) => {
+                        tracing::warn!(error = ?error, "Settlement failed");
+                        // Map to VerifyResponse InvalidScheme if settle failed due to protocol reasons
+                        let mapped = map_error_to_verify_response(error);
+                        let data = serde_json::to_value(&mapped).ok();
+                        Some(
+                            serde_json::to_string(&WsEnvelopeErr {
+                                id: &req.id,
+                                error: WsError::payment_failed("Settlement failed", data),
+                            })
+                            .unwrap(),
+                        )
+                    }
+                },
+                Err(e) => Some(invalid_params(&req.id, e)),
+            }
+        }
+        "x402.settleBatch" => {
+            let parsed: Result<Vec<SettleRequest>, _> = serde_json::from_value(req.params.clone());
+            match parsed {
+                Ok(body) => match settle_batch(facilitator, &body).await {
+                    Ok(items) => Some(
+                        serde_json::to_string(&WsEnvelopeOk {
+                            id: &req.id,
+                            result: items,
+                        })
+                        .unwrap(),
+                    ),
+                    Err(error) => Some(
+                        serde_json::to_string(&WsEnvelopeErr {
+                            id: &req.id,
+                            error: WsError::invalid_params(error),
+                        })
+                        .unwrap(),
+                    ),
+                },
+                Err(e) => Some(invalid_params(&req.id, e)),
+            }
+        }
+        "x402.auth" => Some(authenticate(req, connection).await),
+        "x402.settlementStatus" => Some(deferred::status(req, connection).await),
+        "x402.receipt" => Some(receipt::get(req, connection)),
+        "x402.refund" => Some(refund::handle(req, connection).await),
+        "x402.subscribe" => Some(subscribe::handle(req, connection)),
+        "x402.discovery.list" => Some(discovery::list(req, connection)),
+        "stream.init" => Some(stream::init(req, connection)),
+        "stream.require" => Some(stream::require(req, connection)),
+        "stream.require.cancel" => Some(stream::cancel_require(req, connection)),
+        "stream.pay" => Some(stream::pay(req, connection).await),
+        "stream.voucher" => Some(stream::voucher(req, connection).await),
+        "stream.finalize" => Some(stream::finalize(req, connection).await),
+        "balance.deposit" => Some(balance::deposit(req, connection).await),
+        "balance.charge" => Some(balance::charge(req, connection)),
+        "balance.get" => Some(balance::get(req, connection)),
+        #[cfg(feature = "webhooks")]
+        "x402.watchPayments" => Some(watch_payments::handle(req, connection)),
+        _ => {
+            let methods = connection.methods.as_ref();
+            if let Some(handler) = methods.and_then(|methods| methods.get(method)) {
+                let context = connection.method_context();
+                let response = match handler.call(req.params.clone(), context).await {
+                    Ok(result) => serde_json::to_string(&WsEnvelopeOk {
+                        id: &req.id,
+                        result,
+                    }),
+                    Err(error) => serde_json::to_string(&WsEnvelopeErr { id: &req.id, error }),
+                };
+                return Some(response.unwrap());
+            }
+            tracing::debug!("Unknown WS method");
+            Some(
+                serde_json::to_string(&WsEnvelopeErr {
+                    id: &req.id,
+                    error: WsError::method_not_found("Method not found"),
+                })
+                .unwrap(),
+            )
+        }
+    }
+}
+
+/// Error of a method served by a capability the facilitator does not provide,
+/// see [`FacilitatorServices`](crate::facilitator::FacilitatorServices).
+fn unavailable(id: &serde_json::Value, message: &str) -> String {
+    tracing::debug!(message, "WS method unavailable");
+    serde_json::to_string(&WsEnvelopeErr {
+        id,
+        error: WsError::method_not_found(message),
+    })
+    .unwrap()
+}
+
+/// HTTP endpoint a method mirrors, whose rate limits it shares.
+fn rate_limited_endpoint(method: &str) -> Option<Endpoint> {
+    match method {
+        "x402.verify" | "stream.pay" | "stream.voucher" => Some(Endpoint::Verify),
+        "x402.verifyBatch" => Some(Endpoint::VerifyBatch),
+        "x402.settle" | "x402.settleBatch" | "stream.finalize" | "balance.deposit" => {
+            Some(Endpoint::Settle)
+        }
+        "balance.charge" | "balance.get" => Some(Endpoint::Balance),
+        "x402.refund" => Some(Endpoint::Refund),
+        "x402.supported" => Some(Endpoint::Supported),
+        "x402.discovery.list" => Some(Endpoint::Discovery),
+        _ => None,
+    }
+}
+
+/// Rate limit tokens taken by a request: one per item of an `x402.settleBatch`, counted as as
+/// many `x402.settle` requests, and one otherwise.
+fn rate_limit_cost(method: &str, params: &serde_json::Value) -> NonZeroU32 {
+    match (method, params.as_array()) {
+        ("x402.settleBatch", Some(items)) => {
+            NonZeroU32::new(u32::try_from(items.len()).unwrap_or(u32::MAX))
+                .unwrap_or(NonZeroU32::MIN)
+        }
+        _ => NonZeroU32::MIN,
+    }
+}
+
+/// Error of a request over the rate limit, to retry after `wait`.
+fn rate_limited(id: &serde_json::Value, wait: Duration) -> String {
+    serde_json::to_string(&WsEnvelopeErr {
+        id,
+        error: WsError::rate_limited(wait),
+    })
+    .unwrap()
+}
+
+/// Error of a request the connection is not authenticated for, or of a failed authentication.
+fn unauthorized(id: &serde_json::Value, message: &str) -> String {
+    serde_json::to_string(&WsEnvelopeErr {
+        id,
+        error: WsError::unauthorized(message),
+    })
+    .unwrap()
+}
+
+/// Result of the `x402.auth` method.
+#[derive(serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+struct WsAuthenticated {
+    authenticated: bool,
+    /// Account that signed the challenge, absent for an API key.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    address: Option<alloy::primitives::Address>,
+    /// Time left before the connection must authenticate again, if the session is limited.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    expires_in_ms: Option<u64>,
+}
+
+/// `x402.auth`: authenticates the connection with an API key or a signed challenge. Signatures
+/// are recovered on the [verification pool](crate::verify_pool).
+async fn authenticate<F: ServedFacilitator>(
+    req: &WsEnvelopeReq,
+    connection: &WsConnection<F>,
+) -> String {
+    let Some(auth) = &connection.auth else {
+        return unavailable(&req.id, "Authentication is not enabled");
+    };
+    let params: WsAuthParams = match serde_json::from_value(req.params.clone()) {
+        Ok(params) => params,
+        Err(e) => return invalid_params(&req.id, e),
+    };
+    let challenge = auth.challenge.lock().unwrap().clone();
+    let session = {
+        let (verifier, challenge) = (auth.auth.clone(), challenge.clone());
+        verify_pool::spawn(move || verifier.authenticate(&params, &challenge)).await
+    };
+    let Some(session) = session else {
+        tracing::warn!("Rejected WS authentication");
+        return unauthorized(&req.id, "Unauthorized: invalid credentials");
+    };
+    if session.address.is_some() {
+        let mut current = auth.challenge.lock().unwrap();
+        if *current != challenge {
+            // Used by a concurrent authentication
+            return unauthorized(&req.id, "Unauthorized: invalid credentials");
+        }
+        *current = ws_auth::challenge(&connection.id);
+    }
+    tracing::info!(address = ?session.address, "WS connection authenticated");
+    let result = WsAuthenticated {
+        authenticated: true,
+        address: session.address,
+        expires_in_ms: session.expires_at.map(|expires_at| {
+            expires_at
+                .saturating_duration_since(Instant::now())
+                .as_millis() as u64
+        }),
+    };
+    *auth.state.lock().unwrap() = WsAuthState::Authenticated(session);
+    serde_json::to_string(&WsEnvelopeOk {
+        id: &req.id,
+        result,
+    })
+    .unwrap()
+}
+
+/// `x402.error` notification reporting a connection-level problem.
+fn connection_error(error: ConnectionError) -> String {
+    tracing::debug!(kind = ?error.kind, message = %error.message, "WS connection error");
+    serde_json::to_string(&WsNotification {
+        method: ConnectionError::METHOD,
+        params: error,
+    })
+    .unwrap()
+}
+
+fn invalid_params(id: &serde_json::Value, error: serde_json::Error) -> String {
+    tracing::debug!(error = %error, "Invalid WS params");
+    serde_json::to_string(&WsEnvelopeErr {
+        id,
+        error: WsError::invalid_params(error),
+    })
+    .unwrap()
+}
+
+/// Deferred settlement: `x402.settle` in deferred mode, and `x402.settlementStatus`.
+mod deferred {
+    use super::{
+        WsConnection, WsEnvelopeErr, WsEnvelopeOk, WsEnvelopeReq, WsError, invalid_params,
+        unavailable,
+    };
+    use crate::handlers::{ServedFacilitator, map_error_to_verify_response};
+    use crate::settlement_queue::SettlementQueue;
+    use crate::types::{SettleRequest, VerifyResponse};
+    use crate::ws_error::WsErrorCode;
+
+    #[derive(Debug, serde::Deserialize)]
+    #[serde(rename_all = "camelCase")]
+    struct StatusParams {
+        /// [`PaymentPayload::id`](crate::types::PaymentPayload::id) of the queued payment.
+        payment_id: String,
+    }
+
+    /// Verifies the payment, and queues its settlement. Invalid payments are answered like
+    /// failed settlements, and never queued.
+    pub(super) async fn settle<F: ServedFacilitator>(
+        req: &WsEnvelopeReq,
+        connection: &WsConnection<F>,
+        queue: &SettlementQueue,
+        payment_id: &str,
+        body: &SettleRequest,
+    ) -> String {
+        let verification = match connection.facilitator.verify(body).await {
+            Ok(verification) => verification,
+            Err(error) => {
+                tracing::warn!(error = ?error, "Verification before queuing failed");
+                map_error_to_verify_response(error)
+            }
+        };
+        if let VerifyResponse::Invalid { .. } = verification {
+            return serde_json::to_string(&WsEnvelopeErr {
+                id: &req.id,
+                error: WsError::payment_failed("Settlement failed", serde_json::to_value(&verification).ok()),
+            })
+            .unwrap();
+        }
+        match queue.enqueue(payment_id, body).await {
+            Ok(settlement) => serde_json::to_string(&WsEnvelopeOk {
+                id: &req.id,
+                result: settlement,
+            })
+            .unwrap(),
+            Err(error) => {
+                tracing::warn!(%error, payment_id, "Queuing the settlement failed");
+                internal_error(req)
+            }
+        }
+    }
+
+    pub(super) async fn status<F: ServedFacilitator>(
+        req: &WsEnvelopeReq,
+        connection: &WsConnection<F>,
+    ) -> String {
+        let Some(queue) = connection.facilitator.settlement_queue() else {
+            return unavailable(&req.id, "Deferred settlement is not enabled");
+        };
+        let params: StatusParams = match serde_json::from_value(req.params.clone()) {
+            Ok(params) => params,
+            Err(e) => return invalid_params(&req.id, e),
+        };
+        match queue.status(&params.payment_id).await {
+            Ok(Some(settlement)) => serde_json::to_string(&WsEnvelopeOk {
+                id: &req.id,
+                result: settlement,
+            })
+            .unwrap(),
+            Ok(None) => serde_json::to_string(&WsEnvelopeErr {
+                id: &req.id,
+                error: WsError::new(WsErrorCode::INVALID_PARAMS, "Unknown payment id"),
+            })
+            .unwrap(),
+            Err(error) => {
+                tracing::warn!(%error, "Reading the settlement queue failed");
+                internal_error(req)
+            }
+        }
+    }
+
+    fn internal_error(req: &WsEnvelopeReq) -> String {
+        serde_json::to_string(&WsEnvelopeErr {
+            id: &req.id,
+            error: WsError::internal(),
+        })
+        .unwrap()
+    }
+}
+
+/// `x402.receipt`: receipt of a settlement, signed by the facilitator.
+mod receipt {
+    use super::{
+        WsConnection, WsEnvelopeErr, WsEnvelopeOk, WsEnvelopeReq, WsError, invalid_params,
+        unavailable,
+    };
+    use crate::handlers::ServedFacilitator;
+    use crate::types::TransactionHash;
+    use crate::ws_error::WsErrorCode;
+
+    #[derive(Debug, serde::Deserialize)]
+    struct ReceiptParams {
+        /// Hash of the settlement transaction.
+        transaction: TransactionHash,
+    }
+
+    pub(super) fn get<F: ServedFacilitator>(
+        req: &WsEnvelopeReq,
+        connection: &WsConnection<F>,
+    ) -> String {
+        let Some(receipts) = connection.facilitator.receipts() else {
+            return unavailable(&req.id, "Receipts are not enabled");
+        };
+        let params: ReceiptParams = match serde_json::from_value(req.params.clone()) {
+            Ok(params) => params,
+            Err(e) => return invalid_params(&req.id, e),
+        };
+        match receipts.get(&params.transaction) {
+            Some(receipt) => serde_json::to_string(&WsEnvelopeOk {
+                id: &req.id,
+                result: receipt,
+            })
+            .unwrap(),
+            None => serde_json::to_string(&WsEnvelopeErr {
+                id: &req.id,
+                error: WsError::new(WsErrorCode::INVALID_PARAMS, "Unknown settlement"),
+            })
+            .unwrap(),
+        }
+    }
+}
+
+/// `x402.refund`: refunds part or all of a settled payment, see [`crate::refunds`].
+mod refund {
+    use super::{
+        WsConnection, WsEnvelopeErr, WsEnvelopeOk, WsEnvelopeReq, WsError, invalid_params,
+        unavailable,
+    };
+    use crate::handlers::ServedFacilitator;
+    use crate::refunds::{RefundError, RefundRequest};
+
+    /// Refunds of unknown settlements, or not paying their payer back, are invalid params;
+    /// refunds not signed by the payee are unauthorized; refunds refused are `1001`.
+    fn refund_error(id: &serde_json::Value, e: RefundError) -> String {
+        tracing::debug!(error = %e, "Refund rejected");
+        let error = match e {
+            RefundError::UnknownSettlement(_)
+            | RefundError::InvalidReceipt(_)
+            | RefundError::UnsupportedScheme
+            | RefundError::Mismatch(_) => WsError::invalid_params(&e),
+            RefundError::NotPayee => WsError::unauthorized(e.to_string()),
+            RefundError::Exceeded { .. }
+            | RefundError::InProgress(_)
+            | RefundError::Rejected(_) => WsError::payment_failed(e.to_string(), None),
+            RefundError::UnsupportedUrl(_) | RefundError::Backend(_) => {
+                tracing::error!(error = %e, "Refund ledger failed");
+                WsError::internal()
+            }
+        };
+        serde_json::to_string(&WsEnvelopeErr { id, error }).unwrap()
+    }
+
+    pub(super) async fn handle<F: ServedFacilitator>(
+        req: &WsEnvelopeReq,
+        connection: &WsConnection<F>,
+    ) -> String {
+        let Some(refunds) = connection.facilitator.refunds() else {
+            return unavailable(&req.id, "Refunds are not enabled");
+        };
+        let params: RefundRequest = match serde_json::from_value(req.params.clone()) {
+            Ok(params) => params,
+            Err(e) => return invalid_params(&req.id, e),
+        };
+        match refunds.refund(&connection.facilitator, params).await {
+            Ok(refund) => {
+                tracing::info!(
+                    transaction = %refund.refund.transaction,
+                    amount = %refund.refund.amount,
+                    "Payment refunded"
+                );
+                serde_json::to_string(&WsEnvelopeOk {
+                    id: &req.id,
+                    result: refund,
+                })
+                .unwrap()
+            }
+            Err(e) => refund_error(&req.id, e),
+        }
+    }
+}
+
+/// `x402.discovery.list`: lists the payable resources registered with the facilitator.
+mod discovery {
+    use super::{WsConnection, WsEnvelopeOk, WsEnvelopeReq, invalid_params, unavailable};
+    use crate::discovery::DiscoveryQuery;
+    use crate::handlers::ServedFacilitator;
+
+    pub(super) fn list<F: ServedFacilitator>(
+        req: &WsEnvelopeReq,
+        connection: &WsConnection<F>,
+    ) -> String {
+        let Some(discovery) = connection.facilitator.discovery() else {
+            return unavailable(&req.id, "Resource discovery is not available");
+        };
+        // Listing everything needs no params
+        let query: DiscoveryQuery = if req.params.is_null() {
+            DiscoveryQuery::default()
+        } else {
+            match serde_json::from_value(req.params.clone()) {
+                Ok(query) => query,
+                Err(e) => return invalid_params(&req.id, e),
+            }
+        };
+        serde_json::to_string(&WsEnvelopeOk {
+            id: &req.id,
+            result: discovery.list(&query),
+        })
+        .unwrap()
+    }
+}
+
+/// `balance.deposit`, `balance.charge` and `balance.get`: prepaid balances of repeat buyers, see
+/// [`crate::balance`].
+mod balance {
+    use super::{
+        WsConnection, WsEnvelopeErr, WsEnvelopeOk, WsEnvelopeReq, WsError, invalid_params,
+        unavailable,
+    };
+    use crate::balance::{BalanceError, BalanceLedger, Charge};
+    use crate::handlers::ServedFacilitator;
+    use crate::types::{PaymentPayload, PaymentRequirements, VerifyRequest, X402Version};
+
+    #[derive(Debug, serde::Deserialize)]
+    #[serde(rename_all = "camelCase")]
+    struct DepositParams {
+        payment_payload: PaymentPayload,
+        payment_requirements: PaymentRequirements,
+        /// Balance to top up, a new balance being opened if omitted.
+        #[serde(default)]
+        token: Option<String>,
+    }
+
+    #[derive(Debug, serde::Deserialize)]
+    #[serde(rename_all = "camelCase")]
+    struct ChargeParams {
+        token: String,
+        /// Prices of the request, the first of the network, asset and recipient of the balance
+        /// being charged.
+        charges: Vec<Charge>,
+    }
+
+    #[derive(Debug, serde::Deserialize)]
+    #[serde(rename_all = "camelCase")]
+    struct GetParams {
+        token: String,
+    }
+
+    /// Unknown balances and deposits of other schemes are invalid params; charges and deposits
+    /// refused are `1001`. The data of either carries the reason, see [`BalanceError::data`].
+    fn balance_error(id: &serde_json::Value, e: BalanceError) -> String {
+        tracing::debug!(error = %e, "Balance request rejected");
+        let error = match e {
+            BalanceError::UnknownBalance | BalanceError::UnsupportedScheme => {
+                WsError::invalid_params(&e)
+            }
+            _ => WsError::payment_failed(e.to_string(), None),
+        };
+        serde_json::to_string(&WsEnvelopeErr {
+            id,
+            error: error.with_data(e.data()),
+        })
+        .unwrap()
+    }
+
+    fn ledger<'a, F: ServedFacilitator>(
+        req: &WsEnvelopeReq,
+        connection: &'a WsConnection<F>,
+    ) -> Result<&'a BalanceLedger, String> {
+        connection
+            .facilitator
+            .balances()
+            .ok_or_else(|| unavailable(&req.id, "Prepaid balances are not available"))
+    }
+
+    fn reply<T: serde::Serialize>(req: &WsEnvelopeReq, result: Result<T, BalanceError>) -> String {
+        match result {
+            Ok(result) => serde_json::to_string(&WsEnvelopeOk {
+                id: &req.id,
+                result,
+            })
+            .unwrap(),
+            Err(e) => balance_error(&req.id, e),
+        }
+    }
+
+    pub(super) async fn deposit<F: ServedFacilitator>(
+        req: &WsEnvelopeReq,
+        connection: &WsConnection<F>,
+    ) -> String {
+        let ledger = match ledger(req, connection) {
+            Ok(ledger) => ledger,
+            Err(e) => return e,
+        };
+        let params: DepositParams = match serde_json::from_value(req.params.clone()) {
+            Ok(params) => params,
+            Err(e) => return invalid_params(&req.id, e),
+        };
+        let request = VerifyRequest {
+            x402_version: X402Version::V1,
+            payment_payload: params.payment_payload,
+            payment_requirements: params.payment_requirements,
+            metadata: None,
+            idempotency_key: None,
+        };
+        let deposit = ledger
+            .deposit(&connection.facilitator, &request, params.token.as_deref())
+            .await;
+        if let Ok(deposit) = &deposit {
+            tracing::info!(
+                payer = %deposit.account.payer,
+                balance = %deposit.account.balance,
+                "Prepaid balance credited"
+            );
+        }
+        reply(req, deposit)
+    }
+
+    pub(super) fn charge<F: ServedFacilitator>(
+        req: &WsEnvelopeReq,
+        connection: &WsConnection<F>,
+    ) -> String {
+        let ledger = match ledger(req, connection) {
+            Ok(ledger) => ledger,
+            Err(e) => return e,
+        };
+        let params: ChargeParams = match serde_json::from_value(req.params.clone()) {
+            Ok(params) => params,
+            Err(e) => return invalid_params(&req.id, e),
+        };
+        reply(req, ledger.charge(&params.token, &params.charges))
+    }
+
+    pub(super) fn get<F: ServedFacilitator>(
+        req: &WsEnvelopeReq,
+        connection: &WsConnection<F>,
+    ) -> String {
+        let ledger = match ledger(req, connection) {
+            Ok(ledger) => ledger,
+            Err(e) => return e,
+        };
+        let params: GetParams = match serde_json::from_value(req.params.clone()) {
+            Ok(params) => params,
+            Err(e) => return invalid_params(&req.id, e),
+        };
+        reply(
+            req,
+            ledger.get(&params.token).ok_or(BalanceError::UnknownBalance),
+        )
+    }
+}
+
+/// `x402.subscribe`: follows the settlement of a payment.
+mod subscribe {
+    use tokio::sync::broadcast::error::RecvError;
+
+    use super::{
+        WsConnection, WsEnvelopeOk, WsEnvelopeReq, WsNotification, connection_error,
+        invalid_params, unavailable,
+    };
+    use crate::handlers::ServedFacilitator;
+    use crate::types::{ConnectionError, ConnectionErrorKind};
+
+    #[derive(Debug, serde::Deserialize)]
+    #[serde(rename_all = "camelCase")]
+    struct SubscribeParams {
+        /// [`PaymentPayload::id`](crate::types::PaymentPayload::id) of the payment to follow.
+        payment_id: String,
+    }
+
+    pub(super) fn handle<F: ServedFacilitator>(
+        req: &WsEnvelopeReq,
+        connection: &WsConnection<F>,
+    ) -> String {
+        let Some(settlement_events) = connection.facilitator.settlement_events() else {
+            return unavailable(&req.id, "Settlement events are not available");
+        };
+        let params: SubscribeParams = match serde_json::from_value(req.params.clone()) {
+            Ok(params) => params,
+            Err(e) => return invalid_params(&req.id, e),
+        };
+        let mut events = settlement_events.subscribe();
+        let notifications = connection.outgoing.clone();
+        let payment_id = params.payment_id.clone();
+        connection.subscribe(async move {
+            loop {
+                match events.recv().await {
+                    Ok(event) if event.payment_id == payment_id => {
+                        let terminal = event.status.is_terminal();
+                        let notification = WsNotification {
+                            method: "x402.settlement",
+                            params: event,
+                        };
+                        let text = serde_json::to_string(&notification).unwrap();
+                        if notifications.send(text).is_err() || terminal {
+                            break;
+                        }
+                    }
+                    Ok(_) => {}
+                    Err(RecvError::Lagged(skipped)) => {
+                        tracing::warn!(skipped, "Settlement subscriber lagging, events dropped");
+                        let error = ConnectionError::new(
+                            ConnectionErrorKind::NotificationsDropped,
+                            format!("{skipped} settlement events dropped"),
+                        );
+                        let _ = notifications.send(connection_error(error));
+                    }
+                    Err(RecvError::Closed) => break,
+                }
+            }
+        });
+        tracing::info!(payment_id = %params.payment_id, "Subscribed to settlement events");
+        serde_json::to_string(&WsEnvelopeOk {
+            id: &req.id,
+            result: serde_json::json!({ "paymentId": params.payment_id }),
+        })
+        .unwrap()
+    }
+}
+
+/// `x402.watchPayments`: subscribes the connection to inbound payments.
+#[cfg(feature = "webhooks")]
+mod watch_payments {
+    use tokio::sync::broadcast::error::RecvError;
+
+    use super::{
+        WsConnection, WsEnvelopeErr, WsEnvelopeOk, WsEnvelopeReq, WsError, WsNotification,
+        connection_error,
+    };
+    use crate::handlers::ServedFacilitator;
+    use crate::types::{ConnectionError, ConnectionErrorKind, EvmAddress};
+
+    #[derive(Debug, serde::Deserialize)]
+    #[serde(rename_all = "camelCase")]
+    struct WatchPaymentsParams {
+        /// Recipients to be notified about; all watched addresses if empty.
+        #[serde(default)]
+        pay_to: Vec<EvmAddress>,
+    }
+
+    pub(super) fn handle<F: ServedFacilitator>(
+        req: &WsEnvelopeReq,
+        connection: &WsConnection<F>,
+    ) -> String {
+        let error = |error: WsError| {
+            serde_json::to_string(&WsEnvelopeErr { id: &req.id, error }).unwrap()
+        };
+        let Some(watcher) = connection.facilitator.payment_watcher() else {
+            return error(WsError::method_not_found("Payment watching is not enabled"));
+        };
+        let params: WatchPaymentsParams = if req.params.is_null() {
+            WatchPaymentsParams { pay_to: Vec::new() }
+        } else {
+            match serde_json::from_value(req.params.clone()) {
+                Ok(params) => params,
+                Err(e) => return error(WsError::invalid_params(e)),
+            }
+        };
+        if let Some(unwatched) = params
+            .pay_to
+            .iter()
+            .find(|address| !watcher.addresses().contains(address))
+        {
+            return error(WsError::invalid_params(format!(
+                "{} is not watched",
+                unwatched
+            )));
+        }
+        let pay_to = if params.pay_to.is_empty() {
+            watcher.addresses().to_vec()
+        } else {
+            params.pay_to
+        };
+
+        let mut payments = watcher.subscribe();
+        let notifications = connection.outgoing.clone();
+        let filter = pay_to.clone();
+        connection.subscribe(async move {
+            loop {
+                match payments.recv().await {
+                    Ok(transfer) if filter.contains(&transfer.to) => {
+                        let notification = WsNotification {
+                            method: "x402.paymentReceived",
+                            params: transfer,
+                        };
+                        let text = serde_json::to_string(&notification).unwrap();
+                        if notifications.send(text).is_err() {
+                            break;
+                        }
+                    }
+                    Ok(_) => {}
+                    Err(RecvError::Lagged(skipped)) => {
+                        tracing::warn!(
+                            skipped,
+                            "Payment subscriber lagging, notifications dropped"
+                        );
+                        let error = ConnectionError::new(
+                            ConnectionErrorKind::NotificationsDropped,
+                            format!("{skipped} payment notifications dropped"),
+                        );
+                        let _ = notifications.send(connection_error(error));
+                    }
+                    Err(RecvError::Closed) => break,
+                }
+            }
+        });
+        tracing::info!(addresses = pay_to.len(), "Subscribed to inbound payments");
+        serde_json::to_string(&WsEnvelopeOk {
+            id: &req.id,
+            result: serde_json::json!({ "watching": pay_to }),
+        })
+        .unwrap()
+    }
+}
+
+/// `stream.init`, `stream.require`, `stream.require.cancel`, `stream.pay`, `stream.voucher` and
+/// `stream.finalize`: sessions of pay-per-slice streams, see [`crate::stream`].
+mod stream {
+    use super::{
+        WsConnection, WsEnvelopeErr, WsEnvelopeOk, WsEnvelopeReq, WsError, invalid_params,
+        unavailable,
+    };
+    use crate::channel::{ChannelConfig, SignedVoucher, voucher_domain};
+    use crate::duration::Seconds;
+    use crate::handlers::{ServedFacilitator, map_error_to_verify_response};
+    use crate::stream::{SliceRequirements, StreamError, StreamSessionManager};
+    use crate::timestamp::UnixTimestampMs;
+    use crate::types::{
+        PaymentPayload, PaymentRequirements, Scheme, SettleResponse, TokenAmount, VerifyRequest,
+        VerifyResponse, X402Version,
+    };
+    use crate::ws_error::WsErrorCode;
+
+    #[derive(Debug, serde::Deserialize)]
+    #[serde(rename_all = "camelCase")]
+    struct InitParams {
+        /// Requirements of one slice, `maxAmountRequired` being the price per unit.
+        requirements: PaymentRequirements,
+        unit_seconds: Seconds,
+        /// Pays the stream through a payment channel, see [`crate::channel`].
+        #[serde(default)]
+        channel: Option<ChannelConfig>,
+    }
+
+    #[derive(Debug, serde::Deserialize)]
+    #[serde(rename_all = "camelCase")]
+    struct RequireParams {
+        stream_id: String,
+    }
+
+    #[derive(Debug, serde::Deserialize)]
+    #[serde(rename_all = "camelCase")]
+    struct CancelRequireParams {
+        stream_id: String,
+        require_id: String,
+        /// New requirements of one slice, to reprice the stream with.
+        #[serde(default)]
+        requirements: Option<PaymentRequirements>,
+    }
+
+    #[derive(Debug, serde::Deserialize)]
+    #[serde(rename_all = "camelCase")]
+    struct PayParams {
+        stream_id: String,
+        slice_index: u64,
+        /// Requirements paid, rejected if cancelled since.
+        #[serde(default)]
+        require_id: Option<String>,
+        payment_payload: PaymentPayload,
+        /// Verify the payment without settling it, e.g. to settle slices in batches. Payments of
+        /// metered streams are always only verified, and settled by `stream.finalize`.
+        #[serde(default)]
+        verify_only: bool,
+    }
+
+    #[derive(Debug, serde::Deserialize)]
+    #[serde(rename_all = "camelCase")]
+    struct VoucherParams {
+        stream_id: String,
+        slice_index: u64,
+        #[serde(default)]
+        require_id: Option<String>,
+        voucher: SignedVoucher,
+    }
+
+    #[derive(Debug, serde::Deserialize)]
+    #[serde(rename_all = "camelCase")]
+    struct FinalizeParams {
+        stream_id: String,
+        /// Amount consumed of a metered stream, in token base units.
+        #[serde(default)]
+        consumed_amount: Option<TokenAmount>,
+    }
+
+    /// Result of `stream.require.cancel`: the params of the Seller's `stream.require.cancel`, with
+    /// the requirements superseding the cancelled ones, if repriced.
+    #[derive(serde::Serialize)]
+    #[serde(rename_all = "camelCase")]
+    struct CancelRequireResult {
+        stream_id: String,
+        require_id: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        require: Option<SliceRequirements>,
+    }
+
+    /// Result of `stream.pay`, i.e. the params of the Seller's `stream.accept`.
+    #[derive(serde::Serialize)]
+    #[serde(rename_all = "camelCase")]
+    struct PayResult {
+        stream_id: String,
+        slice_index: u64,
+        verify: VerifyResponse,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        settle: Option<crate::types::SettleResponse>,
+        prepaid_until_ms: UnixTimestampMs,
+    }
+
+    /// Result of `stream.voucher`, i.e. the params of the Seller's `stream.accept`.
+    #[derive(serde::Serialize)]
+    #[serde(rename_all = "camelCase")]
+    struct VoucherResult {
+        stream_id: String,
+        slice_index: u64,
+        cumulative_amount: TokenAmount,
+        /// Settlement of the checkpoint due with the voucher, if any.
+        #[serde(skip_serializing_if = "Option::is_none")]
+        settle: Option<SettleResponse>,
+        prepaid_until_ms: UnixTimestampMs,
+    }
+
+    /// Result of `stream.finalize`.
+    #[derive(serde::Serialize)]
+    #[serde(rename_all = "camelCase")]
+    struct FinalizeResult {
+        stream_id: String,
+        /// Amount consumed of a metered stream, or settled from the last voucher of a channel.
+        #[serde(skip_serializing_if = "Option::is_none")]
+        consumed_amount: Option<TokenAmount>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        settle: Option<SettleResponse>,
+    }
+
+    fn error(id: &serde_json::Value, error: WsError) -> String {
+        serde_json::to_string(&WsEnvelopeErr { id, error }).unwrap()
+    }
+
+    fn stream_error(id: &serde_json::Value, e: StreamError) -> String {
+        tracing::debug!(error = %e, "Stream request rejected");
+        let rejection = match e.code() {
+            WsErrorCode::INVALID_PARAMS => WsError::invalid_params(&e),
+            code => WsError::new(code, e.to_string()),
+        };
+        error(id, rejection)
+    }
+
+    fn sessions<'a, F: ServedFacilitator>(
+        req: &WsEnvelopeReq,
+        connection: &'a WsConnection<F>,
+    ) -> Result<&'a StreamSessionManager, String> {
+        connection
+            .facilitator
+            .stream_sessions()
+            .ok_or_else(|| unavailable(&req.id, "Streams are not available"))
+    }
+
+    pub(super) fn init<F: ServedFacilitator>(
+        req: &WsEnvelopeReq,
+        connection: &WsConnection<F>,
+    ) -> String {
+        let streams = match sessions(req, connection) {
+            Ok(streams) => streams,
+            Err(e) => return e,
+        };
+        let params: InitParams = match serde_json::from_value(req.params.clone()) {
+            Ok(params) => params,
+            Err(e) => return invalid_params(&req.id, e),
+        };
+        let session = match streams.init(params.requirements, params.unit_seconds, params.channel)
+        {
+            Ok(session) => session,
+            Err(e) => return stream_error(&req.id, e),
+        };
+        tracing::info!(stream_id = %session.stream_id, unit_seconds = session.unit_seconds.as_secs(), "Stream session opened");
+        serde_json::to_string(&WsEnvelopeOk {
+            id: &req.id,
+            result: session,
+        })
+        .unwrap()
+    }
+
+    pub(super) fn require<F: ServedFacilitator>(
+        req: &WsEnvelopeReq,
+        connection: &WsConnection<F>,
+    ) -> String {
+        let streams = match sessions(req, connection) {
+            Ok(streams) => streams,
+            Err(e) => return e,
+        };
+        let params: RequireParams = match serde_json::from_value(req.params.clone()) {
+            Ok(params) => params,
+            Err(e) => return invalid_params(&req.id, e),
+        };
+        match streams.require(&params.stream_id) {
+            Ok(slice) => serde_json::to_string(&WsEnvelopeOk {
+                id: &req.id,
+                result: slice,
+            })
+            .unwrap(),
+            Err(e) => stream_error(&req.id, e),
+        }
+    }
+
+    pub(super) fn cancel_require<F: ServedFacilitator>(
+        req: &WsEnvelopeReq,
+        connection: &WsConnection<F>,
+    ) -> String {
+        let streams = match sessions(req, connection) {
+            Ok(streams) => streams,
+            Err(e) => return e,
+        };
+        let params: CancelRequireParams = match serde_json::from_value(req.params.clone()) {
+            Ok(params) => params,
+            Err(e) => return invalid_params(&req.id, e),
+        };
+        match streams.cancel_require(&params.stream_id, &params.require_id, params.requirements) {
+            Ok(require) => {
+                tracing::info!(stream_id = %params.stream_id, require_id = %params.require_id, repriced = require.is_some(), "Stream requirements cancelled");
+                serde_json::to_string(&WsEnvelopeOk {
+                    id: &req.id,
+                    result: CancelRequireResult {
+                        stream_id: params.stream_id,
+                        require_id: params.require_id,
+                        require,
+                    },
+                })
+                .unwrap()
+            }
+            Err(e) => stream_error(&req.id, e),
+        }
+    }
+
+    pub(super) async fn pay<F: ServedFacilitator>(
+        req: &WsEnvelopeReq,
+        connection: &WsConnection<F>,
+    ) -> String {
+        let streams = match sessions(req, connection) {
+            Ok(streams) => streams,
+            Err(e) => return e,
+        };
+        let params: PayParams = match serde_json::from_value(req.params.clone()) {
+            Ok(params) => params,
+            Err(e) => return invalid_params(&req.id, e),
+        };
+        let (stream_id, slice_index) = (params.stream_id, params.slice_index);
+        let requirements = match streams.begin_payment(&stream_id, slice_index, params.require_id.as_deref()) {
+            Ok(requirements) => requirements,
+            Err(e) => return stream_error(&req.id, e),
+        };
+        let verify_only = params.verify_only || requirements.scheme == Scheme::Upto;
+        let request = VerifyRequest {
+            x402_version: X402Version::V1,
+            payment_payload: params.payment_payload,
+            payment_requirements: requirements,
+            // Correlates the settlement with the stream slice it pays for.
+            metadata: Some(serde_json::json!({ "streamId": stream_id, "sliceIndex": slice_index })),
+            idempotency_key: None,
+        };
+        let facilitator = &connection.facilitator;
+        let verify = match facilitator.verify(&request).await {
+            Ok(verify) => verify,
+            Err(e) => map_error_to_verify_response(e),
+        };
+        if !matches!(verify, VerifyResponse::Valid { .. }) {
+            streams.abort_payment(&stream_id, slice_index);
+            tracing::info!(%stream_id, slice_index, "Stream payment rejected");
+            return error(
+                &req.id,
+                WsError::payment_failed("Payment rejected", serde_json::to_value(&verify).ok()),
+            );
+        }
+        let settle = if verify_only {
+            None
+        } else {
+            match facilitator.settle(&request).await {
+                Ok(settle) if settle.success => Some(settle),
+                Ok(settle) => {
+                    streams.abort_payment(&stream_id, slice_index);
+                    return error(
+                        &req.id,
+                        WsError::payment_failed("Settlement failed", serde_json::to_value(&settle).ok()),
+                    );
+                }
+                Err(e) => {
+                    streams.abort_payment(&stream_id, slice_index);
+                    tracing::warn!(error = ?e, %stream_id, slice_index, "Stream settlement failed");
+                    let mapped = map_error_to_verify_response(e);
+                    return error(
+                        &req.id,
+                        WsError::payment_failed("Settlement failed", serde_json::to_value(&mapped).ok()),
+                    );
+                }
+            }
+        };
+        match streams.complete_payment(&stream_id, slice_index, request.payment_payload) {
+            Ok(session) => {
+                tracing::info!(%stream_id, slice_index, prepaid_until_ms = session.prepaid_until.millis_since_epoch(), "Stream slice paid");
+                serde_json::to_string(&WsEnvelopeOk {
+                    id: &req.id,
+                    result: PayResult {
+                        stream_id,
+                        slice_index,
+                        verify,
+                        settle,
+                        prepaid_until_ms: session.prepaid_until,
+                    },
+                })
+                .unwrap()
+            }
+            Err(e) => stream_error(&req.id, e),
+        }
+    }
+
+    pub(super) async fn voucher<F: ServedFacilitator>(
+        req: &WsEnvelopeReq,
+        connection: &WsConnection<F>,
+    ) -> String {
+        let streams = match sessions(req, connection) {
+            Ok(streams) => streams,
+            Err(e) => return e,
+        };
+        let params: VoucherParams = match serde_json::from_value(req.params.clone()) {
+            Ok(params) => params,
+            Err(e) => return invalid_params(&req.id, e),
+        };
+        let (stream_id, slice_index) = (params.stream_id, params.slice_index);
+        let Some(session) = streams.get(&stream_id) else {
+            return stream_error(&req.id, StreamError::UnknownStream(stream_id));
+        };
+        let domain = match voucher_domain(session.requirements.network, &session.requirements.asset)
+        {
+            Ok(domain) => domain,
+            Err(e) => return stream_error(&req.id, e.into()),
+        };
+        let voucher = params.voucher;
+        let cumulative_amount = voucher.cumulative_amount;
+        let channel_id = stream_id.clone();
+        let signer =
+            match crate::verify_pool::spawn(move || voucher.recover(&channel_id, &domain)).await {
+                Ok(signer) => signer,
+                Err(e) => return stream_error(&req.id, e.into()),
+            };
+        let (session, checkpoint) =
+            match streams.accept_voucher(
+                &stream_id,
+                slice_index,
+                params.require_id.as_deref(),
+                cumulative_amount,
+                signer,
+            ) {
+                Ok(accepted) => accepted,
+                Err(e) => return stream_error(&req.id, e),
+            };
+        // A failed checkpoint leaves the voucher paid, and is tried again with the next one
+        let settle = match checkpoint {
+            None => None,
+            Some((payment_payload, payment_requirements)) => {
+                let amount = payment_requirements
+                    .consumed_amount
+                    .unwrap_or(payment_requirements.max_amount_required);
+                let request = VerifyRequest {
+                    x402_version: X402Version::V1,
+                    payment_payload,
+                    payment_requirements,
+                    metadata: Some(serde_json::json!({
+                        "streamId": stream_id,
+                        "consumedAmount": amount,
+                    })),
+                    idempotency_key: None,
+                };
+                // The authorization was verified when paid, and kept by the session since
+                if let Some(strict_settle) = connection.facilitator.strict_settle() {
+                    strict_settle.record(&request);
+                }
+                match connection.facilitator.settle(&request).await {
+                    Ok(settle) if settle.success => {
+                        streams.complete_checkpoint(&stream_id, amount);
+                        tracing::info!(%stream_id, %amount, "Stream channel checkpoint settled");
+                        Some(settle)
+                    }
+                    Ok(settle) => {
+                        streams.abort_checkpoint(&stream_id);
+                        tracing::warn!(%stream_id, reason = ?settle.error_reason, "Stream channel checkpoint failed");
+                        None
+                    }
+                    Err(e) => {
+                        streams.abort_checkpoint(&stream_id);
+                        tracing::warn!(error = ?e, %stream_id, "Stream channel checkpoint failed");
+                        None
+                    }
+                }
+            }
+        };
+        tracing::info!(%stream_id, slice_index, %cumulative_amount, prepaid_until_ms = session.prepaid_until.millis_since_epoch(), "Stream slice paid by voucher");
+        serde_json::to_string(&WsEnvelopeOk {
+            id: &req.id,
+            result: VoucherResult {
+                stream_id,
+                slice_index,
+                cumulative_amount,
+                settle,
+                prepaid_until_ms: session.prepaid_until,
+            },
+        })
+        .unwrap()
+    }
+
+    pub(super) async fn finalize<F: ServedFacilitator>(
+        req: &WsEnvelopeReq,
+        connection: &WsConnection<F>,
+    ) -> String {
+        let streams = match sessions(req, connection) {
+            Ok(streams) => streams,
+            Err(e) => return e,
+        };
+        let params: FinalizeParams = match serde_json::from_value(req.params.clone()) {
+            Ok(params) => params,
+            Err(e) => return invalid_params(&req.id, e),
+        };
+        let stream_id = params.stream_id;
+        let settlement = match streams.begin_finalize(&stream_id, params.consumed_amount) {
+            Ok(settlement) => settlement,
+            Err(e) => return stream_error(&req.id, e),
+        };
+        let consumed_amount = settlement
+            .as_ref()
+            .and_then(|(_, requirements)| requirements.consumed_amount)
+            .or(params.consumed_amount);
+        let settle = match settlement {
+            None => None,
+            Some((payment_payload, payment_requirements)) => {
+                let request = VerifyRequest {
+                    x402_version: X402Version::V1,
+                    payment_payload,
+                    payment_requirements,
+                    metadata: Some(serde_json::json!({
+                        "streamId": stream_id,
+                        "consumedAmount": consumed_amount,
+                    })),
+                    idempotency_key: None,
+                };
+                // The authorization was verified when paid, and kept by the session since
+                if let Some(strict_settle) = connection.facilitator.strict_settle() {
+                    strict_settle.record(&request);
+                }
+                match connection.facilitator.settle(&request).await {
+                    Ok(settle) if settle.success => Some(settle),
+                    Ok(settle) => {
+                        streams.abort_finalize(&stream_id);
+                        return error(
+                            &req.id,
+                            WsError::payment_failed("Settlement failed", serde_json::to_value(&settle).ok()),
+                        );
+                    }
+                    Err(e) => {
+                        streams.abort_finalize(&stream_id);
+                        tracing::warn!(error = ?e, %stream_id, "Stream finalization failed");
+                        let mapped = map_error_to_verify_response(e);
+                        return error(
+                            &req.id,
+                            WsError::payment_failed("Settlement failed", serde_json::to_value(&mapped).ok()),
+                        );
+                    }
+                }
+            }
+        };
+        streams.complete_finalize(&stream_id);
+        tracing::info!(%stream_id, ?consumed_amount, settled = settle.is_some(), "Stream session finalized");
+        serde_json::to_string(&WsEnvelopeOk {
+            id: &req.id,
+            result: FinalizeResult {
+                stream_id,
+                consumed_amount,
+                settle,
+            },
+        })
+        .unwrap()
+    }
+}
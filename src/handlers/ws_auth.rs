@@ -0,0 +1,297 @@
+//! Per-connection authentication of the WebSocket endpoint.
+//!
+//! Disabled by default: anyone reaching the endpoint can verify and settle. With [`WsAuth`]
+//! applied to the routes, a connection must authenticate before calling any method other than
+//! `x402.hello` and `x402.auth`, in one of two ways:
+//! - a bearer token: an API key of the allowlist, sent as `Authorization: Bearer <key>` with the
+//!   handshake, or as `x402.auth` params `{ apiKey }` (browsers can not set handshake headers),
+//! - a signed challenge: `x402.hello` returns a `challenge`, which an allowlisted EVM account
+//!   signs as an EIP-191 personal message and sends as `x402.auth` params `{ address, signature }`.
+//!   A challenge is good for one authentication.
+//!
+//! A handshake carrying an unknown bearer token is rejected with `401 Unauthorized`. Requests of
+//! unauthenticated connections are answered with an `Unauthorized` error (`-32001`), and
+//! connections still unauthenticated after the authentication timeout are closed. With a session
+//! lifetime set, an `x402.error` notification of kind `authExpired` tells the peer to authenticate
+//! again once it is over.
+//!
+//! Environment (see [`WsAuth::from_env`]):
+//! - `WS_API_KEYS` – Comma-separated API keys allowed to connect
+//! - `WS_AUTH_ADDRESSES` – Comma-separated EVM addresses allowed to authenticate by signature
+//! - `WS_AUTH_TIMEOUT_SECS` – Time given to a connection to authenticate, from 1 second to 1 hour
+//!   (default `10`)
+//! - `WS_AUTH_SESSION_SECS` – Lifetime of an authentication, from 1 second to 30 days (default:
+//!   the whole connection)
+//!
+//! Durations are in seconds, or written with a unit, e.g. `5m` (see [`crate::duration`]).
+
+use alloy::primitives::{Address, Bytes, Signature};
+use axum::Extension;
+use axum::http::{HeaderMap, header};
+use std::collections::HashSet;
+use std::env;
+use std::fmt::{Debug, Formatter};
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::time::Instant;
+
+use crate::duration::{self, DurationRange};
+use crate::handlers::admin::token_matches;
+use crate::handlers::router::{Endpoint, FacilitatorRoutes, RoutesConfigError};
+
+const ENV_WS_API_KEYS: &str = "WS_API_KEYS";
+const ENV_WS_AUTH_ADDRESSES: &str = "WS_AUTH_ADDRESSES";
+const ENV_WS_AUTH_TIMEOUT_SECS: &str = "WS_AUTH_TIMEOUT_SECS";
+const ENV_WS_AUTH_SESSION_SECS: &str = "WS_AUTH_SESSION_SECS";
+
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(10);
+const TIMEOUT_RANGE: DurationRange =
+    DurationRange::new(Duration::from_secs(1), Duration::from_secs(3600));
+const SESSION_RANGE: DurationRange =
+    DurationRange::new(Duration::from_secs(1), Duration::from_secs(30 * 86_400));
+
+/// Credentials allowed on the WebSocket endpoint, see the [module documentation](self).
+#[derive(Clone)]
+pub struct WsAuth {
+    api_keys: Arc<HashSet<String>>,
+    addresses: Arc<HashSet<Address>>,
+    timeout: Duration,
+    session: Option<Duration>,
+}
+
+impl Debug for WsAuth {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        // API keys are secrets, and stay out of the logs.
+        f.debug_struct("WsAuth")
+            .field("api_keys", &self.api_keys.len())
+            .field("addresses", &self.addresses)
+            .field("timeout", &self.timeout)
+            .field("session", &self.session)
+            .finish()
+    }
+}
+
+impl Default for WsAuth {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl WsAuth {
+    /// Requires authentication, with nothing allowed yet.
+    pub fn new() -> Self {
+        Self {
+            api_keys: Arc::default(),
+            addresses: Arc::default(),
+            timeout: DEFAULT_TIMEOUT,
+            session: None,
+        }
+    }
+
+    /// Reads the allowlists from `WS_API_KEYS` and `WS_AUTH_ADDRESSES`, and the timings from
+    /// `WS_AUTH_TIMEOUT_SECS` and `WS_AUTH_SESSION_SECS`. Returns `None` if both allowlists are
+    /// empty: authentication is then disabled.
+    pub fn from_env() -> Result<Option<Self>, RoutesConfigError> {
+        let list = |name: &str| {
+            env::var(name)
+                .unwrap_or_default()
+                .split(',')
+                .map(str::trim)
+                .filter(|s| !s.is_empty())
+                .map(str::to_string)
+                .collect::<Vec<_>>()
+        };
+        let api_keys = list(ENV_WS_API_KEYS);
+        let addresses = list(ENV_WS_AUTH_ADDRESSES);
+        if api_keys.is_empty() && addresses.is_empty() {
+            return Ok(None);
+        }
+        let mut auth = Self::new();
+        for key in api_keys {
+            auth = auth.with_api_key(key);
+        }
+        for address in addresses {
+            let address = Address::from_str(&address)
+                .map_err(|_| RoutesConfigError::InvalidWsAuth(address.clone()))?;
+            auth = auth.with_address(address);
+        }
+        let secs = |name: &str, range| duration::from_env(name, duration::SECOND, range);
+        if let Some(timeout) = secs(ENV_WS_AUTH_TIMEOUT_SECS, TIMEOUT_RANGE)? {
+            auth = auth.with_timeout(timeout);
+        }
+        if let Some(session) = secs(ENV_WS_AUTH_SESSION_SECS, SESSION_RANGE)? {
+            auth = auth.with_session(session);
+        }
+        Ok(Some(auth))
+    }
+
+    /// Allows the bearer token `key`.
+    pub fn with_api_key(&self, key: impl Into<String>) -> Self {
+        let mut this = self.clone();
+        Arc::make_mut(&mut this.api_keys).insert(key.into());
+        this
+    }
+
+    /// Allows `address` to authenticate by signing the challenge of its connection.
+    pub fn with_address(&self, address: Address) -> Self {
+        let mut this = self.clone();
+        Arc::make_mut(&mut this.addresses).insert(address);
+        this
+    }
+
+    /// Closes connections not authenticated within `timeout` of opening.
+    pub fn with_timeout(&self, timeout: Duration) -> Self {
+        let mut this = self.clone();
+        this.timeout = timeout;
+        this
+    }
+
+    /// Makes authentications last `session`, after which the connection must authenticate again.
+    pub fn with_session(&self, session: Duration) -> Self {
+        let mut this = self.clone();
+        this.session = Some(session);
+        this
+    }
+
+    /// Requires authentication on the WebSocket endpoint of `routes`.
+    pub fn apply(&self, routes: &FacilitatorRoutes) -> FacilitatorRoutes {
+        routes.layer(Endpoint::Ws, Extension(self.clone()))
+    }
+
+    /// Whether `key` is one of the allowed API keys. Every allowed key is compared in constant
+    /// time with [`token_matches`], so that the time taken does not leak how close `key` is to one.
+    fn allows_api_key(&self, key: &str) -> bool {
+        self.api_keys.iter().fold(false, |allowed, expected| {
+            allowed | token_matches(key, expected)
+        })
+    }
+
+    /// Checks the bearer token of a handshake: `Ok(None)` without one, `Err(())` if not allowed.
+    pub(crate) fn authenticate_headers(
+        &self,
+        headers: &HeaderMap,
+    ) -> Result<Option<WsSession>, ()> {
+        let Some(value) = headers.get(header::AUTHORIZATION) else {
+            return Ok(None);
+        };
+        let key = value
+            .to_str()
+            .ok()
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .ok_or(())?;
+        if self.allows_api_key(key) {
+            Ok(Some(self.session(None, Some(key))))
+        } else {
+            Err(())
+        }
+    }
+
+    /// Checks the `x402.auth` params of a connection whose current challenge is `challenge`.
+    pub(crate) fn authenticate(&self, params: &WsAuthParams, challenge: &str) -> Option<WsSession> {
+        if let Some(key) = &params.api_key {
+            return self
+                .allows_api_key(key)
+                .then(|| self.session(None, Some(key)));
+        }
+        let (Some(address), Some(signature)) = (params.address, &params.signature) else {
+            return None;
+        };
+        if !self.addresses.contains(&address) {
+            return None;
+        }
+        let recovered = Signature::from_raw(signature)
+            .and_then(|signature| signature.recover_address_from_msg(challenge))
+            .ok()?;
+        (recovered == address).then(|| self.session(Some(address), None))
+    }
+
+    /// State of a new connection: authenticated by `session` if its handshake carried a valid
+    /// bearer token, pending until the authentication timeout otherwise.
+    pub(crate) fn state(&self, session: Option<WsSession>) -> WsAuthState {
+        match session {
+            Some(session) => WsAuthState::Authenticated(session),
+            None => WsAuthState::Pending {
+                until: Instant::now() + self.timeout,
+            },
+        }
+    }
+
+    fn session(&self, address: Option<Address>, api_key: Option<&str>) -> WsSession {
+        WsSession {
+            address,
+            api_key: api_key.map(str::to_string),
+            expires_at: self.session.map(|session| Instant::now() + session),
+        }
+    }
+}
+
+/// Params of `x402.auth`: either `apiKey`, or `address` and `signature`.
+#[derive(Debug, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct WsAuthParams {
+    #[serde(default)]
+    api_key: Option<String>,
+    #[serde(default)]
+    address: Option<Address>,
+    /// EIP-191 signature of the challenge returned by `x402.hello`.
+    #[serde(default)]
+    signature: Option<Bytes>,
+}
+
+/// An authentication of a connection.
+#[derive(Debug, Clone)]
+pub(crate) struct WsSession {
+    /// Account that signed the challenge, `None` for an API key.
+    pub address: Option<Address>,
+    /// API key the connection authenticated with, `None` for a signature.
+    pub api_key: Option<String>,
+    /// End of the session, `None` if it lasts as long as the connection.
+    pub expires_at: Option<Instant>,
+}
+
+impl WsSession {
+    /// Credential the requests of the session are rate limited by: its API key or its account.
+    pub fn rate_limit_key(&self) -> Option<String> {
+        self.api_key
+            .clone()
+            .or_else(|| self.address.map(|address| address.to_string()))
+    }
+
+    pub fn is_expired(&self) -> bool {
+        self.expires_at
+            .is_some_and(|expires_at| expires_at <= Instant::now())
+    }
+}
+
+/// Authentication state of a connection.
+#[derive(Debug, Clone)]
+pub(crate) enum WsAuthState {
+    /// Not authenticated; the connection is closed at `until`.
+    Pending {
+        until: Instant,
+    },
+    Authenticated(WsSession),
+}
+
+impl WsAuthState {
+    /// Next time the state must be looked at: the authentication timeout, or the session end.
+    pub fn deadline(&self) -> Option<Instant> {
+        match self {
+            WsAuthState::Pending { until } => Some(*until),
+            WsAuthState::Authenticated(session) => session.expires_at,
+        }
+    }
+
+    pub fn is_authenticated(&self) -> bool {
+        matches!(self, WsAuthState::Authenticated(session) if !session.is_expired())
+    }
+}
+
+/// Challenge for the connection `connection_id` to sign, unique to one authentication.
+pub(crate) fn challenge(connection_id: &str) -> String {
+    format!(
+        "x402 WebSocket authentication\nConnection: {connection_id}\nNonce: {}",
+        uuid::Uuid::new_v4().simple()
+    )
+}
@@ -0,0 +1,76 @@
+//! Compression of the envelopes of the WebSocket connections.
+//!
+//! The payments and requirements exchanged for every slice of a stream are hundreds of bytes of
+//! repetitive JSON, at high frequency. With [`WsCompression`] applied to the routes, the endpoint
+//! selects the `x402.deflate` and `x402.cbor.deflate` subprotocols when offered, and compresses
+//! the envelopes of at least the minimum size sent on the connections selecting them, see
+//! [`ws_codec`](crate::ws_codec). Such connections are listed the `compression` extension.
+//!
+//! Environment (see [`WsCompression::from_env`]):
+//! - `WS_COMPRESSION` – `on` or `off` (default `off`)
+//! - `WS_COMPRESSION_MIN_BYTES` – Size from which envelopes are compressed, in bytes
+//!   (default `256`)
+
+use axum::Extension;
+use std::env;
+
+use crate::handlers::router::{Endpoint, FacilitatorRoutes, RoutesConfigError};
+use crate::ws_codec::WsDeflate;
+
+const ENV_WS_COMPRESSION: &str = "WS_COMPRESSION";
+const ENV_WS_COMPRESSION_MIN_BYTES: &str = "WS_COMPRESSION_MIN_BYTES";
+
+/// Compression offered by the WebSocket endpoint, see the [module documentation](self).
+#[derive(Debug, Clone, Default)]
+pub struct WsCompression {
+    deflate: WsDeflate,
+}
+
+impl WsCompression {
+    /// Compresses the envelopes of at least 256 bytes.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Reads the compression from `WS_COMPRESSION` and `WS_COMPRESSION_MIN_BYTES`. Returns `None`
+    /// unless `WS_COMPRESSION` is `on`: envelopes are then never compressed.
+    pub fn from_env() -> Result<Option<Self>, RoutesConfigError> {
+        let var = |name: &str| env::var(name).ok().filter(|value| !value.trim().is_empty());
+        match var(ENV_WS_COMPRESSION).as_deref().map(str::trim) {
+            None | Some("off") => return Ok(None),
+            Some("on") => {}
+            Some(other) => return Err(RoutesConfigError::InvalidWsCompression(other.to_string())),
+        }
+        let mut compression = Self::new();
+        if let Some(min_bytes) = var(ENV_WS_COMPRESSION_MIN_BYTES) {
+            let min_bytes = min_bytes
+                .trim()
+                .parse()
+                .map_err(|_| RoutesConfigError::InvalidWsCompression(min_bytes.clone()))?;
+            compression = compression.with_min_bytes(min_bytes);
+        }
+        Ok(Some(compression))
+    }
+
+    /// Compresses the envelopes of at least `min_bytes` once encoded.
+    pub fn with_min_bytes(&self, min_bytes: usize) -> Self {
+        Self {
+            deflate: self.deflate.with_min_bytes(min_bytes),
+        }
+    }
+
+    /// Size from which envelopes are compressed, in bytes.
+    pub fn min_bytes(&self) -> usize {
+        self.deflate.min_bytes()
+    }
+
+    /// Applies the compression to the WebSocket endpoint of `routes`.
+    pub fn apply(&self, routes: &FacilitatorRoutes) -> FacilitatorRoutes {
+        routes.layer(Endpoint::Ws, Extension(self.clone()))
+    }
+
+    /// Compression of a connection that selected a compressing subprotocol.
+    pub(crate) fn deflate(&self) -> WsDeflate {
+        self.deflate
+    }
+}
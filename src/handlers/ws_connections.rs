@@ -0,0 +1,76 @@
+//! Registry of the open WebSocket connections, listed by the admin API at `/admin/connections`.
+//!
+//! Every connection accepted by the WebSocket endpoint is registered for its lifetime, with its
+//! id (the `connectionId` of `x402.hello`, also recorded on its traces), its negotiated
+//! subprotocol, the address of its peer and the time it was opened.
+
+use axum::Extension;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+
+use crate::handlers::router::{Endpoint, FacilitatorRoutes};
+use crate::timestamp::UnixTimestampMs;
+
+/// An open WebSocket connection.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WsConnectionInfo {
+    pub id: String,
+    /// Subprotocol negotiated in the handshake, if any.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub subprotocol: Option<String>,
+    /// Address of the peer, if known.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub peer: Option<SocketAddr>,
+    pub opened_at: UnixTimestampMs,
+}
+
+/// Open WebSocket connections, by id.
+///
+/// Clones share the same connections.
+#[derive(Debug, Clone, Default)]
+pub struct WsConnections(Arc<Mutex<HashMap<String, WsConnectionInfo>>>);
+
+impl WsConnections {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registers the WebSocket connections of `routes`, and lists them on the admin API.
+    pub fn apply(&self, routes: &FacilitatorRoutes) -> FacilitatorRoutes {
+        routes
+            .layer(Endpoint::Ws, Extension(self.clone()))
+            .layer(Endpoint::Admin, Extension(self.clone()))
+    }
+
+    /// Registers `connection` until the returned guard is dropped.
+    pub fn register(&self, connection: WsConnectionInfo) -> WsConnectionGuard {
+        let id = connection.id.clone();
+        self.0.lock().unwrap().insert(id.clone(), connection);
+        WsConnectionGuard {
+            connections: self.clone(),
+            id,
+        }
+    }
+
+    /// Open connections, oldest first.
+    pub fn list(&self) -> Vec<WsConnectionInfo> {
+        let mut connections: Vec<_> = self.0.lock().unwrap().values().cloned().collect();
+        connections.sort_by_key(|connection| connection.opened_at);
+        connections
+    }
+}
+
+/// Registration of an open connection, removed from its [`WsConnections`] on drop.
+pub struct WsConnectionGuard {
+    connections: WsConnections,
+    id: String,
+}
+
+impl Drop for WsConnectionGuard {
+    fn drop(&mut self) {
+        self.connections.0.lock().unwrap().remove(&self.id);
+    }
+}
@@ -0,0 +1,167 @@
+//! Policy on the `data` of the WebSocket error envelopes sent to untrusted connections.
+//!
+//! Error envelopes of failed payments carry details in `data`: the `VerifyResponse` or
+//! `SettleResponse` the failure maps to, or the response of an upstream facilitator as is. Sellers
+//! act on these details, but anyone reaching an endpoint without authentication gets them too, and
+//! learns about the internals of the facilitator. With [`WsErrorData`] applied to the routes, the
+//! `data` of the error envelopes sent to connections that did not authenticate (see
+//! [`WsAuth`](crate::handlers::WsAuth)) is:
+//! - `full`: sent as is,
+//! - `redact`: stripped down to the reason of the failure and retry hints ([`REDACTED_FIELDS`]),
+//! - `omit`: removed,
+//!
+//! and removed in any mode if larger than the size limit, once serialized to JSON.
+//! Authenticated connections always get the full `data`, as does every connection without
+//! [`WsErrorData`]. The `code` and `message` of errors are not affected.
+//!
+//! Environment (see [`WsErrorData::from_env`]):
+//! - `WS_ERROR_DATA` – `full`, `redact` or `omit` (default `full`)
+//! - `WS_ERROR_DATA_MAX_BYTES` – Size limit of `data`, in bytes (default: no limit)
+
+use axum::Extension;
+use serde_json::Value;
+use std::env;
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+use crate::handlers::router::{Endpoint, FacilitatorRoutes, RoutesConfigError};
+
+const ENV_WS_ERROR_DATA: &str = "WS_ERROR_DATA";
+const ENV_WS_ERROR_DATA_MAX_BYTES: &str = "WS_ERROR_DATA_MAX_BYTES";
+
+/// Fields of `data` kept by [`WsErrorDataMode::Redact`].
+pub const REDACTED_FIELDS: &[&str] = &[
+    "isValid",
+    "invalidReason",
+    "success",
+    "errorReason",
+    "reason",
+    "retryAfterMs",
+];
+
+/// What is left of the `data` of errors sent to untrusted connections.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum WsErrorDataMode {
+    #[default]
+    Full,
+    Redact,
+    Omit,
+}
+
+impl FromStr for WsErrorDataMode {
+    type Err = RoutesConfigError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "full" => Ok(WsErrorDataMode::Full),
+            "redact" => Ok(WsErrorDataMode::Redact),
+            "omit" => Ok(WsErrorDataMode::Omit),
+            _ => Err(RoutesConfigError::InvalidWsErrorData(s.to_string())),
+        }
+    }
+}
+
+impl Display for WsErrorDataMode {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let mode = match self {
+            WsErrorDataMode::Full => "full",
+            WsErrorDataMode::Redact => "redact",
+            WsErrorDataMode::Omit => "omit",
+        };
+        f.write_str(mode)
+    }
+}
+
+/// Policy on the `data` of the errors sent to untrusted connections, see the
+/// [module documentation](self).
+#[derive(Debug, Clone, Default)]
+pub struct WsErrorData {
+    mode: WsErrorDataMode,
+    max_bytes: Option<usize>,
+}
+
+impl WsErrorData {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Reads the policy from `WS_ERROR_DATA` and `WS_ERROR_DATA_MAX_BYTES`. Returns `None` if
+    /// neither is set: `data` is then sent as is.
+    pub fn from_env() -> Result<Option<Self>, RoutesConfigError> {
+        let var = |name: &str| env::var(name).ok().filter(|value| !value.trim().is_empty());
+        let mode = var(ENV_WS_ERROR_DATA);
+        let max_bytes = var(ENV_WS_ERROR_DATA_MAX_BYTES);
+        if mode.is_none() && max_bytes.is_none() {
+            return Ok(None);
+        }
+        let mut policy = Self::new();
+        if let Some(mode) = mode {
+            policy = policy.with_mode(mode.trim().parse()?);
+        }
+        if let Some(max_bytes) = max_bytes {
+            let max_bytes = max_bytes
+                .trim()
+                .parse()
+                .map_err(|_| RoutesConfigError::InvalidWsErrorData(max_bytes.clone()))?;
+            policy = policy.with_max_bytes(max_bytes);
+        }
+        Ok(Some(policy))
+    }
+
+    /// Sends the `data` of errors to untrusted connections in `mode`.
+    pub fn with_mode(&self, mode: WsErrorDataMode) -> Self {
+        Self {
+            mode,
+            ..self.clone()
+        }
+    }
+
+    /// Removes the `data` of errors sent to untrusted connections if larger than `max_bytes`.
+    pub fn with_max_bytes(&self, max_bytes: usize) -> Self {
+        Self {
+            max_bytes: Some(max_bytes),
+            ..self.clone()
+        }
+    }
+
+    /// Applies the policy to the WebSocket endpoint of `routes`.
+    pub fn apply(&self, routes: &FacilitatorRoutes) -> FacilitatorRoutes {
+        routes.layer(Endpoint::Ws, Extension(self.clone()))
+    }
+
+    /// Whether the policy may change the `data` of errors.
+    pub(crate) fn is_restrictive(&self) -> bool {
+        self.mode != WsErrorDataMode::Full || self.max_bytes.is_some()
+    }
+
+    /// What is sent to an untrusted connection of the `data` of an error, `None` to remove it.
+    pub(crate) fn filter(&self, data: Value) -> Option<Value> {
+        let data = match self.mode {
+            WsErrorDataMode::Full => data,
+            WsErrorDataMode::Redact => redact(data)?,
+            WsErrorDataMode::Omit => return None,
+        };
+        match self.max_bytes {
+            Some(max_bytes) if serde_json::to_vec(&data).ok()?.len() > max_bytes => None,
+            _ => Some(data),
+        }
+    }
+}
+
+impl Display for WsErrorData {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self.max_bytes {
+            Some(max_bytes) => write!(f, "{}, up to {max_bytes} bytes", self.mode),
+            None => write!(f, "{}", self.mode),
+        }
+    }
+}
+
+/// Keeps the [`REDACTED_FIELDS`] of an object, `None` if there are none.
+fn redact(data: Value) -> Option<Value> {
+    let Value::Object(mut object) = data else {
+        return None;
+    };
+    object.retain(|key, _| REDACTED_FIELDS.contains(&key.as_str()));
+    (!object.is_empty()).then_some(Value::Object(object))
+}
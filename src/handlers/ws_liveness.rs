@@ -0,0 +1,283 @@
+//! Liveness policy of the WebSocket connections: server-initiated pings, idle timeout and
+//! maximum lifetime.
+//!
+//! A peer that vanished without closing its socket (a crashed process, a dropped NAT mapping)
+//! leaves its connection open, with its subscriptions, until TCP gives up on it, which may take
+//! hours. With [`WsLiveness`] applied to the routes, every connection:
+//! - is sent a ping every ping interval, and closed with code `1001` and reason `ping-timeout`
+//!   if nothing is received from the peer within the pong timeout of a ping,
+//! - is closed with code `1000` and reason `idle-timeout` once it went the idle timeout without a
+//!   request received, an envelope sent or a request in flight,
+//! - is closed with code `1001` and reason `max-lifetime` once open for the maximum lifetime: it
+//!   stops reading requests, is sent an `x402.error` notification of kind `shuttingDown`, and is
+//!   closed once its requests in flight are answered, so the peer reconnects, possibly to another
+//!   instance.
+//!
+//! Peers learn the policy as `liveness` in the result of `x402.hello`: a peer not pinged within
+//! the ping interval, plus the pong timeout, may take the connection for dead.
+//!
+//! Open connections are counted in the `x402_ws_connections` metric, and connections closed by
+//! the policy in the `x402_ws_liveness_closes` metric, by `reason`. The round-trip time of the
+//! last ping, and the reason the connection was closed for, are recorded on the `ws_connection`
+//! span as `rtt_ms` and `close_reason`.
+//!
+//! Environment (see [`WsLiveness::from_env`]):
+//! - `WS_PING_INTERVAL_SECS` – Interval between two pings, up to 1 hour, or `off` (default `30`)
+//! - `WS_PONG_TIMEOUT_SECS` – Time the peer has to answer a ping, up to 10 minutes (default `10`)
+//! - `WS_IDLE_TIMEOUT_SECS` – Idle timeout, up to 1 day (default: none)
+//! - `WS_MAX_LIFETIME_SECS` – Maximum lifetime of a connection, from 1 minute to 7 days
+//!   (default: none)
+
+use axum::Extension;
+use axum::extract::ws::Message;
+use serde::Serialize;
+use std::env;
+use std::time::Duration;
+use tokio::time::Instant;
+
+use crate::duration::{self, DurationError, DurationRange};
+use crate::handlers::router::{Endpoint, FacilitatorRoutes};
+
+const ENV_WS_PING_INTERVAL_SECS: &str = "WS_PING_INTERVAL_SECS";
+const ENV_WS_PONG_TIMEOUT_SECS: &str = "WS_PONG_TIMEOUT_SECS";
+const ENV_WS_IDLE_TIMEOUT_SECS: &str = "WS_IDLE_TIMEOUT_SECS";
+const ENV_WS_MAX_LIFETIME_SECS: &str = "WS_MAX_LIFETIME_SECS";
+
+const DEFAULT_PING_INTERVAL: Duration = Duration::from_secs(30);
+const DEFAULT_PONG_TIMEOUT: Duration = Duration::from_secs(10);
+/// Ping intervals accepted: up to 1 hour.
+pub const PING_INTERVAL_RANGE: DurationRange =
+    DurationRange::new(Duration::from_secs(1), Duration::from_secs(3600));
+/// Pong timeouts accepted: up to 10 minutes.
+pub const PONG_TIMEOUT_RANGE: DurationRange =
+    DurationRange::new(Duration::from_secs(1), Duration::from_secs(600));
+/// Idle timeouts accepted: up to 1 day.
+pub const IDLE_TIMEOUT_RANGE: DurationRange =
+    DurationRange::new(Duration::from_secs(1), Duration::from_secs(86_400));
+/// Maximum lifetimes accepted: from 1 minute to 7 days.
+pub const MAX_LIFETIME_RANGE: DurationRange =
+    DurationRange::new(Duration::from_secs(60), Duration::from_secs(7 * 86_400));
+
+/// Reason of the close frames of the connections not answering a ping in time.
+pub const PING_TIMEOUT_REASON: &str = "ping-timeout";
+/// Reason of the close frames of the idle connections.
+pub const IDLE_TIMEOUT_REASON: &str = "idle-timeout";
+/// Reason of the close frames of the connections open for the maximum lifetime.
+pub const MAX_LIFETIME_REASON: &str = "max-lifetime";
+
+/// Liveness policy of the WebSocket connections, see the [module documentation](self).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WsLiveness {
+    ping_interval: Option<Duration>,
+    pong_timeout: Duration,
+    idle_timeout: Option<Duration>,
+    max_lifetime: Option<Duration>,
+}
+
+impl Default for WsLiveness {
+    fn default() -> Self {
+        Self {
+            ping_interval: Some(DEFAULT_PING_INTERVAL),
+            pong_timeout: DEFAULT_PONG_TIMEOUT,
+            idle_timeout: None,
+            max_lifetime: None,
+        }
+    }
+}
+
+impl WsLiveness {
+    /// Pings every 30 seconds, with a pong timeout of 10 seconds, and no idle timeout nor
+    /// maximum lifetime.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Reads the policy from `WS_PING_INTERVAL_SECS`, `WS_PONG_TIMEOUT_SECS`,
+    /// `WS_IDLE_TIMEOUT_SECS` and `WS_MAX_LIFETIME_SECS`, in seconds or with a unit (e.g. `2m`).
+    pub fn from_env() -> Result<Self, DurationError> {
+        let mut liveness = Self::new();
+        let pings_off =
+            env::var(ENV_WS_PING_INTERVAL_SECS).is_ok_and(|value| value.trim() == "off");
+        if pings_off {
+            liveness = liveness.without_pings();
+        } else if let Some(interval) = duration::from_env(
+            ENV_WS_PING_INTERVAL_SECS,
+            duration::SECOND,
+            PING_INTERVAL_RANGE,
+        )? {
+            liveness = liveness.with_ping_interval(interval)?;
+        }
+        if let Some(timeout) = duration::from_env(
+            ENV_WS_PONG_TIMEOUT_SECS,
+            duration::SECOND,
+            PONG_TIMEOUT_RANGE,
+        )? {
+            liveness = liveness.with_pong_timeout(timeout)?;
+        }
+        if let Some(timeout) = duration::from_env(
+            ENV_WS_IDLE_TIMEOUT_SECS,
+            duration::SECOND,
+            IDLE_TIMEOUT_RANGE,
+        )? {
+            liveness = liveness.with_idle_timeout(timeout)?;
+        }
+        if let Some(lifetime) = duration::from_env(
+            ENV_WS_MAX_LIFETIME_SECS,
+            duration::SECOND,
+            MAX_LIFETIME_RANGE,
+        )? {
+            liveness = liveness.with_max_lifetime(lifetime)?;
+        }
+        Ok(liveness)
+    }
+
+    /// Pings every connection every `interval`.
+    pub fn with_ping_interval(&self, interval: Duration) -> Result<Self, DurationError> {
+        Ok(Self {
+            ping_interval: Some(PING_INTERVAL_RANGE.check(interval)?),
+            ..self.clone()
+        })
+    }
+
+    /// Sends no ping: dead peers are only noticed by the idle timeout, or by TCP.
+    pub fn without_pings(&self) -> Self {
+        Self {
+            ping_interval: None,
+            ..self.clone()
+        }
+    }
+
+    /// Closes the connections sending nothing within `timeout` of a ping.
+    pub fn with_pong_timeout(&self, timeout: Duration) -> Result<Self, DurationError> {
+        Ok(Self {
+            pong_timeout: PONG_TIMEOUT_RANGE.check(timeout)?,
+            ..self.clone()
+        })
+    }
+
+    /// Closes the connections idle for `timeout`.
+    pub fn with_idle_timeout(&self, timeout: Duration) -> Result<Self, DurationError> {
+        Ok(Self {
+            idle_timeout: Some(IDLE_TIMEOUT_RANGE.check(timeout)?),
+            ..self.clone()
+        })
+    }
+
+    /// Closes the connections open for `lifetime`.
+    pub fn with_max_lifetime(&self, lifetime: Duration) -> Result<Self, DurationError> {
+        Ok(Self {
+            max_lifetime: Some(MAX_LIFETIME_RANGE.check(lifetime)?),
+            ..self.clone()
+        })
+    }
+
+    /// Applies the policy to the WebSocket endpoint of `routes`.
+    pub fn apply(&self, routes: &FacilitatorRoutes) -> FacilitatorRoutes {
+        routes.layer(Endpoint::Ws, Extension(self.clone()))
+    }
+
+    /// The policy as told to peers in `x402.hello`.
+    pub(crate) fn hello(&self) -> WsLivenessHello {
+        let millis = |duration: Duration| duration.as_millis() as u64;
+        WsLivenessHello {
+            ping_interval_ms: self.ping_interval.map(millis),
+            pong_timeout_ms: self.ping_interval.map(|_| millis(self.pong_timeout)),
+            idle_timeout_ms: self.idle_timeout.map(millis),
+            max_lifetime_ms: self.max_lifetime.map(millis),
+        }
+    }
+}
+
+/// Liveness policy of a connection, in the result of `x402.hello`.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct WsLivenessHello {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    ping_interval_ms: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pong_timeout_ms: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    idle_timeout_ms: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    max_lifetime_ms: Option<u64>,
+}
+
+/// Liveness state of a connection, driven by the reader of the connection.
+pub(crate) struct Heartbeat {
+    policy: Option<WsLiveness>,
+    opened_at: Instant,
+    /// Time the next ping is due, `None` while one is unanswered, or without pings.
+    next_ping: Option<Instant>,
+    /// Time the unanswered ping was sent at.
+    ping_sent_at: Option<Instant>,
+    /// Round-trip time of the last ping answered.
+    rtt: Option<Duration>,
+}
+
+impl Heartbeat {
+    pub(crate) fn new(policy: Option<WsLiveness>) -> Self {
+        let opened_at = Instant::now();
+        let next_ping = policy
+            .as_ref()
+            .and_then(|policy| policy.ping_interval)
+            .map(|interval| opened_at + interval);
+        Self {
+            policy,
+            opened_at,
+            next_ping,
+            ping_sent_at: None,
+            rtt: None,
+        }
+    }
+
+    /// Time the next ping is due.
+    pub(crate) fn next_ping(&self) -> Option<Instant> {
+        self.next_ping
+    }
+
+    /// Time the peer must have answered the unanswered ping by.
+    pub(crate) fn pong_deadline(&self) -> Option<Instant> {
+        let policy = self.policy.as_ref()?;
+        Some(self.ping_sent_at? + policy.pong_timeout)
+    }
+
+    /// Time the connection is idle at, if nothing happens on it until then since `last_activity`.
+    pub(crate) fn idle_deadline(&self, last_activity: Instant) -> Option<Instant> {
+        Some(last_activity + self.policy.as_ref()?.idle_timeout?)
+    }
+
+    /// Time the connection reaches its maximum lifetime at.
+    pub(crate) fn lifetime_deadline(&self) -> Option<Instant> {
+        Some(self.opened_at + self.policy.as_ref()?.max_lifetime?)
+    }
+
+    /// Records a ping sent now, and returns it.
+    pub(crate) fn ping(&mut self) -> Message {
+        self.next_ping = None;
+        self.ping_sent_at = Some(Instant::now());
+        Message::Ping(Default::default())
+    }
+
+    /// Records a frame received from the peer, which proves it alive. Returns the round-trip time
+    /// of the ping it answers, if a pong.
+    pub(crate) fn received(&mut self, pong: bool) -> Option<Duration> {
+        let sent_at = self.ping_sent_at.take()?;
+        let interval = self.policy.as_ref()?.ping_interval?;
+        self.next_ping = Some(sent_at + interval);
+        if !pong {
+            return None;
+        }
+        self.rtt = Some(sent_at.elapsed());
+        self.rtt
+    }
+
+    /// Round-trip time of the last ping answered.
+    pub(crate) fn rtt(&self) -> Option<Duration> {
+        self.rtt
+    }
+
+    /// Time since the connection opened.
+    pub(crate) fn age(&self) -> Duration {
+        self.opened_at.elapsed()
+    }
+}
@@ -9,21 +9,100 @@
 //! - _Buyer_: a client that constructs and submits x402-compliant payments
 //!
 //! Modules:
+//! - [`balance`] — prepaid balances of repeat buyers, charged per request without signing (`X-Payment-Balance`).
+//! - [`canonical_json`] — canonical JSON (RFC 8785) of everything hashed or signed, matching other implementations.
+//! - [`channel`] — off-chain payment channels of streams, paid with cumulative vouchers and settled at checkpoints.
+//! - [`config_file`] — TOML configuration file, layered under the environment and reloaded on `SIGHUP`.
+//! - `chaos` — fault injection for resilience testing (only with the `chaos` feature).
+//! - [`disabled_networks`] — networks turned off at runtime, without restarting the facilitator.
+//! - [`discovery`] — registry of payable resources, listed to clients (the x402 bazaar).
+//! - [`duration`] — typed durations (`30s`, `5m`) of configuration and protocol fields, with range validation.
+//! - [`extensions`] — registry of the optional extensions of the WebSocket protocol, negotiated per connection.
 //! - [`facilitator`] — defines the [`facilitator::Facilitator`] trait used to validate and settle x402 payments.
+//! - [`facilitator_cache`] — single-flight, short-lived caching of verifications in front of a [`facilitator::Facilitator`].
 //! - [`facilitator_local`] — a concrete implementation of [`facilitator::Facilitator`].
+//! - [`facilitator_pool`] — latency-aware selection among several [`facilitator::Facilitator`]s.
+//! - `facilitator_remote` — HTTP and WebSocket clients of a remote facilitator, implementing [`facilitator::Facilitator`] (only with the `remote` feature).
+//! - [`gas_monitor`] — native token balance of the settlement accounts, polled in the background, refusing settlements below a floor.
+//! - `payment_watch` — push notifications of inbound payments (only with the `webhooks` feature).
 //! - [`network`] — enumerates supported Ethereum-compatible networks and known token deployments.
+//! - [`nonce_store`] — replay protection for payment authorizations (in memory, `sled` or `postgres`).
+//! - `replay` — recording of facilitator runs, replayed against a mock chain for regression tests (only with the `replay` feature).
 //! - [`provider_cache`] — dynamic initialization and caching of Ethereum JSON-RPC providers.
+//! - [`receipts`] — receipts of settled payments signed by the facilitator, for payers to prove payment to third parties.
+//! - [`refunds`] — refunds of settled payments signed by their payee, with a persisted ledger (in memory, `sled` or `postgres`).
+//! - [`retry`] — retries of requests turned away by an overloaded server, with jittered backoff and a circuit breaker.
+//! - [`settle_idempotency`] — idempotency keys of settlement requests, answering retried settlements with their original response.
+//! - [`scheme`] — the [`scheme::SchemeHandler`] trait for registering custom payment schemes.
+//! - [`settlement_audit`] — verification of settled payments against the chain, for third parties auditing sellers.
+//! - [`settlement_caps`] — hard caps on settled amounts, per settlement, per payer and per day.
+//! - [`settlement_events`] — settlement lifecycle events (submitted, mined, confirmed, failed).
+//! - [`settlement_pause`] — settlements paused per network at runtime, through the admin API.
+//! - [`settlement_queue`] — deferred settlement of payments by a background worker, with retries.
+//! - [`settlement_stats`] — statistics of settlements per network and asset, served as `/stats`.
+//! - [`storage`] — history of every verification and settlement (in memory, `sqlite` or `postgres`), served as `/payments`.
+//! - [`stream`] — session state of pay-per-slice streams (`stream.init`, `stream.require`, `stream.pay`).
+//! - [`strict_settle`] — refusal to settle payments not verified recently (strict verify-before-settle).
 //! - [`telemetry`] — OpenTelemetry instrumentation setup for tracing and observability.
-//! - [`types`] — all shared x402 protocol structures and payload formats.
+//! - [`tokens`] — registry of token deployments by symbol and network (USDC, EURC, PYUSD, DAI and custom tokens).
+//! - [`types`] — all shared x402 protocol structures and payload formats, with the messages of
+//!   pay-per-slice streams in [`types::stream`].
+//! - [`verify_pool`] — dedicated thread pool for the CPU-bound signature work of verification.
+//! - `webhooks` — signed notifications of payment and stream events, with retries and a dead-letter log (only with the `webhooks` feature).
+//! - [`ws_error`] — registry of the error codes of the WebSocket protocol.
 
+pub mod balance;
+pub mod canonical_json;
 pub mod chain;
+pub mod channel;
+pub mod config_file;
+#[cfg(feature = "chaos")]
+pub mod chaos;
+pub mod disabled_networks;
+pub mod discovery;
+pub mod duration;
+pub mod extensions;
 pub mod facilitator;
+pub mod facilitator_cache;
 pub mod facilitator_local;
+pub mod facilitator_pool;
+#[cfg(feature = "remote")]
+pub mod facilitator_remote;
+pub mod gas_monitor;
+pub mod lru_cache;
 pub mod network;
+pub mod nonce_store;
+pub mod payment_spans;
+#[cfg(feature = "webhooks")]
+pub mod payment_watch;
 pub mod provider_cache;
+pub mod receipts;
+pub mod refunds;
+#[cfg(feature = "replay")]
+pub mod replay;
+pub mod retry;
+pub mod scheme;
+pub mod settle_idempotency;
+pub mod settlement_audit;
+pub mod settlement_caps;
+pub mod settlement_events;
+pub mod settlement_pause;
+pub mod settlement_queue;
+pub mod settlement_stats;
+pub mod storage;
+pub mod stream;
+pub mod strict_settle;
 pub mod telemetry;
 pub mod timestamp;
+pub mod tokens;
 pub mod types;
+pub mod verify_pool;
+#[cfg(feature = "webhooks")]
+pub mod webhooks;
+pub mod ws_codec;
+pub mod ws_error;
+pub mod ws_jsonrpc;
+pub mod ws_methods;
 
 // Hidden re-exports just for macro expansion.
 #[doc(hidden)]
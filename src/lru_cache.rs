@@ -0,0 +1,251 @@
+//! Size-bounded in-memory cache shared by the facilitator components.
+//!
+//! Anything keyed by client input (token addresses, payment ids, quote ids, ...) must not grow
+//! without bound under adversarial input variety. [`LruCache`] holds at most `capacity` entries,
+//! evicting the least recently used one first, and optionally expires entries after a TTL.
+//!
+//! Every cache reports its hits, misses and evictions as the `x402_cache_hits`,
+//! `x402_cache_misses` and `x402_cache_evictions` OpenTelemetry counters, with the cache name
+//! as the `cache` attribute, and as [`CacheStats`].
+
+use opentelemetry::KeyValue;
+use opentelemetry::metrics::Counter;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::{Debug, Formatter};
+use std::hash::Hash;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+struct Entry<V> {
+    value: V,
+    inserted_at: Instant,
+    /// Position in the recency order, see [`Entries::recency`].
+    tick: u64,
+}
+
+struct Entries<K, V> {
+    map: HashMap<K, Entry<V>>,
+    /// Keys by last use, least recently used first.
+    recency: BTreeMap<u64, K>,
+    next_tick: u64,
+}
+
+impl<K: Hash + Eq + Clone, V> Entries<K, V> {
+    fn touch(&mut self, key: &K) {
+        let tick = self.next_tick;
+        if let Some(entry) = self.map.get_mut(key) {
+            self.recency.remove(&entry.tick);
+            entry.tick = tick;
+            self.recency.insert(tick, key.clone());
+            self.next_tick += 1;
+        }
+    }
+
+    fn remove(&mut self, key: &K) -> Option<V> {
+        let entry = self.map.remove(key)?;
+        self.recency.remove(&entry.tick);
+        Some(entry.value)
+    }
+}
+
+struct Metrics {
+    attributes: [KeyValue; 1],
+    hits: Counter<u64>,
+    misses: Counter<u64>,
+    evictions: Counter<u64>,
+    hit_count: AtomicU64,
+    miss_count: AtomicU64,
+    eviction_count: AtomicU64,
+}
+
+impl Metrics {
+    fn new(name: &'static str) -> Self {
+        let meter = opentelemetry::global::meter("x402-rs");
+        Self {
+            attributes: [KeyValue::new("cache", name)],
+            hits: meter.u64_counter("x402_cache_hits").build(),
+            misses: meter.u64_counter("x402_cache_misses").build(),
+            evictions: meter.u64_counter("x402_cache_evictions").build(),
+            hit_count: AtomicU64::new(0),
+            miss_count: AtomicU64::new(0),
+            eviction_count: AtomicU64::new(0),
+        }
+    }
+
+    fn hit(&self) {
+        self.hits.add(1, &self.attributes);
+        self.hit_count.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn miss(&self) {
+        self.misses.add(1, &self.attributes);
+        self.miss_count.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn evicted(&self) {
+        self.evictions.add(1, &self.attributes);
+        self.eviction_count.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// Counters of a cache since it was created.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CacheStats {
+    pub len: usize,
+    pub capacity: usize,
+    pub hits: u64,
+    pub misses: u64,
+    /// Entries dropped to make room, not counting expired ones.
+    pub evictions: u64,
+}
+
+/// Thread-safe least-recently-used cache of at most `capacity` entries.
+///
+/// Clones share the same entries.
+pub struct LruCache<K, V> {
+    name: &'static str,
+    capacity: usize,
+    ttl: Option<Duration>,
+    entries: Arc<Mutex<Entries<K, V>>>,
+    metrics: Arc<Metrics>,
+}
+
+impl<K, V> Clone for LruCache<K, V> {
+    fn clone(&self) -> Self {
+        Self {
+            name: self.name,
+            capacity: self.capacity,
+            ttl: self.ttl,
+            entries: self.entries.clone(),
+            metrics: self.metrics.clone(),
+        }
+    }
+}
+
+impl<K, V> Debug for LruCache<K, V> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("LruCache")
+            .field("name", &self.name)
+            .field("capacity", &self.capacity)
+            .field("ttl", &self.ttl)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<K: Hash + Eq + Clone, V: Clone> LruCache<K, V> {
+    /// Creates an empty cache of at most `capacity` entries (at least one), reported as `name`.
+    pub fn new(name: &'static str, capacity: usize) -> Self {
+        Self {
+            name,
+            capacity: capacity.max(1),
+            ttl: None,
+            entries: Arc::new(Mutex::new(Entries {
+                map: HashMap::new(),
+                recency: BTreeMap::new(),
+                next_tick: 0,
+            })),
+            metrics: Arc::new(Metrics::new(name)),
+        }
+    }
+
+    /// Expires entries `ttl` after they are inserted.
+    pub fn with_ttl(self, ttl: Duration) -> Self {
+        Self {
+            ttl: Some(ttl),
+            ..self
+        }
+    }
+
+    /// Name of the cache in metrics.
+    #[allow(dead_code)] // Public for consumption by downstream crates.
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    /// Time after which entries expire, if any.
+    #[allow(dead_code)] // Public for consumption by downstream crates.
+    pub fn ttl(&self) -> Option<Duration> {
+        self.ttl
+    }
+
+    /// Returns a clone of the value of `key`, unless absent or expired, and marks it as
+    /// recently used.
+    pub fn get(&self, key: &K) -> Option<V> {
+        let mut entries = self.entries.lock().unwrap();
+        let expired = match entries.map.get(key) {
+            None => {
+                self.metrics.miss();
+                return None;
+            }
+            Some(entry) => self.is_expired(entry),
+        };
+        if expired {
+            entries.remove(key);
+            self.metrics.miss();
+            return None;
+        }
+        entries.touch(key);
+        self.metrics.hit();
+        entries.map.get(key).map(|entry| entry.value.clone())
+    }
+
+    /// Inserts or replaces the value of `key`, evicting the least recently used entry if full.
+    pub fn insert(&self, key: K, value: V) {
+        let mut entries = self.entries.lock().unwrap();
+        entries.remove(&key);
+        while entries.map.len() >= self.capacity {
+            let Some((_, oldest)) = entries.recency.pop_first() else {
+                break;
+            };
+            let expired = entries
+                .map
+                .get(&oldest)
+                .is_some_and(|entry| self.is_expired(entry));
+            entries.map.remove(&oldest);
+            if !expired {
+                self.metrics.evicted();
+            }
+        }
+        let tick = entries.next_tick;
+        entries.next_tick += 1;
+        entries.recency.insert(tick, key.clone());
+        entries.map.insert(
+            key,
+            Entry {
+                value,
+                inserted_at: Instant::now(),
+                tick,
+            },
+        );
+    }
+
+    /// Removes `key`, returning its value if present and not expired.
+    #[allow(dead_code)] // Public for consumption by downstream crates.
+    pub fn remove(&self, key: &K) -> Option<V> {
+        let mut entries = self.entries.lock().unwrap();
+        let entry = entries.map.get(key)?;
+        let expired = self.is_expired(entry);
+        let value = entries.remove(key);
+        if expired { None } else { value }
+    }
+
+    /// Counters of the cache.
+    #[allow(dead_code)] // Public for consumption by downstream crates.
+    pub fn stats(&self) -> CacheStats {
+        CacheStats {
+            len: self.entries.lock().unwrap().map.len(),
+            capacity: self.capacity,
+            hits: self.metrics.hit_count.load(Ordering::Relaxed),
+            misses: self.metrics.miss_count.load(Ordering::Relaxed),
+            evictions: self.metrics.eviction_count.load(Ordering::Relaxed),
+        }
+    }
+
+    fn is_expired(&self, entry: &Entry<V>) -> bool {
+        self.ttl
+            .is_some_and(|ttl| entry.inserted_at.elapsed() > ttl)
+    }
+}
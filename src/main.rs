@@ -3,12 +3,23 @@
 //! This binary launches an Axum-based HTTP server that exposes the x402 protocol interface
 //! for payment verification and settlement via Ethereum-compatible networks.
 //!
+//! The server is the default command of the `x402-facilitator` binary, which also verifies or
+//! settles a single request, lists the supported payment kinds, manages settlement keys and
+//! checks the configuration without serving (see [`cli`]).
+//!
 //! Endpoints:
 //! - `GET /verify` – Supported verification schema
 //! - `POST /verify` – Verify a payment payload against requirements
+//! - `POST /verify/batch` – Verify an array of payment payloads concurrently
 //! - `GET /settle` – Supported settlement schema
 //! - `POST /settle` – Settle an accepted payment payload on-chain
 //! - `GET /supported` – List supported payment kinds (version/scheme/network)
+//! - `GET /discovery/resources` – List payable resources registered with `POST /discovery/resources`
+//! - `GET /healthz`, `GET /readyz` – Liveness and readiness probes, checking RPC endpoints, gas balances, storage and the
+//!   settlement queue
+//! - `GET /ws` – WebSocket endpoint mirroring the HTTP API
+//! - `/admin/*` – Operator API, enabled by setting `ADMIN_TOKEN`: connections and streams, settlement pause per network,
+//!   settlement queue drain, signer rotation, runtime log filter and rate limits
 //!
 //! This server includes:
 //! - OpenTelemetry tracing via `TraceLayer`
@@ -17,69 +28,331 @@
 //!
 //! Environment:
 //! - `.env` values loaded at startup
+//! - `CONFIG_FILE` names a TOML file of settings, overridden by the environment and `.env`, whose rate limits, log filter,
+//!   trace sampling ratio and disabled networks are reloaded on `SIGHUP` (see `config_file`)
 //! - `HOST`, `PORT` control binding address
+//! - `ROUTE_PREFIX`, `WS_PATH`, `DISABLED_ENDPOINTS` customize the route table (see [`handlers::FacilitatorRoutes`])
+//! - `RATE_LIMITS`, `RATE_LIMITS_PER_KEY`, `RATE_LIMIT_KEY` tune the per-client and per-API-key rate limits of each endpoint (see [`handlers::RateLimits`])
+//! - `WS_API_KEYS`, `WS_AUTH_ADDRESSES` require WebSocket connections to authenticate with an API key or a signed challenge, `WS_AUTH_TIMEOUT_SECS`, `WS_AUTH_SESSION_SECS` tune it (see [`handlers::WsAuth`])
+//! - `WS_ERROR_DATA` (`full`, `redact`, `omit`), `WS_ERROR_DATA_MAX_BYTES` restrict the details of the WebSocket errors sent to unauthenticated connections (see [`handlers::WsErrorData`])
+//! - `WS_PING_INTERVAL_SECS`, `WS_PONG_TIMEOUT_SECS`, `WS_IDLE_TIMEOUT_SECS`, `WS_MAX_LIFETIME_SECS` ping WebSocket connections and close the dead, idle or long-lived ones (see [`handlers::WsLiveness`])
+//! - `WS_COMPRESSION` (`on`, `off`), `WS_COMPRESSION_MIN_BYTES` compress the large envelopes of WebSocket connections offering the `x402.deflate` subprotocols (see [`handlers::WsCompression`])
+//! - `HEALTH_MIN_GAS_BALANCE`, `HEALTH_MIN_GAS_BALANCE_<NETWORK>`, `HEALTH_MAX_QUEUE_DEPTH`, `HEALTH_CHECK_TIMEOUT_MS` tune the checks of
+//!   `/healthz` and `/readyz` (see [`handlers::HealthChecks`])
+//! - `SHUTDOWN_TIMEOUT_SECS` bounds the time given to the requests and settlements in flight on `SIGTERM` (see [`handlers::Shutdown`])
+//! - `ADMIN_TOKEN` enables the admin API, authenticated with `Authorization: Bearer <token>`; `ADMIN_PORT`, `ADMIN_HOST` (default `127.0.0.1`)
+//!   serve it on a listener of its own instead of the public one (see [`handlers::AdminListener`])
+//! - `RUST_LOG` sets the log filter, adjustable at runtime through the admin API
+//! - `VERIFY_POOL_THREADS` sizes the thread pool running the CPU-bound work of verification (see `verify_pool`)
+//! - `NONCE_STORE_URL` persists settled payment nonces (`sled:<path>` or `postgres://...`, see `nonce_store`)
+//! - `MULTICALL_WINDOW_MS`, `MULTICALL_MAX_PAYMENTS` settle EVM payments in Multicall3 batches (see `chain::multicall`)
+//! - `EVM_PRIVATE_KEY` takes comma-separated keys, or `SIGNER_TYPE=mnemonic` derives `EVM_SIGNER_COUNT` accounts from `EVM_MNEMONIC`,
+//!   to settle from several accounts in turn (see `chain::signer_pool`)
+//! - `SIGNER_TYPE=aws-kms`, `gcp-kms` or `remote` signs settlements with keys of AWS KMS (`AWS_KMS_KEY_ID`), Google Cloud KMS (`GCP_KMS_KEY`)
+//!   or a remote signer (`REMOTE_SIGNER_URL`) instead (`kms` feature, see `chain::kms`)
+//! - `RPC_URL_*` may list several comma-separated endpoints per network, failing over to each other; `RPC_HEALTH_CHECK_INTERVAL_SECS` and `RPC_FAILOVER_COOLDOWN_SECS` tune their health checks (see `chain::rpc_failover`)
+//! - `NONCE_SYNC_INTERVAL_SECS` sets how often the settlement account nonces are checked against the chain (see `chain::account_nonce`)
+//! - `GAS_BALANCE_FLOOR`, `GAS_BALANCE_FLOOR_<NETWORK>` refuse settlements on networks whose settlement accounts are short of gas, polled every
+//!   `GAS_MONITOR_INTERVAL_SECS` and reported by `/supported` (see `gas_monitor`)
+//! - `GAS_MAX_FEE_PER_GAS`, `GAS_PRIORITY_FEE_PER_GAS`, `GAS_ESCALATE_AFTER_SECS`, `GAS_ESCALATION_PERCENT`, `GAS_MAX_ESCALATIONS` set the fees of EVM settlement transactions, per network with a suffix (see `chain::gas`)
+//! - `SETTLEMENT_CAP`, `SETTLEMENT_CAP_PAYER_DAILY`, `SETTLEMENT_CAP_DAILY` cap the amounts settled (see `settlement_caps`)
+//! - `SETTLE_REQUIRE_VERIFIED_SECS` refuses to settle payments not verified within the given time, tunable at `/admin/strict-settle` (see `strict_settle`)
+//! - `SETTLE_IDEMPOTENCY_TTL_SECS` sets how long settlement responses are remembered by idempotency key (see `settle_idempotency`)
+//! - `SETTLE_MODE=deferred` queues `x402.settle` payments for a background worker, `SETTLEMENT_QUEUE_URL` (`sled:<path>` or `postgres://...`) persists the queue, `SETTLEMENT_QUEUE_MAX_ATTEMPTS` bounds retries (see `settlement_queue`)
+//! - `SETTLEMENT_CONFIRMATIONS` sets the confirmations required before `x402.subscribe` reports `confirmed`,
+//!   and `SETTLEMENT_FINALITY` the `safe` or `finalized` head required instead, e.g. on rollups;
+//!   `SETTLEMENT_STATUS_CAPACITY` bounds the settlements served at `/settlement/{transaction}`
+//! - `SETTLEMENT_STATS_URL` (`memory`, `sled:<path>` or `postgres://...`) collects settlement statistics served at `/stats`,
+//!   cached for `SETTLEMENT_STATS_CACHE_SECS` (see `settlement_stats`)
+//! - `RECEIPT_CAPACITY` bounds the signed receipts of settlements served at `/receipts/{transaction}`, `0` disables receipts (see `receipts`)
+//! - `REFUND_LEDGER_URL` (`memory`, `sled:<path>` or `postgres://...`) serves refunds of settled payments at `/refund`, keeping their ledger (see `refunds`)
+//! - `PAYMENT_HISTORY_URL` (`memory`, `sqlite:<path>` or `postgres://...`) records verifications and settlements served at `/payments` (see `storage`)
+//! - `DISABLED_NETWORKS` stops serving the given networks, reloadable from the configuration file (see `disabled_networks`)
+//! - `TEST_NETWORK=true` serves the sandboxed `test` network, faking settlement (see `chain::sandbox`)
 //! - `OTEL_*` variables enable tracing to systems like Honeycomb
+//! - `UPSTREAM_FACILITATOR_URL` delegates verification and settlement to another facilitator, over HTTP or WebSocket (`remote` feature, see `facilitator_remote`)
+//! - `VERIFY_CACHE_TTL_MS` caches verification results for the given time, `VERIFY_CACHE_TTL_MS_<NETWORK>` on one network (see `facilitator_cache`)
+//! - `WATCH_*` variables enable inbound payment notifications (`webhooks` feature, see `payment_watch`)
+//! - `WEBHOOK_*` variables enable signed notifications of payment and stream events (`webhooks` feature, see `webhooks`)
+//! - `REPLAY_RECORD_PATH` records verifications, settlements and EVM RPC calls to a file, and `REPLAY_FILE` replays
+//!   such a recording against a mock chain instead of serving (`replay` feature, see `replay`)
 
 use axum::http::Method;
-use axum::routing::post;
 use axum::{Extension, Router, routing::get};
 use dotenvy::dotenv;
 use opentelemetry::trace::Status;
 use std::env;
+use std::future::IntoFuture;
 use std::net::SocketAddr;
+use std::sync::Arc;
 use tower_http::cors;
 use tower_http::trace::TraceLayer;
 use tracing_opentelemetry::OpenTelemetrySpanExt;
 
+use crate::chain::account_nonce;
+use crate::chain::multicall::SettlementBatching;
+use crate::chain::settlement_signer::SettlementSigner;
+use crate::cli::Command;
+use crate::config_file::ConfigFile;
+use crate::disabled_networks::DisabledNetworks;
+use crate::facilitator_cache::{CachedFacilitator, VerifyCacheTtls};
 use crate::facilitator_local::FacilitatorLocal;
+use crate::gas_monitor::GasMonitor;
+use crate::handlers::{
+    AdminListener, FacilitatorRoutes, HealthChecks, RateLimits, RateLimitsControl,
+    ServedFacilitator, Shutdown, WsAuth, WsCompression, WsConnections, WsErrorData, WsLiveness,
+};
 use crate::provider_cache::ProviderCache;
-use crate::telemetry::Telemetry;
+use crate::receipts::Receipts;
+use crate::refunds::Refunds;
+use crate::settle_idempotency::SettleIdempotency;
+use crate::settlement_caps::SettlementCaps;
+use crate::settlement_events::SettlementEvents;
+use crate::settlement_queue::SettleMode;
+use crate::settlement_stats::SettlementStats;
+use crate::storage::PaymentHistory;
+use crate::strict_settle::StrictSettle;
+use crate::telemetry::{Telemetry, TelemetryControl};
 
+mod balance;
+mod canonical_json;
 mod chain;
+mod channel;
+mod cli;
+mod config_file;
+#[cfg(feature = "chaos")]
+mod chaos;
+mod disabled_networks;
+mod discovery;
+mod duration;
+mod extensions;
 mod facilitator;
+mod facilitator_cache;
 mod facilitator_local;
+#[cfg(feature = "remote")]
+mod facilitator_remote;
+mod gas_monitor;
 mod handlers;
+mod lru_cache;
 mod network;
+mod nonce_store;
+mod payment_spans;
+#[cfg(feature = "webhooks")]
+mod payment_watch;
 mod provider_cache;
+mod receipts;
+mod refunds;
+#[cfg(feature = "replay")]
+mod replay;
+#[cfg(feature = "remote")]
+mod retry;
+mod scheme;
+mod settle_idempotency;
+mod settlement_caps;
+mod settlement_events;
+mod settlement_pause;
+mod settlement_queue;
+mod settlement_stats;
+mod storage;
+mod stream;
+mod strict_settle;
 mod telemetry;
 mod timestamp;
+mod tokens;
 mod types;
+mod verify_pool;
+#[cfg(feature = "webhooks")]
+mod webhooks;
+mod ws_codec;
+mod ws_error;
+mod ws_jsonrpc;
+mod ws_methods;
+
+/// Log filter, reloaded from the configuration file.
+const ENV_RUST_LOG: &str = "RUST_LOG";
+/// Trace sampling ratio, reloaded from the configuration file.
+const ENV_OTEL_TRACES_SAMPLER_ARG: &str = "OTEL_TRACES_SAMPLER_ARG";
+
+/// URL of the facilitator to delegate verification and settlement to (`remote` feature).
+#[cfg(feature = "remote")]
+const ENV_UPSTREAM_FACILITATOR_URL: &str = "UPSTREAM_FACILITATOR_URL";
+
+/// Loads `.env` variables, then the configuration file, and runs the command of the arguments,
+/// the server by default.
+#[tokio::main]
+async fn main() {
+    // Load .env variables
+    dotenv().ok();
+    let config = match ConfigFile::from_env() {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("{e}");
+            std::process::exit(1);
+        }
+    };
+    let config = config.map(|mut config| {
+        // SAFETY: like `dotenv` above, before any task is spawned: no other thread reads the
+        // environment yet
+        unsafe { config.apply() };
+        config
+    });
+
+    let command = match Command::parse(env::args().skip(1)) {
+        Ok(command) => command,
+        Err(e) => {
+            eprintln!("{e}\n\n{}", cli::USAGE);
+            std::process::exit(2);
+        }
+    };
+    match command {
+        Command::Serve => serve(config).await,
+        command => std::process::exit(cli::run(command).await),
+    }
+}
 
 /// Initializes the x402 facilitator server.
 ///
-/// - Loads `.env` variables.
 /// - Initializes OpenTelemetry tracing.
 /// - Connects to Ethereum providers for supported networks.
 /// - Starts an Axum HTTP server with the x402 protocol handlers.
 ///
 /// Binds to the address specified by the `HOST` and `PORT` env vars.
-#[tokio::main]
-async fn main() {
-    // Load .env variables
-    dotenv().ok();
-
-    let _telemetry = Telemetry::new()
+///
+/// The settings of `config` that apply at runtime are reloaded on `SIGHUP`.
+async fn serve(config: Option<ConfigFile>) {
+    let telemetry = Telemetry::new()
         .with_name(env!("CARGO_PKG_NAME"))
         .with_version(env!("CARGO_PKG_VERSION"))
         .register();
 
-    let provider_cache = ProviderCache::from_env().await;
-    // Abort if we can't initialize Ethereum providers early
-    if let Err(e) = provider_cache {
-        tracing::error!("Failed to create Ethereum providers: {}", e);
-        std::process::exit(1);
+    #[cfg(feature = "replay")]
+    replay_from_env().await;
+
+    let routes = match FacilitatorRoutes::from_env() {
+        Ok(routes) => routes,
+        Err(e) => {
+            tracing::error!("Invalid route configuration: {}", e);
+            std::process::exit(1);
+        }
+    };
+    tracing::info!(paths = ?routes.paths(), "Enabled endpoints");
+    tracing::info!(
+        threads = verify_pool::threads(),
+        "Started the verification pool"
+    );
+    let routes = match WsAuth::from_env() {
+        Ok(Some(auth)) => {
+            tracing::info!(?auth, "Requiring WebSocket authentication");
+            auth.apply(&routes)
+        }
+        Ok(None) => routes,
+        Err(e) => {
+            tracing::error!("Invalid WebSocket authentication configuration: {}", e);
+            std::process::exit(1);
+        }
+    };
+    let routes = match WsErrorData::from_env() {
+        Ok(Some(error_data)) => {
+            tracing::info!(%error_data, "Restricting WebSocket error data");
+            error_data.apply(&routes)
+        }
+        Ok(None) => routes,
+        Err(e) => {
+            tracing::error!("Invalid WebSocket error data configuration: {}", e);
+            std::process::exit(1);
+        }
+    };
+    let routes = match WsLiveness::from_env() {
+        Ok(liveness) => {
+            tracing::info!(?liveness, "WebSocket liveness policy");
+            liveness.apply(&routes)
+        }
+        Err(e) => {
+            tracing::error!("Invalid WebSocket liveness configuration: {}", e);
+            std::process::exit(1);
+        }
+    };
+    let routes = match WsCompression::from_env() {
+        Ok(Some(compression)) => {
+            tracing::info!(
+                min_bytes = compression.min_bytes(),
+                "Compressing WebSocket envelopes"
+            );
+            compression.apply(&routes)
+        }
+        Ok(None) => routes,
+        Err(e) => {
+            tracing::error!("Invalid WebSocket compression configuration: {}", e);
+            std::process::exit(1);
+        }
+    };
+    let limits = match RateLimits::from_env() {
+        Ok(limits) => {
+            tracing::info!(%limits, "Rate limits");
+            limits.control()
+        }
+        Err(e) => {
+            tracing::error!("Invalid rate limit configuration: {}", e);
+            std::process::exit(1);
+        }
+    };
+    let routes = limits.apply(&routes);
+
+    let shutdown = match Shutdown::from_env() {
+        Ok(shutdown) => shutdown,
+        Err(e) => {
+            tracing::error!("Invalid shutdown timeout: {}", e);
+            std::process::exit(1);
+        }
+    };
+    let routes = shutdown.apply(&routes);
+    let routes = WsConnections::new().apply(&routes);
+    let routes = match HealthChecks::from_env() {
+        Ok(checks) => checks.apply(&routes),
+        Err(e) => {
+            tracing::error!("Invalid health check configuration: {}", e);
+            std::process::exit(1);
+        }
+    };
+    let admin_listener = match AdminListener::from_env() {
+        Ok(admin_listener) => admin_listener,
+        Err(e) => {
+            tracing::error!("Invalid admin listener configuration: {}", e);
+            std::process::exit(1);
+        }
+    };
+
+    let disabled_networks = match DisabledNetworks::from_env() {
+        Ok(disabled_networks) => {
+            let networks = disabled_networks.networks();
+            if !networks.is_empty() {
+                tracing::info!(?networks, "Disabled networks");
+            }
+            disabled_networks
+        }
+        Err(e) => {
+            tracing::error!("Invalid disabled networks: {}", e);
+            std::process::exit(1);
+        }
+    };
+
+    let app = match upstream_router(&routes) {
+        Some(router) => router,
+        None => local_router(&routes, &shutdown, &disabled_networks).await,
+    };
+
+    if let Some(config) = config {
+        tokio::spawn(reload_on_hangup(
+            config,
+            telemetry.control(),
+            limits,
+            disabled_networks,
+        ));
     }
-    let facilitator = FacilitatorLocal::new(provider_cache.unwrap());
 
-    let app = Router::new()
+    let app = app
         .route("/", get(|| async { "Hello, World!" })) // Liveness or sanity check route
-        .route("/verify", get(handlers::get_verify_info))
-        .route("/verify", post(handlers::post_verify))
-        .route("/settle", get(handlers::get_settle_info))
-        .route("/settle", post(handlers::post_settle))
-        .route("/ws", get(handlers::ws_handler))
-        .route("/supported", get(handlers::get_supported))
-        .layer(Extension(facilitator))
+        .layer(Extension(telemetry.control()))
         .layer(
             TraceLayer::new_for_http()
                 .make_span_with(|request: &axum::http::Request<_>| {
@@ -148,7 +421,456 @@ async fn main() {
         }
     };
 
-    if let Err(e) = axum::serve(listener, app).await {
-        tracing::error!("Server error: {}", e);
+    if let Some(admin_listener) = admin_listener {
+        let addr = admin_listener.addr();
+        let listener = match tokio::net::TcpListener::bind(addr).await {
+            Ok(listener) => listener,
+            Err(e) => {
+                tracing::error!("Failed to bind the admin API to {}: {}", addr, e);
+                std::process::exit(1);
+            }
+        };
+        tracing::info!("Serving the admin API at http://{}", addr);
+        let admin_app = admin_listener.apply(app.clone());
+        let shutdown = shutdown.clone();
+        tokio::spawn(async move {
+            let server = axum::serve(
+                listener,
+                admin_app.into_make_service_with_connect_info::<SocketAddr>(),
+            )
+            .with_graceful_shutdown(async move {
+                shutdown.requested().await;
+            });
+            if let Err(e) = server.await {
+                tracing::error!("Admin server error: {}", e);
+            }
+        });
+    }
+
+    let server = axum::serve(
+        listener,
+        app.into_make_service_with_connect_info::<SocketAddr>(),
+    )
+    .with_graceful_shutdown(shutdown.clone().signal());
+    tokio::select! {
+        result = server.into_future() => {
+            if let Err(e) = result {
+                tracing::error!("Server error: {}", e);
+            }
+        }
+        _ = shutdown.elapsed() => {
+            tracing::warn!("Shutdown timeout elapsed, abandoning the HTTP requests in flight");
+        }
+    }
+    shutdown.drain().await;
+    tracing::info!("Shut down");
+}
+
+/// Serves a [`FacilitatorLocal`] settling on the networks configured in the environment, but
+/// `disabled_networks`. The worker of its settlement queue, if any, is stopped by `shutdown`.
+async fn local_router(
+    routes: &FacilitatorRoutes,
+    shutdown: &Shutdown,
+    disabled_networks: &DisabledNetworks,
+) -> Router {
+    #[cfg(feature = "replay")]
+    let recording = match replay::Recording::from_env() {
+        Ok(recording) => recording.inspect(|recording| {
+            tracing::info!("Recording verifications, settlements and EVM RPC calls");
+            recording.install();
+        }),
+        Err(e) => {
+            tracing::error!("Failed to open the recording: {}", e);
+            std::process::exit(1);
+        }
+    };
+    let facilitator = local_facilitator(disabled_networks.clone()).await;
+    if let Some(interval) = account_nonce::sync_interval_from_env() {
+        facilitator.provider_cache.start_nonce_sync(interval);
+    }
+    let facilitator = match GasMonitor::from_env() {
+        Ok(Some(monitor)) => {
+            monitor.start(&facilitator.provider_cache);
+            facilitator.with_gas_monitor(monitor)
+        }
+        Ok(None) => facilitator,
+        Err(e) => {
+            tracing::error!("Invalid gas monitor configuration: {}", e);
+            std::process::exit(1);
+        }
+    };
+    let facilitator = match SettlementBatching::from_env() {
+        Some(batching) => {
+            tracing::info!(?batching, "Settling EVM payments in Multicall3 batches");
+            facilitator.with_settlement_batching(batching)
+        }
+        None => facilitator,
+    };
+    // Before the settlement queue, which settles with the facilitator it is started with.
+    #[cfg(feature = "webhooks")]
+    let facilitator = match webhooks::Webhooks::from_env() {
+        Ok(Some(webhooks)) => {
+            tracing::info!(urls = ?webhooks.urls(), "Notifying webhooks of payment events");
+            webhooks.watch_streams(&facilitator.streams);
+            facilitator.with_webhooks(webhooks)
+        }
+        Ok(None) => facilitator,
+        Err(e) => {
+            tracing::error!("Invalid webhooks configuration: {}", e);
+            std::process::exit(1);
+        }
+    };
+    let facilitator = match SettleMode::from_env().await {
+        Ok(mode) => {
+            if let Some(queue) = mode.queue() {
+                tracing::info!("Deferring settlements to the settlement queue");
+                queue.start(facilitator.clone());
+                shutdown.stop_queue(queue.clone());
+            }
+            facilitator.with_settle_mode(mode)
+        }
+        Err(e) => {
+            tracing::error!("Failed to open the settlement queue: {}", e);
+            std::process::exit(1);
+        }
+    };
+    #[cfg(feature = "webhooks")]
+    let facilitator = match payment_watch::PaymentWatcher::from_env() {
+        Ok(Some(watcher)) => {
+            watcher.start(&facilitator.provider_cache);
+            facilitator.with_payment_watcher(watcher)
+        }
+        Ok(None) => facilitator,
+        Err(e) => {
+            tracing::error!("Invalid payment watcher configuration: {}", e);
+            std::process::exit(1);
+        }
+    };
+
+    #[cfg(feature = "replay")]
+    if let Some(recording) = recording {
+        let facilitator = replay::RecordingFacilitator::new(facilitator, recording);
+        return facilitator_router(routes, facilitator);
+    }
+    facilitator_router(routes, facilitator)
+}
+
+/// Builds a [`FacilitatorLocal`] settling on the networks configured in the environment but
+/// `disabled_networks`, with its nonce store, settlement caps and settlement statistics, and
+/// settling payments as they come. Exits if the configuration is invalid.
+async fn local_facilitator(disabled_networks: DisabledNetworks) -> FacilitatorLocal {
+    let provider_cache = ProviderCache::from_env().await;
+    // Abort if we can't initialize Ethereum providers early
+    if let Err(e) = provider_cache {
+        tracing::error!("Failed to create Ethereum providers: {}", e);
+        std::process::exit(1);
+    }
+    let nonce_store = match nonce_store::from_env().await {
+        Ok(nonce_store) => nonce_store,
+        Err(e) => {
+            tracing::error!("Failed to open the nonce store: {}", e);
+            std::process::exit(1);
+        }
+    };
+    let caps = match SettlementCaps::from_env() {
+        Ok(caps) => caps,
+        Err(e) => {
+            tracing::error!("Invalid settlement caps: {}", e);
+            std::process::exit(1);
+        }
+    };
+    if caps.is_enabled() {
+        tracing::info!(?caps, "Capping settled amounts");
+    }
+    let strict_settle = match StrictSettle::from_env() {
+        Ok(strict_settle) => strict_settle,
+        Err(e) => {
+            tracing::error!("Invalid strict settlement window: {}", e);
+            std::process::exit(1);
+        }
+    };
+    if let Some(window) = strict_settle.window() {
+        tracing::info!(?window, "Refusing to settle payments not verified recently");
+    }
+    let idempotency = match SettleIdempotency::from_env() {
+        Ok(idempotency) => idempotency,
+        Err(e) => {
+            tracing::error!("Invalid settlement idempotency TTL: {}", e);
+            std::process::exit(1);
+        }
+    };
+    let settlement_events = match SettlementEvents::from_env() {
+        Ok(settlement_events) => settlement_events,
+        Err(e) => {
+            tracing::error!("Invalid settlement finality: {}", e);
+            std::process::exit(1);
+        }
+    };
+    let provider_cache = provider_cache.unwrap();
+    let signer = provider_cache.evm_signer().cloned();
+    let facilitator = FacilitatorLocal::new(provider_cache)
+        .with_settlement_events(settlement_events)
+        .with_nonce_store(nonce_store)
+        .with_settlement_caps(caps)
+        .with_strict_settle(strict_settle)
+        .with_settle_idempotency(idempotency)
+        .with_disabled_networks(disabled_networks);
+    let facilitator = with_receipts_from_env(facilitator, signer);
+    let facilitator = with_refunds_from_env(facilitator).await;
+    let facilitator = match PaymentHistory::from_env().await {
+        Ok(Some(history)) => {
+            tracing::info!("Recording payment history");
+            facilitator.with_payment_history(history)
+        }
+        Ok(None) => facilitator,
+        Err(e) => {
+            tracing::error!("Failed to open the payment history: {}", e);
+            std::process::exit(1);
+        }
+    };
+    match SettlementStats::from_env().await {
+        Ok(Some(stats)) => {
+            tracing::info!("Collecting settlement statistics");
+            facilitator.with_settlement_stats(stats)
+        }
+        Ok(None) => facilitator,
+        Err(e) => {
+            tracing::error!("Failed to open the settlement statistics: {}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Signs the receipts of the payments settled by `facilitator` with `signer`, the primary EVM
+/// settlement account, unless receipts are disabled or there is no EVM network. Exits if the
+/// configuration is invalid.
+fn with_receipts_from_env(
+    facilitator: FacilitatorLocal,
+    signer: Option<Arc<dyn SettlementSigner>>,
+) -> FacilitatorLocal {
+    let Some(signer) = signer else {
+        return facilitator;
+    };
+    match Receipts::from_env(signer) {
+        Ok(Some(receipts)) => {
+            let address = receipts.facilitator();
+            tracing::info!(%address, "Signing receipts of settled payments");
+            facilitator.with_receipts(receipts)
+        }
+        Ok(None) => facilitator,
+        Err(e) => {
+            tracing::error!("Invalid receipts configuration: {}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Refunds the payments settled by `facilitator` if `REFUND_LEDGER_URL` is set and receipts are
+/// signed, identifying the settlements refunded. Exits if the ledger can not be opened.
+async fn with_refunds_from_env(facilitator: FacilitatorLocal) -> FacilitatorLocal {
+    let Some(receipts) = facilitator.receipts.clone() else {
+        return facilitator;
+    };
+    match Refunds::from_env(receipts).await {
+        Ok(Some(refunds)) => {
+            tracing::info!("Refunding settled payments");
+            facilitator.with_refunds(refunds)
+        }
+        Ok(None) => facilitator,
+        Err(e) => {
+            tracing::error!("Failed to open the refund ledger: {}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Applies the settings of `config` that can change at runtime, every time the file is reloaded
+/// on `SIGHUP`: the rate limits, the log filter, the trace sampling ratio and the disabled
+/// networks. Invalid settings are logged and left as they are.
+#[cfg(unix)]
+async fn reload_on_hangup(
+    mut config: ConfigFile,
+    telemetry: TelemetryControl,
+    limits: RateLimitsControl,
+    disabled_networks: DisabledNetworks,
+) {
+    use tokio::signal::unix::{SignalKind, signal};
+
+    let mut hangup = match signal(SignalKind::hangup()) {
+        Ok(hangup) => hangup,
+        Err(e) => {
+            tracing::warn!("Can not reload the configuration file on SIGHUP: {}", e);
+            return;
+        }
+    };
+    let mut filter = config.var(ENV_RUST_LOG);
+    let mut sampling_ratio = config.var(ENV_OTEL_TRACES_SAMPLER_ARG);
+    while hangup.recv().await.is_some() {
+        let path = config.path().display().to_string();
+        if let Err(e) = config.reload() {
+            tracing::error!("Failed to reload the configuration file: {}", e);
+            continue;
+        }
+        tracing::info!(%path, "Reloaded the configuration file");
+        match RateLimits::from_vars(|name| config.var(name)) {
+            Ok(reloaded) => limits.set(&reloaded),
+            Err(e) => tracing::error!("Invalid rate limit configuration: {}", e),
+        }
+        match DisabledNetworks::from_vars(|name| config.var(name)) {
+            Ok(reloaded) => disabled_networks.set(reloaded.networks()),
+            Err(e) => tracing::error!("Invalid disabled networks: {}", e),
+        }
+        let reloaded = config.var(ENV_RUST_LOG);
+        if reloaded != filter {
+            match telemetry.set_filter(reloaded.as_deref().unwrap_or_default()) {
+                Ok(()) => filter = reloaded,
+                Err(e) => tracing::error!("{}", e),
+            }
+        }
+        let reloaded = config.var(ENV_OTEL_TRACES_SAMPLER_ARG);
+        if reloaded != sampling_ratio && telemetry.sampling_ratio().is_some() {
+            let ratio = reloaded.as_deref().unwrap_or("1.0");
+            match ratio.parse() {
+                Ok(ratio) => match telemetry.set_sampling_ratio(ratio) {
+                    Ok(()) => sampling_ratio = reloaded,
+                    Err(e) => tracing::error!("{}", e),
+                },
+                Err(_) => tracing::error!("Invalid sampling ratio {}", ratio),
+            }
+        }
+    }
+}
+
+#[cfg(not(unix))]
+async fn reload_on_hangup(
+    _config: ConfigFile,
+    _telemetry: TelemetryControl,
+    _limits: RateLimitsControl,
+    _disabled_networks: DisabledNetworks,
+) {
+    tracing::warn!("Can not reload the configuration file: SIGHUP is not supported");
+}
+
+/// Replays the recording at `REPLAY_FILE`, if set, with the settlement key, settlement caps and
+/// receipts of the environment, and exits: with an error if any outcome differs from the recorded one.
+#[cfg(feature = "replay")]
+async fn replay_from_env() {
+    let replay = match replay::Replay::from_env() {
+        Ok(Some(replay)) => replay,
+        Ok(None) => return,
+        Err(e) => {
+            tracing::error!("Failed to read the recording: {}", e);
+            std::process::exit(1);
+        }
+    };
+    let signers = match async {
+        let signers = provider_cache::SignerType::from_env()?
+            .make_evm_signers()
+            .await?;
+        let wallet = provider_cache::evm_wallet(signers.clone())?;
+        Ok::<_, Box<dyn std::error::Error>>((wallet, signers))
+    }
+    .await
+    {
+        Ok(signers) => signers,
+        Err(e) => {
+            tracing::error!("Failed to create the settlement wallet: {}", e);
+            std::process::exit(1);
+        }
+    };
+    let (wallet, signers) = signers;
+    let caps = match SettlementCaps::from_env() {
+        Ok(caps) => caps,
+        Err(e) => {
+            tracing::error!("Invalid settlement caps: {}", e);
+            std::process::exit(1);
+        }
+    };
+    let strict_settle = match StrictSettle::from_env() {
+        Ok(strict_settle) => strict_settle,
+        Err(e) => {
+            tracing::error!("Invalid strict settlement window: {}", e);
+            std::process::exit(1);
+        }
+    };
+    let facilitator = match replay.facilitator(wallet) {
+        Ok(facilitator) => with_receipts_from_env(
+            facilitator
+                .with_settlement_caps(caps)
+                .with_strict_settle(strict_settle),
+            signers.into_iter().next(),
+        ),
+        Err(e) => {
+            tracing::error!("{}", e);
+            std::process::exit(1);
+        }
+    };
+    let report = replay.run(&facilitator).await;
+    for mismatch in &report.mismatches {
+        tracing::error!(
+            line = mismatch.line,
+            method = mismatch.method,
+            expected = ?mismatch.expected,
+            actual = ?mismatch.actual,
+            "Replayed outcome differs from the recording"
+        );
+    }
+    tracing::info!(
+        replayed = report.replayed,
+        mismatches = report.mismatches.len(),
+        "Replayed the recording"
+    );
+    std::process::exit(if report.mismatches.is_empty() { 0 } else { 1 });
+}
+
+/// Serves the facilitator at `UPSTREAM_FACILITATOR_URL`, if set, as a thin edge: `http(s)://`
+/// URLs are reached through its HTTP endpoints, `ws(s)://` ones through its WebSocket endpoint.
+#[cfg(feature = "remote")]
+fn upstream_router(routes: &FacilitatorRoutes) -> Option<Router> {
+    use crate::facilitator_remote::{FacilitatorHttpClient, FacilitatorWsClient};
+
+    let url = env::var(ENV_UPSTREAM_FACILITATOR_URL).ok()?;
+    let router = if url.starts_with("ws://") || url.starts_with("wss://") {
+        FacilitatorWsClient::try_from(url.as_str())
+            .map(|client| facilitator_router(routes, client))
+            .map_err(|e| e.to_string())
+    } else {
+        FacilitatorHttpClient::try_from(url.as_str())
+            .map(|client| facilitator_router(routes, client))
+            .map_err(|e| e.to_string())
+    };
+    match router {
+        Ok(router) => {
+            tracing::info!(%url, "Delegating to the upstream facilitator");
+            Some(router)
+        }
+        Err(e) => {
+            tracing::error!("Invalid {}: {}", ENV_UPSTREAM_FACILITATOR_URL, e);
+            std::process::exit(1);
+        }
+    }
+}
+
+#[cfg(not(feature = "remote"))]
+fn upstream_router(_routes: &FacilitatorRoutes) -> Option<Router> {
+    None
+}
+
+/// Serves `facilitator`, caching its verifications if `VERIFY_CACHE_TTL_MS` is set, or
+/// `VERIFY_CACHE_TTL_MS_<NETWORK>` for some network.
+fn facilitator_router<F: ServedFacilitator>(routes: &FacilitatorRoutes, facilitator: F) -> Router {
+    match VerifyCacheTtls::from_env() {
+        Ok(ttls) if ttls.is_enabled() => {
+            tracing::info!(?ttls, "Caching verifications");
+            let facilitator = CachedFacilitator::new(facilitator).with_ttls(&ttls);
+            routes
+                .router::<CachedFacilitator<F>>()
+                .layer(Extension(facilitator))
+        }
+        Ok(_) => routes.router::<F>().layer(Extension(facilitator)),
+        Err(e) => {
+            tracing::error!("{}", e);
+            std::process::exit(1);
+        }
     }
 }
@@ -1,7 +1,8 @@
 //! Network definitions and known token deployments.
 //!
 //! This module defines supported networks and their chain IDs,
-//! and provides statically known USDC deployments per network.
+//! and provides statically known USDC deployments per network. Deployments of other tokens are
+//! registered in [`tokens`](crate::tokens).
 
 use crate::types::{MixedAddress, TokenAsset, TokenDeployment, TokenDeploymentEip712};
 use alloy::primitives::address;
@@ -51,6 +52,10 @@ pub enum Network {
     /// Sei testnet (chain ID 1328).
     #[serde(rename = "sei-testnet")]
     SeiTestnet,
+    /// Sandboxed network of the facilitator (chain ID 1337): payments are verified structurally,
+    /// and never settled on any chain, see [`sandbox`](crate::chain::sandbox).
+    #[serde(rename = "test")]
+    Test,
 }
 
 impl Display for Network {
@@ -67,10 +72,29 @@ impl Display for Network {
             Network::Polygon => write!(f, "polygon"),
             Network::Sei => write!(f, "sei"),
             Network::SeiTestnet => write!(f, "sei-testnet"),
+            Network::Test => write!(f, "test"),
         }
     }
 }
 
+/// Error returned when parsing a name not in [`Network::variants`].
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+#[error("Unknown network: {0}")]
+pub struct UnknownNetwork(pub String);
+
+impl FromStr for Network {
+    type Err = UnknownNetwork;
+
+    /// Parses the name of a network, as displayed, e.g. `base-sepolia`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Network::variants()
+            .iter()
+            .find(|network| network.to_string() == s)
+            .copied()
+            .ok_or_else(|| UnknownNetwork(s.to_string()))
+    }
+}
+
 #[derive(Debug, Clone, Copy)]
 pub enum NetworkFamily {
     Evm,
@@ -91,6 +115,7 @@ impl From<Network> for NetworkFamily {
             Network::Polygon => NetworkFamily::Evm,
             Network::Sei => NetworkFamily::Evm,
             Network::SeiTestnet => NetworkFamily::Evm,
+            Network::Test => NetworkFamily::Evm,
         }
     }
 }
@@ -110,6 +135,7 @@ impl Network {
             Network::Polygon,
             Network::Sei,
             Network::SeiTestnet,
+            Network::Test,
         ]
     }
 }
@@ -275,6 +301,22 @@ static USDC_SEI_TESTNET: Lazy<USDCDeployment> = Lazy::new(|| {
     })
 });
 
+/// USDC of the sandboxed `test` network as [`USDCDeployment`]: no contract is deployed at that
+/// address, payments are never settled on-chain.
+static USDC_TEST: Lazy<USDCDeployment> = Lazy::new(|| {
+    USDCDeployment(TokenDeployment {
+        asset: TokenAsset {
+            address: address!("0x0000000000000000000000000000000000000402").into(),
+            network: Network::Test,
+        },
+        decimals: 6,
+        eip712: Some(TokenDeploymentEip712 {
+            name: "USDC".into(),
+            version: "2".into(),
+        }),
+    })
+});
+
 /// A known USDC deployment as a wrapper around [`TokenDeployment`].
 #[derive(Clone, Debug)]
 pub struct USDCDeployment(pub TokenDeployment);
@@ -322,6 +364,7 @@ impl USDCDeployment {
             Network::Polygon => &USDC_POLYGON,
             Network::Sei => &USDC_SEI,
             Network::SeiTestnet => &USDC_SEI_TESTNET,
+            Network::Test => &USDC_TEST,
         }
     }
 }
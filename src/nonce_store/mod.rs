@@ -0,0 +1,212 @@
+//! Replay protection for payment authorizations.
+//!
+//! An ERC-3009 authorization can only be executed once on-chain, but until it is, the same signed
+//! payload can be verified any number of times, and concurrent settlements race each other.
+//! [`FacilitatorLocal`](crate::facilitator_local::FacilitatorLocal) records the nonce of every
+//! payment it settles in a [`PaymentNonceStore`]: a payment whose nonce is recorded fails
+//! verification and settlement with [`FacilitatorLocalError::NonceReused`].
+//!
+//! A nonce is reserved before settlement starts, and released if settlement fails, so that the
+//! buyer can retry with the same authorization. Nonces are keyed per network, token and payer for
+//! EVM payments, and per network and payer signature for Solana payments, see [`nonce_key`].
+//!
+//! The checkpoints of a payment channel settle the same `upto` authorization several times. The
+//! store also keeps the amount settled of every such authorization, see [`settled_key`]: it is
+//! the `settledAmount` of the next checkpoint, and keys its nonce, whatever the seller claims.
+//! An authorization the store has no settled amount for, e.g. after the restart of an
+//! [`InMemoryNonceStore`], can only be settled as a fresh permit, and its checkpoints fail.
+//!
+//! Backends:
+//! - [`InMemoryNonceStore`] – default; forgets nonces on restart
+//! - [`SledNonceStore`](sled::SledNonceStore) – embedded database on disk (`sled` feature)
+//! - [`PostgresNonceStore`](postgres::PostgresNonceStore) – shared by several facilitator instances (`postgres` feature)
+//!
+//! Environment (see [`from_env`]):
+//! - `NONCE_STORE_URL` – `sled:<path>` or `postgres://...`; nonces are kept in memory if unset
+
+use futures_util::future::BoxFuture;
+use std::collections::{HashMap, HashSet};
+use std::env;
+use std::sync::{Arc, Mutex};
+
+use crate::chain::FacilitatorLocalError;
+use crate::types::{ExactPaymentPayload, MixedAddress, Scheme, TokenAmount, VerifyRequest};
+
+#[cfg(feature = "postgres")]
+pub mod postgres;
+#[cfg(feature = "sled")]
+pub mod sled;
+
+const ENV_NONCE_STORE_URL: &str = "NONCE_STORE_URL";
+
+#[derive(Debug, thiserror::Error)]
+pub enum NonceStoreError {
+    #[error("Unsupported {ENV_NONCE_STORE_URL}: {0}")]
+    UnsupportedUrl(String),
+    #[error("Nonce store backend error: {0}")]
+    Backend(String),
+}
+
+impl From<NonceStoreError> for FacilitatorLocalError {
+    fn from(error: NonceStoreError) -> Self {
+        FacilitatorLocalError::NonceStore(error.to_string())
+    }
+}
+
+/// Record of the nonces of settled payments.
+///
+/// Implementations must make [`reserve`](PaymentNonceStore::reserve) atomic: of several
+/// concurrent reservations of the same key, exactly one succeeds.
+pub trait PaymentNonceStore: Send + Sync {
+    /// Whether `key` is reserved.
+    fn contains<'a>(&'a self, key: &'a str) -> BoxFuture<'a, Result<bool, NonceStoreError>>;
+
+    /// Reserves `key`. Returns `false` if it was already reserved.
+    fn reserve<'a>(&'a self, key: &'a str) -> BoxFuture<'a, Result<bool, NonceStoreError>>;
+
+    /// Releases a reservation, after a failed settlement.
+    fn release<'a>(&'a self, key: &'a str) -> BoxFuture<'a, Result<(), NonceStoreError>>;
+
+    /// Amount settled so far of the `upto` authorization of `key`, see [`settled_key`]. Zero if
+    /// none is recorded.
+    fn settled<'a>(&'a self, key: &'a str) -> BoxFuture<'a, Result<TokenAmount, NonceStoreError>>;
+
+    /// Adds `amount` to the amount settled of the `upto` authorization of `key`, after a
+    /// successful settlement.
+    fn add_settled<'a>(
+        &'a self,
+        key: &'a str,
+        amount: TokenAmount,
+    ) -> BoxFuture<'a, Result<(), NonceStoreError>>;
+}
+
+/// Nonces kept in memory, for the lifetime of the process.
+#[derive(Debug, Default)]
+pub struct InMemoryNonceStore {
+    keys: Mutex<HashSet<String>>,
+    settled: Mutex<HashMap<String, TokenAmount>>,
+}
+
+impl InMemoryNonceStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl PaymentNonceStore for InMemoryNonceStore {
+    fn contains<'a>(&'a self, key: &'a str) -> BoxFuture<'a, Result<bool, NonceStoreError>> {
+        let contains = self.keys.lock().unwrap().contains(key);
+        Box::pin(async move { Ok(contains) })
+    }
+
+    fn reserve<'a>(&'a self, key: &'a str) -> BoxFuture<'a, Result<bool, NonceStoreError>> {
+        let reserved = self.keys.lock().unwrap().insert(key.to_string());
+        Box::pin(async move { Ok(reserved) })
+    }
+
+    fn release<'a>(&'a self, key: &'a str) -> BoxFuture<'a, Result<(), NonceStoreError>> {
+        self.keys.lock().unwrap().remove(key);
+        Box::pin(async { Ok(()) })
+    }
+
+    fn settled<'a>(&'a self, key: &'a str) -> BoxFuture<'a, Result<TokenAmount, NonceStoreError>> {
+        let settled = self
+            .settled
+            .lock()
+            .unwrap()
+            .get(key)
+            .copied()
+            .unwrap_or(TokenAmount::from(0u64));
+        Box::pin(async move { Ok(settled) })
+    }
+
+    fn add_settled<'a>(
+        &'a self,
+        key: &'a str,
+        amount: TokenAmount,
+    ) -> BoxFuture<'a, Result<(), NonceStoreError>> {
+        let mut settled = self.settled.lock().unwrap();
+        let total = settled
+            .entry(key.to_string())
+            .or_insert(TokenAmount::from(0u64));
+        *total = total.saturating_add(amount);
+        Box::pin(async { Ok(()) })
+    }
+}
+
+/// Opens the store configured by `NONCE_STORE_URL`, or an [`InMemoryNonceStore`] if unset.
+pub async fn from_env() -> Result<Arc<dyn PaymentNonceStore>, NonceStoreError> {
+    let Some(url) = env::var(ENV_NONCE_STORE_URL)
+        .ok()
+        .filter(|url| !url.is_empty())
+    else {
+        return Ok(Arc::new(InMemoryNonceStore::new()));
+    };
+    #[cfg(feature = "sled")]
+    if let Some(path) = url.strip_prefix("sled:") {
+        return Ok(Arc::new(sled::SledNonceStore::open(path)?));
+    }
+    #[cfg(feature = "postgres")]
+    if url.starts_with("postgres://") || url.starts_with("postgresql://") {
+        return Ok(Arc::new(postgres::PostgresNonceStore::connect(&url).await?));
+    }
+    Err(NonceStoreError::UnsupportedUrl(url))
+}
+
+/// Key identifying the authorization of `request` in a [`PaymentNonceStore`], with the payer
+/// if known. `None` for payments without a nonce, e.g. of custom schemes.
+///
+/// The checkpoints of an `upto` authorization are keyed by its `settledAmount` as well, which
+/// must be the one recorded by the store, see [`settled_key`].
+pub fn nonce_key(request: &VerifyRequest) -> Option<(String, Option<MixedAddress>)> {
+    let payload = &request.payment_payload;
+    match &payload.payload {
+        ExactPaymentPayload::Evm(evm) => {
+            let payer = evm.authorization.from;
+            let key = format!(
+                "{}:{}:{}:{}",
+                payload.network,
+                request.payment_requirements.asset,
+                payer,
+                payload.id()?
+            );
+            Some((key, Some(payer.into())))
+        }
+        ExactPaymentPayload::Permit(permit) => {
+            let payer = permit.permit.owner;
+            let mut key = format!(
+                "{}:{}:{}",
+                payload.network,
+                request.payment_requirements.asset,
+                payload.id()?
+            );
+            // Every checkpoint of a payment channel settles the same authorization once
+            if payload.scheme == Scheme::Upto
+                && let Some(settled) = request.payment_requirements.settled_amount
+                && !settled.0.is_zero()
+            {
+                key = format!("{key}:{settled}");
+            }
+            Some((key, Some(payer.into())))
+        }
+        ExactPaymentPayload::Solana(_) => {
+            Some((format!("{}:{}", payload.network, payload.id()?), None))
+        }
+        ExactPaymentPayload::Custom(_) => None,
+    }
+}
+
+/// Key of the amount settled of the `upto` authorization of `request` in a [`PaymentNonceStore`]:
+/// the [`nonce_key`] of its first settlement. `None` for other payments.
+pub fn settled_key(request: &VerifyRequest) -> Option<String> {
+    let payload = &request.payment_payload;
+    match &payload.payload {
+        ExactPaymentPayload::Permit(_) if payload.scheme == Scheme::Upto => Some(format!(
+            "{}:{}:{}",
+            payload.network,
+            request.payment_requirements.asset,
+            payload.id()?
+        )),
+        _ => None,
+    }
+}
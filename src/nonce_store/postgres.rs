@@ -0,0 +1,122 @@
+//! [`PaymentNonceStore`] backed by PostgreSQL.
+
+use futures_util::future::BoxFuture;
+use tokio_postgres::{Client, NoTls};
+
+use super::{NonceStoreError, PaymentNonceStore};
+use crate::types::TokenAmount;
+
+/// Nonces persisted in the `x402_payment_nonces` table, and settled amounts in the
+/// `x402_payment_settled` table, shared by all facilitator instances connected to the same
+/// database. The tables are created on [`connect`](Self::connect).
+pub struct PostgresNonceStore {
+    client: Client,
+}
+
+impl PostgresNonceStore {
+    /// Connects to the database at `url`, e.g. `postgres://x402@localhost/x402`, without TLS.
+    pub async fn connect(url: &str) -> Result<Self, NonceStoreError> {
+        let (client, connection) = tokio_postgres::connect(url, NoTls)
+            .await
+            .map_err(backend_error)?;
+        tokio::spawn(async move {
+            if let Err(error) = connection.await {
+                tracing::error!(%error, "Nonce store connection failed");
+            }
+        });
+        client
+            .batch_execute(
+                "CREATE TABLE IF NOT EXISTS x402_payment_nonces (
+                    key TEXT PRIMARY KEY,
+                    reserved_at TIMESTAMPTZ NOT NULL DEFAULT now()
+                );
+                CREATE TABLE IF NOT EXISTS x402_payment_settled (
+                    key TEXT PRIMARY KEY,
+                    amount NUMERIC(78, 0) NOT NULL
+                )",
+            )
+            .await
+            .map_err(backend_error)?;
+        Ok(Self { client })
+    }
+}
+
+impl PaymentNonceStore for PostgresNonceStore {
+    fn contains<'a>(&'a self, key: &'a str) -> BoxFuture<'a, Result<bool, NonceStoreError>> {
+        Box::pin(async move {
+            let row = self
+                .client
+                .query_opt("SELECT 1 FROM x402_payment_nonces WHERE key = $1", &[&key])
+                .await
+                .map_err(backend_error)?;
+            Ok(row.is_some())
+        })
+    }
+
+    fn reserve<'a>(&'a self, key: &'a str) -> BoxFuture<'a, Result<bool, NonceStoreError>> {
+        Box::pin(async move {
+            let inserted = self
+                .client
+                .execute(
+                    "INSERT INTO x402_payment_nonces (key) VALUES ($1) ON CONFLICT DO NOTHING",
+                    &[&key],
+                )
+                .await
+                .map_err(backend_error)?;
+            Ok(inserted == 1)
+        })
+    }
+
+    fn release<'a>(&'a self, key: &'a str) -> BoxFuture<'a, Result<(), NonceStoreError>> {
+        Box::pin(async move {
+            self.client
+                .execute("DELETE FROM x402_payment_nonces WHERE key = $1", &[&key])
+                .await
+                .map_err(backend_error)?;
+            Ok(())
+        })
+    }
+
+    fn settled<'a>(&'a self, key: &'a str) -> BoxFuture<'a, Result<TokenAmount, NonceStoreError>> {
+        Box::pin(async move {
+            let row = self
+                .client
+                .query_opt(
+                    "SELECT amount::TEXT FROM x402_payment_settled WHERE key = $1",
+                    &[&key],
+                )
+                .await
+                .map_err(backend_error)?;
+            let Some(row) = row else {
+                return Ok(TokenAmount::from(0u64));
+            };
+            let amount: String = row.get(0);
+            amount
+                .parse()
+                .map(TokenAmount)
+                .map_err(|e| NonceStoreError::Backend(format!("Invalid settled amount: {e}")))
+        })
+    }
+
+    fn add_settled<'a>(
+        &'a self,
+        key: &'a str,
+        amount: TokenAmount,
+    ) -> BoxFuture<'a, Result<(), NonceStoreError>> {
+        Box::pin(async move {
+            self.client
+                .execute(
+                    "INSERT INTO x402_payment_settled (key, amount) VALUES ($1, $2::TEXT::NUMERIC)
+                    ON CONFLICT (key) DO UPDATE SET amount = x402_payment_settled.amount + EXCLUDED.amount",
+                    &[&key, &amount.to_string()],
+                )
+                .await
+                .map_err(backend_error)?;
+            Ok(())
+        })
+    }
+}
+
+fn backend_error(error: tokio_postgres::Error) -> NonceStoreError {
+    NonceStoreError::Backend(error.to_string())
+}
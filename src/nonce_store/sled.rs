@@ -0,0 +1,100 @@
+//! [`PaymentNonceStore`] backed by an embedded [sled](https://docs.rs/sled) database.
+
+use alloy::primitives::U256;
+use futures_util::future::BoxFuture;
+use std::path::Path;
+
+use super::{NonceStoreError, PaymentNonceStore};
+use crate::types::TokenAmount;
+
+/// Prefix of the keys of settled amounts, kept next to the nonces as 32-byte big-endian integers.
+const SETTLED_PREFIX: &str = "settled:";
+
+/// Nonces persisted in a sled database on local disk, surviving restarts of a single
+/// facilitator instance.
+#[derive(Debug, Clone)]
+pub struct SledNonceStore {
+    db: sled::Db,
+}
+
+impl SledNonceStore {
+    /// Opens (or creates) the database at `path`.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, NonceStoreError> {
+        let db = sled::open(path).map_err(|e| NonceStoreError::Backend(e.to_string()))?;
+        Ok(Self { db })
+    }
+}
+
+impl PaymentNonceStore for SledNonceStore {
+    fn contains<'a>(&'a self, key: &'a str) -> BoxFuture<'a, Result<bool, NonceStoreError>> {
+        Box::pin(async move {
+            self.db
+                .contains_key(key)
+                .map_err(|e| NonceStoreError::Backend(e.to_string()))
+        })
+    }
+
+    fn reserve<'a>(&'a self, key: &'a str) -> BoxFuture<'a, Result<bool, NonceStoreError>> {
+        Box::pin(async move {
+            let swapped = self
+                .db
+                .compare_and_swap(key, None as Option<&[u8]>, Some(&[] as &[u8]))
+                .map_err(|e| NonceStoreError::Backend(e.to_string()))?;
+            if swapped.is_err() {
+                return Ok(false);
+            }
+            // The reservation must be durable before the authorization is executed.
+            self.db
+                .flush_async()
+                .await
+                .map_err(|e| NonceStoreError::Backend(e.to_string()))?;
+            Ok(true)
+        })
+    }
+
+    fn release<'a>(&'a self, key: &'a str) -> BoxFuture<'a, Result<(), NonceStoreError>> {
+        Box::pin(async move {
+            self.db
+                .remove(key)
+                .map_err(|e| NonceStoreError::Backend(e.to_string()))?;
+            Ok(())
+        })
+    }
+
+    fn settled<'a>(&'a self, key: &'a str) -> BoxFuture<'a, Result<TokenAmount, NonceStoreError>> {
+        Box::pin(async move {
+            let settled = self
+                .db
+                .get(format!("{SETTLED_PREFIX}{key}"))
+                .map_err(|e| NonceStoreError::Backend(e.to_string()))?;
+            Ok(settled.map_or(TokenAmount::from(0u64), |bytes| {
+                TokenAmount(U256::from_be_slice(&bytes))
+            }))
+        })
+    }
+
+    fn add_settled<'a>(
+        &'a self,
+        key: &'a str,
+        amount: TokenAmount,
+    ) -> BoxFuture<'a, Result<(), NonceStoreError>> {
+        Box::pin(async move {
+            self.db
+                .update_and_fetch(format!("{SETTLED_PREFIX}{key}"), |settled| {
+                    let settled = settled.map_or(U256::ZERO, U256::from_be_slice);
+                    Some(
+                        settled
+                            .saturating_add(amount.0)
+                            .to_be_bytes::<32>()
+                            .to_vec(),
+                    )
+                })
+                .map_err(|e| NonceStoreError::Backend(e.to_string()))?;
+            self.db
+                .flush_async()
+                .await
+                .map_err(|e| NonceStoreError::Backend(e.to_string()))?;
+            Ok(())
+        })
+    }
+}
@@ -0,0 +1,72 @@
+//! Correlation of the verification and settlement traces of a payment.
+//!
+//! A payment is usually verified once, then settled in a separate request, possibly minutes later
+//! and over another transport (HTTP or WebSocket). Both spans carry the payment id as the
+//! `x402.payment_id` attribute, see [`PaymentPayload::id`](crate::types::PaymentPayload::id).
+//! On top of that, [`PaymentSpans`] remembers the span context of the last verification of every
+//! payment for a while, and the settlement span links to it, so trace viewers show the full
+//! payment lifecycle rather than two disjoint operations.
+//!
+//! Links only span a single facilitator instance; the shared attribute correlates the spans
+//! of a payment verified and settled by different instances.
+
+use opentelemetry::KeyValue;
+use opentelemetry::trace::{SpanContext, TraceContextExt};
+use std::time::Duration;
+use tracing::Span;
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+use crate::lru_cache::LruCache;
+
+/// Default time during which a settlement links to the verification of the same payment.
+const DEFAULT_LINK_TTL: Duration = Duration::from_secs(900);
+/// Verifications remembered, least recently used forgotten first.
+const CAPACITY: usize = 100_000;
+
+/// Span contexts of recent verifications, keyed by payment id.
+#[derive(Clone)]
+pub struct PaymentSpans {
+    verifications: LruCache<String, SpanContext>,
+}
+
+impl Default for PaymentSpans {
+    fn default() -> Self {
+        Self {
+            verifications: LruCache::new("payment_spans", CAPACITY).with_ttl(DEFAULT_LINK_TTL),
+        }
+    }
+}
+
+impl PaymentSpans {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the time during which a settlement links to the verification of the same payment.
+    #[allow(dead_code)] // Public for consumption by downstream crates.
+    pub fn with_ttl(&self, ttl: Duration) -> Self {
+        Self {
+            verifications: LruCache::new("payment_spans", CAPACITY).with_ttl(ttl),
+        }
+    }
+
+    /// Records the current span as the verification of `payment_id`.
+    pub fn verified(&self, payment_id: &str) {
+        let span = Span::current();
+        span.record("x402.payment_id", payment_id);
+        let span_context = span.context().span().span_context().clone();
+        if span_context.is_valid() {
+            self.verifications
+                .insert(payment_id.to_string(), span_context);
+        }
+    }
+
+    /// Links the current span, settling `payment_id`, to the last verification of the payment.
+    pub fn settling(&self, payment_id: &str) {
+        let span = Span::current();
+        span.record("x402.payment_id", payment_id);
+        if let Some(span_context) = self.verifications.get(&payment_id.to_string()) {
+            span.add_link_with_attributes(span_context, vec![KeyValue::new("x402.link", "verify")]);
+        }
+    }
+}
@@ -0,0 +1,252 @@
+//! Push notifications of inbound payments (`webhooks` feature).
+//!
+//! Sellers accepting proof-of-transaction payments otherwise have to poll the chain to learn
+//! that a buyer paid. A [`PaymentWatcher`] watches configured recipient addresses instead: it
+//! polls the USDC `Transfer` logs of every configured EVM network, and reports each transfer
+//! to a watched address
+//! - as a JSON `POST` of the [`TokenTransfer`] to every configured webhook URL,
+//! - to WebSocket clients subscribed with `x402.watchPayments`.
+//!
+//! Transfers settling a payment on this facilitator carry the metadata of the settle request,
+//! see [`PaymentWatcher::annotate`].
+//!
+//! Watching starts at the chain head when the facilitator starts; earlier transfers are not
+//! reported. Delivery is best effort: a webhook is retried a few times with backoff, then the
+//! notification is dropped.
+//!
+//! Environment (see [`PaymentWatcher::from_env`]):
+//! - `WATCH_PAY_TO` – Comma-separated EVM addresses to watch; watching is disabled if unset
+//! - `WATCH_WEBHOOK_URLS` – Comma-separated URLs notified of every inbound payment
+//! - `WATCH_POLL_INTERVAL_SECS` – Interval between two polls of a network, in seconds or with a
+//!   unit (e.g. `1m`), from 1 second to 1 hour (default `15`)
+
+use std::env;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::broadcast;
+use url::Url;
+
+use crate::chain::NetworkProvider;
+use crate::chain::evm::{EvmProvider, TokenTransfer};
+use crate::duration::{self, DurationError, DurationRange};
+use crate::lru_cache::LruCache;
+use crate::network::USDCDeployment;
+use crate::provider_cache::ProviderCache;
+use crate::types::{EvmAddress, TransactionHash};
+
+const ENV_WATCH_PAY_TO: &str = "WATCH_PAY_TO";
+const ENV_WATCH_WEBHOOK_URLS: &str = "WATCH_WEBHOOK_URLS";
+const ENV_WATCH_POLL_INTERVAL_SECS: &str = "WATCH_POLL_INTERVAL_SECS";
+
+/// Poll intervals accepted from `WATCH_POLL_INTERVAL_SECS`.
+const POLL_INTERVAL_RANGE: DurationRange =
+    DurationRange::new(Duration::from_secs(1), Duration::from_secs(3600));
+/// Largest block range requested in a single `eth_getLogs` call.
+const MAX_BLOCK_RANGE: u64 = 1000;
+/// Delivery attempts per webhook and notification.
+const WEBHOOK_ATTEMPTS: u32 = 3;
+/// Notifications buffered per WebSocket subscriber before the oldest are dropped.
+const SUBSCRIBER_CAPACITY: usize = 1024;
+/// Settlement transactions remembered with their metadata, least recently used forgotten first.
+const ANNOTATION_CAPACITY: usize = 10_000;
+
+#[derive(Debug, thiserror::Error)]
+pub enum PaymentWatcherConfigError {
+    #[error("Invalid address in {ENV_WATCH_PAY_TO}: {0}")]
+    InvalidAddress(String),
+    #[error("Invalid URL in {ENV_WATCH_WEBHOOK_URLS}: {0}")]
+    InvalidUrl(String),
+    #[error(transparent)]
+    PollInterval(#[from] DurationError),
+}
+
+/// Watches recipient addresses on-chain and notifies sellers of inbound payments.
+#[derive(Clone)]
+pub struct PaymentWatcher {
+    addresses: Arc<[EvmAddress]>,
+    webhooks: Arc<[Url]>,
+    poll_interval: Duration,
+    sender: broadcast::Sender<TokenTransfer>,
+    client: reqwest::Client,
+    /// Metadata of recent settlement transactions.
+    annotations: LruCache<TransactionHash, serde_json::Value>,
+}
+
+impl PaymentWatcher {
+    /// Watches `addresses`, with no webhooks.
+    pub fn new<I: IntoIterator<Item = EvmAddress>>(addresses: I) -> Self {
+        let (sender, _) = broadcast::channel(SUBSCRIBER_CAPACITY);
+        Self {
+            addresses: addresses.into_iter().collect(),
+            webhooks: Arc::new([]),
+            poll_interval: Duration::from_secs(15),
+            sender,
+            client: reqwest::Client::new(),
+            annotations: LruCache::new("payment_annotations", ANNOTATION_CAPACITY),
+        }
+    }
+
+    /// Reads the configuration from `WATCH_PAY_TO`, `WATCH_WEBHOOK_URLS` and
+    /// `WATCH_POLL_INTERVAL_SECS`. Returns `None` if no address is configured.
+    pub fn from_env() -> Result<Option<Self>, PaymentWatcherConfigError> {
+        let Ok(addresses) = env::var(ENV_WATCH_PAY_TO) else {
+            return Ok(None);
+        };
+        let addresses = split_list(&addresses)
+            .map(|s| {
+                s.parse::<EvmAddress>()
+                    .map_err(|_| PaymentWatcherConfigError::InvalidAddress(s.to_string()))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        if addresses.is_empty() {
+            return Ok(None);
+        }
+        let mut watcher = Self::new(addresses);
+        if let Ok(urls) = env::var(ENV_WATCH_WEBHOOK_URLS) {
+            for url in split_list(&urls) {
+                let url = Url::parse(url)
+                    .map_err(|_| PaymentWatcherConfigError::InvalidUrl(url.to_string()))?;
+                watcher = watcher.with_webhook(url);
+            }
+        }
+        if let Some(interval) = duration::from_env(
+            ENV_WATCH_POLL_INTERVAL_SECS,
+            duration::SECOND,
+            POLL_INTERVAL_RANGE,
+        )? {
+            watcher = watcher.with_poll_interval(interval);
+        }
+        Ok(Some(watcher))
+    }
+
+    /// Adds a URL to `POST` every inbound payment to.
+    pub fn with_webhook(&self, url: Url) -> Self {
+        let mut this = self.clone();
+        this.webhooks = self.webhooks.iter().cloned().chain([url]).collect();
+        this
+    }
+
+    /// Sets the interval between two polls of a network. Defaults to 15 seconds.
+    pub fn with_poll_interval(&self, poll_interval: Duration) -> Self {
+        let mut this = self.clone();
+        this.poll_interval = poll_interval.max(Duration::from_secs(1));
+        this
+    }
+
+    /// Watched recipient addresses.
+    pub fn addresses(&self) -> &[EvmAddress] {
+        &self.addresses
+    }
+
+    /// Attaches `metadata` to the transfers of settlement `transaction` reported from now on.
+    pub fn annotate(&self, transaction: TransactionHash, metadata: serde_json::Value) {
+        self.annotations.insert(transaction, metadata);
+    }
+
+    /// Receives every inbound payment reported from now on.
+    pub fn subscribe(&self) -> broadcast::Receiver<TokenTransfer> {
+        self.sender.subscribe()
+    }
+
+    /// Starts watching every EVM network of `provider_cache`, in background tasks.
+    pub fn start(&self, provider_cache: &ProviderCache) {
+        for (network, provider) in provider_cache {
+            let NetworkProvider::Evm(provider) = provider else {
+                continue;
+            };
+            let usdc = USDCDeployment::by_network(network).address();
+            let Ok(token) = alloy::primitives::Address::try_from(usdc).map(EvmAddress::from) else {
+                continue;
+            };
+            tracing::info!(%network, addresses = self.addresses.len(), "Watching inbound payments");
+            let watcher = self.clone();
+            let provider = provider.clone();
+            tokio::spawn(async move { watcher.watch(provider, token).await });
+        }
+    }
+
+    /// Polls `token` transfers to the watched addresses on the network of `provider`, forever.
+    async fn watch(self, provider: EvmProvider, token: EvmAddress) {
+        let mut next_block: Option<u64> = None;
+        let mut interval = tokio::time::interval(self.poll_interval);
+        interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        loop {
+            interval.tick().await;
+            let latest = match provider.block_number().await {
+                Ok(latest) => latest,
+                Err(error) => {
+                    tracing::warn!(?error, "Failed to fetch the latest block");
+                    continue;
+                }
+            };
+            let from_block = next_block.unwrap_or(latest);
+            if from_block > latest {
+                continue;
+            }
+            let to_block = latest.min(from_block + MAX_BLOCK_RANGE - 1);
+            match provider
+                .transfers_to(token, &self.addresses, from_block, to_block)
+                .await
+            {
+                Ok(transfers) => {
+                    for transfer in transfers {
+                        self.notify(transfer);
+                    }
+                    next_block = Some(to_block + 1);
+                }
+                Err(error) => {
+                    tracing::warn!(?error, from_block, to_block, "Failed to fetch transfers");
+                }
+            }
+        }
+    }
+
+    /// Reports `transfer` to WebSocket subscribers and webhooks.
+    fn notify(&self, mut transfer: TokenTransfer) {
+        transfer.metadata = self.annotations.get(&transfer.transaction);
+        tracing::info!(
+            network = %transfer.network,
+            to = %transfer.to,
+            value = %transfer.value,
+            transaction = %transfer.transaction,
+            "Inbound payment"
+        );
+        for url in self.webhooks.iter() {
+            let client = self.client.clone();
+            let url = url.clone();
+            let transfer = transfer.clone();
+            tokio::spawn(async move { deliver_webhook(client, url, transfer).await });
+        }
+        // No subscriber is not an error.
+        let _ = self.sender.send(transfer);
+    }
+}
+
+/// Posts `transfer` to `url`, retrying with exponential backoff.
+async fn deliver_webhook(client: reqwest::Client, url: Url, transfer: TokenTransfer) {
+    let mut backoff = Duration::from_secs(1);
+    for attempt in 1..=WEBHOOK_ATTEMPTS {
+        let result = client
+            .post(url.clone())
+            .timeout(Duration::from_secs(10))
+            .json(&transfer)
+            .send()
+            .await
+            .and_then(|response| response.error_for_status());
+        match result {
+            Ok(_) => return,
+            Err(error) if attempt < WEBHOOK_ATTEMPTS => {
+                tracing::debug!(%url, attempt, %error, "Webhook delivery failed, retrying");
+                tokio::time::sleep(backoff).await;
+                backoff *= 2;
+            }
+            Err(error) => {
+                tracing::warn!(%url, %error, transaction = %transfer.transaction, "Webhook delivery failed");
+            }
+        }
+    }
+}
+
+fn split_list(list: &str) -> impl Iterator<Item = &str> {
+    list.split(',').map(str::trim).filter(|s| !s.is_empty())
+}
@@ -5,37 +5,64 @@
 //!
 //! This enables interaction with multiple Ethereum-compatible networks using Alloy's `ProviderBuilder`.
 //!
-//! Supported signer type: `private-key`.
+//! Supported signer types: `private-key` and `mnemonic`, and with the `kms` feature `aws-kms`,
+//! `gcp-kms` and `remote`, keeping keys out of the environment, see [`kms`](crate::chain::kms).
 //!
 //! Environment variables used:
-//! - `SIGNER_TYPE` — currently only `"private-key"` is supported,
-//! - `PRIVATE_KEY` — the private key used to sign transactions as `"0x..."` string,
-//! - `RPC_URL_BASE`, `RPC_URL_BASE_SEPOLIA` — RPC endpoints per network
-//! - `RPC_WS_URL_POLYGON_AMOY` — WebSocket RPC endpoint for Polygon Amoy (preferred if set)
+//! - `SIGNER_TYPE` — `"private-key"`, `"mnemonic"`, `"aws-kms"`, `"gcp-kms"` or `"remote"`,
+//! - `PRIVATE_KEY` — the private key used to sign transactions as `"0x..."` string, or comma-separated
+//!   keys of several settlement accounts, see [`signer_pool`](crate::chain::signer_pool),
+//! - `EVM_MNEMONIC`, `EVM_SIGNER_COUNT` — the mnemonic settlement accounts are derived from, and their number,
+//! - `RPC_URL_BASE`, `RPC_URL_BASE_SEPOLIA` — RPC endpoints per network, several comma-separated
+//!   ones failing over to each other, see [`rpc_failover`](crate::chain::rpc_failover)
+//! - `RPC_WS_URL_POLYGON_AMOY` — WebSocket RPC endpoints for Polygon Amoy (preferred if set)
+//! - `RPC_HEALTH_CHECK_INTERVAL_SECS`, `RPC_FAILOVER_COOLDOWN_SECS` — health checks of the RPC
+//!   endpoints of networks with several of them
+//! - `GAS_*` — fees of EVM settlement transactions, see [`gas`](crate::chain::gas)
+//! - `TEST_NETWORK` — `true` to serve the sandboxed `test` network, see [`sandbox`](crate::chain::sandbox)
 //!
 //! Example usage:
-//! ```rust
+//! ```rust,no_run
+//! use x402_rs::network::Network;
+//! use x402_rs::provider_cache::{ProviderCache, ProviderMap};
+//!
+//! # async fn example() -> Result<(), Box<dyn std::error::Error>> {
 //! let provider_cache = ProviderCache::from_env().await?;
-//! let provider = provider_cache.by_network(Network::Base)?;
+//! let provider = provider_cache.by_network(Network::Base);
+//! # Ok(())
+//! # }
 //! ```
 
 use alloy::network::EthereumWallet;
 use alloy::providers::ProviderBuilder;
+use alloy::rpc::client::ClientBuilder;
 use alloy::signers::local::PrivateKeySigner;
 use serde::{Deserialize, Serialize};
 use solana_sdk::signature::Keypair;
 use std::borrow::Borrow;
 use std::collections::HashMap;
 use std::env;
+use std::sync::Arc;
+use std::time::Duration;
 // removed explicit WsConnect usage; generic `.connect(&str)` handles ws/http based on scheme
 
 use crate::chain::evm::EvmProvider;
+use crate::chain::gas::GasPolicies;
+#[cfg(feature = "kms")]
+use crate::chain::kms;
+use crate::chain::multicall::SettlementBatching;
+use crate::chain::rpc_failover::{self, FailoverSender, FailoverTransport, RpcFailover};
+use crate::chain::sandbox::SandboxProvider;
+use crate::chain::settlement_signer::{SettlementSigner, SettlementTxSigner};
+use crate::chain::signer_pool::{self, SignerPoolError};
 use crate::chain::solana::SolanaProvider;
-use crate::chain::{NetworkProvider, NetworkProviderOps};
+use crate::chain::{ChainProvider, NetworkProvider, NetworkProviderOps};
 use crate::network::{Network, NetworkFamily};
 
 const ENV_SIGNER_TYPE: &str = "SIGNER_TYPE";
 const ENV_EVM_PRIVATE_KEY: &str = "EVM_PRIVATE_KEY";
+const ENV_EVM_MNEMONIC: &str = "EVM_MNEMONIC";
+const ENV_EVM_SIGNER_COUNT: &str = "EVM_SIGNER_COUNT";
 const ENV_SOLANA_PRIVATE_KEY: &str = "SOLANA_PRIVATE_KEY";
 const ENV_RPC_BASE: &str = "RPC_URL_BASE";
 const ENV_RPC_BASE_SEPOLIA: &str = "RPC_URL_BASE_SEPOLIA";
@@ -49,6 +76,7 @@ const ENV_RPC_WS_POLYGON_AMOY: &str = "RPC_WS_URL_POLYGON_AMOY";
 const ENV_RPC_POLYGON: &str = "RPC_URL_POLYGON";
 const ENV_RPC_SEI: &str = "RPC_URL_SEI";
 const ENV_RPC_SEI_TESTNET: &str = "RPC_URL_SEI_TESTNET";
+const ENV_TEST_NETWORK: &str = "TEST_NETWORK";
 
 /// A cache of pre-initialized [`EthereumProvider`] instances keyed by network.
 ///
@@ -56,9 +84,11 @@ const ENV_RPC_SEI_TESTNET: &str = "RPC_URL_SEI_TESTNET";
 /// and wrapping them with appropriate signing and filler middleware.
 ///
 /// Use [`ProviderCache::from_env`] to load credentials and connect using environment variables.
-#[derive(Clone)]
+#[derive(Clone, Default)]
 pub struct ProviderCache {
     providers: HashMap<Network, NetworkProvider>,
+    /// Primary EVM settlement account, signing facilitator attestations such as receipts.
+    evm_signer: Option<Arc<dyn SettlementSigner>>,
 }
 
 /// A generic cache of pre-initialized Ethereum provider instances [`ProviderMap::Value`] keyed by network.
@@ -85,90 +115,104 @@ impl ProviderCache {
     /// Constructs a new [`ProviderCache`] from environment variables.
     ///
     /// Expects the following to be set:
-    /// - `SIGNER_TYPE` — currently only `"private-key"` is supported
-    /// - `PRIVATE_KEY` — the private key used to sign transactions
-    /// - `RPC_URL_BASE`, `RPC_URL_BASE_SEPOLIA` — RPC endpoints per network
+    /// - `SIGNER_TYPE` — `"private-key"`, `"mnemonic"`, `"aws-kms"`, `"gcp-kms"` or `"remote"`
+    /// - `PRIVATE_KEY` — the private key used to sign transactions, or `EVM_MNEMONIC`, or the
+    ///   keys of the KMS
+    /// - `RPC_URL_BASE`, `RPC_URL_BASE_SEPOLIA` — RPC endpoints per network, comma-separated
+    ///
+    /// The sandboxed `test` network is served only if `TEST_NETWORK` is `true`.
     ///
-    /// Fails if required env vars are missing or if the provider cannot connect.
+    /// Fails if required env vars are missing, if the gas policies are invalid, or if the
+    /// provider cannot connect. A network with several RPC endpoints fails if none connects.
     pub async fn from_env() -> Result<Self, Box<dyn std::error::Error>> {
+        let gas_policies = GasPolicies::from_env()?;
+        let rpc_failover = RpcFailover::from_env()?;
         let mut providers = HashMap::new();
+        // Built once, on the first EVM network: reading KMS keys takes requests
+        let mut evm_wallet: Option<EthereumWallet> = None;
+        let mut evm_signer: Option<Arc<dyn SettlementSigner>> = None;
         for network in Network::variants() {
-            let env_var = match network {
-                Network::BaseSepolia => ENV_RPC_BASE_SEPOLIA,
-                Network::Base => ENV_RPC_BASE,
-                Network::XdcMainnet => ENV_RPC_XDC,
-                Network::AvalancheFuji => ENV_RPC_AVALANCHE_FUJI,
-                Network::Avalanche => ENV_RPC_AVALANCHE,
-                Network::Solana => ENV_RPC_SOLANA,
-                Network::SolanaDevnet => ENV_RPC_SOLANA_DEVNET,
-                Network::PolygonAmoy => ENV_RPC_POLYGON_AMOY,
-                Network::Polygon => ENV_RPC_POLYGON,
-                Network::Sei => ENV_RPC_SEI,
-                Network::SeiTestnet => ENV_RPC_SEI_TESTNET,
-            };
-            let is_eip1559 = match network {
-                Network::BaseSepolia => true,
-                Network::Base => true,
-                Network::XdcMainnet => false,
-                Network::AvalancheFuji => true,
-                Network::Avalanche => true,
-                Network::Solana => false,
-                Network::SolanaDevnet => false,
-                Network::PolygonAmoy => true,
-                Network::Polygon => true,
-                Network::Sei => true,
-                Network::SeiTestnet => true,
+            // The sandboxed test network is the only one without an RPC endpoint
+            let Some(env_var) = rpc_env_var(*network) else {
+                if is_test_network_enabled() {
+                    let provider = NetworkProvider::Custom(Arc::new(SandboxProvider::new()));
+                    providers.insert(*network, provider);
+                    tracing::warn!(
+                        "Serving the test network: its payments are never settled on-chain"
+                    );
+                }
+                continue;
             };
+            let is_eip1559 = is_eip1559(*network);
 
-            // Prefer WebSocket transport for Polygon Amoy if `RPC_WS_URL_POLYGON_AMOY` is set (and non-empty).
-            // Fall back to HTTP `RPC_URL_POLYGON_AMOY` when WS is unset/empty.
-            let rpc_url: Option<String> = if let Network::PolygonAmoy = *network {
-                env::var(ENV_RPC_WS_POLYGON_AMOY)
-                    .ok()
-                    .filter(|s| !s.is_empty())
-                    .or_else(|| env::var(ENV_RPC_POLYGON_AMOY).ok())
-            } else {
-                env::var(env_var).ok()
-            };
-            if let Some(rpc_url) = rpc_url {
+            let rpc_urls = rpc_urls(*network, env_var);
+            if let [rpc_url, ..] = rpc_urls.as_slice() {
                 let family: NetworkFamily = (*network).into();
                 match family {
                     NetworkFamily::Evm => {
-                        let wallet = SignerType::from_env()?.make_evm_wallet()?;
-                        let provider = if rpc_url.starts_with("ws://") || rpc_url.starts_with("wss://") {
-                            ProviderBuilder::new()
-                                .wallet(wallet)
-                                .connect(&rpc_url)
-                                .await
-                                .map_err(|e| format!("Failed to connect to {network} via WS: {e}"))?
+                        let wallet = match &evm_wallet {
+                            Some(wallet) => wallet,
+                            None => {
+                                let signers = SignerType::from_env()?.make_evm_signers().await?;
+                                evm_signer = signers.first().cloned();
+                                evm_wallet.insert(evm_wallet(signers)?)
+                            }
+                        }
+                        .clone();
+                        let transport =
+                            if rpc_url.starts_with("ws://") || rpc_url.starts_with("wss://") {
+                                "WS"
+                            } else {
+                                "HTTP"
+                            };
+                        let client = ClientBuilder::default();
+                        #[cfg(feature = "replay")]
+                        let client = client.layer(crate::replay::RecordingLayer::new(*network));
+                        let client = if rpc_urls.len() == 1 {
+                            client.connect(rpc_url).await.map_err(|e| {
+                                format!("Failed to connect to {network} via {transport}: {e}")
+                            })?
                         } else {
-                            ProviderBuilder::new()
-                                .wallet(wallet)
-                                .connect(&rpc_url)
-                                .await
-                                .map_err(|e| format!("Failed to connect to {network} via HTTP: {e}"))?
+                            let failover =
+                                FailoverTransport::connect(*network, &rpc_urls, rpc_failover)
+                                    .await
+                                    .map_err(|e| format!("Failed to connect to {network}: {e}"))?;
+                            let is_local = failover.is_local();
+                            client.transport(failover, is_local)
                         };
-                        let provider = EvmProvider::try_new(provider, is_eip1559, *network)?;
+                        let provider = ProviderBuilder::new().wallet(wallet).connect_client(client);
+                        let provider = EvmProvider::try_new(provider, is_eip1559, *network)?
+                            .with_gas_policy(gas_policies.for_network(*network));
                         let provider = NetworkProvider::Evm(provider);
                         let signer_address = provider.signer_address();
+                        let signers = match &provider {
+                            NetworkProvider::Evm(provider) => provider.signers().addresses().len(),
+                            _ => 1,
+                        };
                         providers.insert(*network, provider);
                         tracing::info!(
+                            signers,
                             "Initialized provider for {} at {} using {}",
                             network,
-                            rpc_url,
+                            rpc_urls.join(", "),
                             signer_address
                         );
                     }
                     NetworkFamily::Solana => {
                         let keypair = SignerType::from_env()?.make_solana_wallet()?;
-                        let provider = SolanaProvider::try_new(keypair, rpc_url.clone(), *network)?;
+                        let provider = if rpc_urls.len() == 1 {
+                            SolanaProvider::try_new(keypair, rpc_url.clone(), *network)?
+                        } else {
+                            let sender = FailoverSender::new(*network, &rpc_urls, rpc_failover);
+                            SolanaProvider::try_with_sender(keypair, sender, *network)?
+                        };
                         let provider = NetworkProvider::Solana(provider);
                         let signer_address = provider.signer_address();
                         providers.insert(*network, provider);
                         tracing::info!(
                             "Initialized provider for {} at {} using {}",
                             network,
-                            rpc_url,
+                            rpc_urls.join(", "),
                             signer_address
                         );
                     }
@@ -178,10 +222,130 @@ impl ProviderCache {
             }
         }
 
-        Ok(Self { providers })
+        Ok(Self {
+            providers,
+            evm_signer,
+        })
+    }
+
+    /// Settles the payments of every EVM network in Multicall3 batches, see
+    /// [`multicall`](crate::chain::multicall). Must be called from within a Tokio runtime.
+    pub fn with_settlement_batching(&self, batching: SettlementBatching) -> Self {
+        let providers = self
+            .providers
+            .iter()
+            .map(|(network, provider)| {
+                let provider = match provider {
+                    NetworkProvider::Evm(provider) => {
+                        NetworkProvider::Evm(provider.with_settlement_batching(batching))
+                    }
+                    provider => provider.clone(),
+                };
+                (*network, provider)
+            })
+            .collect();
+        Self {
+            providers,
+            evm_signer: self.evm_signer.clone(),
+        }
+    }
+
+    /// Primary EVM settlement account, `None` without EVM networks.
+    pub fn evm_signer(&self) -> Option<&Arc<dyn SettlementSigner>> {
+        self.evm_signer.as_ref()
+    }
+
+    /// Checks the nonce of the settlement account of every EVM network against the chain every
+    /// `interval`, see [`account_nonce`](crate::chain::account_nonce). Must be called from
+    /// within a Tokio runtime.
+    pub fn start_nonce_sync(&self, interval: Duration) {
+        for provider in self.providers.values() {
+            if let NetworkProvider::Evm(provider) = provider {
+                provider.start_nonce_sync(interval);
+            }
+        }
+    }
+
+    /// Adds `provider`, replacing any provider configured for its network.
+    #[cfg(feature = "replay")]
+    pub(crate) fn with_provider(&self, provider: NetworkProvider) -> Self {
+        let mut this = self.clone();
+        this.providers.insert(provider.network(), provider);
+        this
+    }
+
+    /// Verifies and settles payments on the network of `provider` with it, replacing any
+    /// provider configured for that network.
+    pub fn with_chain_provider<P: ChainProvider + 'static>(&self, provider: P) -> Self {
+        let mut this = self.clone();
+        this.providers.insert(
+            provider.network(),
+            NetworkProvider::Custom(Arc::new(provider)),
+        );
+        this
     }
 }
 
+/// Variable of the RPC endpoint of `network`, `None` for the sandboxed `test` network.
+pub(crate) fn rpc_env_var(network: Network) -> Option<&'static str> {
+    match network {
+        Network::BaseSepolia => Some(ENV_RPC_BASE_SEPOLIA),
+        Network::Base => Some(ENV_RPC_BASE),
+        Network::XdcMainnet => Some(ENV_RPC_XDC),
+        Network::AvalancheFuji => Some(ENV_RPC_AVALANCHE_FUJI),
+        Network::Avalanche => Some(ENV_RPC_AVALANCHE),
+        Network::Solana => Some(ENV_RPC_SOLANA),
+        Network::SolanaDevnet => Some(ENV_RPC_SOLANA_DEVNET),
+        Network::PolygonAmoy => Some(ENV_RPC_POLYGON_AMOY),
+        Network::Polygon => Some(ENV_RPC_POLYGON),
+        Network::Sei => Some(ENV_RPC_SEI),
+        Network::SeiTestnet => Some(ENV_RPC_SEI_TESTNET),
+        Network::Test => None,
+    }
+}
+
+/// Variable of the WebSocket RPC endpoint of `network`, preferred to its HTTP endpoint if set.
+pub(crate) fn rpc_ws_env_var(network: Network) -> Option<&'static str> {
+    match network {
+        Network::PolygonAmoy => Some(ENV_RPC_WS_POLYGON_AMOY),
+        _ => None,
+    }
+}
+
+/// RPC endpoints of `network`, comma-separated in `env_var`: its WebSocket endpoints first, as
+/// they are preferred, then its HTTP ones.
+fn rpc_urls(network: Network, env_var: &str) -> Vec<String> {
+    rpc_ws_env_var(network)
+        .into_iter()
+        .chain([env_var])
+        .filter_map(|var| env::var(var).ok())
+        .flat_map(|urls| rpc_failover::parse_urls(&urls))
+        .collect()
+}
+
+/// Whether `network` supports EIP-1559 fee mechanics.
+pub(crate) fn is_eip1559(network: Network) -> bool {
+    match network {
+        Network::BaseSepolia => true,
+        Network::Base => true,
+        Network::XdcMainnet => false,
+        Network::AvalancheFuji => true,
+        Network::Avalanche => true,
+        Network::Solana => false,
+        Network::SolanaDevnet => false,
+        Network::PolygonAmoy => true,
+        Network::Polygon => true,
+        Network::Sei => true,
+        Network::SeiTestnet => true,
+        Network::Test => true,
+    }
+}
+
+/// Whether `TEST_NETWORK` enables the sandboxed `test` network.
+fn is_test_network_enabled() -> bool {
+    env::var(ENV_TEST_NETWORK).is_ok_and(|value| matches!(value.trim(), "true" | "1"))
+}
+
 impl ProviderMap for ProviderCache {
     type Value = NetworkProvider;
     fn by_network<N: Borrow<Network>>(&self, network: N) -> Option<&NetworkProvider> {
@@ -192,41 +356,102 @@ impl ProviderMap for ProviderCache {
 /// Supported methods for constructing an Ethereum wallet from environment variables.
 #[derive(Debug, Hash, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum SignerType {
-    /// A local private key stored in the `PRIVATE_KEY` environment variable.
+    /// A local private key stored in the `PRIVATE_KEY` environment variable, or several
+    /// comma-separated ones.
     #[serde(rename = "private-key")]
     PrivateKey,
+    /// Local private keys derived from the BIP-39 mnemonic in the `EVM_MNEMONIC` environment
+    /// variable, `EVM_SIGNER_COUNT` of them. Solana keys are still read from `SOLANA_PRIVATE_KEY`.
+    #[serde(rename = "mnemonic")]
+    Mnemonic,
+    /// Keys of AWS KMS, in `AWS_KMS_KEY_ID` (`kms` feature).
+    #[serde(rename = "aws-kms")]
+    AwsKms,
+    /// Key versions of Google Cloud KMS, in `GCP_KMS_KEY` (`kms` feature).
+    #[serde(rename = "gcp-kms")]
+    GcpKms,
+    /// Accounts of the JSON-RPC remote signer at `REMOTE_SIGNER_URL` (`kms` feature).
+    #[serde(rename = "remote")]
+    Remote,
 }
 
 impl SignerType {
     /// Parse the signer type from the `SIGNER_TYPE` environment variable.
-    fn from_env() -> Result<Self, Box<dyn std::error::Error>> {
+    pub(crate) fn from_env() -> Result<Self, Box<dyn std::error::Error>> {
         let signer_type_string =
             env::var(ENV_SIGNER_TYPE).map_err(|_| format!("env {ENV_SIGNER_TYPE} not set"))?;
         match signer_type_string.as_str() {
             "private-key" => Ok(SignerType::PrivateKey),
+            "mnemonic" => Ok(SignerType::Mnemonic),
+            "aws-kms" => Ok(SignerType::AwsKms),
+            "gcp-kms" => Ok(SignerType::GcpKms),
+            "remote" => Ok(SignerType::Remote),
             _ => Err(format!("Unknown signer type {signer_type_string}").into()),
         }
     }
 
     /// Constructs an [`EthereumWallet`] based on the [`SignerType`] selected from environment.
     ///
-    /// Currently only supports [`SignerType::PrivateKey`] variant, based on the following environment variables:
-    /// - `SIGNER_TYPE` — currently only `"private-key"` is supported
-    /// - `PRIVATE_KEY` — the private key used to sign transactions
-    pub fn make_evm_wallet(&self) -> Result<EthereumWallet, Box<dyn std::error::Error>> {
-        match self {
+    /// The wallet holds every settlement account, the first one being its default signer, see
+    /// [`signer_pool`](crate::chain::signer_pool). Transactions are signed through
+    /// [`SettlementSigner`]s. Based on the following environment variables:
+    /// - `SIGNER_TYPE` — `"private-key"`, `"mnemonic"`, `"aws-kms"`, `"gcp-kms"` or `"remote"`
+    /// - `PRIVATE_KEY` — the private key used to sign transactions, or comma-separated keys
+    /// - `EVM_MNEMONIC`, `EVM_SIGNER_COUNT` — the mnemonic keys are derived from, and their number
+    /// - `AWS_KMS_KEY_ID`, `GCP_KMS_KEY`, `REMOTE_SIGNER_URL` — see [`kms`](crate::chain::kms)
+    pub async fn make_evm_wallet(&self) -> Result<EthereumWallet, Box<dyn std::error::Error>> {
+        evm_wallet(self.make_evm_signers().await?)
+    }
+
+    /// Constructs the [`SettlementSigner`]s of the settlement accounts, the primary one first,
+    /// based on the environment variables of [`SignerType::make_evm_wallet`].
+    pub async fn make_evm_signers(
+        &self,
+    ) -> Result<Vec<Arc<dyn SettlementSigner>>, Box<dyn std::error::Error>> {
+        let signers: Vec<Arc<dyn SettlementSigner>> = match self {
             SignerType::PrivateKey => {
-                let private_key = env::var(ENV_EVM_PRIVATE_KEY)
+                let private_keys = env::var(ENV_EVM_PRIVATE_KEY)
                     .map_err(|_| format!("env {ENV_EVM_PRIVATE_KEY} not set"))?;
-                let pk_signer: PrivateKeySigner = private_key.parse()?;
-                Ok(EthereumWallet::new(pk_signer))
+                local_signers(signer_pool::private_key_signers(&private_keys)?)
+            }
+            SignerType::Mnemonic => {
+                let mnemonic = env::var(ENV_EVM_MNEMONIC)
+                    .map_err(|_| format!("env {ENV_EVM_MNEMONIC} not set"))?;
+                let count = match env::var(ENV_EVM_SIGNER_COUNT) {
+                    Ok(count) => count
+                        .trim()
+                        .parse()
+                        .ok()
+                        .filter(|count| (1..=1000).contains(count))
+                        .ok_or(SignerPoolError::Count(count))?,
+                    Err(_) => 1,
+                };
+                local_signers(signer_pool::mnemonic_signers(&mnemonic, count)?)
             }
+            #[cfg(feature = "kms")]
+            SignerType::AwsKms => remote_signers(kms::AwsKmsSigner::from_env().await?),
+            #[cfg(feature = "kms")]
+            SignerType::GcpKms => remote_signers(kms::GcpKmsSigner::from_env().await?),
+            #[cfg(feature = "kms")]
+            SignerType::Remote => remote_signers(kms::RemoteSigner::from_env().await?),
+            #[cfg(not(feature = "kms"))]
+            SignerType::AwsKms | SignerType::GcpKms | SignerType::Remote => {
+                return Err(format!("Signer type {self:?} requires the `kms` feature").into());
+            }
+        };
+        if signers.is_empty() {
+            return Err(format!("No settlement account for signer type {self:?}").into());
         }
+        Ok(signers)
     }
 
     pub fn make_solana_wallet(&self) -> Result<Keypair, Box<dyn std::error::Error>> {
         match self {
-            SignerType::PrivateKey => {
+            SignerType::PrivateKey
+            | SignerType::Mnemonic
+            | SignerType::AwsKms
+            | SignerType::GcpKms
+            | SignerType::Remote => {
                 let private_key = env::var(ENV_SOLANA_PRIVATE_KEY)
                     .map_err(|_| format!("env {ENV_EVM_PRIVATE_KEY} not set"))?;
                 let keypair = Keypair::from_base58_string(private_key.as_str());
@@ -235,3 +460,33 @@ impl SignerType {
         }
     }
 }
+
+/// Wallet of `signers`, the first one being its default signer.
+pub fn evm_wallet(
+    signers: Vec<Arc<dyn SettlementSigner>>,
+) -> Result<EthereumWallet, Box<dyn std::error::Error>> {
+    let mut signers = signers.into_iter();
+    let primary = signers.next().ok_or("No settlement account")?;
+    let mut wallet = EthereumWallet::new(SettlementTxSigner(primary));
+    for signer in signers {
+        wallet.register_signer(SettlementTxSigner(signer));
+    }
+    Ok(wallet)
+}
+
+fn local_signers(signers: Vec<PrivateKeySigner>) -> Vec<Arc<dyn SettlementSigner>> {
+    signers
+        .into_iter()
+        .map(|signer| Arc::new(signer) as Arc<dyn SettlementSigner>)
+        .collect()
+}
+
+#[cfg(feature = "kms")]
+fn remote_signers<S: SettlementSigner + 'static>(
+    signers: Vec<S>,
+) -> Vec<Arc<dyn SettlementSigner>> {
+    signers
+        .into_iter()
+        .map(|signer| Arc::new(signer) as Arc<dyn SettlementSigner>)
+        .collect()
+}
@@ -0,0 +1,258 @@
+//! Receipts of settled payments, signed by the facilitator.
+//!
+//! A [`SettleResponse`] tells the client that it paid, but proves nothing to anyone else. A
+//! [`SettlementReceipt`] binds the payer, the recipient, the amount, the asset, the network and
+//! the transaction of a settled payment, the time of its settlement, and the metadata of the
+//! seller (e.g. the order paid, by the Keccak-256 hash of its canonical JSON), under an EIP-712
+//! signature of the facilitator: the primary EVM settlement account, the `feePayer` of its EVM
+//! payment kinds. A buyer can show it to a third party, who checks it offline with
+//! [`SettlementReceipt::verify`] against the facilitator it trusts, and if need be the payment
+//! itself against the chain, see [`settlement_audit`](crate::settlement_audit).
+//!
+//! Receipts are EIP-712 [`Receipt`]s in the domain `x402 Receipt`, version `2`, without chain id
+//! nor verifying contract: the network is part of the receipt. Addresses and transaction hashes
+//! are signed as strings, as displayed, so that receipts of Solana payments are signed the same
+//! way as those of EVM payments.
+//!
+//! A receipt is issued for every successful settlement, and returned in the `receipt` field of the
+//! settle response. Recent receipts are kept by transaction, served by `GET /receipts/{transaction}`
+//! and the `x402.receipt` WebSocket method.
+//!
+//! Environment (see [`Receipts::from_env`]):
+//! - `RECEIPT_CAPACITY` – Receipts kept for lookup by transaction (default `10000`, `0` disables
+//!   receipts)
+//!
+//! ```rust
+//! use alloy::signers::local::PrivateKeySigner;
+//! use x402_rs::receipts::SettlementReceipt;
+//!
+//! fn is_trusted(receipt: &SettlementReceipt, facilitator: &PrivateKeySigner) -> bool {
+//!     receipt.facilitator.0 == facilitator.address() && receipt.verify().is_ok()
+//! }
+//! ```
+
+use alloy::primitives::{Address, B256, Signature};
+use alloy::sol;
+use alloy::sol_types::{Eip712Domain, SolStruct, eip712_domain};
+use serde::{Deserialize, Serialize};
+use std::env;
+use std::sync::Arc;
+
+use crate::canonical_json;
+use crate::chain::settlement_signer::SettlementSigner;
+use crate::lru_cache::LruCache;
+use crate::network::Network;
+use crate::timestamp::UnixTimestamp;
+use crate::types::{
+    EvmAddress, EvmSignature, MixedAddress, SettleRequest, SettleResponse, TokenAmount,
+    TransactionHash,
+};
+
+const ENV_RECEIPT_CAPACITY: &str = "RECEIPT_CAPACITY";
+
+/// Name of the EIP-712 domain of receipts.
+pub const RECEIPT_DOMAIN_NAME: &str = "x402 Receipt";
+/// Version of the EIP-712 domain of receipts, `2` since receipts sign the metadata.
+pub const RECEIPT_DOMAIN_VERSION: &str = "2";
+/// Receipts kept for lookup, by default.
+pub const DEFAULT_RECEIPT_CAPACITY: usize = 10_000;
+
+sol!(
+    /// Solidity-compatible struct definition of a settlement receipt.
+    ///
+    /// `payer` paid `amount` of `asset` to `payTo` on `network`, in the transaction `transaction`,
+    /// settled at `settledAt` (seconds since the Unix epoch), for the seller metadata hashed as
+    /// `metadataHash` (zero without metadata).
+    #[derive(Serialize, Deserialize)]
+    struct Receipt {
+        string network;
+        string transaction;
+        string payer;
+        string payTo;
+        string asset;
+        uint256 amount;
+        uint64 settledAt;
+        bytes32 metadataHash;
+    }
+);
+
+#[derive(Debug, thiserror::Error)]
+pub enum ReceiptError {
+    #[error("Invalid {ENV_RECEIPT_CAPACITY}: {0}, expected a number of receipts")]
+    InvalidCapacity(String),
+    #[error("Malformed receipt signature: {0}")]
+    MalformedSignature(String),
+    #[error("Receipt signed by {actual}, not by the facilitator {expected}")]
+    WrongSigner { expected: Address, actual: Address },
+    #[error("Can not sign the receipt: {0}")]
+    Signing(String),
+}
+
+/// EIP-712 domain of receipts.
+pub fn receipt_domain() -> Eip712Domain {
+    eip712_domain! {
+        name: RECEIPT_DOMAIN_NAME,
+        version: RECEIPT_DOMAIN_VERSION,
+    }
+}
+
+/// Keccak-256 hash of the canonical JSON encoding of `metadata`, as signed in receipts: zero
+/// without metadata.
+pub fn metadata_hash(metadata: Option<&serde_json::Value>) -> B256 {
+    metadata
+        .and_then(|metadata| canonical_json::keccak256(metadata).ok())
+        .unwrap_or(B256::ZERO)
+}
+
+/// A settled payment, with the signature of the facilitator that settled it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SettlementReceipt {
+    pub network: Network,
+    pub transaction: TransactionHash,
+    pub payer: MixedAddress,
+    pub pay_to: MixedAddress,
+    pub asset: MixedAddress,
+    /// Amount paid, in token base units.
+    pub amount: TokenAmount,
+    /// Time of the settlement, in seconds since the Unix epoch.
+    #[serde(with = "crate::timestamp::number")]
+    pub settled_at: UnixTimestamp,
+    /// [`SettleRequest::metadata`] of the settled request, signed by its [`metadata_hash`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub metadata: Option<serde_json::Value>,
+    /// Account of the facilitator signing the receipt.
+    pub facilitator: EvmAddress,
+    pub signature: EvmSignature,
+}
+
+impl SettlementReceipt {
+    /// EIP-712 signing hash of the receipt, in the [`receipt_domain`].
+    pub fn signing_hash(&self) -> B256 {
+        Receipt {
+            network: self.network.to_string(),
+            transaction: self.transaction.to_string(),
+            payer: self.payer.to_string(),
+            payTo: self.pay_to.to_string(),
+            asset: self.asset.to_string(),
+            amount: self.amount.into(),
+            settledAt: self.settled_at.0,
+            metadataHash: metadata_hash(self.metadata.as_ref()),
+        }
+        .eip712_signing_hash(&receipt_domain())
+    }
+
+    /// Recovers the signer of the receipt.
+    pub fn recover(&self) -> Result<Address, ReceiptError> {
+        let signature = Signature::from_raw(&self.signature.0)
+            .map_err(|e| ReceiptError::MalformedSignature(e.to_string()))?;
+        signature
+            .recover_address_from_prehash(&self.signing_hash())
+            .map_err(|e| ReceiptError::MalformedSignature(e.to_string()))
+    }
+
+    /// Checks that the receipt is signed by its `facilitator`. Whether that facilitator is
+    /// trusted is up to the caller.
+    pub fn verify(&self) -> Result<(), ReceiptError> {
+        let signer = self.recover()?;
+        if signer != self.facilitator.0 {
+            return Err(ReceiptError::WrongSigner {
+                expected: self.facilitator.0,
+                actual: signer,
+            });
+        }
+        Ok(())
+    }
+}
+
+/// Issues the receipts of settled payments, and keeps the recent ones by transaction.
+///
+/// Clones share the same receipts.
+#[derive(Debug, Clone)]
+pub struct Receipts {
+    signer: Arc<dyn SettlementSigner>,
+    receipts: LruCache<TransactionHash, SettlementReceipt>,
+}
+
+impl Receipts {
+    /// Signs receipts with `signer`, keeping the last [`DEFAULT_RECEIPT_CAPACITY`] of them.
+    pub fn new(signer: Arc<dyn SettlementSigner>) -> Self {
+        Self {
+            signer,
+            receipts: LruCache::new("receipts", DEFAULT_RECEIPT_CAPACITY),
+        }
+    }
+
+    /// Signs receipts with `signer`, keeping as many as `RECEIPT_CAPACITY`. Returns `None` if
+    /// receipts are disabled.
+    pub fn from_env(signer: Arc<dyn SettlementSigner>) -> Result<Option<Self>, ReceiptError> {
+        let capacity = match env::var(ENV_RECEIPT_CAPACITY) {
+            Ok(capacity) => capacity
+                .trim()
+                .parse()
+                .map_err(|_| ReceiptError::InvalidCapacity(capacity))?,
+            Err(_) => DEFAULT_RECEIPT_CAPACITY,
+        };
+        if capacity == 0 {
+            return Ok(None);
+        }
+        Ok(Some(Self::new(signer).with_capacity(capacity)))
+    }
+
+    /// Keeps the last `capacity` receipts, at least one.
+    pub fn with_capacity(&self, capacity: usize) -> Self {
+        let mut this = self.clone();
+        this.receipts = LruCache::new("receipts", capacity);
+        this
+    }
+
+    /// Account signing the receipts.
+    pub fn facilitator(&self) -> Address {
+        self.signer.address()
+    }
+
+    /// Signs the receipt of the settlement of `request`, answered with `response`, and keeps it.
+    /// Returns `None` for failed settlements.
+    pub async fn issue(
+        &self,
+        request: &SettleRequest,
+        response: &SettleResponse,
+    ) -> Result<Option<SettlementReceipt>, ReceiptError> {
+        let (true, Some(transaction)) = (response.success, &response.transaction) else {
+            return Ok(None);
+        };
+        let requirements = &request.payment_requirements;
+        // What moved on-chain, when decoded, rather than what was authorized
+        let amount = match &response.settlement {
+            Some(settlement) => settlement.amount,
+            None => requirements
+                .consumed_amount
+                .unwrap_or(requirements.max_amount_required),
+        };
+        let mut receipt = SettlementReceipt {
+            network: response.network,
+            transaction: transaction.clone(),
+            payer: response.payer.clone(),
+            pay_to: requirements.pay_to.clone(),
+            asset: requirements.asset.clone(),
+            amount,
+            settled_at: UnixTimestamp::try_now().unwrap_or(UnixTimestamp(0)),
+            metadata: request.metadata.clone(),
+            facilitator: self.signer.address().into(),
+            signature: EvmSignature(Vec::new()),
+        };
+        let signature = self
+            .signer
+            .sign_hash(&receipt.signing_hash())
+            .await
+            .map_err(|e| ReceiptError::Signing(e.to_string()))?;
+        receipt.signature = EvmSignature::from(signature.as_bytes());
+        self.receipts.insert(transaction.clone(), receipt.clone());
+        Ok(Some(receipt))
+    }
+
+    /// Receipt of the settlement by `transaction`, if kept.
+    pub fn get(&self, transaction: &TransactionHash) -> Option<SettlementReceipt> {
+        self.receipts.get(transaction)
+    }
+}
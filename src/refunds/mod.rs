@@ -0,0 +1,389 @@
+//! Refunds of settled payments, served as `POST /refund` and the `x402.refund` WebSocket method.
+//!
+//! A seller handling a disputed stream, or any payment it should not have been paid, sends back
+//! part or all of it through the facilitator. The refund is itself an x402 payment, the other
+//! way around: the payee signs an `exact` authorization moving the amount to refund from its
+//! account to the original payer, and the facilitator settles it like any payment, sponsoring
+//! the gas. The signature of the authorization authenticates the payee: only the recipient of a
+//! settlement can refund it.
+//!
+//! [`Refunds::refund`] identifies the original settlement by its transaction, with the
+//! [`SettlementReceipt`] the facilitator signed for it: kept by [`Receipts`], or sent along with
+//! the refund once the facilitator forgot it, e.g. after a restart. It then checks that the refund
+//! pays the payer of the settlement back, in its network and asset, that the payee signed it, and
+//! that the settlement is not refunded more than it paid, before settling the refund. Settled
+//! refunds are appended to a [`RefundStore`], linking the refund transaction to the original
+//! settlement, and listed by `GET /refunds/{transaction}`.
+//!
+//! Backends:
+//! - [`InMemoryRefunds`] – forgets refunds on restart
+//! - [`SledRefunds`](sled::SledRefunds) – embedded database on disk (`sled` feature)
+//! - [`PostgresRefunds`](postgres::PostgresRefunds) – shared by several facilitator instances (`postgres` feature)
+//!
+//! Refunds need [`Receipts`], and are not served if receipts are disabled.
+//!
+//! Environment (see [`Refunds::from_env`]):
+//! - `REFUND_LEDGER_URL` – `memory`, `sled:<path>` or `postgres://...`; refunds are not served if
+//!   unset
+
+use futures_util::future::BoxFuture;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::env;
+use std::sync::{Arc, Mutex};
+
+use crate::facilitator::Facilitator;
+use crate::network::Network;
+use crate::receipts::{Receipts, SettlementReceipt};
+use crate::timestamp::UnixTimestamp;
+use crate::types::{
+    MixedAddress, PaymentPayload, PaymentRequirements, Scheme, SettleRequest, SettleResponse,
+    TokenAmount, TransactionHash, VerifyResponse, X402Version,
+};
+
+#[cfg(feature = "postgres")]
+pub mod postgres;
+#[cfg(feature = "sled")]
+pub mod sled;
+
+const ENV_REFUND_LEDGER_URL: &str = "REFUND_LEDGER_URL";
+
+#[derive(Debug, thiserror::Error)]
+pub enum RefundError {
+    #[error("Unsupported {ENV_REFUND_LEDGER_URL}: {0}")]
+    UnsupportedUrl(String),
+    #[error("Refund ledger backend error: {0}")]
+    #[allow(dead_code)] // Constructed by the `sled` and `postgres` backends.
+    Backend(String),
+    #[error("Unknown settlement {0}")]
+    UnknownSettlement(TransactionHash),
+    #[error("Invalid receipt of settlement {0}")]
+    InvalidReceipt(TransactionHash),
+    #[error("Refunds must be payments of the exact scheme")]
+    UnsupportedScheme,
+    #[error("The refund does not pay the payer back: {0}")]
+    Mismatch(&'static str),
+    #[error("The refund is not signed by the payee of the settlement")]
+    NotPayee,
+    #[error("Refund of {amount} exceeds the {remaining} left to refund")]
+    Exceeded {
+        remaining: TokenAmount,
+        amount: TokenAmount,
+    },
+    #[error("Another refund of settlement {0} is in progress")]
+    InProgress(TransactionHash),
+    #[error("Refund rejected: {0}")]
+    Rejected(String),
+}
+
+/// A refund of part or all of a settled payment, the body of `POST /refund`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RefundRequest {
+    /// Transaction of the settlement to refund.
+    pub transaction: TransactionHash,
+    /// Authorization of the payee, moving the refund to the payer of the settlement.
+    pub payment_payload: PaymentPayload,
+    /// Requirements of the refund: paid to the payer of the settlement, in its network and asset,
+    /// for the amount to refund.
+    pub payment_requirements: PaymentRequirements,
+    /// Receipt of the settlement, for settlements the facilitator no longer keeps the receipt of.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub receipt: Option<SettlementReceipt>,
+    /// Why the payment is refunded, kept in the ledger.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+}
+
+/// A settled refund, as kept by a [`RefundStore`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RefundRecord {
+    pub id: String,
+    /// Transaction of the refunded settlement.
+    pub settlement: TransactionHash,
+    /// Transaction of the refund.
+    pub transaction: TransactionHash,
+    pub network: Network,
+    pub asset: MixedAddress,
+    /// Payer of the settlement, refunded.
+    pub payer: MixedAddress,
+    /// Payee of the settlement, refunding.
+    pub payee: MixedAddress,
+    /// Amount refunded, in token base units.
+    pub amount: TokenAmount,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+    #[serde(with = "crate::timestamp::number")]
+    pub refunded_at: UnixTimestamp,
+}
+
+/// A settled refund, and the settlement of the refund payment.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Refund {
+    pub refund: RefundRecord,
+    /// Amount of the original settlement left to refund.
+    pub remaining: TokenAmount,
+    pub settlement: SettleResponse,
+}
+
+/// Ledger of the refunds of a facilitator.
+pub trait RefundStore: Send + Sync {
+    /// Appends a refund.
+    fn append(&self, record: RefundRecord) -> BoxFuture<'_, Result<(), RefundError>>;
+    /// Refunds of the settlement by `settlement`, oldest first.
+    fn of_settlement(
+        &self,
+        settlement: &TransactionHash,
+    ) -> BoxFuture<'_, Result<Vec<RefundRecord>, RefundError>>;
+}
+
+/// Refunds kept in memory.
+#[derive(Debug, Default)]
+pub struct InMemoryRefunds {
+    records: Mutex<HashMap<TransactionHash, Vec<RefundRecord>>>,
+}
+
+impl InMemoryRefunds {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl RefundStore for InMemoryRefunds {
+    fn append(&self, record: RefundRecord) -> BoxFuture<'_, Result<(), RefundError>> {
+        self.records
+            .lock()
+            .unwrap()
+            .entry(record.settlement.clone())
+            .or_default()
+            .push(record);
+        Box::pin(async { Ok(()) })
+    }
+
+    fn of_settlement(
+        &self,
+        settlement: &TransactionHash,
+    ) -> BoxFuture<'_, Result<Vec<RefundRecord>, RefundError>> {
+        let records = self
+            .records
+            .lock()
+            .unwrap()
+            .get(settlement)
+            .cloned()
+            .unwrap_or_default();
+        Box::pin(async move { Ok(records) })
+    }
+}
+
+/// Refunds settled payments, and keeps their ledger.
+///
+/// Cloning is cheap: clones share the ledger.
+#[derive(Clone)]
+pub struct Refunds {
+    store: Arc<dyn RefundStore>,
+    receipts: Receipts,
+    /// Settlements being refunded, refunded once at a time.
+    in_progress: Arc<Mutex<HashSet<TransactionHash>>>,
+}
+
+impl Refunds {
+    /// Refunds the settlements of `receipts`, keeping the ledger in `store`.
+    pub fn new(store: Arc<dyn RefundStore>, receipts: Receipts) -> Self {
+        Self {
+            store,
+            receipts,
+            in_progress: Arc::new(Mutex::new(HashSet::new())),
+        }
+    }
+
+    /// Refunds with the ledger configured by `REFUND_LEDGER_URL`, or `None` if not configured.
+    pub async fn from_env(receipts: Receipts) -> Result<Option<Self>, RefundError> {
+        let Some(url) = env::var(ENV_REFUND_LEDGER_URL)
+            .ok()
+            .filter(|url| !url.is_empty())
+        else {
+            return Ok(None);
+        };
+        let store: Arc<dyn RefundStore> = if url == "memory" {
+            Arc::new(InMemoryRefunds::new())
+        } else {
+            store_from_url(url).await?
+        };
+        Ok(Some(Self::new(store, receipts)))
+    }
+
+    /// Refunds of the settlement by `settlement`, oldest first.
+    pub async fn of_settlement(
+        &self,
+        settlement: &TransactionHash,
+    ) -> Result<Vec<RefundRecord>, RefundError> {
+        self.store.of_settlement(settlement).await
+    }
+
+    /// Verifies and settles `request` with `facilitator`, and records the refund.
+    ///
+    /// # Errors
+    /// Returns [`RefundError::UnknownSettlement`] if the settlement has no receipt,
+    /// [`RefundError::Mismatch`] if the refund does not pay its payer back,
+    /// [`RefundError::NotPayee`] if its payee did not sign the refund,
+    /// [`RefundError::Exceeded`] if the refund exceeds what is left to refund, and
+    /// [`RefundError::Rejected`] if the refund payment is invalid or not settled.
+    pub async fn refund<F: Facilitator>(
+        &self,
+        facilitator: &F,
+        request: RefundRequest,
+    ) -> Result<Refund, RefundError> {
+        let receipt = self.receipt(&request)?;
+        let requirements = &request.payment_requirements;
+        if requirements.scheme != Scheme::Exact {
+            return Err(RefundError::UnsupportedScheme);
+        }
+        if requirements.network != receipt.network
+            || request.payment_payload.network != receipt.network
+        {
+            return Err(RefundError::Mismatch("network differs"));
+        }
+        if requirements.asset != receipt.asset {
+            return Err(RefundError::Mismatch("asset differs"));
+        }
+        if requirements.pay_to != receipt.payer {
+            return Err(RefundError::Mismatch("recipient is not the payer"));
+        }
+        let Some(_refunding) = Refunding::start(&self.in_progress, &request.transaction) else {
+            return Err(RefundError::InProgress(request.transaction));
+        };
+        self.settle(facilitator, &request, &receipt).await
+    }
+
+    /// The receipt of the settlement refunded by `request`, kept or sent along and signed by the
+    /// facilitator.
+    fn receipt(&self, request: &RefundRequest) -> Result<SettlementReceipt, RefundError> {
+        if let Some(receipt) = self.receipts.get(&request.transaction) {
+            return Ok(receipt);
+        }
+        let Some(receipt) = &request.receipt else {
+            return Err(RefundError::UnknownSettlement(request.transaction.clone()));
+        };
+        let signed_here = receipt.transaction == request.transaction
+            && receipt.facilitator.0 == self.receipts.facilitator()
+            && receipt.verify().is_ok();
+        if !signed_here {
+            return Err(RefundError::InvalidReceipt(request.transaction.clone()));
+        }
+        Ok(receipt.clone())
+    }
+
+    async fn settle<F: Facilitator>(
+        &self,
+        facilitator: &F,
+        request: &RefundRequest,
+        receipt: &SettlementReceipt,
+    ) -> Result<Refund, RefundError> {
+        let refunded = self
+            .store
+            .of_settlement(&request.transaction)
+            .await?
+            .iter()
+            .fold(TokenAmount::from(0u64), |total, record| {
+                total.saturating_add(record.amount)
+            });
+        let remaining = receipt.amount.saturating_sub(refunded);
+        let amount = request.payment_requirements.max_amount_required;
+        if amount > remaining {
+            return Err(RefundError::Exceeded { remaining, amount });
+        }
+        let settle_request = SettleRequest {
+            x402_version: X402Version::V1,
+            payment_payload: request.payment_payload.clone(),
+            payment_requirements: request.payment_requirements.clone(),
+            metadata: None,
+            idempotency_key: None,
+        };
+        let verify = facilitator
+            .verify(&settle_request)
+            .await
+            .map_err(|e| RefundError::Rejected(e.to_string()))?;
+        match verify {
+            VerifyResponse::Valid { payer, .. } if payer == receipt.pay_to => {}
+            VerifyResponse::Valid { .. } => return Err(RefundError::NotPayee),
+            VerifyResponse::Invalid { reason, .. } => {
+                return Err(RefundError::Rejected(reason.to_string()));
+            }
+        }
+        let settlement = facilitator
+            .settle(&settle_request)
+            .await
+            .map_err(|e| RefundError::Rejected(e.to_string()))?;
+        let (true, Some(transaction)) = (settlement.success, settlement.transaction.clone()) else {
+            let reason = settlement
+                .error_reason
+                .map(|reason| reason.to_string())
+                .unwrap_or_else(|| "settlement failed".to_string());
+            return Err(RefundError::Rejected(reason));
+        };
+        let record = RefundRecord {
+            id: uuid::Uuid::new_v4().to_string(),
+            settlement: request.transaction.clone(),
+            transaction,
+            network: receipt.network,
+            asset: receipt.asset.clone(),
+            payer: receipt.payer.clone(),
+            payee: receipt.pay_to.clone(),
+            amount,
+            reason: request.reason.clone(),
+            refunded_at: UnixTimestamp::try_now().unwrap_or(UnixTimestamp(0)),
+        };
+        // The refund moved on-chain: failing to record it must not hide its settlement
+        if let Err(error) = self.store.append(record.clone()).await {
+            tracing::error!(%error, refund = ?record, "Failed to record refund");
+        }
+        Ok(Refund {
+            refund: record,
+            remaining: remaining.saturating_sub(amount),
+            settlement,
+        })
+    }
+}
+
+/// A settlement being refunded, until dropped.
+struct Refunding<'a> {
+    in_progress: &'a Mutex<HashSet<TransactionHash>>,
+    settlement: TransactionHash,
+}
+
+impl<'a> Refunding<'a> {
+    /// Marks `settlement` as being refunded, or returns `None` if it already is.
+    fn start(
+        in_progress: &'a Mutex<HashSet<TransactionHash>>,
+        settlement: &TransactionHash,
+    ) -> Option<Self> {
+        in_progress
+            .lock()
+            .unwrap()
+            .insert(settlement.clone())
+            .then(|| Self {
+                in_progress,
+                settlement: settlement.clone(),
+            })
+    }
+}
+
+impl Drop for Refunding<'_> {
+    fn drop(&mut self) {
+        self.in_progress.lock().unwrap().remove(&self.settlement);
+    }
+}
+
+async fn store_from_url(url: String) -> Result<Arc<dyn RefundStore>, RefundError> {
+    #[cfg(feature = "sled")]
+    if let Some(path) = url.strip_prefix("sled:") {
+        return Ok(Arc::new(sled::SledRefunds::open(path)?));
+    }
+    #[cfg(feature = "postgres")]
+    if url.starts_with("postgres://") || url.starts_with("postgresql://") {
+        return Ok(Arc::new(postgres::PostgresRefunds::connect(&url).await?));
+    }
+    Err(RefundError::UnsupportedUrl(url))
+}
@@ -0,0 +1,87 @@
+//! [`RefundStore`] backed by PostgreSQL.
+
+use futures_util::future::BoxFuture;
+use tokio_postgres::{Client, NoTls};
+
+use super::{RefundError, RefundRecord, RefundStore};
+use crate::types::TransactionHash;
+
+/// Refunds persisted in the `x402_refunds` table, shared by all facilitator instances connected
+/// to the same database. The table is created on [`connect`](Self::connect).
+pub struct PostgresRefunds {
+    client: Client,
+}
+
+impl PostgresRefunds {
+    /// Connects to the database at `url`, e.g. `postgres://x402@localhost/x402`, without TLS.
+    pub async fn connect(url: &str) -> Result<Self, RefundError> {
+        let (client, connection) = tokio_postgres::connect(url, NoTls)
+            .await
+            .map_err(backend_error)?;
+        tokio::spawn(async move {
+            if let Err(error) = connection.await {
+                tracing::error!(%error, "Refund ledger connection failed");
+            }
+        });
+        client
+            .batch_execute(
+                "CREATE TABLE IF NOT EXISTS x402_refunds (
+                    id BIGSERIAL PRIMARY KEY,
+                    settlement TEXT NOT NULL,
+                    transaction TEXT NOT NULL,
+                    record TEXT NOT NULL
+                );
+                CREATE INDEX IF NOT EXISTS x402_refunds_settlement
+                    ON x402_refunds (settlement)",
+            )
+            .await
+            .map_err(backend_error)?;
+        Ok(Self { client })
+    }
+}
+
+impl RefundStore for PostgresRefunds {
+    fn append(&self, record: RefundRecord) -> BoxFuture<'_, Result<(), RefundError>> {
+        Box::pin(async move {
+            let settlement = record.settlement.to_string();
+            let transaction = record.transaction.to_string();
+            let record =
+                serde_json::to_string(&record).map_err(|e| RefundError::Backend(e.to_string()))?;
+            self.client
+                .execute(
+                    "INSERT INTO x402_refunds (settlement, transaction, record) VALUES ($1, $2, $3)",
+                    &[&settlement, &transaction, &record],
+                )
+                .await
+                .map_err(backend_error)?;
+            Ok(())
+        })
+    }
+
+    fn of_settlement(
+        &self,
+        settlement: &TransactionHash,
+    ) -> BoxFuture<'_, Result<Vec<RefundRecord>, RefundError>> {
+        let settlement = settlement.to_string();
+        Box::pin(async move {
+            let rows = self
+                .client
+                .query(
+                    "SELECT record FROM x402_refunds WHERE settlement = $1 ORDER BY id",
+                    &[&settlement],
+                )
+                .await
+                .map_err(backend_error)?;
+            rows.iter()
+                .map(|row| {
+                    serde_json::from_str(row.get(0))
+                        .map_err(|e| RefundError::Backend(e.to_string()))
+                })
+                .collect()
+        })
+    }
+}
+
+fn backend_error(error: tokio_postgres::Error) -> RefundError {
+    RefundError::Backend(error.to_string())
+}
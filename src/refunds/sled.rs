@@ -0,0 +1,69 @@
+//! [`RefundStore`] backed by an embedded [sled](https://docs.rs/sled) database.
+
+use futures_util::future::BoxFuture;
+use std::path::Path;
+
+use super::{RefundError, RefundRecord, RefundStore};
+use crate::types::TransactionHash;
+
+/// Refunds persisted in a sled database on local disk, surviving restarts of a single
+/// facilitator instance.
+///
+/// Refunds are keyed by their refunded settlement, then by a unique increasing id, so that the
+/// refunds of a settlement are a prefix scan, oldest first.
+#[derive(Debug, Clone)]
+pub struct SledRefunds {
+    db: sled::Db,
+}
+
+impl SledRefunds {
+    /// Opens (or creates) the database at `path`.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, RefundError> {
+        let db = sled::open(path).map_err(backend_error)?;
+        Ok(Self { db })
+    }
+}
+
+/// Prefix of the keys of the refunds of `settlement`.
+fn settlement_prefix(settlement: &TransactionHash) -> Vec<u8> {
+    let mut prefix = settlement.to_string().into_bytes();
+    prefix.push(b'/');
+    prefix
+}
+
+impl RefundStore for SledRefunds {
+    fn append(&self, record: RefundRecord) -> BoxFuture<'_, Result<(), RefundError>> {
+        Box::pin(async move {
+            let id = self.db.generate_id().map_err(backend_error)?;
+            let mut key = settlement_prefix(&record.settlement);
+            key.extend_from_slice(&id.to_be_bytes());
+            let value =
+                serde_json::to_vec(&record).map_err(|e| RefundError::Backend(e.to_string()))?;
+            self.db.insert(key, value).map_err(backend_error)?;
+            // The refund moved funds already: it must not be lost in a crash
+            self.db.flush_async().await.map_err(backend_error)?;
+            Ok(())
+        })
+    }
+
+    fn of_settlement(
+        &self,
+        settlement: &TransactionHash,
+    ) -> BoxFuture<'_, Result<Vec<RefundRecord>, RefundError>> {
+        let prefix = settlement_prefix(settlement);
+        Box::pin(async move {
+            self.db
+                .scan_prefix(prefix)
+                .values()
+                .map(|value| {
+                    let value = value.map_err(backend_error)?;
+                    serde_json::from_slice(&value).map_err(|e| RefundError::Backend(e.to_string()))
+                })
+                .collect()
+        })
+    }
+}
+
+fn backend_error(error: sled::Error) -> RefundError {
+    RefundError::Backend(error.to_string())
+}
@@ -0,0 +1,473 @@
+//! Recording and replay of facilitator runs, for regression tests of upgrades (`replay` feature).
+//!
+//! A facilitator recording a run (see [`Recording`]) appends to its recording, as JSON Lines:
+//! - every verification and settlement: the request, the time it was made at, and its outcome,
+//!   the response or the error;
+//! - every JSON-RPC call made to the nodes of EVM networks, with its response.
+//!
+//! A [`Replay`] runs the verifications and settlements of a recording again, in order, against a
+//! facilitator whose EVM networks are a mock chain ([`ReplayTransport`]): every call to a node is
+//! answered with the response recorded for the same call. The clock of the facilitator is set to
+//! the time each request was made at. Outcomes differing from the recorded ones are reported as
+//! [`ReplayMismatch`]es: a new build replaying the recordings of the deployed one behaves the
+//! same as long as there are none.
+//!
+//! Settlements are only replayed identically with the settlement key of the recording, which
+//! signs the same transactions. Solana networks, custom chains, and settlements deferred to the
+//! settlement queue are not recorded.
+//!
+//! Environment:
+//! - `REPLAY_RECORD_PATH` – File the facilitator appends its recording to
+//! - `REPLAY_FILE` – Recording the binary replays, instead of serving. It exits with an error if
+//!   any outcome differs
+//!
+//! This module is only compiled with the `replay` feature. Recordings contain every request in
+//! full, payment signatures included, and must be kept private.
+
+mod transport;
+
+#[allow(unused_imports)] // Public for consumption by downstream crates.
+pub use transport::{RecordingLayer, RecordingService, ReplayTransport};
+
+use alloy::network::EthereumWallet;
+use alloy::providers::ProviderBuilder;
+use alloy::rpc::client::ClientBuilder;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashSet;
+use std::env;
+use std::fmt::Display;
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+use std::sync::{Arc, Mutex, OnceLock};
+
+use crate::balance::BalanceLedger;
+use crate::chain::evm::EvmProvider;
+use crate::chain::{FacilitatorLocalError, NetworkProvider};
+#[cfg(feature = "chaos")]
+use crate::chaos::FaultInjector;
+use crate::discovery::DiscoveryRegistry;
+use crate::extensions::ExtensionRegistry;
+use crate::facilitator::{Facilitator, FacilitatorServices};
+use crate::facilitator_local::FacilitatorLocal;
+use crate::network::{Network, NetworkFamily};
+use crate::nonce_store::PaymentNonceStore;
+#[cfg(feature = "webhooks")]
+use crate::payment_watch::PaymentWatcher;
+use crate::provider_cache::{self, ProviderCache};
+use crate::receipts::Receipts;
+use crate::refunds::Refunds;
+use crate::settlement_events::SettlementEvents;
+use crate::settlement_pause::SettlementPause;
+use crate::settlement_queue::SettlementQueue;
+use crate::settlement_stats::SettlementStats;
+use crate::storage::PaymentHistory;
+use crate::stream::StreamSessionManager;
+use crate::strict_settle::StrictSettle;
+use crate::timestamp::UnixTimestamp;
+use crate::types::{
+    SettleRequest, SettleResponse, SupportedPaymentKindsResponse, VerifyRequest, VerifyResponse,
+};
+
+const ENV_REPLAY_RECORD_PATH: &str = "REPLAY_RECORD_PATH";
+const ENV_REPLAY_FILE: &str = "REPLAY_FILE";
+
+tokio::task_local! {
+    /// Time of the request being replayed.
+    static CLOCK: UnixTimestamp;
+}
+
+/// Time of the request being replayed by the current task, if any: the current time of the
+/// facilitator replaying it.
+pub(crate) fn now() -> Option<UnixTimestamp> {
+    CLOCK.try_with(|now| *now).ok()
+}
+
+/// Recording installed for the nodes of the networks, see [`Recording::install`].
+static INSTALLED: OnceLock<Recording> = OnceLock::new();
+
+#[derive(Debug, thiserror::Error)]
+pub enum ReplayError {
+    #[error("Can not open recording {0}: {1}")]
+    Io(String, #[source] std::io::Error),
+    #[error("Malformed recording entry at line {0}: {1}")]
+    Malformed(usize, #[source] serde_json::Error),
+    #[error("Can not replay on {0}: {1}")]
+    Provider(Network, #[source] FacilitatorLocalError),
+}
+
+/// Outcome of a verification or settlement: its response, or its error.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ReplayOutcome {
+    Response(Value),
+    Error(String),
+}
+
+impl ReplayOutcome {
+    fn of<T: Serialize, E: Display>(result: &Result<T, E>) -> Self {
+        match result {
+            Ok(response) => {
+                ReplayOutcome::Response(serde_json::to_value(response).unwrap_or(Value::Null))
+            }
+            Err(e) => ReplayOutcome::Error(e.to_string()),
+        }
+    }
+}
+
+/// Entry of a recording, one per line.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "camelCase")]
+pub enum ReplayEntry {
+    /// A verification made at `at`.
+    Verify {
+        at: UnixTimestamp,
+        request: VerifyRequest,
+        outcome: ReplayOutcome,
+    },
+    /// A settlement made at `at`.
+    Settle {
+        at: UnixTimestamp,
+        request: SettleRequest,
+        outcome: ReplayOutcome,
+    },
+    /// A JSON-RPC call to the node of `network`, and its response: `result` or `error`.
+    Rpc {
+        network: Network,
+        method: String,
+        params: Value,
+        response: Value,
+    },
+}
+
+/// Recording of a facilitator run, appended to a file. Clones append to the same file.
+#[derive(Clone)]
+pub struct Recording {
+    file: Arc<Mutex<BufWriter<File>>>,
+}
+
+impl Recording {
+    /// Appends to the recording at `path`, created if missing.
+    pub fn create(path: impl AsRef<Path>) -> Result<Self, ReplayError> {
+        let path = path.as_ref();
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .map_err(|e| ReplayError::Io(path.display().to_string(), e))?;
+        Ok(Self {
+            file: Arc::new(Mutex::new(BufWriter::new(file))),
+        })
+    }
+
+    /// Appends to the recording at `REPLAY_RECORD_PATH`: `None` if unset, in which case nothing
+    /// should be recorded.
+    pub fn from_env() -> Result<Option<Self>, ReplayError> {
+        match env::var(ENV_REPLAY_RECORD_PATH) {
+            Ok(path) if !path.is_empty() => Self::create(path).map(Some),
+            _ => Ok(None),
+        }
+    }
+
+    /// Records the JSON-RPC calls of the providers created from now on, see [`RecordingLayer`].
+    /// Returns `false` if a recording is installed already.
+    pub fn install(&self) -> bool {
+        INSTALLED.set(self.clone()).is_ok()
+    }
+
+    /// The [installed](Self::install) recording, if any.
+    pub fn installed() -> Option<Self> {
+        INSTALLED.get().cloned()
+    }
+
+    /// Appends `entry` to the recording. Failures are logged, and never fail the facilitator.
+    pub fn append(&self, entry: &ReplayEntry) {
+        let line = match serde_json::to_string(entry) {
+            Ok(line) => line,
+            Err(e) => {
+                tracing::warn!(error = %e, "Failed to encode a recording entry");
+                return;
+            }
+        };
+        let mut file = self.file.lock().unwrap();
+        if let Err(e) = writeln!(file, "{line}").and_then(|_| file.flush()) {
+            tracing::warn!(error = %e, "Failed to append to the recording");
+        }
+    }
+}
+
+/// A [`Facilitator`] recording the verifications and settlements of another one, see the
+/// [module documentation](self).
+pub struct RecordingFacilitator<F> {
+    inner: F,
+    recording: Recording,
+}
+
+impl<F: Clone> Clone for RecordingFacilitator<F> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            recording: self.recording.clone(),
+        }
+    }
+}
+
+impl<F> RecordingFacilitator<F> {
+    /// Records the verifications and settlements of `inner` in `recording`.
+    pub fn new(inner: F, recording: Recording) -> Self {
+        Self { inner, recording }
+    }
+
+    /// The wrapped facilitator.
+    #[allow(dead_code)] // Public for consumption by downstream crates.
+    pub fn inner(&self) -> &F {
+        &self.inner
+    }
+}
+
+impl<F> Facilitator for RecordingFacilitator<F>
+where
+    F: Facilitator + Sync,
+    F::Error: Send,
+{
+    type Error = F::Error;
+
+    async fn verify(&self, request: &VerifyRequest) -> Result<VerifyResponse, Self::Error> {
+        let at = UnixTimestamp::try_now();
+        let result = self.inner.verify(request).await;
+        if let Ok(at) = at {
+            self.recording.append(&ReplayEntry::Verify {
+                at,
+                request: request.clone(),
+                outcome: ReplayOutcome::of(&result),
+            });
+        }
+        result
+    }
+
+    async fn settle(&self, request: &SettleRequest) -> Result<SettleResponse, Self::Error> {
+        let at = UnixTimestamp::try_now();
+        let result = self.inner.settle(request).await;
+        if let Ok(at) = at {
+            self.recording.append(&ReplayEntry::Settle {
+                at,
+                request: request.clone(),
+                outcome: ReplayOutcome::of(&result),
+            });
+        }
+        result
+    }
+
+    async fn supported(&self) -> Result<SupportedPaymentKindsResponse, Self::Error> {
+        self.inner.supported().await
+    }
+}
+
+impl<F: FacilitatorServices> FacilitatorServices for RecordingFacilitator<F> {
+    fn settlement_events(&self) -> Option<&SettlementEvents> {
+        self.inner.settlement_events()
+    }
+
+    fn stream_sessions(&self) -> Option<&StreamSessionManager> {
+        self.inner.stream_sessions()
+    }
+
+    fn settlement_queue(&self) -> Option<&SettlementQueue> {
+        self.inner.settlement_queue()
+    }
+
+    fn provider_cache(&self) -> Option<&ProviderCache> {
+        self.inner.provider_cache()
+    }
+
+    fn nonce_store(&self) -> Option<&dyn PaymentNonceStore> {
+        self.inner.nonce_store()
+    }
+
+    fn discovery(&self) -> Option<&DiscoveryRegistry> {
+        self.inner.discovery()
+    }
+
+    fn settlement_stats(&self) -> Option<&SettlementStats> {
+        self.inner.settlement_stats()
+    }
+
+    fn payment_history(&self) -> Option<&PaymentHistory> {
+        self.inner.payment_history()
+    }
+
+    fn receipts(&self) -> Option<&Receipts> {
+        self.inner.receipts()
+    }
+
+    fn refunds(&self) -> Option<&Refunds> {
+        self.inner.refunds()
+    }
+
+    fn balances(&self) -> Option<&BalanceLedger> {
+        self.inner.balances()
+    }
+
+    fn strict_settle(&self) -> Option<&StrictSettle> {
+        self.inner.strict_settle()
+    }
+
+    fn settlement_pause(&self) -> Option<&SettlementPause> {
+        self.inner.settlement_pause()
+    }
+
+    #[cfg(feature = "webhooks")]
+    fn payment_watcher(&self) -> Option<&PaymentWatcher> {
+        self.inner.payment_watcher()
+    }
+
+    #[cfg(feature = "chaos")]
+    fn faults(&self) -> Option<&FaultInjector> {
+        self.inner.faults()
+    }
+
+    fn extensions(&self) -> ExtensionRegistry {
+        self.inner.extensions()
+    }
+}
+
+/// A verification or settlement whose replayed outcome differs from the recorded one.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReplayMismatch {
+    /// Line of the entry in the recording, from 1.
+    pub line: usize,
+    /// `verify` or `settle`.
+    pub method: &'static str,
+    pub expected: ReplayOutcome,
+    pub actual: ReplayOutcome,
+}
+
+/// Result of a [`Replay::run`].
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReplayReport {
+    /// Number of verifications and settlements replayed.
+    pub replayed: usize,
+    pub mismatches: Vec<ReplayMismatch>,
+}
+
+/// A recording to replay, see the [module documentation](self).
+pub struct Replay {
+    /// Entries of the recording, with their line numbers.
+    entries: Vec<(usize, ReplayEntry)>,
+}
+
+impl Replay {
+    /// Reads the recording at `path`.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, ReplayError> {
+        let path = path.as_ref();
+        let io = |e| ReplayError::Io(path.display().to_string(), e);
+        let file = File::open(path).map_err(io)?;
+        let mut entries = Vec::new();
+        for (index, line) in BufReader::new(file).lines().enumerate() {
+            let line = line.map_err(io)?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let entry =
+                serde_json::from_str(&line).map_err(|e| ReplayError::Malformed(index + 1, e))?;
+            entries.push((index + 1, entry));
+        }
+        Ok(Self { entries })
+    }
+
+    /// Reads the recording at `REPLAY_FILE`: `None` if unset, in which case nothing should be
+    /// replayed.
+    pub fn from_env() -> Result<Option<Self>, ReplayError> {
+        match env::var(ENV_REPLAY_FILE) {
+            Ok(path) if !path.is_empty() => Self::open(path).map(Some),
+            _ => Ok(None),
+        }
+    }
+
+    /// The mock chain of `network`, serving the JSON-RPC responses recorded for its node.
+    pub fn transport(&self, network: Network) -> ReplayTransport {
+        let transport = ReplayTransport::default();
+        for (_, entry) in &self.entries {
+            if let ReplayEntry::Rpc {
+                network: recorded,
+                method,
+                params,
+                response,
+            } = entry
+                && *recorded == network
+            {
+                transport.push_response(method, params, response.clone());
+            }
+        }
+        transport
+    }
+
+    /// A facilitator settling with `wallet` on the mock chains of the EVM networks of the
+    /// recording, to [run](Self::run) the replay against. Configure it as the facilitator
+    /// recorded, e.g. with the same settlement caps, for outcomes to match.
+    pub fn facilitator(&self, wallet: EthereumWallet) -> Result<FacilitatorLocal, ReplayError> {
+        let networks = self
+            .entries
+            .iter()
+            .map(|(_, entry)| match entry {
+                ReplayEntry::Verify { request, .. } | ReplayEntry::Settle { request, .. } => {
+                    request.network()
+                }
+                ReplayEntry::Rpc { network, .. } => *network,
+            })
+            .filter(|network| matches!(NetworkFamily::from(*network), NetworkFamily::Evm))
+            .collect::<HashSet<_>>();
+        let mut providers = ProviderCache::default();
+        for network in networks {
+            let client = ClientBuilder::default().transport(self.transport(network), true);
+            let inner = ProviderBuilder::new()
+                .wallet(wallet.clone())
+                .connect_client(client);
+            let provider =
+                EvmProvider::try_new(inner, provider_cache::is_eip1559(network), network)
+                    .map_err(|e| ReplayError::Provider(network, e))?;
+            providers = providers.with_provider(NetworkProvider::Evm(provider));
+        }
+        Ok(FacilitatorLocal::new(providers))
+    }
+
+    /// Replays the verifications and settlements of the recording with `facilitator`, in order,
+    /// each at the time it was made at.
+    pub async fn run<F: Facilitator>(&self, facilitator: &F) -> ReplayReport {
+        let mut report = ReplayReport::default();
+        for (line, entry) in &self.entries {
+            let (method, expected, actual) = match entry {
+                ReplayEntry::Verify {
+                    at,
+                    request,
+                    outcome,
+                } => {
+                    let result = CLOCK.scope(*at, facilitator.verify(request)).await;
+                    ("verify", outcome, ReplayOutcome::of(&result))
+                }
+                ReplayEntry::Settle {
+                    at,
+                    request,
+                    outcome,
+                } => {
+                    let result = CLOCK.scope(*at, facilitator.settle(request)).await;
+                    ("settle", outcome, ReplayOutcome::of(&result))
+                }
+                ReplayEntry::Rpc { .. } => continue,
+            };
+            report.replayed += 1;
+            if *expected != actual {
+                report.mismatches.push(ReplayMismatch {
+                    line: *line,
+                    method,
+                    expected: expected.clone(),
+                    actual,
+                });
+            }
+        }
+        report
+    }
+}
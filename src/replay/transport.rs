@@ -0,0 +1,199 @@
+//! JSON-RPC transports of the recorder, and of the mock chain replaying its recordings.
+
+use alloy::rpc::json_rpc::{Id, RequestPacket, Response, ResponsePacket, SerializedRequest};
+use alloy::transports::{TransportError, TransportErrorKind, TransportFut};
+use serde_json::Value;
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use tower::{Layer, Service};
+
+use crate::network::Network;
+use crate::replay::{Recording, ReplayEntry};
+
+/// Records the JSON-RPC calls made to the node of a network, with their responses.
+#[derive(Clone)]
+pub struct RecordingLayer {
+    network: Network,
+    recording: Option<Recording>,
+}
+
+impl RecordingLayer {
+    /// Records the calls to the node of `network` in the [installed](Recording::install)
+    /// recording. Records nothing if no recording is installed.
+    pub fn new(network: Network) -> Self {
+        Self {
+            network,
+            recording: Recording::installed(),
+        }
+    }
+}
+
+impl<S> Layer<S> for RecordingLayer {
+    type Service = RecordingService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        RecordingService {
+            inner,
+            network: self.network,
+            recording: self.recording.clone(),
+        }
+    }
+}
+
+/// Transport recording the calls made through the transport it wraps, see [`RecordingLayer`].
+#[derive(Clone)]
+pub struct RecordingService<S> {
+    inner: S,
+    network: Network,
+    recording: Option<Recording>,
+}
+
+impl<S> Service<RequestPacket> for RecordingService<S>
+where
+    S: Service<
+            RequestPacket,
+            Response = ResponsePacket,
+            Error = TransportError,
+            Future = TransportFut<'static>,
+        >,
+{
+    type Response = ResponsePacket;
+    type Error = TransportError;
+    type Future = TransportFut<'static>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: RequestPacket) -> Self::Future {
+        let Some(recording) = self.recording.clone() else {
+            return self.inner.call(request);
+        };
+        let network = self.network;
+        let calls = request
+            .requests()
+            .iter()
+            .map(|call| (call.id().clone(), call.method().to_string(), params(call)))
+            .collect::<Vec<_>>();
+        let response = self.inner.call(request);
+        Box::pin(async move {
+            let response = response.await?;
+            for (id, method, params) in calls {
+                let Some(recorded) = response
+                    .responses()
+                    .iter()
+                    .find(|response| response.id == id)
+                    .and_then(payload)
+                else {
+                    continue;
+                };
+                recording.append(&ReplayEntry::Rpc {
+                    network,
+                    method,
+                    params,
+                    response: recorded,
+                });
+            }
+            Ok(response)
+        })
+    }
+}
+
+/// Responses recorded for identical calls, served in order; the last one is served again to
+/// calls made more often than recorded, e.g. polls.
+#[derive(Default)]
+struct RecordedResponses {
+    queue: VecDeque<Value>,
+    last: Option<Value>,
+}
+
+/// Transport of the mock chain: answers JSON-RPC calls with the responses recorded for
+/// identical calls (same method and params) to the node of a network, and fails the others.
+#[derive(Clone, Default)]
+pub struct ReplayTransport {
+    responses: Arc<Mutex<HashMap<String, RecordedResponses>>>,
+}
+
+impl ReplayTransport {
+    /// Serves `response` to the next call of `method` with `params` not answered yet.
+    pub fn push_response(&self, method: &str, params: &Value, response: Value) {
+        self.responses
+            .lock()
+            .unwrap()
+            .entry(call_key(method, params))
+            .or_default()
+            .queue
+            .push_back(response);
+    }
+
+    fn respond(&self, call: &SerializedRequest) -> Result<Response, TransportError> {
+        let key = call_key(call.method(), &params(call));
+        let recorded = {
+            let mut responses = self.responses.lock().unwrap();
+            responses.get_mut(&key).and_then(|recorded| {
+                if let Some(response) = recorded.queue.pop_front() {
+                    recorded.last = Some(response);
+                }
+                recorded.last.clone()
+            })
+        };
+        let Some(Value::Object(mut response)) = recorded else {
+            return Err(TransportErrorKind::custom_str(&format!(
+                "No recorded response to {}",
+                call.method()
+            )));
+        };
+        response.insert("jsonrpc".to_string(), Value::from("2.0"));
+        response.insert("id".to_string(), id_value(call.id()));
+        serde_json::from_value(Value::Object(response))
+            .map_err(|e| TransportErrorKind::custom_str(&format!("Malformed recording: {e}")))
+    }
+}
+
+impl Service<RequestPacket> for ReplayTransport {
+    type Response = ResponsePacket;
+    type Error = TransportError;
+    type Future = TransportFut<'static>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, request: RequestPacket) -> Self::Future {
+        let response = match &request {
+            RequestPacket::Single(call) => self.respond(call).map(ResponsePacket::Single),
+            RequestPacket::Batch(calls) => calls
+                .iter()
+                .map(|call| self.respond(call))
+                .collect::<Result<Vec<_>, _>>()
+                .map(ResponsePacket::Batch),
+        };
+        Box::pin(async move { response })
+    }
+}
+
+fn params(call: &SerializedRequest) -> Value {
+    call.params()
+        .and_then(|params| serde_json::from_str(params.get()).ok())
+        .unwrap_or(Value::Null)
+}
+
+/// Identity of a call: params are compared by value, regardless of their formatting.
+fn call_key(method: &str, params: &Value) -> String {
+    format!("{method}:{params}")
+}
+
+fn id_value(id: &Id) -> Value {
+    serde_json::to_value(id).unwrap_or(Value::Null)
+}
+
+/// Response without its `jsonrpc` and `id` members, i.e. its `result` or `error`.
+fn payload(response: &Response) -> Option<Value> {
+    let Ok(Value::Object(mut response)) = serde_json::to_value(response) else {
+        return None;
+    };
+    response.remove("jsonrpc");
+    response.remove("id");
+    Some(Value::Object(response))
+}
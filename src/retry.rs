@@ -0,0 +1,311 @@
+//! Retries of requests turned away by an overloaded facilitator or seller.
+//!
+//! Under load, servers turn requests away before processing them, and say when to come back:
+//! `429 Too Many Requests` or `503 Service Unavailable` over HTTP, with a `retry-after` header,
+//! and a `Rate limited` error (`-32029`) with `retryAfterMs` over WebSocket. A request turned
+//! away was not processed, so it is safe to send again, even a settlement. See [`Overload`].
+//!
+//! Clients retry such requests per a [`RetryPolicy`], instead of failing them right away:
+//! after the delay the server asked for, or else after an exponential backoff, both with
+//! jitter so that the clients turned away together do not all come back at once.
+//!
+//! A [`CircuitBreaker`] stops sending to a server that turned away several requests in a row:
+//! requests then fail fast, without reaching it, until it asked to be left alone for, or
+//! for `open_for`. The next request after that is let through; if it is turned away too, the
+//! breaker opens again at once.
+//!
+//! Used by the `x402-axum` facilitator client, the [`FacilitatorWsClient`](crate::facilitator_remote::FacilitatorWsClient)
+//! (`remote` feature), and the `x402-reqwest` payment client.
+
+use serde_json::Value;
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::ws_error::WsErrorCode;
+
+/// Code of the WebSocket error envelope of a request over the rate limit, see
+/// [`WsErrorCode::RATE_LIMITED`].
+pub const RATE_LIMITED_CODE: i64 = WsErrorCode::RATE_LIMITED.code() as i64;
+
+/// A request turned away by an overloaded server, to send again after `retry_after`, if known.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Overload {
+    pub retry_after: Option<Duration>,
+}
+
+impl Overload {
+    /// Overload signalled by an HTTP response of `status` (`429` or `503`), with the value of
+    /// its `retry-after` header, if any, in seconds.
+    #[allow(dead_code)] // Public for consumption by downstream crates.
+    pub fn from_http(status: u16, retry_after: Option<&str>) -> Option<Self> {
+        if status != 429 && status != 503 {
+            return None;
+        }
+        let retry_after = retry_after
+            .and_then(|value| value.trim().parse::<u64>().ok())
+            .map(Duration::from_secs);
+        Some(Self { retry_after })
+    }
+
+    /// Overload signalled by a WebSocket error envelope of `code` (`-32029`), with its `data`.
+    pub fn from_ws_error(code: i64, data: Option<&Value>) -> Option<Self> {
+        if code != RATE_LIMITED_CODE {
+            return None;
+        }
+        let retry_after = data
+            .and_then(|data| data.get("retryAfterMs"))
+            .and_then(Value::as_u64)
+            .map(Duration::from_millis);
+        Some(Self { retry_after })
+    }
+}
+
+/// How requests turned away by an overloaded server are retried.
+///
+/// Retry `n` (starting at 1) waits the `retry-after` delay of the server, or else
+/// `initial_backoff` doubled `n - 1` times, up to `max_backoff`. Either is spread by a random
+/// jitter of up to half of it, downwards for backoffs, upwards for server delays. A server
+/// delay longer than `max_wait` is not waited for: the request fails.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryPolicy {
+    max_retries: u32,
+    initial_backoff: Duration,
+    max_backoff: Duration,
+    max_wait: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_retries: 3,
+            initial_backoff: Duration::from_millis(200),
+            max_backoff: Duration::from_secs(5),
+            max_wait: Duration::from_secs(10),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Never retries: requests turned away fail right away.
+    #[allow(dead_code)] // Public for consumption by downstream crates.
+    pub fn disabled() -> Self {
+        Self {
+            max_retries: 0,
+            ..Self::default()
+        }
+    }
+
+    /// Gives up after `max_retries` retries of a request (3 by default).
+    #[allow(dead_code)] // Public for consumption by downstream crates.
+    pub fn with_max_retries(&self, max_retries: u32) -> Self {
+        let mut this = *self;
+        this.max_retries = max_retries;
+        this
+    }
+
+    /// Sets the backoff before the first retry, without server delay (200 milliseconds by default).
+    #[allow(dead_code)] // Public for consumption by downstream crates.
+    pub fn with_initial_backoff(&self, initial_backoff: Duration) -> Self {
+        let mut this = *self;
+        this.initial_backoff = initial_backoff;
+        this
+    }
+
+    /// Sets the longest backoff between two retries (5 seconds by default).
+    #[allow(dead_code)] // Public for consumption by downstream crates.
+    pub fn with_max_backoff(&self, max_backoff: Duration) -> Self {
+        let mut this = *self;
+        this.max_backoff = max_backoff;
+        this
+    }
+
+    /// Sets the longest server delay waited for (10 seconds by default).
+    #[allow(dead_code)] // Public for consumption by downstream crates.
+    pub fn with_max_wait(&self, max_wait: Duration) -> Self {
+        let mut this = *self;
+        this.max_wait = max_wait;
+        this
+    }
+
+    /// Wait before retry `retry` (starting at 1) of a request turned away by `overload`, if
+    /// still allowed.
+    pub fn delay(&self, retry: u32, overload: Overload) -> Option<Duration> {
+        if retry > self.max_retries {
+            return None;
+        }
+        match overload.retry_after {
+            Some(retry_after) if retry_after > self.max_wait => None,
+            Some(retry_after) => Some(retry_after + jitter(retry_after / 2)),
+            None => {
+                let factor = 2u32.saturating_pow(retry.saturating_sub(1));
+                let backoff = self
+                    .initial_backoff
+                    .saturating_mul(factor)
+                    .min(self.max_backoff);
+                Some(backoff - jitter(backoff / 2))
+            }
+        }
+    }
+
+    /// Sends `request` until it is not turned away, as told by `overload`, or retries run out.
+    ///
+    /// Requests are only sent while `breaker` is closed: `open` makes the error of a request
+    /// not sent, from the time left until the breaker closes.
+    pub async fn run<T, E, F, Fut>(
+        &self,
+        breaker: &CircuitBreaker,
+        overload: impl Fn(&E) -> Option<Overload>,
+        open: impl Fn(Duration) -> E,
+        mut request: F,
+    ) -> Result<T, E>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T, E>>,
+    {
+        let mut retry = 0;
+        loop {
+            breaker.check().map_err(&open)?;
+            let result = request().await;
+            let turned_away = match &result {
+                Ok(_) => None,
+                Err(error) => overload(error),
+            };
+            let Some(turned_away) = turned_away else {
+                breaker.record_success();
+                return result;
+            };
+            breaker.record_overload(turned_away);
+            retry += 1;
+            let Some(delay) = self.delay(retry, turned_away) else {
+                return result;
+            };
+            tracing::debug!(
+                retry,
+                ?delay,
+                "Request turned away by an overloaded server, retrying"
+            );
+            tokio::time::sleep(delay).await;
+        }
+    }
+}
+
+/// Random duration between zero and `max`.
+fn jitter(max: Duration) -> Duration {
+    // Every `RandomState` is seeded afresh, which is random enough to spread retries.
+    let random = RandomState::new().build_hasher().finish();
+    max.mul_f64((random % 1_000) as f64 / 1_000.0)
+}
+
+#[derive(Debug, Default)]
+struct BreakerState {
+    /// Requests turned away in a row.
+    failures: u32,
+    /// Until when requests fail fast, once open.
+    open_until: Option<Instant>,
+}
+
+/// Stops sending requests to a server turned away `failure_threshold` requests in a row, see
+/// the [module documentation](self).
+///
+/// Clones share their state: share one breaker among the requests to the same server.
+#[derive(Debug, Clone)]
+pub struct CircuitBreaker {
+    failure_threshold: u32,
+    open_for: Duration,
+    state: Arc<Mutex<BreakerState>>,
+}
+
+impl Default for CircuitBreaker {
+    fn default() -> Self {
+        Self {
+            failure_threshold: 5,
+            open_for: Duration::from_secs(10),
+            state: Arc::new(Mutex::new(BreakerState::default())),
+        }
+    }
+}
+
+impl CircuitBreaker {
+    /// Never opens.
+    #[allow(dead_code)] // Public for consumption by downstream crates.
+    pub fn disabled() -> Self {
+        Self {
+            failure_threshold: 0,
+            ..Self::default()
+        }
+    }
+
+    /// Opens after `failure_threshold` requests turned away in a row (5 by default). `0` never opens.
+    #[allow(dead_code)] // Public for consumption by downstream crates.
+    pub fn with_failure_threshold(&self, failure_threshold: u32) -> Self {
+        Self {
+            failure_threshold,
+            state: Arc::new(Mutex::new(BreakerState::default())),
+            ..self.clone()
+        }
+    }
+
+    /// Fails requests fast for `open_for` once open, or for the `retry-after` delay of the
+    /// server if longer (10 seconds by default).
+    #[allow(dead_code)] // Public for consumption by downstream crates.
+    pub fn with_open_for(&self, open_for: Duration) -> Self {
+        Self {
+            open_for,
+            state: Arc::new(Mutex::new(BreakerState::default())),
+            ..self.clone()
+        }
+    }
+
+    /// Breaker of the same settings, not sharing the state of this one, e.g. for another server.
+    #[allow(dead_code)] // Public for consumption by downstream crates.
+    pub fn detached(&self) -> Self {
+        Self {
+            state: Arc::new(Mutex::new(BreakerState::default())),
+            ..self.clone()
+        }
+    }
+
+    /// Whether a request may be sent now, or else the time left until it may.
+    pub fn check(&self) -> Result<(), Duration> {
+        let state = self.state.lock().unwrap();
+        match state.open_until {
+            Some(open_until) => match open_until.checked_duration_since(Instant::now()) {
+                Some(left) if !left.is_zero() => Err(left),
+                _ => Ok(()),
+            },
+            None => Ok(()),
+        }
+    }
+
+    /// Records a request the server processed, closing the breaker.
+    pub fn record_success(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.failures = 0;
+        state.open_until = None;
+    }
+
+    /// Records a request turned away by `overload`, opening the breaker past the threshold,
+    /// or again if it was open.
+    pub fn record_overload(&self, overload: Overload) {
+        if self.failure_threshold == 0 {
+            return;
+        }
+        let mut state = self.state.lock().unwrap();
+        state.failures = state.failures.saturating_add(1);
+        if state.failures >= self.failure_threshold || state.open_until.is_some() {
+            let open_for = overload
+                .retry_after
+                .map_or(self.open_for, |retry_after| retry_after.max(self.open_for));
+            if state.open_until.is_none() {
+                tracing::warn!(
+                    ?open_for,
+                    failures = state.failures,
+                    "Server overloaded, failing requests fast"
+                );
+            }
+            state.open_until = Some(Instant::now() + open_for);
+        }
+    }
+}
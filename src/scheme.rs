@@ -0,0 +1,95 @@
+//! Pluggable payment schemes.
+//!
+//! The facilitator natively implements the `exact` scheme. Other schemes can be added by
+//! downstream crates without forking [`Scheme`]: implement [`SchemeHandler`] and register it
+//! with [`FacilitatorLocal::with_scheme`](crate::facilitator_local::FacilitatorLocal::with_scheme)
+//! on the facilitator side, and with `X402Payments::with_scheme` (in `x402-reqwest`) on the client
+//! side. Payment kinds of registered schemes are advertised via `/supported`.
+//!
+//! A handler identifies its scheme by name, carried as [`Scheme::Custom`] in payloads and
+//! requirements. The payload itself is carried as [`ExactPaymentPayload::Custom`] JSON
+//! and is interpreted by the handler only.
+//!
+//! Every method has a default implementation that fails with
+//! [`FacilitatorLocalError::UnsupportedScheme`], so a handler only needs to implement the side
+//! it is used on: [`SchemeHandler::verify`] and [`SchemeHandler::settle`] for a facilitator,
+//! [`SchemeHandler::payment_payload`] for a client.
+//!
+//! [`ExactPaymentPayload::Custom`]: crate::types::ExactPaymentPayload::Custom
+
+use futures_util::future::BoxFuture;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use crate::chain::FacilitatorLocalError;
+use crate::types::{
+    PaymentPayload, PaymentRequirements, Scheme, SettleRequest, SettleResponse,
+    SupportedPaymentKind, VerifyRequest, VerifyResponse,
+};
+
+/// Implementation of a payment scheme not natively supported by this crate.
+pub trait SchemeHandler: Send + Sync {
+    /// Scheme this handler implements, usually a [`Scheme::Custom`].
+    fn scheme(&self) -> Scheme;
+
+    /// Payment kinds (version, network, extra) supported by this handler, advertised via `/supported`.
+    fn kinds(&self) -> Vec<SupportedPaymentKind>;
+
+    /// Verifies a payment of this scheme. Called by the facilitator.
+    fn verify<'a>(
+        &'a self,
+        request: &'a VerifyRequest,
+    ) -> BoxFuture<'a, Result<VerifyResponse, FacilitatorLocalError>> {
+        let _ = request;
+        Box::pin(async move { Err(FacilitatorLocalError::UnsupportedScheme(self.scheme())) })
+    }
+
+    /// Settles a payment of this scheme. Called by the facilitator.
+    fn settle<'a>(
+        &'a self,
+        request: &'a SettleRequest,
+    ) -> BoxFuture<'a, Result<SettleResponse, FacilitatorLocalError>> {
+        let _ = request;
+        Box::pin(async move { Err(FacilitatorLocalError::UnsupportedScheme(self.scheme())) })
+    }
+
+    /// Constructs a payment payload for `requirements` of this scheme. Called by the client.
+    #[allow(dead_code)] // Public for consumption by downstream crates.
+    fn payment_payload<'a>(
+        &'a self,
+        requirements: &'a PaymentRequirements,
+    ) -> BoxFuture<'a, Result<PaymentPayload, FacilitatorLocalError>> {
+        let _ = requirements;
+        Box::pin(async move { Err(FacilitatorLocalError::UnsupportedScheme(self.scheme())) })
+    }
+}
+
+/// Set of registered [`SchemeHandler`]s, keyed by scheme.
+#[derive(Clone, Default)]
+pub struct SchemeRegistry {
+    handlers: HashMap<Scheme, Arc<dyn SchemeHandler>>,
+}
+
+impl SchemeRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registers `handler`, replacing any handler previously registered for its scheme.
+    pub fn register<H: SchemeHandler + 'static>(&mut self, handler: H) {
+        self.handlers.insert(handler.scheme(), Arc::new(handler));
+    }
+
+    /// Handler registered for `scheme`, if any.
+    pub fn get(&self, scheme: &Scheme) -> Option<&Arc<dyn SchemeHandler>> {
+        self.handlers.get(scheme)
+    }
+
+    /// Payment kinds of all registered handlers.
+    pub fn kinds(&self) -> Vec<SupportedPaymentKind> {
+        self.handlers
+            .values()
+            .flat_map(|handler| handler.kinds())
+            .collect()
+    }
+}
@@ -0,0 +1,215 @@
+//! Idempotency keys of settlement requests: settlements retried by clients are answered again,
+//! never settled twice.
+//!
+//! A client whose `/settle` or `x402.settle` request timed out cannot tell whether the payment was
+//! settled. Settling it again is refused by replay protection (see
+//! [`nonce_store`](crate::nonce_store)), with an error rather than the outcome of the first
+//! settlement. A settlement request may carry an `idempotencyKey`, in its body or WS params, or
+//! the `Idempotency-Key` header of `/settle`. [`SettleIdempotency`] then remembers the
+//! [`SettleResponse`] of the payment under the key, for the TTL, and:
+//!
+//! - answers a request with the same key and the same payment with the remembered response,
+//! - makes a request with the same key arriving while the first is in flight wait for its outcome,
+//! - refuses a request reusing the key for another payment with
+//!   [`FacilitatorLocalError::IdempotencyKeyReused`], reported to clients as the
+//!   `idempotency_key_reused` reason.
+//!
+//! A payment is identified by its payload and its requirements. Errors are not remembered: a
+//! settlement that failed may be retried with the same key. Keys are kept in memory, per
+//! facilitator instance, up to 100 000, least recently used forgotten first. Requests without a
+//! key are settled as usual. Deferred settlements (see
+//! [`settlement_queue`](crate::settlement_queue)) are already idempotent by payment id.
+//!
+//! Environment (see [`SettleIdempotency::from_env`]):
+//! - `SETTLE_IDEMPOTENCY_TTL_SECS` – Time a settlement response is remembered under its key, up to
+//!   7 days (default `86400`)
+
+use alloy::primitives::{B256, keccak256};
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::watch;
+
+use crate::canonical_json;
+use crate::chain::FacilitatorLocalError;
+use crate::duration::{self, DurationError, DurationRange};
+use crate::lru_cache::LruCache;
+use crate::nonce_store::nonce_key;
+use crate::types::{SettleRequest, SettleResponse};
+
+const ENV_SETTLE_IDEMPOTENCY_TTL_SECS: &str = "SETTLE_IDEMPOTENCY_TTL_SECS";
+
+/// Default time a settlement response is remembered.
+const DEFAULT_TTL: Duration = Duration::from_secs(86_400);
+/// TTLs accepted: up to 7 days.
+pub const TTL_RANGE: DurationRange =
+    DurationRange::new(Duration::from_secs(1), Duration::from_secs(7 * 86_400));
+/// Keys remembered, least recently used forgotten first.
+const CAPACITY: usize = 100_000;
+
+/// Error returned by [`SettleIdempotency::from_env`] for a malformed or out of range
+/// `SETTLE_IDEMPOTENCY_TTL_SECS`.
+#[derive(Debug, thiserror::Error)]
+#[error(transparent)]
+pub struct InvalidIdempotencyTtl(#[from] DurationError);
+
+/// Settlements in flight, by key: the payment settled, and its response once settled.
+type InFlight = Arc<Mutex<HashMap<B256, (B256, watch::Receiver<Option<SettleResponse>>)>>>;
+
+/// Settlement responses remembered by idempotency key, see the [module documentation](self).
+///
+/// Clones share the same responses.
+#[derive(Clone)]
+pub struct SettleIdempotency {
+    /// Payment and response, by key.
+    responses: LruCache<B256, (B256, SettleResponse)>,
+    in_flight: InFlight,
+}
+
+impl Default for SettleIdempotency {
+    fn default() -> Self {
+        Self {
+            responses: LruCache::new("settle_idempotency", CAPACITY).with_ttl(DEFAULT_TTL),
+            in_flight: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+}
+
+impl SettleIdempotency {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Reads the TTL from `SETTLE_IDEMPOTENCY_TTL_SECS`, in seconds or with a unit (e.g. `12h`).
+    pub fn from_env() -> Result<Self, InvalidIdempotencyTtl> {
+        let ttl = duration::from_env(
+            ENV_SETTLE_IDEMPOTENCY_TTL_SECS,
+            duration::SECOND,
+            TTL_RANGE,
+        )?;
+        let this = Self::new();
+        Ok(match ttl {
+            Some(ttl) => this.with_ttl(ttl)?,
+            None => this,
+        })
+    }
+
+    /// Remembers settlement responses for `ttl`. Starts from no response remembered.
+    pub fn with_ttl(&self, ttl: Duration) -> Result<Self, DurationError> {
+        let ttl = TTL_RANGE.check(ttl)?;
+        Ok(Self {
+            responses: LruCache::new("settle_idempotency", CAPACITY).with_ttl(ttl),
+            in_flight: Arc::new(Mutex::new(HashMap::new())),
+        })
+    }
+
+    /// Time a settlement response is remembered.
+    pub fn ttl(&self) -> Duration {
+        self.responses.ttl().unwrap_or(DEFAULT_TTL)
+    }
+
+    /// Settles `request` with `settle`, unless a request with the same idempotency key was
+    /// settled or is being settled.
+    pub async fn settle<S, Fut>(
+        &self,
+        request: &SettleRequest,
+        settle: S,
+    ) -> Result<SettleResponse, FacilitatorLocalError>
+    where
+        S: FnOnce() -> Fut,
+        Fut: Future<Output = Result<SettleResponse, FacilitatorLocalError>>,
+    {
+        let (Some(key), Some(payment)) = (
+            request.idempotency_key.as_deref().map(|key| keccak256(key.as_bytes())),
+            payment_key(request),
+        ) else {
+            return settle().await;
+        };
+        let role = {
+            let mut in_flight = self.in_flight.lock().unwrap();
+            if let Some((settled, response)) = self.responses.get(&key) {
+                Role::Settled(settled, response)
+            } else if let Some((settling, receiver)) = in_flight.get(&key) {
+                Role::Follower(*settling, receiver.clone())
+            } else {
+                let (sender, receiver) = watch::channel(None);
+                in_flight.insert(key, (payment, receiver));
+                Role::Leader(Leader {
+                    in_flight: &self.in_flight,
+                    key,
+                    sender,
+                })
+            }
+        };
+        match role {
+            Role::Settled(settled, response) => {
+                check_payment(request, settled, payment)?;
+                tracing::info!("Replayed the settlement of an idempotency key");
+                Ok(response)
+            }
+            Role::Leader(leader) => {
+                let response = settle().await?;
+                self.responses.insert(key, (payment, response.clone()));
+                leader.sender.send_replace(Some(response.clone()));
+                Ok(response)
+            }
+            Role::Follower(settling, mut receiver) => {
+                check_payment(request, settling, payment)?;
+                let shared = receiver
+                    .wait_for(Option::is_some)
+                    .await
+                    .ok()
+                    .and_then(|response| response.clone());
+                match shared {
+                    Some(response) => Ok(response),
+                    // The first settlement failed: its error is not shared
+                    None => settle().await,
+                }
+            }
+        }
+    }
+}
+
+/// Part a request takes in the settlement of its key.
+enum Role<'a> {
+    /// The key was settled, for the given payment, with the given response.
+    Settled(B256, SettleResponse),
+    /// The request settles the key.
+    Leader(Leader<'a>),
+    /// The key is being settled, for the given payment: the request waits for the response.
+    Follower(B256, watch::Receiver<Option<SettleResponse>>),
+}
+
+/// Refuses `request` if its key was used for another payment than its own.
+fn check_payment(
+    request: &SettleRequest,
+    used_for: B256,
+    payment: B256,
+) -> Result<(), FacilitatorLocalError> {
+    if used_for == payment {
+        return Ok(());
+    }
+    let payer = nonce_key(request).and_then(|(_, payer)| payer);
+    tracing::warn!(payer = ?payer, "Refused to settle a payment under an idempotency key used for another");
+    Err(FacilitatorLocalError::IdempotencyKeyReused(payer))
+}
+
+/// Identity of the payment of `request`.
+fn payment_key(request: &SettleRequest) -> Option<B256> {
+    canonical_json::keccak256(&(&request.payment_payload, &request.payment_requirements)).ok()
+}
+
+/// Removes the in-flight entry of a settlement when dropped, so a failed or cancelled first
+/// settlement releases the requests waiting for it.
+struct Leader<'a> {
+    in_flight: &'a InFlight,
+    key: B256,
+    sender: watch::Sender<Option<SettleResponse>>,
+}
+
+impl Drop for Leader<'_> {
+    fn drop(&mut self) {
+        self.in_flight.lock().unwrap().remove(&self.key);
+    }
+}
@@ -0,0 +1,231 @@
+//! Verification of settled payments against the chain, for third parties.
+//!
+//! A seller claiming to have been paid shows the [`SettleResponse`] of its facilitator. A
+//! marketplace auditing the claim does not have to trust either of them: [`verify_settlement`]
+//! looks the settlement transaction up over any RPC endpoint of the network, and checks that it
+//! succeeded and moved the claimed amount of the claimed asset to the claimed recipient.
+//!
+//! ```rust,no_run
+//! use x402_rs::settlement_audit::{PaymentClaim, verify_settlement_at};
+//! use x402_rs::types::{PaymentRequirements, SettleResponse};
+//!
+//! # async fn audit(response: SettleResponse, requirements: PaymentRequirements) -> Result<(), Box<dyn std::error::Error>> {
+//! let claim = PaymentClaim::from_settle_response(&response, &requirements)?;
+//! let settlement = verify_settlement_at("https://sepolia.base.org", &claim).await?;
+//! println!(
+//!     "{} paid to {} in block {:?}, {} confirmations",
+//!     settlement.details.amount,
+//!     settlement.details.to,
+//!     settlement.details.block_number,
+//!     settlement.confirmations,
+//! );
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Only EVM networks are supported.
+
+use alloy::primitives::B256;
+use alloy::providers::{Provider, ProviderBuilder};
+
+use crate::chain::evm::{EvmChain, USDC};
+use crate::network::Network;
+use crate::types::{
+    EvmAddress, MixedAddress, MixedAddressError, PaymentRequirements, SettleResponse,
+    SettlementDetails, TokenAmount, TransactionHash,
+};
+
+/// Errors of [`verify_settlement`].
+#[derive(Debug, thiserror::Error)]
+pub enum AuditError {
+    #[error("Settle response reports a failed settlement")]
+    NotSettled,
+    #[error("Settle response carries no transaction")]
+    MissingTransaction,
+    #[error("Network {0} is not an EVM network")]
+    UnsupportedNetwork(Network),
+    #[error("Invalid address: {0}")]
+    InvalidAddress(#[from] MixedAddressError),
+    #[error("RPC error: {0}")]
+    Rpc(String),
+    #[error("RPC endpoint is on chain {actual}, not on {network} (chain {expected})")]
+    WrongChain {
+        network: Network,
+        expected: u64,
+        actual: u64,
+    },
+    #[error("Transaction {0} not found")]
+    TransactionNotFound(TransactionHash),
+    #[error("Transaction {0} reverted")]
+    Reverted(TransactionHash),
+    /// The transaction does not pay the claim.
+    #[error(
+        "Transaction {} does not transfer {} of {} to {}",
+        .0.transaction, .0.amount, .0.asset, .0.pay_to
+    )]
+    NoMatchingTransfer(Box<PaymentClaim>),
+}
+
+/// A payment claimed to be settled by a transaction.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PaymentClaim {
+    pub network: Network,
+    pub transaction: TransactionHash,
+    /// Payer of the payment, or `None` to accept a transfer from any sender.
+    pub payer: Option<MixedAddress>,
+    pub pay_to: MixedAddress,
+    pub asset: MixedAddress,
+    /// Amount moved to `pay_to`, in token base units.
+    pub amount: TokenAmount,
+}
+
+impl PaymentClaim {
+    /// Claim that `response` settled a payment of `requirements`, by the payer of the response:
+    /// their `consumedAmount` if set, e.g. for `upto` payments, and else their
+    /// `maxAmountRequired`, of their asset to their `payTo`.
+    ///
+    /// # Errors
+    /// Returns [`AuditError::NotSettled`] if the response reports a failure, and
+    /// [`AuditError::MissingTransaction`] if it carries no transaction.
+    pub fn from_settle_response(
+        response: &SettleResponse,
+        requirements: &PaymentRequirements,
+    ) -> Result<Self, AuditError> {
+        if !response.success {
+            return Err(AuditError::NotSettled);
+        }
+        let transaction = response
+            .transaction
+            .clone()
+            .ok_or(AuditError::MissingTransaction)?;
+        Ok(Self {
+            network: response.network,
+            transaction,
+            payer: Some(response.payer.clone()),
+            pay_to: requirements.pay_to.clone(),
+            asset: requirements.asset.clone(),
+            amount: requirements
+                .consumed_amount
+                .unwrap_or(requirements.max_amount_required),
+        })
+    }
+
+    /// Accepts a transfer of `amount` instead.
+    pub fn with_amount<A: Into<TokenAmount>>(self, amount: A) -> Self {
+        Self {
+            amount: amount.into(),
+            ..self
+        }
+    }
+
+    /// Accepts a transfer from any sender, e.g. a payment settled from a smart wallet of the payer.
+    pub fn with_any_payer(self) -> Self {
+        Self {
+            payer: None,
+            ..self
+        }
+    }
+}
+
+/// A settlement found on-chain, see [`verify_settlement`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AuditedSettlement {
+    /// The transfer matching the claim.
+    pub details: SettlementDetails,
+    /// Confirmations of the transaction, `1` being its own block.
+    pub confirmations: u64,
+}
+
+/// Verifies `claim` against the chain over the RPC endpoint `rpc_url`, HTTP or WebSocket.
+///
+/// # Errors
+/// See [`verify_settlement`]; [`AuditError::Rpc`] if the endpoint can not be reached.
+pub async fn verify_settlement_at(
+    rpc_url: &str,
+    claim: &PaymentClaim,
+) -> Result<AuditedSettlement, AuditError> {
+    let provider = ProviderBuilder::default()
+        .connect(rpc_url)
+        .await
+        .map_err(|e| AuditError::Rpc(e.to_string()))?;
+    verify_settlement(&provider, claim).await
+}
+
+/// Verifies `claim` against the chain over `provider`, which must be on the network of the claim.
+///
+/// The transaction must have succeeded and emitted an ERC-20 `Transfer` of exactly the claimed
+/// amount, by the contract of the asset, from the payer if claimed, to the claimed recipient.
+/// Other transfers of the transaction, e.g. of other payments of a batch, are ignored.
+///
+/// # Errors
+/// Returns [`AuditError::WrongChain`] if `provider` is not on the network of the claim,
+/// [`AuditError::TransactionNotFound`] if the transaction is not included in the chain (yet),
+/// [`AuditError::Reverted`] if it failed, and [`AuditError::NoMatchingTransfer`] if it did not
+/// pay the claim.
+pub async fn verify_settlement<P: Provider>(
+    provider: &P,
+    claim: &PaymentClaim,
+) -> Result<AuditedSettlement, AuditError> {
+    let chain = EvmChain::try_from(claim.network)
+        .map_err(|_| AuditError::UnsupportedNetwork(claim.network))?;
+    let TransactionHash::Evm(hash) = claim.transaction else {
+        return Err(AuditError::UnsupportedNetwork(claim.network));
+    };
+    let asset: EvmAddress = claim.asset.clone().try_into()?;
+    let pay_to: EvmAddress = claim.pay_to.clone().try_into()?;
+    let payer: Option<EvmAddress> = claim.payer.clone().map(TryInto::try_into).transpose()?;
+
+    let chain_id = provider
+        .get_chain_id()
+        .await
+        .map_err(|e| AuditError::Rpc(e.to_string()))?;
+    if chain_id != chain.chain_id {
+        return Err(AuditError::WrongChain {
+            network: claim.network,
+            expected: chain.chain_id,
+            actual: chain_id,
+        });
+    }
+    let receipt = provider
+        .get_transaction_receipt(B256::from(hash))
+        .await
+        .map_err(|e| AuditError::Rpc(e.to_string()))?
+        .ok_or_else(|| AuditError::TransactionNotFound(claim.transaction.clone()))?;
+    if !receipt.status() {
+        return Err(AuditError::Reverted(claim.transaction.clone()));
+    }
+    let details = receipt
+        .inner
+        .logs()
+        .iter()
+        .filter(|log| log.address() == asset.0)
+        .find_map(|log| {
+            let event = log.log_decode::<USDC::Transfer>().ok()?.inner.data;
+            let matches = event.to == pay_to.0
+                && event.value == claim.amount.0
+                && payer.is_none_or(|payer| event.from == payer.0);
+            matches.then(|| SettlementDetails {
+                from: EvmAddress(event.from).into(),
+                to: EvmAddress(event.to).into(),
+                amount: event.value.into(),
+                log_index: log.log_index,
+                authorization_log_index: None,
+                block_number: receipt.block_number,
+            })
+        })
+        .ok_or_else(|| AuditError::NoMatchingTransfer(Box::new(claim.clone())))?;
+    let confirmations = match receipt.block_number {
+        Some(block_number) => {
+            let latest = provider
+                .get_block_number()
+                .await
+                .map_err(|e| AuditError::Rpc(e.to_string()))?;
+            latest.saturating_sub(block_number) + 1
+        }
+        None => 0,
+    };
+    Ok(AuditedSettlement {
+        details,
+        confirmations,
+    })
+}
@@ -0,0 +1,371 @@
+//! Hard caps on the amounts settled by the facilitator.
+//!
+//! Requirements are set by sellers: compromised seller credentials, or a bug producing huge
+//! requirements, could make the facilitator broadcast payments far larger than intended.
+//! [`SettlementCaps`] bounds the damage, independently of what the requirements say:
+//! - per settlement, checked at verification too,
+//! - per payer and per day,
+//! - for the whole facilitator, per day.
+//!
+//! Caps are enforced before a settlement is broadcast. The amount of a settlement counts toward
+//! the daily totals from the moment it is accepted. It is only given back if the settlement is
+//! known not to have moved anything, see [`is_unsettled`]: rejected before broadcast, or
+//! reverted. A settlement failing after broadcast, e.g. waiting for its receipt, may still land,
+//! and keeps counting. Days are UTC days, and totals are kept in memory.
+//!
+//! Caps are amounts of whole tokens (e.g. `100` for 100 USDC, or 100 DAI), scaled to the decimals
+//! of each token, and daily totals are kept per token, that is per network and asset. Decimals
+//! are those of the [token registry](crate::tokens::TokenRegistry::from_env): payments of tokens
+//! it does not know are rejected while a cap is set. The amount of an EVM payment is its
+//! authorized or permitted `value`, or the `consumedAmount` of `upto` payments; for other
+//! payments, the `maxAmountRequired` of the requirements. The payer of Solana payments is not
+//! known before settlement, so they only count toward the per-settlement and facilitator caps.
+//!
+//! A payment over a cap is rejected with [`FacilitatorLocalError::AmountCapExceeded`], reported
+//! to clients as the `amount_cap_exceeded` reason. Every rejection is logged as an error and
+//! counted in the `x402_settlement_caps_exceeded` metric, to alert on.
+//!
+//! Environment (see [`SettlementCaps::from_env`]):
+//! - `SETTLEMENT_CAP` – Maximum amount of a single settlement, in whole tokens
+//! - `SETTLEMENT_CAP_PAYER_DAILY` – Maximum amount settled per payer per day, in whole tokens
+//! - `SETTLEMENT_CAP_DAILY` – Maximum amount settled by the facilitator per day, in whole tokens
+//! - `TOKEN_<SYMBOL>_<NETWORK>` – Decimals of custom tokens, see [`crate::tokens`]
+
+use alloy::primitives::U256;
+use std::collections::HashMap;
+use std::env;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+
+use crate::chain::FacilitatorLocalError;
+use crate::network::Network;
+use crate::timestamp::UnixTimestamp;
+use crate::tokens::{TokenRegistry, TokenRegistryError};
+use crate::types::{
+    ExactPaymentPayload, MixedAddress, MoneyAmount, Scheme, SettleResponse, TokenAsset,
+    VerifyRequest,
+};
+
+const ENV_SETTLEMENT_CAP: &str = "SETTLEMENT_CAP";
+const ENV_SETTLEMENT_CAP_PAYER_DAILY: &str = "SETTLEMENT_CAP_PAYER_DAILY";
+const ENV_SETTLEMENT_CAP_DAILY: &str = "SETTLEMENT_CAP_DAILY";
+
+const SECONDS_PER_DAY: u64 = 86_400;
+
+#[derive(Debug, thiserror::Error)]
+pub enum SettlementCapsError {
+    #[error("Invalid {0}: expected an amount of whole tokens, e.g. 100 or 0.5")]
+    InvalidAmount(&'static str),
+    #[error(transparent)]
+    Tokens(#[from] TokenRegistryError),
+}
+
+/// Daily totals are kept per token: per network, and address of the asset on it.
+type TokenKey = (Network, MixedAddress);
+
+/// Amounts counted toward the daily caps for a settlement, given back with
+/// [`SettlementCaps::release`] if it fails.
+#[derive(Debug)]
+pub struct CapReservation {
+    day: u64,
+    token: TokenKey,
+    payer: Option<MixedAddress>,
+    amount: U256,
+}
+
+/// Amounts settled on the current day, per token.
+#[derive(Debug, Default)]
+struct DailyTotals {
+    day: u64,
+    facilitator: HashMap<TokenKey, U256>,
+    payers: HashMap<(TokenKey, MixedAddress), U256>,
+}
+
+impl DailyTotals {
+    /// Starts over if `day` is a new day.
+    fn roll(&mut self, day: u64) {
+        if self.day != day {
+            *self = DailyTotals {
+                day,
+                ..Default::default()
+            };
+        }
+    }
+}
+
+/// Caps on settled amounts, see the [module documentation](self). No cap is set by default.
+///
+/// Clones share the same daily totals.
+#[derive(Debug, Clone)]
+pub struct SettlementCaps {
+    per_settlement: Option<MoneyAmount>,
+    per_payer_daily: Option<MoneyAmount>,
+    daily: Option<MoneyAmount>,
+    /// Decimals of the tokens, the [known](TokenRegistry::known) ones by default.
+    tokens: TokenRegistry,
+    totals: Arc<Mutex<DailyTotals>>,
+}
+
+impl Default for SettlementCaps {
+    fn default() -> Self {
+        Self {
+            per_settlement: None,
+            per_payer_daily: None,
+            daily: None,
+            tokens: TokenRegistry::known(),
+            totals: Arc::default(),
+        }
+    }
+}
+
+impl SettlementCaps {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Reads the caps from `SETTLEMENT_CAP`, `SETTLEMENT_CAP_PAYER_DAILY` and
+    /// `SETTLEMENT_CAP_DAILY`, and the decimals of the tokens from
+    /// [`TokenRegistry::from_env`]. Unset variables leave the matching cap off.
+    pub fn from_env() -> Result<Self, SettlementCapsError> {
+        let read = |name: &'static str| match env::var(name) {
+            Ok(value) if !value.trim().is_empty() => MoneyAmount::from_str(value.trim())
+                .map(Some)
+                .map_err(|_| SettlementCapsError::InvalidAmount(name)),
+            _ => Ok(None),
+        };
+        Ok(Self {
+            per_settlement: read(ENV_SETTLEMENT_CAP)?,
+            per_payer_daily: read(ENV_SETTLEMENT_CAP_PAYER_DAILY)?,
+            daily: read(ENV_SETTLEMENT_CAP_DAILY)?,
+            tokens: TokenRegistry::from_env()?,
+            totals: Arc::default(),
+        })
+    }
+
+    /// Reads the decimals of the tokens from `tokens`.
+    #[allow(dead_code)] // Public for consumption by downstream crates.
+    pub fn with_tokens(&self, tokens: TokenRegistry) -> Self {
+        Self {
+            tokens,
+            ..self.clone()
+        }
+    }
+
+    /// Caps the amount of a single settlement, in whole tokens.
+    #[allow(dead_code)] // Public for consumption by downstream crates.
+    pub fn with_per_settlement(&self, amount: MoneyAmount) -> Self {
+        Self {
+            per_settlement: Some(amount),
+            ..self.clone()
+        }
+    }
+
+    /// Caps the amount settled per payer per day, in whole tokens.
+    #[allow(dead_code)] // Public for consumption by downstream crates.
+    pub fn with_per_payer_daily(&self, amount: MoneyAmount) -> Self {
+        Self {
+            per_payer_daily: Some(amount),
+            ..self.clone()
+        }
+    }
+
+    /// Caps the amount settled by the facilitator per day, in whole tokens.
+    #[allow(dead_code)] // Public for consumption by downstream crates.
+    pub fn with_daily(&self, amount: MoneyAmount) -> Self {
+        Self {
+            daily: Some(amount),
+            ..self.clone()
+        }
+    }
+
+    /// Whether any cap is set.
+    pub fn is_enabled(&self) -> bool {
+        self.per_settlement.is_some() || self.per_payer_daily.is_some() || self.daily.is_some()
+    }
+
+    /// Checks the per-settlement cap, which does not depend on past settlements.
+    pub fn check(&self, request: &VerifyRequest) -> Result<(), FacilitatorLocalError> {
+        let Some(cap) = &self.per_settlement else {
+            return Ok(());
+        };
+        let (amount, payer) = amount_and_payer(request);
+        let cap = self.scaled(cap, request, &payer)?;
+        if amount > cap {
+            return Err(exceeded(
+                payer,
+                format!("Amount {amount} exceeds the per-settlement cap {cap}"),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Checks every cap, and counts the amount of `request` toward the daily totals.
+    /// Returns `None` if no daily cap is set.
+    pub fn reserve(
+        &self,
+        request: &VerifyRequest,
+    ) -> Result<Option<CapReservation>, FacilitatorLocalError> {
+        self.check(request)?;
+        if self.per_payer_daily.is_none() && self.daily.is_none() {
+            return Ok(None);
+        }
+        let (amount, payer) = amount_and_payer(request);
+        let requirements = &request.payment_requirements;
+        let token = (requirements.network, requirements.asset.clone());
+        let asset = &token.1;
+        let daily = self
+            .daily
+            .as_ref()
+            .map(|cap| self.scaled(cap, request, &payer))
+            .transpose()?;
+        let per_payer_daily = self
+            .per_payer_daily
+            .as_ref()
+            .map(|cap| self.scaled(cap, request, &payer))
+            .transpose()?;
+        let day = UnixTimestamp::try_now()
+            .map_err(FacilitatorLocalError::ClockError)?
+            .seconds_since_epoch()
+            / SECONDS_PER_DAY;
+        let mut totals = self.totals.lock().unwrap();
+        totals.roll(day);
+        let facilitator_total = totals
+            .facilitator
+            .get(&token)
+            .copied()
+            .unwrap_or_default()
+            .saturating_add(amount);
+        if let Some(cap) = daily
+            && facilitator_total > cap
+        {
+            return Err(exceeded(
+                payer,
+                format!(
+                    "Amount {amount} exceeds the daily cap {cap} of the facilitator for {asset}"
+                ),
+            ));
+        }
+        let payer_key = payer.clone().map(|payer| (token.clone(), payer));
+        let payer_total = payer_key.as_ref().map(|key| {
+            totals
+                .payers
+                .get(key)
+                .copied()
+                .unwrap_or_default()
+                .saturating_add(amount)
+        });
+        if let (Some(cap), Some(payer_total)) = (per_payer_daily, payer_total)
+            && payer_total > cap
+        {
+            return Err(exceeded(
+                payer,
+                format!("Amount {amount} exceeds the daily cap {cap} of the payer for {asset}"),
+            ));
+        }
+        totals.facilitator.insert(token.clone(), facilitator_total);
+        if let (Some(key), Some(payer_total)) = (payer_key, payer_total) {
+            totals.payers.insert(key, payer_total);
+        }
+        Ok(Some(CapReservation {
+            day,
+            token,
+            payer,
+            amount,
+        }))
+    }
+
+    /// `cap` in base units of the token of `request`, rounded down. Fails for tokens of unknown
+    /// decimals.
+    fn scaled(
+        &self,
+        cap: &MoneyAmount,
+        request: &VerifyRequest,
+        payer: &Option<MixedAddress>,
+    ) -> Result<U256, FacilitatorLocalError> {
+        let requirements = &request.payment_requirements;
+        let asset = TokenAsset {
+            address: requirements.asset.clone(),
+            network: requirements.network,
+        };
+        let Some(deployment) = self.tokens.by_asset(&asset) else {
+            return Err(exceeded(
+                payer.clone(),
+                format!(
+                    "Unknown decimals of {} on {}: register the token to settle it under the caps",
+                    asset.address, asset.network
+                ),
+            ));
+        };
+        let decimals = u32::from(deployment.decimals);
+        let scale = cap.scale();
+        let mantissa = U256::from(cap.mantissa());
+        Ok(if scale <= decimals {
+            mantissa * U256::from(10).pow(U256::from(decimals - scale))
+        } else {
+            mantissa / U256::from(10).pow(U256::from(scale - decimals))
+        })
+    }
+
+    /// Gives back the amount of a settlement that moved nothing, see [`is_unsettled`], if it was
+    /// counted on the current day.
+    pub fn release(&self, reservation: CapReservation) {
+        let mut totals = self.totals.lock().unwrap();
+        if totals.day != reservation.day {
+            return;
+        }
+        if let Some(total) = totals.facilitator.get_mut(&reservation.token) {
+            *total = total.saturating_sub(reservation.amount);
+        }
+        if let Some(payer) = reservation.payer
+            && let Some(total) = totals.payers.get_mut(&(reservation.token, payer))
+        {
+            *total = total.saturating_sub(reservation.amount);
+        }
+    }
+}
+
+/// Whether the settlement answered with `result` is known not to have moved anything: reported
+/// failed, or rejected before its transaction was broadcast, or reverted. Other errors, e.g. a
+/// timeout waiting for the receipt of a broadcast transaction, or of a remote facilitator, may
+/// hide a settlement that lands later.
+pub fn is_unsettled(result: &Result<SettleResponse, FacilitatorLocalError>) -> bool {
+    match result {
+        Ok(response) => !response.success,
+        Err(error) => !matches!(
+            error,
+            FacilitatorLocalError::ContractCall(_) | FacilitatorLocalError::Upstream(..)
+        ),
+    }
+}
+
+/// Amount moved by `request`, and its payer if known before settlement.
+fn amount_and_payer(request: &VerifyRequest) -> (U256, Option<MixedAddress>) {
+    match &request.payment_payload.payload {
+        ExactPaymentPayload::Evm(evm) => (
+            evm.authorization.value.0,
+            Some(evm.authorization.from.into()),
+        ),
+        ExactPaymentPayload::Permit(permit) => {
+            let amount = match request.payment_requirements.scheme {
+                Scheme::Upto => request
+                    .payment_requirements
+                    .consumed_amount
+                    .unwrap_or(request.payment_requirements.max_amount_required),
+                _ => permit.permit.value,
+            };
+            (amount.0, Some(permit.permit.owner.into()))
+        }
+        ExactPaymentPayload::Solana(_) | ExactPaymentPayload::Custom(_) => {
+            (request.payment_requirements.max_amount_required.0, None)
+        }
+    }
+}
+
+fn exceeded(payer: Option<MixedAddress>, message: String) -> FacilitatorLocalError {
+    tracing::error!(
+        monotonic_counter.x402_settlement_caps_exceeded = 1,
+        payer = ?payer,
+        "{message}"
+    );
+    FacilitatorLocalError::AmountCapExceeded(payer, message)
+}
@@ -0,0 +1,511 @@
+//! Settlement lifecycle events.
+//!
+//! `x402.settle` returns once the settlement transaction is included in a block, which is not
+//! finality. [`SettlementEvents`] reports the progress of every settlement instead, so clients
+//! can follow a payment with `x402.subscribe` rather than polling:
+//!
+//! - `submitted` – the payment was accepted for settlement and is being submitted on-chain,
+//! - `mined` – the settlement transaction is included in a block,
+//! - `confirmed` – the transaction reached the [`FinalityPolicy`] of its network,
+//! - `reorged` – a reorganization dropped the mined transaction from the chain before it was
+//!   `confirmed`; `mined` follows if it is included again,
+//! - `failed` – settlement failed; no further event follows.
+//!
+//! On rollups, a number of confirmations says little: blocks of the sequencer can still be
+//! reorganized until their data is posted to L1. A [`FinalityPolicy`] of `safe` or `finalized`
+//! waits for the transaction to be part of the safe head (derived from L1 data) or the finalized
+//! head (derived from finalized L1 blocks) of the node instead.
+//!
+//! A reorganization is noticed when the receipt of the transaction disappears, or moves to
+//! another block. The facilitator then rebroadcasts the transaction, still known to its node,
+//! so that it is included again; it is reported `failed` if it is not before tracking times out.
+//! Reorganizations deeper than the finality policy go unnoticed: the policy is the depth trusted
+//! to be final, and the default of one confirmation trusts the first block.
+//!
+//! Payments are identified by [`PaymentPayload::id`](crate::types::PaymentPayload::id).
+//! The latest event of recent settlements is kept by transaction hash, served by
+//! `GET /settlement/{transaction}` to clients polling rather than subscribing.
+//! Events are only tracked while someone is subscribed, or while statuses are kept.
+//!
+//! Environment (see [`SettlementEvents::from_env`]):
+//! - `SETTLEMENT_CONFIRMATIONS` – Confirmations required on EVM networks before `confirmed` (default `1`)
+//! - `SETTLEMENT_FINALITY` – Finality policy of all EVM networks (`safe`, `finalized`, or a number
+//!   of confirmations), or comma-separated policies per network, e.g. `base=safe,polygon=finalized`
+//! - `SETTLEMENT_STATUS_CAPACITY` – Settlements whose status is kept for polling (default `10000`,
+//!   `0` disables polling)
+
+use alloy::rpc::types::BlockNumberOrTag;
+use serde::{Serialize, Serializer};
+use std::collections::HashMap;
+use std::env;
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+use std::time::Duration;
+use tokio::sync::broadcast;
+
+use crate::chain::evm::EvmProvider;
+use crate::chain::{FacilitatorLocalError, NetworkProvider};
+use crate::lru_cache::LruCache;
+use crate::network::Network;
+use crate::provider_cache::{ProviderCache, ProviderMap};
+use crate::types::{FacilitatorErrorReason, SettleRequest, SettleResponse, TransactionHash};
+
+const ENV_SETTLEMENT_CONFIRMATIONS: &str = "SETTLEMENT_CONFIRMATIONS";
+const ENV_SETTLEMENT_FINALITY: &str = "SETTLEMENT_FINALITY";
+const ENV_SETTLEMENT_STATUS_CAPACITY: &str = "SETTLEMENT_STATUS_CAPACITY";
+
+/// Settlements whose latest event is kept, by default.
+pub const DEFAULT_STATUS_CAPACITY: usize = 10_000;
+/// Time the latest event of a settlement is kept.
+const STATUS_TTL: Duration = Duration::from_secs(24 * 3600);
+
+/// Events buffered per subscriber before the oldest are dropped.
+const SUBSCRIBER_CAPACITY: usize = 1024;
+/// Interval between two checks of the confirmation depth.
+const CONFIRMATION_POLL_INTERVAL: Duration = Duration::from_secs(2);
+/// Time after which confirmations are no longer tracked.
+const CONFIRMATION_TIMEOUT: Duration = Duration::from_secs(600);
+/// Time after which finalization is no longer tracked: rollup blocks take about as long as L1
+/// blocks to finalize, after their data is posted.
+const FINALIZATION_TIMEOUT: Duration = Duration::from_secs(3600);
+
+#[derive(Debug, thiserror::Error)]
+pub enum SettlementEventsError {
+    #[error(
+        "Invalid finality policy: {0}, expected `safe`, `finalized` or a number of confirmations"
+    )]
+    InvalidFinality(String),
+    #[error("Unknown network: {0}")]
+    UnknownNetwork(String),
+    #[error("Invalid {ENV_SETTLEMENT_STATUS_CAPACITY}: {0}, expected a number of settlements")]
+    InvalidStatusCapacity(String),
+}
+
+/// When a settlement on an EVM network is reported `confirmed`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FinalityPolicy {
+    /// Once the transaction has this many confirmations, `1` being its own block.
+    Confirmations(u64),
+    /// Once the block of the transaction is at or below the `safe` head.
+    Safe,
+    /// Once the block of the transaction is at or below the `finalized` head.
+    Finalized,
+}
+
+impl Default for FinalityPolicy {
+    fn default() -> Self {
+        FinalityPolicy::Confirmations(1)
+    }
+}
+
+impl Display for FinalityPolicy {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FinalityPolicy::Confirmations(confirmations) => write!(f, "{confirmations}"),
+            FinalityPolicy::Safe => write!(f, "safe"),
+            FinalityPolicy::Finalized => write!(f, "finalized"),
+        }
+    }
+}
+
+impl FromStr for FinalityPolicy {
+    type Err = SettlementEventsError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim() {
+            "safe" => Ok(FinalityPolicy::Safe),
+            "finalized" => Ok(FinalityPolicy::Finalized),
+            other => other
+                .parse::<u64>()
+                .map(|confirmations| FinalityPolicy::Confirmations(confirmations.max(1)))
+                .map_err(|_| SettlementEventsError::InvalidFinality(s.to_string())),
+        }
+    }
+}
+
+impl Serialize for FinalityPolicy {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl FinalityPolicy {
+    /// Head block the transaction must be below, `None` for a number of confirmations.
+    fn tag(&self) -> Option<BlockNumberOrTag> {
+        match self {
+            FinalityPolicy::Confirmations(_) => None,
+            FinalityPolicy::Safe => Some(BlockNumberOrTag::Safe),
+            FinalityPolicy::Finalized => Some(BlockNumberOrTag::Finalized),
+        }
+    }
+
+    /// Confirmations required, `1` for policies based on a head block.
+    fn confirmations(&self) -> u64 {
+        match self {
+            FinalityPolicy::Confirmations(confirmations) => *confirmations,
+            FinalityPolicy::Safe | FinalityPolicy::Finalized => 1,
+        }
+    }
+
+    fn timeout(&self) -> Duration {
+        match self {
+            FinalityPolicy::Confirmations(_) | FinalityPolicy::Safe => CONFIRMATION_TIMEOUT,
+            FinalityPolicy::Finalized => FINALIZATION_TIMEOUT,
+        }
+    }
+}
+
+/// Stage of a settlement.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum SettlementStatus {
+    Submitted,
+    Mined,
+    Confirmed,
+    Reorged,
+    Failed,
+}
+
+impl SettlementStatus {
+    /// Whether no further event follows.
+    pub fn is_terminal(&self) -> bool {
+        matches!(self, SettlementStatus::Confirmed | SettlementStatus::Failed)
+    }
+}
+
+/// Progress of the settlement of a payment.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SettlementEvent {
+    pub payment_id: String,
+    pub status: SettlementStatus,
+    pub network: Network,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub transaction: Option<TransactionHash>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub block_number: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub confirmations: Option<u64>,
+    /// Finality policy the settlement was `confirmed` by.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub finality: Option<FinalityPolicy>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error_reason: Option<FacilitatorErrorReason>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// [`VerifyRequest::metadata`](crate::types::VerifyRequest::metadata) of the settle request.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub metadata: Option<serde_json::Value>,
+}
+
+impl SettlementEvent {
+    fn new(payment_id: &str, status: SettlementStatus, request: &SettleRequest) -> Self {
+        Self {
+            payment_id: payment_id.to_string(),
+            status,
+            network: request.network(),
+            transaction: None,
+            block_number: None,
+            confirmations: None,
+            finality: None,
+            error_reason: None,
+            error: None,
+            metadata: request.metadata.clone(),
+        }
+    }
+}
+
+/// Publishes settlement lifecycle events to subscribers.
+#[derive(Clone)]
+pub struct SettlementEvents {
+    sender: broadcast::Sender<SettlementEvent>,
+    finality: FinalityPolicy,
+    /// Finality policies overriding `finality` on some networks.
+    network_finality: HashMap<Network, FinalityPolicy>,
+    /// Latest event of recent settlements, by transaction, if kept.
+    statuses: Option<LruCache<TransactionHash, SettlementEvent>>,
+}
+
+impl Default for SettlementEvents {
+    fn default() -> Self {
+        let (sender, _) = broadcast::channel(SUBSCRIBER_CAPACITY);
+        Self {
+            sender,
+            finality: FinalityPolicy::default(),
+            network_finality: HashMap::new(),
+            statuses: None,
+        }
+    }
+}
+
+impl SettlementEvents {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Reads the required confirmations from `SETTLEMENT_CONFIRMATIONS`, the finality
+    /// policies from `SETTLEMENT_FINALITY`, and the number of statuses kept from
+    /// `SETTLEMENT_STATUS_CAPACITY`.
+    pub fn from_env() -> Result<Self, SettlementEventsError> {
+        let capacity = match env::var(ENV_SETTLEMENT_STATUS_CAPACITY) {
+            Ok(capacity) => capacity
+                .trim()
+                .parse()
+                .map_err(|_| SettlementEventsError::InvalidStatusCapacity(capacity))?,
+            Err(_) => DEFAULT_STATUS_CAPACITY,
+        };
+        let mut events = Self::new();
+        if capacity > 0 {
+            events = events.with_statuses(capacity);
+        }
+        if let Some(confirmations) = env::var(ENV_SETTLEMENT_CONFIRMATIONS)
+            .ok()
+            .and_then(|s| s.parse().ok())
+        {
+            events = events.with_confirmations(confirmations);
+        }
+        let finality = env::var(ENV_SETTLEMENT_FINALITY).unwrap_or_default();
+        for entry in finality.split(',').map(str::trim).filter(|s| !s.is_empty()) {
+            events = match entry.split_once('=') {
+                Some((name, policy)) => {
+                    let name = name.trim();
+                    let network = Network::variants()
+                        .iter()
+                        .find(|network| network.to_string() == name)
+                        .ok_or_else(|| SettlementEventsError::UnknownNetwork(name.to_string()))?;
+                    events.with_network_finality(*network, policy.parse()?)
+                }
+                None => events.with_finality(entry.parse()?),
+            };
+        }
+        Ok(events)
+    }
+
+    /// Sets the confirmations required on EVM networks before a settlement is `confirmed`.
+    /// Defaults to 1, i.e. `confirmed` immediately follows `mined`.
+    pub fn with_confirmations(&self, confirmations: u64) -> Self {
+        self.with_finality(FinalityPolicy::Confirmations(confirmations.max(1)))
+    }
+
+    /// Sets the finality policy of EVM networks without a policy of their own.
+    pub fn with_finality(&self, finality: FinalityPolicy) -> Self {
+        let mut this = self.clone();
+        this.finality = finality;
+        this
+    }
+
+    /// Sets the finality policy of `network`, e.g. [`FinalityPolicy::Safe`] on a rollup.
+    pub fn with_network_finality(&self, network: Network, finality: FinalityPolicy) -> Self {
+        let mut this = self.clone();
+        this.network_finality.insert(network, finality);
+        this
+    }
+
+    /// Keeps the latest event of the last `capacity` settlements, for a day, served by
+    /// [`SettlementEvents::status`].
+    pub fn with_statuses(&self, capacity: usize) -> Self {
+        let mut this = self.clone();
+        this.statuses = Some(LruCache::new("settlement_statuses", capacity).with_ttl(STATUS_TTL));
+        this
+    }
+
+    /// Latest event of the settlement by `transaction`, if kept.
+    pub fn status(&self, transaction: &TransactionHash) -> Option<SettlementEvent> {
+        self.statuses.as_ref()?.get(transaction)
+    }
+
+    /// Finality policy of settlements on `network`.
+    pub fn finality(&self, network: Network) -> FinalityPolicy {
+        self.network_finality
+            .get(&network)
+            .copied()
+            .unwrap_or(self.finality)
+    }
+
+    /// Receives every settlement event published from now on.
+    pub fn subscribe(&self) -> broadcast::Receiver<SettlementEvent> {
+        self.sender.subscribe()
+    }
+
+    /// Whether anyone is subscribed or statuses are kept, i.e. events need to be tracked.
+    pub fn is_observed(&self) -> bool {
+        self.statuses.is_some() || self.sender.receiver_count() > 0
+    }
+
+    fn publish(&self, event: SettlementEvent) {
+        tracing::debug!(payment_id = %event.payment_id, status = ?event.status, "Settlement event");
+        if let (Some(statuses), Some(transaction)) = (&self.statuses, &event.transaction) {
+            statuses.insert(transaction.clone(), event.clone());
+        }
+        // No subscriber is not an error.
+        let _ = self.sender.send(event);
+    }
+
+    /// Reports that the settlement of payment `payment_id` starts.
+    pub fn submitted(&self, payment_id: &str, request: &SettleRequest) {
+        self.publish(SettlementEvent::new(
+            payment_id,
+            SettlementStatus::Submitted,
+            request,
+        ));
+    }
+
+    /// Reports the outcome of the settlement of payment `payment_id`, and keeps tracking its
+    /// confirmations in a background task on EVM networks.
+    pub fn settled(
+        &self,
+        payment_id: &str,
+        request: &SettleRequest,
+        result: &Result<SettleResponse, FacilitatorLocalError>,
+        provider_cache: &ProviderCache,
+    ) {
+        let response = match result {
+            Ok(response) => response,
+            Err(error) => {
+                let mut event = SettlementEvent::new(payment_id, SettlementStatus::Failed, request);
+                event.error = Some(error.to_string());
+                self.publish(event);
+                return;
+            }
+        };
+        if !response.success {
+            let mut event = SettlementEvent::new(payment_id, SettlementStatus::Failed, request);
+            event.transaction = response.transaction.clone();
+            event.error_reason = response.error_reason.clone();
+            self.publish(event);
+            return;
+        }
+        let mut mined = SettlementEvent::new(payment_id, SettlementStatus::Mined, request);
+        mined.transaction = response.transaction.clone();
+        mined.block_number = response
+            .settlement
+            .as_ref()
+            .and_then(|settlement| settlement.block_number);
+        self.publish(mined.clone());
+
+        match (
+            provider_cache.by_network(request.network()),
+            &response.transaction,
+        ) {
+            (Some(NetworkProvider::Evm(provider)), Some(TransactionHash::Evm(hash))) => {
+                let this = self.clone();
+                let provider = provider.clone();
+                let hash = (*hash).into();
+                let finality = self.finality(request.network());
+                tokio::spawn(async move {
+                    this.await_finality(&provider, hash, finality, mined).await;
+                });
+            }
+            // Solana settlements and custom schemes are only reported once confirmed.
+            _ => {
+                let mut confirmed = mined;
+                confirmed.status = SettlementStatus::Confirmed;
+                self.publish(confirmed);
+            }
+        }
+    }
+
+    /// Publishes `confirmed` once transaction `hash` reached `finality`, and `reorged` whenever
+    /// a reorganization drops it from the chain, or `failed` if it is not included again.
+    async fn await_finality(
+        &self,
+        provider: &EvmProvider,
+        hash: alloy::primitives::B256,
+        finality: FinalityPolicy,
+        mut event: SettlementEvent,
+    ) {
+        let deadline = tokio::time::Instant::now() + finality.timeout();
+        let mut interval = tokio::time::interval(CONFIRMATION_POLL_INTERVAL);
+        // Block the transaction was last seen in
+        let mut mined_in = event.block_number;
+        let mut reorged = false;
+        loop {
+            interval.tick().await;
+            if tokio::time::Instant::now() >= deadline {
+                if reorged {
+                    event.status = SettlementStatus::Failed;
+                    event.confirmations = None;
+                    event.error = Some("Transaction dropped by a chain reorganization".to_string());
+                    self.publish(event);
+                } else {
+                    tracing::warn!(payment_id = %event.payment_id, "Gave up tracking settlement confirmations");
+                }
+                return;
+            }
+            let included = match provider.transaction_block_number(hash).await {
+                Ok(included) => included,
+                Err(error) => {
+                    tracing::warn!(?error, "Failed to check settlement confirmations");
+                    continue;
+                }
+            };
+            if let Some(previous) = mined_in
+                && included != Some(previous)
+            {
+                tracing::warn!(payment_id = %event.payment_id, %hash, block = previous, "Settlement transaction reorganized");
+                event.status = SettlementStatus::Reorged;
+                event.block_number = None;
+                event.confirmations = None;
+                self.publish(event.clone());
+                mined_in = None;
+                reorged = true;
+                if included.is_none() {
+                    match provider.rebroadcast(hash).await {
+                        Ok(true) => {}
+                        Ok(false) => {
+                            tracing::warn!(%hash, "Reorganized settlement transaction unknown to the node")
+                        }
+                        Err(error) => {
+                            tracing::warn!(?error, %hash, "Failed to rebroadcast settlement transaction")
+                        }
+                    }
+                }
+            }
+            let Some(included) = included else {
+                continue;
+            };
+            if reorged {
+                event.status = SettlementStatus::Mined;
+                event.block_number = Some(included);
+                self.publish(event.clone());
+                reorged = false;
+            }
+            mined_in = Some(included);
+            let depth = async {
+                let latest = provider.block_number().await?;
+                let confirmations = latest.saturating_sub(included) + 1;
+                let reached = match finality.tag() {
+                    Some(tag) => provider
+                        .tagged_block_number(tag)
+                        .await?
+                        .is_some_and(|head| head >= included),
+                    None => confirmations >= finality.confirmations(),
+                };
+                Ok::<_, FacilitatorLocalError>((confirmations, reached))
+            }
+            .await;
+            match depth {
+                Ok((confirmations, true)) => {
+                    event.status = SettlementStatus::Confirmed;
+                    event.block_number = Some(included);
+                    event.confirmations = Some(confirmations);
+                    event.finality = Some(finality);
+                    self.publish(event);
+                    return;
+                }
+                Ok((confirmations, false)) => {
+                    // Kept for polling, not pushed: subscribers are told of changes of status only
+                    if let (Some(statuses), Some(transaction)) =
+                        (&self.statuses, &event.transaction)
+                    {
+                        event.confirmations = Some(confirmations);
+                        statuses.insert(transaction.clone(), event.clone());
+                    }
+                }
+                Err(error) => {
+                    tracing::warn!(?error, "Failed to check settlement confirmations");
+                }
+            }
+        }
+    }
+}
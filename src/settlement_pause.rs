@@ -0,0 +1,72 @@
+//! Settlement paused per network, at runtime, without refusing verifications.
+//!
+//! During an incident on a network (a congested chain, a settlement account running out of gas, a
+//! token contract paused by its issuer), an operator may stop broadcasting settlement
+//! transactions while keeping verifications going, so that sellers keep serving buyers whose
+//! payments are valid. Settlements on a network of [`SettlementPause`] are refused with
+//! [`FacilitatorLocalError::SettlementPaused`], reported over HTTP as `503 Service Unavailable`,
+//! which clients retry later (see [`retry`](crate::retry)), and as the `settlement_paused` reason
+//! otherwise. Settlements already in flight complete.
+//!
+//! Payments queued for deferred settlement (see [`settlement_queue`](crate::settlement_queue))
+//! stay queued while their network is paused, without using up their attempts, and are settled
+//! once it is resumed.
+//!
+//! Unlike [`DisabledNetworks`](crate::disabled_networks::DisabledNetworks), which stops serving
+//! a network altogether, a paused network is still listed by `/supported`.
+//!
+//! Networks are paused and resumed through the admin API (`/admin/paused-networks`); clones share
+//! the same networks. Nothing is paused at startup.
+
+use std::collections::HashSet;
+use std::sync::{Arc, RwLock};
+
+use crate::chain::FacilitatorLocalError;
+use crate::network::Network;
+
+/// Networks whose settlements are paused, see the [module documentation](self).
+#[derive(Debug, Clone, Default)]
+pub struct SettlementPause(Arc<RwLock<HashSet<Network>>>);
+
+impl SettlementPause {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Networks whose settlements are paused.
+    pub fn networks(&self) -> HashSet<Network> {
+        self.0.read().unwrap().clone()
+    }
+
+    /// Pauses the settlements on `network`, for every clone. Returns `false` if already paused.
+    pub fn pause(&self, network: Network) -> bool {
+        let paused = self.0.write().unwrap().insert(network);
+        if paused {
+            tracing::warn!(%network, "Settlement paused");
+        }
+        paused
+    }
+
+    /// Resumes the settlements on `network`, for every clone. Returns `false` if not paused.
+    pub fn resume(&self, network: Network) -> bool {
+        let resumed = self.0.write().unwrap().remove(&network);
+        if resumed {
+            tracing::info!(%network, "Settlement resumed");
+        }
+        resumed
+    }
+
+    /// Whether the settlements on `network` are paused.
+    pub fn is_paused(&self, network: Network) -> bool {
+        self.0.read().unwrap().contains(&network)
+    }
+
+    /// Refuses the settlements on `network` if paused.
+    pub fn check(&self, network: Network) -> Result<(), FacilitatorLocalError> {
+        if self.is_paused(network) {
+            Err(FacilitatorLocalError::SettlementPaused(network))
+        } else {
+            Ok(())
+        }
+    }
+}
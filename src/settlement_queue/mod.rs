@@ -0,0 +1,615 @@
+//! Deferred settlement: payments queued durably and settled by a background worker.
+//!
+//! Settling on-chain takes seconds, during which an `x402.settle` request is held open. With
+//! [`SettleMode::Deferred`], `x402.settle` verifies the payment, persists it to a
+//! [`SettlementQueue`] and returns right away with the `queued` status. A background worker (see
+//! [`SettlementQueue::start`]) then settles queued payments, retrying transient failures (RPC
+//! errors, dropped transactions) with exponential backoff. Progress is available from
+//! `x402.settlementStatus`, by payment id, and pushed to `x402.subscribe` subscribers as usual.
+//!
+//! Payments are identified by [`PaymentPayload::id`](crate::types::PaymentPayload::id); payments
+//! without an id are settled immediately. Before every attempt, the worker claims the payment in
+//! the store, atomically (see [`SettlementStore::claim`]): facilitator instances sharing a store
+//! never attempt the same payment at once. A claim is a lease of 5 minutes: payments interrupted
+//! by a restart, or by an instance gone, while `settling` are settled again once their lease
+//! expired; replay protection (see [`nonce_store`](crate::nonce_store)) keeps an authorization
+//! from being executed twice. [`SettlementQueue::stop`] stops the worker on shutdown, once its
+//! attempts in flight are recorded.
+//!
+//! Payments on a network whose settlements are paused (see
+//! [`settlement_pause`](crate::settlement_pause)), or whose settlement accounts are short of gas
+//! (see [`gas_monitor`](crate::gas_monitor)), stay queued, without using up their attempts.
+//! [`SettlementQueue::drain`] has the worker attempt every queued payment at once, regardless of
+//! backoff, e.g. once an incident is over.
+//!
+//! Backends:
+//! - [`InMemorySettlementStore`] – default; loses queued payments on restart
+//! - [`SledSettlementStore`](sled::SledSettlementStore) – embedded database on disk (`sled` feature)
+//! - [`PostgresSettlementStore`](postgres::PostgresSettlementStore) – PostgreSQL database (`postgres` feature)
+//!
+//! Environment (see [`SettleMode::from_env`]):
+//! - `SETTLE_MODE` – `immediate` (default) or `deferred`
+//! - `SETTLEMENT_QUEUE_URL` – `sled:<path>` or `postgres://...`; queued payments are kept in memory if unset
+//! - `SETTLEMENT_QUEUE_MAX_ATTEMPTS` – Settlement attempts of a payment before it is failed (default `5`)
+
+use futures_util::StreamExt;
+use futures_util::future::BoxFuture;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::env;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::{Notify, watch};
+use tokio::task::JoinHandle;
+use tokio::time::Instant;
+
+use crate::chain::FacilitatorLocalError;
+use crate::facilitator::Facilitator;
+use crate::lru_cache::LruCache;
+use crate::timestamp::UnixTimestamp;
+use crate::types::{SettleRequest, SettleResponse};
+
+#[cfg(feature = "postgres")]
+pub mod postgres;
+#[cfg(feature = "sled")]
+pub mod sled;
+
+const ENV_SETTLE_MODE: &str = "SETTLE_MODE";
+const ENV_SETTLEMENT_QUEUE_URL: &str = "SETTLEMENT_QUEUE_URL";
+const ENV_SETTLEMENT_QUEUE_MAX_ATTEMPTS: &str = "SETTLEMENT_QUEUE_MAX_ATTEMPTS";
+
+/// Default number of settlement attempts of a payment.
+const DEFAULT_MAX_ATTEMPTS: u32 = 5;
+/// Delay before the first retry, doubled on every further attempt.
+const INITIAL_BACKOFF: Duration = Duration::from_secs(2);
+/// Maximum delay between two attempts.
+const MAX_BACKOFF: Duration = Duration::from_secs(300);
+/// Interval between two scans of the queue when idle, picking up payments queued by other
+/// facilitator instances sharing the store.
+const POLL_INTERVAL: Duration = Duration::from_secs(5);
+/// Time a payment is held by the instance settling it, past which it is taken over.
+const SETTLING_LEASE: Duration = Duration::from_secs(300);
+/// Payments settled at once by the worker.
+const CONCURRENCY: usize = 16;
+/// Finished settlements remembered by the in-memory store.
+const FINISHED_CAPACITY: usize = 100_000;
+
+#[derive(Debug, thiserror::Error)]
+pub enum SettlementQueueError {
+    #[error("Unsupported {ENV_SETTLE_MODE}: {0}")]
+    UnsupportedMode(String),
+    #[error("Unsupported {ENV_SETTLEMENT_QUEUE_URL}: {0}")]
+    UnsupportedUrl(String),
+    #[error("Settlement queue backend error: {0}")]
+    #[allow(dead_code)] // Constructed by the `sled` and `postgres` backends.
+    Backend(String),
+}
+
+/// How `x402.settle` settles payments.
+#[derive(Clone, Default)]
+pub enum SettleMode {
+    /// Settles the payment before replying, with the [`SettleResponse`].
+    #[default]
+    Immediate,
+    /// Queues the payment, and replies with its [`DeferredSettlement`] status.
+    Deferred(SettlementQueue),
+}
+
+impl SettleMode {
+    /// Reads the mode from `SETTLE_MODE`, opening the queue configured by `SETTLEMENT_QUEUE_URL`
+    /// in `deferred` mode. The worker of the queue must be started separately, see
+    /// [`SettlementQueue::start`].
+    pub async fn from_env() -> Result<Self, SettlementQueueError> {
+        match env::var(ENV_SETTLE_MODE).ok().as_deref() {
+            None | Some("") | Some("immediate") => Ok(SettleMode::Immediate),
+            Some("deferred") => {
+                let queue = SettlementQueue::new(store_from_env().await?);
+                let queue = match env::var(ENV_SETTLEMENT_QUEUE_MAX_ATTEMPTS)
+                    .ok()
+                    .and_then(|s| s.parse().ok())
+                {
+                    Some(max_attempts) => queue.with_max_attempts(max_attempts),
+                    None => queue,
+                };
+                Ok(SettleMode::Deferred(queue))
+            }
+            Some(mode) => Err(SettlementQueueError::UnsupportedMode(mode.to_string())),
+        }
+    }
+
+    /// Queue of the payments to settle, in deferred mode.
+    pub fn queue(&self) -> Option<&SettlementQueue> {
+        match self {
+            SettleMode::Immediate => None,
+            SettleMode::Deferred(queue) => Some(queue),
+        }
+    }
+}
+
+/// Stage of a deferred settlement.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum DeferredStatus {
+    /// Waiting for its first or next settlement attempt.
+    Queued,
+    /// Being settled on-chain.
+    Settling,
+    /// Settled successfully; see the response.
+    Settled,
+    /// Settlement failed for good; see the response or the error.
+    Failed,
+}
+
+impl DeferredStatus {
+    /// Whether the settlement is over.
+    pub fn is_finished(&self) -> bool {
+        matches!(self, DeferredStatus::Settled | DeferredStatus::Failed)
+    }
+}
+
+/// Progress of a deferred settlement, as returned by `x402.settle` and `x402.settlementStatus`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DeferredSettlement {
+    pub payment_id: String,
+    pub status: DeferredStatus,
+    /// Settlement attempts so far.
+    pub attempts: u32,
+    pub queued_at: UnixTimestamp,
+    pub updated_at: UnixTimestamp,
+    /// Outcome of the last attempt that reached the chain.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub response: Option<SettleResponse>,
+    /// Error of the last failed attempt.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// A payment in a [`SettlementStore`]: the request to settle, and its progress.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct QueuedSettlement {
+    pub request: SettleRequest,
+    pub progress: DeferredSettlement,
+    /// Earliest time of the next attempt, for queued payments.
+    pub next_attempt_at: UnixTimestamp,
+    /// End of the lease of the instance settling the payment, for `settling` payments. Past it,
+    /// the payment can be claimed by another instance, see [`SettlementStore::claim`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub lease_expires_at: Option<UnixTimestamp>,
+}
+
+impl QueuedSettlement {
+    /// Whether the payment can be claimed at `now` by an instance that read it after `attempts`
+    /// attempts: queued, or settling by an instance whose lease expired, and not attempted since.
+    pub fn is_claimable(&self, attempts: u32, now: UnixTimestamp) -> bool {
+        let available = match self.progress.status {
+            DeferredStatus::Queued => true,
+            DeferredStatus::Settling => self.lease_expires_at.is_none_or(|lease| lease <= now),
+            DeferredStatus::Settled | DeferredStatus::Failed => false,
+        };
+        available && self.progress.attempts == attempts
+    }
+
+    /// Whether the worker should attempt the payment at `now`: queued and due, or `draining`, or
+    /// settling by an instance whose lease expired.
+    fn is_due(&self, now: UnixTimestamp, draining: bool) -> bool {
+        match self.progress.status {
+            DeferredStatus::Queued => draining || self.next_attempt_at <= now,
+            DeferredStatus::Settling => self.is_claimable(self.progress.attempts, now),
+            DeferredStatus::Settled | DeferredStatus::Failed => false,
+        }
+    }
+
+    /// Time at which the payment becomes due, if later than `now`.
+    fn due_at(&self, now: UnixTimestamp) -> Option<UnixTimestamp> {
+        let at = match self.progress.status {
+            DeferredStatus::Queued => Some(self.next_attempt_at),
+            DeferredStatus::Settling => self.lease_expires_at,
+            DeferredStatus::Settled | DeferredStatus::Failed => None,
+        };
+        at.filter(|at| *at > now)
+    }
+}
+
+/// Durable record of deferred settlements, keyed by payment id.
+pub trait SettlementStore: Send + Sync {
+    /// Inserts or replaces the entry of `entry.progress.payment_id`.
+    fn put<'a>(
+        &'a self,
+        entry: &'a QueuedSettlement,
+    ) -> BoxFuture<'a, Result<(), SettlementQueueError>>;
+
+    /// Entry of `payment_id`, if any.
+    fn get<'a>(
+        &'a self,
+        payment_id: &'a str,
+    ) -> BoxFuture<'a, Result<Option<QueuedSettlement>, SettlementQueueError>>;
+
+    /// Entries not finished yet, i.e. `queued` or `settling`.
+    fn pending(&self) -> BoxFuture<'_, Result<Vec<QueuedSettlement>, SettlementQueueError>>;
+
+    /// Replaces the entry of `entry.progress.payment_id` with `entry`, a `settling` entry, only if
+    /// the stored entry is still [claimable](QueuedSettlement::is_claimable) after `attempts`
+    /// attempts at `now`, atomically. Returns whether the entry was claimed: if not, another
+    /// instance is settling the payment, or did since it was read.
+    fn claim<'a>(
+        &'a self,
+        entry: &'a QueuedSettlement,
+        attempts: u32,
+        now: UnixTimestamp,
+    ) -> BoxFuture<'a, Result<bool, SettlementQueueError>>;
+
+    /// Writes the entries put so far to persistent storage.
+    fn flush(&self) -> BoxFuture<'_, Result<(), SettlementQueueError>> {
+        Box::pin(async { Ok(()) })
+    }
+
+    /// Whether the entries survive a restart.
+    fn is_persistent(&self) -> bool {
+        true
+    }
+}
+
+/// Settlements kept in memory, for the lifetime of the process. Finished settlements are
+/// forgotten, least recently used first, past 100 000.
+pub struct InMemorySettlementStore {
+    pending: Mutex<HashMap<String, QueuedSettlement>>,
+    finished: LruCache<String, QueuedSettlement>,
+}
+
+impl Default for InMemorySettlementStore {
+    fn default() -> Self {
+        Self {
+            pending: Mutex::new(HashMap::new()),
+            finished: LruCache::new("finished_settlements", FINISHED_CAPACITY),
+        }
+    }
+}
+
+impl InMemorySettlementStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl SettlementStore for InMemorySettlementStore {
+    fn put<'a>(
+        &'a self,
+        entry: &'a QueuedSettlement,
+    ) -> BoxFuture<'a, Result<(), SettlementQueueError>> {
+        let payment_id = entry.progress.payment_id.clone();
+        let mut pending = self.pending.lock().unwrap();
+        if entry.progress.status.is_finished() {
+            pending.remove(&payment_id);
+            self.finished.insert(payment_id, entry.clone());
+        } else {
+            self.finished.remove(&payment_id);
+            pending.insert(payment_id, entry.clone());
+        }
+        Box::pin(async { Ok(()) })
+    }
+
+    fn get<'a>(
+        &'a self,
+        payment_id: &'a str,
+    ) -> BoxFuture<'a, Result<Option<QueuedSettlement>, SettlementQueueError>> {
+        let entry = self
+            .pending
+            .lock()
+            .unwrap()
+            .get(payment_id)
+            .cloned()
+            .or_else(|| self.finished.get(&payment_id.to_string()));
+        Box::pin(async move { Ok(entry) })
+    }
+
+    fn pending(&self) -> BoxFuture<'_, Result<Vec<QueuedSettlement>, SettlementQueueError>> {
+        let pending = self.pending.lock().unwrap().values().cloned().collect();
+        Box::pin(async move { Ok(pending) })
+    }
+
+    fn claim<'a>(
+        &'a self,
+        entry: &'a QueuedSettlement,
+        attempts: u32,
+        now: UnixTimestamp,
+    ) -> BoxFuture<'a, Result<bool, SettlementQueueError>> {
+        let mut pending = self.pending.lock().unwrap();
+        let claimed = match pending.get_mut(&entry.progress.payment_id) {
+            Some(stored) if stored.is_claimable(attempts, now) => {
+                *stored = entry.clone();
+                true
+            }
+            _ => false,
+        };
+        Box::pin(async move { Ok(claimed) })
+    }
+
+    fn is_persistent(&self) -> bool {
+        false
+    }
+}
+
+/// Opens the store configured by `SETTLEMENT_QUEUE_URL`, or an [`InMemorySettlementStore`]
+/// if unset.
+async fn store_from_env() -> Result<Arc<dyn SettlementStore>, SettlementQueueError> {
+    let Some(url) = env::var(ENV_SETTLEMENT_QUEUE_URL)
+        .ok()
+        .filter(|url| !url.is_empty())
+    else {
+        return Ok(Arc::new(InMemorySettlementStore::new()));
+    };
+    #[cfg(feature = "sled")]
+    if let Some(path) = url.strip_prefix("sled:") {
+        return Ok(Arc::new(sled::SledSettlementStore::open(path)?));
+    }
+    #[cfg(feature = "postgres")]
+    if url.starts_with("postgres://") || url.starts_with("postgresql://") {
+        return Ok(Arc::new(
+            postgres::PostgresSettlementStore::connect(&url).await?,
+        ));
+    }
+    Err(SettlementQueueError::UnsupportedUrl(url))
+}
+
+/// Queue of deferred settlements, see the [module documentation](self).
+///
+/// Clones share the same queue.
+#[derive(Clone)]
+pub struct SettlementQueue {
+    store: Arc<dyn SettlementStore>,
+    /// Wakes the worker up when a payment is queued.
+    queued: Arc<Notify>,
+    /// Set to attempt every queued payment on the next scan, see [`SettlementQueue::drain`].
+    draining: Arc<AtomicBool>,
+    /// Set when the worker is to stop, see [`SettlementQueue::stop`].
+    stopping: Arc<watch::Sender<bool>>,
+    worker: Arc<Mutex<Option<JoinHandle<()>>>>,
+    max_attempts: u32,
+}
+
+impl SettlementQueue {
+    /// Creates a queue persisted in `store`.
+    pub fn new(store: Arc<dyn SettlementStore>) -> Self {
+        Self {
+            store,
+            queued: Arc::new(Notify::new()),
+            draining: Arc::new(AtomicBool::new(false)),
+            stopping: Arc::new(watch::Sender::new(false)),
+            worker: Arc::new(Mutex::new(None)),
+            max_attempts: DEFAULT_MAX_ATTEMPTS,
+        }
+    }
+
+    /// Sets the number of settlement attempts of a payment before it is failed (at least one).
+    pub fn with_max_attempts(&self, max_attempts: u32) -> Self {
+        let mut this = self.clone();
+        this.max_attempts = max_attempts.max(1);
+        this
+    }
+
+    /// Queues the settlement of payment `payment_id`. Queuing a payment again returns its
+    /// current progress, unless its settlement failed, in which case it is queued anew.
+    pub async fn enqueue(
+        &self,
+        payment_id: &str,
+        request: &SettleRequest,
+    ) -> Result<DeferredSettlement, SettlementQueueError> {
+        if let Some(entry) = self.store.get(payment_id).await?
+            && entry.progress.status != DeferredStatus::Failed
+        {
+            return Ok(entry.progress);
+        }
+        let now = now();
+        let entry = QueuedSettlement {
+            request: request.clone(),
+            progress: DeferredSettlement {
+                payment_id: payment_id.to_string(),
+                status: DeferredStatus::Queued,
+                attempts: 0,
+                queued_at: now,
+                updated_at: now,
+                response: None,
+                error: None,
+            },
+            next_attempt_at: now,
+            lease_expires_at: None,
+        };
+        self.store.put(&entry).await?;
+        self.queued.notify_one();
+        tracing::info!(payment_id, "Settlement queued");
+        Ok(entry.progress)
+    }
+
+    /// Progress of the settlement of payment `payment_id`, if it was queued.
+    pub async fn status(
+        &self,
+        payment_id: &str,
+    ) -> Result<Option<DeferredSettlement>, SettlementQueueError> {
+        Ok(self
+            .store
+            .get(payment_id)
+            .await?
+            .map(|entry| entry.progress))
+    }
+
+    /// Payments queued or being settled.
+    pub async fn pending(&self) -> Result<Vec<QueuedSettlement>, SettlementQueueError> {
+        self.store.pending().await
+    }
+
+    /// Has the worker attempt every queued payment right away, regardless of backoff. Payments on
+    /// paused networks stay queued.
+    pub fn drain(&self) {
+        self.draining.store(true, Ordering::Relaxed);
+        self.queued.notify_one();
+        tracing::info!("Settlement queue drained");
+    }
+
+    /// Starts the background worker settling queued payments with `facilitator`.
+    /// Must be called from within a Tokio runtime.
+    pub fn start<F>(&self, facilitator: F)
+    where
+        F: Facilitator<Error: Into<FacilitatorLocalError>> + Send + Sync + 'static,
+    {
+        let this = self.clone();
+        let worker = tokio::spawn(async move { this.run(facilitator).await });
+        *self.worker.lock().unwrap() = Some(worker);
+    }
+
+    /// Stops the worker: no settlement attempt is started anymore, and the attempts in flight are
+    /// waited for, up to `deadline`, before the store is flushed. Payments still queued are
+    /// settled by the next worker started on the store.
+    pub async fn stop(&self, deadline: Instant) {
+        self.stopping.send_replace(true);
+        let worker = self.worker.lock().unwrap().take();
+        if let Some(worker) = worker
+            && tokio::time::timeout_at(deadline, worker).await.is_err()
+        {
+            tracing::warn!("Settlement attempts still in flight at shutdown, abandoning them");
+        }
+        if let Err(error) = self.store.flush().await {
+            tracing::error!(%error, "Failed to flush the settlement queue");
+        }
+        if !self.store.is_persistent() {
+            match self.store.pending().await {
+                Ok(pending) if !pending.is_empty() => tracing::error!(
+                    pending = pending.len(),
+                    "Queued settlements lost: the settlement queue is kept in memory"
+                ),
+                Ok(_) => {}
+                Err(error) => tracing::warn!(%error, "Failed to read the settlement queue"),
+            }
+        }
+    }
+
+    fn is_stopping(&self) -> bool {
+        *self.stopping.borrow()
+    }
+
+    async fn run<F>(&self, facilitator: F)
+    where
+        F: Facilitator<Error: Into<FacilitatorLocalError>> + Send + Sync,
+    {
+        let mut stopping = self.stopping.subscribe();
+        while !self.is_stopping() {
+            let pending = match self.store.pending().await {
+                Ok(pending) => pending,
+                Err(error) => {
+                    tracing::warn!(%error, "Failed to read the settlement queue");
+                    Vec::new()
+                }
+            };
+            let draining = self.draining.swap(false, Ordering::Relaxed);
+            let now = now();
+            let next_attempt_at = pending.iter().filter_map(|entry| entry.due_at(now)).min();
+            futures_util::stream::iter(
+                pending
+                    .into_iter()
+                    .filter(|entry| entry.is_due(now, draining)),
+            )
+            .take_while(|_| std::future::ready(!self.is_stopping()))
+            .for_each_concurrent(CONCURRENCY, |entry| self.attempt(&facilitator, entry))
+            .await;
+            let idle = next_attempt_at
+                .map(|at| Duration::from_secs(at.seconds_since_epoch() - now.seconds_since_epoch()))
+                .unwrap_or(POLL_INTERVAL)
+                .min(POLL_INTERVAL);
+            tokio::select! {
+                _ = self.queued.notified() => {}
+                _ = tokio::time::sleep(idle) => {}
+                _ = stopping.wait_for(|stopping| *stopping) => {}
+            }
+        }
+    }
+
+    /// Claims `entry`, makes one settlement attempt of it, and records the outcome. Entries
+    /// claimed by another instance since they were read are left to it.
+    async fn attempt<F>(&self, facilitator: &F, mut entry: QueuedSettlement)
+    where
+        F: Facilitator<Error: Into<FacilitatorLocalError>> + Send + Sync,
+    {
+        let payment_id = entry.progress.payment_id.clone();
+        let attempts = entry.progress.attempts;
+        let claimed_at = now();
+        entry.progress.status = DeferredStatus::Settling;
+        entry.progress.attempts += 1;
+        entry.progress.updated_at = claimed_at;
+        entry.lease_expires_at = Some(claimed_at + SETTLING_LEASE.as_secs());
+        match self.store.claim(&entry, attempts, claimed_at).await {
+            Ok(true) => {}
+            Ok(false) => {
+                tracing::debug!(payment_id, "Settlement claimed by another instance");
+                return;
+            }
+            Err(error) => {
+                tracing::warn!(%error, payment_id, "Failed to claim the settlement");
+                return;
+            }
+        }
+        let result = facilitator.settle(&entry.request).await.map_err(Into::into);
+        entry.progress.updated_at = now();
+        entry.lease_expires_at = None;
+        match result {
+            Ok(response) => {
+                entry.progress.status = if response.success {
+                    DeferredStatus::Settled
+                } else {
+                    DeferredStatus::Failed
+                };
+                entry.progress.response = Some(response);
+                entry.progress.error = None;
+            }
+            Err(
+                error @ (FacilitatorLocalError::SettlementPaused(_)
+                | FacilitatorLocalError::InsufficientGas(_)),
+            ) => {
+                // Not an attempt: the payment waits for its network to be resumed, or topped up
+                entry.progress.error = Some(error.to_string());
+                entry.progress.status = DeferredStatus::Queued;
+                entry.progress.attempts -= 1;
+                entry.next_attempt_at = entry.progress.updated_at + POLL_INTERVAL.as_secs();
+            }
+            Err(error) => {
+                entry.progress.error = Some(error.to_string());
+                if is_transient(&error) && entry.progress.attempts < self.max_attempts {
+                    let backoff = backoff(entry.progress.attempts);
+                    entry.progress.status = DeferredStatus::Queued;
+                    entry.next_attempt_at = entry.progress.updated_at + backoff.as_secs();
+                    tracing::info!(%error, payment_id, ?backoff, "Settlement attempt failed, retrying");
+                } else {
+                    entry.progress.status = DeferredStatus::Failed;
+                }
+            }
+        }
+        tracing::info!(payment_id, status = ?entry.progress.status, attempts = entry.progress.attempts, "Deferred settlement attempt");
+        if let Err(error) = self.store.put(&entry).await {
+            tracing::warn!(%error, payment_id, "Failed to update the settlement queue");
+        }
+    }
+}
+
+/// Whether settling again may succeed: infrastructure failures are transient, while invalid
+/// payments stay invalid.
+fn is_transient(error: &FacilitatorLocalError) -> bool {
+    matches!(
+        error,
+        FacilitatorLocalError::ContractCall(_)
+            | FacilitatorLocalError::NonceStore(_)
+            | FacilitatorLocalError::ClockError(_)
+            | FacilitatorLocalError::Upstream(_, None)
+    )
+}
+
+/// Delay before attempt `attempts + 1`.
+fn backoff(attempts: u32) -> Duration {
+    INITIAL_BACKOFF
+        .saturating_mul(2u32.saturating_pow(attempts.saturating_sub(1)))
+        .min(MAX_BACKOFF)
+}
+
+fn now() -> UnixTimestamp {
+    UnixTimestamp::try_now().unwrap_or(UnixTimestamp(0))
+}
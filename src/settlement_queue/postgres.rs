@@ -0,0 +1,146 @@
+//! [`SettlementStore`] backed by PostgreSQL.
+
+use futures_util::future::BoxFuture;
+use tokio_postgres::{Client, NoTls};
+
+use super::{QueuedSettlement, SettlementQueueError, SettlementStore};
+use crate::timestamp::UnixTimestamp;
+
+/// Deferred settlements persisted as JSON in the `x402_settlement_queue` table. The table is
+/// created on [`connect`](Self::connect).
+///
+/// Facilitator instances sharing the table can all run the worker (see
+/// [`SettlementQueue::start`](super::SettlementQueue::start)): payments are claimed with a
+/// conditional `UPDATE`, so a payment is attempted by one instance at a time.
+pub struct PostgresSettlementStore {
+    client: Client,
+}
+
+impl PostgresSettlementStore {
+    /// Connects to the database at `url`, e.g. `postgres://x402@localhost/x402`, without TLS.
+    pub async fn connect(url: &str) -> Result<Self, SettlementQueueError> {
+        let (client, connection) = tokio_postgres::connect(url, NoTls)
+            .await
+            .map_err(backend_error)?;
+        tokio::spawn(async move {
+            if let Err(error) = connection.await {
+                tracing::error!(%error, "Settlement queue connection failed");
+            }
+        });
+        client
+            .batch_execute(
+                "CREATE TABLE IF NOT EXISTS x402_settlement_queue (
+                    payment_id TEXT PRIMARY KEY,
+                    finished BOOLEAN NOT NULL,
+                    entry TEXT NOT NULL,
+                    updated_at TIMESTAMPTZ NOT NULL DEFAULT now()
+                )",
+            )
+            .await
+            .map_err(backend_error)?;
+        Ok(Self { client })
+    }
+}
+
+impl SettlementStore for PostgresSettlementStore {
+    fn put<'a>(
+        &'a self,
+        entry: &'a QueuedSettlement,
+    ) -> BoxFuture<'a, Result<(), SettlementQueueError>> {
+        Box::pin(async move {
+            let value = serde_json::to_string(entry)
+                .map_err(|e| SettlementQueueError::Backend(e.to_string()))?;
+            let finished = entry.progress.status.is_finished();
+            self.client
+                .execute(
+                    "INSERT INTO x402_settlement_queue (payment_id, finished, entry)
+                    VALUES ($1, $2, $3)
+                    ON CONFLICT (payment_id) DO UPDATE
+                    SET finished = $2, entry = $3, updated_at = now()",
+                    &[&entry.progress.payment_id, &finished, &value],
+                )
+                .await
+                .map_err(backend_error)?;
+            Ok(())
+        })
+    }
+
+    fn get<'a>(
+        &'a self,
+        payment_id: &'a str,
+    ) -> BoxFuture<'a, Result<Option<QueuedSettlement>, SettlementQueueError>> {
+        Box::pin(async move {
+            let row = self
+                .client
+                .query_opt(
+                    "SELECT entry FROM x402_settlement_queue WHERE payment_id = $1",
+                    &[&payment_id],
+                )
+                .await
+                .map_err(backend_error)?;
+            row.map(|row| decode(row.get(0))).transpose()
+        })
+    }
+
+    fn pending(&self) -> BoxFuture<'_, Result<Vec<QueuedSettlement>, SettlementQueueError>> {
+        Box::pin(async move {
+            let rows = self
+                .client
+                .query(
+                    "SELECT entry FROM x402_settlement_queue WHERE NOT finished",
+                    &[],
+                )
+                .await
+                .map_err(backend_error)?;
+            rows.iter().map(|row| decode(row.get(0))).collect()
+        })
+    }
+
+    fn claim<'a>(
+        &'a self,
+        entry: &'a QueuedSettlement,
+        attempts: u32,
+        now: UnixTimestamp,
+    ) -> BoxFuture<'a, Result<bool, SettlementQueueError>> {
+        Box::pin(async move {
+            let value = serde_json::to_string(entry)
+                .map_err(|e| SettlementQueueError::Backend(e.to_string()))?;
+            // The conditions of `QueuedSettlement::is_claimable`, re-evaluated by PostgreSQL on
+            // the locked row when another instance updated it concurrently
+            let row = self
+                .client
+                .query_opt(
+                    "UPDATE x402_settlement_queue
+                    SET entry = $2, updated_at = now()
+                    WHERE payment_id = $1
+                    AND NOT finished
+                    AND (entry::jsonb #>> '{progress,attempts}')::bigint = $3
+                    AND (
+                        entry::jsonb #>> '{progress,status}' = 'queued'
+                        OR (
+                            entry::jsonb #>> '{progress,status}' = 'settling'
+                            AND COALESCE((entry::jsonb ->> 'leaseExpiresAt')::bigint, 0) <= $4
+                        )
+                    )
+                    RETURNING payment_id",
+                    &[
+                        &entry.progress.payment_id,
+                        &value,
+                        &i64::from(attempts),
+                        &(now.seconds_since_epoch() as i64),
+                    ],
+                )
+                .await
+                .map_err(backend_error)?;
+            Ok(row.is_some())
+        })
+    }
+}
+
+fn decode(value: &str) -> Result<QueuedSettlement, SettlementQueueError> {
+    serde_json::from_str(value).map_err(|e| SettlementQueueError::Backend(e.to_string()))
+}
+
+fn backend_error(error: tokio_postgres::Error) -> SettlementQueueError {
+    SettlementQueueError::Backend(error.to_string())
+}
@@ -0,0 +1,111 @@
+//! [`SettlementStore`] backed by an embedded [sled](https://docs.rs/sled) database.
+
+use futures_util::future::BoxFuture;
+use std::path::Path;
+
+use super::{QueuedSettlement, SettlementQueueError, SettlementStore};
+use crate::timestamp::UnixTimestamp;
+
+/// Deferred settlements persisted as JSON in a sled database on local disk, surviving restarts of
+/// a single facilitator instance.
+#[derive(Debug, Clone)]
+pub struct SledSettlementStore {
+    db: sled::Db,
+}
+
+impl SledSettlementStore {
+    /// Opens (or creates) the database at `path`.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, SettlementQueueError> {
+        let db = sled::open(path).map_err(backend_error)?;
+        Ok(Self { db })
+    }
+}
+
+impl SettlementStore for SledSettlementStore {
+    fn put<'a>(
+        &'a self,
+        entry: &'a QueuedSettlement,
+    ) -> BoxFuture<'a, Result<(), SettlementQueueError>> {
+        Box::pin(async move {
+            let value = serde_json::to_vec(entry)
+                .map_err(|e| SettlementQueueError::Backend(e.to_string()))?;
+            self.db
+                .insert(entry.progress.payment_id.as_bytes(), value)
+                .map_err(backend_error)?;
+            // A queued payment must be durable before it is acknowledged.
+            self.db.flush_async().await.map_err(backend_error)?;
+            Ok(())
+        })
+    }
+
+    fn get<'a>(
+        &'a self,
+        payment_id: &'a str,
+    ) -> BoxFuture<'a, Result<Option<QueuedSettlement>, SettlementQueueError>> {
+        Box::pin(async move {
+            self.db
+                .get(payment_id)
+                .map_err(backend_error)?
+                .map(|value| decode(&value))
+                .transpose()
+        })
+    }
+
+    fn pending(&self) -> BoxFuture<'_, Result<Vec<QueuedSettlement>, SettlementQueueError>> {
+        Box::pin(async move {
+            let mut pending = Vec::new();
+            for item in self.db.iter() {
+                let (_, value) = item.map_err(backend_error)?;
+                let entry = decode(&value)?;
+                if !entry.progress.status.is_finished() {
+                    pending.push(entry);
+                }
+            }
+            Ok(pending)
+        })
+    }
+
+    fn claim<'a>(
+        &'a self,
+        entry: &'a QueuedSettlement,
+        attempts: u32,
+        now: UnixTimestamp,
+    ) -> BoxFuture<'a, Result<bool, SettlementQueueError>> {
+        Box::pin(async move {
+            let key = entry.progress.payment_id.as_bytes();
+            let Some(current) = self.db.get(key).map_err(backend_error)? else {
+                return Ok(false);
+            };
+            if !decode(&current)?.is_claimable(attempts, now) {
+                return Ok(false);
+            }
+            let value = serde_json::to_vec(entry)
+                .map_err(|e| SettlementQueueError::Backend(e.to_string()))?;
+            // Swapped only if no other instance wrote the entry since it was read
+            let swapped = self
+                .db
+                .compare_and_swap(key, Some(current), Some(value))
+                .map_err(backend_error)?;
+            if swapped.is_err() {
+                return Ok(false);
+            }
+            self.db.flush_async().await.map_err(backend_error)?;
+            Ok(true)
+        })
+    }
+
+    fn flush(&self) -> BoxFuture<'_, Result<(), SettlementQueueError>> {
+        Box::pin(async move {
+            self.db.flush_async().await.map_err(backend_error)?;
+            Ok(())
+        })
+    }
+}
+
+fn decode(value: &[u8]) -> Result<QueuedSettlement, SettlementQueueError> {
+    serde_json::from_slice(value).map_err(|e| SettlementQueueError::Backend(e.to_string()))
+}
+
+fn backend_error(error: sled::Error) -> SettlementQueueError {
+    SettlementQueueError::Backend(error.to_string())
+}
@@ -0,0 +1,381 @@
+//! Settlement statistics, served as `GET /stats`.
+//!
+//! Public facilitators publish transparency numbers: how many payments they settled, on which
+//! networks and assets, how many failed, how much was moved, and how long settlements take.
+//! [`FacilitatorLocal`](crate::facilitator_local::FacilitatorLocal) appends a
+//! [`SettlementRecord`] to a [`SettlementRecordStore`] for every settlement, and
+//! [`SettlementStats`] aggregates the records of a window, e.g. the last 24 hours, per network
+//! and asset. Reports are cached for a while, so that a popular stats page does not scan the
+//! store on every request.
+//!
+//! The confirmation time of a settlement is the time from its submission to the facilitator
+//! until the inclusion of its transaction, as observed by the facilitator. It is averaged over
+//! successful settlements only, as is the volume.
+//!
+//! Backends:
+//! - [`InMemorySettlementRecords`] – forgets records on restart, and records older than
+//!   [`MAX_WINDOW`]
+//! - [`SledSettlementRecords`](sled::SledSettlementRecords) – embedded database on disk (`sled` feature)
+//! - [`PostgresSettlementRecords`](postgres::PostgresSettlementRecords) – shared by several facilitator instances (`postgres` feature)
+//!
+//! Environment (see [`SettlementStats::from_env`]):
+//! - `SETTLEMENT_STATS_URL` – `memory`, `sled:<path>` or `postgres://...`; statistics are not
+//!   collected, and `/stats` answers 404, if unset
+//! - `SETTLEMENT_STATS_CACHE_SECS` – How long a report is served from cache (default `60s`, at
+//!   most `1h`, `0` disables the cache)
+
+use futures_util::future::BoxFuture;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::env;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::duration::{self, DurationError, DurationRange, Seconds};
+use crate::network::Network;
+use crate::timestamp::UnixTimestampMs;
+use crate::types::{MixedAddress, SettleRequest, SettleResponse, TokenAmount};
+
+#[cfg(feature = "postgres")]
+pub mod postgres;
+#[cfg(feature = "sled")]
+pub mod sled;
+
+const ENV_SETTLEMENT_STATS_URL: &str = "SETTLEMENT_STATS_URL";
+const ENV_SETTLEMENT_STATS_CACHE_SECS: &str = "SETTLEMENT_STATS_CACHE_SECS";
+
+/// Window of reports when none is requested.
+pub const DEFAULT_WINDOW: Duration = Duration::from_secs(86_400);
+/// Longest window of reports.
+pub const MAX_WINDOW: Duration = Duration::from_secs(30 * 86_400);
+/// Windows of reports.
+pub const WINDOW_RANGE: DurationRange = DurationRange::new(Duration::from_secs(60), MAX_WINDOW);
+
+const DEFAULT_CACHE_TTL: Duration = Duration::from_secs(60);
+const CACHE_TTL_RANGE: DurationRange =
+    DurationRange::new(Duration::ZERO, Duration::from_secs(3600));
+
+#[derive(Debug, thiserror::Error)]
+pub enum StatsError {
+    #[error("Unsupported {ENV_SETTLEMENT_STATS_URL}: {0}")]
+    UnsupportedUrl(String),
+    #[error("Settlement stats backend error: {0}")]
+    #[allow(dead_code)] // Constructed by the `sled` and `postgres` backends.
+    Backend(String),
+    #[error("Invalid stats window: {0}")]
+    InvalidWindow(DurationError),
+    #[error(transparent)]
+    InvalidCacheTtl(DurationError),
+}
+
+/// Outcome of a settlement, as kept by a [`SettlementRecordStore`].
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SettlementRecord {
+    /// When the settlement completed.
+    pub at: UnixTimestampMs,
+    pub network: Network,
+    pub asset: MixedAddress,
+    pub success: bool,
+    /// Amount moved, in token base units. Zero for failed settlements.
+    pub amount: TokenAmount,
+    /// Time from the submission of the settlement until its transaction was included.
+    pub confirmation_ms: u64,
+}
+
+impl SettlementRecord {
+    /// Record of the settlement of `request`, completed with `result` after `elapsed`.
+    pub fn new<E>(
+        request: &SettleRequest,
+        result: &Result<SettleResponse, E>,
+        elapsed: Duration,
+    ) -> Self {
+        let requirements = &request.payment_requirements;
+        let (success, amount) = match result {
+            Ok(response) if response.success => {
+                // What actually moved, or else what was settled for
+                let amount = response
+                    .settlement
+                    .as_ref()
+                    .map(|settlement| settlement.amount)
+                    .or(requirements.consumed_amount)
+                    .unwrap_or(requirements.max_amount_required);
+                (true, amount)
+            }
+            _ => (false, TokenAmount::from(0u64)),
+        };
+        Self {
+            at: UnixTimestampMs::now(),
+            network: request.payment_payload.network,
+            asset: requirements.asset.clone(),
+            success,
+            amount,
+            confirmation_ms: elapsed.as_millis() as u64,
+        }
+    }
+}
+
+/// Record of the settlements of a facilitator.
+pub trait SettlementRecordStore: Send + Sync {
+    /// Appends a record.
+    fn append(&self, record: SettlementRecord) -> BoxFuture<'_, Result<(), StatsError>>;
+    /// Records of the settlements completed at or after `from`.
+    fn since(
+        &self,
+        from: UnixTimestampMs,
+    ) -> BoxFuture<'_, Result<Vec<SettlementRecord>, StatsError>>;
+}
+
+/// Records kept in memory, for at most [`MAX_WINDOW`].
+#[derive(Debug, Default)]
+pub struct InMemorySettlementRecords {
+    records: Mutex<VecDeque<SettlementRecord>>,
+}
+
+impl InMemorySettlementRecords {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl SettlementRecordStore for InMemorySettlementRecords {
+    fn append(&self, record: SettlementRecord) -> BoxFuture<'_, Result<(), StatsError>> {
+        let mut records = self.records.lock().unwrap();
+        let expired = UnixTimestampMs(record.at.0.saturating_sub(MAX_WINDOW.as_millis() as u64));
+        while records.front().is_some_and(|oldest| oldest.at < expired) {
+            records.pop_front();
+        }
+        records.push_back(record);
+        Box::pin(async { Ok(()) })
+    }
+
+    fn since(
+        &self,
+        from: UnixTimestampMs,
+    ) -> BoxFuture<'_, Result<Vec<SettlementRecord>, StatsError>> {
+        let records = self
+            .records
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|record| record.at >= from)
+            .cloned()
+            .collect();
+        Box::pin(async move { Ok(records) })
+    }
+}
+
+/// Statistics of the settlements of one asset on one network.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AssetStats {
+    pub network: Network,
+    pub asset: MixedAddress,
+    pub settlements: u64,
+    pub succeeded: u64,
+    pub failed: u64,
+    /// Share of successful settlements, between 0 and 1.
+    pub success_rate: f64,
+    /// Amount moved by successful settlements, in token base units.
+    pub volume: TokenAmount,
+    /// Average confirmation time of successful settlements, if any.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub average_confirmation_ms: Option<u64>,
+}
+
+/// Statistics of the settlements of a window, the body of `GET /stats`.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StatsReport {
+    pub window_seconds: Seconds,
+    /// Start of the window.
+    pub from: UnixTimestampMs,
+    /// End of the window, when the report was computed.
+    pub to: UnixTimestampMs,
+    pub settlements: u64,
+    pub succeeded: u64,
+    pub failed: u64,
+    /// Share of successful settlements, between 0 and 1. Zero without settlements.
+    pub success_rate: f64,
+    /// Statistics per network and asset, ordered by network and asset.
+    pub assets: Vec<AssetStats>,
+}
+
+impl StatsReport {
+    /// Aggregates `records` into the report of the window of `window_seconds` up to `to`.
+    pub fn aggregate(
+        window_seconds: Seconds,
+        to: UnixTimestampMs,
+        records: &[SettlementRecord],
+    ) -> Self {
+        struct Totals {
+            settlements: u64,
+            succeeded: u64,
+            volume: TokenAmount,
+            confirmation_ms: u128,
+        }
+        let mut per_asset: BTreeMap<(String, String), (Network, MixedAddress, Totals)> =
+            BTreeMap::new();
+        for record in records {
+            let key = (record.network.to_string(), record.asset.to_string());
+            let (_, _, totals) = per_asset.entry(key).or_insert_with(|| {
+                let totals = Totals {
+                    settlements: 0,
+                    succeeded: 0,
+                    volume: TokenAmount::from(0u64),
+                    confirmation_ms: 0,
+                };
+                (record.network, record.asset.clone(), totals)
+            });
+            totals.settlements += 1;
+            if record.success {
+                totals.succeeded += 1;
+                totals.volume = totals.volume.saturating_add(record.amount);
+                totals.confirmation_ms += u128::from(record.confirmation_ms);
+            }
+        }
+        let assets: Vec<AssetStats> = per_asset
+            .into_values()
+            .map(|(network, asset, totals)| AssetStats {
+                network,
+                asset,
+                settlements: totals.settlements,
+                succeeded: totals.succeeded,
+                failed: totals.settlements - totals.succeeded,
+                success_rate: rate(totals.succeeded, totals.settlements),
+                volume: totals.volume,
+                average_confirmation_ms: (totals.succeeded > 0)
+                    .then(|| (totals.confirmation_ms / u128::from(totals.succeeded)) as u64),
+            })
+            .collect();
+        let settlements = assets.iter().map(|asset| asset.settlements).sum();
+        let succeeded = assets.iter().map(|asset| asset.succeeded).sum();
+        Self {
+            window_seconds,
+            from: UnixTimestampMs(to.0.saturating_sub(window_seconds.as_millis())),
+            to,
+            settlements,
+            succeeded,
+            failed: settlements - succeeded,
+            success_rate: rate(succeeded, settlements),
+            assets,
+        }
+    }
+}
+
+fn rate(succeeded: u64, total: u64) -> f64 {
+    if total == 0 {
+        0.0
+    } else {
+        succeeded as f64 / total as f64
+    }
+}
+
+/// Reports per window, with the time they were computed.
+type CachedReports = HashMap<Seconds, (Instant, Arc<StatsReport>)>;
+
+/// Collects settlement records, and reports their statistics over windows.
+///
+/// Cloning is cheap: clones share the store and the cache of reports.
+#[derive(Clone)]
+pub struct SettlementStats {
+    store: Arc<dyn SettlementRecordStore>,
+    cache_ttl: Duration,
+    cache: Arc<Mutex<CachedReports>>,
+}
+
+impl SettlementStats {
+    /// Statistics of the records of `store`, with reports cached for a minute.
+    pub fn new(store: Arc<dyn SettlementRecordStore>) -> Self {
+        Self {
+            store,
+            cache_ttl: DEFAULT_CACHE_TTL,
+            cache: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// Serves reports from cache for `ttl`. A zero `ttl` computes every report afresh.
+    pub fn with_cache_ttl(&self, ttl: Duration) -> Self {
+        let mut this = self.clone();
+        this.cache_ttl = ttl;
+        this
+    }
+
+    /// Statistics configured by `SETTLEMENT_STATS_URL` and `SETTLEMENT_STATS_CACHE_SECS`, or
+    /// `None` if not configured.
+    pub async fn from_env() -> Result<Option<Self>, StatsError> {
+        let Some(url) = env::var(ENV_SETTLEMENT_STATS_URL)
+            .ok()
+            .filter(|url| !url.is_empty())
+        else {
+            return Ok(None);
+        };
+        let store: Arc<dyn SettlementRecordStore> = if url == "memory" {
+            Arc::new(InMemorySettlementRecords::new())
+        } else {
+            store_from_url(url).await?
+        };
+        let ttl = duration::from_env(
+            ENV_SETTLEMENT_STATS_CACHE_SECS,
+            duration::SECOND,
+            CACHE_TTL_RANGE,
+        )
+        .map_err(StatsError::InvalidCacheTtl)?
+        .unwrap_or(DEFAULT_CACHE_TTL);
+        Ok(Some(Self::new(store).with_cache_ttl(ttl)))
+    }
+
+    /// Records the settlement of `request`, completed with `result` after `elapsed`, in the
+    /// background. Failures to record are logged, never surfaced to the settlement.
+    pub fn record<E>(
+        &self,
+        request: &SettleRequest,
+        result: &Result<SettleResponse, E>,
+        elapsed: Duration,
+    ) {
+        let record = SettlementRecord::new(request, result, elapsed);
+        let store = self.store.clone();
+        tokio::spawn(async move {
+            if let Err(error) = store.append(record).await {
+                tracing::warn!(%error, "Failed to record settlement");
+            }
+        });
+    }
+
+    /// Statistics of the settlements of the last `window`, within [`WINDOW_RANGE`] and rounded
+    /// down to whole seconds.
+    pub async fn report(&self, window: Duration) -> Result<Arc<StatsReport>, StatsError> {
+        WINDOW_RANGE
+            .check(window)
+            .map_err(StatsError::InvalidWindow)?;
+        let window = Seconds::new(window.as_secs());
+        if let Some((computed_at, report)) = self.cache.lock().unwrap().get(&window)
+            && computed_at.elapsed() < self.cache_ttl
+        {
+            return Ok(report.clone());
+        }
+        let to = UnixTimestampMs::now();
+        let from = UnixTimestampMs(to.0.saturating_sub(window.as_millis()));
+        let records = self.store.since(from).await?;
+        let report = Arc::new(StatsReport::aggregate(window, to, &records));
+        self.cache
+            .lock()
+            .unwrap()
+            .insert(window, (Instant::now(), report.clone()));
+        Ok(report)
+    }
+}
+
+async fn store_from_url(url: String) -> Result<Arc<dyn SettlementRecordStore>, StatsError> {
+    #[cfg(feature = "sled")]
+    if let Some(path) = url.strip_prefix("sled:") {
+        return Ok(Arc::new(sled::SledSettlementRecords::open(path)?));
+    }
+    #[cfg(feature = "postgres")]
+    if url.starts_with("postgres://") || url.starts_with("postgresql://") {
+        return Ok(Arc::new(
+            postgres::PostgresSettlementRecords::connect(&url).await?,
+        ));
+    }
+    Err(StatsError::UnsupportedUrl(url))
+}
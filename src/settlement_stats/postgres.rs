@@ -0,0 +1,84 @@
+//! [`SettlementRecordStore`] backed by PostgreSQL.
+
+use futures_util::future::BoxFuture;
+use tokio_postgres::{Client, NoTls};
+
+use super::{SettlementRecord, SettlementRecordStore, StatsError};
+use crate::timestamp::UnixTimestampMs;
+
+/// Records persisted in the `x402_settlement_records` table, shared by all facilitator
+/// instances connected to the same database. The table is created on [`connect`](Self::connect).
+pub struct PostgresSettlementRecords {
+    client: Client,
+}
+
+impl PostgresSettlementRecords {
+    /// Connects to the database at `url`, e.g. `postgres://x402@localhost/x402`, without TLS.
+    pub async fn connect(url: &str) -> Result<Self, StatsError> {
+        let (client, connection) = tokio_postgres::connect(url, NoTls)
+            .await
+            .map_err(backend_error)?;
+        tokio::spawn(async move {
+            if let Err(error) = connection.await {
+                tracing::error!(%error, "Settlement stats connection failed");
+            }
+        });
+        client
+            .batch_execute(
+                "CREATE TABLE IF NOT EXISTS x402_settlement_records (
+                    id BIGSERIAL PRIMARY KEY,
+                    at_ms BIGINT NOT NULL,
+                    record TEXT NOT NULL
+                );
+                CREATE INDEX IF NOT EXISTS x402_settlement_records_at_ms
+                    ON x402_settlement_records (at_ms)",
+            )
+            .await
+            .map_err(backend_error)?;
+        Ok(Self { client })
+    }
+}
+
+impl SettlementRecordStore for PostgresSettlementRecords {
+    fn append(&self, record: SettlementRecord) -> BoxFuture<'_, Result<(), StatsError>> {
+        Box::pin(async move {
+            let at_ms = record.at.0 as i64;
+            let record =
+                serde_json::to_string(&record).map_err(|e| StatsError::Backend(e.to_string()))?;
+            self.client
+                .execute(
+                    "INSERT INTO x402_settlement_records (at_ms, record) VALUES ($1, $2)",
+                    &[&at_ms, &record],
+                )
+                .await
+                .map_err(backend_error)?;
+            Ok(())
+        })
+    }
+
+    fn since(
+        &self,
+        from: UnixTimestampMs,
+    ) -> BoxFuture<'_, Result<Vec<SettlementRecord>, StatsError>> {
+        Box::pin(async move {
+            let from = from.0 as i64;
+            let rows = self
+                .client
+                .query(
+                    "SELECT record FROM x402_settlement_records WHERE at_ms >= $1",
+                    &[&from],
+                )
+                .await
+                .map_err(backend_error)?;
+            rows.iter()
+                .map(|row| {
+                    serde_json::from_str(row.get(0)).map_err(|e| StatsError::Backend(e.to_string()))
+                })
+                .collect()
+        })
+    }
+}
+
+fn backend_error(error: tokio_postgres::Error) -> StatsError {
+    StatsError::Backend(error.to_string())
+}
@@ -0,0 +1,59 @@
+//! [`SettlementRecordStore`] backed by an embedded [sled](https://docs.rs/sled) database.
+
+use futures_util::future::BoxFuture;
+use std::path::Path;
+
+use super::{SettlementRecord, SettlementRecordStore, StatsError};
+use crate::timestamp::UnixTimestampMs;
+
+/// Records persisted in a sled database on local disk, surviving restarts of a single
+/// facilitator instance.
+///
+/// Records are keyed by their completion time, then by a unique id, so that a window is a
+/// range scan.
+#[derive(Debug, Clone)]
+pub struct SledSettlementRecords {
+    db: sled::Db,
+}
+
+impl SledSettlementRecords {
+    /// Opens (or creates) the database at `path`.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, StatsError> {
+        let db = sled::open(path).map_err(backend_error)?;
+        Ok(Self { db })
+    }
+}
+
+impl SettlementRecordStore for SledSettlementRecords {
+    fn append(&self, record: SettlementRecord) -> BoxFuture<'_, Result<(), StatsError>> {
+        Box::pin(async move {
+            let id = self.db.generate_id().map_err(backend_error)?;
+            let mut key = record.at.0.to_be_bytes().to_vec();
+            key.extend_from_slice(&id.to_be_bytes());
+            let value =
+                serde_json::to_vec(&record).map_err(|e| StatsError::Backend(e.to_string()))?;
+            self.db.insert(key, value).map_err(backend_error)?;
+            Ok(())
+        })
+    }
+
+    fn since(
+        &self,
+        from: UnixTimestampMs,
+    ) -> BoxFuture<'_, Result<Vec<SettlementRecord>, StatsError>> {
+        Box::pin(async move {
+            self.db
+                .range(from.0.to_be_bytes()..)
+                .values()
+                .map(|value| {
+                    let value = value.map_err(backend_error)?;
+                    serde_json::from_slice(&value).map_err(|e| StatsError::Backend(e.to_string()))
+                })
+                .collect()
+        })
+    }
+}
+
+fn backend_error(error: sled::Error) -> StatsError {
+    StatsError::Backend(error.to_string())
+}
@@ -0,0 +1,550 @@
+//! History of the payments verified and settled by the facilitator, served as `GET /payments`.
+//!
+//! Operators reconciling payments, or answering a buyer asking what happened to theirs, need more
+//! than logs: [`FacilitatorLocal`](crate::facilitator_local::FacilitatorLocal) records every
+//! verification and settlement as a [`PaymentRecord`] in a [`PaymentRepository`]: the hash of the
+//! payment payload, the payer, the recipient, the amount, the network, the transaction, the
+//! metadata of the seller, the outcome, and when it started and completed.
+//! [`PaymentHistory::query`] pages through the records matching a [`PaymentQuery`], newest first.
+//!
+//! Records are written in the background: a repository failing never fails a payment, it is
+//! logged instead.
+//!
+//! Backends:
+//! - [`InMemoryPayments`] – forgets records on restart, and keeps the last
+//!   [`DEFAULT_MEMORY_CAPACITY`]
+//! - [`SqlitePayments`](sqlite::SqlitePayments) – SQLite database file, over `sqlx` (`sqlite` feature)
+//! - [`PostgresPayments`](postgres::PostgresPayments) – shared by several facilitator instances, over
+//!   `sqlx` with TLS (`postgres` feature)
+//!
+//! Environment (see [`PaymentHistory::from_env`]):
+//! - `PAYMENT_HISTORY_URL` – `memory`, `sqlite:<path>` or `postgres://...`; payments are not
+//!   recorded, and `/payments` answers 404, if unset
+
+use alloy::primitives::B256;
+use futures_util::future::BoxFuture;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use serde_json::{Map, Value};
+use std::collections::VecDeque;
+use std::env;
+use std::fmt::{Display, Formatter};
+use std::sync::{Arc, Mutex};
+
+use crate::canonical_json;
+use crate::network::Network;
+use crate::timestamp::UnixTimestampMs;
+use crate::types::{
+    MixedAddress, Scheme, SettleRequest, SettleResponse, TokenAmount, TransactionHash,
+    VerifyRequest, VerifyResponse,
+};
+
+#[cfg(feature = "postgres")]
+pub mod postgres;
+#[cfg(feature = "sqlite")]
+pub mod sqlite;
+
+const ENV_PAYMENT_HISTORY_URL: &str = "PAYMENT_HISTORY_URL";
+
+/// Records kept by [`InMemoryPayments`], by default.
+pub const DEFAULT_MEMORY_CAPACITY: usize = 100_000;
+/// Records per page, when not requested.
+pub const DEFAULT_LIMIT: usize = 100;
+/// Most records per page.
+pub const MAX_LIMIT: usize = 1000;
+
+#[derive(Debug, thiserror::Error)]
+pub enum StorageError {
+    #[error("Unsupported {ENV_PAYMENT_HISTORY_URL}: {0}")]
+    UnsupportedUrl(String),
+    #[error("Payment history backend error: {0}")]
+    #[allow(dead_code)] // Constructed by the `sqlite` and `postgres` backends.
+    Backend(String),
+}
+
+/// Operation of the facilitator on a payment.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PaymentOperation {
+    Verify,
+    Settle,
+}
+
+impl PaymentOperation {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PaymentOperation::Verify => "verify",
+            PaymentOperation::Settle => "settle",
+        }
+    }
+}
+
+impl Display for PaymentOperation {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Outcome of an operation on a payment.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PaymentStatus {
+    /// Verified valid.
+    Valid,
+    /// Verified invalid.
+    Invalid,
+    /// Settled on-chain.
+    Settled,
+    /// Settlement reported failed, e.g. reverted.
+    Failed,
+    /// The facilitator could not complete the operation.
+    Error,
+}
+
+impl PaymentStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PaymentStatus::Valid => "valid",
+            PaymentStatus::Invalid => "invalid",
+            PaymentStatus::Settled => "settled",
+            PaymentStatus::Failed => "failed",
+            PaymentStatus::Error => "error",
+        }
+    }
+}
+
+impl Display for PaymentStatus {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// A verification or settlement, as kept by a [`PaymentRepository`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PaymentRecord {
+    pub id: String,
+    pub operation: PaymentOperation,
+    pub status: PaymentStatus,
+    pub network: Network,
+    pub scheme: Scheme,
+    /// Keccak-256 hash of the canonical JSON encoding of the payment payload, the same for the
+    /// verification and the settlement of a payment.
+    pub payload_hash: B256,
+    /// Payer, unless the operation failed before identifying it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub payer: Option<MixedAddress>,
+    pub pay_to: MixedAddress,
+    pub asset: MixedAddress,
+    /// Amount required, or moved by a settlement, in token base units.
+    pub amount: TokenAmount,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub transaction: Option<TransactionHash>,
+    /// Why the payment is invalid, or the operation failed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// [`VerifyRequest::metadata`] of the request, e.g. the order paid.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub metadata: Option<Value>,
+    pub started_at: UnixTimestampMs,
+    pub completed_at: UnixTimestampMs,
+}
+
+impl PaymentRecord {
+    /// Record of an operation on the payment of `request`, completed now.
+    fn new(
+        operation: PaymentOperation,
+        status: PaymentStatus,
+        request: &VerifyRequest,
+        started_at: UnixTimestampMs,
+    ) -> Self {
+        let requirements = &request.payment_requirements;
+        Self {
+            id: uuid::Uuid::new_v4().to_string(),
+            operation,
+            status,
+            network: request.payment_payload.network,
+            scheme: request.payment_payload.scheme.clone(),
+            payload_hash: canonical_json::keccak256(&request.payment_payload).unwrap_or_default(),
+            payer: None,
+            pay_to: requirements.pay_to.clone(),
+            asset: requirements.asset.clone(),
+            amount: requirements
+                .consumed_amount
+                .unwrap_or(requirements.max_amount_required),
+            transaction: None,
+            error: None,
+            metadata: request.metadata.clone(),
+            started_at,
+            completed_at: UnixTimestampMs::now(),
+        }
+    }
+
+    /// Record of the verification of `request`, started at `started_at` and completed with
+    /// `result`.
+    pub fn verified<E: Display>(
+        request: &VerifyRequest,
+        result: &Result<VerifyResponse, E>,
+        started_at: UnixTimestampMs,
+    ) -> Self {
+        let (status, payer, error) = match result {
+            Ok(VerifyResponse::Valid { payer, .. }) => {
+                (PaymentStatus::Valid, Some(payer.clone()), None)
+            }
+            Ok(VerifyResponse::Invalid { reason, payer }) => (
+                PaymentStatus::Invalid,
+                payer.clone(),
+                Some(reason.to_string()),
+            ),
+            Err(e) => (PaymentStatus::Error, None, Some(e.to_string())),
+        };
+        Self {
+            payer,
+            error,
+            ..Self::new(PaymentOperation::Verify, status, request, started_at)
+        }
+    }
+
+    /// Record of the settlement of `request`, started at `started_at` and completed with
+    /// `result`.
+    pub fn settled<E: Display>(
+        request: &SettleRequest,
+        result: &Result<SettleResponse, E>,
+        started_at: UnixTimestampMs,
+    ) -> Self {
+        let record = Self::new(
+            PaymentOperation::Settle,
+            PaymentStatus::Error,
+            request,
+            started_at,
+        );
+        match result {
+            Ok(response) => Self {
+                status: if response.success {
+                    PaymentStatus::Settled
+                } else {
+                    PaymentStatus::Failed
+                },
+                payer: Some(response.payer.clone()),
+                // What actually moved, or else what was settled for
+                amount: response
+                    .settlement
+                    .as_ref()
+                    .map_or(record.amount, |settlement| settlement.amount),
+                transaction: response.transaction.clone(),
+                error: response.error_reason.as_ref().map(ToString::to_string),
+                ..record
+            },
+            Err(e) => Self {
+                error: Some(e.to_string()),
+                ..record
+            },
+        }
+    }
+}
+
+/// Filters and page of `GET /payments`. Every filter set must match.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PaymentQuery {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub operation: Option<PaymentOperation>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub status: Option<PaymentStatus>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub network: Option<Network>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub payer: Option<MixedAddress>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pay_to: Option<MixedAddress>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub transaction: Option<TransactionHash>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub payload_hash: Option<B256>,
+    /// Entries the metadata of the records must have, e.g. `metadata={"orderId":"42"}`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub metadata: Option<MetadataFilter>,
+    /// Earliest completion, in milliseconds since the Unix epoch.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub from: Option<UnixTimestampMs>,
+    /// Latest completion, exclusive, in milliseconds since the Unix epoch.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub to: Option<UnixTimestampMs>,
+    /// Records per page (default `100`, at most `1000`).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub limit: Option<usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub offset: Option<usize>,
+}
+
+impl PaymentQuery {
+    /// Records per page, within [`MAX_LIMIT`].
+    pub fn limit(&self) -> usize {
+        self.limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT)
+    }
+
+    pub fn offset(&self) -> usize {
+        self.offset.unwrap_or(0)
+    }
+
+    pub fn matches(&self, record: &PaymentRecord) -> bool {
+        self.operation.is_none_or(|o| o == record.operation)
+            && self.status.is_none_or(|s| s == record.status)
+            && self.network.is_none_or(|n| n == record.network)
+            && self
+                .payer
+                .as_ref()
+                .is_none_or(|p| record.payer.as_ref() == Some(p))
+            && self.pay_to.as_ref().is_none_or(|p| *p == record.pay_to)
+            && self
+                .transaction
+                .as_ref()
+                .is_none_or(|t| record.transaction.as_ref() == Some(t))
+            && self.payload_hash.is_none_or(|h| h == record.payload_hash)
+            && self
+                .metadata
+                .as_ref()
+                .is_none_or(|m| m.matches(record.metadata.as_ref()))
+            && self.from.is_none_or(|from| record.completed_at >= from)
+            && self.to.is_none_or(|to| record.completed_at < to)
+    }
+
+    /// Filters of the query on the indexed columns of the SQL backends, with their values as
+    /// stored.
+    #[cfg(any(feature = "sqlite", feature = "postgres"))]
+    fn sql_filters(&self) -> Vec<SqlFilter> {
+        let mut filters = Vec::new();
+        let mut text = |column, value: Option<String>| {
+            if let Some(value) = value {
+                filters.push(SqlFilter::Equals(column, value));
+            }
+        };
+        text("operation", self.operation.map(|o| o.to_string()));
+        text("status", self.status.map(|s| s.to_string()));
+        text("network", self.network.map(|n| n.to_string()));
+        text("payer", self.payer.as_ref().map(ToString::to_string));
+        text("pay_to", self.pay_to.as_ref().map(ToString::to_string));
+        text(
+            "transaction_hash",
+            self.transaction.as_ref().map(ToString::to_string),
+        );
+        text("payload_hash", self.payload_hash.map(|h| h.to_string()));
+        if let Some(metadata) = &self.metadata {
+            for (key, value) in &metadata.0 {
+                filters.push(SqlFilter::MetadataEquals(key.clone(), value.to_string()));
+            }
+        }
+        if let Some(from) = self.from {
+            filters.push(SqlFilter::AtLeast("completed_at_ms", from.0 as i64));
+        }
+        if let Some(to) = self.to {
+            filters.push(SqlFilter::Before("completed_at_ms", to.0 as i64));
+        }
+        filters
+    }
+}
+
+/// A condition on a column of the SQL backends.
+#[cfg(any(feature = "sqlite", feature = "postgres"))]
+enum SqlFilter {
+    Equals(&'static str, String),
+    AtLeast(&'static str, i64),
+    Before(&'static str, i64),
+    /// The metadata has the key, with the JSON value.
+    MetadataEquals(String, String),
+}
+
+/// Entries of a JSON object, which the metadata of a record must have, each with the same value.
+///
+/// Written in a query string as the JSON object itself, e.g. `metadata={"orderId":"42"}`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MetadataFilter(pub Map<String, Value>);
+
+impl MetadataFilter {
+    /// Whether `metadata` is an object with all the entries of the filter.
+    pub fn matches(&self, metadata: Option<&Value>) -> bool {
+        let Some(Value::Object(metadata)) = metadata else {
+            return self.0.is_empty();
+        };
+        self.0
+            .iter()
+            .all(|(key, value)| metadata.get(key) == Some(value))
+    }
+}
+
+impl Serialize for MetadataFilter {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let json = serde_json::to_string(&self.0).map_err(serde::ser::Error::custom)?;
+        serializer.serialize_str(&json)
+    }
+}
+
+impl<'de> Deserialize<'de> for MetadataFilter {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let json = String::deserialize(deserializer)?;
+        serde_json::from_str(&json)
+            .map(Self)
+            .map_err(|e| serde::de::Error::custom(format!("metadata must be a JSON object: {e}")))
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PaymentPagination {
+    pub limit: usize,
+    pub offset: usize,
+    /// Records matching the query, on all pages.
+    pub total: usize,
+}
+
+/// Result of `GET /payments`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PaymentPage {
+    pub items: Vec<PaymentRecord>,
+    pub pagination: PaymentPagination,
+}
+
+/// History of the payments of a facilitator.
+pub trait PaymentRepository: Send + Sync {
+    /// Appends a record.
+    fn record(&self, record: PaymentRecord) -> BoxFuture<'_, Result<(), StorageError>>;
+    /// The page of records matching `query`, newest first.
+    fn query<'a>(
+        &'a self,
+        query: &'a PaymentQuery,
+    ) -> BoxFuture<'a, Result<PaymentPage, StorageError>>;
+}
+
+/// Records kept in memory, the newest [`DEFAULT_MEMORY_CAPACITY`] by default.
+#[derive(Debug)]
+pub struct InMemoryPayments {
+    records: Mutex<VecDeque<PaymentRecord>>,
+    capacity: usize,
+}
+
+impl Default for InMemoryPayments {
+    fn default() -> Self {
+        Self::new(DEFAULT_MEMORY_CAPACITY)
+    }
+}
+
+impl InMemoryPayments {
+    /// Keeps the newest `capacity` records, at least one.
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            records: Mutex::new(VecDeque::new()),
+            capacity: capacity.max(1),
+        }
+    }
+}
+
+impl PaymentRepository for InMemoryPayments {
+    fn record(&self, record: PaymentRecord) -> BoxFuture<'_, Result<(), StorageError>> {
+        let mut records = self.records.lock().unwrap();
+        if records.len() >= self.capacity {
+            records.pop_front();
+        }
+        records.push_back(record);
+        Box::pin(async { Ok(()) })
+    }
+
+    fn query<'a>(
+        &'a self,
+        query: &'a PaymentQuery,
+    ) -> BoxFuture<'a, Result<PaymentPage, StorageError>> {
+        let (limit, offset) = (query.limit(), query.offset());
+        let records = self.records.lock().unwrap();
+        // Appended in completion order, newest last
+        let matching = records.iter().rev().filter(|record| query.matches(record));
+        let total = matching.clone().count();
+        let items = matching.skip(offset).take(limit).cloned().collect();
+        let page = PaymentPage {
+            items,
+            pagination: PaymentPagination {
+                limit,
+                offset,
+                total,
+            },
+        };
+        Box::pin(async move { Ok(page) })
+    }
+}
+
+/// Records the payments of a facilitator, and pages through them.
+///
+/// Cloning is cheap: clones share the repository.
+#[derive(Clone)]
+pub struct PaymentHistory {
+    repository: Arc<dyn PaymentRepository>,
+}
+
+impl PaymentHistory {
+    pub fn new(repository: Arc<dyn PaymentRepository>) -> Self {
+        Self { repository }
+    }
+
+    /// History kept in the repository configured by `PAYMENT_HISTORY_URL`, or `None` if not
+    /// configured.
+    pub async fn from_env() -> Result<Option<Self>, StorageError> {
+        let Some(url) = env::var(ENV_PAYMENT_HISTORY_URL)
+            .ok()
+            .filter(|url| !url.is_empty())
+        else {
+            return Ok(None);
+        };
+        let repository: Arc<dyn PaymentRepository> = if url == "memory" {
+            Arc::new(InMemoryPayments::default())
+        } else {
+            repository_from_url(url).await?
+        };
+        Ok(Some(Self::new(repository)))
+    }
+
+    /// Records the verification of `request`, started at `started_at` and completed with
+    /// `result`, in the background.
+    pub fn verified<E: Display>(
+        &self,
+        request: &VerifyRequest,
+        result: &Result<VerifyResponse, E>,
+        started_at: UnixTimestampMs,
+    ) {
+        self.spawn_record(PaymentRecord::verified(request, result, started_at));
+    }
+
+    /// Records the settlement of `request`, started at `started_at` and completed with `result`,
+    /// in the background.
+    pub fn settled<E: Display>(
+        &self,
+        request: &SettleRequest,
+        result: &Result<SettleResponse, E>,
+        started_at: UnixTimestampMs,
+    ) {
+        self.spawn_record(PaymentRecord::settled(request, result, started_at));
+    }
+
+    fn spawn_record(&self, record: PaymentRecord) {
+        let repository = self.repository.clone();
+        tokio::spawn(async move {
+            if let Err(error) = repository.record(record).await {
+                tracing::warn!(%error, "Failed to record payment");
+            }
+        });
+    }
+
+    /// The page of records matching `query`, newest first.
+    pub async fn query(&self, query: &PaymentQuery) -> Result<PaymentPage, StorageError> {
+        self.repository.query(query).await
+    }
+}
+
+async fn repository_from_url(url: String) -> Result<Arc<dyn PaymentRepository>, StorageError> {
+    #[cfg(feature = "sqlite")]
+    if let Some(path) = url.strip_prefix("sqlite:") {
+        return Ok(Arc::new(sqlite::SqlitePayments::open(path).await?));
+    }
+    #[cfg(feature = "postgres")]
+    if url.starts_with("postgres://") || url.starts_with("postgresql://") {
+        return Ok(Arc::new(postgres::PostgresPayments::connect(&url).await?));
+    }
+    Err(StorageError::UnsupportedUrl(url))
+}
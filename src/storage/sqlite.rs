@@ -0,0 +1,178 @@
+//! [`PaymentRepository`] backed by a SQLite database, over [sqlx](https://docs.rs/sqlx).
+
+use futures_util::future::BoxFuture;
+use sqlx::sqlite::{SqliteConnectOptions, SqlitePool, SqlitePoolOptions};
+use sqlx::{QueryBuilder, Row, Sqlite};
+
+use super::{
+    PaymentPage, PaymentPagination, PaymentQuery, PaymentRecord, PaymentRepository, SqlFilter,
+    StorageError,
+};
+
+/// Connections to the database file, shared by the writes and the queries of the history.
+const MAX_CONNECTIONS: u32 = 4;
+
+/// Records persisted in the `x402_payments` table of a SQLite database file, surviving restarts
+/// of a single facilitator instance. The table is created on [`open`](Self::open).
+#[derive(Debug, Clone)]
+pub struct SqlitePayments {
+    pool: SqlitePool,
+}
+
+impl SqlitePayments {
+    /// Opens (or creates) the database at `path`.
+    pub async fn open(path: &str) -> Result<Self, StorageError> {
+        let options = SqliteConnectOptions::new()
+            .filename(path)
+            .create_if_missing(true);
+        let pool = SqlitePoolOptions::new()
+            .max_connections(MAX_CONNECTIONS)
+            .connect_with(options)
+            .await
+            .map_err(backend_error)?;
+        for statement in [
+            "CREATE TABLE IF NOT EXISTS x402_payments (
+                seq INTEGER PRIMARY KEY AUTOINCREMENT,
+                id TEXT NOT NULL UNIQUE,
+                operation TEXT NOT NULL,
+                status TEXT NOT NULL,
+                network TEXT NOT NULL,
+                payer TEXT,
+                pay_to TEXT NOT NULL,
+                transaction_hash TEXT,
+                payload_hash TEXT NOT NULL,
+                completed_at_ms INTEGER NOT NULL,
+                record TEXT NOT NULL,
+                metadata TEXT
+            )",
+            "CREATE INDEX IF NOT EXISTS x402_payments_completed_at_ms
+                ON x402_payments (completed_at_ms)",
+            "CREATE INDEX IF NOT EXISTS x402_payments_payer ON x402_payments (payer)",
+            "CREATE INDEX IF NOT EXISTS x402_payments_pay_to ON x402_payments (pay_to)",
+            "CREATE INDEX IF NOT EXISTS x402_payments_transaction_hash
+                ON x402_payments (transaction_hash)",
+            "CREATE INDEX IF NOT EXISTS x402_payments_payload_hash
+                ON x402_payments (payload_hash)",
+        ] {
+            sqlx::query(statement)
+                .execute(&pool)
+                .await
+                .map_err(backend_error)?;
+        }
+        // Tables created before metadata was recorded
+        let has_metadata: i64 = sqlx::query_scalar(
+            "SELECT COUNT(*) FROM pragma_table_info('x402_payments') WHERE name = 'metadata'",
+        )
+        .fetch_one(&pool)
+        .await
+        .map_err(backend_error)?;
+        if has_metadata == 0 {
+            sqlx::query("ALTER TABLE x402_payments ADD COLUMN metadata TEXT")
+                .execute(&pool)
+                .await
+                .map_err(backend_error)?;
+        }
+        Ok(Self { pool })
+    }
+}
+
+/// Appends the conditions of `filters` to a `WHERE` clause.
+fn push_filters(builder: &mut QueryBuilder<'_, Sqlite>, filters: Vec<SqlFilter>) {
+    for filter in filters {
+        match filter {
+            SqlFilter::Equals(column, value) => {
+                builder.push(format!(" AND {column} = ")).push_bind(value);
+            }
+            SqlFilter::AtLeast(column, value) => {
+                builder.push(format!(" AND {column} >= ")).push_bind(value);
+            }
+            SqlFilter::Before(column, value) => {
+                builder.push(format!(" AND {column} < ")).push_bind(value);
+            }
+            SqlFilter::MetadataEquals(key, value) => {
+                builder
+                    .push(" AND EXISTS (SELECT 1 FROM json_each(metadata) WHERE key = ")
+                    .push_bind(key)
+                    .push(" AND value = json_extract(")
+                    .push_bind(value)
+                    .push(", '$'))");
+            }
+        }
+    }
+}
+
+impl PaymentRepository for SqlitePayments {
+    fn record(&self, record: PaymentRecord) -> BoxFuture<'_, Result<(), StorageError>> {
+        Box::pin(async move {
+            let json =
+                serde_json::to_string(&record).map_err(|e| StorageError::Backend(e.to_string()))?;
+            sqlx::query(
+                "INSERT INTO x402_payments (id, operation, status, network, payer, pay_to,
+                    transaction_hash, payload_hash, completed_at_ms, record, metadata)
+                VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+            )
+            .bind(&record.id)
+            .bind(record.operation.as_str())
+            .bind(record.status.as_str())
+            .bind(record.network.to_string())
+            .bind(record.payer.as_ref().map(ToString::to_string))
+            .bind(record.pay_to.to_string())
+            .bind(record.transaction.as_ref().map(ToString::to_string))
+            .bind(record.payload_hash.to_string())
+            .bind(record.completed_at.0 as i64)
+            .bind(json)
+            .bind(record.metadata.as_ref().map(ToString::to_string))
+            .execute(&self.pool)
+            .await
+            .map_err(backend_error)?;
+            Ok(())
+        })
+    }
+
+    fn query<'a>(
+        &'a self,
+        query: &'a PaymentQuery,
+    ) -> BoxFuture<'a, Result<PaymentPage, StorageError>> {
+        Box::pin(async move {
+            let (limit, offset) = (query.limit(), query.offset());
+            let mut count = QueryBuilder::new("SELECT COUNT(*) FROM x402_payments WHERE 1 = 1");
+            push_filters(&mut count, query.sql_filters());
+            let total: i64 = count
+                .build_query_scalar()
+                .fetch_one(&self.pool)
+                .await
+                .map_err(backend_error)?;
+            let mut select = QueryBuilder::new("SELECT record FROM x402_payments WHERE 1 = 1");
+            push_filters(&mut select, query.sql_filters());
+            select
+                .push(" ORDER BY completed_at_ms DESC, seq DESC LIMIT ")
+                .push_bind(limit as i64)
+                .push(" OFFSET ")
+                .push_bind(offset as i64);
+            let rows = select
+                .build()
+                .fetch_all(&self.pool)
+                .await
+                .map_err(backend_error)?;
+            let items = rows
+                .iter()
+                .map(|row| {
+                    let record: String = row.try_get(0).map_err(backend_error)?;
+                    serde_json::from_str(&record).map_err(|e| StorageError::Backend(e.to_string()))
+                })
+                .collect::<Result<_, _>>()?;
+            Ok(PaymentPage {
+                items,
+                pagination: PaymentPagination {
+                    limit,
+                    offset,
+                    total: total as usize,
+                },
+            })
+        })
+    }
+}
+
+fn backend_error(error: sqlx::Error) -> StorageError {
+    StorageError::Backend(error.to_string())
+}
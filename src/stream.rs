@@ -0,0 +1,725 @@
+//! Session state of pay-per-slice streams, see `x402-ws-stream.md`.
+//!
+//! A Seller streaming content in paid slices opens a session with `stream.init`, giving the
+//! requirements of one slice (`maxAmountRequired` is the price per unit) and the unit length.
+//! [`StreamSessionManager`] then tracks, per stream id:
+//!
+//! - the index of the next slice to be paid,
+//! - the requirements issued for it by `stream.require`, valid until `expiresAt`, under a
+//!   `requireId`,
+//! - the prepaid window, extended by one unit for every slice paid with `stream.pay`.
+//!
+//! Payments are verified against the requirements issued by the facilitator, never against
+//! requirements echoed by the Buyer. A Seller repricing the stream before the Buyer pays cancels
+//! the pending requirements with `stream.require.cancel`, optionally issuing new ones: the last
+//! requirements issued are the only authoritative ones, and payments naming the `requireId` of
+//! cancelled ones are rejected. A session expires once it has been idle for the idle timeout
+//! (5 minutes by default) past the end of its prepaid window.
+//!
+//! The unit length is between [`UNIT_RANGE`] bounds. `expiresAt` is in seconds since the Unix
+//! epoch, `prepaidUntilMs` in milliseconds.
+//!
+//! Sessions opened with requirements of the `upto` scheme are metered: the whole stream is one
+//! slice, of at most one unit, paid with an authorization of up to `maxAmountRequired`. The
+//! payment is only verified, and kept by the session. At the end of the stream, the Seller
+//! finalizes the session with `stream.finalize`, settling the amount consumed, and closes it.
+//! Finalizing a session of slices closes it.
+//!
+//! Sessions opened with a channel, and requirements of the `upto` scheme, are paid through an
+//! off-chain payment channel (see [`crate::channel`]): the first slice required opens the channel
+//! with an authorization of up to its capacity, and slices are then paid with vouchers
+//! (`stream.voucher`). Finalizing the session settles the last voucher.
+//!
+//! Closed sessions, finalized or expired, are reported to the subscribers of
+//! [`StreamSessionManager::subscribe_closed`]. Expired sessions are noticed, and reported, when
+//! the next session is opened.
+
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::broadcast;
+
+use crate::channel::{ChannelConfig, ChannelError, ChannelState};
+use crate::duration::{DurationError, DurationRange, Seconds};
+use crate::timestamp::UnixTimestampMs;
+use crate::types::stream::StreamRequire;
+use crate::types::{ExactPaymentPayload, PaymentPayload, PaymentRequirements, Scheme, TokenAmount};
+use crate::ws_error::WsErrorCode;
+
+/// Default time after which a session without activity expires.
+const DEFAULT_IDLE_TIMEOUT: Duration = Duration::from_secs(300);
+/// Grace on top of the unit length during which the requirements of a slice can be paid.
+const REQUIRE_GRACE: Duration = Duration::from_secs(10);
+/// Closed sessions buffered per subscriber before the oldest are dropped.
+const SUBSCRIBER_CAPACITY: usize = 1024;
+/// Unit lengths accepted by [`StreamSessionManager::init`]: from 1 second to 1 day.
+pub const UNIT_RANGE: DurationRange =
+    DurationRange::new(Duration::from_secs(1), Duration::from_secs(86_400));
+
+#[derive(Debug, thiserror::Error)]
+pub enum StreamError {
+    #[error("Unknown or expired stream {0}")]
+    UnknownStream(String),
+    #[error("No slice required, call stream.require first")]
+    NoPendingSlice,
+    #[error("Slice {actual} is not the required slice {expected}")]
+    SliceMismatch { expected: u64, actual: u64 },
+    #[error("Requirements of slice {0} expired")]
+    RequireExpired(u64),
+    #[error("Payment of slice {0} already in progress")]
+    PaymentInProgress(u64),
+    #[error("Invalid unitSeconds: {0}")]
+    InvalidUnit(#[source] DurationError),
+    #[error("Metered stream {0} already authorized, finalize it with stream.finalize")]
+    AlreadyAuthorized(String),
+    #[error("Stream {0} is being finalized")]
+    Finalizing(String),
+    #[error("Stream {0} is not metered, and consumes no amount")]
+    NotMetered(String),
+    #[error("consumedAmount required to finalize metered stream {0}")]
+    ConsumedAmountRequired(String),
+    #[error("Consumed amount {consumed} exceeds the authorized maximum {max}")]
+    ConsumedExceedsMax {
+        consumed: TokenAmount,
+        max: TokenAmount,
+    },
+    #[error("Stream {0} is not paid through a channel")]
+    NotChannel(String),
+    #[error("Requirements {0} were cancelled, pay the last stream.require")]
+    RequireSuperseded(String),
+    #[error("Invalid repricing: {0}")]
+    InvalidReprice(String),
+    #[error(transparent)]
+    Channel(#[from] ChannelError),
+}
+
+impl StreamError {
+    /// Code of the error envelope of the error, see [`crate::ws_error`].
+    pub fn code(&self) -> WsErrorCode {
+        match self {
+            StreamError::UnknownStream(_) => WsErrorCode::STREAM_NOT_FOUND,
+            StreamError::NoPendingSlice | StreamError::SliceMismatch { .. } => {
+                WsErrorCode::SLICE_NOT_REQUIRED
+            }
+            StreamError::RequireExpired(_) => WsErrorCode::REQUIRE_EXPIRED,
+            StreamError::RequireSuperseded(_) => WsErrorCode::REQUIRE_CANCELLED,
+            StreamError::PaymentInProgress(_)
+            | StreamError::AlreadyAuthorized(_)
+            | StreamError::Finalizing(_) => WsErrorCode::STREAM_BUSY,
+            StreamError::InvalidUnit(_)
+            | StreamError::NotMetered(_)
+            | StreamError::ConsumedAmountRequired(_)
+            | StreamError::ConsumedExceedsMax { .. }
+            | StreamError::NotChannel(_)
+            | StreamError::InvalidReprice(_)
+            | StreamError::Channel(_) => WsErrorCode::INVALID_PARAMS,
+        }
+    }
+}
+
+/// Requirements issued for a slice, i.e. the params of a `stream.require` message.
+pub type SliceRequirements = StreamRequire;
+
+#[derive(Debug, Clone)]
+struct PendingSlice {
+    requirements: SliceRequirements,
+    /// Set while the payment is being verified or settled.
+    paying: bool,
+}
+
+/// State of a stream, as tracked by the [`StreamSessionManager`].
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StreamSession {
+    pub stream_id: String,
+    /// Requirements of one slice.
+    pub requirements: PaymentRequirements,
+    pub unit_seconds: Seconds,
+    /// Index of the next slice to be paid.
+    pub slice_index: u64,
+    /// End of the prepaid window; `0` before the first payment.
+    #[serde(rename = "prepaidUntilMs")]
+    pub prepaid_until: UnixTimestampMs,
+    /// Payment channel of the stream, if paid with vouchers.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub channel: Option<ChannelState>,
+    #[serde(skip)]
+    pending: Option<PendingSlice>,
+    #[serde(skip)]
+    last_activity: UnixTimestampMs,
+    /// Verified payment of a metered stream, settled when finalizing.
+    #[serde(skip)]
+    authorization: Option<PaymentPayload>,
+    /// Set while the session is being finalized.
+    #[serde(skip)]
+    finalizing: bool,
+}
+
+impl StreamSession {
+    /// Whether the session is metered, paid with a single `upto` authorization.
+    pub fn is_metered(&self) -> bool {
+        self.requirements.scheme == Scheme::Upto && self.channel.is_none()
+    }
+
+    /// Payment of the amount of the channel not settled yet, with the requirements to settle it
+    /// against: `None` if the channel is not open, or has nothing to settle.
+    fn channel_settlement(&self) -> Option<(PaymentPayload, PaymentRequirements)> {
+        let channel = self.channel.as_ref()?;
+        let authorization = channel.authorization.clone()?;
+        let unsettled = channel.unsettled_amount();
+        if unsettled.0.is_zero() {
+            return None;
+        }
+        let mut requirements = self.requirements.clone();
+        requirements.max_amount_required = channel.config.capacity;
+        requirements.consumed_amount = Some(unsettled);
+        requirements.settled_amount =
+            Some(channel.settled_amount).filter(|settled| !settled.0.is_zero());
+        Some((authorization, requirements))
+    }
+
+    fn expires_at(&self, idle_timeout: Duration) -> UnixTimestampMs {
+        self.prepaid_until.max(self.last_activity) + idle_timeout
+    }
+}
+
+/// Why a stream session closed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum StreamCloseReason {
+    /// Finalized by the Seller with `stream.finalize`.
+    Finalized,
+    /// Idle for the idle timeout past the end of its prepaid window.
+    Expired,
+}
+
+/// Last state of a closed stream session.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ClosedStream {
+    #[serde(flatten)]
+    pub session: StreamSession,
+    pub reason: StreamCloseReason,
+}
+
+/// Sessions of all streams served by a facilitator, keyed by stream id.
+#[derive(Clone)]
+pub struct StreamSessionManager {
+    sessions: Arc<Mutex<HashMap<String, StreamSession>>>,
+    idle_timeout: Duration,
+    closed: broadcast::Sender<ClosedStream>,
+}
+
+impl Default for StreamSessionManager {
+    fn default() -> Self {
+        let (closed, _) = broadcast::channel(SUBSCRIBER_CAPACITY);
+        Self {
+            sessions: Arc::new(Mutex::new(HashMap::new())),
+            idle_timeout: DEFAULT_IDLE_TIMEOUT,
+            closed,
+        }
+    }
+}
+
+impl StreamSessionManager {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the time after which a session without activity expires, past its prepaid window.
+    #[allow(dead_code)] // Public for consumption by downstream crates.
+    pub fn with_idle_timeout(&self, idle_timeout: Duration) -> Self {
+        let mut this = self.clone();
+        this.idle_timeout = idle_timeout;
+        this
+    }
+
+    /// Opens a session for a stream of slices of `unit_seconds`, each paid with `requirements`,
+    /// through a payment channel if `channel` is set.
+    ///
+    /// # Errors
+    /// Returns [`StreamError::InvalidUnit`] if `unit_seconds` is out of [`UNIT_RANGE`], and
+    /// [`StreamError::Channel`] if the channel can not be paid with `requirements`.
+    pub fn init(
+        &self,
+        requirements: PaymentRequirements,
+        unit_seconds: Seconds,
+        channel: Option<ChannelConfig>,
+    ) -> Result<StreamSession, StreamError> {
+        let unit_seconds = unit_seconds
+            .check(UNIT_RANGE)
+            .map_err(StreamError::InvalidUnit)?;
+        if let Some(channel) = &channel {
+            if requirements.scheme != Scheme::Upto {
+                return Err(ChannelError::SchemeRequired.into());
+            }
+            // Vouchers are signed in a domain of the chain and the token
+            crate::channel::voucher_domain(requirements.network, &requirements.asset)?;
+            if channel.capacity < requirements.max_amount_required {
+                return Err(ChannelError::CapacityBelowPrice {
+                    capacity: channel.capacity,
+                    price: requirements.max_amount_required,
+                }
+                .into());
+            }
+        }
+        let now = UnixTimestampMs::now();
+        let session = StreamSession {
+            stream_id: uuid::Uuid::new_v4().to_string(),
+            requirements,
+            unit_seconds,
+            slice_index: 0,
+            prepaid_until: UnixTimestampMs::default(),
+            channel: channel.map(ChannelState::new),
+            pending: None,
+            last_activity: now,
+            authorization: None,
+            finalizing: false,
+        };
+        let mut sessions = self.sessions.lock().unwrap();
+        let expired: Vec<String> = sessions
+            .iter()
+            .filter(|(_, session)| session.expires_at(self.idle_timeout) <= now)
+            .map(|(stream_id, _)| stream_id.clone())
+            .collect();
+        for stream_id in expired {
+            if let Some(session) = sessions.remove(&stream_id) {
+                self.report_closed(session, StreamCloseReason::Expired);
+            }
+        }
+        sessions.insert(session.stream_id.clone(), session.clone());
+        Ok(session)
+    }
+
+    /// Receives every session closed from now on.
+    #[allow(dead_code)] // Public for consumption by downstream crates.
+    pub fn subscribe_closed(&self) -> broadcast::Receiver<ClosedStream> {
+        self.closed.subscribe()
+    }
+
+    /// Returns the session of `stream_id`, unless unknown or expired.
+    #[allow(dead_code)] // Public for consumption by downstream crates.
+    pub fn get(&self, stream_id: &str) -> Option<StreamSession> {
+        let now = UnixTimestampMs::now();
+        self.sessions
+            .lock()
+            .unwrap()
+            .get(stream_id)
+            .filter(|session| session.expires_at(self.idle_timeout) > now)
+            .cloned()
+    }
+
+    /// Returns the sessions not expired, by stream id.
+    pub fn sessions(&self) -> Vec<StreamSession> {
+        let now = UnixTimestampMs::now();
+        let mut sessions: Vec<_> = self
+            .sessions
+            .lock()
+            .unwrap()
+            .values()
+            .filter(|session| session.expires_at(self.idle_timeout) > now)
+            .cloned()
+            .collect();
+        sessions.sort_by(|a, b| a.stream_id.cmp(&b.stream_id));
+        sessions
+    }
+
+    /// Issues the requirements of the next slice of `stream_id`. Requirements issued before for
+    /// the same slice are returned again while still valid.
+    ///
+    /// A metered session is paid once: requirements are not issued again once authorized. The
+    /// first slice of a channel is paid by the authorization opening the channel, of up to its
+    /// capacity.
+    pub fn require(&self, stream_id: &str) -> Result<SliceRequirements, StreamError> {
+        self.with_session(stream_id, |session, now| {
+            if session.is_metered() && session.authorization.is_some() {
+                return Err(StreamError::AlreadyAuthorized(session.stream_id.clone()));
+            }
+            if let Some(pending) = &session.pending
+                && (pending.paying || UnixTimestampMs::from(pending.requirements.expires_at) > now)
+            {
+                return Ok(pending.requirements.clone());
+            }
+            Ok(Self::issue(session, now, None))
+        })
+    }
+
+    /// Cancels the requirements `require_id` of `stream_id`, not paid yet, e.g. because the price
+    /// moved. With `requirements`, the session is repriced to them, and the requirements of the
+    /// slice are issued again, superseding the cancelled ones. Returns the new requirements.
+    ///
+    /// `requirements` may only change the price and description of a slice: the scheme, network,
+    /// asset and recipient of the stream stay the same.
+    ///
+    /// # Errors
+    /// Returns [`StreamError::RequireSuperseded`] if `require_id` is not the pending requirements,
+    /// [`StreamError::PaymentInProgress`] if they are being paid, and
+    /// [`StreamError::InvalidReprice`] if `requirements` do not fit the stream.
+    pub fn cancel_require(
+        &self,
+        stream_id: &str,
+        require_id: &str,
+        requirements: Option<PaymentRequirements>,
+    ) -> Result<Option<SliceRequirements>, StreamError> {
+        self.with_session(stream_id, |session, now| {
+            if session.finalizing {
+                return Err(StreamError::Finalizing(session.stream_id.clone()));
+            }
+            let pending = session
+                .pending
+                .as_ref()
+                .filter(|pending| pending.requirements.require_id == require_id)
+                .ok_or_else(|| StreamError::RequireSuperseded(require_id.to_string()))?;
+            if pending.paying {
+                return Err(StreamError::PaymentInProgress(
+                    pending.requirements.slice_index,
+                ));
+            }
+            let Some(requirements) = requirements else {
+                session.pending = None;
+                return Ok(None);
+            };
+            Self::check_reprice(session, &requirements)?;
+            session.requirements = requirements;
+            Ok(Some(Self::issue(
+                session,
+                now,
+                Some(require_id.to_string()),
+            )))
+        })
+    }
+
+    /// Starts the payment of slice `slice_index`, returning the requirements to verify it against.
+    /// A payment naming `require_id` must pay the pending requirements, not cancelled ones.
+    /// Must be followed by [`complete_payment`](Self::complete_payment) or
+    /// [`abort_payment`](Self::abort_payment).
+    pub fn begin_payment(
+        &self,
+        stream_id: &str,
+        slice_index: u64,
+        require_id: Option<&str>,
+    ) -> Result<PaymentRequirements, StreamError> {
+        self.with_session(stream_id, |session, now| {
+            if session.channel.as_ref().is_some_and(ChannelState::is_open) {
+                return Err(ChannelError::AlreadyOpen(session.stream_id.clone()).into());
+            }
+            let pending = Self::payable_slice(session, slice_index, require_id, now)?;
+            pending.paying = true;
+            Ok(pending.requirements.requirements.clone())
+        })
+    }
+
+    /// Pays slice `slice_index` of the channel of `stream_id` with a voucher of `cumulative`,
+    /// signed by `signer`, naming `require_id` if any: extends the prepaid window by one unit, and
+    /// moves to the next slice.
+    /// Returns the updated session, and the payment to settle if a checkpoint is due, with the
+    /// requirements to settle it against; the checkpoint must then be followed by
+    /// [`complete_checkpoint`](Self::complete_checkpoint) or
+    /// [`abort_checkpoint`](Self::abort_checkpoint).
+    #[allow(clippy::type_complexity)]
+    pub fn accept_voucher(
+        &self,
+        stream_id: &str,
+        slice_index: u64,
+        require_id: Option<&str>,
+        cumulative: TokenAmount,
+        signer: alloy::primitives::Address,
+    ) -> Result<(StreamSession, Option<(PaymentPayload, PaymentRequirements)>), StreamError> {
+        self.with_session(stream_id, |session, now| {
+            let price = session.requirements.max_amount_required;
+            let channel = session
+                .channel
+                .as_ref()
+                .ok_or_else(|| StreamError::NotChannel(session.stream_id.clone()))?;
+            channel.check_voucher(&session.stream_id, cumulative, signer, price)?;
+            Self::payable_slice(session, slice_index, require_id, now)?;
+            session.pending = None;
+            session.slice_index += 1;
+            session.prepaid_until =
+                session.prepaid_until.max(now) + session.unit_seconds.as_duration();
+            let channel = session.channel.as_mut().expect("Channel checked above");
+            channel.paid_amount = cumulative;
+            let checkpoint = if channel.is_checkpoint_due(now) {
+                channel.checkpointing = true;
+                session.channel_settlement()
+            } else {
+                None
+            };
+            Ok((session.clone(), checkpoint))
+        })
+    }
+
+    /// Records the settlement of `amount` by a checkpoint of the channel of `stream_id`.
+    pub fn complete_checkpoint(&self, stream_id: &str, amount: TokenAmount) {
+        let _ = self.with_session(stream_id, |session, now| {
+            if let Some(channel) = session.channel.as_mut() {
+                channel.settled_amount = channel.settled_amount + amount;
+                channel.last_checkpoint = now;
+                channel.checkpointing = false;
+            }
+            Ok(())
+        });
+    }
+
+    /// Gives up a checkpoint of the channel of `stream_id`, tried again with the next voucher.
+    pub fn abort_checkpoint(&self, stream_id: &str) {
+        let _ = self.with_session(stream_id, |session, _| {
+            if let Some(channel) = session.channel.as_mut() {
+                channel.checkpointing = false;
+            }
+            Ok(())
+        });
+    }
+
+    /// Records the payment of slice `slice_index`: extends the prepaid window by one unit, and
+    /// moves to the next slice. Metered sessions keep `payment`, to be settled when finalizing.
+    /// A payment of a channel not open yet opens it instead, without paying the slice.
+    /// Returns the updated session.
+    pub fn complete_payment(
+        &self,
+        stream_id: &str,
+        slice_index: u64,
+        payment: PaymentPayload,
+    ) -> Result<StreamSession, StreamError> {
+        self.with_session(stream_id, |session, now| {
+            match &session.pending {
+                Some(pending)
+                    if pending.paying && pending.requirements.slice_index == slice_index => {}
+                _ => return Err(StreamError::NoPendingSlice),
+            }
+            session.pending = None;
+            if let Some(channel) = session.channel.as_mut()
+                && !channel.is_open()
+            {
+                channel.payer = match &payment.payload {
+                    ExactPaymentPayload::Permit(permit) => Some(permit.permit.owner.0),
+                    _ => None,
+                };
+                channel.authorization = Some(payment);
+                channel.last_checkpoint = now;
+                return Ok(session.clone());
+            }
+            session.slice_index += 1;
+            if session.is_metered() {
+                session.authorization = Some(payment);
+            }
+            session.prepaid_until =
+                session.prepaid_until.max(now) + session.unit_seconds.as_duration();
+            Ok(session.clone())
+        })
+    }
+
+    /// Gives up the payment of slice `slice_index`, which can be paid again.
+    pub fn abort_payment(&self, stream_id: &str, slice_index: u64) {
+        let _ = self.with_session(stream_id, |session, _| {
+            if let Some(pending) = session.pending.as_mut()
+                && pending.requirements.slice_index == slice_index
+            {
+                pending.paying = false;
+            }
+            Ok(())
+        });
+    }
+
+    /// Starts finalizing `stream_id`, having consumed `consumed`. Returns the payment to settle,
+    /// with the requirements to settle it against, and marks the session as being finalized; must
+    /// then be followed by [`complete_finalize`](Self::complete_finalize) or
+    /// [`abort_finalize`](Self::abort_finalize). Returns `None`, and closes the session, if there
+    /// is nothing to settle: a session of slices, a metered session not authorized yet, nothing
+    /// consumed, or a channel without vouchers not settled yet. The amount of a channel is the
+    /// amount of its last voucher, not `consumed`.
+    pub fn begin_finalize(
+        &self,
+        stream_id: &str,
+        consumed: Option<TokenAmount>,
+    ) -> Result<Option<(PaymentPayload, PaymentRequirements)>, StreamError> {
+        let settlement = self.with_session(stream_id, |session, _| {
+            if session.finalizing {
+                return Err(StreamError::Finalizing(session.stream_id.clone()));
+            }
+            if let Some(pending) = &session.pending
+                && pending.paying
+            {
+                return Err(StreamError::PaymentInProgress(
+                    pending.requirements.slice_index,
+                ));
+            }
+            if let Some(channel) = &session.channel {
+                if consumed.is_some() {
+                    return Err(ChannelError::ConsumedAmountNotAllowed.into());
+                }
+                if channel.checkpointing {
+                    return Err(
+                        ChannelError::CheckpointInProgress(session.stream_id.clone()).into(),
+                    );
+                }
+                let settlement = session.channel_settlement();
+                session.finalizing = settlement.is_some();
+                return Ok(settlement);
+            }
+            if !session.is_metered() {
+                return match consumed {
+                    Some(_) => Err(StreamError::NotMetered(session.stream_id.clone())),
+                    None => Ok(None),
+                };
+            }
+            let Some(authorization) = &session.authorization else {
+                return Ok(None);
+            };
+            let consumed = consumed
+                .ok_or_else(|| StreamError::ConsumedAmountRequired(session.stream_id.clone()))?;
+            let max = session.requirements.max_amount_required;
+            if consumed > max {
+                return Err(StreamError::ConsumedExceedsMax { consumed, max });
+            }
+            if consumed.0.is_zero() {
+                return Ok(None);
+            }
+            session.finalizing = true;
+            let mut requirements = session.requirements.clone();
+            requirements.consumed_amount = Some(consumed);
+            Ok(Some((authorization.clone(), requirements)))
+        })?;
+        if settlement.is_none() {
+            self.finalized(stream_id);
+        }
+        Ok(settlement)
+    }
+
+    /// Closes `stream_id`, once its payment is settled.
+    pub fn complete_finalize(&self, stream_id: &str) {
+        self.finalized(stream_id);
+    }
+
+    /// Closes `stream_id`, finalized.
+    fn finalized(&self, stream_id: &str) {
+        let session = self.sessions.lock().unwrap().remove(stream_id);
+        if let Some(session) = session {
+            self.report_closed(session, StreamCloseReason::Finalized);
+        }
+    }
+
+    /// Reports `session` closed for `reason`.
+    fn report_closed(&self, session: StreamSession, reason: StreamCloseReason) {
+        // No subscriber is not an error.
+        let _ = self.closed.send(ClosedStream { session, reason });
+    }
+
+    /// Gives up finalizing `stream_id`, which can be finalized again.
+    pub fn abort_finalize(&self, stream_id: &str) {
+        let _ = self.with_session(stream_id, |session, _| {
+            session.finalizing = false;
+            Ok(())
+        });
+    }
+
+    /// Issues the requirements of the next slice of `session`, superseding `supersedes`.
+    fn issue(
+        session: &mut StreamSession,
+        now: UnixTimestampMs,
+        supersedes: Option<String>,
+    ) -> SliceRequirements {
+        let expires_at = now + session.unit_seconds.as_duration() + REQUIRE_GRACE;
+        let mut requirements = SliceRequirements {
+            stream_id: session.stream_id.clone(),
+            require_id: uuid::Uuid::new_v4().to_string(),
+            supersedes,
+            slice_index: session.slice_index,
+            expires_at: expires_at.to_seconds(),
+            requirements: session.requirements.clone(),
+            quote_id: None,
+        };
+        if let Some(channel) = &session.channel
+            && !channel.is_open()
+        {
+            requirements.requirements.max_amount_required = channel.config.capacity;
+        }
+        session.pending = Some(PendingSlice {
+            requirements: requirements.clone(),
+            paying: false,
+        });
+        requirements
+    }
+
+    /// Checks that `requirements` only reprice the stream of `session`.
+    fn check_reprice(
+        session: &StreamSession,
+        requirements: &PaymentRequirements,
+    ) -> Result<(), StreamError> {
+        let current = &session.requirements;
+        if requirements.scheme != current.scheme
+            || requirements.network != current.network
+            || requirements.asset != current.asset
+            || requirements.pay_to != current.pay_to
+        {
+            return Err(StreamError::InvalidReprice(
+                "scheme, network, asset and payTo must not change".to_string(),
+            ));
+        }
+        if session.is_metered() && session.authorization.is_some() {
+            return Err(StreamError::AlreadyAuthorized(session.stream_id.clone()));
+        }
+        if let Some(channel) = &session.channel
+            && channel.config.capacity < requirements.max_amount_required
+        {
+            return Err(ChannelError::CapacityBelowPrice {
+                capacity: channel.config.capacity,
+                price: requirements.max_amount_required,
+            }
+            .into());
+        }
+        Ok(())
+    }
+
+    /// The pending slice of `session`, if `slice_index`, not superseded by requirements other than
+    /// `require_id`, and still payable at `now`.
+    fn payable_slice<'a>(
+        session: &'a mut StreamSession,
+        slice_index: u64,
+        require_id: Option<&str>,
+        now: UnixTimestampMs,
+    ) -> Result<&'a mut PendingSlice, StreamError> {
+        if session.finalizing {
+            return Err(StreamError::Finalizing(session.stream_id.clone()));
+        }
+        let pending = session.pending.as_mut();
+        if let Some(require_id) = require_id
+            && pending
+                .as_ref()
+                .is_none_or(|pending| pending.requirements.require_id != require_id)
+        {
+            return Err(StreamError::RequireSuperseded(require_id.to_string()));
+        }
+        let pending = pending.ok_or(StreamError::NoPendingSlice)?;
+        let expected = pending.requirements.slice_index;
+        if slice_index != expected {
+            return Err(StreamError::SliceMismatch {
+                expected,
+                actual: slice_index,
+            });
+        }
+        if pending.paying {
+            return Err(StreamError::PaymentInProgress(slice_index));
+        }
+        if UnixTimestampMs::from(pending.requirements.expires_at) <= now {
+            return Err(StreamError::RequireExpired(slice_index));
+        }
+        Ok(pending)
+    }
+
+    fn with_session<T>(
+        &self,
+        stream_id: &str,
+        f: impl FnOnce(&mut StreamSession, UnixTimestampMs) -> Result<T, StreamError>,
+    ) -> Result<T, StreamError> {
+        let now = UnixTimestampMs::now();
+        let mut sessions = self.sessions.lock().unwrap();
+        let session = sessions
+            .get_mut(stream_id)
+            .filter(|session| session.expires_at(self.idle_timeout) > now)
+            .ok_or_else(|| StreamError::UnknownStream(stream_id.to_string()))?;
+        session.last_activity = now;
+        f(session, now)
+    }
+}
@@ -0,0 +1,163 @@
+//! Strict verify-before-settle: refusing to settle payments not verified recently.
+//!
+//! A seller is expected to verify a payment before serving the resource, and to settle it after.
+//! A buggy seller settling payloads it never verified makes the facilitator broadcast
+//! transactions bound to revert (bad signature, spent nonce, insufficient balance), wasting gas.
+//! With a window set, [`StrictSettle`] remembers every payment successfully verified by the
+//! facilitator, and settlement of a payment not verified within the window is refused with
+//! [`FacilitatorLocalError::NotVerified`], reported to clients as the `unverified_payment` reason.
+//!
+//! A payment is identified by its payload and its requirements, `consumedAmount` aside: an `upto`
+//! payment verified for its maximum amount may be settled for the amount consumed. The payment is
+//! forgotten once settled. Verified payments are kept in memory, per facilitator instance: with
+//! several instances behind a load balancer, a payment must be verified and settled by the same
+//! instance.
+//!
+//! The window must cover the time between verification and settlement: the time to serve the
+//! resource, and with deferred settlement (see [`settlement_queue`](crate::settlement_queue)),
+//! the time payments stay queued. Payments of streams, verified when paid and settled when the
+//! stream is finalized or checkpointed, are settled regardless of the window.
+//!
+//! The window can be changed at runtime through the admin API (`/admin/strict-settle`); clones
+//! share the same window and verified payments.
+//!
+//! Environment (see [`StrictSettle::from_env`]):
+//! - `SETTLE_REQUIRE_VERIFIED_SECS` – Window within which a payment must have been verified to be
+//!   settled, up to 1 hour. Settlement is not restricted if unset.
+
+use alloy::primitives::B256;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+use crate::canonical_json;
+use crate::chain::FacilitatorLocalError;
+use crate::duration::{self, DurationError, DurationRange};
+use crate::lru_cache::LruCache;
+use crate::nonce_store::nonce_key;
+use crate::types::{PaymentRequirements, SettleRequest, VerifyRequest};
+
+const ENV_SETTLE_REQUIRE_VERIFIED_SECS: &str = "SETTLE_REQUIRE_VERIFIED_SECS";
+
+/// Windows accepted: up to 1 hour.
+pub const WINDOW_RANGE: DurationRange =
+    DurationRange::new(Duration::from_secs(1), Duration::from_secs(3600));
+/// Verified payments remembered, least recently used forgotten first.
+const CAPACITY: usize = 100_000;
+
+/// Error returned by [`StrictSettle::from_env`] for a malformed or out of range
+/// `SETTLE_REQUIRE_VERIFIED_SECS`.
+#[derive(Debug, thiserror::Error)]
+#[error(transparent)]
+pub struct InvalidStrictSettleWindow(#[from] DurationError);
+
+/// Verifications of the payments to settle, see the [module documentation](self). Settlement is
+/// not restricted by default.
+#[derive(Debug, Clone)]
+pub struct StrictSettle {
+    /// Window in milliseconds, `0` if settlement is not restricted.
+    window_ms: Arc<AtomicU64>,
+    verified: LruCache<B256, Instant>,
+}
+
+impl Default for StrictSettle {
+    fn default() -> Self {
+        Self {
+            window_ms: Arc::new(AtomicU64::new(0)),
+            verified: LruCache::new("strict_settle", CAPACITY).with_ttl(WINDOW_RANGE.max),
+        }
+    }
+}
+
+impl StrictSettle {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Reads the window from `SETTLE_REQUIRE_VERIFIED_SECS`, in seconds or with a unit (e.g.
+    /// `5m`). Settlement is not restricted if unset.
+    pub fn from_env() -> Result<Self, InvalidStrictSettleWindow> {
+        let window = duration::from_env(
+            ENV_SETTLE_REQUIRE_VERIFIED_SECS,
+            duration::SECOND,
+            WINDOW_RANGE,
+        )?;
+        let this = Self::new();
+        this.set_window(window)?;
+        Ok(this)
+    }
+
+    /// Refuses to settle payments not verified within `window`.
+    #[allow(dead_code)] // Public for consumption by downstream crates.
+    pub fn with_window(&self, window: Duration) -> Result<Self, DurationError> {
+        let window = WINDOW_RANGE.check(window)?;
+        Ok(Self {
+            window_ms: Arc::new(AtomicU64::new(window.as_millis() as u64)),
+            ..self.clone()
+        })
+    }
+
+    /// Window within which a payment must have been verified to be settled, `None` if
+    /// settlement is not restricted.
+    pub fn window(&self) -> Option<Duration> {
+        match self.window_ms.load(Ordering::Relaxed) {
+            0 => None,
+            ms => Some(Duration::from_millis(ms)),
+        }
+    }
+
+    /// Sets the window, or lifts the restriction with `None`, for every clone.
+    pub fn set_window(&self, window: Option<Duration>) -> Result<(), DurationError> {
+        let ms = match window {
+            Some(window) => WINDOW_RANGE.check(window)?.as_millis() as u64,
+            None => 0,
+        };
+        self.window_ms.store(ms, Ordering::Relaxed);
+        Ok(())
+    }
+
+    /// Records the successful verification of `request`. Payments are recorded even while
+    /// settlement is not restricted, so that restricting it does not refuse the payments
+    /// verified just before.
+    pub fn record(&self, request: &VerifyRequest) {
+        if let Some(key) = payment_key(request) {
+            self.verified.insert(key, Instant::now());
+        }
+    }
+
+    /// Checks that `request` was verified within the window, if set.
+    pub fn check(&self, request: &SettleRequest) -> Result<(), FacilitatorLocalError> {
+        let Some(window) = self.window() else {
+            return Ok(());
+        };
+        let verified_at = payment_key(request).and_then(|key| self.verified.get(&key));
+        match verified_at {
+            Some(verified_at) if verified_at.elapsed() <= window => Ok(()),
+            _ => {
+                let payer = nonce_key(request).and_then(|(_, payer)| payer);
+                tracing::warn!(
+                    monotonic_counter.x402_unverified_settlements = 1,
+                    payer = ?payer,
+                    "Refused to settle a payment not verified within {window:?}"
+                );
+                Err(FacilitatorLocalError::NotVerified(payer))
+            }
+        }
+    }
+
+    /// Forgets `request`, settled.
+    pub fn forget(&self, request: &SettleRequest) {
+        if let Some(key) = payment_key(request) {
+            self.verified.remove(&key);
+        }
+    }
+}
+
+/// Identity of the payment of `request`, its `consumedAmount` aside.
+fn payment_key(request: &VerifyRequest) -> Option<B256> {
+    let requirements = PaymentRequirements {
+        consumed_amount: None,
+        ..request.payment_requirements.clone()
+    };
+    canonical_json::keccak256(&(&request.payment_payload, &requirements)).ok()
+}
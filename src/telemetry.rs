@@ -1,8 +1,11 @@
-use opentelemetry::{KeyValue, Value, global, trace::TracerProvider as _};
+use opentelemetry::{
+    Context, KeyValue, Value, global,
+    trace::{Link, SamplingResult, SpanKind, TraceId, TracerProvider as _},
+};
 use opentelemetry_sdk::{
     Resource,
     metrics::{MeterProviderBuilder, PeriodicReader, SdkMeterProvider},
-    trace::{RandomIdGenerator, Sampler, SdkTracerProvider},
+    trace::{RandomIdGenerator, Sampler, SdkTracerProvider, ShouldSample},
 };
 use opentelemetry_semantic_conventions::{
     SCHEMA_URL,
@@ -10,8 +13,20 @@ use opentelemetry_semantic_conventions::{
 };
 use serde::{Deserialize, Serialize};
 use std::env;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
 use tracing_opentelemetry::{MetricsLayer, OpenTelemetryLayer};
-use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
+use tracing_subscriber::{
+    EnvFilter, Registry, filter::LevelFilter, layer::SubscriberExt, reload, util::SubscriberInitExt,
+};
+
+/// Initial trace sampling ratio, as per the OpenTelemetry SDK environment variables.
+const ENV_OTEL_TRACES_SAMPLER_ARG: &str = "OTEL_TRACES_SAMPLER_ARG";
+
+/// Libraries of the OTLP exporter network stack. Their spans and events are capped at `INFO`
+/// while OpenTelemetry is enabled, whatever the filter, so that exporting does not reenter
+/// the globally installed OpenTelemetryLayer.
+const EXPORTER_TARGETS: &[&str] = &["h2", "hyper", "hyper_util", "reqwest", "tonic", "tower"];
 
 /// Supported telemetry transport protocols for exporting OTLP data.
 ///
@@ -177,7 +192,11 @@ impl Telemetry {
     }
 
     /// Initializes the OpenTelemetry tracer provider.
-    fn init_tracer_provider(&self, telemetry_protocol: &TelemetryProtocol) -> SdkTracerProvider {
+    fn init_tracer_provider(
+        &self,
+        telemetry_protocol: &TelemetryProtocol,
+        sampler: RatioSampler,
+    ) -> SdkTracerProvider {
         let exporter = opentelemetry_otlp::SpanExporter::builder();
         // Choose transport protocol
         let exporter = match telemetry_protocol {
@@ -188,10 +207,8 @@ impl Telemetry {
 
         // Construct and return a tracer provider
         SdkTracerProvider::builder()
-            // Customize sampling strategy
-            .with_sampler(Sampler::ParentBased(Box::new(Sampler::TraceIdRatioBased(
-                1.0,
-            ))))
+            // Parent-based ratio sampling, tunable at runtime through TelemetryControl
+            .with_sampler(sampler)
             // If export trace to AWS X-Ray, you can use XrayIdGenerator
             .with_id_generator(RandomIdGenerator::default())
             .with_resource(self.resource())
@@ -246,25 +263,36 @@ impl Telemetry {
     ///
     /// Otherwise, it defaults to console logging via `tracing-subscriber`.
     ///
+    /// Events and spans are filtered with the `RUST_LOG` directives (default `info`), and traces
+    /// are sampled with the `OTEL_TRACES_SAMPLER_ARG` ratio (default `1.0`). Both can be changed
+    /// at runtime through [`TelemetryProviders::control`].
+    ///
     /// Returns a [`TelemetryProviders`] struct that performs graceful exporter shutdown on `Drop`.
     pub fn register(&self) -> TelemetryProviders {
         let telemetry_protocol = TelemetryProtocol::from_env();
+        let filter = EnvFilter::builder()
+            .with_default_directive(LevelFilter::INFO.into())
+            .from_env_lossy();
+        let filter = if telemetry_protocol.is_some() {
+            cap_exporter_targets(filter)
+        } else {
+            filter
+        };
+        let (filter, filter_handle) = reload::Layer::new(filter);
         match telemetry_protocol {
             Some(telemetry_protocol) => {
-                let tracer_provider = self.init_tracer_provider(&telemetry_protocol);
+                let sampler = RatioSampler::new(sampling_ratio_from_env());
+                let tracer_provider =
+                    self.init_tracer_provider(&telemetry_protocol, sampler.clone());
                 let meter_provider = self.init_meter_provider(&telemetry_protocol);
                 let tracer = tracer_provider.tracer("tracing-otel-subscriber");
 
                 // Register tracing subscriber with OpenTelemetry layers
                 tracing_subscriber::registry()
-                    // The global level filter prevents the exporter network stack
+                    // The global filter prevents the exporter network stack
                     // from reentering the globally installed OpenTelemetryLayer with
-                    // its own spans while exporting, as the libraries should not use
-                    // tracing levels below DEBUG. If the OpenTelemetry layer needs to
-                    // trace spans and events with higher verbosity levels, consider using
-                    // per-layer filtering to target the telemetry layer specifically,
-                    // e.g. by target matching.
-                    .with(tracing_subscriber::filter::LevelFilter::INFO)
+                    // its own spans while exporting: see `EXPORTER_TARGETS`.
+                    .with(filter)
                     .with(tracing_subscriber::fmt::layer())
                     .with(MetricsLayer::new(meter_provider.clone()))
                     .with(OpenTelemetryLayer::new(tracer))
@@ -277,11 +305,17 @@ impl Telemetry {
                 TelemetryProviders {
                     tracer_provider: Some(tracer_provider),
                     meter_provider: Some(meter_provider),
+                    control: TelemetryControl {
+                        filter: filter_handle,
+                        cap_exporter_targets: true,
+                        sampler: Some(sampler),
+                    },
                 }
             }
             None => {
                 // Fallback: just use local logging
                 tracing_subscriber::registry()
+                    .with(filter)
                     .with(tracing_subscriber::fmt::layer())
                     .init();
 
@@ -290,6 +324,11 @@ impl Telemetry {
                 TelemetryProviders {
                     tracer_provider: None,
                     meter_provider: None,
+                    control: TelemetryControl {
+                        filter: filter_handle,
+                        cap_exporter_targets: false,
+                        sampler: None,
+                    },
                 }
             }
         }
@@ -305,6 +344,14 @@ pub struct TelemetryProviders {
     pub tracer_provider: Option<SdkTracerProvider>,
     /// Metrics provider for OpenTelemetry metrics
     pub meter_provider: Option<SdkMeterProvider>,
+    control: TelemetryControl,
+}
+
+impl TelemetryProviders {
+    /// Handle to tune the log filter and trace sampling at runtime.
+    pub fn control(&self) -> TelemetryControl {
+        self.control.clone()
+    }
 }
 
 /// Drops the telemetry providers with graceful shutdown.
@@ -325,3 +372,139 @@ impl Drop for TelemetryProviders {
         }
     }
 }
+
+#[derive(Debug, thiserror::Error)]
+pub enum TelemetryControlError {
+    #[error("Invalid log filter: {0}")]
+    InvalidFilter(String),
+    #[error("Invalid sampling ratio {0}: must be between 0.0 and 1.0")]
+    InvalidSamplingRatio(f64),
+    #[error("OpenTelemetry tracing is not enabled")]
+    TracingDisabled,
+    #[error("Failed to reload the log filter: {0}")]
+    Reload(String),
+}
+
+/// Runtime control over the installed log filter and trace sampler.
+///
+/// Obtained from [`TelemetryProviders::control`]. Cheap to clone; all clones act on the
+/// globally installed subscriber.
+#[derive(Clone)]
+pub struct TelemetryControl {
+    filter: reload::Handle<EnvFilter, Registry>,
+    cap_exporter_targets: bool,
+    sampler: Option<RatioSampler>,
+}
+
+impl TelemetryControl {
+    /// Directives of the current log filter, in `RUST_LOG` syntax.
+    pub fn filter(&self) -> String {
+        self.filter
+            .with_current(|filter| filter.to_string())
+            .unwrap_or_default()
+    }
+
+    /// Replaces the log filter with `directives`, in `RUST_LOG` syntax,
+    /// e.g. `info,x402_rs::handlers::ws=debug`.
+    pub fn set_filter(&self, directives: &str) -> Result<(), TelemetryControlError> {
+        let filter = EnvFilter::builder()
+            .with_default_directive(LevelFilter::INFO.into())
+            .parse(directives)
+            .map_err(|e| TelemetryControlError::InvalidFilter(e.to_string()))?;
+        let filter = if self.cap_exporter_targets {
+            cap_exporter_targets(filter)
+        } else {
+            filter
+        };
+        self.filter
+            .reload(filter)
+            .map_err(|e| TelemetryControlError::Reload(e.to_string()))?;
+        tracing::info!(filter = %self.filter(), "Log filter changed");
+        Ok(())
+    }
+
+    /// Current trace sampling ratio, or `None` if OpenTelemetry tracing is not enabled.
+    pub fn sampling_ratio(&self) -> Option<f64> {
+        self.sampler.as_ref().map(RatioSampler::ratio)
+    }
+
+    /// Sets the ratio (`0.0`–`1.0`) of new root traces to sample. Child spans keep following
+    /// the sampling decision of their parent.
+    pub fn set_sampling_ratio(&self, ratio: f64) -> Result<(), TelemetryControlError> {
+        let sampler = self
+            .sampler
+            .as_ref()
+            .ok_or(TelemetryControlError::TracingDisabled)?;
+        if !(0.0..=1.0).contains(&ratio) {
+            return Err(TelemetryControlError::InvalidSamplingRatio(ratio));
+        }
+        sampler.set_ratio(ratio);
+        tracing::info!(ratio, "Trace sampling ratio changed");
+        Ok(())
+    }
+}
+
+/// Caps the [`EXPORTER_TARGETS`] at `INFO`.
+fn cap_exporter_targets(filter: EnvFilter) -> EnvFilter {
+    EXPORTER_TARGETS.iter().fold(filter, |filter, target| {
+        filter.add_directive(
+            format!("{target}=info")
+                .parse()
+                .expect("exporter target directives are valid"),
+        )
+    })
+}
+
+/// Reads the initial sampling ratio from `OTEL_TRACES_SAMPLER_ARG`, defaulting to `1.0`.
+fn sampling_ratio_from_env() -> f64 {
+    env::var(ENV_OTEL_TRACES_SAMPLER_ARG)
+        .ok()
+        .and_then(|s| s.parse::<f64>().ok())
+        .filter(|ratio| (0.0..=1.0).contains(ratio))
+        .unwrap_or(1.0)
+}
+
+/// Parent-based trace ID ratio sampler whose ratio can be changed after the
+/// tracer provider is built.
+#[derive(Debug, Clone)]
+struct RatioSampler {
+    /// Bits of the `f64` ratio.
+    ratio: Arc<AtomicU64>,
+}
+
+impl RatioSampler {
+    fn new(ratio: f64) -> Self {
+        Self {
+            ratio: Arc::new(AtomicU64::new(ratio.to_bits())),
+        }
+    }
+
+    fn ratio(&self) -> f64 {
+        f64::from_bits(self.ratio.load(Ordering::Relaxed))
+    }
+
+    fn set_ratio(&self, ratio: f64) {
+        self.ratio.store(ratio.to_bits(), Ordering::Relaxed);
+    }
+}
+
+impl ShouldSample for RatioSampler {
+    fn should_sample(
+        &self,
+        parent_context: Option<&Context>,
+        trace_id: TraceId,
+        name: &str,
+        span_kind: &SpanKind,
+        attributes: &[KeyValue],
+        links: &[Link],
+    ) -> SamplingResult {
+        Sampler::ParentBased(Box::new(Sampler::TraceIdRatioBased(self.ratio()))).should_sample(
+            parent_context,
+            trace_id,
+            name,
+            span_kind,
+            attributes,
+            links,
+        )
+    }
+}
@@ -2,7 +2,9 @@ use alloy::primitives::U256;
 use serde::{Deserialize, Deserializer, Serialize, Serializer};
 use std::fmt::{Display, Formatter};
 use std::ops::Add;
-use std::time::{SystemTime, SystemTimeError};
+use std::time::{Duration, SystemTime, SystemTimeError};
+
+use crate::duration::Seconds;
 
 /// A Unix timestamp represented as a `u64`, used in payment authorization windows.
 ///
@@ -54,8 +56,22 @@ impl Add<u64> for UnixTimestamp {
     }
 }
 
+impl Add<Seconds> for UnixTimestamp {
+    type Output = Self;
+
+    fn add(self, rhs: Seconds) -> Self::Output {
+        UnixTimestamp(self.0 + rhs.as_secs())
+    }
+}
+
 impl UnixTimestamp {
+    /// The current time, or the time of the request being replayed, see
+    /// [`replay`](crate::replay).
     pub fn try_now() -> Result<Self, SystemTimeError> {
+        #[cfg(feature = "replay")]
+        if let Some(now) = crate::replay::now() {
+            return Ok(now);
+        }
         let now = SystemTime::now()
             .duration_since(SystemTime::UNIX_EPOCH)?
             .as_secs();
@@ -66,3 +82,72 @@ impl UnixTimestamp {
         self.0
     }
 }
+
+/// Serializes a [`UnixTimestamp`] as an integer rather than a string, for protocol fields that
+/// carry integers, e.g. `#[serde(with = "crate::timestamp::number")]`.
+pub mod number {
+    use super::UnixTimestamp;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(
+        timestamp: &UnixTimestamp,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.serialize_u64(timestamp.0)
+    }
+
+    #[allow(dead_code)] // Public for consumption by downstream crates.
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<UnixTimestamp, D::Error> {
+        u64::deserialize(deserializer).map(UnixTimestamp)
+    }
+}
+
+/// A Unix timestamp in milliseconds, e.g. the `prepaidUntilMs` of a stream.
+///
+/// Serialized as an integer.
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, PartialOrd, Ord, Eq, Hash, Serialize, Deserialize,
+)]
+#[serde(transparent)]
+pub struct UnixTimestampMs(pub u64);
+
+impl UnixTimestampMs {
+    /// The current time, or the Unix epoch if the system clock is set before it.
+    pub fn now() -> Self {
+        let now = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default();
+        Self(now.as_millis() as u64)
+    }
+
+    pub fn millis_since_epoch(&self) -> u64 {
+        self.0
+    }
+
+    /// The timestamp in seconds, rounded down.
+    pub fn to_seconds(self) -> UnixTimestamp {
+        UnixTimestamp(self.0 / 1000)
+    }
+}
+
+impl From<UnixTimestamp> for UnixTimestampMs {
+    fn from(value: UnixTimestamp) -> Self {
+        UnixTimestampMs(value.0.saturating_mul(1000))
+    }
+}
+
+impl Add<Duration> for UnixTimestampMs {
+    type Output = Self;
+
+    fn add(self, rhs: Duration) -> Self::Output {
+        UnixTimestampMs(self.0.saturating_add(rhs.as_millis() as u64))
+    }
+}
+
+impl Display for UnixTimestampMs {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
@@ -0,0 +1,413 @@
+//! Registry of token deployments, by symbol and network.
+//!
+//! [`USDCDeployment`] knows USDC only. A [`TokenRegistry`] holds the deployments of any token,
+//! with their decimals and EIP-712 domain name and version, registered under a symbol. The
+//! [known](TokenRegistry::known) registry holds the stablecoins of [`KnownToken`]:
+//!
+//! | Token   | Networks                                                     |
+//! |---------|--------------------------------------------------------------|
+//! | `USDC`  | every network                                                |
+//! | `EURC`  | `base`, `base-sepolia`, `avalanche`, `solana`                |
+//! | `PYUSD` | `solana`, `solana-devnet`                                    |
+//! | `DAI`   | `base`, `polygon`                                            |
+//!
+//! DAI does not implement ERC-3009 `transferWithAuthorization`: it is paid with the `permit`
+//! scheme, on the networks where its deployment implements EIP-2612 `permit`.
+//!
+//! Custom tokens are registered with [`TokenRegistry::with_token`], or read from the environment
+//! by [`TokenRegistry::from_env`], one variable per deployment:
+//!
+//! ```text
+//! TOKEN_<SYMBOL>_<NETWORK>=<address>,<decimals>[,<EIP-712 name>,<EIP-712 version>]
+//! ```
+//!
+//! e.g. `TOKEN_MYT_BASE_SEPOLIA=0x036CbD53842c5426634e7929541eC2318f3dCF7e,6,MyToken,1`.
+//!
+//! ```rust
+//! use x402_rs::network::Network;
+//! use x402_rs::tokens::{KnownToken, TokenRegistry};
+//!
+//! let eurc = KnownToken::Eurc.by_network(Network::Base).unwrap();
+//! assert_eq!(eurc.decimals, 6);
+//!
+//! let registry = TokenRegistry::known();
+//! assert_eq!(registry.by_symbol("eurc", Network::Base), Some(eurc));
+//! assert_eq!(registry.symbol(&eurc.asset), Some("EURC"));
+//! ```
+
+use alloy::primitives::address;
+use once_cell::sync::Lazy;
+use solana_sdk::pubkey::Pubkey;
+use std::borrow::Borrow;
+use std::env;
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+use crate::network::{Network, NetworkFamily, USDCDeployment};
+use crate::types::{MixedAddress, TokenAsset, TokenDeployment, TokenDeploymentEip712};
+
+const ENV_TOKEN_PREFIX: &str = "TOKEN_";
+
+#[derive(Debug, thiserror::Error)]
+pub enum TokenRegistryError {
+    #[error("Invalid {0}: {1}, expected <address>,<decimals>[,<EIP-712 name>,<EIP-712 version>]")]
+    InvalidToken(String, String),
+    #[error("Unknown token {0}")]
+    UnknownToken(String),
+}
+
+/// Stablecoins of the [known](TokenRegistry::known) registry.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum KnownToken {
+    /// USD Coin, by Circle.
+    Usdc,
+    /// Euro Coin, by Circle.
+    Eurc,
+    /// PayPal USD, by Paxos.
+    Pyusd,
+    /// Dai, by Sky (formerly MakerDAO).
+    Dai,
+}
+
+impl KnownToken {
+    /// Return all known [`KnownToken`] variants.
+    pub fn variants() -> &'static [KnownToken] {
+        &[
+            KnownToken::Usdc,
+            KnownToken::Eurc,
+            KnownToken::Pyusd,
+            KnownToken::Dai,
+        ]
+    }
+
+    /// Symbol of the token, under which it is registered.
+    pub fn symbol(&self) -> &'static str {
+        match self {
+            KnownToken::Usdc => "USDC",
+            KnownToken::Eurc => "EURC",
+            KnownToken::Pyusd => "PYUSD",
+            KnownToken::Dai => "DAI",
+        }
+    }
+
+    /// Return the deployment of the token on `network`, if any.
+    #[allow(dead_code)] // Public for consumption by downstream crates.
+    pub fn by_network<N: Borrow<Network>>(&self, network: N) -> Option<&'static TokenDeployment> {
+        KNOWN_TOKENS.by_symbol(self.symbol(), *network.borrow())
+    }
+
+    /// Return the known deployment at the address and network of `asset`, if any.
+    pub fn by_asset(asset: &TokenAsset) -> Option<&'static TokenDeployment> {
+        KNOWN_TOKENS.by_asset(asset)
+    }
+}
+
+impl Display for KnownToken {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.symbol())
+    }
+}
+
+impl FromStr for KnownToken {
+    type Err = TokenRegistryError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        KnownToken::variants()
+            .iter()
+            .find(|token| token.symbol().eq_ignore_ascii_case(s))
+            .copied()
+            .ok_or_else(|| TokenRegistryError::UnknownToken(s.to_string()))
+    }
+}
+
+/// A deployment of a [`TokenRegistry`], with its symbol if registered under one.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RegisteredToken {
+    pub symbol: Option<String>,
+    pub deployment: TokenDeployment,
+}
+
+/// Token deployments, by symbol and network, see the [module documentation](self).
+#[derive(Debug, Clone, Default)]
+pub struct TokenRegistry {
+    tokens: Vec<RegisteredToken>,
+}
+
+static KNOWN_TOKENS: Lazy<TokenRegistry> = Lazy::new(|| {
+    let usdc = Network::variants()
+        .iter()
+        .fold(TokenRegistry::new(), |registry, network| {
+            registry.with_token(
+                KnownToken::Usdc.symbol(),
+                USDCDeployment::by_network(network),
+            )
+        });
+    [
+        (
+            KnownToken::Eurc,
+            evm(
+                Network::Base,
+                address!("0x60a3E35Cc302bFA44Cb288Bc5a4F316Fdb1adb42"),
+                6,
+                "EURC",
+                "2",
+            ),
+        ),
+        (
+            KnownToken::Eurc,
+            evm(
+                Network::BaseSepolia,
+                address!("0x808456652fdb597867f38412077A9182bf77359F"),
+                6,
+                "EURC",
+                "2",
+            ),
+        ),
+        (
+            KnownToken::Eurc,
+            evm(
+                Network::Avalanche,
+                address!("0xC891EB4cbdEFf6e073e859e987815Ed1505c2ACD"),
+                6,
+                "Euro Coin",
+                "2",
+            ),
+        ),
+        (
+            KnownToken::Eurc,
+            solana(
+                Network::Solana,
+                "HzwqbKZw8HxMN6bF2yFZNrht3c2iXXzpKcFu7uBEDKtr",
+                6,
+            ),
+        ),
+        (
+            KnownToken::Pyusd,
+            solana(
+                Network::Solana,
+                "2b1kV6DkPAnxd5ixfnxCpjxmKwqjjaYmCZfHsFu24GXo",
+                6,
+            ),
+        ),
+        (
+            KnownToken::Pyusd,
+            solana(
+                Network::SolanaDevnet,
+                "CXk2AMBfi3TwaEL2468s6zP8xq9NxTXjp9gjMgzeUynM",
+                6,
+            ),
+        ),
+        (
+            KnownToken::Dai,
+            evm(
+                Network::Base,
+                address!("0x50c5725949A6F0c72E6C4a641F24049A917DB0Cb"),
+                18,
+                "Dai Stablecoin",
+                "2",
+            ),
+        ),
+        (
+            KnownToken::Dai,
+            evm(
+                Network::Polygon,
+                address!("0x8f3Cf7ad23Cd3CaDbD9735AFf958023239c6A063"),
+                18,
+                "(PoS) Dai Stablecoin",
+                "1",
+            ),
+        ),
+    ]
+    .into_iter()
+    .fold(usdc, |registry, (token, deployment)| {
+        registry.with_token(token.symbol(), deployment)
+    })
+});
+
+/// Deployment of an ERC-20 token on an EVM network.
+fn evm(
+    network: Network,
+    address: alloy::primitives::Address,
+    decimals: u8,
+    name: &str,
+    version: &str,
+) -> TokenDeployment {
+    TokenDeployment {
+        asset: TokenAsset {
+            address: address.into(),
+            network,
+        },
+        decimals,
+        eip712: Some(TokenDeploymentEip712 {
+            name: name.into(),
+            version: version.into(),
+        }),
+    }
+}
+
+/// Deployment of an SPL token on a Solana network.
+fn solana(network: Network, mint: &str, decimals: u8) -> TokenDeployment {
+    TokenDeployment {
+        asset: TokenAsset {
+            address: MixedAddress::Solana(Pubkey::from_str(mint).unwrap()),
+            network,
+        },
+        decimals,
+        eip712: None,
+    }
+}
+
+impl TokenRegistry {
+    /// An empty registry.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registry of the token deployments known to `x402-rs`, see [`KnownToken`].
+    pub fn known() -> Self {
+        KNOWN_TOKENS.clone()
+    }
+
+    /// The [known](Self::known) registry, with the custom tokens of the `TOKEN_<SYMBOL>_<NETWORK>`
+    /// variables.
+    pub fn from_env() -> Result<Self, TokenRegistryError> {
+        let mut vars = env::vars()
+            .filter(|(name, _)| name.starts_with(ENV_TOKEN_PREFIX))
+            .collect::<Vec<_>>();
+        vars.sort();
+        let mut registry = Self::known();
+        for (name, value) in vars {
+            let Some((symbol, network)) = parse_var_name(&name) else {
+                continue;
+            };
+            let deployment = parse_deployment(network, &value)
+                .ok_or_else(|| TokenRegistryError::InvalidToken(name.clone(), value.clone()))?;
+            registry = registry.with_token(symbol, deployment);
+        }
+        Ok(registry)
+    }
+
+    /// Adds a deployment to the registry, replacing a previous one at the same address and
+    /// network, whose symbol is kept.
+    #[allow(dead_code)] // Public for consumption by downstream crates.
+    pub fn with<T: Into<TokenDeployment>>(&self, deployment: T) -> Self {
+        let deployment = deployment.into();
+        let symbol = self
+            .tokens
+            .iter()
+            .find(|token| token.deployment.asset == deployment.asset)
+            .and_then(|token| token.symbol.clone());
+        self.with_registered(RegisteredToken { symbol, deployment })
+    }
+
+    /// Adds a deployment to the registry under `symbol` (case-insensitive), replacing a previous
+    /// one at the same address and network, or of the same symbol on the same network.
+    pub fn with_token<S: Into<String>, T: Into<TokenDeployment>>(
+        &self,
+        symbol: S,
+        deployment: T,
+    ) -> Self {
+        let symbol = symbol.into().to_uppercase();
+        let deployment = deployment.into();
+        let mut this = self.clone();
+        this.tokens.retain(|token| {
+            token.deployment.asset.network != deployment.asset.network
+                || token.symbol.as_deref() != Some(symbol.as_str())
+        });
+        this.with_registered(RegisteredToken {
+            symbol: Some(symbol),
+            deployment,
+        })
+    }
+
+    fn with_registered(&self, registered: RegisteredToken) -> Self {
+        let mut this = self.clone();
+        this.tokens
+            .retain(|token| token.deployment.asset != registered.deployment.asset);
+        this.tokens.push(registered);
+        this
+    }
+
+    /// Returns the registered deployment for the token's address and network, if any.
+    #[allow(dead_code)] // Public for consumption by downstream crates.
+    pub fn get(&self, token: &TokenDeployment) -> Option<&TokenDeployment> {
+        self.by_asset(&token.asset)
+    }
+
+    /// Returns the registered deployment at the address and network of `asset`, if any.
+    pub fn by_asset(&self, asset: &TokenAsset) -> Option<&TokenDeployment> {
+        self.tokens
+            .iter()
+            .find(|token| &token.deployment.asset == asset)
+            .map(|token| &token.deployment)
+    }
+
+    /// Returns the deployment of the token `symbol` (case-insensitive) on `network`, if any.
+    #[allow(dead_code)] // Public for consumption by downstream crates.
+    pub fn by_symbol(&self, symbol: &str, network: Network) -> Option<&TokenDeployment> {
+        self.tokens
+            .iter()
+            .find(|token| {
+                token.deployment.asset.network == network
+                    && token
+                        .symbol
+                        .as_deref()
+                        .is_some_and(|registered| registered.eq_ignore_ascii_case(symbol))
+            })
+            .map(|token| &token.deployment)
+    }
+
+    /// Returns the symbol of the deployment at the address and network of `asset`, if any.
+    #[allow(dead_code)] // Public for consumption by downstream crates.
+    pub fn symbol(&self, asset: &TokenAsset) -> Option<&str> {
+        self.tokens
+            .iter()
+            .find(|token| &token.deployment.asset == asset)
+            .and_then(|token| token.symbol.as_deref())
+    }
+
+    /// Registered deployments, in order of registration.
+    #[allow(dead_code)] // Public for consumption by downstream crates.
+    pub fn tokens(&self) -> &[RegisteredToken] {
+        &self.tokens
+    }
+}
+
+/// Splits `TOKEN_<SYMBOL>_<NETWORK>` into its symbol and network.
+fn parse_var_name(name: &str) -> Option<(&str, Network)> {
+    let rest = name.strip_prefix(ENV_TOKEN_PREFIX)?;
+    Network::variants().iter().find_map(|network| {
+        let suffix = network.to_string().to_uppercase().replace('-', "_");
+        let symbol = rest.strip_suffix(&suffix)?.strip_suffix('_')?;
+        (!symbol.is_empty()).then_some((symbol, *network))
+    })
+}
+
+/// Parses `<address>,<decimals>[,<EIP-712 name>,<EIP-712 version>]` on `network`: a hex address
+/// on EVM networks, a base58 mint on Solana networks.
+fn parse_deployment(network: Network, value: &str) -> Option<TokenDeployment> {
+    let parts = value.split(',').map(str::trim).collect::<Vec<_>>();
+    let (address, decimals, eip712) = match parts.as_slice() {
+        [address, decimals] => (address, decimals, None),
+        [address, decimals, name, version] => (
+            address,
+            decimals,
+            Some(TokenDeploymentEip712 {
+                name: name.to_string(),
+                version: version.to_string(),
+            }),
+        ),
+        _ => return None,
+    };
+    let address = match NetworkFamily::from(network) {
+        NetworkFamily::Evm => {
+            MixedAddress::from(address.parse::<alloy::primitives::Address>().ok()?)
+        }
+        NetworkFamily::Solana => MixedAddress::Solana(Pubkey::from_str(address).ok()?),
+    };
+    Some(TokenDeployment {
+        asset: TokenAsset { address, network },
+        decimals: decimals.parse().ok()?,
+        eip712,
+    })
+}
@@ -26,9 +26,16 @@ use std::ops::{Add, Div, Mul, Rem, Sub};
 use std::str::FromStr;
 use url::Url;
 
+use crate::duration::Seconds;
+use crate::extensions::ExtensionDescriptor;
+use crate::gas_monitor::NetworkGas;
 use crate::network::Network;
+use crate::receipts::SettlementReceipt;
 use crate::timestamp::UnixTimestamp;
 
+#[allow(dead_code)] // Public for consumption by downstream crates.
+pub mod stream;
+
 /// Represents the protocol version. Currently only version 1 is supported.
 #[derive(Debug, Copy, Clone)]
 pub enum X402Version {
@@ -84,20 +91,69 @@ impl<'de> Deserialize<'de> for X402Version {
     }
 }
 
-/// Enumerates payment schemes. Only "exact" is supported in this implementation,
-/// meaning the amount to be transferred must match exactly.
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
-#[serde(rename_all = "lowercase")]
+/// Enumerates payment schemes. Three are implemented natively:
+/// - "exact", meaning the amount to be transferred must match exactly, authorized with
+///   ERC-3009 `transferWithAuthorization` on EVM networks,
+/// - "permit", for ERC-20 tokens without ERC-3009: the payer signs an EIP-2612 `permit`
+///   allowing the facilitator to move the amount, which the facilitator settles with `transferFrom`,
+/// - "upto", for metered payments: the payer permits up to `maxAmountRequired` as for "permit",
+///   and only the amount consumed, [`PaymentRequirements::consumed_amount`], is settled.
+///
+/// Any other scheme name is carried as [`Scheme::Custom`], and handled by a registered
+/// [`SchemeHandler`](crate::scheme::SchemeHandler), if any.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub enum Scheme {
     Exact,
+    /// EIP-2612 `permit` signed by the payer, settled by the facilitator with `transferFrom`.
+    Permit,
+    /// EIP-2612 `permit` of a maximum amount, of which the facilitator settles the amount consumed.
+    Upto,
+    /// A scheme implemented outside of this crate, identified by its name.
+    Custom(String),
+}
+
+impl Scheme {
+    /// Name of the scheme, as used on the wire.
+    pub fn as_str(&self) -> &str {
+        match self {
+            Scheme::Exact => "exact",
+            Scheme::Permit => "permit",
+            Scheme::Upto => "upto",
+            Scheme::Custom(name) => name,
+        }
+    }
+}
+
+impl From<&str> for Scheme {
+    fn from(name: &str) -> Self {
+        match name {
+            "exact" => Scheme::Exact,
+            "permit" => Scheme::Permit,
+            "upto" => Scheme::Upto,
+            name => Scheme::Custom(name.to_string()),
+        }
+    }
+}
+
+impl Serialize for Scheme {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+impl<'de> Deserialize<'de> for Scheme {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let name = String::deserialize(deserializer)?;
+        Ok(Scheme::from(name.as_str()))
+    }
 }
 
 impl Display for Scheme {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        let s = match self {
-            Scheme::Exact => "exact",
-        };
-        write!(f, "{s}")
+        write!(f, "{}", self.as_str())
     }
 }
 
@@ -275,6 +331,32 @@ pub struct ExactEvmPayload {
     pub authorization: ExactEvmPayloadAuthorization,
 }
 
+/// EIP-712 structured data of an EIP-2612 `permit`.
+/// Allows `spender`, the facilitator, to transfer up to `value` of the tokens of `owner`
+/// until `deadline`.
+#[derive(Debug, Copy, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PermitEvmPayloadAuthorization {
+    pub owner: EvmAddress,
+    pub spender: EvmAddress,
+    pub value: TokenAmount,
+    /// Permit nonce of `owner` on the token contract, as returned by `nonces(owner)`.
+    pub nonce: TokenAmount,
+    pub deadline: UnixTimestamp,
+}
+
+/// Full payload of a [`Scheme::Permit`] or [`Scheme::Upto`] payment on EVM networks:
+/// includes the signature and the EIP-712 struct.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PermitEvmPayload {
+    pub signature: EvmSignature,
+    pub permit: PermitEvmPayloadAuthorization,
+    /// Signature of the [`PermitIntent`] of the payment, binding the permit to the `payTo` and
+    /// `maxAmountRequired` of the requirements.
+    pub intent_signature: EvmSignature,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct ExactSolanaPayload {
@@ -285,7 +367,11 @@ pub struct ExactSolanaPayload {
 #[serde(untagged)]
 pub enum ExactPaymentPayload {
     Evm(ExactEvmPayload),
+    /// Payload of a [`Scheme::Permit`] or [`Scheme::Upto`] payment.
+    Permit(PermitEvmPayload),
     Solana(ExactSolanaPayload),
+    /// Payload of a [`Scheme::Custom`] scheme, left for its [`SchemeHandler`](crate::scheme::SchemeHandler) to interpret.
+    Custom(serde_json::Value),
 }
 
 /// Describes a signed request to transfer a specific amount of funds on-chain.
@@ -299,6 +385,39 @@ pub struct PaymentPayload {
     pub payload: ExactPaymentPayload,
 }
 
+impl PaymentPayload {
+    /// Identifier of the payment, known to the buyer before settlement.
+    ///
+    /// - EVM `exact`: the ERC-3009 authorization nonce, as 0x-prefixed hex.
+    /// - EVM `permit` and `upto`: the owner and its EIP-2612 nonce, as `<owner>:<nonce>`.
+    /// - Solana `exact`: the first signature present in the partially signed transaction
+    ///   (the payer's), base58-encoded.
+    ///
+    /// Returns `None` for custom schemes and undecodable Solana transactions.
+    pub fn id(&self) -> Option<String> {
+        match &self.payload {
+            ExactPaymentPayload::Evm(payload) => {
+                Some(hex::encode_prefixed(payload.authorization.nonce.0))
+            }
+            ExactPaymentPayload::Permit(payload) => {
+                Some(format!("{}:{}", payload.permit.owner, payload.permit.nonce))
+            }
+            ExactPaymentPayload::Solana(payload) => {
+                let bytes = b64.decode(&payload.transaction).ok()?;
+                let transaction =
+                    bincode::deserialize::<solana_sdk::transaction::VersionedTransaction>(&bytes)
+                        .ok()?;
+                transaction
+                    .signatures
+                    .iter()
+                    .find(|signature| **signature != solana_sdk::signature::Signature::default())
+                    .map(|signature| signature.to_string())
+            }
+            ExactPaymentPayload::Custom(_) => None,
+        }
+    }
+}
+
 /// Error returned when decoding a base64-encoded [`PaymentPayload`] fails.
 ///
 /// This error type is used by a payment-gated endpoint or a facilitator to signal that the client-supplied
@@ -762,7 +881,7 @@ impl Serialize for MixedAddress {
     }
 }
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub enum TransactionHash {
     /// A 32-byte EVM transaction hash, encoded as 0x-prefixed hex string.
     Evm([u8; 32]),
@@ -840,9 +959,19 @@ pub struct PaymentRequirements {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub output_schema: Option<serde_json::Value>,
     pub pay_to: MixedAddress,
-    pub max_timeout_seconds: u64,
+    /// Time given to settle the payment, e.g. the validity window of its authorization.
+    pub max_timeout_seconds: Seconds,
     pub asset: MixedAddress,
     pub extra: Option<serde_json::Value>,
+    /// Amount consumed, settled by [`Scheme::Upto`] payments in place of `maxAmountRequired`,
+    /// and at most that. Set by the seller when settling; ignored by other schemes.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub consumed_amount: Option<TokenAmount>,
+    /// Amount of the same [`Scheme::Upto`] authorization settled already, by the checkpoints of a
+    /// payment channel (see [`crate::channel`]). `consumedAmount` is settled on top of it, and
+    /// both together are at most `maxAmountRequired`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub settled_amount: Option<TokenAmount>,
 }
 
 impl PaymentRequirements {
@@ -860,8 +989,10 @@ impl PaymentRequirements {
     /// ```
     /// use x402_rs::types::{PaymentRequirements, TokenAsset};
     ///
-    /// let reqs: PaymentRequirements = /* from parsed response or constructed */;
-    /// let token: TokenAsset = reqs.token_asset();
+    /// fn token_of(reqs: &PaymentRequirements) -> TokenAsset {
+    ///     // `reqs` usually comes from a parsed 402 response
+    ///     reqs.token_asset()
+    /// }
     /// ```
     #[allow(dead_code)] // Public for consumption by downstream crates.
     pub fn token_asset(&self) -> TokenAsset {
@@ -880,6 +1011,15 @@ pub struct VerifyRequest {
     pub x402_version: X402Version,
     pub payment_payload: PaymentPayload,
     pub payment_requirements: PaymentRequirements,
+    /// Opaque seller data (e.g. order or user id), echoed in the [`SettleResponse`] and in
+    /// settlement notifications so payments can be correlated without parsing resource URLs.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub metadata: Option<serde_json::Value>,
+    /// Key of a settlement retried by the client: the settlement of a payment under a key already
+    /// settled is answered with the original [`SettleResponse`], see
+    /// [`settle_idempotency`](crate::settle_idempotency). Ignored by verification.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub idempotency_key: Option<String>,
 }
 
 impl Display for VerifyRequest {
@@ -921,11 +1061,59 @@ pub enum FacilitatorErrorReason {
     #[error("unexpected_settle_error")]
     #[serde(rename = "unexpected_settle_error")]
     UnexpectedSettleError,
+    /// Verification did not complete in time, e.g. within the per-item timeout of a batch.
+    #[error("timeout")]
+    #[serde(rename = "timeout")]
+    Timeout,
+    /// The payment exceeds a settlement cap of the facilitator, see [`crate::settlement_caps`].
+    #[error("amount_cap_exceeded")]
+    #[serde(rename = "amount_cap_exceeded")]
+    AmountCapExceeded,
+    /// The payment was not verified by the facilitator before settlement, see [`crate::strict_settle`].
+    #[error("unverified_payment")]
+    #[serde(rename = "unverified_payment")]
+    UnverifiedPayment,
+    /// The idempotency key of the settlement was used for another payment, see [`crate::settle_idempotency`].
+    #[error("idempotency_key_reused")]
+    #[serde(rename = "idempotency_key_reused")]
+    IdempotencyKeyReused,
+    /// Settlements on the network are paused by the facilitator operator, see [`crate::settlement_pause`].
+    #[error("settlement_paused")]
+    #[serde(rename = "settlement_paused")]
+    SettlementPaused,
+    /// The settlement accounts of the facilitator on the network are short of gas, see [`crate::gas_monitor`].
+    #[error("insufficient_gas")]
+    #[serde(rename = "insufficient_gas")]
+    InsufficientGas,
+    /// The authorization, or the permit nonce, was already used or canceled on-chain.
+    #[error("authorization_used")]
+    #[serde(rename = "authorization_used")]
+    AuthorizationUsed,
+    /// The token rejected the signature of the payment.
+    #[error("invalid_signature")]
+    #[serde(rename = "invalid_signature")]
+    InvalidSignature,
+    /// The token rejected the payment as expired.
+    #[error("authorization_expired")]
+    #[serde(rename = "authorization_expired")]
+    AuthorizationExpired,
+    /// The token rejected the payment as not valid yet.
+    #[error("authorization_not_yet_valid")]
+    #[serde(rename = "authorization_not_yet_valid")]
+    AuthorizationNotYetValid,
+    /// The allowance given to the facilitator by a `permit` does not cover the payment.
+    #[error("insufficient_allowance")]
+    #[serde(rename = "insufficient_allowance")]
+    InsufficientAllowance,
+    /// The token refused the transfer: the payer or the recipient is blacklisted, or the token is paused.
+    #[error("transfer_blocked")]
+    #[serde(rename = "transfer_blocked")]
+    TransferBlocked,
 }
 
 /// Returned from a facilitator after attempting to settle a payment on-chain.
 /// Indicates success/failure, transaction hash, and payer identity.
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct SettleResponse {
     pub success: bool,
@@ -935,6 +1123,44 @@ pub struct SettleResponse {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub transaction: Option<TransactionHash>,
     pub network: Network,
+    /// Token movement decoded from the settlement transaction receipt, when available.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub settlement: Option<SettlementDetails>,
+    /// Confirmations of the settlement transaction when responding, `1` being its own block.
+    /// Follow `GET /settlement/{transaction}` or `x402.subscribe` for finality.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub confirmations: Option<u64>,
+    /// [`VerifyRequest::metadata`] of the settled request.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub metadata: Option<serde_json::Value>,
+    /// Receipt of the settlement signed by the facilitator, for the payer to prove the payment to
+    /// third parties, see [`crate::receipts`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub receipt: Option<SettlementReceipt>,
+}
+
+/// Token movement decoded from the logs of a settlement transaction.
+///
+/// Reports what actually moved on-chain (as opposed to what was authorized),
+/// so sellers can reconcile payments without running a separate indexer.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SettlementDetails {
+    /// Sender of the tokens, as reported by the `Transfer` log.
+    pub from: MixedAddress,
+    /// Recipient of the tokens, as reported by the `Transfer` log.
+    pub to: MixedAddress,
+    /// Amount actually moved, in token base units.
+    pub amount: TokenAmount,
+    /// Index of the `Transfer` log within its block, if reported by the node.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub log_index: Option<u64>,
+    /// Index of the matching `AuthorizationUsed` log within its block, if emitted.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub authorization_log_index: Option<u64>,
+    /// Block the settlement transaction was included in.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub block_number: Option<u64>,
 }
 
 /// Error returned when encoding a [`SettleResponse`] into base64 fails.
@@ -970,7 +1196,12 @@ impl TryInto<Base64Bytes<'static>> for SettleResponse {
 #[derive(Debug, Clone)]
 pub enum VerifyResponse {
     /// The payload matches the requirements and passes all checks.
-    Valid { payer: MixedAddress },
+    ///
+    /// `warnings` lists non-fatal findings the seller may want to take into account.
+    Valid {
+        payer: MixedAddress,
+        warnings: Vec<VerifyWarning>,
+    },
     /// The payload was well-formed but failed verification due to the specified [`FacilitatorErrorReason`]
     Invalid {
         reason: FacilitatorErrorReason,
@@ -983,7 +1214,28 @@ impl VerifyResponse {
     ///
     /// Indicates that the provided payment payload has been validated against the payment requirements.
     pub fn valid(payer: MixedAddress) -> Self {
-        VerifyResponse::Valid { payer }
+        VerifyResponse::Valid {
+            payer,
+            warnings: Vec::new(),
+        }
+    }
+
+    /// Attaches non-fatal `warnings` to a successful verification response.
+    /// Has no effect on a failed one.
+    pub fn with_warnings(self, warnings: Vec<VerifyWarning>) -> Self {
+        match self {
+            VerifyResponse::Valid { payer, .. } => VerifyResponse::Valid { payer, warnings },
+            invalid => invalid,
+        }
+    }
+
+    /// Non-fatal warnings reported along a successful verification.
+    #[allow(dead_code)] // Public for consumption by downstream crates.
+    pub fn warnings(&self) -> &[VerifyWarning] {
+        match self {
+            VerifyResponse::Valid { warnings, .. } => warnings,
+            VerifyResponse::Invalid { .. } => &[],
+        }
     }
 
     /// Constructs a failed verification response with the given `payer` address and error `reason`.
@@ -1001,14 +1253,17 @@ impl Serialize for VerifyResponse {
         S: Serializer,
     {
         let mut s = match self {
-            VerifyResponse::Valid { .. } => serializer.serialize_struct("VerifyResponse", 2)?,
+            VerifyResponse::Valid { .. } => serializer.serialize_struct("VerifyResponse", 3)?,
             VerifyResponse::Invalid { .. } => serializer.serialize_struct("VerifyResponse", 3)?,
         };
 
         match self {
-            VerifyResponse::Valid { payer } => {
+            VerifyResponse::Valid { payer, warnings } => {
                 s.serialize_field("isValid", &true)?;
                 s.serialize_field("payer", payer)?;
+                if !warnings.is_empty() {
+                    s.serialize_field("warnings", warnings)?
+                }
             }
             VerifyResponse::Invalid { reason, payer } => {
                 s.serialize_field("isValid", &false)?;
@@ -1036,6 +1291,8 @@ impl<'de> Deserialize<'de> for VerifyResponse {
             payer: Option<MixedAddress>,
             #[serde(default)]
             invalid_reason: Option<FacilitatorErrorReason>,
+            #[serde(default)]
+            warnings: Vec<VerifyWarning>,
         }
 
         let raw = Raw::deserialize(deserializer)?;
@@ -1045,7 +1302,10 @@ impl<'de> Deserialize<'de> for VerifyResponse {
                 None => Err(serde::de::Error::custom(
                     "`payer` must be present when `isValid` is true",
                 )),
-                Some(payer) => Ok(VerifyResponse::Valid { payer }),
+                Some(payer) => Ok(VerifyResponse::Valid {
+                    payer,
+                    warnings: raw.warnings,
+                }),
             },
             (false, Some(reason)) => Ok(VerifyResponse::Invalid {
                 payer: raw.payer,
@@ -1061,6 +1321,39 @@ impl<'de> Deserialize<'de> for VerifyResponse {
     }
 }
 
+/// Non-fatal finding reported in a successful [`VerifyResponse`].
+///
+/// The payment is acceptable, but the seller may want to make a more nuanced decision,
+/// e.g. settle immediately an authorization that is about to expire.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct VerifyWarning {
+    pub code: VerifyWarningCode,
+    /// Human-readable details.
+    pub message: String,
+}
+
+impl VerifyWarning {
+    pub fn new<M: Into<String>>(code: VerifyWarningCode, message: M) -> Self {
+        Self {
+            code,
+            message: message.into(),
+        }
+    }
+}
+
+/// Machine-readable kind of a [`VerifyWarning`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum VerifyWarningCode {
+    /// The authorization expires soon, leaving little time to settle it.
+    AuthorizationExpiresSoon,
+    /// The authorized amount largely exceeds the required amount.
+    AmountExceedsRequirement,
+    /// The token is not on the facilitator's allowlist for settlement.
+    TokenNotAllowlisted,
+}
+
 /// A simple error structure returned on unexpected or fatal server errors.
 /// Used when no structured protocol-level response is appropriate.
 #[derive(Debug, Serialize, Deserialize)]
@@ -1069,6 +1362,67 @@ pub struct ErrorResponse {
     pub error: String,
 }
 
+/// Kind of a connection-level problem reported in an `x402.error` notification.
+///
+/// Unknown kinds, sent by newer servers, deserialize as [`ConnectionErrorKind::Other`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ConnectionErrorKind {
+    /// The credentials of the connection expired; requests will be rejected until the client
+    /// authenticates again or reconnects.
+    AuthExpired,
+    /// The client is close to, or over, its rate limit; requests may be rejected until
+    /// `retryAfterMs` elapses.
+    RateLimited,
+    /// The server is shutting down and will close the connection; clients should reconnect,
+    /// after `retryAfterMs` if set.
+    ShuttingDown,
+    /// Notifications of the connection were dropped because the client did not read them fast
+    /// enough; subscriptions may have missed events.
+    NotificationsDropped,
+    /// A kind this version does not know about.
+    #[serde(other)]
+    Other,
+}
+
+/// Connection-level problem, pushed by a WebSocket server as an `x402.error` notification
+/// (`{ method: "x402.error", params: ConnectionError }`).
+///
+/// Unlike an error envelope, which answers a single request, an `x402.error` notification concerns
+/// the connection as a whole, and has no `id`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ConnectionError {
+    pub kind: ConnectionErrorKind,
+    /// Human-readable description of the problem.
+    pub message: String,
+    /// Time after which the client may retry, in milliseconds, if known.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub retry_after_ms: Option<u64>,
+}
+
+impl ConnectionError {
+    /// Method of the notification carrying a [`ConnectionError`].
+    pub const METHOD: &'static str = "x402.error";
+
+    pub fn new(kind: ConnectionErrorKind, message: impl Into<String>) -> Self {
+        Self {
+            kind,
+            message: message.into(),
+            retry_after_ms: None,
+        }
+    }
+
+    /// Sets the time after which the client may retry.
+    #[allow(dead_code)] // Public for consumption by downstream crates.
+    pub fn with_retry_after(self, retry_after: std::time::Duration) -> Self {
+        Self {
+            retry_after_ms: Some(retry_after.as_millis() as u64),
+            ..self
+        }
+    }
+}
+
 /// Contains bytes of base64 encoded some other bytes.
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Base64Bytes<'a>(pub Cow<'a, [u8]>);
@@ -1268,11 +1622,12 @@ pub struct TokenDeploymentEip712 {
 /// # Example
 ///
 /// ```
-/// use x402_rs::types::{TokenAsset, EvmAddress};
+/// use x402_rs::address_evm;
+/// use x402_rs::types::TokenAsset;
 /// use x402_rs::network::Network;
 ///
 /// let asset = TokenAsset {
-///     address: "0x036CbD53842c5426634e7929541eC2318f3dCF7e".parse().unwrap(),
+///     address: address_evm!("0x036CbD53842c5426634e7929541eC2318f3dCF7e"),
 ///     network: Network::BaseSepolia,
 /// };
 ///
@@ -1305,21 +1660,22 @@ impl Display for TokenAsset {
 /// # Example
 ///
 /// ```
+/// use x402_rs::address_evm;
 /// use x402_rs::types::{TokenAsset, TokenDeployment, TokenDeploymentEip712};
 /// use x402_rs::network::Network;
 ///
 /// let asset = TokenAsset {
-///     address: "0x036CbD53842c5426634e7929541eC2318f3dCF7e".parse().unwrap(),
+///     address: address_evm!("0x036CbD53842c5426634e7929541eC2318f3dCF7e"),
 ///     network: Network::BaseSepolia,
 /// };
 ///
 /// let deployment = TokenDeployment {
 ///     asset,
 ///     decimals: 6,
-///     eip712: TokenDeploymentEip712 {
+///     eip712: Some(TokenDeploymentEip712 {
 ///         name: "MyToken".into(),
 ///         version: "1".into(),
-///     },
+///     }),
 /// };
 ///
 /// assert_eq!(deployment.asset.address.to_string(), "0x036CbD53842c5426634e7929541eC2318f3dCF7e");
@@ -1413,6 +1769,12 @@ pub struct SupportedPaymentKindExtra {
 #[allow(dead_code)] // Public for consumption by downstream crates.
 pub struct SupportedPaymentKindsResponse {
     pub kinds: Vec<SupportedPaymentKind>,
+    /// Protocol extensions served by the facilitator, see [`crate::extensions`].
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub extensions: Vec<ExtensionDescriptor>,
+    /// Gas of the settlement accounts per network, if monitored, see [`crate::gas_monitor`].
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub gas: Vec<NetworkGas>,
 }
 
 sol!(
@@ -1435,3 +1797,34 @@ sol!(
         bytes32 nonce;
     }
 );
+
+sol!(
+    /// Solidity-compatible struct definition for EIP-2612 `permit`.
+    ///
+    /// Reconstructs the typed data signed by the payer of a [`Scheme::Permit`] or [`Scheme::Upto`]
+    /// payment: it allows `spender` to transfer up to `value` of the tokens of `owner` until
+    /// `deadline`, and is identified by the `nonce` of `owner` on the token contract.
+    #[derive(Serialize, Deserialize)]
+    struct Permit {
+        address owner;
+        address spender;
+        uint256 value;
+        uint256 nonce;
+        uint256 deadline;
+    }
+);
+
+sol!(
+    /// Solidity-compatible struct definition of the intent signed along an EIP-2612 `permit`.
+    ///
+    /// A permit allows the facilitator to move the tokens of `owner`, but not to whom: the payer
+    /// also signs the `payTo` and `maxAmountRequired` of the requirements as `value`, under the
+    /// EIP-712 domain of the token, for the permit of the same `nonce`.
+    #[derive(Serialize, Deserialize)]
+    struct PermitIntent {
+        address owner;
+        address payTo;
+        uint256 value;
+        uint256 nonce;
+    }
+);
@@ -0,0 +1,421 @@
+//! Messages of pay-per-slice streams between Buyers and Sellers, see `x402-ws-stream.md`.
+//!
+//! Every message is the `params` of a JSON-RPC-like envelope `{ id, version?, method, params }`,
+//! modelled by [`StreamEnvelope`]. The Seller answers `stream.init` and `stream.pay` with an envelope
+//! `{ id, result }` whose `result` is a [`StreamMessage`] (`stream.accept`).
+//!
+//! Envelopes are versioned after the `stream` extension (see
+//! [`ExtensionDescriptor::stream`](crate::extensions::ExtensionDescriptor::stream)): an envelope
+//! without `version` is of version 1, the only version defined so far.
+//!
+//! ```
+//! use x402_rs::types::stream::{StreamClose, StreamEnvelope, StreamMessage};
+//!
+//! let envelope: StreamEnvelope = serde_json::from_str(
+//!     r#"{ "id": "1", "method": "stream.end", "params": { "streamId": "s-1" } }"#,
+//! )
+//! .unwrap();
+//! assert_eq!(envelope.version(), 1);
+//! assert!(matches!(
+//!     envelope.message(),
+//!     StreamMessage::Close(StreamClose { stream_id, .. }) if stream_id == "s-1"
+//! ));
+//! ```
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use url::Url;
+
+use crate::duration::Seconds;
+use crate::network::Network;
+use crate::timestamp::{UnixTimestamp, UnixTimestampMs};
+use crate::types::{
+    MixedAddress, PaymentPayload, PaymentRequirements, SettleResponse, TokenAmount, VerifyResponse,
+};
+
+/// Version of the stream messages, the version of the `stream` extension.
+pub const STREAM_VERSION: u32 = 1;
+
+/// An asset the Buyer accepts to pay a stream with, in the `accepts` of `stream.init`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StreamOffer {
+    pub network: Network,
+    pub asset: MixedAddress,
+    /// Highest price per unit the Buyer pays, as advertised by the Seller, e.g. `0.05`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_price_per_unit: Option<String>,
+}
+
+/// Params of `stream.init` (Buyer→Seller), opening a stream.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StreamInit {
+    pub resource: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub network: Option<Network>,
+    /// Candidate assets and prices.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub accepts: Vec<StreamOffer>,
+    /// Facilitator the Buyer would like the Seller to use.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub facilitator_ws: Option<Url>,
+    /// End-to-end encryption offered by the Buyer, e.g. `{ alg, publicKey }`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub encryption: Option<serde_json::Value>,
+}
+
+impl StreamInit {
+    pub fn new(resource: impl Into<String>) -> Self {
+        Self {
+            resource: resource.into(),
+            network: None,
+            accepts: Vec::new(),
+            facilitator_ws: None,
+            encryption: None,
+        }
+    }
+}
+
+/// Params of `stream.accept` (Seller→Buyer), the result of `stream.init` or `stream.pay`.
+///
+/// Accepting `stream.init` sets the terms of the stream (`pricePerUnit`, `unitSeconds`, `payTo`,
+/// `asset`, `network`, `encryption`); accepting `stream.pay` or `stream.voucher` sets the outcome
+/// of the payment (`sliceIndex`, `verify`, `settle`, `prepaidUntilMs`, `cumulativeAmount`).
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StreamAccept {
+    pub stream_id: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub price_per_unit: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub unit_seconds: Option<Seconds>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pay_to: Option<MixedAddress>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub asset: Option<MixedAddress>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub network: Option<Network>,
+    /// End-to-end encryption of the Seller, answering the offer of the Buyer.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub encryption: Option<serde_json::Value>,
+    /// Index of the slice paid.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub slice_index: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub verify: Option<VerifyResponse>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub settle: Option<SettleResponse>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub prepaid_until_ms: Option<UnixTimestampMs>,
+    /// Amount authorized so far by the vouchers of a payment channel.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cumulative_amount: Option<TokenAmount>,
+}
+
+impl StreamAccept {
+    /// Accepts the stream `stream_id`, with no term nor payment set yet.
+    pub fn new(stream_id: impl Into<String>) -> Self {
+        Self {
+            stream_id: stream_id.into(),
+            ..Self::default()
+        }
+    }
+}
+
+/// Params of `stream.require` (Seller→Buyer): the requirements to pay the next slice.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StreamRequire {
+    pub stream_id: String,
+    /// Id of these requirements, echoed by the payment.
+    pub require_id: String,
+    /// Id of the requirements these supersede, if issued by cancelling them.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub supersedes: Option<String>,
+    pub slice_index: u64,
+    /// Deadline to pay the slice, in seconds since the Unix epoch.
+    #[serde(with = "crate::timestamp::number")]
+    pub expires_at: UnixTimestamp,
+    pub requirements: PaymentRequirements,
+    /// Quote the requirements are locked under until `expiresAt`, for oracle-priced streams.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub quote_id: Option<String>,
+}
+
+/// Params of `stream.require.cancel` (Seller→Buyer): the requirements no longer payable.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StreamRequireCancel {
+    pub stream_id: String,
+    pub require_id: String,
+}
+
+/// Params of `stream.pay` (Buyer→Seller): the payment of the slice required.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StreamPay {
+    pub stream_id: String,
+    pub slice_index: u64,
+    /// Id of the `stream.require` paid.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub require_id: Option<String>,
+    pub payment_payload: PaymentPayload,
+    /// Verifies the payment without settling it.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub verify_only: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub quote_id: Option<String>,
+}
+
+impl StreamPay {
+    /// Pays the slice required by `require`.
+    pub fn new(require: &StreamRequire, payment_payload: PaymentPayload) -> Self {
+        Self {
+            stream_id: require.stream_id.clone(),
+            slice_index: require.slice_index,
+            require_id: Some(require.require_id.clone()),
+            payment_payload,
+            verify_only: false,
+            quote_id: require.quote_id.clone(),
+        }
+    }
+}
+
+/// Params of `stream.data` (Seller→Buyer): a chunk of the content of a paid slice.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StreamData {
+    pub stream_id: String,
+    /// Paid slice the chunk belongs to.
+    pub slice_index: u64,
+    /// Sequence number of the chunk, starting at 0.
+    pub seq: u64,
+    /// Hash of the content of the chunk.
+    pub hash: String,
+    /// Base64 content, for streams in the clear.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub data: Option<String>,
+    /// Base64 sealed content, for end-to-end encrypted streams.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ciphertext: Option<String>,
+}
+
+/// Params of `stream.summary` (Seller→Buyer): the commitment to the content delivered so far.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StreamSummary {
+    pub stream_id: String,
+    pub slice_index: u64,
+    /// Number of chunks delivered so far.
+    pub chunks: u64,
+    /// Merkle root over the hashes of the chunks delivered, `None` before the first chunk.
+    pub merkle_root: Option<String>,
+}
+
+/// Params of `stream.end` (either party): the stream is closed.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StreamClose {
+    pub stream_id: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+}
+
+/// Params of `stream.closing` (either party): the stream is closing cooperatively.
+///
+/// The Seller answers the `stream.closing` of the Buyer with its own, then sends `stream.closed`
+/// with the final bill, once the remainder, if any, is paid with a final `stream.pay`.
+/// Requirements issued before the `stream.closing` of the Seller, other than `require`, are void.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StreamClosing {
+    pub stream_id: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+    /// Requirements of the unpaid remainder, e.g. content held while the prepaid window had
+    /// lapsed, set by the Seller only.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub require: Option<StreamRequire>,
+}
+
+impl StreamClosing {
+    /// Closes the stream `stream_id` with nothing left to pay.
+    pub fn new(stream_id: impl Into<String>, reason: Option<String>) -> Self {
+        Self {
+            stream_id: stream_id.into(),
+            reason,
+            require: None,
+        }
+    }
+}
+
+/// Params of `stream.closed` (Seller→Buyer): the final bill of a stream closed cooperatively.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StreamClosed {
+    pub stream_id: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+    /// Number of slices paid, the final `stream.pay` included.
+    pub slices: u64,
+    /// Total paid, in token base units of the asset of the slices.
+    pub amount: TokenAmount,
+    /// Number of `stream.data` chunks delivered.
+    pub chunks: u64,
+}
+
+/// A stream message: its method and params.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "method", content = "params")]
+pub enum StreamMessage {
+    #[serde(rename = "stream.init")]
+    Init(StreamInit),
+    #[serde(rename = "stream.accept")]
+    Accept(StreamAccept),
+    #[serde(rename = "stream.require")]
+    Require(StreamRequire),
+    #[serde(rename = "stream.require.cancel")]
+    RequireCancel(StreamRequireCancel),
+    #[serde(rename = "stream.pay")]
+    Pay(StreamPay),
+    #[serde(rename = "stream.data")]
+    Data(StreamData),
+    #[serde(rename = "stream.summary")]
+    Summary(StreamSummary),
+    #[serde(rename = "stream.end")]
+    Close(StreamClose),
+    #[serde(rename = "stream.closing")]
+    Closing(StreamClosing),
+    #[serde(rename = "stream.closed")]
+    Closed(StreamClosed),
+}
+
+impl StreamMessage {
+    /// Methods of the stream messages; other methods, e.g. `stream.keepalive`, are not typed.
+    pub const METHODS: [&str; 10] = [
+        "stream.init",
+        "stream.accept",
+        "stream.require",
+        "stream.require.cancel",
+        "stream.pay",
+        "stream.data",
+        "stream.summary",
+        "stream.end",
+        "stream.closing",
+        "stream.closed",
+    ];
+
+    /// Method of the message, e.g. `stream.init`.
+    pub fn method(&self) -> &'static str {
+        match self {
+            StreamMessage::Init(_) => "stream.init",
+            StreamMessage::Accept(_) => "stream.accept",
+            StreamMessage::Require(_) => "stream.require",
+            StreamMessage::RequireCancel(_) => "stream.require.cancel",
+            StreamMessage::Pay(_) => "stream.pay",
+            StreamMessage::Data(_) => "stream.data",
+            StreamMessage::Summary(_) => "stream.summary",
+            StreamMessage::Close(_) => "stream.end",
+            StreamMessage::Closing(_) => "stream.closing",
+            StreamMessage::Closed(_) => "stream.closed",
+        }
+    }
+}
+
+/// Envelope of a stream message, by version of the stream messages.
+#[derive(Debug, Clone)]
+pub enum StreamEnvelope {
+    V1 {
+        /// Id of the envelope, echoed by the reply.
+        id: serde_json::Value,
+        message: StreamMessage,
+    },
+}
+
+impl StreamEnvelope {
+    /// Envelope of `message` in the current version.
+    pub fn new(id: impl Into<serde_json::Value>, message: StreamMessage) -> Self {
+        StreamEnvelope::V1 {
+            id: id.into(),
+            message,
+        }
+    }
+
+    pub fn version(&self) -> u32 {
+        match self {
+            StreamEnvelope::V1 { .. } => STREAM_VERSION,
+        }
+    }
+
+    pub fn id(&self) -> &serde_json::Value {
+        match self {
+            StreamEnvelope::V1 { id, .. } => id,
+        }
+    }
+
+    pub fn message(&self) -> &StreamMessage {
+        match self {
+            StreamEnvelope::V1 { message, .. } => message,
+        }
+    }
+
+    pub fn into_message(self) -> StreamMessage {
+        match self {
+            StreamEnvelope::V1 { message, .. } => message,
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct EnvelopeRef<'a> {
+    id: &'a serde_json::Value,
+    version: u32,
+    #[serde(flatten)]
+    message: &'a StreamMessage,
+}
+
+#[derive(Deserialize)]
+struct EnvelopeRaw {
+    #[serde(default)]
+    id: serde_json::Value,
+    #[serde(default)]
+    version: Option<u32>,
+    method: String,
+    #[serde(default)]
+    params: serde_json::Value,
+}
+
+impl Serialize for StreamEnvelope {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            StreamEnvelope::V1 { id, message } => EnvelopeRef {
+                id,
+                version: STREAM_VERSION,
+                message,
+            }
+            .serialize(serializer),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for StreamEnvelope {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let raw = EnvelopeRaw::deserialize(deserializer)?;
+        match raw.version.unwrap_or(STREAM_VERSION) {
+            STREAM_VERSION => {
+                let message = serde_json::from_value(serde_json::json!({
+                    "method": raw.method,
+                    "params": raw.params,
+                }))
+                .map_err(serde::de::Error::custom)?;
+                Ok(StreamEnvelope::V1 {
+                    id: raw.id,
+                    message,
+                })
+            }
+            version => Err(serde::de::Error::custom(format!(
+                "Unsupported stream message version: {version}"
+            ))),
+        }
+    }
+}
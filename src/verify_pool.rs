@@ -0,0 +1,57 @@
+//! Dedicated thread pool for the CPU-bound work of verification.
+//!
+//! Signature work (EIP-712 hashing, ECDSA recovery, decoding and signing Solana transactions)
+//! is cheap per payment, but a burst of verifications running it on the Tokio workers delays
+//! everything else scheduled there: WebSocket keepalives, settlement I/O, RPC responses.
+//! [`spawn`] runs it on a separate [rayon](https://docs.rs/rayon) pool instead, sized
+//! independently of the Tokio runtime, and awaits the result.
+//!
+//! A panic on the pool is resumed in the awaiting task, so it is contained like any other panic
+//! of the request.
+//!
+//! Environment:
+//! - `VERIFY_POOL_THREADS` – Threads of the pool (default: the available parallelism)
+
+use once_cell::sync::Lazy;
+use std::env;
+use std::panic::{AssertUnwindSafe, catch_unwind, resume_unwind};
+use std::thread;
+use tokio::sync::oneshot;
+
+const ENV_VERIFY_POOL_THREADS: &str = "VERIFY_POOL_THREADS";
+
+static POOL: Lazy<rayon::ThreadPool> = Lazy::new(|| {
+    let threads = env::var(ENV_VERIFY_POOL_THREADS)
+        .ok()
+        .and_then(|s| s.parse().ok())
+        .filter(|threads| *threads > 0)
+        .unwrap_or_else(|| thread::available_parallelism().map_or(1, |n| n.get()));
+    rayon::ThreadPoolBuilder::new()
+        .num_threads(threads)
+        .thread_name(|index| format!("x402-verify-{index}"))
+        .build()
+        .expect("Failed to start the verification thread pool")
+});
+
+/// Threads of the pool.
+pub fn threads() -> usize {
+    POOL.current_num_threads()
+}
+
+/// Runs `work` on the verification pool, and waits for its result without blocking the runtime.
+pub async fn spawn<T, W>(work: W) -> T
+where
+    T: Send + 'static,
+    W: FnOnce() -> T + Send + 'static,
+{
+    let (sender, receiver) = oneshot::channel();
+    POOL.spawn(move || {
+        let _ = sender.send(catch_unwind(AssertUnwindSafe(work)));
+    });
+    match receiver.await {
+        Ok(Ok(result)) => result,
+        Ok(Err(panic)) => resume_unwind(panic),
+        // The pool always runs its tasks, and the task always sends
+        Err(_) => unreachable!("Verification pool task dropped"),
+    }
+}
@@ -0,0 +1,499 @@
+//! Signed webhook notifications of payment and stream events (`webhooks` feature).
+//!
+//! Accounting systems otherwise have to poll the facilitator, or parse its logs, to learn about
+//! payments. [`Webhooks`] `POST`s a [`WebhookEvent`] to every configured URL instead, on:
+//! - `payment.verified` – a payment was verified valid,
+//! - `payment.settled` – a payment was settled on-chain,
+//! - `payment.failed` – the settlement of a payment failed,
+//! - `stream.closed` – a stream session was finalized, or expired.
+//!
+//! Every request carries the id and type of its event in the `x402-event-id` and
+//! `x402-event-type` headers, and a signature of its body in `x402-signature`:
+//! `t=<unix seconds>,v1=<hex HMAC-SHA256 of "<t>.<body>" keyed with the secret>`, see [`sign`].
+//! Receivers should check it with [`verify_signature`], reject stale timestamps, and ignore the
+//! ids of events already processed: an event is delivered at least once.
+//!
+//! A delivery answered with anything but a `2xx` status is retried with exponential backoff,
+//! from 1 second up to 5 minutes between attempts. An event still not delivered after the last
+//! attempt is written to the dead-letter log, one JSON line per event and URL, to be replayed by
+//! the operator; without a log, it is logged as an error.
+//!
+//! Environment (see [`Webhooks::from_env`]):
+//! - `WEBHOOK_URLS` – Comma-separated URLs notified of events; webhooks are disabled if unset
+//! - `WEBHOOK_SECRET` – Key of the signatures, required with `WEBHOOK_URLS`
+//! - `WEBHOOK_EVENTS` – Comma-separated types of the events sent (default: all)
+//! - `WEBHOOK_MAX_ATTEMPTS` – Delivery attempts per event and URL, from 1 to 20 (default `10`)
+//! - `WEBHOOK_DEAD_LETTER_PATH` – File events not delivered are appended to
+//!
+//! ```rust
+//! use x402_rs::webhooks::{sign, verify_signature};
+//!
+//! let body = br#"{"id":"...","type":"payment.settled"}"#;
+//! let signature = sign(b"secret", 1_700_000_000, body);
+//! assert!(verify_signature(b"secret", &signature, body));
+//! assert!(!verify_signature(b"other secret", &signature, body));
+//! ```
+
+use alloy::hex;
+use hmac::{Hmac, Mac};
+use serde::Serialize;
+use sha2::Sha256;
+use std::collections::HashSet;
+use std::env;
+use std::fmt::{Display, Formatter};
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+use tokio::io::AsyncWriteExt;
+use tokio::sync::broadcast::error::RecvError;
+use url::Url;
+
+use crate::network::Network;
+use crate::stream::{ClosedStream, StreamSessionManager};
+use crate::timestamp::UnixTimestamp;
+use crate::types::{
+    FacilitatorErrorReason, MixedAddress, SettleRequest, SettleResponse, TokenAmount,
+    TransactionHash, VerifyRequest,
+};
+
+const ENV_WEBHOOK_URLS: &str = "WEBHOOK_URLS";
+const ENV_WEBHOOK_SECRET: &str = "WEBHOOK_SECRET";
+const ENV_WEBHOOK_EVENTS: &str = "WEBHOOK_EVENTS";
+const ENV_WEBHOOK_MAX_ATTEMPTS: &str = "WEBHOOK_MAX_ATTEMPTS";
+const ENV_WEBHOOK_DEAD_LETTER_PATH: &str = "WEBHOOK_DEAD_LETTER_PATH";
+
+const SIGNATURE_HEADER: &str = "x402-signature";
+const EVENT_ID_HEADER: &str = "x402-event-id";
+const EVENT_TYPE_HEADER: &str = "x402-event-type";
+
+/// Delivery attempts per event and URL, by default.
+const DEFAULT_MAX_ATTEMPTS: u32 = 10;
+/// Delivery attempts accepted from `WEBHOOK_MAX_ATTEMPTS`.
+const MAX_ATTEMPTS_RANGE: std::ops::RangeInclusive<u32> = 1..=20;
+/// Wait before the first retry, doubled on every retry.
+const INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+/// Longest wait between two attempts.
+const MAX_BACKOFF: Duration = Duration::from_secs(300);
+/// Time a receiver has to answer a delivery.
+const DELIVERY_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Debug, thiserror::Error)]
+pub enum WebhooksConfigError {
+    #[error("Invalid URL in {ENV_WEBHOOK_URLS}: {0}")]
+    InvalidUrl(String),
+    #[error("{ENV_WEBHOOK_SECRET} is required with {ENV_WEBHOOK_URLS}")]
+    SecretRequired,
+    #[error("Unknown event type in {ENV_WEBHOOK_EVENTS}: {0}")]
+    UnknownEvent(String),
+    #[error("Invalid {ENV_WEBHOOK_MAX_ATTEMPTS}: {0}, expected a number from 1 to 20")]
+    InvalidMaxAttempts(String),
+}
+
+/// Type of a [`WebhookEvent`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
+pub enum WebhookEventType {
+    #[serde(rename = "payment.verified")]
+    PaymentVerified,
+    #[serde(rename = "payment.settled")]
+    PaymentSettled,
+    #[serde(rename = "payment.failed")]
+    PaymentFailed,
+    #[serde(rename = "stream.closed")]
+    StreamClosed,
+}
+
+impl WebhookEventType {
+    pub const ALL: [WebhookEventType; 4] = [
+        WebhookEventType::PaymentVerified,
+        WebhookEventType::PaymentSettled,
+        WebhookEventType::PaymentFailed,
+        WebhookEventType::StreamClosed,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            WebhookEventType::PaymentVerified => "payment.verified",
+            WebhookEventType::PaymentSettled => "payment.settled",
+            WebhookEventType::PaymentFailed => "payment.failed",
+            WebhookEventType::StreamClosed => "stream.closed",
+        }
+    }
+}
+
+impl Display for WebhookEventType {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for WebhookEventType {
+    type Err = WebhooksConfigError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        WebhookEventType::ALL
+            .into_iter()
+            .find(|event_type| event_type.as_str() == s.trim())
+            .ok_or_else(|| WebhooksConfigError::UnknownEvent(s.to_string()))
+    }
+}
+
+/// Body of a webhook request.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WebhookEvent {
+    /// Unique id of the event, the same on every delivery attempt.
+    pub id: String,
+    #[serde(rename = "type")]
+    pub event_type: WebhookEventType,
+    /// Time of the event, in seconds since the Unix epoch.
+    #[serde(with = "crate::timestamp::number")]
+    pub created_at: UnixTimestamp,
+    /// [`PaymentEvent`] of `payment.*` events, [`ClosedStream`] of `stream.closed`.
+    pub data: serde_json::Value,
+}
+
+/// Data of the `payment.*` events.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PaymentEvent {
+    /// [`PaymentPayload::id`](crate::types::PaymentPayload::id) of the payment, if any.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub payment_id: Option<String>,
+    pub network: Network,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub payer: Option<MixedAddress>,
+    pub pay_to: MixedAddress,
+    pub asset: MixedAddress,
+    /// `consumedAmount` of the payment requirements if set, `maxAmountRequired` otherwise.
+    pub amount: TokenAmount,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub transaction: Option<TransactionHash>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error_reason: Option<FacilitatorErrorReason>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// [`VerifyRequest::metadata`] of the request.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub metadata: Option<serde_json::Value>,
+}
+
+impl PaymentEvent {
+    fn new(request: &VerifyRequest, payer: Option<MixedAddress>) -> Self {
+        let requirements = &request.payment_requirements;
+        Self {
+            payment_id: request.payment_payload.id(),
+            network: request.payment_payload.network,
+            payer,
+            pay_to: requirements.pay_to.clone(),
+            asset: requirements.asset.clone(),
+            amount: requirements
+                .consumed_amount
+                .unwrap_or(requirements.max_amount_required),
+            transaction: None,
+            error_reason: None,
+            error: None,
+            metadata: request.metadata.clone(),
+        }
+    }
+}
+
+/// Signature of `body` sent at `timestamp`, as in the `x402-signature` header.
+pub fn sign(secret: &[u8], timestamp: u64, body: &[u8]) -> String {
+    let digest = hex::encode(mac(secret, timestamp, body).finalize().into_bytes());
+    format!("t={timestamp},v1={digest}")
+}
+
+/// Whether `signature`, the value of an `x402-signature` header, signs `body` with `secret`.
+/// Does not check the age of its timestamp.
+#[allow(dead_code)] // Public for consumption by downstream crates.
+pub fn verify_signature(secret: &[u8], signature: &str, body: &[u8]) -> bool {
+    let mut timestamp = None;
+    let mut digest = None;
+    for part in signature.split(',') {
+        match part.trim().split_once('=') {
+            Some(("t", value)) => timestamp = value.parse::<u64>().ok(),
+            Some(("v1", value)) => digest = hex::decode(value).ok(),
+            _ => {}
+        }
+    }
+    let (Some(timestamp), Some(digest)) = (timestamp, digest) else {
+        return false;
+    };
+    mac(secret, timestamp, body).verify_slice(&digest).is_ok()
+}
+
+/// HMAC-SHA256 of `"<timestamp>.<body>"` keyed with `secret`.
+fn mac(secret: &[u8], timestamp: u64, body: &[u8]) -> Hmac<Sha256> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret).expect("HMAC takes keys of any length");
+    mac.update(timestamp.to_string().as_bytes());
+    mac.update(b".");
+    mac.update(body);
+    mac
+}
+
+/// Sends signed notifications of facilitator events to webhooks, see the
+/// [module documentation](self).
+#[derive(Clone)]
+pub struct Webhooks {
+    urls: Arc<[Url]>,
+    secret: Arc<[u8]>,
+    events: Arc<HashSet<WebhookEventType>>,
+    max_attempts: u32,
+    dead_letter_path: Option<PathBuf>,
+    client: reqwest::Client,
+}
+
+impl Webhooks {
+    /// Sends every event to `urls`, signed with `secret`.
+    pub fn new<I: IntoIterator<Item = Url>>(urls: I, secret: &[u8]) -> Self {
+        Self {
+            urls: urls.into_iter().collect(),
+            secret: secret.into(),
+            events: Arc::new(WebhookEventType::ALL.into_iter().collect()),
+            max_attempts: DEFAULT_MAX_ATTEMPTS,
+            dead_letter_path: None,
+            client: reqwest::Client::new(),
+        }
+    }
+
+    /// Reads the configuration from the `WEBHOOK_*` variables. Returns `None` if
+    /// `WEBHOOK_URLS` is unset.
+    pub fn from_env() -> Result<Option<Self>, WebhooksConfigError> {
+        let Ok(urls) = env::var(ENV_WEBHOOK_URLS) else {
+            return Ok(None);
+        };
+        let urls = split_list(&urls)
+            .map(|url| Url::parse(url).map_err(|_| WebhooksConfigError::InvalidUrl(url.into())))
+            .collect::<Result<Vec<_>, _>>()?;
+        if urls.is_empty() {
+            return Ok(None);
+        }
+        let secret = env::var(ENV_WEBHOOK_SECRET)
+            .ok()
+            .filter(|secret| !secret.is_empty())
+            .ok_or(WebhooksConfigError::SecretRequired)?;
+        let mut webhooks = Self::new(urls, secret.as_bytes());
+        if let Ok(events) = env::var(ENV_WEBHOOK_EVENTS) {
+            let events = split_list(&events)
+                .map(str::parse)
+                .collect::<Result<HashSet<_>, _>>()?;
+            webhooks = webhooks.with_events(events);
+        }
+        if let Ok(max_attempts) = env::var(ENV_WEBHOOK_MAX_ATTEMPTS) {
+            let attempts = max_attempts
+                .trim()
+                .parse()
+                .ok()
+                .filter(|attempts| MAX_ATTEMPTS_RANGE.contains(attempts))
+                .ok_or(WebhooksConfigError::InvalidMaxAttempts(max_attempts))?;
+            webhooks = webhooks.with_max_attempts(attempts);
+        }
+        if let Ok(path) = env::var(ENV_WEBHOOK_DEAD_LETTER_PATH) {
+            webhooks = webhooks.with_dead_letter_path(path.into());
+        }
+        Ok(Some(webhooks))
+    }
+
+    /// Sends only the events of `events`.
+    pub fn with_events(&self, events: HashSet<WebhookEventType>) -> Self {
+        let mut this = self.clone();
+        this.events = Arc::new(events);
+        this
+    }
+
+    /// Sets the delivery attempts per event and URL, at least one. Defaults to 10.
+    pub fn with_max_attempts(&self, max_attempts: u32) -> Self {
+        let mut this = self.clone();
+        this.max_attempts = max_attempts.max(1);
+        this
+    }
+
+    /// Appends the events not delivered to the file at `path`, as JSON lines.
+    pub fn with_dead_letter_path(&self, path: PathBuf) -> Self {
+        let mut this = self.clone();
+        this.dead_letter_path = Some(path);
+        this
+    }
+
+    /// URLs notified of events.
+    pub fn urls(&self) -> &[Url] {
+        &self.urls
+    }
+
+    /// Reports a payment verified valid for `payer`.
+    pub fn payment_verified(&self, request: &VerifyRequest, payer: &MixedAddress) {
+        let event = PaymentEvent::new(request, Some(payer.clone()));
+        self.send(WebhookEventType::PaymentVerified, &event);
+    }
+
+    /// Reports the outcome of the settlement of `request`: `payment.settled` if it succeeded,
+    /// `payment.failed` otherwise.
+    pub fn payment_settled<E: Display>(
+        &self,
+        request: &SettleRequest,
+        result: &Result<SettleResponse, E>,
+    ) {
+        match result {
+            Ok(response) => {
+                let event = PaymentEvent {
+                    transaction: response.transaction.clone(),
+                    error_reason: response.error_reason.clone(),
+                    ..PaymentEvent::new(request, Some(response.payer.clone()))
+                };
+                let event_type = if response.success {
+                    WebhookEventType::PaymentSettled
+                } else {
+                    WebhookEventType::PaymentFailed
+                };
+                self.send(event_type, &event);
+            }
+            Err(error) => {
+                let event = PaymentEvent {
+                    error: Some(error.to_string()),
+                    ..PaymentEvent::new(request, None)
+                };
+                self.send(WebhookEventType::PaymentFailed, &event);
+            }
+        }
+    }
+
+    /// Reports the stream sessions of `streams` closed from now on, in a background task.
+    pub fn watch_streams(&self, streams: &StreamSessionManager) {
+        if !self.events.contains(&WebhookEventType::StreamClosed) {
+            return;
+        }
+        let webhooks = self.clone();
+        let mut closed = streams.subscribe_closed();
+        tokio::spawn(async move {
+            loop {
+                match closed.recv().await {
+                    Ok(stream) => webhooks.stream_closed(&stream),
+                    Err(RecvError::Lagged(skipped)) => {
+                        tracing::warn!(skipped, "Closed streams not reported to webhooks");
+                    }
+                    Err(RecvError::Closed) => break,
+                }
+            }
+        });
+    }
+
+    fn stream_closed(&self, stream: &ClosedStream) {
+        self.send(WebhookEventType::StreamClosed, stream);
+    }
+
+    /// Delivers an event of `event_type` with `data` to every URL, in background tasks.
+    fn send<T: Serialize>(&self, event_type: WebhookEventType, data: &T) {
+        if !self.events.contains(&event_type) {
+            return;
+        }
+        let data = match serde_json::to_value(data) {
+            Ok(data) => data,
+            Err(error) => {
+                tracing::warn!(%error, %event_type, "Can not encode webhook event");
+                return;
+            }
+        };
+        let event = WebhookEvent {
+            id: uuid::Uuid::new_v4().to_string(),
+            event_type,
+            created_at: UnixTimestamp::try_now().unwrap_or(UnixTimestamp(0)),
+            data,
+        };
+        let body = match serde_json::to_vec(&event) {
+            Ok(body) => Arc::<[u8]>::from(body),
+            Err(error) => {
+                tracing::warn!(%error, %event_type, "Can not encode webhook event");
+                return;
+            }
+        };
+        for url in self.urls.iter() {
+            let webhooks = self.clone();
+            let url = url.clone();
+            let event = event.clone();
+            let body = body.clone();
+            tokio::spawn(async move { webhooks.deliver(url, event, body).await });
+        }
+    }
+
+    /// Posts `body`, the encoding of `event`, to `url`, retrying with exponential backoff, and
+    /// records it as a dead letter if every attempt fails.
+    async fn deliver(&self, url: Url, event: WebhookEvent, body: Arc<[u8]>) {
+        let mut backoff = INITIAL_BACKOFF;
+        let mut attempt = 1;
+        loop {
+            let timestamp = SystemTime::now()
+                .duration_since(SystemTime::UNIX_EPOCH)
+                .map(|now| now.as_secs())
+                .unwrap_or_default();
+            let result = self
+                .client
+                .post(url.clone())
+                .timeout(DELIVERY_TIMEOUT)
+                .header(reqwest::header::CONTENT_TYPE, "application/json")
+                .header(SIGNATURE_HEADER, sign(&self.secret, timestamp, &body))
+                .header(EVENT_ID_HEADER, &event.id)
+                .header(EVENT_TYPE_HEADER, event.event_type.as_str())
+                .body(body.to_vec())
+                .send()
+                .await
+                .and_then(|response| response.error_for_status());
+            match result {
+                Ok(_) => return,
+                Err(error) if attempt < self.max_attempts => {
+                    tracing::debug!(%url, attempt, %error, id = %event.id, "Webhook delivery failed, retrying");
+                    tokio::time::sleep(backoff).await;
+                    backoff = (backoff * 2).min(MAX_BACKOFF);
+                    attempt += 1;
+                }
+                Err(error) => {
+                    self.dead_letter(&url, &event, attempt, &error.to_string())
+                        .await;
+                    return;
+                }
+            }
+        }
+    }
+
+    /// Records `event`, not delivered to `url` after `attempts`, in the dead-letter log.
+    async fn dead_letter(&self, url: &Url, event: &WebhookEvent, attempts: u32, error: &str) {
+        #[derive(Serialize)]
+        struct DeadLetter<'a> {
+            url: &'a Url,
+            attempts: u32,
+            error: &'a str,
+            event: &'a WebhookEvent,
+        }
+        let letter = DeadLetter {
+            url,
+            attempts,
+            error,
+            event,
+        };
+        let Some(path) = &self.dead_letter_path else {
+            let letter = serde_json::to_string(&letter).unwrap_or_default();
+            tracing::error!(%url, id = %event.id, %letter, "Webhook delivery failed");
+            return;
+        };
+        tracing::warn!(%url, id = %event.id, %error, "Webhook delivery failed, written to the dead-letter log");
+        let result = async {
+            let mut line = serde_json::to_vec(&letter)?;
+            line.push(b'\n');
+            let mut file = tokio::fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)
+                .await?;
+            file.write_all(&line).await
+        }
+        .await;
+        if let Err(e) = result {
+            let letter = serde_json::to_string(&letter).unwrap_or_default();
+            tracing::error!(path = %path.display(), error = %e, %letter, "Can not write to the webhook dead-letter log");
+        }
+    }
+}
+
+fn split_list(list: &str) -> impl Iterator<Item = &str> {
+    list.split(',').map(str::trim).filter(|s| !s.is_empty())
+}
@@ -0,0 +1,227 @@
+//! Encoding of the envelopes of the WebSocket protocol, see `x402-ws-stream.md`.
+//!
+//! Envelopes are JSON text frames by default. A peer offering the `x402.cbor` subprotocol
+//! (`Sec-WebSocket-Protocol: x402.cbor`) in the WebSocket handshake switches the connection to
+//! binary frames carrying [CBOR](https://cbor.io) envelopes, with the same structure as the JSON
+//! ones. CBOR cuts the size of high-frequency messages, e.g. the payments of a stream of slices.
+//!
+//! [`WsCodec`] is used by the facilitator endpoint, and by clients to encode their requests and
+//! decode the replies:
+//!
+//! ```rust
+//! use x402_rs::ws_codec::{WsCodec, WsFrame};
+//!
+//! let codec = WsCodec::from_subprotocol(Some("x402.cbor"));
+//! let frame = codec
+//!     .encode(&serde_json::json!({ "id": 1, "method": "x402.supported" }))
+//!     .unwrap();
+//! assert!(matches!(frame, WsFrame::Binary(_)));
+//! let envelope: serde_json::Value = codec.decode(&frame).unwrap();
+//! assert_eq!(envelope["method"], "x402.supported");
+//! ```
+//!
+//! Text frames are decoded as JSON whatever the codec, so a CBOR peer can still send JSON.
+//!
+//! A peer offering the `x402.deflate` subprotocol, or `x402.cbor.deflate` for CBOR envelopes,
+//! also compresses envelopes: once the subprotocol is selected, either side may send an envelope
+//! as a binary frame carrying its [zlib](https://www.rfc-editor.org/rfc/rfc1950) stream, told apart
+//! from a plain envelope by its first byte (`0x78`, which starts no JSON nor CBOR envelope).
+//! [`WsDeflate`] compresses the envelopes of at least a minimum size, the payments and
+//! requirements of a stream of slices being hundreds of bytes of repetitive text, and leaves
+//! small ones, which compression would not shrink, as they are. Both sides can always decode
+//! either.
+//!
+//! Per-message compression is done on envelopes rather than with the `permessage-deflate`
+//! WebSocket extension, which the WebSocket libraries used by the facilitator and its clients do
+//! not implement.
+
+use flate2::Compression;
+use flate2::read::ZlibDecoder;
+use flate2::write::ZlibEncoder;
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use std::io::{Read, Write};
+
+/// WebSocket subprotocol selecting CBOR envelopes.
+pub const CBOR_SUBPROTOCOL: &str = "x402.cbor";
+/// WebSocket subprotocol selecting compressed JSON envelopes.
+pub const DEFLATE_SUBPROTOCOL: &str = "x402.deflate";
+/// WebSocket subprotocol selecting compressed CBOR envelopes.
+pub const CBOR_DEFLATE_SUBPROTOCOL: &str = "x402.cbor.deflate";
+
+/// First byte of the zlib streams of compressed envelopes: deflate with a 32 KiB window.
+const ZLIB_HEADER: u8 = 0x78;
+/// Envelopes smaller than this are not compressed by default, in bytes.
+pub const DEFAULT_MIN_BYTES: usize = 256;
+/// Size limit of a decompressed envelope, in bytes.
+pub const MAX_INFLATED_BYTES: usize = 16 * 1024 * 1024;
+
+#[derive(Debug, thiserror::Error)]
+pub enum WsCodecError {
+    #[error("Invalid JSON envelope: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("Can not encode CBOR envelope: {0}")]
+    CborEncode(String),
+    #[error("Invalid CBOR envelope: {0}")]
+    CborDecode(String),
+    #[error("Invalid compressed envelope: {0}")]
+    Inflate(String),
+}
+
+/// Payload of a WebSocket data frame, independent of the WebSocket library.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WsFrame {
+    Text(String),
+    Binary(Vec<u8>),
+}
+
+/// Encoding of the envelopes of a connection, negotiated in the WebSocket handshake.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum WsCodec {
+    /// JSON envelopes in text frames.
+    #[default]
+    Json,
+    /// CBOR envelopes in binary frames.
+    Cbor,
+}
+
+impl WsCodec {
+    /// Codec of the subprotocol selected in the handshake, JSON if none.
+    pub fn from_subprotocol(protocol: Option<&str>) -> Self {
+        match protocol {
+            Some(CBOR_SUBPROTOCOL | CBOR_DEFLATE_SUBPROTOCOL) => WsCodec::Cbor,
+            _ => WsCodec::Json,
+        }
+    }
+
+    /// Subprotocol to offer in the handshake to select this codec.
+    #[allow(dead_code)] // Public for consumption by downstream crates.
+    pub fn subprotocol(&self) -> Option<&'static str> {
+        match self {
+            WsCodec::Json => None,
+            WsCodec::Cbor => Some(CBOR_SUBPROTOCOL),
+        }
+    }
+
+    /// Subprotocol to offer in the handshake to select this codec with compression.
+    #[allow(dead_code)] // Public for consumption by downstream crates.
+    pub fn deflate_subprotocol(&self) -> &'static str {
+        match self {
+            WsCodec::Json => DEFLATE_SUBPROTOCOL,
+            WsCodec::Cbor => CBOR_DEFLATE_SUBPROTOCOL,
+        }
+    }
+
+    /// Encodes `value` into a frame.
+    pub fn encode<T: Serialize>(&self, value: &T) -> Result<WsFrame, WsCodecError> {
+        match self {
+            WsCodec::Json => Ok(WsFrame::Text(serde_json::to_string(value)?)),
+            WsCodec::Cbor => {
+                let mut bytes = Vec::new();
+                ciborium::into_writer(value, &mut bytes)
+                    .map_err(|e| WsCodecError::CborEncode(e.to_string()))?;
+                Ok(WsFrame::Binary(bytes))
+            }
+        }
+    }
+
+    /// Decodes a frame. Text frames are JSON; binary frames are CBOR with the CBOR codec,
+    /// and UTF-8 JSON otherwise.
+    pub fn decode<T: DeserializeOwned>(&self, frame: &WsFrame) -> Result<T, WsCodecError> {
+        match (self, frame) {
+            (_, WsFrame::Text(text)) => Ok(serde_json::from_str(text)?),
+            (WsCodec::Json, WsFrame::Binary(bytes)) => Ok(serde_json::from_slice(bytes)?),
+            (WsCodec::Cbor, WsFrame::Binary(bytes)) => ciborium::from_reader(bytes.as_slice())
+                .map_err(|e| WsCodecError::CborDecode(e.to_string())),
+        }
+    }
+}
+
+/// Compression of the envelopes of a connection, see the [module documentation](self).
+///
+/// ```rust
+/// use x402_rs::ws_codec::{WsCodec, WsDeflate, WsFrame};
+///
+/// let deflate = WsDeflate::new().with_min_bytes(64);
+/// let envelope = serde_json::json!({ "id": 1, "method": "x402.verify", "params": { "padding": "x".repeat(512) } });
+/// let frame = deflate.compress(WsCodec::Json.encode(&envelope).unwrap());
+/// assert!(matches!(frame, WsFrame::Binary(_)));
+/// let decoded: serde_json::Value = WsCodec::Json.decode(&WsDeflate::decompress(frame).unwrap()).unwrap();
+/// assert_eq!(decoded, envelope);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WsDeflate {
+    min_bytes: usize,
+}
+
+impl Default for WsDeflate {
+    fn default() -> Self {
+        Self {
+            min_bytes: DEFAULT_MIN_BYTES,
+        }
+    }
+}
+
+impl WsDeflate {
+    /// Compresses the envelopes of at least [`DEFAULT_MIN_BYTES`].
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Compression of the subprotocol selected in the handshake, `None` if it does not compress.
+    pub fn from_subprotocol(protocol: Option<&str>) -> Option<Self> {
+        match protocol {
+            Some(DEFLATE_SUBPROTOCOL | CBOR_DEFLATE_SUBPROTOCOL) => Some(Self::new()),
+            _ => None,
+        }
+    }
+
+    /// Compresses the envelopes of at least `min_bytes` once encoded.
+    pub fn with_min_bytes(&self, min_bytes: usize) -> Self {
+        Self { min_bytes }
+    }
+
+    /// Size from which envelopes are compressed, in bytes.
+    pub fn min_bytes(&self) -> usize {
+        self.min_bytes
+    }
+
+    /// Compresses the envelope of `frame` into a binary frame, if large enough and made smaller
+    /// by compression. Returns `frame` as is otherwise.
+    pub fn compress(&self, frame: WsFrame) -> WsFrame {
+        let bytes = match &frame {
+            WsFrame::Text(text) => text.as_bytes(),
+            WsFrame::Binary(bytes) => bytes.as_slice(),
+        };
+        if bytes.len() < self.min_bytes {
+            return frame;
+        }
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+        match encoder.write_all(bytes).and_then(|_| encoder.finish()) {
+            Ok(compressed) if compressed.len() < bytes.len() => WsFrame::Binary(compressed),
+            _ => frame,
+        }
+    }
+
+    /// Decompresses `frame` if it carries a compressed envelope, into a binary frame that
+    /// [`WsCodec::decode`] decodes. Returns `frame` as is otherwise.
+    pub fn decompress(frame: WsFrame) -> Result<WsFrame, WsCodecError> {
+        let WsFrame::Binary(bytes) = &frame else {
+            return Ok(frame);
+        };
+        if bytes.first() != Some(&ZLIB_HEADER) {
+            return Ok(frame);
+        }
+        let mut inflated = Vec::new();
+        ZlibDecoder::new(bytes.as_slice())
+            .take(MAX_INFLATED_BYTES as u64 + 1)
+            .read_to_end(&mut inflated)
+            .map_err(|e| WsCodecError::Inflate(e.to_string()))?;
+        if inflated.len() > MAX_INFLATED_BYTES {
+            return Err(WsCodecError::Inflate(format!(
+                "larger than {MAX_INFLATED_BYTES} bytes"
+            )));
+        }
+        Ok(WsFrame::Binary(inflated))
+    }
+}
@@ -0,0 +1,169 @@
+//! Registry of the error codes of the WebSocket protocol, see `x402-ws-stream.md`.
+//!
+//! A request failing is answered with an error envelope `{ id, error: { code, message, data? } }`,
+//! its `error` being a [`WsError`]. Codes are stable: a code keeps its meaning across versions,
+//! and new conditions get new codes.
+//!
+//! | Code     | Constant                              | Meaning                                              |
+//! |----------|---------------------------------------|------------------------------------------------------|
+//! | `-32700` | [`WsErrorCode::PARSE_ERROR`]          | Envelope is not valid JSON or CBOR                   |
+//! | `-32600` | [`WsErrorCode::INVALID_REQUEST`]      | Envelope is not a request                            |
+//! | `-32601` | [`WsErrorCode::METHOD_NOT_FOUND`]     | Unknown method, or method of an extension not served |
+//! | `-32602` | [`WsErrorCode::INVALID_PARAMS`]       | Params missing or invalid                            |
+//! | `-32603` | [`WsErrorCode::INTERNAL_ERROR`]       | Request failed on the server, e.g. a handler panic   |
+//! | `-32001` | [`WsErrorCode::UNAUTHORIZED`]         | Connection not authenticated with `x402.auth`        |
+//! | `-32029` | [`WsErrorCode::RATE_LIMITED`]         | Over the rate limit, or overloaded; `retryAfterMs`   |
+//! | `1001`   | [`WsErrorCode::PAYMENT_FAILED`]       | Payment rejected, or settlement failed               |
+//! | `1101`   | [`WsErrorCode::STREAM_NOT_FOUND`]     | Unknown or expired stream                            |
+//! | `1102`   | [`WsErrorCode::SLICE_NOT_REQUIRED`]   | Slice paid is not the slice required                 |
+//! | `1103`   | [`WsErrorCode::REQUIRE_EXPIRED`]      | Requirements of the slice expired                    |
+//! | `1104`   | [`WsErrorCode::REQUIRE_CANCELLED`]    | Requirements cancelled by `stream.require.cancel`    |
+//! | `1105`   | [`WsErrorCode::STREAM_BUSY`]          | Payment or finalization of the stream in progress    |
+//!
+//! ```rust
+//! use x402_rs::ws_error::{WsError, WsErrorCode};
+//!
+//! let error = WsError::payment_failed("Settlement failed", None);
+//! assert_eq!(error.code, WsErrorCode::PAYMENT_FAILED);
+//! assert_eq!(
+//!     serde_json::to_value(&error).unwrap(),
+//!     serde_json::json!({ "code": 1001, "message": "Settlement failed" })
+//! );
+//! ```
+
+use serde::{Deserialize, Serialize};
+use std::fmt::{Display, Formatter};
+use std::time::Duration;
+
+/// Code of a [`WsError`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct WsErrorCode(pub i32);
+
+impl WsErrorCode {
+    pub const PARSE_ERROR: Self = Self(-32700);
+    pub const INVALID_REQUEST: Self = Self(-32600);
+    pub const METHOD_NOT_FOUND: Self = Self(-32601);
+    pub const INVALID_PARAMS: Self = Self(-32602);
+    pub const INTERNAL_ERROR: Self = Self(-32603);
+    pub const UNAUTHORIZED: Self = Self(-32001);
+    pub const RATE_LIMITED: Self = Self(-32029);
+    pub const PAYMENT_FAILED: Self = Self(1001);
+    pub const STREAM_NOT_FOUND: Self = Self(1101);
+    pub const SLICE_NOT_REQUIRED: Self = Self(1102);
+    pub const REQUIRE_EXPIRED: Self = Self(1103);
+    pub const REQUIRE_CANCELLED: Self = Self(1104);
+    pub const STREAM_BUSY: Self = Self(1105);
+
+    #[allow(dead_code)] // Public for consumption by downstream crates.
+    pub const fn code(self) -> i32 {
+        self.0
+    }
+
+    /// Whether the code is one of the stream-specific codes.
+    #[allow(dead_code)] // Public for consumption by downstream crates.
+    pub const fn is_stream(self) -> bool {
+        1100 <= self.0 && self.0 < 1200
+    }
+}
+
+impl Display for WsErrorCode {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl From<WsErrorCode> for i64 {
+    fn from(code: WsErrorCode) -> Self {
+        code.0 as i64
+    }
+}
+
+impl PartialEq<i64> for WsErrorCode {
+    fn eq(&self, other: &i64) -> bool {
+        self.0 as i64 == *other
+    }
+}
+
+/// The `error` of an error envelope.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct WsError {
+    pub code: WsErrorCode,
+    pub message: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub data: Option<serde_json::Value>,
+}
+
+impl WsError {
+    pub fn new(code: WsErrorCode, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+            data: None,
+        }
+    }
+
+    pub fn with_data(self, data: serde_json::Value) -> Self {
+        Self {
+            data: Some(data),
+            ..self
+        }
+    }
+
+    /// `-32700`, the envelope not being JSON.
+    pub fn parse_error(error: impl Display) -> Self {
+        Self::new(WsErrorCode::PARSE_ERROR, format!("Parse error: {error}"))
+    }
+
+    /// `-32600`, the envelope not being a valid request, with `reason`.
+    pub fn invalid_request(reason: impl Display) -> Self {
+        Self::new(
+            WsErrorCode::INVALID_REQUEST,
+            format!("Invalid Request: {reason}"),
+        )
+    }
+
+    /// `-32602`, with `error` as the reason.
+    pub fn invalid_params(error: impl Display) -> Self {
+        Self::new(
+            WsErrorCode::INVALID_PARAMS,
+            format!("Invalid params: {error}"),
+        )
+    }
+
+    /// `-32601`, e.g. `Method not found`.
+    pub fn method_not_found(message: impl Into<String>) -> Self {
+        Self::new(WsErrorCode::METHOD_NOT_FOUND, message)
+    }
+
+    /// `-32603`, the details being kept on the server.
+    pub fn internal() -> Self {
+        Self::new(WsErrorCode::INTERNAL_ERROR, "Internal error")
+    }
+
+    /// `-32001`.
+    pub fn unauthorized(message: impl Into<String>) -> Self {
+        Self::new(WsErrorCode::UNAUTHORIZED, message)
+    }
+
+    /// `-32029`, to send the request again after `retry_after`.
+    pub fn rate_limited(retry_after: Duration) -> Self {
+        Self::new(WsErrorCode::RATE_LIMITED, "Rate limited").with_data(serde_json::json!({
+            "retryAfterMs": retry_after.as_millis() as u64
+        }))
+    }
+
+    /// `1001`, `data` being e.g. the `VerifyResponse` or `SettleResponse`.
+    pub fn payment_failed(message: impl Into<String>, data: Option<serde_json::Value>) -> Self {
+        Self {
+            data,
+            ..Self::new(WsErrorCode::PAYMENT_FAILED, message)
+        }
+    }
+}
+
+impl Display for WsError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} ({})", self.message, self.code)
+    }
+}
@@ -0,0 +1,149 @@
+//! Strict [JSON-RPC 2.0](https://www.jsonrpc.org/specification) mode of the WebSocket envelope.
+//!
+//! Envelopes are JSON-RPC-like by default: requests `{ id, method, params }` are answered with
+//! `{ id, result }` or `{ id, error }`, without the `jsonrpc` member, and frames holding no
+//! request are dropped. A peer offering the `x402.jsonrpc` subprotocol in the WebSocket handshake
+//! gets the strict mode instead, for generic JSON-RPC 2.0 clients:
+//! - envelopes carry `"jsonrpc": "2.0"`, both ways,
+//! - requests without `id` are notifications, served but never answered,
+//! - an array of requests is a batch, answered with the array of the responses to its requests,
+//!   notifications left out, and not at all if it holds only notifications,
+//! - a frame that is not JSON is answered with a `Parse error` (`-32700`), and an envelope that
+//!   is not a valid request with an `Invalid Request` error (`-32600`), whose `id` is `null` if
+//!   it can not be read.
+//!
+//! ```rust
+//! use x402_rs::ws_error::WsErrorCode;
+//! use x402_rs::ws_jsonrpc::{self, JsonRpcMessage};
+//!
+//! let message = ws_jsonrpc::parse(
+//!     br#"[
+//!         { "jsonrpc": "2.0", "id": 1, "method": "x402.supported" },
+//!         { "jsonrpc": "2.0", "method": "x402.hello" },
+//!         { "id": 2, "method": "x402.supported" }
+//!     ]"#,
+//! );
+//! let JsonRpcMessage::Batch(requests) = message else {
+//!     unreachable!()
+//! };
+//! assert!(!requests[0].as_ref().unwrap().is_notification());
+//! assert!(requests[1].as_ref().unwrap().is_notification());
+//! let invalid = requests[2].as_ref().unwrap_err();
+//! assert_eq!(invalid.error.code, WsErrorCode::INVALID_REQUEST);
+//! assert_eq!(invalid.id, 2);
+//! ```
+//!
+//! Envelopes of the strict mode are JSON text frames: the subprotocol combines neither with CBOR
+//! nor with compression, see [`ws_codec`](crate::ws_codec).
+
+use serde_json::Value;
+
+use crate::ws_error::WsError;
+
+/// WebSocket subprotocol selecting the strict JSON-RPC 2.0 mode.
+pub const JSONRPC_SUBPROTOCOL: &str = "x402.jsonrpc";
+/// Value of the `jsonrpc` member of the envelopes of the strict mode.
+pub const JSONRPC_VERSION: &str = "2.0";
+
+/// A valid request of the strict mode.
+#[derive(Debug, Clone, PartialEq)]
+pub struct JsonRpcRequest {
+    /// Id of the request, `None` for a notification.
+    pub id: Option<Value>,
+    pub method: String,
+    /// Params of the request, `null` if it has none.
+    pub params: Value,
+}
+
+impl JsonRpcRequest {
+    /// Whether the request is a notification, which is not answered.
+    #[allow(dead_code)] // Public for consumption by downstream crates.
+    pub fn is_notification(&self) -> bool {
+        self.id.is_none()
+    }
+}
+
+/// An envelope that is not a valid request, answered with `error`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct JsonRpcInvalid {
+    /// Id of the envelope, `null` if it can not be read.
+    pub id: Value,
+    pub error: WsError,
+}
+
+impl JsonRpcInvalid {
+    fn new(id: Option<&Value>, error: WsError) -> Self {
+        Self {
+            id: id.cloned().unwrap_or(Value::Null),
+            error,
+        }
+    }
+}
+
+/// A frame received in the strict mode.
+#[derive(Debug, Clone, PartialEq)]
+pub enum JsonRpcMessage {
+    /// A single envelope, possibly not a valid request.
+    Single(Result<JsonRpcRequest, JsonRpcInvalid>),
+    /// A non-empty batch of envelopes.
+    Batch(Vec<Result<JsonRpcRequest, JsonRpcInvalid>>),
+}
+
+/// Parses a frame of the strict mode, holding UTF-8 JSON.
+pub fn parse(frame: &[u8]) -> JsonRpcMessage {
+    match serde_json::from_slice(frame) {
+        Ok(Value::Array(envelopes)) if envelopes.is_empty() => JsonRpcMessage::Single(Err(
+            JsonRpcInvalid::new(None, WsError::invalid_request("empty batch")),
+        )),
+        Ok(Value::Array(envelopes)) => {
+            JsonRpcMessage::Batch(envelopes.into_iter().map(request).collect())
+        }
+        Ok(envelope) => JsonRpcMessage::Single(request(envelope)),
+        Err(error) => {
+            JsonRpcMessage::Single(Err(JsonRpcInvalid::new(None, WsError::parse_error(error))))
+        }
+    }
+}
+
+/// Reads the request of an envelope.
+fn request(envelope: Value) -> Result<JsonRpcRequest, JsonRpcInvalid> {
+    let Value::Object(mut envelope) = envelope else {
+        return Err(JsonRpcInvalid::new(
+            None,
+            WsError::invalid_request("not an object"),
+        ));
+    };
+    let id = envelope.remove("id");
+    if let Some(id) = &id
+        && !(id.is_string() || id.is_number() || id.is_null())
+    {
+        return Err(JsonRpcInvalid::new(
+            None,
+            WsError::invalid_request("id must be a string, a number or null"),
+        ));
+    }
+    let invalid = |reason: &str| JsonRpcInvalid::new(id.as_ref(), WsError::invalid_request(reason));
+    if envelope.get("jsonrpc").and_then(Value::as_str) != Some(JSONRPC_VERSION) {
+        return Err(invalid("jsonrpc must be \"2.0\""));
+    }
+    let Some(Value::String(method)) = envelope.remove("method") else {
+        return Err(invalid("method must be a string"));
+    };
+    let params = match envelope.remove("params") {
+        None => Value::Null,
+        Some(params @ (Value::Array(_) | Value::Object(_))) => params,
+        Some(_) => return Err(invalid("params must be an array or an object")),
+    };
+    Ok(JsonRpcRequest { id, method, params })
+}
+
+/// Adds `"jsonrpc": "2.0"` to `envelope`, or to every envelope of a batch.
+pub fn stamp(envelope: &mut Value) {
+    match envelope {
+        Value::Object(envelope) => {
+            envelope.insert("jsonrpc".to_string(), JSONRPC_VERSION.into());
+        }
+        Value::Array(envelopes) => envelopes.iter_mut().for_each(stamp),
+        _ => {}
+    }
+}
@@ -0,0 +1,193 @@
+//! Custom methods of the WebSocket endpoint.
+//!
+//! A facilitator embedded by a seller may serve bespoke RPCs next to the methods of the protocol,
+//! e.g. a quote of the price of a resource, without forking the WebSocket handlers. Methods are
+//! registered by name in a [`WsMethods`] registry, each with an async handler receiving the params
+//! of the request and a [`WsMethodContext`]: the facilitator served, and the identity of the
+//! connection. The registry is handed to the endpoint with `FacilitatorRoutes::with_ws_methods`.
+//!
+//! ```rust
+//! use x402_rs::ws_error::WsError;
+//! use x402_rs::ws_methods::{WsMethodContext, WsMethods};
+//!
+//! #[derive(Clone)]
+//! struct MyFacilitator;
+//!
+//! let methods = WsMethods::new("quotes", 1).with_method(
+//!     "quotes.get",
+//!     |params: serde_json::Value, context: WsMethodContext<MyFacilitator>| async move {
+//!         let resource = params["resource"]
+//!             .as_str()
+//!             .ok_or_else(|| WsError::invalid_params("missing resource"))?;
+//!         let quote = serde_json::json!({ "resource": resource, "connectionId": context.connection_id() });
+//!         Ok::<_, WsError>(quote)
+//!     },
+//! );
+//! assert!(methods.get("quotes.get").is_some());
+//! ```
+//!
+//! Custom methods form an extension of the protocol (see [`crate::extensions`]), listed by
+//! `x402.supported` and negotiated with `x402.hello` as the built-in ones. They go through the
+//! same authentication and panic isolation as the methods of the protocol, which they can not
+//! replace: a custom method named like a built-in one is never called. Handlers answer with a
+//! result, or a [`WsError`], sent as the error envelope of the request.
+
+use alloy::primitives::Address;
+use futures_util::FutureExt;
+use futures_util::future::BoxFuture;
+use std::collections::BTreeMap;
+use std::fmt::{Debug, Formatter};
+use std::future::Future;
+use std::sync::Arc;
+
+use crate::extensions::ExtensionDescriptor;
+use crate::ws_error::WsError;
+
+/// What a custom method knows of the request it serves.
+#[derive(Debug, Clone)]
+#[allow(dead_code)] // Public for consumption by downstream crates.
+pub struct WsMethodContext<F> {
+    facilitator: F,
+    connection_id: String,
+    address: Option<Address>,
+    api_key: Option<String>,
+}
+
+impl<F> WsMethodContext<F> {
+    /// Context of a request of the connection `connection_id`, not authenticated.
+    pub fn new(facilitator: F, connection_id: impl Into<String>) -> Self {
+        Self {
+            facilitator,
+            connection_id: connection_id.into(),
+            address: None,
+            api_key: None,
+        }
+    }
+
+    /// Sets the account the connection authenticated as, by signature.
+    pub fn with_address(self, address: Address) -> Self {
+        Self {
+            address: Some(address),
+            ..self
+        }
+    }
+
+    /// Sets the API key the connection authenticated with.
+    pub fn with_api_key(self, api_key: impl Into<String>) -> Self {
+        Self {
+            api_key: Some(api_key.into()),
+            ..self
+        }
+    }
+
+    /// Facilitator served by the endpoint.
+    #[allow(dead_code)] // Public for consumption by downstream crates.
+    pub fn facilitator(&self) -> &F {
+        &self.facilitator
+    }
+
+    /// Id of the connection, as returned by `x402.hello`.
+    #[allow(dead_code)] // Public for consumption by downstream crates.
+    pub fn connection_id(&self) -> &str {
+        &self.connection_id
+    }
+
+    /// Account the connection authenticated as, if it signed the challenge of `x402.hello`.
+    #[allow(dead_code)] // Public for consumption by downstream crates.
+    pub fn address(&self) -> Option<Address> {
+        self.address
+    }
+
+    /// API key the connection authenticated with, if any.
+    #[allow(dead_code)] // Public for consumption by downstream crates.
+    pub fn api_key(&self) -> Option<&str> {
+        self.api_key.as_deref()
+    }
+}
+
+/// Handler of a custom method.
+///
+/// Implemented by every `Fn(serde_json::Value, WsMethodContext<F>) -> impl Future<Output =
+/// Result<serde_json::Value, WsError>>`.
+pub trait WsMethod<F>: Send + Sync + 'static {
+    /// Serves a request with `params`, `null` if it has none.
+    fn call(
+        &self,
+        params: serde_json::Value,
+        context: WsMethodContext<F>,
+    ) -> BoxFuture<'static, Result<serde_json::Value, WsError>>;
+}
+
+impl<F, H, Fut> WsMethod<F> for H
+where
+    H: Fn(serde_json::Value, WsMethodContext<F>) -> Fut + Send + Sync + 'static,
+    Fut: Future<Output = Result<serde_json::Value, WsError>> + Send + 'static,
+{
+    fn call(
+        &self,
+        params: serde_json::Value,
+        context: WsMethodContext<F>,
+    ) -> BoxFuture<'static, Result<serde_json::Value, WsError>> {
+        self(params, context).boxed()
+    }
+}
+
+/// Custom methods of the WebSocket endpoint, forming an extension of the protocol, see the
+/// [module documentation](self).
+///
+/// Clones share the same handlers.
+#[allow(dead_code)] // Public for consumption by downstream crates.
+pub struct WsMethods<F> {
+    extension: ExtensionDescriptor,
+    methods: BTreeMap<String, Arc<dyn WsMethod<F>>>,
+}
+
+impl<F> Clone for WsMethods<F> {
+    fn clone(&self) -> Self {
+        Self {
+            extension: self.extension.clone(),
+            methods: self.methods.clone(),
+        }
+    }
+}
+
+impl<F> Debug for WsMethods<F> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("WsMethods")
+            .field("extension", &self.extension)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<F: 'static> WsMethods<F> {
+    /// No method yet, in the extension `name` at `version`.
+    #[allow(dead_code)] // Public for consumption by downstream crates.
+    pub fn new(name: impl Into<String>, version: u32) -> Self {
+        Self {
+            extension: ExtensionDescriptor::new(name, version),
+            methods: BTreeMap::new(),
+        }
+    }
+
+    /// Registers `handler` as the method `method`, replacing any handler of the same method.
+    #[allow(dead_code)] // Public for consumption by downstream crates.
+    pub fn with_method(&self, method: impl Into<String>, handler: impl WsMethod<F>) -> Self {
+        let method = method.into();
+        let mut this = self.clone();
+        if !this.extension.methods.contains(&method) {
+            this.extension = this.extension.with_method(method.clone());
+        }
+        this.methods.insert(method, Arc::new(handler));
+        this
+    }
+
+    /// Handler of `method`, if registered.
+    pub fn get(&self, method: &str) -> Option<&dyn WsMethod<F>> {
+        self.methods.get(method).map(|handler| handler.as_ref())
+    }
+
+    /// Extension formed by the methods, listing them.
+    pub fn extension(&self) -> &ExtensionDescriptor {
+        &self.extension
+    }
+}